@@ -0,0 +1,333 @@
+/// Destructuring desugaring
+///
+/// Rewrites destructuring binding forms into plain symbol bindings built from
+/// `get` and `subs` calls, so the evaluator and compiler only ever see symbols
+/// on the left-hand side of `let` and in parameter vectors.
+/// - Sequential: `[a b & more :as all]`
+/// - Associative: `{:keys [name age] :strs [id] :or {age 0} :as user}` and `{n :name}`
+/// - Patterns nest in any position that binds a value
+use super::{Node, Primitive};
+
+/// Desugar every `let`, `fn` and `defn` in a tree, recursing into all subforms
+pub fn desugar(node: &Node) -> Result<Node, String> {
+    let mut counter = 0;
+    desugar_node(node, &mut counter)
+}
+
+/// Expand a `let` binding vector into symbol/value pairs
+pub fn expand_let_bindings(bindings: &[Node]) -> Result<Vec<Node>, String> {
+    let mut counter = 0;
+    expand_bindings(bindings, &mut counter)
+}
+
+/// Replace destructuring patterns in a parameter vector with generated symbols and
+/// wrap the body in a `let` that destructures them
+pub fn expand_params(params: &Node, body: &Node) -> Result<(Node, Node), String> {
+    let mut counter = 0;
+    expand_params_with(params, body.clone(), &mut counter)
+}
+
+fn desugar_node(node: &Node, counter: &mut usize) -> Result<Node, String> {
+    match node {
+        Node::List { root } => desugar_list(root, counter),
+        Node::Vector { root } => Ok(Node::Vector {
+            root: root.iter().map(|child| desugar_node(child, counter)).collect::<Result<_, _>>()?,
+        }),
+        Node::Set { root } => Ok(Node::Set {
+            root: root.iter().map(|child| desugar_node(child, counter)).collect::<Result<_, _>>()?,
+        }),
+        Node::Map { entries } => Ok(Node::Map {
+            entries: entries
+                .iter()
+                .map(|(key, value)| Ok((desugar_node(key, counter)?, desugar_node(value, counter)?)))
+                .collect::<Result<_, String>>()?,
+        }),
+        Node::Primitive { .. } | Node::Symbol { .. } => Ok(node.clone()),
+    }
+}
+
+fn desugar_list(root: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let head = match root.first() {
+        Some(Node::Symbol { value }) => value.as_str(),
+        _ => return desugar_children(root, counter),
+    };
+
+    match head {
+        // Quoted forms are data, so their `let`s and `fn`s are left alone
+        "quote" | "syntax-quote" => Ok(list(root.to_vec())),
+        "let" if root.len() == 3 => {
+            let Node::Vector { root: bindings } = &root[1] else {
+                return desugar_children(root, counter);
+            };
+            let desugared = bindings.iter().map(|child| desugar_node(child, counter)).collect::<Result<Vec<_>, _>>()?;
+            let expanded = expand_bindings(&desugared, counter)?;
+            Ok(list(vec![symbol("let"), Node::Vector { root: expanded }, desugar_node(&root[2], counter)?]))
+        }
+        "fn" if root.len() == 3 => {
+            let (params, body) = expand_params_with(&root[1], desugar_node(&root[2], counter)?, counter)?;
+            Ok(list(vec![root[0].clone(), params, body]))
+        }
+        "defn" if root.len() == 4 && matches!(root[2], Node::Vector { .. }) => {
+            let (params, body) = expand_params_with(&root[2], desugar_node(&root[3], counter)?, counter)?;
+            Ok(list(vec![root[0].clone(), root[1].clone(), params, body]))
+        }
+        "defn" if root.len() >= 3 && matches!(root[2], Node::List { .. }) => {
+            let mut forms = vec![root[0].clone(), root[1].clone()];
+            for clause in &root[2..] {
+                match clause {
+                    Node::List { root: clause_root } if clause_root.len() == 2 => {
+                        let (params, body) = expand_params_with(&clause_root[0], desugar_node(&clause_root[1], counter)?, counter)?;
+                        forms.push(list(vec![params, body]));
+                    }
+                    other => forms.push(desugar_node(other, counter)?),
+                }
+            }
+            Ok(list(forms))
+        }
+        _ => desugar_children(root, counter),
+    }
+}
+
+fn desugar_children(root: &[Node], counter: &mut usize) -> Result<Node, String> {
+    Ok(list(root.iter().map(|child| desugar_node(child, counter)).collect::<Result<_, _>>()?))
+}
+
+fn expand_bindings(bindings: &[Node], counter: &mut usize) -> Result<Vec<Node>, String> {
+    // Odd-length binding vectors are reported by the `let` implementations themselves
+    if bindings.len() % 2 != 0 || bindings.iter().step_by(2).all(|pattern| matches!(pattern, Node::Symbol { .. })) {
+        return Ok(bindings.to_vec());
+    }
+
+    let mut expanded = Vec::new();
+    for chunk in bindings.chunks(2) {
+        bind_pattern(&chunk[0], chunk[1].clone(), &mut expanded, counter)?;
+    }
+    Ok(expanded)
+}
+
+fn expand_params_with(params: &Node, body: Node, counter: &mut usize) -> Result<(Node, Node), String> {
+    let Node::Vector { root } = params else {
+        return Ok((params.clone(), body));
+    };
+    if root.iter().all(|param| matches!(param, Node::Symbol { .. })) {
+        return Ok((params.clone(), body));
+    }
+
+    let mut symbols = Vec::with_capacity(root.len());
+    let mut destructured = Vec::new();
+    for param in root {
+        match param {
+            Node::Symbol { .. } => symbols.push(param.clone()),
+            Node::Vector { .. } | Node::Map { .. } => {
+                let generated = gensym("p", counter);
+                destructured.push(param.clone());
+                destructured.push(generated.clone());
+                symbols.push(generated);
+            }
+            _ => return Err("Function parameters must be symbols or destructuring patterns".to_string()),
+        }
+    }
+
+    let bindings = expand_bindings(&destructured, counter)?;
+    let body = list(vec![symbol("let"), Node::Vector { root: bindings }, body]);
+    Ok((Node::Vector { root: symbols }, body))
+}
+
+/// Append `symbol value` pairs binding `pattern` to `value`
+fn bind_pattern(pattern: &Node, value: Node, out: &mut Vec<Node>, counter: &mut usize) -> Result<(), String> {
+    match pattern {
+        Node::Symbol { .. } => {
+            out.push(pattern.clone());
+            out.push(value);
+            Ok(())
+        }
+        Node::Vector { root } => {
+            let target = bind_target(pattern, value, "vec", out, counter);
+            bind_sequential(root, &target, out, counter)
+        }
+        Node::Map { entries } => {
+            let target = bind_target(pattern, value, "map", out, counter);
+            bind_associative(entries, &target, out, counter)
+        }
+        _ => Err("Binding patterns must be symbols, vectors or maps".to_string()),
+    }
+}
+
+/// Symbol the pattern's lookups read from. A symbol value is used directly unless the
+/// pattern rebinds that name; anything else is evaluated once into a generated local.
+fn bind_target(pattern: &Node, value: Node, prefix: &str, out: &mut Vec<Node>, counter: &mut usize) -> Node {
+    if let Node::Symbol { value: name } = &value {
+        if !binds_name(pattern, name) {
+            return value;
+        }
+    }
+    let generated = gensym(prefix, counter);
+    out.push(generated.clone());
+    out.push(value);
+    generated
+}
+
+fn bind_sequential(elements: &[Node], target: &Node, out: &mut Vec<Node>, counter: &mut usize) -> Result<(), String> {
+    let mut index = 0;
+    let mut iter = elements.iter();
+    while let Some(element) = iter.next() {
+        match element {
+            Node::Symbol { value } if value == "&" => {
+                let rest = iter.next().ok_or_else(|| "& must be followed by a binding pattern".to_string())?;
+                bind_pattern(rest, rest_of(target, index), out, counter)?;
+            }
+            Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == "as" => {
+                let alias = iter.next().ok_or_else(|| ":as must be followed by a symbol".to_string())?;
+                if !matches!(alias, Node::Symbol { .. }) {
+                    return Err(":as must be followed by a symbol".to_string());
+                }
+                bind_pattern(alias, target.clone(), out, counter)?;
+            }
+            _ => {
+                bind_pattern(element, list(vec![symbol("get"), target.clone(), Node::new_number(index)]), out, counter)?;
+                index += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `(if (> n (count target)) [] (subs target n))`, so a short vector yields an empty rest
+fn rest_of(target: &Node, index: usize) -> Node {
+    let count = list(vec![symbol("count"), target.clone()]);
+    list(vec![
+        symbol("if"),
+        list(vec![symbol(">"), Node::new_number(index), count]),
+        Node::Vector { root: Vec::new() },
+        list(vec![symbol("subs"), target.clone(), Node::new_number(index)]),
+    ])
+}
+
+fn bind_associative(entries: &[(Node, Node)], target: &Node, out: &mut Vec<Node>, counter: &mut usize) -> Result<(), String> {
+    let defaults = entries
+        .iter()
+        .find(|(key, _)| is_keyword(key, "or"))
+        .map(|(_, value)| match value {
+            Node::Map { entries } => Ok(entries.as_slice()),
+            _ => Err(":or must be followed by a map".to_string()),
+        })
+        .transpose()?
+        .unwrap_or(&[]);
+
+    let lookup = |name: &str, key: Node| {
+        let default = defaults.iter().find(|(symbol, _)| matches!(symbol, Node::Symbol { value } if value == name));
+        let mut call = vec![symbol("get"), target.clone(), key];
+        if let Some((_, default)) = default {
+            call.push(default.clone());
+        }
+        list(call)
+    };
+
+    for (key, value) in entries {
+        match key {
+            Node::Primitive { value: Primitive::Keyword(keyword) } => match keyword.as_str() {
+                "or" => {}
+                "as" => match value {
+                    Node::Symbol { .. } => bind_pattern(value, target.clone(), out, counter)?,
+                    _ => return Err(":as must be followed by a symbol".to_string()),
+                },
+                "keys" | "strs" => {
+                    let Node::Vector { root: names } = value else {
+                        return Err(format!(":{} must be followed by a vector of symbols", keyword));
+                    };
+                    for name in names {
+                        let Node::Symbol { value: name_str } = name else {
+                            return Err(format!(":{} must be followed by a vector of symbols", keyword));
+                        };
+                        let lookup_key = if keyword == "keys" {
+                            Node::new_keyword_from_raw(name_str.clone())
+                        } else {
+                            Node::Primitive {
+                                value: Primitive::String(name_str.clone()),
+                            }
+                        };
+                        bind_pattern(name, lookup(name_str, lookup_key), out, counter)?;
+                    }
+                }
+                other => return Err(format!("Unsupported map destructuring option :{}", other)),
+            },
+            Node::Symbol { value: name } => bind_pattern(key, lookup(name, value.clone()), out, counter)?,
+            Node::Vector { .. } | Node::Map { .. } => bind_pattern(key, list(vec![symbol("get"), target.clone(), value.clone()]), out, counter)?,
+            _ => return Err("Map binding keys must be symbols, patterns, :keys, :strs, :or or :as".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Whether a pattern introduces a binding named `name`
+fn binds_name(pattern: &Node, name: &str) -> bool {
+    match pattern {
+        Node::Symbol { value } => value == name,
+        Node::Vector { root } => root.iter().any(|element| binds_name(element, name)),
+        Node::Map { entries } => entries.iter().any(|(key, value)| match key {
+            Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == "or" => false,
+            Node::Primitive { value: Primitive::Keyword(_) } => binds_name(value, name),
+            _ => binds_name(key, name),
+        }),
+        _ => false,
+    }
+}
+
+fn is_keyword(node: &Node, name: &str) -> bool {
+    matches!(node, Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == name)
+}
+
+fn gensym(prefix: &str, counter: &mut usize) -> Node {
+    let generated = symbol(&format!("{}__{}", prefix, counter));
+    *counter += 1;
+    generated
+}
+
+fn symbol(name: &str) -> Node {
+    Node::Symbol { value: name.to_string() }
+}
+
+fn list(nodes: Vec<Node>) -> Node {
+    Node::new_list_from_raw(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AstParser, AstParserTrt};
+
+    fn parse(input: &str) -> Node {
+        AstParser::parse_sexp_new_domain(input.as_bytes(), &mut 0)
+    }
+
+    #[test]
+    fn expands_sequential_bindings() {
+        let expanded = desugar(&parse("(let [[a b & more :as all] v] a)")).unwrap();
+        assert_eq!(expanded, parse("(let [a (get v 0) b (get v 1) more (if (> 2 (count v)) [] (subs v 2)) all v] a)"));
+    }
+
+    #[test]
+    fn expands_associative_bindings_with_defaults() {
+        let expanded = desugar(&parse("(let [{:keys [name age] :or {age 0} :as user} (f)] name)")).unwrap();
+        assert_eq!(expanded, parse("(let [map__0 (f) name (get map__0 :name) age (get map__0 :age 0) user map__0] name)"));
+    }
+
+    #[test]
+    fn expands_nested_patterns_and_params() {
+        let expanded = desugar(&parse("(defn area [{[w h] :size}] (* w h))")).unwrap();
+        assert_eq!(expanded, parse("(defn area [p__0] (let [vec__1 (get p__0 :size) w (get vec__1 0) h (get vec__1 1)] (* w h)))"));
+    }
+
+    #[test]
+    fn evaluates_value_once_when_pattern_rebinds_it() {
+        let expanded = desugar(&parse("(let [[x y] x] y)")).unwrap();
+        assert_eq!(expanded, parse("(let [vec__0 x x (get vec__0 0) y (get vec__0 1)] y)"));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert!(desugar(&parse("(let [[a &] v] a)")).is_err());
+        assert!(desugar(&parse("(let [{:keys x} m] x)")).is_err());
+        assert!(desugar(&parse("(fn [1] 1)")).is_err());
+    }
+}
@@ -0,0 +1,613 @@
+/// Derived conditional and threading forms
+///
+/// Rewrites convenience forms into the core `if` and `let` forms, so the evaluator and
+/// compiler only implement the primitives.
+/// - Conditionals: `cond`, `when`, `when-not`, `if-not`, `if-let`, `when-let`
+/// - `not=`, as `not` of `=`
+/// - `min-key` and `max-key`, as a `let` chain keeping the best item and its key
+/// - Threading: `->`, `->>`, `some->`, `as->`
+/// - `update`, as an `assoc` of the function applied to the current value
+/// - `get-in`, `assoc-in` and `update-in`, as nested `get`/`assoc`/`update` over a literal key path
+/// - `swap!`, as a `reset!` of the function applied to the atom's current value
+/// - Calls of keywords and literal maps or sets, as `get` or a `contains?` test (`lookup_call`)
+///
+/// `case` is not derived here: both backends dispatch on its constants directly, sharing the
+/// clause parsing in `case_clauses`.
+use super::{Node, Primitive};
+
+/// A parsed `case` form: the target, each clause's test constants and body, and the default
+pub struct CaseForm<'a> {
+    pub target: &'a Node,
+    pub clauses: Vec<(Vec<&'a Node>, &'a Node)>,
+    pub default: Option<&'a Node>,
+}
+
+/// Heads of the forms handled by `expand_form`
+pub const DERIVED_FORMS: &[&str] = &[
+    "cond",
+    "when",
+    "when-not",
+    "if-not",
+    "if-let",
+    "when-let",
+    "not=",
+    "min-key",
+    "max-key",
+    "->",
+    "->>",
+    "some->",
+    "as->",
+    "update",
+    "get-in",
+    "assoc-in",
+    "update-in",
+    "swap!",
+];
+
+/// Expand every derived form in a tree, recursing into all subforms
+pub fn expand(node: &Node) -> Result<Node, String> {
+    let mut counter = 0;
+    expand_node(node, &mut counter)
+}
+
+/// Expand a single derived form one step; the result may itself contain derived forms
+pub fn expand_form(head: &str, args: &[Node]) -> Result<Node, String> {
+    let mut counter = 0;
+    expand_with(head, args, &mut counter)
+}
+
+/// Split `(case target c1 e1 (c2 c3) e2 default?)` into its clauses. A list of constants
+/// matches any of them; a constant may only appear once.
+pub fn case_clauses(args: &[Node]) -> Result<CaseForm<'_>, String> {
+    let Some((target, rest)) = args.split_first() else {
+        return Err("case requires a target expression".to_string());
+    };
+
+    let mut clauses = Vec::new();
+    let mut seen: Vec<&Node> = Vec::new();
+    for pair in rest.chunks_exact(2) {
+        let constants: Vec<&Node> = match &pair[0] {
+            Node::List { root } => root.iter().collect(),
+            constant => vec![constant],
+        };
+        for constant in &constants {
+            if seen.contains(constant) {
+                return Err(format!("duplicate case test constant: {:?}", constant));
+            }
+            seen.push(constant);
+        }
+        clauses.push((constants, &pair[1]));
+    }
+
+    let default = if rest.len() % 2 == 0 { None } else { rest.last() };
+    Ok(CaseForm { target, clauses, default })
+}
+
+/// The expression a `case` without a default evaluates when no clause matches
+pub fn no_matching_clause() -> Node {
+    list(vec![
+        symbol("throw"),
+        list(vec![
+            symbol("ex-info"),
+            Node::Primitive {
+                value: Primitive::String("No matching clause".to_string()),
+            },
+            Node::Map { entries: Vec::new() },
+        ]),
+    ])
+}
+
+fn expand_node(node: &Node, counter: &mut usize) -> Result<Node, String> {
+    match node {
+        Node::List { root } => {
+            if let Some(Node::Symbol { value }) = root.first() {
+                // Quoted forms are data and keep their derived forms unexpanded
+                if value == "quote" || value == "syntax-quote" {
+                    return Ok(node.clone());
+                }
+                if DERIVED_FORMS.contains(&value.as_str()) {
+                    let expanded = expand_with(value, &root[1..], counter)?;
+                    return expand_node(&expanded, counter);
+                }
+                if value == "case" {
+                    return expand_case(root, counter);
+                }
+            }
+            if let Some(head @ (Node::Primitive { value: Primitive::Keyword(_) } | Node::Map { .. } | Node::Set { .. })) = root.first() {
+                let expanded = lookup_call(head, &root[1..], counter)?;
+                return expand_node(&expanded, counter);
+            }
+            Ok(list(root.iter().map(|child| expand_node(child, counter)).collect::<Result<_, _>>()?))
+        }
+        Node::Vector { root } => Ok(Node::Vector {
+            root: root.iter().map(|child| expand_node(child, counter)).collect::<Result<_, _>>()?,
+        }),
+        Node::Set { root } => Ok(Node::Set {
+            root: root.iter().map(|child| expand_node(child, counter)).collect::<Result<_, _>>()?,
+        }),
+        Node::Map { entries } => Ok(Node::Map {
+            entries: entries
+                .iter()
+                .map(|(key, value)| Ok((expand_node(key, counter)?, expand_node(value, counter)?)))
+                .collect::<Result<_, String>>()?,
+        }),
+        Node::Primitive { .. } | Node::Symbol { .. } => Ok(node.clone()),
+    }
+}
+
+/// Expand the target and bodies of a `case`; its test constants are data, so a list of keywords
+/// is not a keyword call
+fn expand_case(root: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let clause_count = root.len().saturating_sub(2);
+    let expanded = root
+        .iter()
+        .enumerate()
+        .map(|(index, child)| {
+            let is_constant = index >= 2 && (index - 2) % 2 == 0 && index - 2 + 1 < clause_count;
+            if index == 0 || is_constant {
+                Ok(child.clone())
+            } else {
+                expand_node(child, counter)
+            }
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(list(expanded))
+}
+
+/// Rewrite a call of a keyword, map or set into a lookup with an optional default:
+/// `(:k m d?)` and `(m k d?)` become `(get m k d?)`, and `(s x d?)` yields `x` when `s` contains it,
+/// binding `x` first unless it is a symbol or literal
+pub fn lookup_call(head: &Node, args: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let (argument, default) = match args {
+        [argument] => (argument, None),
+        [argument, default] => (argument, Some(default)),
+        _ => return Err("a keyword, map or set call takes a key and an optional default".to_string()),
+    };
+
+    match head {
+        Node::Primitive { value: Primitive::Keyword(_) } => Ok(list([symbol("get"), argument.clone(), head.clone()].into_iter().chain(default.cloned()).collect())),
+        Node::Set { .. } => Ok(set_lookup(head, argument, default, counter)),
+        _ => Ok(list([symbol("get"), head.clone(), argument.clone()].into_iter().chain(default.cloned()).collect())),
+    }
+}
+
+/// (if (contains? set x) x default), with `x` bound to a temporary unless it is a symbol or literal
+pub fn set_lookup(set: &Node, member: &Node, default: Option<&Node>, counter: &mut usize) -> Node {
+    let (member, bindings) = match member {
+        Node::Symbol { .. } | Node::Primitive { .. } => (member.clone(), None),
+        _ => {
+            let temp = gensym("member", counter);
+            (temp.clone(), Some(vec![temp, member.clone()]))
+        }
+    };
+    let test = list(vec![symbol("contains?"), set.clone(), member.clone()]);
+    let body = list(vec![symbol("if"), test, member, default.cloned().unwrap_or_else(|| symbol("nil"))]);
+    match bindings {
+        Some(bindings) => list(vec![symbol("let"), Node::Vector { root: bindings }, body]),
+        None => body,
+    }
+}
+
+fn expand_with(head: &str, args: &[Node], counter: &mut usize) -> Result<Node, String> {
+    match head {
+        "cond" => expand_cond(args),
+        "when" => match args {
+            [test, body] => Ok(list(vec![symbol("if"), test.clone(), body.clone()])),
+            _ => Err("when requires a test and a body".to_string()),
+        },
+        "when-not" => match args {
+            [test, body] => Ok(list(vec![symbol("if"), test.clone(), symbol("nil"), body.clone()])),
+            _ => Err("when-not requires a test and a body".to_string()),
+        },
+        "if-not" => match args {
+            [test, then] => Ok(list(vec![symbol("if"), test.clone(), symbol("nil"), then.clone()])),
+            [test, then, otherwise] => Ok(list(vec![symbol("if"), test.clone(), otherwise.clone(), then.clone()])),
+            _ => Err("if-not requires a test, a then branch and an optional else branch".to_string()),
+        },
+        "if-let" => match args {
+            [bindings, then] => expand_if_let(bindings, then, None, counter),
+            [bindings, then, otherwise] => expand_if_let(bindings, then, Some(otherwise), counter),
+            _ => Err("if-let requires a binding vector, a then branch and an optional else branch".to_string()),
+        },
+        "when-let" => match args {
+            [bindings, body] => expand_if_let(bindings, body, None, counter),
+            _ => Err("when-let requires a binding vector and a body".to_string()),
+        },
+        "not=" => match args {
+            [left, right] => Ok(list(vec![symbol("not"), list(vec![symbol("="), left.clone(), right.clone()])])),
+            _ => Err("not= requires two arguments".to_string()),
+        },
+        "min-key" => expand_extreme_key(args, "<=", counter),
+        "max-key" => expand_extreme_key(args, ">=", counter),
+        "->" => expand_thread(args, false),
+        "->>" => expand_thread(args, true),
+        "some->" => expand_some_thread(args, counter),
+        "as->" => expand_as_thread(args),
+        "update" => expand_update(args, counter),
+        "get-in" => expand_get_in(args),
+        "swap!" => expand_swap(args, counter),
+        "assoc-in" => match args {
+            [coll, path, value] => expand_in("assoc-in", coll, key_path("assoc-in", path)?, "assoc", std::slice::from_ref(value), counter),
+            _ => Err("assoc-in requires a collection, a key path and a value".to_string()),
+        },
+        "update-in" => match args {
+            [coll, path, function, extra @ ..] => {
+                let mut tail = vec![function.clone()];
+                tail.extend(extra.iter().cloned());
+                expand_in("update-in", coll, key_path("update-in", path)?, "update", &tail, counter)
+            }
+            _ => Err("update-in requires a collection, a key path and a function".to_string()),
+        },
+        _ => Ok(list(std::iter::once(symbol(head)).chain(args.iter().cloned()).collect())),
+    }
+}
+
+fn expand_cond(args: &[Node]) -> Result<Node, String> {
+    if args.len() % 2 != 0 {
+        return Err("cond requires an even number of forms".to_string());
+    }
+
+    let mut expanded: Option<Node> = None;
+    for clause in args.chunks(2).rev() {
+        let (test, expr) = (&clause[0], &clause[1]);
+        // `:else` and `true` always match, so the clause is just its expression
+        let always = matches!(test, Node::Primitive { value: Primitive::Keyword(_) } | Node::Primitive { value: Primitive::Boolean(true) });
+        expanded = Some(match (always, expanded) {
+            (true, _) => expr.clone(),
+            (false, Some(rest)) => list(vec![symbol("if"), test.clone(), expr.clone(), rest]),
+            (false, None) => list(vec![symbol("if"), test.clone(), expr.clone()]),
+        });
+    }
+    Ok(expanded.unwrap_or_else(|| symbol("nil")))
+}
+
+fn expand_if_let(bindings: &Node, then: &Node, otherwise: Option<&Node>, counter: &mut usize) -> Result<Node, String> {
+    let (pattern, value) = match bindings {
+        Node::Vector { root } if root.len() == 2 => (&root[0], &root[1]),
+        _ => return Err("if-let and when-let require a vector with exactly one binding".to_string()),
+    };
+
+    // A plain symbol with no else branch can be tested directly
+    if let (Node::Symbol { .. }, None) = (pattern, otherwise) {
+        return Ok(list(vec![
+            symbol("let"),
+            Node::Vector {
+                root: vec![pattern.clone(), value.clone()],
+            },
+            list(vec![symbol("if"), pattern.clone(), then.clone()]),
+        ]));
+    }
+
+    // Otherwise bind a temporary so the pattern is only bound (and destructured) on success
+    let temp = gensym("if_let", counter);
+    let mut branch = vec![
+        symbol("if"),
+        temp.clone(),
+        list(vec![
+            symbol("let"),
+            Node::Vector {
+                root: vec![pattern.clone(), temp.clone()],
+            },
+            then.clone(),
+        ]),
+    ];
+    branch.extend(otherwise.cloned());
+    Ok(list(vec![symbol("let"), Node::Vector { root: vec![temp, value.clone()] }, list(branch)]))
+}
+
+fn thread_into(form: &Node, value: Node, last: bool) -> Node {
+    match form {
+        Node::List { root } if !root.is_empty() => {
+            let mut threaded = root.clone();
+            if last {
+                threaded.push(value);
+            } else {
+                threaded.insert(1, value);
+            }
+            list(threaded)
+        }
+        _ => list(vec![form.clone(), value]),
+    }
+}
+
+fn expand_thread(args: &[Node], last: bool) -> Result<Node, String> {
+    let Some((initial, forms)) = args.split_first() else {
+        return Err("threading forms require an initial value".to_string());
+    };
+    Ok(forms.iter().fold(initial.clone(), |value, form| thread_into(form, value, last)))
+}
+
+fn expand_some_thread(args: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let Some((initial, forms)) = args.split_first() else {
+        return Err("some-> requires an initial value".to_string());
+    };
+    let Some((form, rest)) = forms.split_first() else {
+        return Ok(initial.clone());
+    };
+
+    // (let [t initial] (if (= t nil) nil (some-> (form t) rest...)))
+    let temp = gensym("some", counter);
+    let mut remaining = vec![symbol("some->"), thread_into(form, temp.clone(), false)];
+    remaining.extend(rest.iter().cloned());
+    let continuation = if rest.is_empty() { thread_into(form, temp.clone(), false) } else { list(remaining) };
+    Ok(list(vec![
+        symbol("let"),
+        Node::Vector {
+            root: vec![temp.clone(), initial.clone()],
+        },
+        list(vec![symbol("if"), list(vec![symbol("="), temp, symbol("nil")]), symbol("nil"), continuation]),
+    ]))
+}
+
+fn expand_as_thread(args: &[Node]) -> Result<Node, String> {
+    let [initial, name @ Node::Symbol { .. }, forms @ ..] = args else {
+        return Err("as-> requires an initial value and a binding symbol".to_string());
+    };
+
+    let mut bindings = vec![name.clone(), initial.clone()];
+    let body = match forms.split_last() {
+        Some((last, steps)) => {
+            steps.iter().for_each(|step| bindings.extend([name.clone(), step.clone()]));
+            last.clone()
+        }
+        None => name.clone(),
+    };
+    Ok(list(vec![symbol("let"), Node::Vector { root: bindings }, body]))
+}
+
+/// `min-key`/`max-key`: the item whose key wins under `test`, a later item winning ties
+fn expand_extreme_key(args: &[Node], test: &str, counter: &mut usize) -> Result<Node, String> {
+    let [function, first, rest @ ..] = args else {
+        return Err("min-key and max-key require a key function and at least one item".to_string());
+    };
+    if rest.is_empty() {
+        return Ok(first.clone());
+    }
+
+    // (let [best a best_key (f best) item b item_key (f item)
+    //       best (if (test item_key best_key) item best) best_key (if ... item_key best_key) ...] best)
+    let mut bindings = Vec::new();
+    let function = match function {
+        Node::Symbol { .. } => function.clone(),
+        _ => {
+            let temp = gensym("key_fn", counter);
+            bindings.extend([temp.clone(), function.clone()]);
+            temp
+        }
+    };
+    let mut best = gensym("best", counter);
+    let mut best_key = gensym("best_key", counter);
+    bindings.extend([best.clone(), first.clone(), best_key.clone(), list(vec![function.clone(), best.clone()])]);
+    for item in rest {
+        let (item_temp, item_key) = (gensym("item", counter), gensym("item_key", counter));
+        bindings.extend([item_temp.clone(), item.clone(), item_key.clone(), list(vec![function.clone(), item_temp.clone()])]);
+
+        let wins = list(vec![symbol(test), item_key.clone(), best_key.clone()]);
+        let (next_best, next_key) = (gensym("best", counter), gensym("best_key", counter));
+        bindings.extend([
+            next_best.clone(),
+            list(vec![symbol("if"), wins.clone(), item_temp, best]),
+            next_key.clone(),
+            list(vec![symbol("if"), wins, item_key, best_key]),
+        ]);
+        (best, best_key) = (next_best, next_key);
+    }
+    Ok(list(vec![symbol("let"), Node::Vector { root: bindings }, best]))
+}
+
+fn expand_update(args: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let [coll, key, function, extra @ ..] = args else {
+        return Err("update requires a collection, a key and a function".to_string());
+    };
+
+    // (let [t coll] (assoc t key (f (get t key) extra...))), binding the key too unless it is a literal
+    let temp = gensym("update", counter);
+    let mut bindings = vec![temp.clone(), coll.clone()];
+    let key = match key {
+        Node::Primitive { .. } => key.clone(),
+        _ => {
+            let key_temp = gensym("update", counter);
+            bindings.extend([key_temp.clone(), key.clone()]);
+            key_temp
+        }
+    };
+    let mut call = vec![function.clone(), list(vec![symbol("get"), temp.clone(), key.clone()])];
+    call.extend(extra.iter().cloned());
+    Ok(list(vec![symbol("let"), Node::Vector { root: bindings }, list(vec![symbol("assoc"), temp, key, list(call)])]))
+}
+
+fn expand_swap(args: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let [atom, function, extra @ ..] = args else {
+        return Err("swap! requires an atom and a function".to_string());
+    };
+
+    // (let [t atom] (reset! t (f (deref t) extra...)))
+    let temp = gensym("swap", counter);
+    let mut call = vec![function.clone(), list(vec![symbol("deref"), temp.clone()])];
+    call.extend(extra.iter().cloned());
+    Ok(list(vec![
+        symbol("let"),
+        Node::Vector {
+            root: vec![temp.clone(), atom.clone()],
+        },
+        list(vec![symbol("reset!"), temp, list(call)]),
+    ]))
+}
+
+/// The keys of a literal, non-empty key path vector
+fn key_path<'a>(head: &str, path: &'a Node) -> Result<&'a [Node], String> {
+    match path {
+        Node::Vector { root } if !root.is_empty() => Ok(root),
+        _ => Err(format!("{} requires a non-empty literal key path vector", head)),
+    }
+}
+
+fn expand_get_in(args: &[Node]) -> Result<Node, String> {
+    let (coll, path, default) = match args {
+        [coll, path] => (coll, path, None),
+        [coll, path, default] => (coll, path, Some(default)),
+        _ => return Err("get-in requires a collection, a key path and an optional default".to_string()),
+    };
+
+    // (get (get coll k1) k2 default?), with only the outermost lookup taking the default
+    let keys = key_path("get-in", path)?;
+    let (last, inner) = keys.split_last().expect("key path is non-empty");
+    let target = inner.iter().fold(coll.clone(), |target, key| list(vec![symbol("get"), target, key.clone()]));
+    let mut lookup = vec![symbol("get"), target, last.clone()];
+    lookup.extend(default.cloned());
+    Ok(list(lookup))
+}
+
+/// `assoc-in`/`update-in`: the last key applies `op` directly, every other key rebuilds its level
+/// as (let [t coll] (assoc t k (head (get t k) rest tail...))). The collection is bound unless it
+/// is already a symbol, and the key unless it is a literal.
+fn expand_in(head: &str, coll: &Node, keys: &[Node], op: &str, tail: &[Node], counter: &mut usize) -> Result<Node, String> {
+    let (key, rest) = keys.split_first().expect("key path is non-empty");
+    if rest.is_empty() {
+        let mut call = vec![symbol(op), coll.clone(), key.clone()];
+        call.extend(tail.iter().cloned());
+        return Ok(list(call));
+    }
+
+    let mut bindings = Vec::new();
+    let coll = match coll {
+        Node::Symbol { .. } => coll.clone(),
+        _ => {
+            let temp = gensym(&head.replace('-', "_"), counter);
+            bindings.extend([temp.clone(), coll.clone()]);
+            temp
+        }
+    };
+    let key = match key {
+        Node::Primitive { .. } => key.clone(),
+        _ => {
+            let temp = gensym(&head.replace('-', "_"), counter);
+            bindings.extend([temp.clone(), key.clone()]);
+            temp
+        }
+    };
+    let mut nested = vec![symbol(head), list(vec![symbol("get"), coll.clone(), key.clone()]), Node::Vector { root: rest.to_vec() }];
+    nested.extend(tail.iter().cloned());
+    let body = list(vec![symbol("assoc"), coll, key, list(nested)]);
+    if bindings.is_empty() {
+        Ok(body)
+    } else {
+        Ok(list(vec![symbol("let"), Node::Vector { root: bindings }, body]))
+    }
+}
+
+fn gensym(prefix: &str, counter: &mut usize) -> Node {
+    let generated = symbol(&format!("{}__{}", prefix, counter));
+    *counter += 1;
+    generated
+}
+
+fn symbol(name: &str) -> Node {
+    Node::Symbol { value: name.to_string() }
+}
+
+fn list(nodes: Vec<Node>) -> Node {
+    Node::new_list_from_raw(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AstParser, AstParserTrt};
+
+    fn parse(input: &str) -> Node {
+        AstParser::parse_sexp_new_domain(input.as_bytes(), &mut 0)
+    }
+
+    #[test]
+    fn expands_cond_into_nested_ifs() {
+        let expanded = expand(&parse("(cond (< x 0) :neg (= x 0) :zero :else :pos)")).unwrap();
+        assert_eq!(expanded, parse("(if (< x 0) :neg (if (= x 0) :zero :pos))"));
+        assert_eq!(expand(&parse("(cond)")).unwrap(), parse("nil"));
+        assert!(expand(&parse("(cond x)")).is_err());
+    }
+
+    #[test]
+    fn expands_if_let_with_temporary() {
+        let expanded = expand(&parse("(if-let [[a b] v] (+ a b) 0)")).unwrap();
+        assert_eq!(expanded, parse("(let [if_let__0 v] (if if_let__0 (let [[a b] if_let__0] (+ a b)) 0))"));
+        assert_eq!(expand(&parse("(when-let [x (f)] x)")).unwrap(), parse("(let [x (f)] (if x x))"));
+    }
+
+    #[test]
+    fn expands_not_equal() {
+        assert_eq!(expand(&parse("(not= a [1 2])")).unwrap(), parse("(not (= a [1 2]))"));
+        assert!(expand(&parse("(not= a)")).is_err());
+    }
+
+    #[test]
+    fn expands_min_and_max_key() {
+        assert_eq!(expand(&parse("(max-key count a)")).unwrap(), parse("a"));
+        assert_eq!(
+            expand(&parse("(min-key count a b)")).unwrap(),
+            parse(
+                "(let [best__0 a best_key__1 (count best__0) item__2 b item_key__3 (count item__2) \
+                 best__4 (if (<= item_key__3 best_key__1) item__2 best__0) \
+                 best_key__5 (if (<= item_key__3 best_key__1) item_key__3 best_key__1)] best__4)"
+            )
+        );
+        assert!(expand(&parse("(max-key count)")).is_err());
+    }
+
+    #[test]
+    fn expands_threading_forms() {
+        assert_eq!(expand(&parse("(-> x (f 1) g)")).unwrap(), parse("(g (f x 1))"));
+        assert_eq!(expand(&parse("(->> x (f 1) g)")).unwrap(), parse("(g (f 1 x))"));
+        assert_eq!(expand(&parse("(as-> 1 n (+ n 1) (* n n))")).unwrap(), parse("(let [n 1 n (+ n 1)] (* n n))"));
+        assert_eq!(
+            expand(&parse("(some-> m (get :a) (get :b))")).unwrap(),
+            parse("(let [some__0 m] (if (= some__0 nil) nil (let [some__1 (get some__0 :a)] (if (= some__1 nil) nil (get some__1 :b)))))")
+        );
+    }
+
+    #[test]
+    fn expands_update_into_assoc() {
+        assert_eq!(expand(&parse("(update v 0 inc)")).unwrap(), parse("(let [update__0 v] (assoc update__0 0 (inc (get update__0 0))))"));
+        assert_eq!(
+            expand(&parse("(update m k + 1 2)")).unwrap(),
+            parse("(let [update__0 m update__1 k] (assoc update__0 update__1 (+ (get update__0 update__1) 1 2)))")
+        );
+        assert!(expand(&parse("(update v 0)")).is_err());
+    }
+
+    #[test]
+    fn expands_swap_into_reset() {
+        assert_eq!(expand(&parse("(swap! counter inc)")).unwrap(), parse("(let [swap__0 counter] (reset! swap__0 (inc (deref swap__0))))"));
+        assert_eq!(expand(&parse("(swap! a + 1 2)")).unwrap(), parse("(let [swap__0 a] (reset! swap__0 (+ (deref swap__0) 1 2)))"));
+        assert!(expand(&parse("(swap! a)")).is_err());
+    }
+
+    #[test]
+    fn expands_nested_key_paths() {
+        assert_eq!(expand(&parse("(get-in m [:a :b])")).unwrap(), parse("(get (get m :a) :b)"));
+        assert_eq!(expand(&parse("(get-in m [:a] 0)")).unwrap(), parse("(get m :a 0)"));
+        assert_eq!(expand(&parse("(assoc-in m [:a :b] 1)")).unwrap(), parse("(assoc m :a (assoc (get m :a) :b 1))"));
+        assert_eq!(
+            expand(&parse("(assoc-in (f) [k :b] 1)")).unwrap(),
+            parse("(let [assoc_in__0 (f) assoc_in__1 k] (assoc assoc_in__0 assoc_in__1 (assoc (get assoc_in__0 assoc_in__1) :b 1)))")
+        );
+        assert_eq!(
+            expand(&parse("(update-in m [:a :b] + 1)")).unwrap(),
+            parse("(assoc m :a (let [update__0 (get m :a)] (assoc update__0 :b (+ (get update__0 :b) 1))))")
+        );
+        assert!(expand(&parse("(get-in m [])")).is_err());
+        assert!(expand(&parse("(assoc-in m ks 1)")).is_err());
+    }
+
+    #[test]
+    fn expands_collection_calls_into_lookups() {
+        assert_eq!(expand(&parse("(:name user)")).unwrap(), parse("(get user :name)"));
+        assert_eq!(expand(&parse("(:port config 80)")).unwrap(), parse("(get config :port 80)"));
+        assert_eq!(expand(&parse("({:a 1} k)")).unwrap(), parse("(get {:a 1} k)"));
+        assert_eq!(expand(&parse("(#{1 2} x 0)")).unwrap(), parse("(if (contains? #{1 2} x) x 0)"));
+        assert_eq!(expand(&parse("(#{1 2} (f))")).unwrap(), parse("(let [member__0 (f)] (if (contains? #{1 2} member__0) member__0 nil))"));
+        assert_eq!(expand(&parse("(-> user :address :city)")).unwrap(), parse("(get (get user :address) :city)"));
+        assert_eq!(expand(&parse("(case k (:a :b) (:x m) 0)")).unwrap(), parse("(case k (:a :b) (get m :x) 0)"));
+        assert!(expand(&parse("(:name)")).is_err());
+    }
+}
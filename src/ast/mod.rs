@@ -3,7 +3,13 @@
 /// This module defines the AST data structures and parser for SLisp.
 /// - AST node types (Node, Primitive)
 /// - Parser to convert source text to AST
+/// - Destructuring desugaring shared by the evaluator and compiler
+/// - Derived conditional and threading forms expanded into `if` and `let`
+/// - Pattern parsing for `match`, shared by the evaluator and compiler
+pub mod destructure;
+pub mod forms;
 pub mod parser;
+pub mod patterns;
 
 // Re-export the main types for convenience
 pub use parser::{parse_file, AstParser, AstParserTrt};
@@ -28,10 +28,19 @@ impl AstParser {
     fn parse_container(input: &[u8], offset: &mut usize, inside_container: bool, kind: ContainerKind) -> Node {
         let mut buffer = String::new();
         let mut sexp = vec![];
-
-        let flush_buffer = |buffer: &mut String, sexp: &mut Vec<Node>| {
+        // Reader macros (', `, ~, ~@) waiting for the form they apply to, innermost last
+        let mut prefixes: Vec<&'static str> = Vec::new();
+
+        let push_form = |sexp: &mut Vec<Node>, prefixes: &mut Vec<&'static str>, node: Node| {
+            let wrapped = prefixes
+                .drain(..)
+                .rev()
+                .fold(node, |form, prefix| Node::new_list_from_raw(vec![Node::Symbol { value: prefix.to_string() }, form]));
+            sexp.push(wrapped);
+        };
+        let flush_buffer = |buffer: &mut String, sexp: &mut Vec<Node>, prefixes: &mut Vec<&'static str>| {
             if !buffer.is_empty() {
-                sexp.push(Self::parse_atom(buffer.as_str()));
+                push_form(sexp, prefixes, Self::parse_atom(buffer.as_str()));
                 buffer.clear();
             }
         };
@@ -40,52 +49,80 @@ impl AstParser {
             let c = input[*offset] as char;
             match c {
                 '(' => {
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
                     *offset += 1;
-                    sexp.push(Self::parse_container(input, offset, true, ContainerKind::List));
+                    let container = Self::parse_container(input, offset, true, ContainerKind::List);
+                    push_form(&mut sexp, &mut prefixes, container);
                 }
                 '[' => {
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
                     *offset += 1;
-                    sexp.push(Self::parse_container(input, offset, true, ContainerKind::Vector));
+                    let container = Self::parse_container(input, offset, true, ContainerKind::Vector);
+                    push_form(&mut sexp, &mut prefixes, container);
                 }
+                // Inside a symbol, `#` is the auto-gensym suffix of syntax-quote (`name#`)
+                '#' if !buffer.is_empty() => buffer.push(c),
                 '#' => {
-                    flush_buffer(&mut buffer, &mut sexp);
                     if *offset + 1 >= input.len() || input[*offset + 1] as char != '{' {
                         panic!("Unexpected # sequence");
                     }
                     *offset += 2;
-                    sexp.push(Self::parse_container(input, offset, true, ContainerKind::Set));
+                    let container = Self::parse_container(input, offset, true, ContainerKind::Set);
+                    push_form(&mut sexp, &mut prefixes, container);
                 }
                 '{' => {
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
                     *offset += 1;
-                    sexp.push(Self::parse_container(input, offset, true, ContainerKind::Map));
+                    let container = Self::parse_container(input, offset, true, ContainerKind::Map);
+                    push_form(&mut sexp, &mut prefixes, container);
+                }
+                // Inside a symbol, `'` is part of the name (`x'`)
+                '\'' if !buffer.is_empty() => buffer.push(c),
+                '\'' => prefixes.push("quote"),
+                // Inside a symbol, `@` is part of the name
+                '@' if !buffer.is_empty() => buffer.push(c),
+                '@' => prefixes.push("deref"),
+                '`' => {
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
+                    prefixes.push("syntax-quote");
+                }
+                '~' => {
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
+                    if *offset + 1 < input.len() && input[*offset + 1] as char == '@' {
+                        *offset += 1;
+                        prefixes.push("unquote-splicing");
+                    } else {
+                        prefixes.push("unquote");
+                    }
                 }
                 '"' => {
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
                     *offset += 1;
-                    sexp.push(Self::parse_string_literal(input, offset));
+                    let literal = Self::parse_string_literal(input, offset);
+                    push_form(&mut sexp, &mut prefixes, literal);
                 }
                 ')' => {
                     if !inside_container || kind != ContainerKind::List {
                         panic!("Unexpected closing parenthesis");
                     }
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
+                    expect_no_prefixes(&prefixes);
                     return Node::new_list_from_raw(sexp);
                 }
                 ']' => {
                     if !inside_container || kind != ContainerKind::Vector {
                         panic!("Unexpected closing bracket");
                     }
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
+                    expect_no_prefixes(&prefixes);
                     return Node::new_vector_from_raw(sexp);
                 }
                 '}' => {
                     if !inside_container || (kind != ContainerKind::Map && kind != ContainerKind::Set) {
                         panic!("Unexpected closing brace");
                     }
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
+                    expect_no_prefixes(&prefixes);
                     if kind == ContainerKind::Map {
                         if sexp.len() % 2 != 0 {
                             panic!("Map literal requires key/value pairs");
@@ -102,12 +139,12 @@ impl AstParser {
                     }
                 }
                 ';' => {
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
                     *offset = skip_comment(input, *offset);
                     continue;
                 }
                 c if c.is_whitespace() => {
-                    flush_buffer(&mut buffer, &mut sexp);
+                    flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
                 }
                 _ => {
                     buffer.push(c);
@@ -121,7 +158,8 @@ impl AstParser {
             panic!("Unclosed container");
         }
 
-        flush_buffer(&mut buffer, &mut sexp);
+        flush_buffer(&mut buffer, &mut sexp, &mut prefixes);
+        expect_no_prefixes(&prefixes);
 
         if sexp.is_empty() {
             panic!("No expression found");
@@ -302,6 +340,20 @@ mod tests {
         assert_eq!(parsed, Node::Symbol { value: "abc123def".to_string() });
     }
 
+    #[test]
+    fn parse_deref_reader_macro() {
+        let parsed = AstParser::parse_sexp_new_domain(b"(+ @a @(f))", &mut 0);
+        let deref = |form: Node| Node::new_list_from_raw(vec![Node::Symbol { value: "deref".to_string() }, form]);
+        assert_eq!(
+            parsed,
+            Node::new_list_from_raw(vec![
+                Node::Symbol { value: "+".to_string() },
+                deref(Node::Symbol { value: "a".to_string() }),
+                deref(Node::new_list_from_raw(vec![Node::Symbol { value: "f".to_string() }])),
+            ])
+        );
+    }
+
     #[test]
     #[should_panic]
     fn parse_empty_input() {
@@ -379,6 +431,45 @@ mod tests {
         assert_eq!(parsed, Node::new_map_from_raw(vec![]));
     }
 
+    #[test]
+    fn parse_syntax_quote_reader_macros() {
+        let parsed = AstParser::parse_sexp_new_domain(b"`(let [v# ~x] ~@body)", &mut 0);
+        let wrap = |head: &str, form: Node| Node::new_list_from_raw(vec![Node::Symbol { value: head.to_string() }, form]);
+        let symbol = |name: &str| Node::Symbol { value: name.to_string() };
+        assert_eq!(
+            parsed,
+            wrap(
+                "syntax-quote",
+                Node::new_list_from_raw(vec![
+                    symbol("let"),
+                    Node::new_vector_from_raw(vec![symbol("v#"), wrap("unquote", symbol("x"))]),
+                    wrap("unquote-splicing", symbol("body"))
+                ])
+            )
+        );
+    }
+
+    #[test]
+    fn parse_quote_reader_macro() {
+        let parsed = AstParser::parse_sexp_new_domain(b"(f 'a '(1 b) x')", &mut 0);
+        let quote = |form: Node| Node::new_list_from_raw(vec![Node::Symbol { value: "quote".to_string() }, form]);
+        assert_eq!(
+            parsed,
+            Node::new_list_from_raw(vec![
+                Node::Symbol { value: "f".to_string() },
+                quote(Node::Symbol { value: "a".to_string() }),
+                quote(Node::new_list_from_raw(vec![Node::Primitive { value: Primitive::Number(1) }, Node::Symbol { value: "b".to_string() }])),
+                Node::Symbol { value: "x'".to_string() },
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_reader_macro_without_form() {
+        AstParser::parse_sexp_new_domain(b"(a ~)", &mut 0);
+    }
+
     #[test]
     fn parse_set_literal() {
         let parsed = AstParser::parse_sexp_new_domain(b"#{1 2}", &mut 0);
@@ -542,6 +633,8 @@ fn skip_whitespace(bytes: &[u8], offset: usize) -> usize {
 fn find_expression_end(bytes: &[u8], offset: usize) -> Result<usize, String> {
     match bytes[offset] {
         b'(' | b'[' | b'{' => find_delimited_expression_end(bytes, offset),
+        // Reader macros and set literals extend over the form that follows them
+        b'\'' | b'`' | b'~' | b'@' | b'#' if offset + 1 < bytes.len() => find_expression_end(bytes, offset + 1),
         b';' => {
             // Skip comments at top level
             let next_offset = skip_comment(bytes, offset);
@@ -671,6 +764,13 @@ fn skip_string_literal_boundary(bytes: &[u8], offset: usize) -> Result<usize, St
     Err("Unterminated string literal".to_string())
 }
 
+/// Reader macros must be followed by a form before the enclosing container closes
+fn expect_no_prefixes(prefixes: &[&str]) {
+    if !prefixes.is_empty() {
+        panic!("Reader macro without a form");
+    }
+}
+
 /// Skip a comment (from ';' to end of line)
 fn skip_comment(bytes: &[u8], offset: usize) -> usize {
     let mut offset = offset;
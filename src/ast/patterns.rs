@@ -0,0 +1,161 @@
+/// Pattern matching clauses
+///
+/// Parses `(match target pattern body ...)` for both backends. Each clause is a pattern, an
+/// optional `:when` guard and a body; the first clause whose pattern matches and whose guard is
+/// truthy runs, with the pattern's symbols bound. Patterns are:
+/// - `_`, matching anything, and `:else` as a whole clause pattern
+/// - a symbol, matching anything and binding it
+/// - a number, string, keyword, boolean or `nil`, matching an equal value
+/// - a vector of patterns, matching a vector of exactly that many items, or of at least that many
+///   when it ends in `& rest`; the rest pattern matches the remaining items as a vector
+/// - a map of keys to patterns, matching a map that holds every key with a value matching its
+///   pattern (`{:k _}` only requires the key)
+use super::{Node, Primitive};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    Bind(String),
+    Literal(Node),
+    Vector { items: Vec<Pattern>, rest: Option<Box<Pattern>> },
+    Map(Vec<(Node, Pattern)>), // Literal key and the pattern its value must match
+}
+
+pub struct MatchClause<'a> {
+    pub pattern: Pattern,
+    pub guard: Option<&'a Node>,
+    pub body: &'a Node,
+}
+
+/// A parsed `match` form: the target and its clauses in order
+pub struct MatchForm<'a> {
+    pub target: &'a Node,
+    pub clauses: Vec<MatchClause<'a>>,
+}
+
+/// Split `(match target p1 e1 p2 :when g2 e2 ...)` into its clauses
+pub fn match_clauses(args: &[Node]) -> Result<MatchForm<'_>, String> {
+    let Some((target, mut rest)) = args.split_first() else {
+        return Err("match requires a target expression".to_string());
+    };
+
+    let mut clauses = Vec::new();
+    while let Some((pattern, after)) = rest.split_first() {
+        let (guard, after) = match after {
+            [keyword, guard, after @ ..] if is_keyword(keyword, "when") => (Some(guard), after),
+            _ => (None, after),
+        };
+        let Some((body, after)) = after.split_first() else {
+            return Err("match clauses need a pattern, an optional :when guard and a body".to_string());
+        };
+        let pattern = if is_keyword(pattern, "else") { Pattern::Wildcard } else { parse_pattern(pattern)? };
+        let mut names = Vec::new();
+        bound_names(&pattern, &mut names)?;
+        clauses.push(MatchClause { pattern, guard, body });
+        rest = after;
+    }
+    Ok(MatchForm { target, clauses })
+}
+
+/// Parse one pattern
+pub fn parse_pattern(node: &Node) -> Result<Pattern, String> {
+    match node {
+        Node::Symbol { value } if value == "_" => Ok(Pattern::Wildcard),
+        Node::Symbol { value } if value == "nil" => Ok(Pattern::Literal(node.clone())),
+        Node::Symbol { value } if value == "&" => Err("& may only precede the rest pattern of a vector pattern".to_string()),
+        Node::Symbol { value } => Ok(Pattern::Bind(value.clone())),
+        Node::Primitive { .. } => Ok(Pattern::Literal(node.clone())),
+        Node::Vector { root } => {
+            let (items, rest) = match root.iter().position(|item| matches!(item, Node::Symbol { value } if value == "&")) {
+                Some(index) if index + 2 == root.len() => (&root[..index], Some(Box::new(parse_pattern(&root[index + 1])?))),
+                Some(_) => return Err("& must be followed by exactly one rest pattern at the end of a vector pattern".to_string()),
+                None => (&root[..], None),
+            };
+            let items = items.iter().map(parse_pattern).collect::<Result<_, _>>()?;
+            Ok(Pattern::Vector { items, rest })
+        }
+        Node::Map { entries } => entries
+            .iter()
+            .map(|(key, value)| match key {
+                Node::Primitive { .. } => Ok((key.clone(), parse_pattern(value)?)),
+                Node::Symbol { value: name } if name == "nil" => Ok((key.clone(), parse_pattern(value)?)),
+                _ => Err("map pattern keys must be literals".to_string()),
+            })
+            .collect::<Result<_, _>>()
+            .map(Pattern::Map),
+        Node::List { .. } | Node::Set { .. } => Err("match patterns must be literals, symbols, vectors or maps".to_string()),
+    }
+}
+
+/// The names a pattern binds, in order; a name may only be bound once
+fn bound_names(pattern: &Pattern, names: &mut Vec<String>) -> Result<(), String> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Literal(_) => Ok(()),
+        Pattern::Bind(name) if names.contains(name) => Err(format!("match pattern binds {} more than once", name)),
+        Pattern::Bind(name) => {
+            names.push(name.clone());
+            Ok(())
+        }
+        Pattern::Vector { items, rest } => items.iter().chain(rest.as_deref()).try_for_each(|item| bound_names(item, names)),
+        Pattern::Map(entries) => entries.iter().try_for_each(|(_, value)| bound_names(value, names)),
+    }
+}
+
+/// Whether any clause's pattern binds `name`
+pub fn binds_name(form: &MatchForm<'_>, name: &str) -> bool {
+    let mut names = Vec::new();
+    form.clauses.iter().any(|clause| {
+        names.clear();
+        bound_names(&clause.pattern, &mut names).is_ok() && names.iter().any(|bound| bound == name)
+    })
+}
+
+fn is_keyword(node: &Node, name: &str) -> bool {
+    matches!(node, Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AstParser, AstParserTrt};
+
+    fn parse(input: &str) -> Node {
+        AstParser::parse_sexp_new_domain(input.as_bytes(), &mut 0)
+    }
+
+    fn clauses(input: &str) -> Result<Vec<(Pattern, bool)>, String> {
+        let Node::List { root } = parse(input) else { unreachable!("match forms are lists") };
+        let form = match_clauses(&root[1..])?;
+        Ok(form.clauses.into_iter().map(|clause| (clause.pattern, clause.guard.is_some())).collect())
+    }
+
+    #[test]
+    fn parses_clauses_with_guards() {
+        let parsed = clauses("(match v [x & more] :when (> x 0) x {:k _} 1 :else 0)").unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                (
+                    Pattern::Vector {
+                        items: vec![Pattern::Bind("x".to_string())],
+                        rest: Some(Box::new(Pattern::Bind("more".to_string()))),
+                    },
+                    true
+                ),
+                (Pattern::Map(vec![(parse(":k"), Pattern::Wildcard)]), false),
+                (Pattern::Wildcard, false),
+            ]
+        );
+        assert_eq!(clauses("(match v nil 0 [:a] 1)").unwrap()[0].0, Pattern::Literal(parse("nil")));
+    }
+
+    #[test]
+    fn rejects_malformed_clauses() {
+        assert!(clauses("(match)").is_err());
+        assert!(clauses("(match v 1)").is_err());
+        assert!(clauses("(match v [a & b c] 1)").is_err());
+        assert!(clauses("(match v [a a] 1)").is_err());
+        assert!(clauses("(match v {k 1} 1)").is_err());
+        assert!(clauses("(match v (a b) 1)").is_err());
+    }
+}
@@ -13,6 +13,9 @@ pub fn compile_file_to_executable(input_file: &str, output_file: &str, keep_obje
     let expressions = parse_file(&file_content)?;
     let mut ir_program = compile_program(&expressions).map_err(|e| format_compile_error(&e))?;
     ir_program.telemetry_enabled = trace_allocations;
+    for warning in &ir_program.warnings {
+        eprintln!("Warning: {}", warning);
+    }
 
     let target = detect_host_target();
     let object = compile_to_object(&ir_program, target);
@@ -25,3 +28,24 @@ pub fn compile_file_to_executable(input_file: &str, output_file: &str, keep_obje
 
     Ok(())
 }
+
+#[cfg(all(test, feature = "allocator-telemetry"))]
+mod tests {
+    use super::compile_file_to_executable;
+    use std::process::Command;
+
+    #[test]
+    fn caught_exceptions_leave_no_outstanding_allocations() {
+        let output_path = std::env::temp_dir().join(format!("slisp_exceptions_caught_{}", std::process::id()));
+        let output_file = output_path.to_str().unwrap();
+        compile_file_to_executable("tests/programs/memory/exceptions_caught.slisp", output_file, false, true).unwrap();
+
+        let run = Command::new(output_file).output().unwrap();
+        let _ = std::fs::remove_file(&output_path);
+
+        assert_eq!(run.status.code(), Some(0));
+        let log = format!("{}{}", String::from_utf8_lossy(&run.stdout), String::from_utf8_lossy(&run.stderr));
+        let summary = log.lines().find(|line| line.starts_with("[allocator] summary")).expect("expected allocator summary");
+        assert!(summary.contains(" outstanding=0 "), "{}", summary);
+    }
+}
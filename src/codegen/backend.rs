@@ -56,10 +56,18 @@ pub struct RuntimeAddresses {
     pub string_from_number: Option<usize>,
     pub string_from_boolean: Option<usize>,
     pub string_equals: Option<usize>,
+    pub string_hash: Option<usize>,
     pub map_value_clone: Option<usize>,
     pub map_free: Option<usize>,
     pub set_free: Option<usize>,
     pub vector_free: Option<usize>,
+    pub exception_push_handler: Option<usize>,
+    pub exception_pop_handler: Option<usize>,
+    pub exception_throw: Option<usize>,
+    pub exception_is_info: Option<usize>,
+    pub exception_value_clone: Option<usize>,
+    pub exception_free: Option<usize>,
+    pub arithmetic_error: Option<usize>,
 }
 
 /// Code generation backend trait for different target architectures
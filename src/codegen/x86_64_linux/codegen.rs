@@ -63,10 +63,18 @@ impl X86CodeGen {
                 string_from_number: Some(slisp_runtime::_string_from_number as usize),
                 string_from_boolean: Some(slisp_runtime::_string_from_boolean as usize),
                 string_equals: Some(slisp_runtime::_string_equals as usize),
+                string_hash: Some(slisp_runtime::_string_hash as *const () as usize),
                 map_value_clone: Some(slisp_runtime::_map_value_clone as usize),
                 map_free: Some(slisp_runtime::_map_free as usize),
                 set_free: Some(slisp_runtime::_set_free as usize),
                 vector_free: Some(slisp_runtime::_vector_free as usize),
+                exception_push_handler: Some(slisp_runtime::_exception_push_handler as *const () as usize),
+                exception_pop_handler: Some(slisp_runtime::_exception_pop_handler as *const () as usize),
+                exception_throw: Some(slisp_runtime::_exception_throw as *const () as usize),
+                exception_is_info: Some(slisp_runtime::_exception_is_info as *const () as usize),
+                exception_value_clone: Some(slisp_runtime::_exception_value_clone as *const () as usize),
+                exception_free: Some(slisp_runtime::_exception_free as *const () as usize),
+                arithmetic_error: Some(slisp_runtime::_arithmetic_error as *const () as usize),
             },
             LinkMode::ObjFile => RuntimeAddresses {
                 heap_init: None,
@@ -81,10 +89,18 @@ impl X86CodeGen {
                 string_from_number: None,
                 string_from_boolean: None,
                 string_equals: None,
+                string_hash: None,
                 map_value_clone: None,
                 map_free: None,
                 set_free: None,
                 vector_free: None,
+                exception_push_handler: None,
+                exception_pop_handler: None,
+                exception_throw: None,
+                exception_is_info: None,
+                exception_value_clone: None,
+                exception_free: None,
+                arithmetic_error: None,
             },
         };
 
@@ -244,6 +260,15 @@ impl X86CodeGen {
         code
     }
 
+    /// Record the `_arithmetic_error` calls of a checked arithmetic sequence
+    fn generate_checked_arithmetic_code(&mut self, (code, error_calls): (Vec<u8>, Vec<usize>)) -> Vec<u8> {
+        let current_pos = self.code.len();
+        for disp in error_calls {
+            self.record_runtime_relocation(current_pos + disp, "_arithmetic_error");
+        }
+        code
+    }
+
     /// Generate x86-64 machine code from IR program
     pub fn generate(&mut self, program: &IRProgram) -> Vec<u8> {
         if !program.functions.is_empty() && program.entry_point.is_some() {
@@ -358,16 +383,20 @@ impl X86CodeGen {
                 }
                 code
             }
-            IRInstruction::Add => instructions::generate_add(),
-            IRInstruction::Sub => instructions::generate_sub(),
-            IRInstruction::Mul => instructions::generate_mul(),
-            IRInstruction::Div => instructions::generate_div(),
+            IRInstruction::Add => self.generate_checked_arithmetic_code(instructions::generate_checked_add()),
+            IRInstruction::Sub => self.generate_checked_arithmetic_code(instructions::generate_checked_sub()),
+            IRInstruction::Mul => self.generate_checked_arithmetic_code(instructions::generate_checked_mul()),
+            IRInstruction::Div => self.generate_checked_arithmetic_code(instructions::generate_checked_div()),
+            IRInstruction::UncheckedAdd => instructions::generate_add(),
+            IRInstruction::UncheckedSub => instructions::generate_sub(),
+            IRInstruction::UncheckedMul => instructions::generate_mul(),
             IRInstruction::Equal => instructions::generate_equal(),
             IRInstruction::Less => instructions::generate_less(),
             IRInstruction::Greater => instructions::generate_greater(),
             IRInstruction::LessEqual => instructions::generate_less_equal(),
             IRInstruction::GreaterEqual => instructions::generate_greater_equal(),
             IRInstruction::Not => instructions::generate_not(),
+            IRInstruction::LoadField(offset) => instructions::generate_load_field(*offset),
             IRInstruction::LoadParam(slot) => instructions::generate_load_param(*slot),
             IRInstruction::StoreLocal(slot) => instructions::generate_store_local(*slot, func_info),
             IRInstruction::LoadLocal(slot) => instructions::generate_load_local(*slot, func_info),
@@ -380,6 +409,8 @@ impl X86CodeGen {
                 code
             }
 
+            IRInstruction::PushFunction(func_name) => instructions::generate_push_function_address(func_name, &self.function_addresses, self.code.len()),
+
             IRInstruction::InitHeap => {
                 let current_pos = self.code.len();
                 self.generate_heap_init_code(current_pos)
@@ -431,6 +462,36 @@ impl X86CodeGen {
                 pending_jumps.push(PendingJump { target: *target, patch_offset });
                 code
             }
+
+            IRInstruction::JumpTable(targets) => {
+                let current_pos = self.code.len();
+                let (code, disp_offsets) = instructions::generate_jump_table(targets.len());
+                for (target, disp_offset) in targets.iter().zip(disp_offsets) {
+                    pending_jumps.push(PendingJump {
+                        target: *target,
+                        patch_offset: current_pos + disp_offset,
+                    });
+                }
+                code
+            }
+
+            IRInstruction::PushHandler(landing) => {
+                let current_pos = self.code.len();
+                let (code, landing_disp_offset, call_disp_offset) = instructions::generate_push_handler();
+                pending_jumps.push(PendingJump {
+                    target: *landing,
+                    patch_offset: current_pos + landing_disp_offset,
+                });
+                self.record_runtime_relocation(current_pos + call_disp_offset, "_exception_push_handler");
+                code
+            }
+
+            IRInstruction::PopHandler => {
+                let current_pos = self.code.len();
+                let (code, call_disp_offset) = instructions::generate_pop_handler();
+                self.record_runtime_relocation(current_pos + call_disp_offset, "_exception_pop_handler");
+                code
+            }
         };
 
         code
@@ -597,6 +658,25 @@ mod tests {
         assert_eq!(result, 42);
     }
 
+    #[test]
+    fn jit_dispatches_through_jump_table() {
+        for (index, expected) in [(0, 10), (1, 20), (5, 9)] {
+            let mut program = IRProgram::new();
+            program.add_instruction(IRInstruction::Push(index));
+            program.add_instruction(IRInstruction::JumpTable(vec![4, 6]));
+            program.add_instruction(IRInstruction::Push(9));
+            program.add_instruction(IRInstruction::Jump(8));
+            program.add_instruction(IRInstruction::Push(10));
+            program.add_instruction(IRInstruction::Jump(8));
+            program.add_instruction(IRInstruction::Push(20));
+            program.add_instruction(IRInstruction::Jump(8));
+            program.add_instruction(IRInstruction::Return);
+
+            let artifact = compile_to_executable(&program);
+            assert_eq!(JitRunner::exec_artifact(&artifact), expected);
+        }
+    }
+
     #[test]
     fn jit_executes_if_false_branch() {
         let mut program = IRProgram::new();
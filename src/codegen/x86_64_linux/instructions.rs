@@ -7,10 +7,15 @@ use std::collections::HashMap;
 pub fn generate_push(value: i64) -> Vec<u8> {
     if (-128..=127).contains(&value) {
         vec![0x6a, value as u8] // push imm8
-    } else {
-        let mut code = vec![0x68]; // push imm32
+    } else if i32::try_from(value).is_ok() {
+        let mut code = vec![0x68]; // push imm32 (sign-extended)
         code.extend_from_slice(&(value as u32).to_le_bytes());
         code
+    } else {
+        let mut code = vec![0x48, 0xb8]; // movabs rax, imm64
+        code.extend_from_slice(&value.to_le_bytes());
+        code.push(0x50); // push rax
+        code
     }
 }
 
@@ -62,14 +67,71 @@ pub fn generate_div() -> Vec<u8> {
     vec![
         0x58, // pop rax (divisor)
         0x5b, // pop rbx (dividend)
-        0x48, 0x89, 0xd8, // mov rax, rbx
         0x48, 0x89, 0xc1, // mov rcx, rax
+        0x48, 0x89, 0xd8, // mov rax, rbx
         0x48, 0x99, // cqo (sign extend)
         0x48, 0xf7, 0xf9, // idiv rcx
         0x50, // push rax
     ]
 }
 
+const ARITHMETIC_DIVIDE_BY_ZERO: u32 = 0;
+const ARITHMETIC_OVERFLOW: u32 = 1;
+
+/// Emit `mov edi, kind; call _arithmetic_error` (10 bytes), returning the call displacement offset
+fn emit_arithmetic_error(code: &mut Vec<u8>, kind: u32) -> usize {
+    code.push(0xbf); // mov edi, imm32
+    code.extend_from_slice(&kind.to_le_bytes());
+    code.push(0xe8); // call _arithmetic_error
+    let call_disp_offset = code.len();
+    code.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    call_disp_offset
+}
+
+/// Insert an overflow check between a wrapping operation and the push of its result
+fn with_overflow_check(mut op: Vec<u8>) -> (Vec<u8>, Vec<usize>) {
+    let push_result = op.pop().expect("arithmetic op ends with a push");
+    op.extend_from_slice(&[0x71, 0x0a]); // jno over the error call
+    let call_disp_offset = emit_arithmetic_error(&mut op, ARITHMETIC_OVERFLOW);
+    op.push(push_result);
+    (op, vec![call_disp_offset])
+}
+
+/// Generate machine code for addition that raises an arithmetic error on overflow
+/// Returns (code bytes, offsets of `_arithmetic_error` call displacements)
+pub fn generate_checked_add() -> (Vec<u8>, Vec<usize>) {
+    with_overflow_check(generate_add())
+}
+
+/// Generate machine code for subtraction that raises an arithmetic error on overflow
+pub fn generate_checked_sub() -> (Vec<u8>, Vec<usize>) {
+    with_overflow_check(generate_sub())
+}
+
+/// Generate machine code for multiplication that raises an arithmetic error on overflow
+pub fn generate_checked_mul() -> (Vec<u8>, Vec<usize>) {
+    with_overflow_check(generate_mul())
+}
+
+/// Generate machine code for division that raises an arithmetic error on a zero divisor or on
+/// `i64::MIN / -1`, both of which would otherwise trap in `idiv`
+pub fn generate_checked_div() -> (Vec<u8>, Vec<usize>) {
+    let mut code = Vec::new();
+    code.extend_from_slice(&[0x48, 0x8b, 0x04, 0x24]); // mov rax, [rsp] (divisor)
+    code.extend_from_slice(&[0x48, 0x85, 0xc0]); // test rax, rax
+    code.extend_from_slice(&[0x75, 0x0a]); // jnz over the error call
+    let zero_call = emit_arithmetic_error(&mut code, ARITHMETIC_DIVIDE_BY_ZERO);
+    code.extend_from_slice(&[0x48, 0x83, 0xf8, 0xff]); // cmp rax, -1
+    code.extend_from_slice(&[0x75, 0x1b]); // jne past the overflow check
+    code.extend_from_slice(&[0x48, 0xb9]); // movabs rcx, i64::MIN
+    code.extend_from_slice(&i64::MIN.to_le_bytes());
+    code.extend_from_slice(&[0x48, 0x39, 0x4c, 0x24, 0x08]); // cmp [rsp + 8], rcx (dividend)
+    code.extend_from_slice(&[0x75, 0x0a]); // jne over the error call
+    let overflow_call = emit_arithmetic_error(&mut code, ARITHMETIC_OVERFLOW);
+    code.extend(generate_div());
+    (code, vec![zero_call, overflow_call])
+}
+
 fn cmp_and_set(condition_opcode: u8) -> Vec<u8> {
     vec![
         0x58, // pop rax (first operand)
@@ -124,6 +186,17 @@ pub fn generate_not() -> Vec<u8> {
     ]
 }
 
+/// Generate machine code for replacing the address on top of the stack with the word at `offset` from it
+pub fn generate_load_field(offset: usize) -> Vec<u8> {
+    let mut code = vec![
+        0x58, // pop rax
+        0x48, 0x8b, 0x80, // mov rax, [rax + disp32]
+    ];
+    code.extend_from_slice(&(offset as i32).to_le_bytes());
+    code.push(0x50); // push rax
+    code
+}
+
 /// Generate machine code for loading a parameter from stack
 pub fn generate_load_param(slot: usize) -> Vec<u8> {
     let offset = 8 * (slot + 1);
@@ -209,6 +282,18 @@ pub fn generate_call(func_name: &str, function_addresses: &HashMap<String, usize
     code
 }
 
+/// Generate machine code that pushes the address of a compiled function (lea rax, [rip + rel32])
+pub fn generate_push_function_address(func_name: &str, function_addresses: &HashMap<String, usize>, current_pos: usize) -> Vec<u8> {
+    let mut code = vec![0x48, 0x8d, 0x05];
+
+    let offset = function_addresses.get(func_name).map(|&func_addr| (func_addr as i32) - ((current_pos + 7) as i32)).unwrap_or(0);
+    code.extend_from_slice(&offset.to_le_bytes());
+
+    code.push(0x50); // push rax
+
+    code
+}
+
 /// Generate machine code for return (just pop return value into RAX)
 /// Epilogue is generated separately
 pub fn generate_return() -> Vec<u8> {
@@ -319,6 +404,55 @@ pub fn generate_jump() -> (Vec<u8>, usize) {
     (code, disp_offset)
 }
 
+/// Generate machine code for a jump table with `entries` targets. The index on the stack selects
+/// a `jmp rel32` slot; indices outside `0..entries` (compared unsigned) continue after the table.
+/// Returns (code bytes, offsets of each entry's 32-bit displacement)
+pub fn generate_jump_table(entries: usize) -> (Vec<u8>, Vec<usize>) {
+    const ENTRY_SIZE: usize = 5;
+    const DISPATCH_SIZE: usize = 16; // lea rcx + lea rax + add + jmp
+
+    let mut code = Vec::new();
+    code.push(0x58); // pop rax
+    code.extend_from_slice(&[0x48, 0x3d]); // cmp rax, imm32
+    code.extend_from_slice(&(entries as i32).to_le_bytes());
+    code.extend_from_slice(&[0x0f, 0x83]); // jae past the table
+    code.extend_from_slice(&((DISPATCH_SIZE + entries * ENTRY_SIZE) as i32).to_le_bytes());
+    code.extend_from_slice(&[0x48, 0x8d, 0x0d, 0x09, 0x00, 0x00, 0x00]); // lea rcx, [rip + 9] (table start)
+    code.extend_from_slice(&[0x48, 0x8d, 0x04, 0x80]); // lea rax, [rax + rax*4]
+    code.extend_from_slice(&[0x48, 0x01, 0xc8]); // add rax, rcx
+    code.extend_from_slice(&[0xff, 0xe0]); // jmp rax
+
+    let mut disp_offsets = Vec::with_capacity(entries);
+    for _ in 0..entries {
+        code.push(0xe9); // jmp rel32
+        disp_offsets.push(code.len());
+        code.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    }
+    (code, disp_offsets)
+}
+
+/// Generate machine code that registers an exception handler for the current frame.
+/// Passes (rsp, rbp, landing address) to `_exception_push_handler`.
+/// Returns (code bytes, offset of the landing displacement, offset of the call displacement)
+pub fn generate_push_handler() -> (Vec<u8>, usize, usize) {
+    let mut code = Vec::new();
+    code.extend_from_slice(&[0x48, 0x8d, 0x15]); // lea rdx, [rip + rel32]
+    let landing_disp_offset = code.len();
+    code.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    code.extend_from_slice(&[0x48, 0x89, 0xe7]); // mov rdi, rsp
+    code.extend_from_slice(&[0x48, 0x89, 0xee]); // mov rsi, rbp
+    code.push(0xe8); // call _exception_push_handler
+    let call_disp_offset = code.len();
+    code.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    (code, landing_disp_offset, call_disp_offset)
+}
+
+/// Generate machine code that removes the innermost exception handler
+/// Returns (code bytes, offset of the call displacement)
+pub fn generate_pop_handler() -> (Vec<u8>, usize) {
+    (vec![0xe8, 0x00, 0x00, 0x00, 0x00], 1) // call _exception_pop_handler
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -335,6 +469,19 @@ mod tests {
         assert_eq!(code.len(), 5);
     }
 
+    #[test]
+    fn test_push_wide() {
+        let code = generate_push(i64::MAX);
+        assert_eq!(&code[..2], &[0x48, 0xb8]);
+        assert_eq!(&code[2..10], &i64::MAX.to_le_bytes());
+        assert_eq!(code[10], 0x50);
+    }
+
+    #[test]
+    fn test_load_field() {
+        assert_eq!(generate_load_field(48), vec![0x58, 0x48, 0x8b, 0x80, 48, 0, 0, 0, 0x50]);
+    }
+
     #[test]
     fn test_arithmetic_ops() {
         assert_eq!(generate_add().len(), 6);
@@ -343,6 +490,20 @@ mod tests {
         assert_eq!(generate_div().len(), 14);
     }
 
+    #[test]
+    fn checked_arithmetic_reports_error_calls() {
+        let (add, add_calls) = generate_checked_add();
+        assert_eq!(add.len(), generate_add().len() + 12);
+        assert_eq!(add_calls, vec![13]);
+        assert_eq!(add[add_calls[0] - 1], 0xe8);
+        assert_eq!(*add.last().unwrap(), 0x50);
+
+        let (div, div_calls) = generate_checked_div();
+        assert_eq!(div_calls.len(), 2);
+        assert!(div_calls.iter().all(|offset| div[offset - 1] == 0xe8));
+        assert!(div.ends_with(&generate_div()));
+    }
+
     #[test]
     fn runtime_call_supports_up_to_six_args() {
         let (code, _) = generate_runtime_call(Some(0), 5);
@@ -375,4 +536,26 @@ mod tests {
         let (jmp_code, _) = generate_jump();
         assert_eq!(jmp_code.len(), 5);
     }
+
+    #[test]
+    fn push_handler_leaves_machine_stack_untouched() {
+        let (code, landing_disp, call_disp) = generate_push_handler();
+        assert_eq!(&code[..3], &[0x48, 0x8d, 0x15]);
+        assert_eq!(landing_disp, 3);
+        assert_eq!(code[call_disp - 1], 0xe8);
+        assert_eq!(code.len(), call_disp + 4);
+        assert!(!code.contains(&0x50));
+    }
+    #[test]
+    fn jump_table_entries_follow_dispatch() {
+        let (code, disps) = generate_jump_table(3);
+        assert_eq!(disps.len(), 3);
+        // The bounds check skips exactly the dispatch sequence and the entries
+        let skip = i32::from_le_bytes(code[9..13].try_into().unwrap()) as usize;
+        assert_eq!(13 + skip, code.len());
+        // `lea rcx, [rip + 9]` points at the first entry
+        assert_eq!(20 + 9, disps[0] - 1);
+        assert!(disps.iter().all(|offset| code[offset - 1] == 0xe9));
+        assert_eq!(disps[1] - disps[0], 5);
+    }
 }
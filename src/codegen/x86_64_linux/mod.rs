@@ -188,12 +188,28 @@ pub fn compile_to_object(program: &IRProgram) -> ObjectArtifact {
         "_string_from_number",
         "_string_from_boolean",
         "_string_equals",
+        "_string_hash",
+        "_string_readable",
         "_vector_create",
         "_vector_clone",
+        "_vector_clone_owned",
         "_vector_count",
         "_vector_get",
         "_vector_slice",
         "_vector_to_string",
+        "_list_to_string",
+        "_list_cons",
+        "_list_conj",
+        "_list_rest",
+        "_list_pop",
+        "_vector_conj",
+        "_vector_conj_owned",
+        "_vector_assoc",
+        "_vector_assoc_owned",
+        "_vector_pop",
+        "_vector_pop_owned",
+        "_vector_peek",
+        "_vector_subvec",
         "_vector_free",
         "_map_create",
         "_map_clone",
@@ -205,6 +221,14 @@ pub fn compile_to_object(program: &IRProgram) -> ObjectArtifact {
         "_map_free",
         "_map_value_clone",
         "_map_to_string",
+        "_map_keys",
+        "_map_vals",
+        "_map_merge",
+        "_map_merge_with",
+        "_map_select_keys",
+        "_map_zipmap",
+        "_map_find",
+        "_map_invert",
         "_set_create",
         "_set_clone",
         "_set_disj",
@@ -212,6 +236,78 @@ pub fn compile_to_object(program: &IRProgram) -> ObjectArtifact {
         "_set_count",
         "_set_to_string",
         "_set_free",
+        "_set_conj",
+        "_set_union",
+        "_set_intersection",
+        "_set_difference",
+        "_set_subset",
+        "_set_superset",
+        "_set_select",
+        "_value_equals",
+        "_value_hash",
+        "_value_compare",
+        "_value_tag",
+        "_value_count",
+        "_seq_sort",
+        "_seq_sort_by",
+        "_sorted_map_from",
+        "_sorted_set_from",
+        "_sorted_subseq",
+        "_record_create",
+        "_record_type_id",
+        "_seq_items",
+        "_seq_map",
+        "_seq_filter",
+        "_seq_reduce",
+        "_seq_reduce_last_read",
+        "_seq_reduce_first",
+        "_seq_reduce_first_last_read",
+        "_seq_every",
+        "_seq_some",
+        "_seq_range",
+        "_seq_into",
+        "_seq_take",
+        "_seq_drop",
+        "_seq_concat",
+        "_seq_reverse",
+        "_seq_frequencies",
+        "_seq_group_by",
+        "_seq_first",
+        "_seq_rest",
+        "_seq_next",
+        "_seq_of",
+        "_seq_nth",
+        "_seq_nth_last_read",
+        "_seq_last",
+        "_seq_empty",
+        "_lazy_range",
+        "_lazy_repeat",
+        "_lazy_cycle",
+        "_lazy_iterate",
+        "_lazy_map",
+        "_lazy_filter",
+        "_lazy_drop",
+        "_lazy_take",
+        "_lazy_items",
+        "_lazy_first",
+        "_lazy_count",
+        "_lazy_retain",
+        "_lazy_stream",
+        "_lazy_free",
+        "_atom_create",
+        "_atom_retain",
+        "_atom_free",
+        "_atom_deref",
+        "_atom_reset",
+        "_atom_compare_and_set",
+        "_atom_add_watch",
+        "_exception_push_handler",
+        "_exception_pop_handler",
+        "_exception_throw",
+        "_exception_is_info",
+        "_exception_value_clone",
+        "_exception_free",
+        "_arithmetic_error",
     ];
 
     if program.telemetry_enabled {
@@ -0,0 +1,201 @@
+/// Atoms: atom, deref, reset!, compare-and-set! and add-watch (`swap!` is derived from reset!)
+///
+/// An atom is a reference-counted runtime cell (`_atom_*`) that owns its current value. Storing a
+/// value clones it into the cell and releases the value it replaces, and `deref` hands back a clone
+/// the program owns, so a later `reset!` never frees a value still in use. The atom itself is
+/// released like any other owned heap value.
+///
+/// The kind of the value an atom holds travels with the atom as its element kind, the way a list's
+/// item kind does, together with the value types of a map it holds, so `deref` results are typed.
+/// Storing a value of another kind is rejected.
+use super::{
+    builtins::resolve_value_kind,
+    compile_node, extend_with_offset,
+    sequences::{adopt_owning_result, release_sources, resolve_callback, shared_result},
+    slots::SlotTracker,
+    CompileContext, CompileError, CompileResult, HeapOwnership, MapValueTypes, RetainedSlot, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Compile an atom argument onto the stack, returning the kind of the value it holds when known and
+/// the value types of a map it holds. An owned atom is released by `tracker` after its last use.
+fn compile_atom_source(
+    op: &str,
+    node: &Node,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<(Option<ValueKind>, Option<MapValueTypes>), CompileError> {
+    let mut result = compile_node(node, context, program)?;
+    let kind = resolve_value_kind(node, result.kind, context);
+    if !matches!(kind, ValueKind::Atom | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression(format!("{} requires an atom", op)));
+    }
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+    retained_slots.extend(result.take_retained_slots());
+    tracker.track_if_owned(instructions, context, result.heap_ownership, ValueKind::Atom);
+    Ok((result.vector_element_kind, result.map_value_types.take()))
+}
+
+/// Compile a value the runtime clones what it keeps of, leaving the value and its tag on the
+/// stack and returning its kind and map value types. Heap values must have a known kind so the
+/// atom can release them; an untyped value is taken to have the kind the atom holds, if that is
+/// known.
+#[allow(clippy::too_many_arguments)]
+fn compile_stored_value(
+    op: &str,
+    node: &Node,
+    content_kind: Option<ValueKind>,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<(ValueKind, Option<MapValueTypes>), CompileError> {
+    let mut result = compile_node(node, context, program)?;
+    let kind = match resolve_value_kind(node, result.kind, context) {
+        ValueKind::Any => content_kind.unwrap_or(ValueKind::Any),
+        kind => kind,
+    };
+    if kind == ValueKind::Any && result.heap_ownership != HeapOwnership::None {
+        return Err(CompileError::InvalidExpression(format!("{} requires a value whose type is known", op)));
+    }
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+    retained_slots.extend(result.take_retained_slots());
+    tracker.track_if_owned(instructions, context, result.heap_ownership, kind);
+    instructions.push(IRInstruction::Push(kind.runtime_tag()));
+    Ok((kind, result.map_value_types.take()))
+}
+
+/// Reject storing a value of one kind in an atom known to hold another
+fn check_stored_kind(op: &str, content_kind: Option<ValueKind>, kind: ValueKind) -> Result<(), CompileError> {
+    match content_kind {
+        Some(content_kind) if kind != ValueKind::Any && content_kind != kind => Err(CompileError::InvalidExpression(format!("{} requires a value of the type the atom holds", op))),
+        _ => Ok(()),
+    }
+}
+
+/// An owned value of `kind`; values off the heap, and untyped ones, have no owner. Collections come
+/// back owning their elements, so they are kept in a retained slot like an owning `map` result.
+fn owned_value(mut instructions: Vec<IRInstruction>, kind: ValueKind, map_value_types: Option<MapValueTypes>, context: &mut CompileContext) -> CompileResult {
+    if matches!(kind, ValueKind::Vector | ValueKind::List | ValueKind::Map | ValueKind::Set) {
+        let mut retained_slots = Vec::new();
+        adopt_owning_result(&mut instructions, &mut retained_slots, kind, context);
+        return shared_result(instructions, kind, retained_slots).with_map_value_types(map_value_types.filter(|_| kind == ValueKind::Map));
+    }
+    let ownership = if kind.is_heap_kind() { HeapOwnership::Owned } else { HeapOwnership::None };
+    CompileResult::with_instructions(instructions, kind).with_heap_ownership(ownership)
+}
+
+fn atom_result(instructions: Vec<IRInstruction>, content_kind: Option<ValueKind>, map_value_types: Option<MapValueTypes>) -> CompileResult {
+    CompileResult::with_instructions(instructions, ValueKind::Atom)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(content_kind)
+        .with_map_value_types(map_value_types.filter(|_| content_kind == Some(ValueKind::Map)))
+}
+
+fn finish(instructions: Vec<IRInstruction>, tracker: SlotTracker, retained_slots: Vec<RetainedSlot>, context: &mut CompileContext) -> Vec<IRInstruction> {
+    let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+    release_sources(&mut instructions, retained_slots, context);
+    instructions
+}
+
+/// Compile atom (a new atom holding a clone of its argument)
+pub(super) fn compile_atom(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("atom".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let (kind, map_value_types) = compile_stored_value("atom", &args[0], None, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::RuntimeCall("_atom_create".to_string(), 2));
+    let instructions = finish(instructions, tracker, retained_slots, context);
+
+    Ok(atom_result(instructions, Some(kind).filter(|kind| *kind != ValueKind::Any), map_value_types))
+}
+
+/// Compile deref (a clone of the atom's current value)
+pub(super) fn compile_deref(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("deref".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let (content_kind, map_value_types) = compile_atom_source("deref", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::RuntimeCall("_atom_deref".to_string(), 1));
+    let instructions = finish(instructions, tracker, retained_slots, context);
+
+    Ok(owned_value(instructions, content_kind.unwrap_or(ValueKind::Any), map_value_types, context))
+}
+
+/// Compile reset! (store a value in the atom, returning it)
+pub(super) fn compile_reset(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("reset!".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let (content_kind, atom_map_value_types) = compile_atom_source("reset!", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let (kind, map_value_types) = compile_stored_value("reset!", &args[1], content_kind, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    check_stored_kind("reset!", content_kind, kind)?;
+    instructions.push(IRInstruction::RuntimeCall("_atom_reset".to_string(), 3));
+    let instructions = finish(instructions, tracker, retained_slots, context);
+
+    Ok(owned_value(instructions, kind, map_value_types.or(atom_map_value_types), context))
+}
+
+/// Compile compare-and-set! (store a value only if the atom holds one equal to the expected value)
+pub(super) fn compile_compare_and_set(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 3 {
+        return Err(CompileError::ArityError("compare-and-set!".to_string(), 3, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let op = "compare-and-set!";
+    let (content_kind, _) = compile_atom_source(op, &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    compile_stored_value(op, &args[1], content_kind, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let (kind, _) = compile_stored_value(op, &args[2], content_kind, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    check_stored_kind(op, content_kind, kind)?;
+    instructions.push(IRInstruction::RuntimeCall("_atom_compare_and_set".to_string(), 5));
+    let instructions = finish(instructions, tracker, retained_slots, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean))
+}
+
+/// Compile add-watch (call a defn function of key, atom, old and new value after each change),
+/// returning the atom
+pub(super) fn compile_add_watch(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 3 {
+        return Err(CompileError::ArityError("add-watch".to_string(), 3, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let (content_kind, map_value_types) = compile_atom_source("add-watch", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let (key_kind, _) = compile_stored_value("add-watch", &args[1], None, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let key_param = Some(key_kind).filter(|kind| *kind != ValueKind::Any);
+    let callback = resolve_callback("add-watch", &args[2], &[key_param, Some(ValueKind::Atom), content_kind, content_kind], context)?;
+    instructions.push(IRInstruction::PushFunction(callback.symbol.clone()));
+    instructions.push(IRInstruction::Push(callback.result_mode()));
+    instructions.push(IRInstruction::RuntimeCall("_atom_add_watch".to_string(), 5));
+    let instructions = finish(instructions, tracker, retained_slots, context);
+
+    Ok(atom_result(instructions, content_kind, map_value_types))
+}
@@ -1,16 +1,17 @@
 use super::{
-    builtins::{emit_free_for_slot, free_retained_dependents, free_retained_slot},
+    builtins::{clone_runtime_for_kind, emit_free_for_slot, free_retained_dependents, free_retained_slot},
     extend_with_offset, CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind,
 };
 /// Variable binding compilation (let expressions)
 use crate::ast::Node;
-use crate::compiler::liveness::{apply_liveness_plan, compute_liveness_plan};
+use crate::compiler::liveness::{apply_liveness_plan, compute_liveness_plan_from, LivenessPlan};
 use crate::ir::{IRInstruction, IRProgram};
 use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Debug)]
 struct BindingInfo {
     slot: usize,
+    stored_at: usize, // index of the instruction storing the value, where the slot starts holding it
     owns_heap: bool,
     kind: ValueKind,
     retained_slots: Vec<RetainedSlot>,
@@ -60,21 +61,22 @@ pub fn compile_let(args: &[Node], context: &mut CompileContext, program: &mut IR
 
     apply_body_symbol_clone(&args[1], &added_variables, context, &mut body_instructions, &mut body_kind, &mut body_heap_ownership);
 
-    let mut slot_kinds_for_plan: HashMap<usize, ValueKind> = HashMap::new();
-    let mut tracked_slots_for_plan: HashSet<usize> = HashSet::new();
-    collect_slot_tracking(&binding_infos, &mut tracked_slots_for_plan, &mut slot_kinds_for_plan);
-
-    let freed_on_all_paths = plan_liveness(&mut body_instructions, &tracked_slots_for_plan, &slot_kinds_for_plan);
-
+    let body_start = instructions.len();
     extend_with_offset(&mut instructions, body_instructions);
 
+    let freed_on_all_paths = plan_liveness(&mut instructions, &binding_infos, body_start);
+
     emit_scope_cleanup(&mut instructions, &mut binding_infos, &freed_on_all_paths, context);
 
     context.remove_variables(&added_variables);
 
+    // What is known about the body's collection, e.g. the nested maps `assoc-in` rebuilds
     Ok(CompileResult::with_instructions(instructions, body_kind)
         .with_heap_ownership(body_heap_ownership)
-        .with_retained_slots(body_retained_slots))
+        .with_retained_slots(body_retained_slots)
+        .with_map_value_types(body_result.map_value_types.take())
+        .with_set_element_kind(body_result.set_element_kind)
+        .with_vector_element_kind(body_result.vector_element_kind))
 }
 
 fn collect_bindings(bindings: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<BindingCollection, CompileError> {
@@ -96,12 +98,7 @@ fn collect_bindings(bindings: &[Node], context: &mut CompileContext, program: &m
         if let Node::Symbol { value } = val_node {
             if crate::compiler::is_heap_allocated_symbol(value, context) {
                 let source_kind = context.get_variable_type(value).or_else(|| context.get_parameter_type(value)).unwrap_or(ValueKind::String);
-                let runtime = match source_kind {
-                    ValueKind::Vector => "_vector_clone",
-                    ValueKind::Map => "_map_clone",
-                    ValueKind::Set => "_set_clone",
-                    _ => "_string_clone",
-                };
+                let runtime = clone_runtime_for_kind(source_kind).unwrap_or("_string_clone");
                 value_result.instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
                 value_result.heap_ownership = HeapOwnership::Owned;
                 cloned_from_existing = Some(source_kind);
@@ -116,6 +113,7 @@ fn collect_bindings(bindings: &[Node], context: &mut CompileContext, program: &m
         extend_with_offset(&mut collected.instructions, value_result.instructions);
 
         let slot = context.add_variable(var_name.clone());
+        let stored_at = collected.instructions.len();
         collected.instructions.push(IRInstruction::StoreLocal(slot));
 
         let mut value_kind = match value_result.kind {
@@ -136,7 +134,8 @@ fn collect_bindings(bindings: &[Node], context: &mut CompileContext, program: &m
             inferred_vector_element_kind = vector_element_kind;
         }
         context.set_variable_type(var_name, value_kind);
-        if value_kind == ValueKind::Map {
+        // An atom carries the value types of the map it holds
+        if matches!(value_kind, ValueKind::Map | ValueKind::Atom) {
             let mut combined = value_map_value_types.or(cloned_map_value_types);
             if let Some(mut inferred) = inferred_map_value_types.filter(|m| !m.is_empty()) {
                 if let Some(existing) = combined.as_mut() {
@@ -155,8 +154,7 @@ fn collect_bindings(bindings: &[Node], context: &mut CompileContext, program: &m
         let vector_element_kind = value_result.vector_element_kind.or(inferred_vector_element_kind);
         context.set_variable_set_element_kind(var_name, set_element_kind);
         context.set_variable_vector_element_kind(var_name, vector_element_kind);
-        context.set_variable_set_element_kind(var_name, inferred_set_element_kind);
-        context.set_variable_vector_element_kind(var_name, inferred_vector_element_kind);
+        context.set_variable_record_type(var_name, value_result.record_type.filter(|_| value_kind == ValueKind::Map));
 
         // Mark variable as heap-allocated if needed
         let heap_owned = value_result.heap_ownership == HeapOwnership::Owned || cloned_from_existing.is_some() || matches!(inferred_heap_ownership, Some(HeapOwnership::Owned));
@@ -167,6 +165,7 @@ fn collect_bindings(bindings: &[Node], context: &mut CompileContext, program: &m
         collected.added_variables.push(var_name.clone());
         collected.binding_infos.push(BindingInfo {
             slot,
+            stored_at,
             owns_heap: heap_owned,
             kind: value_kind,
             retained_slots,
@@ -187,20 +186,22 @@ fn apply_body_symbol_clone(
     if let Node::Symbol { value } = body_node {
         if added_variables.iter().any(|name| name == value) && crate::compiler::is_heap_allocated_symbol(value, context) {
             let symbol_kind = context.get_variable_type(value).unwrap_or(ValueKind::String);
-            let runtime = match symbol_kind {
-                ValueKind::Vector => "_vector_clone",
-                ValueKind::Map => "_map_clone",
-                ValueKind::Set => "_set_clone",
-                _ => "_string_clone",
-            };
+            let runtime = clone_runtime_for_kind(symbol_kind).unwrap_or("_string_clone");
             body_instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
             *body_heap_ownership = HeapOwnership::Owned;
             *body_kind = symbol_kind;
         }
     }
 }
-fn collect_slot_tracking(binding_infos: &[BindingInfo], tracked: &mut HashSet<usize>, slot_kinds: &mut HashMap<usize, ValueKind>) {
-    for info in binding_infos {
+/// Plan the frees of the slots the bindings own over the rest of the `let`, each from where its
+/// binding is stored, so a binding dead after the value of a later one (`b (conj a 2)`) is
+/// released, or handed to an in-place update, right there rather than at the end of the scope.
+/// A later binding that may borrow from it (`x (nth items 0)`) keeps it until the body.
+fn plan_liveness(instructions: &mut Vec<IRInstruction>, binding_infos: &[BindingInfo], body_start: usize) -> HashSet<usize> {
+    let mut plan = LivenessPlan::default();
+    let mut slot_kinds: HashMap<usize, ValueKind> = HashMap::new();
+    for (index, info) in binding_infos.iter().enumerate() {
+        let mut tracked = HashSet::new();
         if info.owns_heap {
             tracked.insert(info.slot);
             slot_kinds.insert(info.slot, info.kind);
@@ -209,29 +210,34 @@ fn collect_slot_tracking(binding_infos: &[BindingInfo], tracked: &mut HashSet<us
             tracked.insert(retained.slot);
             slot_kinds.insert(retained.slot, retained.kind);
         }
-    }
-}
-
-fn plan_liveness(body_instructions: &mut Vec<IRInstruction>, tracked: &HashSet<usize>, slot_kinds: &HashMap<usize, ValueKind>) -> HashSet<usize> {
-    let mut freed_on_all_paths = HashSet::new();
-    if tracked.is_empty() {
-        return freed_on_all_paths;
+        if tracked.is_empty() {
+            continue;
+        }
+        let later_borrow = binding_infos[index + 1..]
+            .iter()
+            .any(|later| !later.owns_heap && (later.kind.is_heap_clone_kind() || later.kind == ValueKind::Any));
+        let start = if later_borrow { body_start } else { info.stored_at + 1 };
+        let binding_plan = compute_liveness_plan_from(instructions, &tracked, start);
+        for (idx, slots) in binding_plan.insert_after {
+            plan.insert_after.entry(idx).or_default().extend(slots);
+        }
+        plan.handoffs.extend(binding_plan.handoffs);
+        plan.last_reads.extend(binding_plan.last_reads);
+        plan.freed_everywhere.extend(binding_plan.freed_everywhere);
     }
 
-    let plan = compute_liveness_plan(body_instructions, tracked);
-    if !plan.insert_after.is_empty() {
-        *body_instructions = apply_liveness_plan(std::mem::take(body_instructions), &plan, |insts, slot| {
+    if !plan.is_empty() {
+        *instructions = apply_liveness_plan(std::mem::take(instructions), &plan, |insts, slot| {
             let kind = slot_kinds.get(&slot).copied().unwrap_or(ValueKind::Any);
             emit_free_for_slot(insts, slot, kind);
         });
     }
-    freed_on_all_paths.extend(plan.freed_everywhere.iter().copied());
     #[cfg(debug_assertions)]
     if std::env::var("SLISP_DEBUG_LET").is_ok() {
         eprintln!("[slisp:debug_let] freed_on_all_paths={:?}", plan.freed_everywhere);
     }
 
-    freed_on_all_paths
+    plan.freed_everywhere
 }
 
 fn emit_scope_cleanup(instructions: &mut Vec<IRInstruction>, binding_infos: &mut [BindingInfo], freed_on_all_paths: &HashSet<usize>, context: &mut CompileContext) {
@@ -1,11 +1,19 @@
 use super::{
-    compile_node, extend_with_offset, is_heap_allocated_symbol, slots::SlotTracker, CompileContext, CompileError, CompileResult, HeapOwnership, MapKeyLiteral, MapValueTypes, RetainedSlot, ValueKind,
+    compile_node, extend_with_offset, is_heap_allocated_symbol, records, sequences,
+    slots::SlotTracker,
+    types::{map_value_type, nested_map_value_types, remove_map_value_type, set_map_value_type},
+    vectors, CompileContext, CompileError, CompileResult, HeapOwnership, MapKeyLiteral, MapValueTypes, RetainedSlot, ValueKind,
 };
-use crate::ast::{Node, Primitive};
+use crate::ast::{forms, Node, Primitive};
 use crate::ir::{IRInstruction, IRProgram};
 use std::collections::HashMap;
 
 pub(super) fn compile_vector_literal(elements: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_sequence_literal(elements, ValueKind::Vector, context, program)
+}
+
+/// Build a vector or list (`kind`) from evaluated elements; both share the runtime vector layout
+pub(super) fn compile_sequence_literal(elements: &[Node], kind: ValueKind, context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
     if elements.is_empty() {
         return Ok(CompileResult::with_instructions(
             vec![
@@ -14,7 +22,7 @@ pub(super) fn compile_vector_literal(elements: &[Node], context: &mut CompileCon
                 IRInstruction::Push(0),
                 IRInstruction::RuntimeCall("_vector_create".to_string(), 3),
             ],
-            ValueKind::Vector,
+            kind,
         )
         .with_heap_ownership(HeapOwnership::Owned));
     }
@@ -54,7 +62,11 @@ pub(super) fn compile_vector_literal(elements: &[Node], context: &mut CompileCon
             }
         }
 
-        ensure_owned_on_stack(&mut instructions, element_kind, &mut element_result.heap_ownership);
+        // Quoted symbols live in rodata like keywords, so they are stored without a clone or a free
+        let rodata_symbol = element_kind == ValueKind::Symbol && element_result.heap_ownership == HeapOwnership::None;
+        if !rodata_symbol {
+            ensure_owned_on_stack(&mut instructions, element_kind, &mut element_result.heap_ownership);
+        }
         if element_kind != ValueKind::Any {
             if let Some(existing) = element_kind_accumulator {
                 if existing != element_kind {
@@ -66,7 +78,9 @@ pub(super) fn compile_vector_literal(elements: &[Node], context: &mut CompileCon
         }
         let element_dependents = element_result.take_retained_slots();
         instructions.push(IRInstruction::StoreLocal(value_slot));
-        track_heap_slot(&mut retained_slots, value_slot, element_kind, None, element_dependents);
+        if !rodata_symbol {
+            track_heap_slot(&mut retained_slots, value_slot, element_kind, None, element_dependents);
+        }
 
         instructions.push(IRInstruction::Push(element_kind.runtime_tag()));
         instructions.push(IRInstruction::StoreLocal(tag_slot));
@@ -87,7 +101,7 @@ pub(super) fn compile_vector_literal(elements: &[Node], context: &mut CompileCon
     });
     tag_slots.into_iter().for_each(|slot| context.release_temp_slot(slot));
 
-    Ok(CompileResult::with_instructions(instructions, ValueKind::Vector)
+    Ok(CompileResult::with_instructions(instructions, kind)
         .with_heap_ownership(HeapOwnership::Owned)
         .with_vector_element_kind(element_kind_accumulator)
         .with_retained_slots(retained_slots))
@@ -170,7 +184,7 @@ pub(super) fn compile_set_literal(args: &[Node], context: &mut CompileContext, p
         .with_retained_slots(retained_slots))
 }
 
-/// Compile count operation (string length)
+/// Compile count operation, picked by the argument's kind
 pub(super) fn compile_count(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
     if args.len() != 1 {
         return Err(CompileError::ArityError("count".to_string(), 1, args.len()));
@@ -188,9 +202,12 @@ pub(super) fn compile_count(args: &[Node], context: &mut CompileContext, program
     let target_kind = resolve_value_kind(&args[0], arg_result.kind, context);
 
     let runtime = match target_kind {
-        ValueKind::Vector => "_vector_count",
+        ValueKind::Vector | ValueKind::List => "_vector_count",
         ValueKind::Map => "_map_count",
         ValueKind::Set => "_set_count",
+        ValueKind::LazySeq => "_lazy_count",
+        // A value read out of an untyped collection is counted by the kind it turns out to have
+        ValueKind::Any => "_value_count",
         _ => "_string_count",
     };
     instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
@@ -202,7 +219,7 @@ pub(super) fn compile_count(args: &[Node], context: &mut CompileContext, program
 }
 
 /// Compile get operation (string indexing)
-fn resolve_value_kind(node: &Node, initial: ValueKind, context: &CompileContext) -> ValueKind {
+pub(super) fn resolve_value_kind(node: &Node, initial: ValueKind, context: &CompileContext) -> ValueKind {
     if initial != ValueKind::Any {
         return initial;
     }
@@ -213,12 +230,14 @@ fn resolve_value_kind(node: &Node, initial: ValueKind, context: &CompileContext)
     }
 }
 
-fn resolve_map_key_kind(node: &Node, initial: ValueKind, context: &CompileContext) -> Result<ValueKind, CompileError> {
+pub(super) fn resolve_map_key_kind(node: &Node, initial: ValueKind, context: &CompileContext) -> Result<ValueKind, CompileError> {
     let resolved = resolve_value_kind(node, initial, context);
     match resolved {
-        ValueKind::Number | ValueKind::Boolean | ValueKind::String | ValueKind::Keyword | ValueKind::Nil => Ok(resolved),
+        _ if resolved.is_key_kind() => Ok(resolved),
         ValueKind::Any => Err(CompileError::InvalidExpression("map keys must have a concrete type".to_string())),
-        _ => Err(CompileError::InvalidExpression("map keys must be numbers, booleans, strings, keywords, or nil".to_string())),
+        _ => Err(CompileError::InvalidExpression(
+            "map keys must be numbers, booleans, strings, keywords, nil, or collections of them".to_string(),
+        )),
     }
 }
 
@@ -235,7 +254,7 @@ fn literal_map_key_from_primitive(value: &Primitive) -> Option<MapKeyLiteral> {
     }
 }
 
-fn literal_map_key(node: &Node) -> Option<MapKeyLiteral> {
+pub(super) fn literal_map_key(node: &Node) -> Option<MapKeyLiteral> {
     match node {
         Node::Primitive { value } => literal_map_key_from_primitive(value),
         Node::Symbol { value } if value == "nil" => Some(MapKeyLiteral::Nil),
@@ -249,19 +268,23 @@ fn runtime_tag_for_value(kind: ValueKind) -> i64 {
 
 pub(super) fn clone_runtime_for_kind(kind: ValueKind) -> Option<&'static str> {
     match kind {
-        ValueKind::String => Some("_string_clone"),
-        ValueKind::Vector => Some("_vector_clone"),
+        ValueKind::String | ValueKind::Symbol => Some("_string_clone"),
+        ValueKind::Vector | ValueKind::List => Some("_vector_clone"),
         ValueKind::Map => Some("_map_clone"),
         ValueKind::Set => Some("_set_clone"),
+        ValueKind::LazySeq => Some("_lazy_retain"),
+        ValueKind::Atom => Some("_atom_retain"),
         _ => None,
     }
 }
 
 pub(super) fn runtime_free_for_kind(kind: ValueKind) -> Option<&'static str> {
     match kind {
-        ValueKind::Vector => Some("_vector_free"),
+        ValueKind::Vector | ValueKind::List => Some("_vector_free"),
         ValueKind::Map => Some("_map_free"),
         ValueKind::Set => Some("_set_free"),
+        ValueKind::LazySeq => Some("_lazy_free"),
+        ValueKind::Atom => Some("_atom_free"),
         _ => None,
     }
 }
@@ -288,7 +311,7 @@ pub(super) fn free_retained_dependents(slot: &mut RetainedSlot, instructions: &m
     });
 }
 
-fn ensure_owned_on_stack(instructions: &mut Vec<IRInstruction>, kind: ValueKind, ownership: &mut HeapOwnership) {
+pub(super) fn ensure_owned_on_stack(instructions: &mut Vec<IRInstruction>, kind: ValueKind, ownership: &mut HeapOwnership) {
     if *ownership == HeapOwnership::Owned {
         return;
     }
@@ -299,7 +322,7 @@ fn ensure_owned_on_stack(instructions: &mut Vec<IRInstruction>, kind: ValueKind,
     }
 }
 
-fn track_heap_slot(retained_slots: &mut Vec<RetainedSlot>, slot: usize, kind: ValueKind, key: Option<MapKeyLiteral>, dependents: Vec<RetainedSlot>) {
+pub(super) fn track_heap_slot(retained_slots: &mut Vec<RetainedSlot>, slot: usize, kind: ValueKind, key: Option<MapKeyLiteral>, dependents: Vec<RetainedSlot>) {
     if kind.is_heap_kind() {
         retained_slots.push(RetainedSlot { slot, key, kind, dependents });
     }
@@ -334,7 +357,7 @@ fn dedup_retained_slots(slots: &mut Vec<RetainedSlot>) {
     });
 }
 
-fn retains_slot(slots: &[RetainedSlot], slot: usize) -> bool {
+pub(super) fn retains_slot(slots: &[RetainedSlot], slot: usize) -> bool {
     slots.iter().any(|info| info.slot == slot)
 }
 
@@ -465,12 +488,33 @@ fn emit_string_get(instructions: &mut Vec<IRInstruction>, context: &mut CompileC
     context.release_temp_slot(result_slot);
 }
 
+/// Compile a call whose operator is a local map or set, as `(get m k d?)` or a `contains?` test, so
+/// `(config :port)` takes the same path as `(get config :port)`
+pub(super) fn compile_collection_call(target: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let head = Node::Symbol { value: target.to_string() };
+    let kind = resolve_value_kind(&head, ValueKind::Any, context);
+    if !matches!(kind, ValueKind::Map | ValueKind::Set | ValueKind::Nil | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression(format!("{} is not a function, map or set", target)));
+    }
+
+    let form = match (kind, args) {
+        (ValueKind::Set, [member]) => forms::set_lookup(&head, member, None, &mut 0),
+        (ValueKind::Set, [member, default]) => forms::set_lookup(&head, member, Some(default), &mut 0),
+        (ValueKind::Set, _) => return Err(CompileError::ArityError(target.to_string(), 1, args.len())),
+        _ => forms::lookup_call(&head, args, &mut 0).map_err(CompileError::InvalidExpression)?,
+    };
+    compile_node(&form, context, program)
+}
+
 pub(super) fn compile_get(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
     if args.len() < 2 || args.len() > 3 {
         return Err(CompileError::ArityError("get".to_string(), 2, args.len()));
     }
 
     let mut target_result = compile_node(&args[0], context, program)?;
+    if let Some(index) = records::field_get_index(&target_result, args, context) {
+        return Ok(records::compile_field_get(target_result, index, context));
+    }
     let target_map_value_types = target_result.map_value_types.clone();
     let mut instructions = std::mem::take(&mut target_result.instructions);
     let mut tracker = SlotTracker::new();
@@ -502,12 +546,17 @@ pub(super) fn compile_get(args: &[Node], context: &mut CompileContext, program:
     }
 
     let mut default_handling = DefaultHandling::from_parts(default_slot, default_owned, default_kind, default_retained_slots);
-    let target_kind = resolve_value_kind(&args[0], target_result.kind, context);
+    let target_kind = match resolve_value_kind(&args[0], target_result.kind, context) {
+        // Only maps are looked up by keyword or string, so an untyped target (e.g. a value pulled out
+        // of a nested map) dispatches as one; `_map_get` falls back to the default for nil
+        ValueKind::Any if matches!(literal_map_key(&args[1]), Some(MapKeyLiteral::Keyword(_) | MapKeyLiteral::String(_))) => ValueKind::Map,
+        kind => kind,
+    };
 
     owned_arg_slot.into_iter().for_each(|slot| tracker.set_slot_kind(slot, target_kind));
 
     match target_kind {
-        ValueKind::Vector => {
+        ValueKind::Vector | ValueKind::List => {
             emit_vector_get(&mut instructions, context, &mut tracker, owned_arg_slot, &mut default_handling);
         }
         ValueKind::Map => {
@@ -535,7 +584,7 @@ pub(super) fn compile_get(args: &[Node], context: &mut CompileContext, program:
             let failure_jump_pos = instructions.len();
             instructions.push(IRInstruction::JumpIfZero(0));
 
-            let inferred_value_kind = target_map_value_types.as_ref().and_then(|types| literal_map_key(&args[1]).and_then(|key| types.get(&key).copied()));
+            let inferred_value_kind = target_map_value_types.as_ref().and_then(|types| map_value_type(types, literal_map_key(&args[1]).as_ref()));
             let needs_clone = inferred_value_kind.map(|kind| kind.is_heap_kind()).unwrap_or(true);
             if needs_clone {
                 instructions.push(IRInstruction::LoadLocal(value_slot));
@@ -568,10 +617,17 @@ pub(super) fn compile_get(args: &[Node], context: &mut CompileContext, program:
     default_handling.release_slot(context);
     target_result.free_retained_slots(&mut instructions, context);
 
-    let inferred_map_value_kind = target_map_value_types.as_ref().and_then(|types| literal_map_key(&args[1]).and_then(|key| types.get(&key).copied()));
+    let inferred_map_value_kind = target_map_value_types.as_ref().and_then(|types| map_value_type(types, literal_map_key(&args[1]).as_ref()));
+    let nested_map_value_types = match (target_kind, inferred_map_value_kind) {
+        (ValueKind::Map, Some(ValueKind::Map)) => target_map_value_types
+            .as_ref()
+            .zip(literal_map_key(&args[1]))
+            .and_then(|(types, key)| nested_map_value_types(types, &key)),
+        _ => None,
+    };
 
     let result_kind = match target_kind {
-        ValueKind::Vector => default_handling.inferred_kind().unwrap_or(ValueKind::Any),
+        ValueKind::Vector | ValueKind::List => default_handling.inferred_kind().or(target_result.vector_element_kind).unwrap_or(ValueKind::Any),
         ValueKind::Map => inferred_map_value_kind.or_else(|| default_handling.inferred_kind()).unwrap_or(ValueKind::Any),
         _ if default_handling.has_value() => default_handling.inferred_kind().unwrap_or(ValueKind::String),
         _ => ValueKind::String,
@@ -583,10 +639,9 @@ pub(super) fn compile_get(args: &[Node], context: &mut CompileContext, program:
     };
 
     let heap_ownership = match target_kind {
-        ValueKind::Vector => HeapOwnership::None,
+        ValueKind::Vector | ValueKind::List => HeapOwnership::None,
         ValueKind::Map => match (inferred_map_value_kind, target_result.heap_ownership) {
-            (Some(kind), HeapOwnership::Owned) if kind.is_heap_kind() => HeapOwnership::Owned,
-            (Some(kind), _) if kind.is_heap_kind() => HeapOwnership::Borrowed,
+            (Some(kind), _) if kind.is_heap_kind() => HeapOwnership::Owned,
             _ if result_kind.is_heap_clone_kind() || map_needs_clone_flag => HeapOwnership::Owned,
             _ => HeapOwnership::None,
         },
@@ -597,6 +652,7 @@ pub(super) fn compile_get(args: &[Node], context: &mut CompileContext, program:
 
     Ok(CompileResult::with_instructions(instructions, result_kind)
         .with_heap_ownership(heap_ownership)
+        .with_map_value_types(nested_map_value_types)
         .with_retained_slots(retained_slots))
 }
 
@@ -655,7 +711,9 @@ mod tests {
         ];
         let result = compile_get(&args, &mut context, &mut program).unwrap();
         assert_eq!(result.kind, ValueKind::String);
-        assert_eq!(result.heap_ownership, HeapOwnership::Borrowed);
+        // The string is cloned out of the map, so the caller owns and frees the copy
+        assert_eq!(result.heap_ownership, HeapOwnership::Owned);
+        assert!(result.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 2) if name == "_map_value_clone")));
         assert!(context.get_variable("m").is_some());
         // ensure slot still allocated
         assert_eq!(slot, 0);
@@ -747,7 +805,7 @@ pub(super) fn compile_str(args: &[Node], context: &mut CompileContext, program:
         }
 
         match arg_kind {
-            ValueKind::String => {
+            ValueKind::String | ValueKind::Symbol => {
                 let clone_flag = if let Node::Symbol { value } = arg {
                     if is_heap_allocated_symbol(value, context) {
                         1
@@ -773,16 +831,46 @@ pub(super) fn compile_str(args: &[Node], context: &mut CompileContext, program:
                 instructions.push(IRInstruction::RuntimeCall("_string_normalize".to_string(), 2));
                 slot_needs_free = false;
             }
-            ValueKind::Vector => {
-                instructions.push(IRInstruction::RuntimeCall("_vector_to_string".to_string(), 1));
-                slot_needs_free = true;
-            }
-            ValueKind::Map => {
-                instructions.push(IRInstruction::RuntimeCall("_map_to_string".to_string(), 1));
+            ValueKind::Vector | ValueKind::List | ValueKind::Map | ValueKind::Set => {
+                let runtime = match arg_kind {
+                    ValueKind::Vector => "_vector_to_string",
+                    ValueKind::List => "_list_to_string",
+                    ValueKind::Map => "_map_to_string",
+                    _ => "_set_to_string",
+                };
+                // An owned collection is only needed for rendering
+                let owned_slot = slot_needs_free.then(|| {
+                    let slot = context.allocate_temp_slot();
+                    instructions.push(IRInstruction::StoreLocal(slot));
+                    instructions.push(IRInstruction::LoadLocal(slot));
+                    slot
+                });
+                instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
+                if let Some(slot) = owned_slot {
+                    emit_free_for_slot(&mut instructions, slot, arg_kind);
+                    context.release_temp_slot(slot);
+                }
                 slot_needs_free = true;
             }
-            ValueKind::Set => {
-                instructions.push(IRInstruction::RuntimeCall("_set_to_string".to_string(), 1));
+            ValueKind::LazySeq => {
+                // Rendering realizes the whole sequence into a temporary list
+                let owned_slot = slot_needs_free.then(|| {
+                    let slot = context.allocate_temp_slot();
+                    instructions.push(IRInstruction::StoreLocal(slot));
+                    instructions.push(IRInstruction::LoadLocal(slot));
+                    slot
+                });
+                let items_slot = context.allocate_temp_slot();
+                instructions.push(IRInstruction::RuntimeCall("_lazy_items".to_string(), 1));
+                instructions.push(IRInstruction::StoreLocal(items_slot));
+                instructions.push(IRInstruction::LoadLocal(items_slot));
+                instructions.push(IRInstruction::RuntimeCall("_list_to_string".to_string(), 1));
+                emit_free_for_slot(&mut instructions, items_slot, ValueKind::List);
+                context.release_temp_slot(items_slot);
+                if let Some(slot) = owned_slot {
+                    emit_free_for_slot(&mut instructions, slot, arg_kind);
+                    context.release_temp_slot(slot);
+                }
                 slot_needs_free = true;
             }
             ValueKind::Boolean => {
@@ -793,6 +881,7 @@ pub(super) fn compile_str(args: &[Node], context: &mut CompileContext, program:
                 instructions.push(IRInstruction::RuntimeCall("_string_from_number".to_string(), 1));
                 slot_needs_free = true;
             }
+            ValueKind::Atom => return Err(CompileError::InvalidExpression("str cannot render an atom; deref it first".to_string())),
         }
 
         instructions.push(IRInstruction::StoreLocal(*slot));
@@ -897,10 +986,10 @@ pub(super) fn compile_hash_map(args: &[Node], context: &mut CompileContext, prog
         if let Some(key_literal) = key_literal {
             if value_kind == ValueKind::Any {
                 if let Some(types) = map_value_types.as_mut() {
-                    types.remove(&key_literal);
+                    remove_map_value_type(types, &key_literal);
                 }
             } else {
-                map_value_types.get_or_insert_with(HashMap::new).insert(key_literal, value_kind);
+                set_map_value_type(map_value_types.get_or_insert_with(HashMap::new), key_literal, value_kind, value_result.map_value_types.as_ref());
             }
         } else {
             map_value_types = None;
@@ -933,8 +1022,12 @@ pub(super) fn compile_assoc(args: &[Node], context: &mut CompileContext, program
     }
 
     let mut base_result = compile_node(&args[0], context, program)?;
+    if resolve_value_kind(&args[0], base_result.kind, context) == ValueKind::Vector {
+        return vectors::compile_vector_assoc(base_result, args, context, program);
+    }
     let base_heap_ownership = base_result.heap_ownership;
     let mut map_value_types = base_result.map_value_types.clone();
+    let record_type = records::assoc_record_type(base_result.record_type.take(), args, context);
     let mut instructions = std::mem::take(&mut base_result.instructions);
     let mut tracker = SlotTracker::new();
     let mut temp_slots = Vec::new();
@@ -996,10 +1089,10 @@ pub(super) fn compile_assoc(args: &[Node], context: &mut CompileContext, program
         if let Some(key_literal) = literal_map_key(&args[key_index]) {
             if value_result.kind == ValueKind::Any {
                 if let Some(types) = map_value_types.as_mut() {
-                    types.remove(&key_literal);
+                    remove_map_value_type(types, &key_literal);
                 }
             } else {
-                map_value_types.get_or_insert_with(HashMap::new).insert(key_literal, value_result.kind);
+                set_map_value_type(map_value_types.get_or_insert_with(HashMap::new), key_literal, value_result.kind, value_result.map_value_types.as_ref());
             }
         } else {
             map_value_types = None;
@@ -1014,6 +1107,7 @@ pub(super) fn compile_assoc(args: &[Node], context: &mut CompileContext, program
     Ok(CompileResult::with_instructions(instructions, ValueKind::Map)
         .with_heap_ownership(HeapOwnership::Owned)
         .with_map_value_types(map_value_types)
+        .with_record_type(record_type)
         .with_retained_slots(retained_slots))
 }
 
@@ -1049,7 +1143,7 @@ pub(super) fn compile_dissoc(args: &[Node], context: &mut CompileContext, progra
         if let Some(key_literal) = literal_map_key(&args[key_idx]) {
             release_slots_for_literal(&mut retained_slots, &key_literal, &mut instructions, context);
             if let Some(types) = map_value_types.as_mut() {
-                types.remove(&key_literal);
+                remove_map_value_type(types, &key_literal);
             }
         } else {
             map_value_types = None;
@@ -1106,6 +1200,16 @@ pub(super) fn compile_disj(args: &[Node], context: &mut CompileContext, program:
 }
 
 pub(super) fn compile_contains(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_key_check(args, context, program, true)
+}
+
+/// Compile a `contains?` that always asks the runtime, for tests like a map pattern's that must
+/// tell apart maps of different shapes reaching the same code
+pub(super) fn compile_runtime_contains(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_key_check(args, context, program, false)
+}
+
+fn compile_key_check(args: &[Node], context: &mut CompileContext, program: &mut IRProgram, fold_known_keys: bool) -> Result<CompileResult, CompileError> {
     if args.len() != 2 {
         return Err(CompileError::ArityError("contains?".to_string(), 2, args.len()));
     }
@@ -1121,7 +1225,9 @@ pub(super) fn compile_contains(args: &[Node], context: &mut CompileContext, prog
         tracker.set_slot_kind(slot, target_kind);
     }
 
-    if target_kind == ValueKind::Map {
+    // A parameter's value types merge the keys of every map passed in, so they prove nothing
+    let parameter = matches!(&args[0], Node::Symbol { value } if context.get_parameter(value).is_some());
+    if fold_known_keys && !parameter && target_kind == ValueKind::Map {
         if let Some(map_value_types) = target_result.map_value_types.as_ref() {
             if let Some(key_literal) = literal_map_key(&args[1]) {
                 if map_value_types.contains_key(&key_literal) {
@@ -1166,3 +1272,231 @@ fn discard_loaded_target(instructions: &mut Vec<IRInstruction>, context: &mut Co
         context.release_temp_slot(slot);
     }
 }
+
+/// Compile quote: the form is materialized as data instead of being evaluated. Symbols become
+/// rodata strings, lists and vectors are built with their elements quoted in turn.
+pub(super) fn compile_quote(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    match args {
+        [form] => compile_quoted(form, context, program),
+        _ => Err(CompileError::ArityError("quote".to_string(), 1, args.len())),
+    }
+}
+
+fn compile_quoted(form: &Node, context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let quote_each = |nodes: &[Node]| -> Vec<Node> { nodes.iter().map(quoted).collect() };
+    match form {
+        Node::Primitive { .. } => compile_node(form, context, program),
+        Node::Symbol { value } if value == "nil" => Ok(CompileResult::with_instructions(vec![IRInstruction::Push(0)], ValueKind::Nil)),
+        Node::Symbol { value } => {
+            let index = program.add_string(value.clone());
+            Ok(CompileResult::with_instructions(vec![IRInstruction::PushString(index)], ValueKind::Symbol))
+        }
+        Node::List { root } => compile_sequence_literal(&quote_each(root), ValueKind::List, context, program),
+        Node::Vector { root } => compile_sequence_literal(&quote_each(root), ValueKind::Vector, context, program),
+        Node::Set { root } => compile_set_literal(&quote_each(root), context, program),
+        Node::Map { entries } => {
+            let flattened: Vec<Node> = entries.iter().flat_map(|(key, value)| [quoted(key), quoted(value)]).collect();
+            compile_hash_map(&flattened, context, program)
+        }
+    }
+}
+
+fn quoted(node: &Node) -> Node {
+    Node::new_list_from_raw(vec![Node::Symbol { value: "quote".to_string() }, node.clone()])
+}
+
+/// Compile list (a list of the evaluated arguments)
+pub(super) fn compile_list_literal(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_sequence_literal(args, ValueKind::List, context, program)
+}
+
+/// Resolve the kind of a `first`/`rest`/`cons` sequence argument, rejecting non-sequences
+fn resolve_sequence_kind(op: &str, node: &Node, initial: ValueKind, context: &CompileContext) -> Result<ValueKind, CompileError> {
+    let kind = resolve_value_kind(node, initial, context);
+    match kind {
+        ValueKind::List | ValueKind::Vector | ValueKind::Nil | ValueKind::Any => Ok(kind),
+        _ => Err(CompileError::InvalidExpression(format!("{} requires a collection, string, or nil", op))),
+    }
+}
+
+/// Sets, strings and maps go through the seq protocol instead of the list fast paths
+fn is_protocol_kind(kind: ValueKind) -> bool {
+    matches!(kind, ValueKind::Set | ValueKind::String | ValueKind::Map)
+}
+
+/// Compile cons (prepend a value to a list or vector, producing a list)
+pub(super) fn compile_cons(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("cons".to_string(), 2, args.len()));
+    }
+
+    let mut head_result = compile_node(&args[0], context, program)?;
+    let head_kind = resolve_value_kind(&args[0], head_result.kind, context);
+    let mut instructions = std::mem::take(&mut head_result.instructions);
+    let mut retained_slots: Vec<RetainedSlot> = Vec::new();
+
+    let rodata_symbol = head_kind == ValueKind::Symbol && head_result.heap_ownership == HeapOwnership::None;
+    if !rodata_symbol {
+        ensure_owned_on_stack(&mut instructions, head_kind, &mut head_result.heap_ownership);
+    }
+    let head_slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(head_slot));
+    let head_dependents = head_result.take_retained_slots();
+    if !rodata_symbol {
+        track_heap_slot(&mut retained_slots, head_slot, head_kind, None, head_dependents);
+    }
+
+    let mut seq_result = compile_node(&args[1], context, program)?;
+    if is_protocol_kind(resolve_value_kind(&args[1], seq_result.kind, context)) {
+        // Other collections become a list of their items first
+        seq_result = sequences::compile_protocol_items("cons", "_seq_of", &args[1], seq_result, context)?;
+    }
+    let seq_kind = resolve_sequence_kind("cons", &args[1], seq_result.kind, context)?;
+    instructions.push(IRInstruction::LoadLocal(head_slot));
+    instructions.push(IRInstruction::Push(head_kind.runtime_tag()));
+    extend_with_offset(&mut instructions, std::mem::take(&mut seq_result.instructions));
+
+    // The new list shares the tail's elements, so only the tail's own cells may be released
+    let mut tracker = SlotTracker::new();
+    tracker.track_if_owned(&mut instructions, context, seq_result.heap_ownership, seq_kind);
+    instructions.push(IRInstruction::RuntimeCall("_list_cons".to_string(), 3));
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+    retained_slots.extend(seq_result.take_retained_slots());
+
+    if !retains_slot(&retained_slots, head_slot) {
+        context.release_temp_slot(head_slot);
+    }
+    dedup_retained_slots(&mut retained_slots);
+
+    let element_kind = match (seq_kind, seq_result.vector_element_kind) {
+        (ValueKind::Nil, _) => Some(head_kind),
+        (_, Some(kind)) if kind == head_kind => Some(kind),
+        _ => None,
+    }
+    .filter(|kind| *kind != ValueKind::Any);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::List)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(element_kind)
+        .with_retained_slots(retained_slots))
+}
+
+/// Compile first (the first item of a collection or string, nil when it is empty)
+pub(super) fn compile_first(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("first".to_string(), 1, args.len()));
+    }
+
+    let mut target_result = compile_node(&args[0], context, program)?;
+    match resolve_value_kind(&args[0], target_result.kind, context) {
+        ValueKind::LazySeq => return Ok(compile_lazy_first(target_result, context)),
+        kind if is_protocol_kind(kind) => return sequences::compile_first_item(&args[0], target_result, context),
+        _ => {}
+    }
+    let target_kind = resolve_sequence_kind("first", &args[0], target_result.kind, context)?;
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let mut tracker = SlotTracker::new();
+
+    // Elements outlive the list cells, so an owned target is released right after the lookup
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, target_kind);
+    instructions.push(IRInstruction::Push(0));
+    emit_vector_get(&mut instructions, context, &mut tracker, None, &mut DefaultHandling::None);
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let element_kind = target_result.vector_element_kind.unwrap_or(ValueKind::Any);
+    let ownership = if element_kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+    let retained_slots = target_result.take_retained_slots();
+
+    Ok(CompileResult::with_instructions(instructions, element_kind)
+        .with_heap_ownership(ownership)
+        .with_retained_slots(retained_slots))
+}
+
+/// The first item of a lazy sequence comes back as a copy the caller owns
+fn compile_lazy_first(mut target_result: CompileResult, context: &mut CompileContext) -> CompileResult {
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let mut tracker = SlotTracker::new();
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, ValueKind::LazySeq);
+    instructions.push(IRInstruction::RuntimeCall("_lazy_first".to_string(), 1));
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+    target_result.free_retained_slots(&mut instructions, context);
+
+    let element_kind = target_result.vector_element_kind.unwrap_or(ValueKind::Any);
+    let ownership = if element_kind.is_heap_kind() { HeapOwnership::Owned } else { HeapOwnership::None };
+    CompileResult::with_instructions(instructions, element_kind).with_heap_ownership(ownership)
+}
+
+/// Compile rest (everything after the first item: a possibly empty list, or a lazy sequence for a
+/// lazy one)
+pub(super) fn compile_rest(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("rest".to_string(), 1, args.len()));
+    }
+
+    let mut target_result = compile_node(&args[0], context, program)?;
+    let kind = resolve_value_kind(&args[0], target_result.kind, context);
+    if kind == ValueKind::LazySeq || is_protocol_kind(kind) {
+        return sequences::compile_protocol_items("rest", "_seq_rest", &args[0], target_result, context);
+    }
+    let target_kind = resolve_sequence_kind("rest", &args[0], target_result.kind, context)?;
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let mut tracker = SlotTracker::new();
+
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, target_kind);
+    instructions.push(IRInstruction::RuntimeCall("_list_rest".to_string(), 1));
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let retained_slots = target_result.take_retained_slots();
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::List)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(target_result.vector_element_kind)
+        .with_retained_slots(retained_slots))
+}
+
+/// Compile list? (resolved from the argument's static kind)
+pub(super) fn compile_is_list(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("list?".to_string(), 1, args.len()));
+    }
+
+    let mut arg_result = compile_node(&args[0], context, program)?;
+    let arg_kind = resolve_value_kind(&args[0], arg_result.kind, context);
+    if arg_kind == ValueKind::Any {
+        return Err(CompileError::InvalidExpression("list? requires an argument of known type".to_string()));
+    }
+
+    let mut instructions = std::mem::take(&mut arg_result.instructions);
+    let slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(slot));
+    if arg_result.heap_ownership == HeapOwnership::Owned {
+        emit_free_for_slot(&mut instructions, slot, arg_kind);
+    }
+    context.release_temp_slot(slot);
+    instructions.push(IRInstruction::Push((arg_kind == ValueKind::List) as i64));
+    arg_result.free_retained_slots(&mut instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean))
+}
+
+/// Compile symbol (a symbol named by a string or another symbol)
+pub(super) fn compile_symbol(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("symbol".to_string(), 1, args.len()));
+    }
+
+    let mut arg_result = compile_node(&args[0], context, program)?;
+    let arg_kind = resolve_value_kind(&args[0], arg_result.kind, context);
+    if !matches!(arg_kind, ValueKind::String | ValueKind::Symbol | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression("symbol requires a string or symbol".to_string()));
+    }
+
+    let mut instructions = std::mem::take(&mut arg_result.instructions);
+    let mut tracker = SlotTracker::new();
+    tracker.track_if_owned(&mut instructions, context, arg_result.heap_ownership, arg_kind);
+    instructions.push(IRInstruction::RuntimeCall("_string_clone".to_string(), 1));
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+    arg_result.free_retained_slots(&mut instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Symbol).with_heap_ownership(HeapOwnership::Owned))
+}
@@ -0,0 +1,287 @@
+use super::{
+    builtins::emit_free_for_slot,
+    expressions::{dedup_retained_slots, ensure_branch_result_owned, merge_branch_results},
+    extend_with_offset, CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind,
+};
+/// `case` compilation - constant dispatch through jump tables
+///
+/// The target is evaluated once into a temporary slot, then:
+/// - dense integer constants index a `JumpTable` after subtracting the smallest constant
+/// - keyword and string constants are hashed with `_string_hash` into a table sized so each
+///   constant gets its own bucket; the arm confirms the match with `_string_equals` and jumps
+///   back to the default otherwise
+/// - sparse integers (or text constants with no collision-free table) are tested in order
+///
+/// Keywords and strings share one representation, so constants whose tag cannot match the
+/// target's kind are dropped first: a string target never reaches a keyword arm, even one with
+/// the same text. A `nil` constant is tested before the dispatch. An untyped target of a text
+/// case is first checked with `_string_readable`, so a number that arrives at run time goes to
+/// the default instead of being read as a string.
+///
+/// The default arm (or a thrown "No matching clause" error) follows the dispatch, and every
+/// arm jumps to the shared end.
+use crate::ast::{forms, Node, Primitive};
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Largest number of slots an integer table may span per constant before falling back to tests
+const MAX_TABLE_SPAN_PER_CONSTANT: u64 = 4;
+/// Table spans up to this size are always accepted, however few constants there are
+const MIN_TABLE_SPAN: u64 = 8;
+
+enum CaseConstant {
+    Number(i64),
+    Text(ValueKind, String), // keywords keep their leading `:` as in the string table
+    Nil,
+}
+
+impl CaseConstant {
+    /// Whether a target of `kind` can ever equal this constant
+    fn can_match(&self, kind: ValueKind) -> bool {
+        match self {
+            CaseConstant::Number(_) => matches!(kind, ValueKind::Number | ValueKind::Any),
+            CaseConstant::Text(tag, _) => kind == *tag || kind == ValueKind::Any,
+            CaseConstant::Nil => !matches!(kind, ValueKind::Number | ValueKind::Boolean),
+        }
+    }
+}
+
+/// Compile a case expression
+pub fn compile_case(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let case = forms::case_clauses(args).map_err(CompileError::InvalidExpression)?;
+
+    let mut constants: Vec<(CaseConstant, usize)> = Vec::new();
+    for (arm, (tests, _)) in case.clauses.iter().enumerate() {
+        for test in tests {
+            constants.push((case_constant(test)?, arm));
+        }
+    }
+    let numeric = constants.iter().all(|(constant, _)| !matches!(constant, CaseConstant::Text(..)));
+    let textual = constants.iter().all(|(constant, _)| !matches!(constant, CaseConstant::Number(_)));
+    if !numeric && !textual {
+        return Err(CompileError::InvalidExpression("case constants must all be numbers or all be keywords/strings".to_string()));
+    }
+
+    let mut target_result = crate::compiler::compile_node(case.target, context, program)?;
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let target_slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(target_slot));
+
+    // Compile bodies in source order so let bindings line up with type inference
+    let mut retained_slots: Vec<RetainedSlot> = Vec::new();
+    let mut arms = Vec::with_capacity(case.clauses.len());
+    for (_, body) in &case.clauses {
+        arms.push(compile_arm(body, context, program, &mut retained_slots)?);
+    }
+    let no_match = forms::no_matching_clause();
+    let default = compile_arm(case.default.unwrap_or(&no_match), context, program, &mut retained_slots)?;
+
+    let (mut kind, mut ownership, mut diverges) = (default.0.kind, default.0.heap_ownership, default.0.diverges);
+    for (result, _) in &arms {
+        let accumulated = CompileResult::with_instructions(Vec::new(), kind).with_heap_ownership(ownership).with_diverges(diverges);
+        (kind, ownership) = merge_branch_results(&accumulated, result);
+        diverges = diverges && result.diverges;
+    }
+
+    // Only constants the target's kind can equal take part in the dispatch
+    let (nil_arm, candidates) = constants
+        .into_iter()
+        .filter(|(constant, _)| constant.can_match(target_result.kind))
+        .fold((None, Vec::new()), |(nil_arm, mut candidates), (constant, arm)| match constant {
+            CaseConstant::Nil => (Some(arm), candidates),
+            constant => {
+                candidates.push((constant, arm));
+                (nil_arm, candidates)
+            }
+        });
+    let arm_constants = |arm: usize| -> Vec<&CaseConstant> { candidates.iter().filter(|(_, owner)| *owner == arm).map(|(constant, _)| constant).collect() };
+
+    let mut end_jumps = Vec::new();
+    let nil_jump = nil_arm.map(|arm| {
+        instructions.push(IRInstruction::LoadLocal(target_slot));
+        instructions.push(IRInstruction::Push(0));
+        instructions.push(IRInstruction::Equal);
+        instructions.push(IRInstruction::JumpIfZero(instructions.len() + 2));
+        instructions.push(IRInstruction::Jump(0));
+        (instructions.len() - 1, arm)
+    });
+    let textual_candidates = candidates.iter().any(|(constant, _)| matches!(constant, CaseConstant::Text(..)));
+    let text_guard = (textual_candidates && target_result.kind == ValueKind::Any).then(|| {
+        instructions.push(IRInstruction::LoadLocal(target_slot));
+        instructions.push(IRInstruction::RuntimeCall("_string_readable".to_string(), 1));
+        instructions.push(IRInstruction::JumpIfZero(0));
+        instructions.len() - 1
+    });
+    let mut body_starts = vec![0; arms.len()];
+    match table_layout(&candidates, target_slot) {
+        Some((prefix, entries, confirm)) => {
+            instructions.extend(prefix);
+            let table_idx = instructions.len();
+            instructions.push(IRInstruction::JumpTable(Vec::new()));
+
+            let default_start = instructions.len();
+            text_guard.into_iter().for_each(|guard| instructions[guard] = IRInstruction::JumpIfZero(default_start));
+            append_arm(&mut instructions, default.1, &mut end_jumps);
+
+            let mut arm_starts = Vec::with_capacity(arms.len());
+            for (arm, (_, arm_instructions)) in arms.into_iter().enumerate() {
+                arm_starts.push(instructions.len());
+                let tests = arm_constants(arm);
+                if confirm && !tests.is_empty() {
+                    // The bucket only narrows the target down; compare it against the arm's constants
+                    emit_constant_tests(&mut instructions, &tests, target_slot, program);
+                    instructions.push(IRInstruction::JumpIfZero(default_start));
+                }
+                body_starts[arm] = instructions.len();
+                append_arm(&mut instructions, arm_instructions, &mut end_jumps);
+            }
+
+            let targets = entries.iter().map(|entry| entry.map_or(default_start, |arm| arm_starts[arm])).collect();
+            instructions[table_idx] = IRInstruction::JumpTable(targets);
+        }
+        None => {
+            for (arm, (_, arm_instructions)) in arms.into_iter().enumerate() {
+                let tests = arm_constants(arm);
+                if tests.is_empty() && nil_arm != Some(arm) {
+                    continue;
+                }
+                // An arm reached only through its nil constant is skipped by the in-order tests
+                let next_test = if tests.is_empty() {
+                    instructions.push(IRInstruction::Jump(0));
+                    instructions.len() - 1
+                } else {
+                    emit_constant_tests(&mut instructions, &tests, target_slot, program);
+                    instructions.push(IRInstruction::JumpIfZero(0));
+                    instructions.len() - 1
+                };
+                body_starts[arm] = instructions.len();
+                append_arm(&mut instructions, arm_instructions, &mut end_jumps);
+                instructions[next_test] = match instructions[next_test] {
+                    IRInstruction::Jump(_) => IRInstruction::Jump(instructions.len()),
+                    _ => IRInstruction::JumpIfZero(instructions.len()),
+                };
+            }
+            let default_start = instructions.len();
+            text_guard.into_iter().for_each(|guard| instructions[guard] = IRInstruction::JumpIfZero(default_start));
+            append_arm(&mut instructions, default.1, &mut end_jumps);
+        }
+    }
+    nil_jump.into_iter().for_each(|(jump, arm)| instructions[jump] = IRInstruction::Jump(body_starts[arm]));
+
+    let end = instructions.len();
+    end_jumps.into_iter().for_each(|jump| instructions[jump] = IRInstruction::Jump(end));
+
+    if target_result.heap_ownership == HeapOwnership::Owned {
+        emit_free_for_slot(&mut instructions, target_slot, target_result.kind);
+    }
+    target_result.free_retained_slots(&mut instructions, context);
+    context.release_temp_slot(target_slot);
+
+    dedup_retained_slots(&mut retained_slots);
+
+    Ok(CompileResult::with_instructions(instructions, kind)
+        .with_heap_ownership(ownership)
+        .with_retained_slots(retained_slots)
+        .with_diverges(diverges))
+}
+
+fn case_constant(node: &Node) -> Result<CaseConstant, CompileError> {
+    match node {
+        Node::Primitive { value: Primitive::Number(n) } => Ok(CaseConstant::Number(*n as i64)),
+        Node::Primitive { value: Primitive::Keyword(k) } => Ok(CaseConstant::Text(ValueKind::Keyword, format!(":{}", k))),
+        Node::Primitive { value: Primitive::String(s) } => Ok(CaseConstant::Text(ValueKind::String, s.clone())),
+        Node::Symbol { value } if value == "nil" => Ok(CaseConstant::Nil),
+        other => Err(CompileError::InvalidExpression(format!("case constants must be numbers, keywords, strings or nil, found {:?}", other))),
+    }
+}
+
+/// Compile one arm body, making sure a borrowed heap result is owned like the other branches
+fn compile_arm(body: &Node, context: &mut CompileContext, program: &mut IRProgram, retained_slots: &mut Vec<RetainedSlot>) -> Result<(CompileResult, Vec<IRInstruction>), CompileError> {
+    let mut result = crate::compiler::compile_node(body, context, program)?;
+    retained_slots.extend(result.take_retained_slots());
+    let mut instructions = std::mem::take(&mut result.instructions);
+    ensure_branch_result_owned(body, &mut result, &mut instructions, context);
+    Ok((result, instructions))
+}
+
+fn append_arm(instructions: &mut Vec<IRInstruction>, arm_instructions: Vec<IRInstruction>, end_jumps: &mut Vec<usize>) {
+    extend_with_offset(instructions, arm_instructions);
+    end_jumps.push(instructions.len());
+    instructions.push(IRInstruction::Jump(0));
+}
+
+/// Push 1 when the target equals one of `constants`, 0 otherwise
+fn emit_constant_tests(instructions: &mut Vec<IRInstruction>, constants: &[&CaseConstant], target_slot: usize, program: &mut IRProgram) {
+    for (idx, constant) in constants.iter().enumerate() {
+        instructions.push(IRInstruction::LoadLocal(target_slot));
+        match constant {
+            CaseConstant::Number(n) => {
+                instructions.push(IRInstruction::Push(*n));
+                instructions.push(IRInstruction::Equal);
+            }
+            CaseConstant::Text(_, text) => {
+                instructions.push(IRInstruction::PushString(program.add_string(text.clone())));
+                instructions.push(IRInstruction::RuntimeCall("_string_equals".to_string(), 2));
+            }
+            CaseConstant::Nil => unreachable!("nil constants are tested before the dispatch"),
+        }
+        if idx > 0 {
+            instructions.push(IRInstruction::UncheckedAdd);
+        }
+    }
+}
+
+/// Instructions computing the table index, the arm owning each table entry, and whether arms
+/// must confirm the match. `None` when the constants are too sparse or collide.
+fn table_layout(constants: &[(CaseConstant, usize)], target_slot: usize) -> Option<(Vec<IRInstruction>, Vec<Option<usize>>, bool)> {
+    if constants.is_empty() {
+        return None;
+    }
+
+    if let CaseConstant::Text(..) = constants[0].0 {
+        let hashes: Vec<u64> = constants
+            .iter()
+            .map(|(constant, _)| match constant {
+                CaseConstant::Text(_, text) => slisp_runtime::string_hash_bytes(text.as_bytes()),
+                _ => unreachable!("case constants are all text"),
+            })
+            .collect();
+        let count = hashes.len() as u64;
+        let buckets = (count..=count * MAX_TABLE_SPAN_PER_CONSTANT + MIN_TABLE_SPAN).find(|size| {
+            let mut used: Vec<u64> = hashes.iter().map(|hash| hash % size).collect();
+            used.sort_unstable();
+            used.windows(2).all(|pair| pair[0] != pair[1])
+        })?;
+
+        let mut entries = vec![None; buckets as usize];
+        for (hash, (_, arm)) in hashes.iter().zip(constants) {
+            entries[(hash % buckets) as usize] = Some(*arm);
+        }
+        let prefix = vec![
+            IRInstruction::LoadLocal(target_slot),
+            IRInstruction::Push(buckets as i64),
+            IRInstruction::RuntimeCall("_string_hash".to_string(), 2),
+        ];
+        return Some((prefix, entries, true));
+    }
+
+    let numbers: Vec<i64> = constants
+        .iter()
+        .map(|(constant, _)| match constant {
+            CaseConstant::Number(n) => *n,
+            _ => unreachable!("case constants are all numbers"),
+        })
+        .collect();
+    let min = *numbers.iter().min()?;
+    let max = *numbers.iter().max()?;
+    let span = (max as i128 - min as i128 + 1) as u128;
+    if span > (numbers.len() as u64 * MAX_TABLE_SPAN_PER_CONSTANT).max(MIN_TABLE_SPAN) as u128 {
+        return None;
+    }
+
+    let mut entries = vec![None; span as usize];
+    for (number, (_, arm)) in numbers.iter().zip(constants) {
+        entries[(*number - min) as usize] = Some(*arm);
+    }
+    let prefix = vec![IRInstruction::LoadLocal(target_slot), IRInstruction::Push(min), IRInstruction::UncheckedSub];
+    Some((prefix, entries, false))
+}
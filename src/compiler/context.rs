@@ -1,10 +1,12 @@
 /// Compilation context for tracking variables, parameters, and functions
 use super::{
     inference::{BindingOwner, FunctionKey, TypeInferenceSummary},
+    polymorphism::Protocol,
+    records::RecordLayout,
     HeapOwnership, MapValueTypes, ValueKind,
 };
 use crate::ir::FunctionInfo;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Context maintained during compilation
 /// Tracks variables, parameters, and function definitions
@@ -16,21 +18,30 @@ pub struct CompileContext {
     pub variable_map_value_types: HashMap<String, MapValueTypes>,                         // tracks map entry metadata for locals
     pub variable_set_element_kinds: HashMap<String, ValueKind>,                           // set element kinds for locals
     pub variable_vector_element_kinds: HashMap<String, ValueKind>,                        // vector element kinds for locals
+    pub variable_record_types: HashMap<String, String>,                                   // record type names for locals
     pub parameters: HashMap<String, usize>,                                               // parameter name -> param slot index
     pub parameter_types: HashMap<String, ValueKind>,                                      // inferred parameter types
     pub parameter_map_value_types: HashMap<String, MapValueTypes>,                        // map metadata for parameters
     pub parameter_set_element_kinds: HashMap<String, ValueKind>,                          // set element kinds for parameters
     pub parameter_vector_element_kinds: HashMap<String, ValueKind>,                       // vector element kinds for parameters
+    pub parameter_record_types: HashMap<String, String>,                                  // record type names for parameters
     pub functions: HashMap<String, FunctionInfo>,                                         // function name -> function info
     pub function_return_types: HashMap<String, ValueKind>,                                // function name -> return kind
     pub function_return_map_value_types: HashMap<String, MapValueTypes>,                  // function name -> map metadata
     pub function_return_set_element_kinds: HashMap<String, ValueKind>,                    // function name -> set element kind
     pub function_return_vector_element_kinds: HashMap<String, ValueKind>,                 // function name -> vector element kind
+    pub function_return_record_types: HashMap<String, String>,                            // function name -> record type name
     pub function_parameter_types: HashMap<String, Vec<ValueKind>>,                        // function name -> parameter kinds
     pub function_parameter_map_value_types: HashMap<String, Vec<Option<MapValueTypes>>>,  // function name -> parameter map metadata
     pub function_parameter_set_element_kinds: HashMap<String, Vec<Option<ValueKind>>>,    // function name -> parameter set element kind
     pub function_parameter_vector_element_kinds: HashMap<String, Vec<Option<ValueKind>>>, // function name -> parameter vector element kind
+    pub function_parameter_record_types: HashMap<String, Vec<Option<String>>>,            // function name -> parameter record type names
     pub function_return_ownership: HashMap<String, HeapOwnership>,                        // function name -> heap ownership semantics
+    pub variadic_functions: HashSet<String>,                                              // functions whose last parameter collects extra arguments
+    pub consumed_parameters: HashSet<(String, usize)>,                                    // (function, parameter index) pairs the function takes over
+    pub function_arities: HashMap<String, Vec<String>>,                                   // multi-arity function name -> per-arity function symbols
+    pub records: HashMap<String, RecordLayout>,                                           // record type name -> field layout
+    pub protocol_methods: HashMap<String, String>,                                        // protocol method name -> protocol name
     pub type_inference: Option<TypeInferenceSummary>,                                     // cached inference summary for current compilation unit
     pub current_function: FunctionKey,
     pub local_binding_offsets: HashMap<FunctionKey, usize>,
@@ -48,21 +59,30 @@ impl CompileContext {
             variable_map_value_types: HashMap::new(),
             variable_set_element_kinds: HashMap::new(),
             variable_vector_element_kinds: HashMap::new(),
+            variable_record_types: HashMap::new(),
             parameters: HashMap::new(),
             parameter_types: HashMap::new(),
             parameter_map_value_types: HashMap::new(),
             parameter_set_element_kinds: HashMap::new(),
             parameter_vector_element_kinds: HashMap::new(),
+            parameter_record_types: HashMap::new(),
             functions: HashMap::new(),
             function_return_types: HashMap::new(),
             function_return_map_value_types: HashMap::new(),
             function_return_set_element_kinds: HashMap::new(),
             function_return_vector_element_kinds: HashMap::new(),
+            function_return_record_types: HashMap::new(),
             function_parameter_types: HashMap::new(),
             function_parameter_map_value_types: HashMap::new(),
             function_parameter_set_element_kinds: HashMap::new(),
             function_parameter_vector_element_kinds: HashMap::new(),
+            function_parameter_record_types: HashMap::new(),
             function_return_ownership: HashMap::new(),
+            variadic_functions: HashSet::new(),
+            consumed_parameters: HashSet::new(),
+            function_arities: HashMap::new(),
+            records: HashMap::new(),
+            protocol_methods: HashMap::new(),
             type_inference: None,
             current_function: FunctionKey::Program,
             local_binding_offsets: HashMap::new(),
@@ -100,21 +120,30 @@ impl CompileContext {
             variable_map_value_types: HashMap::new(),
             variable_set_element_kinds: HashMap::new(),
             variable_vector_element_kinds: HashMap::new(),
+            variable_record_types: HashMap::new(),
             parameters: HashMap::new(),
             parameter_types: HashMap::new(),
             parameter_map_value_types: HashMap::new(),
             parameter_set_element_kinds: HashMap::new(),
             parameter_vector_element_kinds: HashMap::new(),
+            parameter_record_types: HashMap::new(),
             functions: self.functions.clone(),
             function_return_types: self.function_return_types.clone(),
             function_return_map_value_types: self.function_return_map_value_types.clone(),
             function_return_set_element_kinds: self.function_return_set_element_kinds.clone(),
             function_return_vector_element_kinds: self.function_return_vector_element_kinds.clone(),
+            function_return_record_types: self.function_return_record_types.clone(),
             function_parameter_types: self.function_parameter_types.clone(),
             function_parameter_map_value_types: self.function_parameter_map_value_types.clone(),
             function_parameter_set_element_kinds: self.function_parameter_set_element_kinds.clone(),
             function_parameter_vector_element_kinds: self.function_parameter_vector_element_kinds.clone(),
+            function_parameter_record_types: self.function_parameter_record_types.clone(),
             function_return_ownership: self.function_return_ownership.clone(),
+            variadic_functions: self.variadic_functions.clone(),
+            consumed_parameters: self.consumed_parameters.clone(),
+            function_arities: self.function_arities.clone(),
+            records: self.records.clone(),
+            protocol_methods: self.protocol_methods.clone(),
             type_inference: self.type_inference.clone(),
             current_function: key,
             local_binding_offsets,
@@ -188,6 +217,21 @@ impl CompileContext {
         self.variable_vector_element_kinds.get(name).copied()
     }
 
+    pub fn set_variable_record_type(&mut self, name: &str, record: Option<String>) {
+        match record {
+            Some(record) => {
+                self.variable_record_types.insert(name.to_string(), record);
+            }
+            None => {
+                self.variable_record_types.remove(name);
+            }
+        }
+    }
+
+    pub fn get_variable_record_type(&self, name: &str) -> Option<&str> {
+        self.variable_record_types.get(name).map(String::as_str)
+    }
+
     /// Get the inferred type for a variable
     pub fn get_variable_type(&self, name: &str) -> Option<ValueKind> {
         self.variable_types.get(name).copied()
@@ -205,6 +249,7 @@ impl CompileContext {
         self.heap_allocated_vars.entry(name.clone()).or_insert(false);
         self.parameter_set_element_kinds.remove(&name);
         self.parameter_vector_element_kinds.remove(&name);
+        self.parameter_record_types.remove(&name);
     }
 
     /// Get the slot index for a parameter
@@ -267,6 +312,21 @@ impl CompileContext {
         self.parameter_vector_element_kinds.get(name).copied()
     }
 
+    pub fn set_parameter_record_type(&mut self, name: &str, record: Option<String>) {
+        match record {
+            Some(record) => {
+                self.parameter_record_types.insert(name.to_string(), record);
+            }
+            None => {
+                self.parameter_record_types.remove(name);
+            }
+        }
+    }
+
+    pub fn get_parameter_record_type(&self, name: &str) -> Option<&str> {
+        self.parameter_record_types.get(name).map(String::as_str)
+    }
+
     /// Add a function to the context
     pub fn add_function(&mut self, name: String, info: FunctionInfo) -> Result<(), super::CompileError> {
         debug_assert!(!self.in_function, "function declarations must be registered on the root context");
@@ -277,6 +337,64 @@ impl CompileContext {
         Ok(())
     }
 
+    /// Mark a function as taking `& rest`; its last parameter receives the extra arguments as a vector
+    pub fn mark_function_variadic(&mut self, name: &str) {
+        self.variadic_functions.insert(name.to_string());
+    }
+
+    pub fn is_function_variadic(&self, name: &str) -> bool {
+        self.variadic_functions.contains(name)
+    }
+
+    /// Mark a vector parameter as taken over by its function: callers hand it a vector of its own,
+    /// which the function updates in place, returns or frees
+    pub fn mark_parameter_consumed(&mut self, name: &str, index: usize) {
+        self.consumed_parameters.insert((name.to_string(), index));
+    }
+
+    pub fn is_parameter_consumed(&self, name: &str, index: usize) -> bool {
+        self.consumed_parameters.contains(&(name.to_string(), index))
+    }
+
+    /// Record the per-arity function symbols of a multi-arity function
+    pub fn set_function_arities(&mut self, name: &str, symbols: Vec<String>) {
+        self.function_arities.insert(name.to_string(), symbols);
+    }
+
+    pub fn get_function_arities(&self, name: &str) -> Option<&Vec<String>> {
+        self.function_arities.get(name)
+    }
+
+    /// Register the fields of a record type, giving it the next type id; redeclaring the same
+    /// fields keeps the existing layout
+    pub fn add_record(&mut self, name: &str, fields: Vec<String>) -> Result<(), super::CompileError> {
+        match self.records.get(name) {
+            Some(layout) if layout.fields == fields => Ok(()),
+            Some(_) => Err(super::CompileError::InvalidExpression(format!("record {} is already defined with other fields", name))),
+            None => {
+                let type_id = self.records.len() + 1;
+                self.records.insert(name.to_string(), RecordLayout { type_id, fields });
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_record(&self, name: &str) -> Option<&RecordLayout> {
+        self.records.get(name)
+    }
+
+    /// Register the methods of a protocol, so calls to them dispatch on their first argument
+    pub(super) fn add_protocol(&mut self, protocol: &Protocol) {
+        for (method, _) in &protocol.methods {
+            self.protocol_methods.insert(method.clone(), protocol.name.clone());
+        }
+    }
+
+    /// The protocol declaring `method`, if it is a protocol method
+    pub fn get_protocol(&self, method: &str) -> Option<&str> {
+        self.protocol_methods.get(method).map(String::as_str)
+    }
+
     /// Get function info by name
     pub fn get_function(&self, name: &str) -> Option<&FunctionInfo> {
         self.functions.get(name)
@@ -334,6 +452,21 @@ impl CompileContext {
         self.function_return_vector_element_kinds.get(name).copied()
     }
 
+    pub fn set_function_return_record_type(&mut self, name: &str, record: Option<String>) {
+        match record {
+            Some(record) => {
+                self.function_return_record_types.insert(name.to_string(), record);
+            }
+            None => {
+                self.function_return_record_types.remove(name);
+            }
+        }
+    }
+
+    pub fn get_function_return_record_type(&self, name: &str) -> Option<&str> {
+        self.function_return_record_types.get(name).map(String::as_str)
+    }
+
     pub fn set_function_return_ownership(&mut self, name: &str, ownership: HeapOwnership) {
         self.function_return_ownership.insert(name.to_string(), ownership);
     }
@@ -383,6 +516,9 @@ impl CompileContext {
                 if let Some(vector_element_kind) = summary.binding_vector_element_kind(return_binding) {
                     self.set_function_return_vector_element_kind(name, Some(vector_element_kind));
                 }
+                if let Some(record) = summary.binding_record_type(return_binding) {
+                    self.set_function_return_record_type(name, Some(record.to_string()));
+                }
             }
 
             for (idx, binding_id) in analysis.parameter_bindings.iter().enumerate() {
@@ -398,6 +534,9 @@ impl CompileContext {
                 if let Some(vector_element_kind) = summary.binding_vector_element_kind(*binding_id) {
                     self.set_function_parameter_vector_element_kind(name, idx, Some(vector_element_kind));
                 }
+                if let Some(record) = summary.binding_record_type(*binding_id) {
+                    self.set_function_parameter_record_type(name, idx, Some(record.to_string()));
+                }
             }
         }
     }
@@ -497,6 +636,18 @@ impl CompileContext {
             .copied()
     }
 
+    pub fn set_function_parameter_record_type(&mut self, name: &str, index: usize, record: Option<String>) {
+        let entry = self.function_parameter_record_types.entry(name.to_string()).or_default();
+        if entry.len() <= index {
+            entry.resize(index + 1, None);
+        }
+        entry[index] = record;
+    }
+
+    pub fn get_function_parameter_record_type(&self, name: &str, index: usize) -> Option<&str> {
+        self.function_parameter_record_types.get(name).and_then(|values| values.get(index)).and_then(|slot| slot.as_deref())
+    }
+
     /// Get the recorded parameter types for a function if available
     pub fn get_function_parameter_type(&self, name: &str, index: usize) -> Option<ValueKind> {
         self.function_parameter_types.get(name).and_then(|params| params.get(index)).copied()
@@ -510,6 +661,7 @@ impl CompileContext {
             self.variable_map_value_types.remove(name);
             self.variable_set_element_kinds.remove(name);
             self.variable_vector_element_kinds.remove(name);
+            self.variable_record_types.remove(name);
             Some(slot)
         } else {
             None
@@ -585,7 +737,7 @@ impl CompileContext {
     /// Mark a variable as holding a heap-allocated pointer
     pub fn mark_heap_allocated(&mut self, name: &str, kind: ValueKind) {
         self.heap_allocated_vars.insert(name.to_string(), true);
-        if matches!(kind, ValueKind::String | ValueKind::Vector | ValueKind::Map | ValueKind::Set) {
+        if kind.is_heap_kind() {
             if self.variables.contains_key(name) {
                 self.variable_types.insert(name.to_string(), kind);
             } else if self.parameters.contains_key(name) {
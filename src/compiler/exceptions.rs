@@ -0,0 +1,371 @@
+use super::{
+    builtins::{clone_runtime_for_kind, compile_get, compile_hash_map, emit_free_for_slot},
+    expressions::merge_branch_results,
+    extend_with_offset, CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind,
+};
+/// Exception compilation - throw, try/catch/finally, ex-info and its accessors
+///
+/// `ex-info` builds a plain map with `:message`, `:data` and an optional `:cause`. `throw` hands
+/// that map to the runtime, which unwinds to the innermost handler installed by `try` and frees
+/// what the unwound frames allocated, keeping values they stored into older atoms and lazy
+/// sequences. Any value can be thrown: a `catch ExceptionInfo` clause takes only the maps `ex-info`
+/// built and binds a map, any other class takes every exception and binds a value of unknown kind,
+/// and one that no clause takes is thrown on.
+use crate::ast::{Node, Primitive};
+use crate::ir::{IRInstruction, IRProgram};
+
+struct CatchClause<'a> {
+    // Whether the clause only takes `ex-info` maps
+    info_only: bool,
+    binding: &'a str,
+    body: &'a [Node],
+}
+
+struct TryForm<'a> {
+    body: &'a [Node],
+    catches: Vec<CatchClause<'a>>,
+    finally: Option<&'a [Node]>,
+}
+
+/// Compile (ex-info msg data [cause]) into an exception map
+pub(super) fn compile_ex_info(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(CompileError::ArityError("ex-info".to_string(), 2, args.len()));
+    }
+
+    let mut entries = vec![keyword("message"), args[0].clone(), keyword("data"), args[1].clone()];
+    if let Some(cause) = args.get(2) {
+        entries.push(keyword("cause"));
+        entries.push(cause.clone());
+    }
+
+    compile_hash_map(&entries, context, program)
+}
+
+/// Compile (ex-message e)
+pub(super) fn compile_ex_message(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_exception_field(args, context, program, "ex-message", "message", ValueKind::String)
+}
+
+/// Compile (ex-data e)
+pub(super) fn compile_ex_data(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_exception_field(args, context, program, "ex-data", "data", ValueKind::Map)
+}
+
+/// Compile (ex-cause e)
+pub(super) fn compile_ex_cause(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_exception_field(args, context, program, "ex-cause", "cause", ValueKind::Any)
+}
+
+fn compile_exception_field(args: &[Node], context: &mut CompileContext, program: &mut IRProgram, op_name: &str, field: &str, kind: ValueKind) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError(op_name.to_string(), 1, args.len()));
+    }
+
+    let mut result = compile_get(&[args[0].clone(), keyword(field)], context, program)?;
+    if result.kind == ValueKind::Any {
+        result.kind = kind;
+    }
+    Ok(result)
+}
+
+/// Compile (throw exception)
+pub(super) fn compile_throw(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("throw".to_string(), 1, args.len()));
+    }
+
+    let mut thrown = crate::compiler::compile_node(&args[0], context, program)?;
+    let mut instructions = std::mem::take(&mut thrown.instructions);
+    instructions.push(IRInstruction::RuntimeCall("_exception_throw".to_string(), 1));
+    // Unreachable, but keeps the temporaries' slots balanced in the context.
+    thrown.free_retained_slots(&mut instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Nil).with_diverges(true))
+}
+
+/// Compile (try body* (catch Class e handler*)? (finally cleanup*)?)
+pub(super) fn compile_try(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let form = parse_try(args)?;
+
+    match form.finally {
+        Some(finally) => compile_try_finally(form.body, form.catches, finally, context, program),
+        None if !form.catches.is_empty() => compile_try_catch(form.body, form.catches, context, program),
+        None => compile_sequence(form.body, context, program),
+    }
+}
+
+fn parse_try(args: &[Node]) -> Result<TryForm<'_>, CompileError> {
+    let clause_start = args.iter().position(|node| clause_name(node).is_some()).unwrap_or(args.len());
+    let mut form = TryForm {
+        body: &args[..clause_start],
+        catches: Vec::new(),
+        finally: None,
+    };
+
+    for (idx, clause) in args[clause_start..].iter().enumerate() {
+        let Node::List { root } = clause else {
+            return Err(CompileError::InvalidExpression("try body forms must precede catch and finally clauses".to_string()));
+        };
+
+        match clause_name(clause) {
+            Some("catch") => {
+                if root.len() < 3 {
+                    return Err(CompileError::ArityError("catch".to_string(), 2, root.len() - 1));
+                }
+                let info_only = catches_info_only(&root[1])?;
+                let binding = match &root[2] {
+                    Node::Symbol { value } => value.as_str(),
+                    _ => return Err(CompileError::InvalidExpression("catch binding must be a symbol".to_string())),
+                };
+                // Clauses after one taking every exception never run
+                if form.catches.last().is_none_or(|clause| clause.info_only) {
+                    form.catches.push(CatchClause { info_only, binding, body: &root[3..] });
+                }
+            }
+            Some("finally") => {
+                if clause_start + idx != args.len() - 1 {
+                    return Err(CompileError::InvalidExpression("finally must be the last clause in try".to_string()));
+                }
+                form.finally = Some(&root[1..]);
+            }
+            _ => return Err(CompileError::InvalidExpression("try body forms must precede catch and finally clauses".to_string())),
+        }
+    }
+
+    Ok(form)
+}
+
+fn clause_name(node: &Node) -> Option<&str> {
+    match node {
+        Node::List { root } => match root.first() {
+            Some(Node::Symbol { value }) if value == "catch" || value == "finally" => Some(value.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a catch class takes only `ex-info` maps (`ExceptionInfo`) rather than every exception
+fn catches_info_only(class: &Node) -> Result<bool, CompileError> {
+    match class {
+        Node::Symbol { value } if value == "ExceptionInfo" => Ok(true),
+        Node::Symbol { value } if matches!(value.as_str(), "Exception" | "Throwable" | "Object") => Ok(false),
+        Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == "default" => Ok(false),
+        _ => Err(CompileError::InvalidExpression("catch expects Exception, Throwable, Object, ExceptionInfo, or :default".to_string())),
+    }
+}
+
+/// Layout: PushHandler(landing); body; PopHandler; Jump(end); landing: StoreLocal(pending);
+///         per clause: [is-info test; JumpIfZero(next)] handler; Jump(end);
+///         then, unless a clause takes everything: LoadLocal(pending); throw; end
+fn compile_try_catch(body: &[Node], catches: Vec<CatchClause<'_>>, context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let mut instructions = vec![IRInstruction::PushHandler(0)];
+
+    let mut body_result = compile_sequence(body, context, program)?;
+    extend_with_offset(&mut instructions, std::mem::take(&mut body_result.instructions));
+    ensure_result_owned(&mut body_result, &mut instructions);
+    let mut retained_slots = body_result.take_retained_slots();
+    instructions.push(IRInstruction::PopHandler);
+
+    let mut end_jumps = vec![instructions.len()];
+    instructions.push(IRInstruction::Jump(0));
+
+    let landing = instructions.len();
+    instructions[0] = IRInstruction::PushHandler(landing);
+    clear_unwound_slots(&retained_slots, &mut instructions);
+
+    let pending_slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(pending_slot));
+
+    let mut merged = CompileResult::with_instructions(Vec::new(), body_result.kind)
+        .with_heap_ownership(body_result.heap_ownership)
+        .with_diverges(body_result.diverges);
+    let rethrows = catches.iter().all(|clause| clause.info_only);
+    for catch in catches {
+        let skip_jump = catch.info_only.then(|| {
+            instructions.push(IRInstruction::LoadLocal(pending_slot));
+            instructions.push(IRInstruction::RuntimeCall("_exception_is_info".to_string(), 1));
+            instructions.push(IRInstruction::JumpIfZero(0));
+            instructions.len() - 1
+        });
+
+        let mut handler_result = compile_catch(catch, pending_slot, &mut instructions, context, program)?;
+        retained_slots.extend(handler_result.take_retained_slots());
+        let (kind, ownership) = merge_handler_results(&merged, &handler_result);
+        merged = CompileResult::with_instructions(Vec::new(), kind)
+            .with_heap_ownership(ownership)
+            .with_diverges(merged.diverges && handler_result.diverges);
+
+        end_jumps.push(instructions.len());
+        instructions.push(IRInstruction::Jump(0));
+        if let Some(skip_jump) = skip_jump {
+            instructions[skip_jump] = IRInstruction::JumpIfZero(instructions.len());
+        }
+    }
+
+    if rethrows {
+        instructions.push(IRInstruction::LoadLocal(pending_slot));
+        instructions.push(IRInstruction::RuntimeCall("_exception_throw".to_string(), 1));
+    }
+    context.release_temp_slot(pending_slot);
+
+    let end = instructions.len();
+    for jump in end_jumps {
+        instructions[jump] = IRInstruction::Jump(end);
+    }
+
+    Ok(CompileResult::with_instructions(instructions, merged.kind)
+        .with_heap_ownership(merged.heap_ownership)
+        .with_retained_slots(retained_slots)
+        .with_diverges(merged.diverges))
+}
+
+/// Bind the exception to the clause's name, run its body and release the exception
+fn compile_catch(catch: CatchClause<'_>, pending_slot: usize, instructions: &mut Vec<IRInstruction>, context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let exception_slot = context.add_variable(catch.binding.to_string());
+    instructions.push(IRInstruction::LoadLocal(pending_slot));
+    instructions.push(IRInstruction::StoreLocal(exception_slot));
+    let exception_kind = if catch.info_only { ValueKind::Map } else { ValueKind::Any };
+    context.set_variable_type(catch.binding, exception_kind);
+    context.mark_heap_allocated(catch.binding, exception_kind);
+
+    let mut handler_result = compile_sequence(catch.body, context, program)?;
+    let handler_instructions = std::mem::take(&mut handler_result.instructions);
+    let reads_exception = handler_instructions.iter().any(|inst| matches!(inst, IRInstruction::LoadLocal(slot) if *slot == exception_slot));
+    extend_with_offset(instructions, handler_instructions);
+    ensure_result_owned(&mut handler_result, instructions);
+
+    // Values read out of the exception may still point into it; give the result its own copy
+    // before the exception is released.
+    if reads_exception && handler_result.kind == ValueKind::Any && handler_result.heap_ownership != HeapOwnership::Owned {
+        // An untyped value may be the exception or part of it; copy it by the kind it has at run time
+        instructions.push(IRInstruction::Push(handler_result.kind.runtime_tag()));
+        instructions.push(IRInstruction::RuntimeCall("_exception_value_clone".to_string(), 2));
+        handler_result.heap_ownership = HeapOwnership::Owned;
+    } else if reads_exception && matches!(handler_result.kind, ValueKind::Map | ValueKind::Vector | ValueKind::Set) && handler_result.heap_ownership == HeapOwnership::Owned {
+        let shallow_slot = context.allocate_temp_slot();
+        instructions.push(IRInstruction::StoreLocal(shallow_slot));
+        instructions.push(IRInstruction::LoadLocal(shallow_slot));
+        instructions.push(IRInstruction::Push(handler_result.kind.runtime_tag()));
+        instructions.push(IRInstruction::RuntimeCall("_exception_value_clone".to_string(), 2));
+        emit_free_for_slot(instructions, shallow_slot, handler_result.kind);
+        context.release_temp_slot(shallow_slot);
+    }
+
+    instructions.push(IRInstruction::FreeLocalWithRuntime(exception_slot, "_exception_free".to_string()));
+    context.remove_variable(catch.binding);
+    Ok(handler_result)
+}
+
+/// Layout: PushHandler(landing); protected; PopHandler; cleanup; Jump(end);
+///         landing: StoreLocal(e); cleanup; LoadLocal(e); throw; end
+fn compile_try_finally(body: &[Node], catches: Vec<CatchClause<'_>>, finally: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let mut instructions = vec![IRInstruction::PushHandler(0)];
+
+    let mut protected = if catches.is_empty() {
+        compile_sequence(body, context, program)?
+    } else {
+        compile_try_catch(body, catches, context, program)?
+    };
+    extend_with_offset(&mut instructions, std::mem::take(&mut protected.instructions));
+    ensure_result_owned(&mut protected, &mut instructions);
+    let protected_retained_slots = protected.take_retained_slots();
+    instructions.push(IRInstruction::PopHandler);
+    emit_discarded_sequence(finally, &mut instructions, context, program)?;
+
+    let end_jump_pos = instructions.len();
+    instructions.push(IRInstruction::Jump(0));
+
+    let landing = instructions.len();
+    instructions[0] = IRInstruction::PushHandler(landing);
+    clear_unwound_slots(&protected_retained_slots, &mut instructions);
+
+    let pending_slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(pending_slot));
+    emit_discarded_sequence(finally, &mut instructions, context, program)?;
+    instructions.push(IRInstruction::LoadLocal(pending_slot));
+    instructions.push(IRInstruction::RuntimeCall("_exception_throw".to_string(), 1));
+    context.release_temp_slot(pending_slot);
+
+    let end = instructions.len();
+    instructions[end_jump_pos] = IRInstruction::Jump(end);
+
+    Ok(CompileResult::with_instructions(instructions, protected.kind)
+        .with_heap_ownership(protected.heap_ownership)
+        .with_map_value_types(protected.map_value_types)
+        .with_retained_slots(protected_retained_slots)
+        .with_diverges(protected.diverges))
+}
+
+/// Evaluate forms in order, keeping only the value of the last one
+fn compile_sequence(forms: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let Some((last, leading)) = forms.split_last() else {
+        return Ok(CompileResult::with_instructions(vec![IRInstruction::Push(0)], ValueKind::Nil));
+    };
+
+    let mut instructions = Vec::new();
+    emit_discarded_sequence(leading, &mut instructions, context, program)?;
+
+    let mut result = crate::compiler::compile_node(last, context, program)?;
+    extend_with_offset(&mut instructions, std::mem::take(&mut result.instructions));
+    result.instructions = instructions;
+    Ok(result)
+}
+
+fn emit_discarded_sequence(forms: &[Node], instructions: &mut Vec<IRInstruction>, context: &mut CompileContext, program: &mut IRProgram) -> Result<(), CompileError> {
+    for form in forms {
+        let mut result = crate::compiler::compile_node(form, context, program)?;
+        extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+
+        // There is no pop instruction; park the value in a scratch slot and release it there.
+        let scratch_slot = context.allocate_temp_slot();
+        instructions.push(IRInstruction::StoreLocal(scratch_slot));
+        if result.heap_ownership == HeapOwnership::Owned {
+            emit_free_for_slot(instructions, scratch_slot, result.kind);
+        }
+        context.release_temp_slot(scratch_slot);
+        result.free_retained_slots(instructions, context);
+    }
+    Ok(())
+}
+
+/// Both paths hand back owned values (or plain immediates), so unlike `if` the merged result never
+/// needs to be treated as borrowed.
+fn merge_handler_results(body: &CompileResult, handler: &CompileResult) -> (ValueKind, HeapOwnership) {
+    let (kind, ownership) = merge_branch_results(body, handler);
+    let owned_or_immediate = |result: &CompileResult| result.heap_ownership == HeapOwnership::Owned || (result.heap_ownership == HeapOwnership::None && !result.kind.is_heap_clone_kind());
+
+    if ownership == HeapOwnership::Borrowed && owned_or_immediate(body) && owned_or_immediate(handler) {
+        (kind, HeapOwnership::Owned)
+    } else {
+        (kind, ownership)
+    }
+}
+
+fn ensure_result_owned(result: &mut CompileResult, instructions: &mut Vec<IRInstruction>) {
+    if result.heap_ownership != HeapOwnership::Borrowed {
+        return;
+    }
+
+    if let Some(runtime) = clone_runtime_for_kind(result.kind) {
+        instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
+        result.heap_ownership = HeapOwnership::Owned;
+    }
+}
+
+/// Slots that outlive the protected body were filled after the handler was pushed, so the runtime
+/// already released their contents when it unwound. Zero them so later cleanup skips them.
+fn clear_unwound_slots(retained_slots: &[RetainedSlot], instructions: &mut Vec<IRInstruction>) {
+    for retained in retained_slots {
+        instructions.push(IRInstruction::Push(0));
+        instructions.push(IRInstruction::StoreLocal(retained.slot));
+        clear_unwound_slots(&retained.dependents, instructions);
+    }
+}
+
+fn keyword(name: &str) -> Node {
+    Node::Primitive {
+        value: Primitive::Keyword(name.to_string()),
+    }
+}
@@ -1,6 +1,7 @@
 use super::{extend_with_offset, slots::SlotTracker, CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind};
 /// Expression compilation - arithmetic, comparisons, conditionals, logical operations
 use crate::ast::{Node, Primitive};
+use crate::compiler::builtins::emit_free_for_slot;
 use crate::compiler::is_heap_allocated_symbol;
 use crate::ir::{IRInstruction, IRProgram};
 
@@ -42,57 +43,136 @@ pub fn compile_arithmetic_op(args: &[Node], context: &mut CompileContext, progra
     Ok(CompileResult::with_instructions(instructions, ValueKind::Number))
 }
 
-/// Compile comparison operations (=, <, >, <=, >=)
-pub fn compile_comparison_op(args: &[Node], context: &mut CompileContext, program: &mut IRProgram, instruction: IRInstruction, op_name: &str) -> Result<CompileResult, CompileError> {
-    if args.len() != 2 {
-        return Err(CompileError::ArityError(op_name.to_string(), 2, args.len()));
-    }
+/// Both operands of a binary operation on the stack, with their resolved kinds
+struct Operands {
+    instructions: Vec<IRInstruction>,
+    tracker: SlotTracker,
+    left: CompileResult,
+    right: CompileResult,
+    left_kind: ValueKind,
+    right_kind: ValueKind,
+}
 
-    let mut tracker = SlotTracker::new();
+impl Operands {
+    fn compile(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<Self, CompileError> {
+        let mut tracker = SlotTracker::new();
 
-    let mut left_result = crate::compiler::compile_node(&args[0], context, program)?;
-    let mut instructions = std::mem::take(&mut left_result.instructions);
-    let mut left_kind = left_result.kind;
+        let mut left = crate::compiler::compile_node(&args[0], context, program)?;
+        let mut instructions = std::mem::take(&mut left.instructions);
+        let left_slot = tracker.track_if_owned(&mut instructions, context, left.heap_ownership, ValueKind::Any);
 
-    let left_slot = tracker.track_if_owned(&mut instructions, context, left_result.heap_ownership, ValueKind::Any);
+        let mut right = crate::compiler::compile_node(&args[1], context, program)?;
+        extend_with_offset(&mut instructions, std::mem::take(&mut right.instructions));
+        let right_slot = tracker.track_if_owned(&mut instructions, context, right.heap_ownership, ValueKind::Any);
 
-    let mut right_result = crate::compiler::compile_node(&args[1], context, program)?;
-    let mut right_kind = right_result.kind;
+        let left_kind = resolve_operand_kind(&args[0], left.kind, context);
+        let right_kind = resolve_operand_kind(&args[1], right.kind, context);
+        if let Some(slot) = left_slot {
+            tracker.set_slot_kind(slot, left_kind);
+        }
+        if let Some(slot) = right_slot {
+            tracker.set_slot_kind(slot, right_kind);
+        }
 
-    let right_instructions = std::mem::take(&mut right_result.instructions);
-    extend_with_offset(&mut instructions, right_instructions);
+        Ok(Operands {
+            instructions,
+            tracker,
+            left,
+            right,
+            left_kind,
+            right_kind,
+        })
+    }
 
-    let right_slot = tracker.track_if_owned(&mut instructions, context, right_result.heap_ownership, ValueKind::Any);
+    /// Runtime tags for the operands. Compiled values carry no tag, so an operand of unknown kind
+    /// is taken to have the other operand's kind.
+    fn tags(&self) -> (i64, i64) {
+        match (self.left_kind, self.right_kind) {
+            (ValueKind::Any, kind) | (kind, ValueKind::Any) => (kind.runtime_tag(), kind.runtime_tag()),
+            (left, right) => (left.runtime_tag(), right.runtime_tag()),
+        }
+    }
 
-    if left_kind == ValueKind::Any {
-        left_kind = resolve_operand_kind(&args[0], left_kind, context);
+    /// Push both tags and call a `(left, right, left_tag, right_tag)` runtime helper
+    fn call_tagged(&mut self, runtime: &str) {
+        let (left_tag, right_tag) = self.tags();
+        self.instructions
+            .extend([IRInstruction::Push(left_tag), IRInstruction::Push(right_tag), IRInstruction::RuntimeCall(runtime.to_string(), 4)]);
     }
-    if right_kind == ValueKind::Any {
-        right_kind = resolve_operand_kind(&args[1], right_kind, context);
+
+    fn finish(mut self, kind: ValueKind, context: &mut CompileContext) -> CompileResult {
+        self.left.free_retained_slots(&mut self.instructions, context);
+        self.right.free_retained_slots(&mut self.instructions, context);
+        let instructions = self.tracker.apply_liveness_and_release(self.instructions, context);
+        CompileResult::with_instructions(instructions, kind)
     }
+}
 
-    if let Some(slot) = left_slot {
-        tracker.set_slot_kind(slot, left_kind);
+/// Compile comparison operations (=, <, >, <=, >=)
+///
+/// `=` compares numbers, booleans and nil as machine words, strings, keywords and symbols by
+/// content, and anything else structurally through `_value_equals`. A comparison with a nil
+/// operand stays a word comparison, since nil is the null pointer of every heap kind.
+pub fn compile_comparison_op(args: &[Node], context: &mut CompileContext, program: &mut IRProgram, instruction: IRInstruction, op_name: &str) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError(op_name.to_string(), 2, args.len()));
     }
-    if let Some(slot) = right_slot {
-        tracker.set_slot_kind(slot, right_kind);
+
+    let mut operands = Operands::compile(args, context, program)?;
+
+    if !matches!(instruction, IRInstruction::Equal) {
+        operands.instructions.push(instruction);
+        return Ok(operands.finish(ValueKind::Boolean, context));
     }
 
-    let string_equality =
-        matches!(instruction, IRInstruction::Equal) && ((left_kind == ValueKind::String && right_kind == ValueKind::String) || (left_kind == ValueKind::Keyword && right_kind == ValueKind::Keyword));
+    let (left_kind, right_kind) = match (operands.left_kind, operands.right_kind) {
+        (ValueKind::Any, kind) | (kind, ValueKind::Any) => (kind, kind),
+        kinds => kinds,
+    };
+    let string_like = |kind: ValueKind| matches!(kind, ValueKind::String | ValueKind::Keyword | ValueKind::Symbol);
+    let word_like = |kind: ValueKind| matches!(kind, ValueKind::Number | ValueKind::Boolean | ValueKind::Any);
 
-    if string_equality {
-        instructions.push(IRInstruction::RuntimeCall("_string_equals".to_string(), 2));
+    if left_kind == ValueKind::Nil || right_kind == ValueKind::Nil || (word_like(left_kind) && word_like(right_kind)) {
+        operands.instructions.push(instruction);
+    } else if left_kind == right_kind && string_like(left_kind) {
+        operands.instructions.push(IRInstruction::RuntimeCall("_string_equals".to_string(), 2));
     } else {
-        instructions.push(instruction);
+        operands.call_tagged("_value_equals");
     }
 
-    left_result.free_retained_slots(&mut instructions, context);
-    right_result.free_retained_slots(&mut instructions, context);
+    Ok(operands.finish(ValueKind::Boolean, context))
+}
 
-    instructions = tracker.apply_liveness_and_release(instructions, context);
+/// Compile compare (-1, 0 or 1 as the first value orders before, with or after the second)
+pub fn compile_compare(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("compare".to_string(), 2, args.len()));
+    }
 
-    Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean))
+    let mut operands = Operands::compile(args, context, program)?;
+    operands.call_tagged("_value_compare");
+    Ok(operands.finish(ValueKind::Number, context))
+}
+
+/// Compile hash (a structural hash that agrees with `=`)
+pub fn compile_hash(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("hash".to_string(), 1, args.len()));
+    }
+
+    let mut tracker = SlotTracker::new();
+    let mut result = crate::compiler::compile_node(&args[0], context, program)?;
+    let mut instructions = std::mem::take(&mut result.instructions);
+    let kind = resolve_operand_kind(&args[0], result.kind, context);
+    if let Some(slot) = tracker.track_if_owned(&mut instructions, context, result.heap_ownership, kind) {
+        tracker.set_slot_kind(slot, kind);
+    }
+    instructions.push(IRInstruction::Push(kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_value_hash".to_string(), 2));
+    result.free_retained_slots(&mut instructions, context);
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Number))
 }
 
 fn resolve_operand_kind(node: &Node, fallback: ValueKind, context: &CompileContext) -> ValueKind {
@@ -108,11 +188,24 @@ fn resolve_operand_kind(node: &Node, fallback: ValueKind, context: &CompileConte
 
 /// Compile if expression
 pub fn compile_if(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
-    if args.len() != 3 {
+    if args.len() != 2 && args.len() != 3 {
         return Err(CompileError::ArityError("if".to_string(), 3, args.len()));
     }
-
-    let mut instructions = crate::compiler::compile_node(&args[0], context, program)?.instructions;
+    // A missing else branch evaluates to nil
+    let nil = Node::Symbol { value: "nil".to_string() };
+    let else_node = args.get(2).unwrap_or(&nil);
+
+    // Only the truthiness of the test is needed, so a heap value it produced is released before
+    // branching
+    let mut test_result = crate::compiler::compile_node(&args[0], context, program)?;
+    let mut instructions = std::mem::take(&mut test_result.instructions);
+    if test_result.heap_ownership == HeapOwnership::Owned {
+        let slot = context.allocate_temp_slot();
+        instructions.extend([IRInstruction::StoreLocal(slot), IRInstruction::LoadLocal(slot)]);
+        emit_free_for_slot(&mut instructions, slot, test_result.kind);
+        context.release_temp_slot(slot);
+    }
+    test_result.free_retained_slots(&mut instructions, context);
 
     let else_jump_pos = instructions.len();
     instructions.push(IRInstruction::JumpIfZero(0));
@@ -129,15 +222,36 @@ pub fn compile_if(args: &[Node], context: &mut CompileContext, program: &mut IRP
     let else_start = instructions.len();
     instructions[else_jump_pos] = IRInstruction::JumpIfZero(else_start);
 
-    let mut else_result = crate::compiler::compile_node(&args[2], context, program)?;
+    let mut else_result = crate::compiler::compile_node(else_node, context, program)?;
     let mut else_retained_slots = else_result.take_retained_slots();
     let else_instructions = std::mem::take(&mut else_result.instructions);
     extend_with_offset(&mut instructions, else_instructions);
-    ensure_branch_result_owned(&args[2], &mut else_result, &mut instructions, context);
+    ensure_branch_result_owned(else_node, &mut else_result, &mut instructions, context);
 
     let end_pos = instructions.len();
     instructions[end_jump_pos] = IRInstruction::Jump(end_pos);
 
+    let (resulting_kind, ownership) = merge_branch_results(&then_result, &else_result);
+
+    then_retained_slots.extend(else_retained_slots.drain(..));
+    dedup_retained_slots(&mut then_retained_slots);
+
+    Ok(CompileResult::with_instructions(instructions, resulting_kind)
+        .with_heap_ownership(ownership)
+        .with_retained_slots(then_retained_slots)
+        .with_diverges(then_result.diverges && else_result.diverges))
+}
+
+/// Combine the kind and ownership of two alternative results. A branch that diverges never
+/// produces a value, so the other branch decides on its own.
+pub(super) fn merge_branch_results(then_result: &CompileResult, else_result: &CompileResult) -> (ValueKind, HeapOwnership) {
+    if then_result.diverges && !else_result.diverges {
+        return (else_result.kind, else_result.heap_ownership);
+    }
+    if else_result.diverges && !then_result.diverges {
+        return (then_result.kind, then_result.heap_ownership);
+    }
+
     let resulting_kind = if then_result.kind == else_result.kind {
         then_result.kind
     } else if (then_result.kind == ValueKind::String && else_result.kind == ValueKind::Nil) || (then_result.kind == ValueKind::Nil && else_result.kind == ValueKind::String) {
@@ -146,6 +260,8 @@ pub fn compile_if(args: &[Node], context: &mut CompileContext, program: &mut IRP
         ValueKind::Keyword
     } else if (then_result.kind == ValueKind::Vector && else_result.kind == ValueKind::Nil) || (then_result.kind == ValueKind::Nil && else_result.kind == ValueKind::Vector) {
         ValueKind::Vector
+    } else if (then_result.kind == ValueKind::List && else_result.kind == ValueKind::Nil) || (then_result.kind == ValueKind::Nil && else_result.kind == ValueKind::List) {
+        ValueKind::List
     } else if (then_result.kind == ValueKind::Map && else_result.kind == ValueKind::Nil) || (then_result.kind == ValueKind::Nil && else_result.kind == ValueKind::Map) {
         ValueKind::Map
     } else if (then_result.kind == ValueKind::Boolean && else_result.kind == ValueKind::Nil) || (then_result.kind == ValueKind::Nil && else_result.kind == ValueKind::Boolean) {
@@ -154,17 +270,10 @@ pub fn compile_if(args: &[Node], context: &mut CompileContext, program: &mut IRP
         ValueKind::Any
     };
 
-    let ownership = then_result.heap_ownership.combine(else_result.heap_ownership);
-
-    then_retained_slots.extend(else_retained_slots.drain(..));
-    dedup_retained_slots(&mut then_retained_slots);
-
-    Ok(CompileResult::with_instructions(instructions, resulting_kind)
-        .with_heap_ownership(ownership)
-        .with_retained_slots(then_retained_slots))
+    (resulting_kind, then_result.heap_ownership.combine(else_result.heap_ownership))
 }
 
-fn ensure_branch_result_owned(branch_node: &Node, branch_result: &mut CompileResult, instructions: &mut Vec<IRInstruction>, context: &CompileContext) {
+pub(super) fn ensure_branch_result_owned(branch_node: &Node, branch_result: &mut CompileResult, instructions: &mut Vec<IRInstruction>, context: &CompileContext) {
     if branch_result.heap_ownership == HeapOwnership::Owned {
         return;
     }
@@ -187,10 +296,12 @@ fn ensure_branch_result_owned(branch_node: &Node, branch_result: &mut CompileRes
 
 fn clone_runtime_for_kind(kind: ValueKind) -> Option<&'static str> {
     match kind {
-        ValueKind::String => Some("_string_clone"),
-        ValueKind::Vector => Some("_vector_clone"),
+        ValueKind::String | ValueKind::Symbol => Some("_string_clone"),
+        ValueKind::Vector | ValueKind::List => Some("_vector_clone"),
         ValueKind::Map => Some("_map_clone"),
         ValueKind::Set => Some("_set_clone"),
+        ValueKind::LazySeq => Some("_lazy_retain"),
+        ValueKind::Atom => Some("_atom_retain"),
         _ => None,
     }
 }
@@ -255,7 +366,7 @@ fn compile_variadic_logical(
     Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean))
 }
 
-fn dedup_retained_slots(slots: &mut Vec<RetainedSlot>) {
+pub(super) fn dedup_retained_slots(slots: &mut Vec<RetainedSlot>) {
     if slots.is_empty() {
         return;
     }
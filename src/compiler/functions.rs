@@ -1,11 +1,79 @@
-use super::{builtins::free_retained_dependents, extend_with_offset, slots::SlotTracker, CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind};
+use super::{
+    builtins::{compile_vector_literal, emit_free_for_slot, free_retained_slot},
+    extend_with_offset,
+    inference::FunctionKey,
+    liveness::{apply_liveness_plan, compute_liveness_plan},
+    slots::SlotTracker,
+    CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind,
+};
 /// Function definition and call compilation
 use crate::ast::Node;
 use crate::ir::{FunctionInfo, IRInstruction, IRProgram};
+use std::collections::HashSet;
 
-/// Compile a function definition (defn)
-pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<(Vec<IRInstruction>, FunctionInfo), CompileError> {
-    if args.len() != 3 {
+/// Builtins that update the vector given as their first argument, in place when it is dead after them
+const UPDATING_BUILTINS: &[&str] = &["conj", "assoc", "pop", "update"];
+
+/// Parameter names of a `defn`, with the optional `& rest` binding split out
+pub(super) struct ParameterList {
+    pub names: Vec<String>,
+    pub rest: Option<String>,
+}
+
+impl ParameterList {
+    /// Number of argument slots the compiled function receives; the rest vector occupies the last one
+    pub fn arity(&self) -> usize {
+        self.names.len() + usize::from(self.rest.is_some())
+    }
+}
+
+/// Parse a parameter vector such as `[level & parts]`
+pub(super) fn parse_parameters(params: &Node) -> Result<ParameterList, CompileError> {
+    let params = match params {
+        Node::Vector { root } => root,
+        _ => return Err(CompileError::InvalidExpression("Function parameters must be a vector".to_string())),
+    };
+
+    let mut names = Vec::new();
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match param {
+            Node::Symbol { value } if value == "&" => {
+                let rest = match (iter.next(), iter.next()) {
+                    (Some(Node::Symbol { value }), None) if value != "&" => value.clone(),
+                    _ => return Err(CompileError::InvalidExpression("& must be followed by exactly one parameter symbol".to_string())),
+                };
+                return Ok(ParameterList { names, rest: Some(rest) });
+            }
+            Node::Symbol { value } => names.push(value.clone()),
+            _ => return Err(CompileError::InvalidExpression("Function parameters must be symbols".to_string())),
+        }
+    }
+
+    Ok(ParameterList { names, rest: None })
+}
+
+/// One parameter vector and body of a `defn`
+pub(super) struct FunctionClause<'a> {
+    pub symbol: String, // name the clause is compiled and called under
+    pub parameters: ParameterList,
+    pub body: &'a Node,
+}
+
+/// Symbol for one arity of a multi-arity function, e.g. `f/2`, or `f/1+` for the variadic arity
+pub(super) fn arity_symbol(name: &str, fixed_count: usize, variadic: bool) -> String {
+    if variadic {
+        format!("{}/{}+", name, fixed_count)
+    } else {
+        format!("{}/{}", name, fixed_count)
+    }
+}
+
+/// Split a `defn` into its clauses; `(defn f [x] ...)` has one clause named `f`,
+/// `(defn f ([x] ...) ([x y] ...))` has one clause per arity named by `arity_symbol`
+pub(super) fn defn_clauses(args: &[Node]) -> Result<(String, Vec<FunctionClause<'_>>), CompileError> {
+    let multi_arity = matches!(args.get(1), Some(Node::List { .. }));
+    if args.len() != 3 && !multi_arity {
         return Err(CompileError::ArityError("defn".to_string(), 3, args.len()));
     }
 
@@ -14,34 +82,236 @@ pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut I
         _ => return Err(CompileError::InvalidExpression("Function name must be a symbol".to_string())),
     };
 
-    let params = match &args[1] {
-        Node::Vector { root } => root,
-        _ => return Err(CompileError::InvalidExpression("Function parameters must be a vector".to_string())),
-    };
+    if !multi_arity {
+        let clause = FunctionClause {
+            symbol: func_name.clone(),
+            parameters: parse_parameters(&args[1])?,
+            body: &args[2],
+        };
+        return Ok((func_name, vec![clause]));
+    }
 
-    let mut param_names = Vec::new();
-    for param in params {
-        match param {
-            Node::Symbol { value } => param_names.push(value.clone()),
-            _ => return Err(CompileError::InvalidExpression("Function parameters must be symbols".to_string())),
+    let mut clauses: Vec<FunctionClause> = Vec::new();
+    for clause in &args[1..] {
+        let (parameters, body) = match clause {
+            Node::List { root } if root.len() == 2 => (parse_parameters(&root[0])?, &root[1]),
+            _ => return Err(CompileError::InvalidExpression("defn arity must be a list of a parameter vector and a body".to_string())),
+        };
+        let symbol = arity_symbol(&func_name, parameters.names.len(), parameters.rest.is_some());
+        if clauses.iter().any(|existing| existing.symbol == symbol) {
+            return Err(CompileError::InvalidExpression(format!("{} defines arity {} more than once", func_name, symbol)));
+        }
+        if parameters.rest.is_some() && clauses.iter().any(|existing| existing.parameters.rest.is_some()) {
+            return Err(CompileError::InvalidExpression(format!("{} can have at most one variadic arity", func_name)));
+        }
+        clauses.push(FunctionClause { symbol, parameters, body });
+    }
+
+    if let Some(variadic) = clauses.iter().find(|clause| clause.parameters.rest.is_some()) {
+        let variadic_fixed = variadic.parameters.names.len();
+        if clauses.iter().any(|clause| clause.parameters.rest.is_none() && clause.parameters.names.len() > variadic_fixed) {
+            return Err(CompileError::InvalidExpression(format!("{} has a fixed arity with more parameters than its variadic arity", func_name)));
         }
     }
 
-    let param_count = param_names.len();
+    Ok((func_name, clauses))
+}
+
+/// Register every clause of a `defn` as a function so call sites can be resolved before bodies are compiled
+pub(super) fn register_defn(func_name: &str, clauses: &[FunctionClause], context: &mut CompileContext) -> Result<(), CompileError> {
+    if context.get_function_arities(func_name).is_some() {
+        return Err(CompileError::DuplicateFunction(func_name.to_string()));
+    }
 
-    if context.get_function(&func_name).is_none() {
+    for clause in clauses {
         let func_info = FunctionInfo {
-            name: func_name.clone(),
-            param_count,
-            start_address: 0,
+            name: clause.symbol.clone(),
+            param_count: clause.parameters.arity(),
+            start_address: 0, // Will be set during compilation
             local_count: 0,
         };
-        context.add_function(func_name.clone(), func_info)?;
+        context.add_function(clause.symbol.clone(), func_info)?;
+        if clause.parameters.rest.is_some() {
+            context.mark_function_variadic(&clause.symbol);
+        }
     }
 
+    if clauses.iter().any(|clause| clause.symbol != func_name) {
+        context.set_function_arities(func_name, clauses.iter().map(|clause| clause.symbol.clone()).collect());
+    }
+
+    Ok(())
+}
+
+/// Resolve the function symbol and parameter count a call to `name` with `arg_count` arguments targets
+pub(super) fn resolve_call_target(name: &str, arg_count: usize, context: &CompileContext) -> Result<Option<(String, usize)>, CompileError> {
+    let Some(symbols) = context.get_function_arities(name) else {
+        return Ok(context.get_function(name).map(|info| (name.to_string(), info.param_count)));
+    };
+
+    let arities: Vec<(&String, usize, bool)> = symbols
+        .iter()
+        .filter_map(|symbol| {
+            let info = context.get_function(symbol)?;
+            let variadic = context.is_function_variadic(symbol);
+            let fixed_count = if variadic { info.param_count - 1 } else { info.param_count };
+            Some((symbol, fixed_count, variadic))
+        })
+        .collect();
+
+    // An exact fixed arity wins over the variadic one
+    let target = arities
+        .iter()
+        .find(|(_, fixed_count, variadic)| !variadic && *fixed_count == arg_count)
+        .or_else(|| arities.iter().find(|(_, fixed_count, variadic)| *variadic && *fixed_count <= arg_count));
+
+    match target {
+        Some((symbol, _, _)) => Ok(context.get_function(symbol).map(|info| (symbol.to_string(), info.param_count))),
+        None => {
+            let available: Vec<String> = arities
+                .iter()
+                .map(|(_, fixed_count, variadic)| if *variadic { format!("{}+", fixed_count) } else { fixed_count.to_string() })
+                .collect();
+            let closest = arities.iter().map(|(_, fixed_count, _)| *fixed_count).min_by_key(|count| count.abs_diff(arg_count)).unwrap_or(0);
+            Err(CompileError::ArityError(format!("{} (available arities: {})", name, available.join(", ")), closest, arg_count))
+        }
+    }
+}
+
+/// Decide which vector parameters each function takes over: those it updates with `conj`,
+/// `assoc`, `pop` or `update`, returns, or passes on to a parameter taken over in turn. A caller
+/// hands such a parameter a vector of its own (a fresh one, a dead local or else a copy), so a
+/// function growing a vector through its recursive calls keeps updating one vector in place.
+/// Functions also passed as callbacks are left alone, since the runtime calls them with borrowed
+/// values.
+pub(super) fn mark_consumed_parameters(expressions: &[Node], context: &mut CompileContext) -> Result<(), CompileError> {
+    let mut callbacks = HashSet::new();
+    expressions.iter().for_each(|expr| collect_value_symbols(expr, &mut callbacks));
+
+    let mut clauses = Vec::new();
+    for expr in expressions {
+        if let Node::List { root } = expr {
+            if matches!(root.first(), Some(Node::Symbol { value }) if value == "defn") {
+                let (func_name, defn) = defn_clauses(&root[1..])?;
+                if !callbacks.contains(&func_name) {
+                    clauses.extend(defn);
+                }
+            }
+        }
+    }
+
+    loop {
+        let mut changed = false;
+        for clause in &clauses {
+            for (index, name) in clause.parameters.names.iter().enumerate() {
+                if context.is_parameter_consumed(&clause.symbol, index) || context.get_function_parameter_type(&clause.symbol, index) != Some(ValueKind::Vector) {
+                    continue;
+                }
+                if returns_symbol(clause.body, name) || hands_over(clause.body, name, context) {
+                    context.mark_parameter_consumed(&clause.symbol, index);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+    }
+}
+
+/// Symbols used other than as the head of a call or the name of a `defn`
+fn collect_value_symbols(node: &Node, symbols: &mut HashSet<String>) {
+    match node {
+        Node::Symbol { value } => {
+            symbols.insert(value.clone());
+        }
+        Node::List { root } => {
+            let skipped = match root.first() {
+                Some(Node::Symbol { value }) if value == "defn" => 2,
+                Some(Node::Symbol { .. }) => 1,
+                _ => 0,
+            };
+            root.iter().skip(skipped).for_each(|child| collect_value_symbols(child, symbols));
+        }
+        Node::Vector { root } | Node::Set { root } => root.iter().for_each(|child| collect_value_symbols(child, symbols)),
+        Node::Map { entries } => entries.iter().for_each(|(key, value)| {
+            collect_value_symbols(key, symbols);
+            collect_value_symbols(value, symbols);
+        }),
+        Node::Primitive { .. } => {}
+    }
+}
+
+/// Whether `node` can evaluate to the value of `name` itself, through `if`, `let` and `cond`
+fn returns_symbol(node: &Node, name: &str) -> bool {
+    match node {
+        Node::Symbol { value } => value == name,
+        Node::List { root } => match root.first() {
+            Some(Node::Symbol { value }) if value == "if" => root.iter().skip(2).any(|branch| returns_symbol(branch, name)),
+            Some(Node::Symbol { value }) if value == "let" => root.len() == 3 && returns_symbol(&root[2], name),
+            Some(Node::Symbol { value }) if value == "cond" => root[1..].chunks(2).any(|clause| clause.get(1).is_some_and(|result| returns_symbol(result, name))),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether `node` passes `name` to an updating builtin or to a parameter taken over by its function
+fn hands_over(node: &Node, name: &str, context: &CompileContext) -> bool {
+    let is_name = |arg: &Node| matches!(arg, Node::Symbol { value } if value == name);
+    match node {
+        Node::List { root } => {
+            if let Some(Node::Symbol { value: head }) = root.first() {
+                if UPDATING_BUILTINS.contains(&head.as_str()) && root.get(1).is_some_and(is_name) {
+                    return true;
+                }
+                if let Ok(Some((symbol, _))) = resolve_call_target(head, root.len() - 1, context) {
+                    if root[1..].iter().enumerate().any(|(index, arg)| is_name(arg) && context.is_parameter_consumed(&symbol, index)) {
+                        return true;
+                    }
+                }
+            }
+            root.iter().any(|child| hands_over(child, name, context))
+        }
+        Node::Vector { root } | Node::Set { root } => root.iter().any(|child| hands_over(child, name, context)),
+        Node::Map { entries } => entries.iter().any(|(key, value)| hands_over(key, name, context) || hands_over(value, name, context)),
+        Node::Symbol { .. } | Node::Primitive { .. } => false,
+    }
+}
+
+/// Compile a function definition (defn), returning one compiled function per arity
+pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<Vec<(Vec<IRInstruction>, FunctionInfo)>, CompileError> {
+    let (func_name, clauses) = defn_clauses(args)?;
+    if clauses.iter().all(|clause| context.get_function(&clause.symbol).is_none()) {
+        register_defn(&func_name, &clauses, context)?;
+    }
+
+    clauses.iter().map(|clause| compile_clause(clause, context, program)).collect()
+}
+
+/// Compile a single arity of a function definition
+fn compile_clause(clause: &FunctionClause, context: &mut CompileContext, program: &mut IRProgram) -> Result<(Vec<IRInstruction>, FunctionInfo), CompileError> {
+    let func_name = clause.symbol.clone();
+    let parameters = &clause.parameters;
+    let param_count = parameters.arity();
+
     let mut func_context = context.new_function_scope(&func_name);
+    let mut prologue = Vec::new();
+    let mut consumed_slots = HashSet::new();
 
-    for (i, param_name) in param_names.iter().enumerate() {
+    for (i, param_name) in parameters.names.iter().enumerate() {
+        if context.is_parameter_consumed(&func_name, i) {
+            // A parameter the function takes over lives in a local slot, released or handed on
+            // after its last use like a let binding
+            let slot = func_context.add_variable(param_name.clone());
+            prologue.push(IRInstruction::LoadParam(i));
+            prologue.push(IRInstruction::StoreLocal(slot));
+            func_context.set_variable_type(param_name, ValueKind::Vector);
+            func_context.mark_heap_allocated(param_name, ValueKind::Vector);
+            func_context.set_variable_vector_element_kind(param_name, context.get_function_parameter_vector_element_kind(&func_name, i));
+            consumed_slots.insert(slot);
+            continue;
+        }
         func_context.add_parameter(param_name.clone(), i);
         if let Some(kind) = context.get_function_parameter_type(&func_name, i) {
             func_context.set_parameter_type(param_name, kind);
@@ -58,6 +328,19 @@ pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut I
         if let Some(vec_kind) = context.get_function_parameter_vector_element_kind(&func_name, i) {
             func_context.set_parameter_vector_element_kind(param_name, Some(vec_kind));
         }
+        if let Some(record) = context.get_function_parameter_record_type(&func_name, i) {
+            func_context.set_parameter_record_type(param_name, Some(record.to_string()));
+        }
+    }
+
+    if let Some(rest_name) = &parameters.rest {
+        let index = parameters.names.len();
+        func_context.add_parameter(rest_name.clone(), index);
+        func_context.set_parameter_type(rest_name, ValueKind::Vector);
+        func_context.mark_heap_allocated(rest_name, ValueKind::Vector);
+        if let Some(vec_kind) = context.get_function_parameter_vector_element_kind(&func_name, index) {
+            func_context.set_parameter_vector_element_kind(rest_name, Some(vec_kind));
+        }
     }
 
     let mut instructions = vec![IRInstruction::DefineFunction(
@@ -65,8 +348,9 @@ pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut I
         param_count,
         0, // Will be set by caller
     )];
+    instructions.extend(prologue);
 
-    let mut body_result = crate::compiler::compile_node(&args[2], &mut func_context, program)?;
+    let mut body_result = crate::compiler::compile_node(clause.body, &mut func_context, program)?;
     let mut body_kind = body_result.kind;
     let body_map_value_types = body_result.map_value_types.clone();
     let body_set_element_kind = body_result.set_element_kind;
@@ -74,10 +358,12 @@ pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut I
 
     if body_result.heap_ownership == HeapOwnership::Borrowed {
         let clone_runtime = match body_kind {
-            ValueKind::String => Some("_string_clone"),
-            ValueKind::Vector => Some("_vector_clone"),
+            ValueKind::String | ValueKind::Symbol => Some("_string_clone"),
+            ValueKind::Vector | ValueKind::List => Some("_vector_clone"),
             ValueKind::Map => Some("_map_clone"),
             ValueKind::Set => Some("_set_clone"),
+            ValueKind::LazySeq => Some("_lazy_retain"),
+            ValueKind::Atom => Some("_atom_retain"),
             ValueKind::Any => {
                 body_kind = ValueKind::String;
                 Some("_string_clone")
@@ -93,7 +379,14 @@ pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut I
 
     let body_ownership = body_result.heap_ownership;
 
-    let body_instructions = std::mem::take(&mut body_result.instructions);
+    let mut body_instructions = std::mem::take(&mut body_result.instructions);
+    if !consumed_slots.is_empty() {
+        let plan = compute_liveness_plan(&body_instructions, &consumed_slots);
+        body_instructions = apply_liveness_plan(body_instructions, &plan, |insts, slot| emit_free_for_slot(insts, slot, ValueKind::Vector));
+        for slot in consumed_slots.difference(&plan.freed_everywhere) {
+            emit_free_for_slot(&mut body_instructions, *slot, ValueKind::Vector);
+        }
+    }
     extend_with_offset(&mut instructions, body_instructions);
     body_result.free_retained_slots(&mut instructions, &mut func_context);
     instructions.push(IRInstruction::Return);
@@ -118,45 +411,86 @@ pub fn compile_defn(args: &[Node], context: &mut CompileContext, program: &mut I
 }
 
 /// Compile a function call
+/// For variadic functions the arguments past the fixed parameters are packed into a vector passed as the last argument.
 pub fn compile_function_call(func_name: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram, expected_param_count: usize) -> Result<CompileResult, CompileError> {
-    if args.len() != expected_param_count {
-        return Err(CompileError::ArityError(func_name.to_string(), expected_param_count, args.len()));
+    compile_call(func_name, None, args, context, program, expected_param_count)
+}
+
+/// Compile a function call whose first argument the caller already compiled, when `first` is
+/// given; `args` then holds the remaining arguments. The first argument must fill a fixed parameter.
+pub(super) fn compile_call(
+    func_name: &str,
+    mut first: Option<CompileResult>,
+    args: &[Node],
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+    expected_param_count: usize,
+) -> Result<CompileResult, CompileError> {
+    let variadic = context.is_function_variadic(func_name);
+    let fixed_count = if variadic { expected_param_count - 1 } else { expected_param_count };
+    let precompiled = usize::from(first.is_some());
+    let arg_count = args.len() + precompiled;
+    if (variadic && arg_count < fixed_count) || (!variadic && arg_count != expected_param_count) || fixed_count < precompiled {
+        return Err(CompileError::ArityError(func_name.to_string(), fixed_count, arg_count));
     }
 
     let mut instructions = Vec::new();
     let mut tracker = SlotTracker::new();
     let mut retained_argument_slots: Vec<RetainedSlot> = Vec::new();
 
-    args.iter().enumerate().try_for_each(|(index, arg)| {
-        let mut arg_result = crate::compiler::compile_node(arg, context, program)?;
+    for index in 0..fixed_count {
+        let mut arg_result = match first.take() {
+            Some(result) => result,
+            None => crate::compiler::compile_node(&args[index - precompiled], context, program)?,
+        };
         context.record_function_parameter_type(func_name, index, arg_result.kind);
         retained_argument_slots.extend(arg_result.take_retained_slots());
         let arg_instructions = std::mem::take(&mut arg_result.instructions);
         extend_with_offset(&mut instructions, arg_instructions);
+        if context.is_parameter_consumed(func_name, index) {
+            // The function takes the vector over: a temporary passes as it is, anything else as a
+            // copy, which liveness turns into a handoff when the copied local is dead after the call
+            if arg_result.heap_ownership != HeapOwnership::Owned {
+                instructions.push(IRInstruction::RuntimeCall("_vector_clone".to_string(), 1));
+            }
+            continue;
+        }
         tracker.track_if_owned(&mut instructions, context, arg_result.heap_ownership, arg_result.kind);
-        Ok::<(), CompileError>(())
-    })?;
+    }
+
+    if variadic {
+        let mut rest_result = compile_vector_literal(&args[fixed_count - precompiled..], context, program)?;
+        context.record_function_parameter_type(func_name, fixed_count, ValueKind::Vector);
+        retained_argument_slots.extend(rest_result.take_retained_slots());
+        let rest_instructions = std::mem::take(&mut rest_result.instructions);
+        extend_with_offset(&mut instructions, rest_instructions);
+        tracker.track_if_owned(&mut instructions, context, rest_result.heap_ownership, ValueKind::Vector);
+    }
 
-    instructions.push(IRInstruction::Call(func_name.to_string(), args.len()));
+    instructions.push(IRInstruction::Call(func_name.to_string(), expected_param_count));
 
-    tracker.track_retained_slots(&retained_argument_slots);
     instructions = tracker.apply_liveness_and_release(instructions, context);
 
-    retained_argument_slots.into_iter().for_each(|mut slot| {
-        free_retained_dependents(&mut slot, &mut instructions, context);
-        context.release_temp_slot(slot.slot);
-    });
+    // Items of a collection built for an argument stay alive until the function has returned
+    retained_argument_slots.into_iter().for_each(|slot| free_retained_slot(slot, &mut instructions, context));
 
-    // Without full type inference, assume any return kind for user-defined functions.
-    let return_kind = context.get_function_return_type(func_name).unwrap_or(ValueKind::Any);
+    // Without full type inference, assume any return kind for user-defined functions. A function
+    // calling itself before its return type is known gets the type its other branches give, so the
+    // recursive call counts as producing no value of its own.
+    let known_return_kind = context.get_function_return_type(func_name);
+    let unknown_recursion = known_return_kind.is_none() && matches!(&context.current_function, FunctionKey::Named(name) if name == func_name);
+    let return_kind = known_return_kind.unwrap_or(ValueKind::Any);
     let return_ownership = context.get_function_return_ownership(func_name).unwrap_or(HeapOwnership::None);
     let map_value_types = context.get_function_return_map_value_types(func_name).cloned();
     let set_element_kind = context.get_function_return_set_element_kind(func_name);
     let vector_element_kind = context.get_function_return_vector_element_kind(func_name);
+    let record_type = context.get_function_return_record_type(func_name).map(str::to_string);
 
     Ok(CompileResult::with_instructions(instructions, return_kind)
         .with_heap_ownership(return_ownership)
         .with_map_value_types(map_value_types)
         .with_set_element_kind(set_element_kind)
-        .with_vector_element_kind(vector_element_kind))
+        .with_vector_element_kind(vector_element_kind)
+        .with_record_type(record_type)
+        .with_diverges(unknown_recursion))
 }
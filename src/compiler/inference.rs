@@ -1,8 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::ast::{Node, Primitive};
 
-use super::{CompileError, HeapOwnership, MapKeyLiteral, MapValueTypes, ValueKind};
+use super::{
+    functions::defn_clauses,
+    matching,
+    polymorphism::{self, defprotocol_methods, dispatch_target},
+    records::defrecord_fields,
+    types::{map_value_type, nested_map_value_types, remove_map_value_type, set_map_value_type},
+    CompileError, HeapOwnership, MapKeyLiteral, MapValueTypes, ValueKind,
+};
 
 /// Execute the type inference scaffolding over a list of AST expressions.
 ///
@@ -100,6 +107,7 @@ pub struct BindingInfo {
     pub map_value_types: Option<MapValueTypes>,
     pub set_element_kind: Option<ValueKind>,
     pub vector_element_kind: Option<ValueKind>,
+    pub record_type: Option<String>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -107,6 +115,7 @@ pub struct FunctionAnalysis {
     pub parameter_bindings: Vec<BindingId>,
     pub local_bindings: Vec<BindingId>,
     pub return_binding: Option<BindingId>,
+    pub variadic: bool, // last parameter binding collects extra call arguments as a vector
 }
 
 #[derive(Clone, Debug)]
@@ -146,6 +155,11 @@ impl TypeInferenceSummary {
         self.binding(id).and_then(|info| info.vector_element_kind)
     }
 
+    /// The record type every value of the binding is known to have
+    pub fn binding_record_type(&self, id: BindingId) -> Option<&str> {
+        self.binding(id).and_then(|info| info.record_type.as_deref())
+    }
+
     pub fn iter_named_functions(&self) -> impl Iterator<Item = (&str, &FunctionAnalysis)> {
         self.functions.iter().filter_map(|(key, analysis)| match key {
             FunctionKey::Named(name) => Some((name.as_str(), analysis)),
@@ -159,10 +173,36 @@ struct BindingNode {
     owner: BindingOwner,
     ast_id: AstId,
     value_kind: ValueKind,
+    // Set once two assignments disagree on the kind, which then stays Any
+    mixed_kind: bool,
     heap_ownership: HeapOwnership,
     pub map_value_types: Option<MapValueTypes>,
     pub set_element_kind: Option<ValueKind>,
     pub vector_element_kind: Option<ValueKind>,
+    record_type: RecordType,
+}
+
+/// What inference knows of the records a binding holds. Unlike the other metadata, a record type
+/// must hold for every value assigned to the binding, so it only survives while each assignment
+/// agrees on it.
+#[derive(Clone, Debug, PartialEq)]
+enum RecordType {
+    Unknown,
+    Record(String),
+    Mixed,
+}
+
+/// Where an assignment's value comes from, as far as records go
+#[derive(Clone, Debug)]
+enum RecordSource {
+    /// A `->Name` constructor call
+    Constructor(String),
+    /// A local, a parameter or a function return
+    Binding(BindingId),
+    /// `assoc` of literal keywords onto `base`; `records` lists the record types declaring them all
+    Assoc { base: Box<RecordSource>, records: Vec<String> },
+    /// Anything else, which may not be a record
+    Other,
 }
 
 struct BindingGraph {
@@ -180,6 +220,9 @@ struct GraphBuilder {
     binding_map_metadata: HashMap<BindingId, MapValueTypes>,
     binding_set_metadata: HashMap<BindingId, ValueKind>,
     binding_vector_metadata: HashMap<BindingId, ValueKind>,
+    function_arities: HashMap<String, Vec<String>>, // multi-arity function name -> per-arity function symbols
+    records: HashMap<String, Vec<String>>,          // record type name -> field names
+    protocol_methods: HashSet<String>,              // methods declared by defprotocol
 }
 
 impl GraphBuilder {
@@ -195,10 +238,15 @@ impl GraphBuilder {
             binding_map_metadata: HashMap::new(),
             binding_set_metadata: HashMap::new(),
             binding_vector_metadata: HashMap::new(),
+            function_arities: HashMap::new(),
+            records: HashMap::new(),
+            protocol_methods: HashSet::new(),
         }
     }
 
     fn build(&mut self, expressions: &[Node]) {
+        self.register_records(expressions);
+        self.register_protocols(expressions);
         self.register_function_signatures(expressions);
         let mut path = AstId::root();
         for (idx, expr) in expressions.iter().enumerate() {
@@ -238,6 +286,40 @@ impl GraphBuilder {
         self.env.iter().rev().find_map(|frame| frame.get(name).copied())
     }
 
+    fn register_records(&mut self, expressions: &[Node]) {
+        for expr in expressions {
+            if let Node::List { root } = expr {
+                if matches!(root.first(), Some(Node::Symbol { value }) if value == "defrecord") {
+                    if let Ok((name, fields)) = defrecord_fields(&root[1..]) {
+                        self.records.insert(name, fields);
+                    }
+                }
+            }
+        }
+    }
+
+    fn register_protocols(&mut self, expressions: &[Node]) {
+        for expr in expressions {
+            if let Node::List { root } = expr {
+                if matches!(root.first(), Some(Node::Symbol { value }) if value == "defprotocol") {
+                    if let Ok(protocol) = defprotocol_methods(&root[1..]) {
+                        self.protocol_methods.extend(protocol.methods.into_iter().map(|(method, _)| method));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a call to `op` is a protocol method call
+    fn is_protocol_call(&self, op: &str) -> bool {
+        self.lookup_symbol(op).is_none() && self.protocol_methods.contains(op)
+    }
+
+    /// The record type a `->Name` constructor builds, if `op` is one
+    fn constructor_record<'a>(&self, op: &'a str) -> Option<&'a str> {
+        op.strip_prefix("->").filter(|name| self.records.contains_key(*name))
+    }
+
     fn register_function_signatures(&mut self, expressions: &[Node]) {
         let mut path = AstId::root();
         for (idx, expr) in expressions.iter().enumerate() {
@@ -247,6 +329,24 @@ impl GraphBuilder {
         }
     }
 
+    /// What `assoc` updates. A numeric key only marks a parameter as a vector: a local already gets
+    /// its kind from its value, and forcing it to a vector would fight a map local's own kind
+    fn assoc_target(&self, nodes: &[Node]) -> ValueKind {
+        let kind = assoc_target_kind(nodes);
+        let Some(Node::Symbol { value }) = nodes.get(1) else {
+            return kind;
+        };
+        let local = self
+            .lookup_symbol(value)
+            .and_then(|binding| self.nodes.get(binding.to_index()))
+            .is_some_and(|node| matches!(node.owner, BindingOwner::Local { .. }));
+        if kind == ValueKind::Vector && local {
+            ValueKind::Any
+        } else {
+            kind
+        }
+    }
+
     fn binding_map_value_types_clone(&self, binding: BindingId) -> Option<MapValueTypes> {
         if let Some(metadata) = self.binding_map_metadata.get(&binding) {
             return Some(metadata.clone());
@@ -272,10 +372,50 @@ impl GraphBuilder {
         match node {
             Node::Symbol { value } => self.lookup_symbol(value).and_then(|binding| self.binding_map_value_types_clone(binding)),
             Node::Map { entries } => infer_map_literal_metadata(entries),
+            Node::List { root } => match root.first() {
+                Some(Node::Symbol { value }) if value == "get" && root.len() >= 3 => {
+                    let key_literal = map_key_literal_from_node(&root[2])?;
+                    nested_map_value_types(&self.extract_map_metadata(&root[1])?, &key_literal)
+                }
+                Some(Node::Symbol { value }) if value == "assoc" && self.assoc_target(root) == ValueKind::Map => self.assoc_map_metadata(root),
+                Some(Node::Symbol { value }) if self.lookup_symbol(value).is_none() => self.constructor_record(value).and_then(|name| self.record_map_metadata(name, &root[1..])),
+                _ => None,
+            },
             _ => None,
         }
     }
 
+    /// Value kinds of the fields a record constructor sets, like a map literal of the same entries
+    fn record_map_metadata(&self, name: &str, args: &[Node]) -> Option<MapValueTypes> {
+        let entries: Vec<(Node, Node)> = self
+            .records
+            .get(name)?
+            .iter()
+            .zip(args)
+            .map(|(field, value)| {
+                (
+                    Node::Primitive {
+                        value: Primitive::Keyword(field.clone()),
+                    },
+                    value.clone(),
+                )
+            })
+            .collect();
+        infer_map_literal_metadata(&entries)
+    }
+
+    /// Value kinds of a map assoc result: the base map's, updated for every literal key
+    fn assoc_map_metadata(&self, nodes: &[Node]) -> Option<MapValueTypes> {
+        let mut metadata = nodes.get(1).and_then(|expr| self.extract_map_metadata(expr)).unwrap_or_default();
+        for chunk in nodes[2..].chunks_exact(2) {
+            let key_literal = map_key_literal_from_node(&chunk[0])?;
+            let nested = self.extract_map_metadata(&chunk[1]);
+            let kind = collection_literal_kind(&chunk[1]).or(nested.as_ref().map(|_| ValueKind::Map)).unwrap_or(ValueKind::Any);
+            set_map_value_type(&mut metadata, key_literal, kind, nested.as_ref());
+        }
+        (!metadata.is_empty()).then_some(metadata)
+    }
+
     fn extract_set_element_kind(&self, node: &Node) -> Option<ValueKind> {
         extract_set_element_kind(self, node)
     }
@@ -306,41 +446,50 @@ impl GraphBuilder {
         let Node::List { root } = node else {
             return;
         };
-        if root.len() != 4 {
-            return;
-        }
-        let Node::Symbol { value } = &root[0] else {
+        let Some(arities) = defn_arities(root) else {
             return;
         };
-        if value != "defn" {
-            return;
+        if let Node::Symbol { value } = &root[1] {
+            if arities.iter().any(|arity| &arity.symbol != value) {
+                self.function_arities.insert(value.clone(), arities.iter().map(|arity| arity.symbol.clone()).collect());
+            }
         }
-        let func_name = match &root[1] {
-            Node::Symbol { value } => value.clone(),
-            _ => return,
-        };
-        let func_key = FunctionKey::Named(func_name.clone());
-        if let Node::Vector { root: params } = &root[2] {
-            path.push(2);
-            for (idx, param) in params.iter().enumerate() {
+
+        for arity in arities {
+            let func_key = FunctionKey::Named(arity.symbol);
+            let mut params_path = path.clone();
+            arity.params_path.iter().for_each(|idx| params_path.push(*idx));
+            let mut position = 0;
+            let mut rest = false;
+            for (idx, param) in arity.params.iter().enumerate() {
                 if let Node::Symbol { value } = param {
-                    path.push(idx);
-                    self.add_binding(
+                    if value == "&" {
+                        rest = true;
+                        continue;
+                    }
+                    params_path.push(idx);
+                    let binding = self.add_binding(
                         BindingOwner::Parameter {
                             function: func_key.clone(),
                             name: value.clone(),
-                            position: idx,
+                            position,
                         },
-                        path.clone(),
+                        params_path.clone(),
                     );
-                    path.pop();
+                    if rest {
+                        // Callers pack the trailing arguments into a fresh vector for this binding.
+                        self.add_literal_constraint(binding, ValueKind::Vector, HeapOwnership::Borrowed, None);
+                        self.functions.entry(func_key.clone()).or_default().variadic = true;
+                    }
+                    position += 1;
+                    params_path.pop();
                 }
             }
-            path.pop();
+
+            let mut body_path = path.clone();
+            arity.body_path.iter().for_each(|idx| body_path.push(*idx));
+            self.add_binding(BindingOwner::Return { function: func_key }, body_path);
         }
-        path.push(3);
-        self.add_binding(BindingOwner::Return { function: func_key }, path.clone());
-        path.pop();
     }
 
     fn visit_node(&mut self, node: &Node, path: &mut AstId) {
@@ -363,7 +512,8 @@ impl GraphBuilder {
                     path.pop();
                 }
             }
-            Node::Primitive { .. } | Node::Symbol { .. } => {}
+            Node::Symbol { value } => self.plan_escaped_function(value),
+            Node::Primitive { .. } => {}
         }
     }
 
@@ -382,57 +532,71 @@ impl GraphBuilder {
                     self.visit_let(nodes, path);
                     return;
                 }
+                // Quoted forms are data, not bindings or calls
+                "quote" | "defrecord" | "defprotocol" => return,
+                // Callbacks are planned here, where the collection argument's bindings are in scope
+                "map" | "filter" | "remove" | "reduce" | "every?" | "some" | "group-by" | "iterate" | "sort" | "sort-by" | "sorted-map-by" | "sorted-set-by" => self.plan_sequence_callbacks(nodes),
+                "add-watch" => self.plan_watch_callback(nodes),
                 _ => {}
             }
         }
 
-        for (idx, child) in nodes.iter().enumerate() {
+        // The operator is called, not passed along, so only the arguments can escape
+        for (idx, child) in nodes.iter().enumerate().skip(usize::from(matches!(nodes[0], Node::Symbol { .. }))) {
             path.push(idx);
             self.visit_node(child, path);
             path.pop();
         }
     }
 
-    fn visit_defn(&mut self, nodes: &[Node], path: &mut AstId) {
-        if nodes.len() != 4 {
-            self.visit_children(nodes, path);
+    /// A function named outside operator position is passed along as a value, so its parameters can
+    /// receive arguments inference never sees
+    fn plan_escaped_function(&mut self, name: &str) {
+        if self.lookup_symbol(name).is_some() {
             return;
         }
-
-        let func_name = match &nodes[1] {
-            Node::Symbol { value } => value.clone(),
-            _ => {
-                self.visit_children(nodes, path);
-                return;
+        let symbols = self.function_arities.get(name).cloned().unwrap_or_else(|| vec![name.to_string()]);
+        for symbol in symbols {
+            let params = self.functions.get(&FunctionKey::Named(symbol)).map(|analysis| analysis.parameter_bindings.clone()).unwrap_or_default();
+            for param in params {
+                self.constraints.push(Box::new(RecordConstraint::new(param, RecordSource::Other)));
             }
+        }
+    }
+
+    fn visit_defn(&mut self, nodes: &[Node], path: &mut AstId) {
+        let Some(arities) = defn_arities(nodes) else {
+            self.visit_children(nodes, path);
+            return;
         };
 
-        let func_key = FunctionKey::Named(func_name.clone());
-        self.function_stack.push(func_key.clone());
-        self.push_env();
+        for arity in arities {
+            let func_key = FunctionKey::Named(arity.symbol);
+            self.function_stack.push(func_key.clone());
+            self.push_env();
 
-        path.push(2);
-        if let Node::Vector { root } = &nodes[2] {
-            for (idx, param) in root.iter().enumerate() {
+            let mut position = 0;
+            for param in arity.params {
                 if let Node::Symbol { value } = param {
-                    path.push(idx);
-                    if let Some(binding_id) = self.get_parameter_binding(&func_key, idx) {
+                    if value == "&" {
+                        continue;
+                    }
+                    if let Some(binding_id) = self.get_parameter_binding(&func_key, position) {
                         self.register_binding_name(value, binding_id);
                     }
-                    path.pop();
+                    position += 1;
                 }
             }
-        }
-        path.pop();
 
-        let return_binding = self.get_return_binding(&func_key).expect("return binding missing");
-        path.push(3);
-        self.visit_node(&nodes[3], path);
-        self.plan_assignment(return_binding, &nodes[3]);
-        path.pop();
+            let return_binding = self.get_return_binding(&func_key).expect("return binding missing");
+            let mut body_path = path.clone();
+            arity.body_path.iter().for_each(|idx| body_path.push(*idx));
+            self.visit_node(arity.body, &mut body_path);
+            self.plan_assignment(return_binding, arity.body);
 
-        self.pop_env();
-        self.function_stack.pop();
+            self.pop_env();
+            self.function_stack.pop();
+        }
     }
 
     fn visit_let(&mut self, nodes: &[Node], path: &mut AstId) {
@@ -486,6 +650,8 @@ impl GraphBuilder {
         path.push(2);
         self.visit_node(body_node, path);
         path.pop();
+        // Calls in the body pass arguments that may name the let's bindings
+        self.plan_assignment_for_node(body_node);
         self.pop_env();
     }
 
@@ -504,10 +670,12 @@ impl GraphBuilder {
             owner: owner.clone(),
             ast_id,
             value_kind: ValueKind::Any,
+            mixed_kind: false,
             heap_ownership: HeapOwnership::None,
             map_value_types: None,
             set_element_kind: None,
             vector_element_kind: None,
+            record_type: RecordType::Unknown,
         });
 
         match &owner {
@@ -525,6 +693,26 @@ impl GraphBuilder {
         id
     }
 
+    /// Function key a call to `name` with `arg_count` arguments dispatches to, picking the arity for multi-arity functions
+    fn resolve_call_key(&self, name: &str, arg_count: usize) -> FunctionKey {
+        let Some(symbols) = self.function_arities.get(name) else {
+            return FunctionKey::Named(name.to_string());
+        };
+        let arities: Vec<(&String, usize, bool)> = symbols
+            .iter()
+            .filter_map(|symbol| {
+                let analysis = self.functions.get(&FunctionKey::Named(symbol.clone()))?;
+                Some((symbol, fixed_parameter_bindings(analysis).len(), analysis.variadic))
+            })
+            .collect();
+        arities
+            .iter()
+            .find(|(_, fixed_count, variadic)| !variadic && *fixed_count == arg_count)
+            .or_else(|| arities.iter().find(|(_, fixed_count, variadic)| *variadic && *fixed_count <= arg_count))
+            .map(|(symbol, _, _)| FunctionKey::Named(symbol.to_string()))
+            .unwrap_or_else(|| FunctionKey::Named(name.to_string()))
+    }
+
     fn get_parameter_binding(&self, function: &FunctionKey, index: usize) -> Option<BindingId> {
         self.functions.get(function).and_then(|analysis| analysis.parameter_bindings.get(index).copied())
     }
@@ -534,6 +722,8 @@ impl GraphBuilder {
     }
 
     fn plan_assignment(&mut self, binding: BindingId, node: &Node) {
+        let source = self.record_source(node);
+        self.constraints.push(Box::new(RecordConstraint::new(binding, source)));
         match node {
             Node::Primitive { value } => match value {
                 Primitive::Number(_) => self.add_literal_constraint(binding, ValueKind::Number, HeapOwnership::None, None),
@@ -564,6 +754,50 @@ impl GraphBuilder {
         }
     }
 
+    /// How an assigned expression relates to records: only constructors, `assoc` of fields onto a
+    /// record, and bindings or function results that hold records can be records
+    fn record_source(&self, node: &Node) -> RecordSource {
+        let Node::List { root } = node else {
+            return match node {
+                Node::Symbol { value } => self.lookup_symbol(value).map_or(RecordSource::Other, RecordSource::Binding),
+                _ => RecordSource::Other,
+            };
+        };
+        let Some(Node::Symbol { value }) = root.first() else {
+            return RecordSource::Other;
+        };
+        if self.lookup_symbol(value).is_some() {
+            return RecordSource::Other;
+        }
+        if let Some(name) = self.constructor_record(value) {
+            return RecordSource::Constructor(name.to_string());
+        }
+        if value == "assoc" && root.len() >= 4 && root.len() % 2 == 0 {
+            let keys: Option<Vec<&String>> = root[2..]
+                .iter()
+                .step_by(2)
+                .map(|key| match key {
+                    Node::Primitive { value: Primitive::Keyword(name) } => Some(name),
+                    _ => None,
+                })
+                .collect();
+            return match keys {
+                Some(keys) => RecordSource::Assoc {
+                    base: Box::new(self.record_source(&root[1])),
+                    records: self
+                        .records
+                        .iter()
+                        .filter(|(_, fields)| keys.iter().all(|key| fields.contains(key)))
+                        .map(|(name, _)| name.clone())
+                        .collect(),
+                },
+                None => RecordSource::Other,
+            };
+        }
+        self.get_return_binding(&self.resolve_call_key(value, root.len() - 1))
+            .map_or(RecordSource::Other, RecordSource::Binding)
+    }
+
     fn plan_list_assignment(&mut self, binding: BindingId, nodes: &[Node]) {
         if nodes.is_empty() {
             self.add_literal_constraint(binding, ValueKind::Nil, HeapOwnership::None, None);
@@ -575,7 +809,7 @@ impl GraphBuilder {
         };
 
         match value.as_str() {
-            "+" | "-" | "*" | "/" => {
+            "+" | "-" | "*" | "/" | "unchecked-add" | "unchecked-subtract" | "unchecked-multiply" => {
                 self.plan_builtin_arguments(nodes);
                 self.add_literal_constraint(binding, ValueKind::Number, HeapOwnership::None, None);
             }
@@ -583,23 +817,106 @@ impl GraphBuilder {
                 self.plan_builtin_arguments(nodes);
                 self.add_literal_constraint(binding, ValueKind::Boolean, HeapOwnership::None, None);
             }
-            "str" | "subs" => {
+            "compare" | "hash" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Number, HeapOwnership::None, None);
+            }
+            "str" => {
                 self.plan_builtin_arguments(nodes);
                 self.add_literal_constraint(binding, ValueKind::String, HeapOwnership::Owned, None);
             }
+            // A slice of a vector is a vector, of anything else a string
+            "subs" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                let source = nodes.get(1);
+                match (source.and_then(collection_literal_kind), source.and_then(|source| self.sequence_binding(source))) {
+                    (Some(ValueKind::Vector), _) => {
+                        self.add_literal_constraint_with_metadata(binding, ValueKind::Vector, HeapOwnership::Owned, None, None, element_kind);
+                    }
+                    (None, Some(collection)) => self.constraints.push(Box::new(SequenceResultConstraint::slicing(binding, collection, element_kind))),
+                    _ => self.add_literal_constraint(binding, ValueKind::String, HeapOwnership::Owned, None),
+                }
+            }
             "vec" => {
                 self.plan_builtin_arguments(nodes);
                 let element_kind = infer_element_kind(nodes.iter().skip(1));
                 self.add_literal_constraint_with_metadata(binding, ValueKind::Vector, HeapOwnership::Owned, None, None, element_kind);
             }
+            "quote" => {
+                if let Some(form) = nodes.get(1) {
+                    let kind = quoted_literal_kind(form);
+                    let ownership = if kind.is_heap_kind() && kind != ValueKind::Symbol {
+                        HeapOwnership::Owned
+                    } else {
+                        HeapOwnership::None
+                    };
+                    let element_kind = quoted_element_kind(form);
+                    self.add_literal_constraint_with_metadata(binding, kind, ownership, None, None, element_kind);
+                }
+            }
+            "list" | "cons" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = self.extract_vector_element_kind(&Node::new_list_from_raw(nodes.to_vec()));
+                self.add_literal_constraint_with_metadata(binding, ValueKind::List, HeapOwnership::Owned, None, None, element_kind);
+            }
+            "rest" | "next" | "seq" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                self.plan_sequence_result(binding, nodes.get(1), element_kind);
+            }
+            "first" | "last" | "nth" | "peek" => {
+                self.plan_builtin_arguments(nodes);
+                if let Some(collection) = nodes.get(1) {
+                    self.plan_element_metadata(binding, collection);
+                }
+            }
+            "conj" | "pop" => {
+                self.plan_builtin_arguments(nodes);
+                let set_element_kind = self.extract_set_element_kind(&Node::new_list_from_raw(nodes.to_vec()));
+                if set_element_kind.is_some() {
+                    self.add_literal_constraint_with_metadata(binding, ValueKind::Set, HeapOwnership::Owned, None, set_element_kind, None);
+                } else {
+                    let element_kind = call_element_kind(self, nodes);
+                    self.plan_collection_result(binding, nodes.get(1), element_kind);
+                }
+            }
+            "subvec" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                self.add_literal_constraint_with_metadata(binding, ValueKind::Vector, HeapOwnership::Owned, None, None, element_kind);
+            }
+            "subseq" | "rsubseq" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::List, HeapOwnership::Owned, None);
+            }
+            "reverse" | "sort" | "sort-by" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                self.add_literal_constraint_with_metadata(binding, ValueKind::List, HeapOwnership::Owned, None, None, element_kind);
+            }
+            "list?" | "empty?" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Boolean, HeapOwnership::None, None);
+            }
+            "symbol" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Symbol, HeapOwnership::Owned, None);
+            }
             "set" => {
                 self.plan_builtin_arguments(nodes);
                 self.plan_set_metadata(binding, nodes);
             }
-            "hash-map" => {
+            "hash-map" | "ex-info" | "sorted-map" | "sorted-map-by" => {
                 self.plan_builtin_arguments(nodes);
                 self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, None);
             }
+            "sorted-set" | "sorted-set-by" => {
+                self.plan_builtin_arguments(nodes);
+                let members = if value == "sorted-set-by" { 2 } else { 1 };
+                let element_kind = infer_element_kind(nodes.iter().skip(members));
+                self.add_literal_constraint_with_metadata(binding, ValueKind::Set, HeapOwnership::Owned, None, element_kind, None);
+            }
             "count" => {
                 self.plan_builtin_arguments(nodes);
                 self.add_literal_constraint(binding, ValueKind::Number, HeapOwnership::None, None);
@@ -625,6 +942,139 @@ impl GraphBuilder {
                 self.plan_builtin_arguments(nodes);
                 self.plan_get_metadata(binding, nodes);
             }
+            "map" | "filter" | "remove" | "take" | "drop" => {
+                // Lazy sources give lazy results; anything else is realized into a list
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                self.plan_sequence_result(binding, nodes.get(2), element_kind);
+            }
+            "concat" | "range" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                let kind = if nodes.len() == 1 && value == "range" { ValueKind::LazySeq } else { ValueKind::List };
+                self.add_literal_constraint_with_metadata(binding, kind, HeapOwnership::Owned, None, None, element_kind);
+            }
+            "iterate" | "repeat" | "cycle" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                self.add_literal_constraint_with_metadata(binding, ValueKind::LazySeq, HeapOwnership::Owned, None, None, element_kind);
+            }
+            "atom" | "add-watch" => {
+                // An atom carries the kind of the value it holds as its element kind, and the
+                // value types of a map it holds as its own
+                self.plan_builtin_arguments(nodes);
+                let element_kind = call_element_kind(self, nodes);
+                let map_value_types = nodes.get(1).and_then(|value| self.extract_map_metadata(value));
+                self.add_literal_constraint_with_metadata(binding, ValueKind::Atom, HeapOwnership::Owned, map_value_types, None, element_kind);
+            }
+            "deref" | "reset!" => {
+                // Both give the program its own clone of the value
+                self.plan_builtin_arguments(nodes);
+                if let Some(atom) = nodes.get(1) {
+                    self.plan_owned_element_metadata(binding, atom);
+                    if let Some(metadata) = self.extract_map_metadata(atom) {
+                        self.prime_binding_map_metadata(binding, &metadata);
+                    }
+                }
+            }
+            "compare-and-set!" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Boolean, HeapOwnership::None, None);
+            }
+            "into" => {
+                self.plan_builtin_arguments(nodes);
+                match nodes.get(1) {
+                    Some(Node::Symbol { value }) if value == "nil" => self.add_literal_constraint(binding, ValueKind::List, HeapOwnership::Owned, None),
+                    Some(Node::Symbol { value }) => {
+                        if let Some(source) = self.lookup_symbol(value) {
+                            self.constraints.push(Box::new(CopyConstraint::new(binding, source)));
+                        }
+                    }
+                    Some(target) => {
+                        if let Some(kind) = node_literal_kind(target) {
+                            self.add_literal_constraint(binding, kind, HeapOwnership::Owned, None);
+                        }
+                    }
+                    None => {}
+                }
+            }
+            "reduce" | "some" => {
+                self.plan_builtin_arguments(nodes);
+                if let Some(Node::Symbol { value: callback }) = nodes.get(1) {
+                    let arity = if value == "some" { 1 } else { 2 };
+                    let func_key = self.resolve_call_key(callback, arity);
+                    if let Some(return_binding) = self.get_return_binding(&func_key) {
+                        self.constraints.push(Box::new(CopyConstraint::new(binding, return_binding)));
+                    }
+                }
+            }
+            "every?" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Boolean, HeapOwnership::None, None);
+            }
+            "frequencies" | "group-by" => {
+                self.plan_builtin_arguments(nodes);
+                let value_kind = if value == "group-by" { ValueKind::Vector } else { ValueKind::Number };
+                let metadata = MapValueTypes::from([(MapKeyLiteral::Every, value_kind)]);
+                self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, Some(metadata));
+            }
+            "keys" | "vals" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::List, HeapOwnership::Owned, None);
+            }
+            "merge" | "merge-with" | "select-keys" | "zipmap" => {
+                self.plan_builtin_arguments(nodes);
+                // A lone map passes through merge unchanged, so only a real merge is known to build one
+                let first_map = if value == "merge-with" { 2 } else { 1 };
+                if nodes.len() > first_map + 1 || matches!(value.as_str(), "select-keys" | "zipmap") {
+                    let metadata = match value.as_str() {
+                        "merge" => nodes.last().and_then(|map| self.extract_map_metadata(map)).and_then(|mut types| {
+                            types.remove(&MapKeyLiteral::Every);
+                            (!types.is_empty()).then_some(types)
+                        }),
+                        _ => None,
+                    };
+                    self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, metadata);
+                }
+            }
+            "find" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Vector, HeapOwnership::Owned, None);
+            }
+            "map-invert" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, None);
+            }
+            "union" | "intersection" | "difference" | "select" => {
+                self.plan_builtin_arguments(nodes);
+                let element_kind = self.extract_set_element_kind(&Node::new_list_from_raw(nodes.to_vec()));
+                self.add_literal_constraint_with_metadata(binding, ValueKind::Set, HeapOwnership::Owned, None, element_kind, None);
+            }
+            "subset?" | "superset?" => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Boolean, HeapOwnership::None, None);
+            }
+            // The body is planned by `visit_let`, where the let's bindings are in scope
+            "let" => {}
+            other if self.lookup_symbol(other).is_none() && self.constructor_record(other).is_some() => {
+                self.plan_builtin_arguments(nodes);
+                let metadata = self.constructor_record(other).and_then(|name| self.record_map_metadata(name, &nodes[1..]));
+                self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, metadata);
+            }
+            polymorphism::RECORD_TYPE | polymorphism::VALUE_TYPE => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Number, HeapOwnership::None, None);
+            }
+            matching::SHAPE_TEST | matching::KEY_TEST => {
+                self.plan_builtin_arguments(nodes);
+                self.add_literal_constraint(binding, ValueKind::Boolean, HeapOwnership::None, None);
+            }
+            other if self.is_protocol_call(other) => self.plan_protocol_call(Some(binding), other, nodes),
+            // A local map or set in operator position is a lookup, compiled as `get`
+            other if self.lookup_symbol(other).is_some() => {
+                let lookup: Vec<Node> = std::iter::once(Node::Symbol { value: "get".to_string() }).chain(nodes.iter().cloned()).collect();
+                self.plan_list_assignment(binding, &lookup);
+            }
             other => self.plan_function_call(binding, other, nodes),
         }
     }
@@ -660,11 +1110,11 @@ impl GraphBuilder {
 
     fn plan_function_call(&mut self, binding: BindingId, name: &str, nodes: &[Node]) {
         self.plan_builtin_arguments(nodes);
-        let func_key = FunctionKey::Named(name.to_string());
+        let func_key = self.resolve_call_key(name, nodes.len() - 1);
         if let Some(return_binding) = self.get_return_binding(&func_key) {
             self.constraints.push(Box::new(CopyConstraint::new(binding, return_binding)));
         }
-        if let Some(params) = self.functions.get(&func_key).map(|analysis| analysis.parameter_bindings.clone()) {
+        if let Some(params) = self.functions.get(&func_key).map(fixed_parameter_bindings) {
             for (idx, arg) in nodes[1..].iter().enumerate() {
                 if let Some(param_binding) = params.get(idx) {
                     self.plan_assignment(*param_binding, arg);
@@ -675,27 +1125,71 @@ impl GraphBuilder {
         }
     }
 
+    /// Arguments of a protocol call land in temporaries until their kinds show which
+    /// implementation the call reaches
+    fn plan_protocol_call(&mut self, binding: Option<BindingId>, method: &str, nodes: &[Node]) {
+        let arguments: Vec<BindingId> = nodes[1..]
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| {
+                let owner = BindingOwner::Local {
+                    function: self.current_function(),
+                    name: format!("argument {} of {}", index, method),
+                    _depth: self.env.len(),
+                };
+                let temp = self.add_binding(owner, AstId::root());
+                self.plan_assignment(temp, arg);
+                temp
+            })
+            .collect();
+
+        let implementation_prefix = polymorphism::implementation_name(method, "");
+        let dispatcher = polymorphism::record_dispatcher_name(method);
+        let names: HashSet<String> = self
+            .functions
+            .keys()
+            .filter_map(|key| match key {
+                FunctionKey::Named(name) => Some(name),
+                FunctionKey::Program => None,
+            })
+            .chain(self.function_arities.keys())
+            .filter(|name| name.starts_with(&implementation_prefix) || **name == dispatcher)
+            .cloned()
+            .collect();
+        let implementations = names
+            .into_iter()
+            .filter_map(|name| {
+                let analysis = self.functions.get(&self.resolve_call_key(&name, arguments.len()))?;
+                Some((name, (fixed_parameter_bindings(analysis), analysis.return_binding)))
+            })
+            .collect();
+        self.constraints.push(Box::new(DispatchConstraint {
+            method: method.to_string(),
+            target: binding,
+            arguments,
+            implementations,
+        }));
+    }
+
     fn plan_assoc_metadata(&mut self, binding: BindingId, nodes: &[Node]) {
         if nodes.len() < 3 {
             self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, None);
             return;
         }
-
-        let base_metadata = nodes.get(1).and_then(|expr| self.extract_map_metadata(expr));
-        let mut metadata = base_metadata.unwrap_or_else(MapValueTypes::new);
-
-        for chunk in nodes[2..].chunks(2) {
-            if chunk.len() < 2 {
-                break;
+        match self.assoc_target(nodes) {
+            ValueKind::Vector => {
+                let element_kind = call_element_kind(self, nodes);
+                self.add_literal_constraint_with_metadata(binding, ValueKind::Vector, HeapOwnership::Owned, None, None, element_kind);
+                return;
             }
-            if let Some(key_literal) = map_key_literal_from_node(&chunk[0]) {
-                if let Some(kind) = node_literal_kind(&chunk[1]) {
-                    metadata.insert(key_literal, kind);
-                }
+            ValueKind::Any => {
+                self.plan_collection_result(binding, nodes.get(1), None);
+                return;
             }
+            _ => {}
         }
 
-        let metadata_opt = if metadata.is_empty() { None } else { Some(metadata) };
+        let metadata_opt = self.assoc_map_metadata(nodes);
         self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, metadata_opt);
     }
 
@@ -706,7 +1200,7 @@ impl GraphBuilder {
         }
         let mut metadata = nodes.get(1).and_then(|expr| self.extract_map_metadata(expr)).unwrap_or_else(MapValueTypes::new);
         nodes.iter().skip(2).filter_map(map_key_literal_from_node).for_each(|key_literal| {
-            metadata.remove(&key_literal);
+            remove_map_value_type(&mut metadata, &key_literal);
         });
         let metadata_opt = if metadata.is_empty() { None } else { Some(metadata) };
         self.add_literal_constraint(binding, ValueKind::Map, HeapOwnership::Owned, metadata_opt);
@@ -717,37 +1211,192 @@ impl GraphBuilder {
             return;
         }
 
-        let key_expr = &nodes[2];
+        let key_literal = map_key_literal_from_node(&nodes[2]);
         if let Some(metadata) = self.extract_map_metadata(&nodes[1]) {
-            if let Some(key_literal) = map_key_literal_from_node(key_expr) {
-                if let Some(kind) = metadata.get(&key_literal) {
-                    let ownership = if kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
-                    self.add_literal_constraint(binding, *kind, ownership, None);
-                    return;
-                }
+            if let Some(kind) = map_value_type(&metadata, key_literal.as_ref()) {
+                let ownership = if kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+                let nested = key_literal.as_ref().and_then(|key| nested_map_value_types(&metadata, key));
+                self.add_literal_constraint(binding, kind, ownership, nested);
+                return;
             }
         }
 
         if let Node::Symbol { value } = &nodes[1] {
-            if let Some(key_literal) = map_key_literal_from_node(key_expr) {
-                if let Some(map_binding) = self.lookup_symbol(value) {
-                    self.constraints.push(Box::new(GetConstraint::new(binding, map_binding, key_literal)));
-                } else {
-                    debug_assert!(false, "unresolved map symbol {}", value);
-                }
+            if let Some(map_binding) = self.lookup_symbol(value) {
+                self.constraints.push(Box::new(GetConstraint::new(binding, map_binding, key_literal)));
+            } else {
+                debug_assert!(false, "unresolved map symbol {}", value);
             }
         }
 
-        if let Some(element_kind) = self.extract_vector_element_kind(&nodes[1]) {
+        self.plan_element_metadata(binding, &nodes[1]);
+    }
+
+    /// An element read from a vector or list takes the collection's element kind, borrowed
+    fn plan_element_metadata(&mut self, binding: BindingId, collection: &Node) {
+        if let Some(element_kind) = self.extract_vector_element_kind(collection) {
             let ownership = if element_kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
             self.add_literal_constraint_with_metadata(binding, element_kind, ownership, None, None, None);
-        } else if let Node::Symbol { value } = &nodes[1] {
+        } else if let Node::Symbol { value } = collection {
             if let Some(vector_binding) = self.lookup_symbol(value) {
                 self.constraints.push(Box::new(VectorElementConstraint::new(binding, vector_binding)));
             }
         }
     }
 
+    /// Like `plan_element_metadata`, for results that own their copy of the element
+    fn plan_owned_element_metadata(&mut self, binding: BindingId, collection: &Node) {
+        if let Some(element_kind) = self.extract_vector_element_kind(collection) {
+            let ownership = if element_kind.is_heap_kind() { HeapOwnership::Owned } else { HeapOwnership::None };
+            self.add_literal_constraint_with_metadata(binding, element_kind, ownership, None, None, None);
+        } else if let Node::Symbol { value } = collection {
+            if let Some(vector_binding) = self.lookup_symbol(value) {
+                self.constraints.push(Box::new(VectorElementConstraint::owned(binding, vector_binding)));
+            }
+        }
+    }
+
+    /// A sequence function result follows its source: lazy sources give lazy results, anything else
+    /// a list
+    fn plan_sequence_result(&mut self, binding: BindingId, source: Option<&Node>, element_kind: Option<ValueKind>) {
+        match source.and_then(|source| self.sequence_binding(source)) {
+            Some(collection) => self.constraints.push(Box::new(SequenceResultConstraint::new(binding, collection, element_kind))),
+            None => {
+                let kind = if source.is_some_and(is_lazy_call) { ValueKind::LazySeq } else { ValueKind::List };
+                self.add_literal_constraint_with_metadata(binding, kind, HeapOwnership::Owned, None, None, element_kind);
+            }
+        }
+    }
+
+    /// A conj, pop or assoc result follows its collection: vectors, maps and sets keep their kind and
+    /// anything else gives a list. Nothing is planned when the collection's kind cannot be traced.
+    fn plan_collection_result(&mut self, binding: BindingId, source: Option<&Node>, element_kind: Option<ValueKind>) {
+        let Some(source) = source else {
+            return;
+        };
+        if let Some(kind) = collection_literal_kind(source) {
+            self.add_literal_constraint_with_metadata(binding, preserved_collection_kind(kind), HeapOwnership::Owned, None, None, element_kind);
+        } else if let Some(collection) = self.sequence_binding(source) {
+            self.constraints.push(Box::new(SequenceResultConstraint::preserving(binding, collection, element_kind)));
+        }
+    }
+
+    /// The binding describing a sequence argument: a local, or the return of a function call
+    fn sequence_binding(&self, node: &Node) -> Option<BindingId> {
+        match node {
+            Node::Symbol { value } => self.lookup_symbol(value),
+            Node::List { root } => match root.first() {
+                Some(Node::Symbol { value }) => self.get_return_binding(&self.resolve_call_key(value, root.len() - 1)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Callback parameters of a sequence function receive the items of its collection argument
+    fn plan_sequence_callbacks(&mut self, nodes: &[Node]) {
+        let Some(Node::Symbol { value: op }) = nodes.first() else {
+            return;
+        };
+        let keys;
+        let (arity, source) = match (op.as_str(), nodes.len()) {
+            // iterate feeds the callback its seed, then its own results
+            ("iterate", 3) => (1, &nodes[2]),
+            ("map" | "filter" | "remove" | "every?" | "some" | "group-by" | "select", 3) => (1, &nodes[2]),
+            ("sort-by", 3) => (1, &nodes[2]),
+            ("sort-by", 4) => (1, &nodes[3]),
+            // A comparator receives two items
+            ("reduce" | "sort", 3) => (2, &nodes[2]),
+            ("reduce", 4) => (2, &nodes[3]),
+            // A sorted collection's comparator receives two of its keys
+            ("sorted-map-by" | "sorted-set-by", len) if len > 1 => {
+                let step = if op == "sorted-map-by" { 2 } else { 1 };
+                keys = Node::Set {
+                    root: nodes[2..].iter().step_by(step).cloned().collect(),
+                };
+                (2, &keys)
+            }
+            _ => return,
+        };
+        let Some(Node::Symbol { value: callback }) = nodes.get(1) else {
+            return;
+        };
+        if self.lookup_symbol(callback).is_some() {
+            return;
+        }
+
+        let func_key = self.resolve_call_key(callback, arity);
+        let Some(params) = self.functions.get(&func_key).map(fixed_parameter_bindings) else {
+            return;
+        };
+        if params.len() != arity {
+            return;
+        }
+
+        if op == "iterate" {
+            self.plan_assignment(params[0], source);
+            if let Some(return_binding) = self.get_return_binding(&func_key) {
+                self.constraints.push(Box::new(CopyConstraint::new(params[0], return_binding)));
+            }
+            return;
+        }
+
+        if op == "reduce" && nodes.len() == 4 {
+            self.plan_assignment(params[0], &nodes[2]);
+        } else if arity == 2 {
+            self.plan_sequence_item(params[0], source);
+        }
+        if let Some(return_binding) = self.get_return_binding(&func_key).filter(|_| op == "reduce") {
+            self.constraints.push(Box::new(CopyConstraint::new(params[0], return_binding)));
+        }
+        self.plan_sequence_item(params[arity - 1], source);
+    }
+
+    /// A watch receives its key, the atom, and the atom's old and new values borrowed from it
+    fn plan_watch_callback(&mut self, nodes: &[Node]) {
+        let [_, atom, key, Node::Symbol { value: callback }] = nodes else {
+            return;
+        };
+        if self.lookup_symbol(callback).is_some() {
+            return;
+        }
+
+        let func_key = self.resolve_call_key(callback, 4);
+        let Some(params) = self.functions.get(&func_key).map(fixed_parameter_bindings) else {
+            return;
+        };
+        if params.len() != 4 {
+            return;
+        }
+
+        self.plan_assignment(params[0], key);
+        self.plan_assignment(params[1], atom);
+        self.plan_sequence_item(params[2], atom);
+        self.plan_sequence_item(params[3], atom);
+    }
+
+    /// An item of a sequence function's collection: characters of a string, `[key value]` entries
+    /// of a map, or the elements of a vector, list or set, borrowed from the collection
+    fn plan_sequence_item(&mut self, binding: BindingId, collection: &Node) {
+        if let Node::Symbol { value } = collection {
+            if let Some(collection_binding) = self.lookup_symbol(value) {
+                self.constraints.push(Box::new(SequenceItemConstraint::new(binding, collection_binding)));
+            }
+            return;
+        }
+
+        let item_kind = match node_literal_kind(collection) {
+            Some(ValueKind::String) => Some(ValueKind::String),
+            Some(ValueKind::Map) => Some(ValueKind::Vector),
+            Some(ValueKind::Set) => self.extract_set_element_kind(collection),
+            _ => self.extract_vector_element_kind(collection),
+        };
+        if let Some(kind) = item_kind {
+            let ownership = if kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+            self.add_literal_constraint(binding, kind, ownership, None);
+        }
+    }
+
     fn plan_set_metadata(&mut self, binding: BindingId, nodes: &[Node]) {
         let element_kind = infer_element_kind(nodes.iter().skip(1));
         self.add_literal_constraint_with_metadata(binding, ValueKind::Set, HeapOwnership::Owned, None, element_kind, None);
@@ -768,18 +1417,30 @@ impl GraphBuilder {
         };
 
         let arg_kinds: Option<Vec<ValueKind>> = match value.as_str() {
-            "+" | "-" | "*" | "/" | "=" | "<" | ">" | "<=" | ">=" => {
+            "+" | "-" | "*" | "/" | "unchecked-add" | "unchecked-subtract" | "unchecked-multiply" | "<" | ">" | "<=" | ">=" => {
                 let count = nodes.len().saturating_sub(1);
                 Some(vec![ValueKind::Number; count])
             }
+            "=" => Some(vec![ValueKind::Any; nodes.len() - 1]),
             "and" | "or" => {
                 let count = nodes.len().saturating_sub(1);
                 Some(vec![ValueKind::Boolean; count])
             }
             "not" => Some(vec![ValueKind::Boolean]),
+            "compare" | "hash" | "sort" | "sort-by" => Some(vec![ValueKind::Any; nodes.len() - 1]),
+            "sorted-map" | "sorted-set" | "sorted-map-by" | "sorted-set-by" | "subseq" | "rsubseq" => Some(vec![ValueKind::Any; nodes.len() - 1]),
             "str" => Some(vec![ValueKind::Any; nodes.len() - 1]),
-            "subs" => Some(vec![ValueKind::String, ValueKind::Number, ValueKind::Number]),
-            "vec" => Some(vec![ValueKind::Any; nodes.len() - 1]),
+            "subs" => Some(vec![ValueKind::Any, ValueKind::Number, ValueKind::Number]),
+            "vec" | "list" => Some(vec![ValueKind::Any; nodes.len() - 1]),
+            "cons" => Some(vec![ValueKind::Any, ValueKind::Any]),
+            "first" | "rest" | "next" | "seq" | "last" | "empty?" | "list?" | "symbol" | "peek" | "pop" | "reverse" => Some(vec![ValueKind::Any]),
+            "conj" => Some(vec![ValueKind::Any; nodes.len() - 1]),
+            "subvec" => Some(vec![ValueKind::Vector, ValueKind::Number, ValueKind::Number]),
+            "nth" => {
+                let mut kinds = vec![ValueKind::Any, ValueKind::Number];
+                kinds.resize(nodes.len().saturating_sub(1).max(2), ValueKind::Any);
+                Some(kinds)
+            }
             "set" => Some(vec![ValueKind::Any; nodes.len() - 1]),
             "hash-map" => Some(vec![ValueKind::Any; nodes.len() - 1]),
             "count" => Some(vec![ValueKind::Any]),
@@ -789,7 +1450,7 @@ impl GraphBuilder {
                     Some(Vec::new())
                 } else {
                     let mut kinds = Vec::with_capacity(nodes.len() - 1);
-                    kinds.push(ValueKind::Map);
+                    kinds.push(self.assoc_target(nodes));
                     kinds.extend(std::iter::repeat(ValueKind::Any).take(nodes.len() - 2));
                     Some(kinds)
                 }
@@ -815,6 +1476,28 @@ impl GraphBuilder {
                 }
             }
             "contains?" => Some(vec![ValueKind::Any, ValueKind::Any]),
+            "keys" | "vals" | "map-invert" => Some(vec![ValueKind::Map]),
+            "union" | "intersection" | "difference" | "subset?" | "superset?" => Some(vec![ValueKind::Set; nodes.len() - 1]),
+            "select" => Some(vec![ValueKind::Any, ValueKind::Set]),
+            "find" | "select-keys" => Some(vec![ValueKind::Map, ValueKind::Any]),
+            "merge" => Some(vec![ValueKind::Map; nodes.len() - 1]),
+            "merge-with" => {
+                let mut kinds = vec![ValueKind::Map; nodes.len() - 1];
+                if let Some(callback) = kinds.first_mut() {
+                    *callback = ValueKind::Any;
+                }
+                Some(kinds)
+            }
+            "take" | "drop" => Some(vec![ValueKind::Number, ValueKind::Any]),
+            "repeat" if nodes.len() == 3 => Some(vec![ValueKind::Number, ValueKind::Any]),
+            "range" => Some(vec![ValueKind::Number; nodes.len() - 1]),
+            "deref" | "reset!" | "compare-and-set!" | "add-watch" => {
+                let mut kinds = vec![ValueKind::Any; nodes.len() - 1];
+                if let Some(atom) = kinds.first_mut() {
+                    *atom = ValueKind::Atom;
+                }
+                Some(kinds)
+            }
             _ => None,
         };
 
@@ -855,10 +1538,18 @@ impl GraphBuilder {
     fn plan_assignment_for_node(&mut self, node: &Node) {
         match node {
             Node::Primitive { .. } | Node::Symbol { .. } | Node::Vector { .. } | Node::Map { .. } | Node::Set { .. } => {}
+            // Quoted forms are data; a nested let is planned by `visit_let`, where its bindings are in scope
+            Node::List { root } if matches!(root.first(), Some(Node::Symbol { value }) if value == "quote" || value == "let") => {}
+            Node::List { root } if matches!(root.first(), Some(Node::Symbol { value }) if self.is_protocol_call(value)) => {
+                if let Some(Node::Symbol { value }) = root.first() {
+                    self.plan_protocol_call(None, value, root);
+                }
+            }
             Node::List { root } => {
                 if !root.is_empty() {
                     if let Node::Symbol { value } = &root[0] {
-                        if let Some(params) = self.functions.get(&FunctionKey::Named(value.clone())).map(|analysis| analysis.parameter_bindings.clone()) {
+                        let func_key = self.resolve_call_key(value, root.len() - 1);
+                        if let Some(params) = self.functions.get(&func_key).map(fixed_parameter_bindings) {
                             root[1..].iter().enumerate().for_each(|(idx, arg)| {
                                 if let Some(binding_id) = params.get(idx) {
                                     self.plan_assignment(*binding_id, arg);
@@ -873,13 +1564,68 @@ impl GraphBuilder {
     }
 }
 
+/// One arity of a `defn` with the AST paths, relative to the defn list, of its parameter vector and body
+struct DefnArity<'a> {
+    symbol: String,
+    params: &'a [Node],
+    params_path: Vec<usize>,
+    body: &'a Node,
+    body_path: Vec<usize>,
+}
+
+/// Arities of a well-formed `defn` list; `None` for anything else
+fn defn_arities(root: &[Node]) -> Option<Vec<DefnArity<'_>>> {
+    match root.first() {
+        Some(Node::Symbol { value }) if value == "defn" => {}
+        _ => return None,
+    }
+    let (_, clauses) = defn_clauses(&root[1..]).ok()?;
+    let multi_arity = matches!(root.get(2), Some(Node::List { .. }));
+
+    clauses
+        .into_iter()
+        .enumerate()
+        .map(|(idx, clause)| {
+            let (params_path, body_path) = if multi_arity { (vec![idx + 2, 0], vec![idx + 2, 1]) } else { (vec![2], vec![3]) };
+            let params_node = if multi_arity {
+                match &root[idx + 2] {
+                    Node::List { root: clause_root } => &clause_root[0],
+                    _ => return None,
+                }
+            } else {
+                &root[2]
+            };
+            let Node::Vector { root: params } = params_node else {
+                return None;
+            };
+            Some(DefnArity {
+                symbol: clause.symbol,
+                params,
+                params_path,
+                body: clause.body,
+                body_path,
+            })
+        })
+        .collect()
+}
+
+/// Parameter bindings that receive call arguments one-to-one; a variadic rest binding is excluded
+fn fixed_parameter_bindings(analysis: &FunctionAnalysis) -> Vec<BindingId> {
+    let mut params = analysis.parameter_bindings.clone();
+    if analysis.variadic {
+        params.pop();
+    }
+    params
+}
+
 fn infer_element_kind<'a, I>(nodes: I) -> Option<ValueKind>
 where
     I: IntoIterator<Item = &'a Node>,
 {
-    nodes.into_iter().filter_map(node_literal_kind).fold(None, |mut acc, kind| {
-        merge_element_kind(&mut acc, kind);
-        acc
+    // Literal kinds are never Any, so an Any here records a conflict and must stay
+    nodes.into_iter().filter_map(node_literal_kind).fold(None, |acc, kind| match acc {
+        Some(existing) if existing != kind => Some(ValueKind::Any),
+        _ => Some(kind),
     })
 }
 
@@ -898,8 +1644,24 @@ fn extract_set_element_kind(builder: &GraphBuilder, node: &Node) -> Option<Value
         Node::List { root } => {
             if let Some(Node::Symbol { value }) = root.first() {
                 match value.as_str() {
-                    "set" => infer_element_kind(root.iter().skip(1)),
-                    "disj" => root.get(1).and_then(|expr| extract_set_element_kind(builder, expr)),
+                    "set" | "sorted-set" => infer_element_kind(root.iter().skip(1)),
+                    "sorted-set-by" => infer_element_kind(root.iter().skip(2)),
+                    "disj" | "intersection" | "difference" => root.get(1).and_then(|expr| extract_set_element_kind(builder, expr)),
+                    "select" => root.get(2).and_then(|expr| extract_set_element_kind(builder, expr)),
+                    "union" => {
+                        let kinds: Option<Vec<ValueKind>> = root.iter().skip(1).map(|expr| extract_set_element_kind(builder, expr)).collect();
+                        infer_common_kind(&kinds?)
+                    }
+                    "conj" => {
+                        // Every added member must be a literal of the set's member kind
+                        let kinds: Option<Vec<ValueKind>> = root.iter().skip(2).map(node_literal_kind).collect();
+                        let kind = infer_common_kind(&kinds?)?;
+                        match root.get(1) {
+                            Some(Node::Set { root }) if root.is_empty() => Some(kind),
+                            Some(base) => extract_set_element_kind(builder, base).filter(|existing| *existing == kind),
+                            None => None,
+                        }
+                    }
                     _ => None,
                 }
             } else {
@@ -914,14 +1676,50 @@ fn extract_vector_element_kind(builder: &GraphBuilder, node: &Node) -> Option<Va
     match node {
         Node::Vector { root } => infer_vector_literal_kind(root),
         Node::Symbol { value } => builder.lookup_symbol(value).and_then(|binding| builder.binding_vector_element_kind(binding)),
-        Node::List { root } => {
-            if let Some(Node::Symbol { value }) = root.first() {
-                match value.as_str() {
-                    "vec" => infer_element_kind(root.iter().skip(1)),
-                    _ => None,
-                }
+        Node::List { root } => call_element_kind(builder, root),
+        _ => None,
+    }
+}
+
+/// Element kind of the vector or list built by a `vec`, `list`, `quote`, `cons`, `rest`, `range` or
+/// item-preserving sequence call, or of the value held by the atom an `atom` or `add-watch` gives
+fn call_element_kind(builder: &GraphBuilder, root: &[Node]) -> Option<ValueKind> {
+    let Some(Node::Symbol { value }) = root.first() else {
+        return None;
+    };
+    match value.as_str() {
+        "vec" | "list" => infer_element_kind(root.iter().skip(1)),
+        "quote" => root.get(1).and_then(quoted_element_kind),
+        "rest" | "next" | "seq" => root.get(1).and_then(|expr| extract_vector_element_kind(builder, expr)),
+        "filter" | "remove" | "take" | "drop" if root.len() == 3 => extract_vector_element_kind(builder, &root[2]),
+        "range" => Some(ValueKind::Number),
+        "iterate" | "repeat" => root.last().and_then(node_literal_kind),
+        "atom" => root.get(1).and_then(node_literal_kind),
+        "add-watch" => root.get(1).and_then(|expr| extract_vector_element_kind(builder, expr)),
+        "cycle" | "pop" | "subvec" | "subs" | "reverse" => root.get(1).and_then(|expr| extract_vector_element_kind(builder, expr)),
+        "sort" | "sort-by" => root.last().and_then(|expr| extract_vector_element_kind(builder, expr)),
+        "conj" | "assoc" => {
+            // Every added item must be a literal of one kind, matching any items already there
+            let added: Vec<&Node> = if value == "assoc" {
+                root.iter().skip(3).step_by(2).collect()
             } else {
-                None
+                root.iter().skip(2).collect()
+            };
+            let kinds: Option<Vec<ValueKind>> = added.into_iter().map(node_literal_kind).collect();
+            let kind = infer_common_kind(&kinds?)?;
+            match root.get(1) {
+                Some(Node::Symbol { value }) if value == "nil" => Some(kind),
+                Some(Node::Vector { root }) if root.is_empty() => Some(kind),
+                Some(base) => extract_vector_element_kind(builder, base).filter(|existing| *existing == kind),
+                None => None,
+            }
+        }
+        "cons" => {
+            let head = root.get(1).and_then(node_literal_kind)?;
+            match root.get(2) {
+                Some(Node::Symbol { value }) if value == "nil" => Some(head),
+                Some(tail) => extract_vector_element_kind(builder, tail).filter(|kind| *kind == head),
+                None => None,
             }
         }
         _ => None,
@@ -933,7 +1731,11 @@ fn infer_map_literal_metadata(entries: &[(Node, Node)]) -> Option<MapValueTypes>
     for (key_node, value_node) in entries {
         if let Some(map_key) = map_key_literal_from_node(key_node) {
             if let Some(kind) = node_literal_kind(value_node) {
-                metadata.insert(map_key, kind);
+                let nested = match value_node {
+                    Node::Map { entries } => infer_map_literal_metadata(entries),
+                    _ => None,
+                };
+                set_map_value_type(&mut metadata, map_key, kind, nested.as_ref());
             }
         }
     }
@@ -957,6 +1759,72 @@ fn map_key_literal_from_node(node: &Node) -> Option<MapKeyLiteral> {
     }
 }
 
+/// Whether a call builds a lazy sequence: iterate, repeat, cycle and (range), and map, filter,
+/// remove, drop, rest, next or seq over one of those
+fn is_lazy_call(node: &Node) -> bool {
+    let Node::List { root } = node else {
+        return false;
+    };
+    let Some(Node::Symbol { value }) = root.first() else {
+        return false;
+    };
+    match value.as_str() {
+        "iterate" | "repeat" | "cycle" => true,
+        "range" => root.len() == 1,
+        "map" | "filter" | "remove" | "take" | "drop" => root.get(2).is_some_and(is_lazy_call),
+        "rest" | "next" | "seq" => root.get(1).is_some_and(is_lazy_call),
+        _ => false,
+    }
+}
+
+/// The one kind shared by every entry, if there is one
+fn infer_common_kind(kinds: &[ValueKind]) -> Option<ValueKind> {
+    let (first, rest) = kinds.split_first()?;
+    rest.iter().all(|kind| kind == first).then_some(*first)
+}
+
+/// What `assoc` updates: a vector for a vector literal or a numeric key, a map for any other
+/// literal key, and unknown otherwise
+fn assoc_target_kind(nodes: &[Node]) -> ValueKind {
+    match (nodes.get(1).and_then(collection_literal_kind), nodes.get(2)) {
+        (Some(ValueKind::Vector), _) | (None, Some(Node::Primitive { value: Primitive::Number(_) })) => ValueKind::Vector,
+        (Some(kind), _) => kind,
+        (None, Some(key)) if map_key_literal_from_node(key).is_some() => ValueKind::Map,
+        _ => ValueKind::Any,
+    }
+}
+
+/// Kind of a literal collection, or of a builtin call that builds one from its first argument
+fn collection_literal_kind(node: &Node) -> Option<ValueKind> {
+    let Node::List { root } = node else {
+        return node_literal_kind(node);
+    };
+    let Some(Node::Symbol { value }) = root.first() else {
+        return None;
+    };
+    match value.as_str() {
+        "vec" | "subvec" => Some(ValueKind::Vector),
+        "hash-map" | "dissoc" | "sorted-map" | "sorted-map-by" => Some(ValueKind::Map),
+        "set" | "disj" | "union" | "intersection" | "difference" | "select" | "sorted-set" | "sorted-set-by" => Some(ValueKind::Set),
+        "map-invert" => Some(ValueKind::Map),
+        "list" | "cons" | "reverse" | "concat" | "sort" | "sort-by" | "subseq" | "rsubseq" => Some(ValueKind::List),
+        "conj" | "pop" => root.get(1).and_then(collection_literal_kind).map(preserved_collection_kind),
+        "assoc" => match assoc_target_kind(root) {
+            ValueKind::Any => None,
+            kind => Some(kind),
+        },
+        _ => node_literal_kind(node),
+    }
+}
+
+/// Kind of a conj, pop or assoc result over a collection of `kind`
+fn preserved_collection_kind(kind: ValueKind) -> ValueKind {
+    match kind {
+        ValueKind::Vector | ValueKind::Map | ValueKind::Set => kind,
+        _ => ValueKind::List,
+    }
+}
+
 fn node_literal_kind(node: &Node) -> Option<ValueKind> {
     match node {
         Node::Primitive { value } => match value {
@@ -969,6 +1837,31 @@ fn node_literal_kind(node: &Node) -> Option<ValueKind> {
         Node::Vector { .. } => Some(ValueKind::Vector),
         Node::Map { .. } => Some(ValueKind::Map),
         Node::Set { .. } => Some(ValueKind::Set),
+        Node::List { root } => match root.as_slice() {
+            [Node::Symbol { value }, form] if value == "quote" => Some(quoted_literal_kind(form)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Kind of the value a quoted form materializes
+fn quoted_literal_kind(form: &Node) -> ValueKind {
+    match form {
+        Node::Symbol { value } if value == "nil" => ValueKind::Nil,
+        Node::Symbol { .. } => ValueKind::Symbol,
+        Node::List { .. } => ValueKind::List,
+        _ => node_literal_kind(form).unwrap_or(ValueKind::Any),
+    }
+}
+
+/// Element kind of a quoted list or vector
+fn quoted_element_kind(form: &Node) -> Option<ValueKind> {
+    match form {
+        Node::List { root } | Node::Vector { root } => root.iter().map(quoted_literal_kind).fold(None, |mut acc, kind| {
+            merge_element_kind(&mut acc, kind);
+            acc
+        }),
         _ => None,
     }
 }
@@ -1021,6 +1914,10 @@ impl TypeInferenceEngine {
                 map_value_types: node.map_value_types,
                 set_element_kind: node.set_element_kind,
                 vector_element_kind: node.vector_element_kind,
+                record_type: match node.record_type {
+                    RecordType::Record(name) => Some(name),
+                    RecordType::Unknown | RecordType::Mixed => None,
+                },
             })
             .collect();
 
@@ -1039,7 +1936,12 @@ impl<'a> ConstraintContext<'a> {
 
     fn update_binding_kind(&mut self, id: BindingId, kind: ValueKind) -> bool {
         let node = self.nodes.get_mut(id.to_index()).expect("invalid binding id");
+        if node.mixed_kind {
+            return false;
+        }
         let merged = merge_kinds(node.value_kind, kind);
+        // Going back from Any to a kind would undo the conflict on the next pass and never settle
+        node.mixed_kind = merged == ValueKind::Any && kind != ValueKind::Any;
         if merged != node.value_kind {
             node.value_kind = merged;
             true
@@ -1066,6 +1968,13 @@ impl<'a> ConstraintContext<'a> {
         self.nodes[id.to_index()].value_kind
     }
 
+    fn binding_record(&self, id: BindingId) -> Option<&str> {
+        match &self.nodes[id.to_index()].record_type {
+            RecordType::Record(name) => Some(name),
+            _ => None,
+        }
+    }
+
     fn binding_ownership(&self, id: BindingId) -> HeapOwnership {
         self.nodes[id.to_index()].heap_ownership
     }
@@ -1105,6 +2014,32 @@ impl<'a> ConstraintContext<'a> {
         let node = self.nodes.get_mut(id.to_index()).expect("invalid binding id");
         merge_element_kind(&mut node.vector_element_kind, kind)
     }
+
+    /// The record type an assignment's value has, given what is known of the bindings it reads
+    fn source_record_type(&self, source: &RecordSource) -> RecordType {
+        match source {
+            RecordSource::Constructor(name) => RecordType::Record(name.clone()),
+            RecordSource::Binding(id) => self.nodes[id.to_index()].record_type.clone(),
+            RecordSource::Assoc { base, records } => match self.source_record_type(base) {
+                RecordType::Record(name) if !records.contains(&name) => RecordType::Mixed,
+                other => other,
+            },
+            RecordSource::Other => RecordType::Mixed,
+        }
+    }
+
+    fn update_record_type(&mut self, id: BindingId, incoming: RecordType) -> bool {
+        let node = self.nodes.get_mut(id.to_index()).expect("invalid binding id");
+        let merged = match (&node.record_type, incoming) {
+            (_, RecordType::Unknown) => return false,
+            (RecordType::Unknown, incoming) => incoming,
+            (current, incoming) if *current == incoming => return false,
+            _ => RecordType::Mixed,
+        };
+        let changed = merged != node.record_type;
+        node.record_type = merged;
+        changed
+    }
 }
 
 fn merge_kinds(current: ValueKind, next: ValueKind) -> ValueKind {
@@ -1123,6 +2058,16 @@ fn merge_kinds(current: ValueKind, next: ValueKind) -> ValueKind {
     }
 }
 
+/// Like `merge_kinds` for metadata, where a recorded Any is an earlier conflict rather than an
+/// unknown kind and so stays
+fn merge_settled_kinds(current: ValueKind, next: ValueKind) -> ValueKind {
+    if current == ValueKind::Any {
+        current
+    } else {
+        merge_kinds(current, next)
+    }
+}
+
 fn merge_ownership(current: HeapOwnership, next: HeapOwnership) -> HeapOwnership {
     use HeapOwnership::*;
     match (current, next) {
@@ -1139,7 +2084,7 @@ fn merge_map_value_types(target: &mut Option<MapValueTypes>, incoming: &MapValue
             for (key, value) in incoming {
                 match existing.get(key) {
                     Some(existing_kind) => {
-                        let merged = merge_kinds(*existing_kind, *value);
+                        let merged = merge_settled_kinds(*existing_kind, *value);
                         if merged != *existing_kind {
                             existing.insert(key.clone(), merged);
                             changed = true;
@@ -1167,7 +2112,7 @@ fn merge_map_value_types(target: &mut Option<MapValueTypes>, incoming: &MapValue
 fn merge_element_kind(target: &mut Option<ValueKind>, incoming: ValueKind) -> bool {
     match target {
         Some(existing) => {
-            let merged = merge_kinds(*existing, incoming);
+            let merged = merge_settled_kinds(*existing, incoming);
             if merged != *existing {
                 *existing = merged;
                 true
@@ -1285,14 +2230,76 @@ impl Constraint for CopyConstraint {
     }
 }
 
+/// One assignment's say in the binding's record type
+struct RecordConstraint {
+    target: BindingId,
+    source: RecordSource,
+}
+
+impl RecordConstraint {
+    fn new(target: BindingId, source: RecordSource) -> Self {
+        RecordConstraint { target, source }
+    }
+}
+
+impl Constraint for RecordConstraint {
+    fn apply(&mut self, context: &mut ConstraintContext<'_>) -> ConstraintState {
+        let incoming = context.source_record_type(&self.source);
+        if context.update_record_type(self.target, incoming) {
+            ConstraintState::Progress
+        } else {
+            ConstraintState::Stable
+        }
+    }
+}
+
+/// A protocol call's say in the implementation it reaches: once the first argument's kind is
+/// known, the arguments flow into that implementation's parameters and its result into the call's
+struct DispatchConstraint {
+    method: String,
+    target: Option<BindingId>,
+    arguments: Vec<BindingId>,
+    implementations: HashMap<String, (Vec<BindingId>, Option<BindingId>)>, // function name -> parameters, return
+}
+
+impl Constraint for DispatchConstraint {
+    fn apply(&mut self, context: &mut ConstraintContext<'_>) -> ConstraintState {
+        let Some(first) = self.arguments.first() else {
+            return ConstraintState::Stable;
+        };
+        let (kind, record) = (context.binding_kind(*first), context.binding_record(*first).map(str::to_string));
+        // A kind still unknown may yet be settled; the call only falls back to the `by type`
+        // dispatcher once compiled
+        if kind == ValueKind::Any {
+            return ConstraintState::Stable;
+        }
+        let Ok(name) = dispatch_target(&self.method, kind, record.as_deref(), |name| self.implementations.contains_key(name)) else {
+            return ConstraintState::Stable;
+        };
+        let (params, return_binding) = &self.implementations[&name];
+        let flows = params.iter().zip(&self.arguments).map(|(param, arg)| (*param, *arg)).chain(self.target.zip(*return_binding));
+        let mut progress = false;
+        for (target, source) in flows {
+            let copied = CopyConstraint::new(target, source).apply(context);
+            let recorded = RecordConstraint::new(target, RecordSource::Binding(source)).apply(context);
+            progress |= copied == ConstraintState::Progress || recorded == ConstraintState::Progress;
+        }
+        if progress {
+            ConstraintState::Progress
+        } else {
+            ConstraintState::Stable
+        }
+    }
+}
+
 struct GetConstraint {
     target: BindingId,
     map_binding: BindingId,
-    key: MapKeyLiteral,
+    key: Option<MapKeyLiteral>, // None for a computed key
 }
 
 impl GetConstraint {
-    fn new(target: BindingId, map_binding: BindingId, key: MapKeyLiteral) -> Self {
+    fn new(target: BindingId, map_binding: BindingId, key: Option<MapKeyLiteral>) -> Self {
         GetConstraint { target, map_binding, key }
     }
 }
@@ -1303,11 +2310,12 @@ impl Constraint for GetConstraint {
             return ConstraintState::Stable;
         };
 
-        let Some(kind) = metadata.get(&self.key).copied() else {
+        let Some(kind) = map_value_type(metadata, self.key.as_ref()) else {
             return ConstraintState::Stable;
         };
 
         let ownership = if kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+        let nested = self.key.as_ref().and_then(|key| nested_map_value_types(metadata, key));
         let mut progress = false;
         if context.update_binding_kind(self.target, kind) {
             progress = true;
@@ -1315,6 +2323,9 @@ impl Constraint for GetConstraint {
         if context.update_binding_ownership(self.target, ownership) {
             progress = true;
         }
+        if context.update_map_value_types(self.target, nested.as_ref()) {
+            progress = true;
+        }
         if progress {
             ConstraintState::Progress
         } else {
@@ -1326,11 +2337,24 @@ impl Constraint for GetConstraint {
 struct VectorElementConstraint {
     target: BindingId,
     vector_binding: BindingId,
+    heap_ownership: HeapOwnership, // of heap elements: borrowed from the collection, or a clone
 }
 
 impl VectorElementConstraint {
     fn new(target: BindingId, vector_binding: BindingId) -> Self {
-        VectorElementConstraint { target, vector_binding }
+        VectorElementConstraint {
+            target,
+            vector_binding,
+            heap_ownership: HeapOwnership::Borrowed,
+        }
+    }
+
+    fn owned(target: BindingId, vector_binding: BindingId) -> Self {
+        VectorElementConstraint {
+            target,
+            vector_binding,
+            heap_ownership: HeapOwnership::Owned,
+        }
     }
 }
 
@@ -1340,7 +2364,7 @@ impl Constraint for VectorElementConstraint {
             return ConstraintState::Stable;
         };
 
-        let ownership = if element_kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+        let ownership = if element_kind.is_heap_kind() { self.heap_ownership } else { HeapOwnership::None };
         let mut progress = false;
         if context.update_binding_kind(self.target, element_kind) {
             progress = true;
@@ -1356,6 +2380,119 @@ impl Constraint for VectorElementConstraint {
     }
 }
 
+struct SequenceItemConstraint {
+    target: BindingId,
+    collection: BindingId,
+}
+
+impl SequenceItemConstraint {
+    fn new(target: BindingId, collection: BindingId) -> Self {
+        SequenceItemConstraint { target, collection }
+    }
+}
+
+impl Constraint for SequenceItemConstraint {
+    fn apply(&mut self, context: &mut ConstraintContext<'_>) -> ConstraintState {
+        let item_kind = match context.binding_kind(self.collection) {
+            ValueKind::String => Some(ValueKind::String),
+            ValueKind::Map => Some(ValueKind::Vector),
+            // An atom's value rides as its element kind
+            ValueKind::Vector | ValueKind::List | ValueKind::LazySeq | ValueKind::Atom => context.binding_vector_element_kind(self.collection),
+            ValueKind::Set => context.binding_set_element_kind(self.collection),
+            _ => None,
+        };
+        let Some(item_kind) = item_kind else {
+            return ConstraintState::Stable;
+        };
+
+        let ownership = if item_kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+        let mut progress = false;
+        if context.update_binding_kind(self.target, item_kind) {
+            progress = true;
+        }
+        if context.update_binding_ownership(self.target, ownership) {
+            progress = true;
+        }
+        if progress {
+            ConstraintState::Progress
+        } else {
+            ConstraintState::Stable
+        }
+    }
+}
+
+/// The result of a sequence function such as map or rest: lazy over a lazy sequence, a list
+/// otherwise. Collection updates (conj, pop, assoc) instead keep vectors, maps and sets.
+struct SequenceResultConstraint {
+    target: BindingId,
+    collection: BindingId,
+    element_kind: Option<ValueKind>,
+    keeps: &'static [ValueKind], // collection kinds the result shares; any other gives `otherwise`
+    otherwise: ValueKind,
+    // The kind last given to the target; a recursive function can feed the target back into its
+    // own collection, so each kind is applied once rather than fighting over the merged kind
+    applied: Option<ValueKind>,
+}
+
+impl SequenceResultConstraint {
+    fn new(target: BindingId, collection: BindingId, element_kind: Option<ValueKind>) -> Self {
+        SequenceResultConstraint {
+            target,
+            collection,
+            element_kind,
+            keeps: &[ValueKind::LazySeq],
+            otherwise: ValueKind::List,
+            applied: None,
+        }
+    }
+
+    fn preserving(target: BindingId, collection: BindingId, element_kind: Option<ValueKind>) -> Self {
+        SequenceResultConstraint {
+            keeps: &[ValueKind::Vector, ValueKind::Map, ValueKind::Set],
+            ..Self::new(target, collection, element_kind)
+        }
+    }
+
+    /// `subs` slices vectors into vectors and anything else into strings
+    fn slicing(target: BindingId, collection: BindingId, element_kind: Option<ValueKind>) -> Self {
+        SequenceResultConstraint {
+            keeps: &[ValueKind::Vector],
+            otherwise: ValueKind::String,
+            ..Self::new(target, collection, element_kind)
+        }
+    }
+}
+
+impl Constraint for SequenceResultConstraint {
+    fn apply(&mut self, context: &mut ConstraintContext<'_>) -> ConstraintState {
+        let kind = match context.binding_kind(self.collection) {
+            ValueKind::Any => return ConstraintState::Stable,
+            kind if self.keeps.contains(&kind) => kind,
+            _ => self.otherwise,
+        };
+        if self.applied == Some(kind) {
+            return ConstraintState::Stable;
+        }
+        self.applied = Some(kind);
+
+        let mut progress = false;
+        if context.update_binding_kind(self.target, kind) {
+            progress = true;
+        }
+        if context.update_binding_ownership(self.target, HeapOwnership::Owned) {
+            progress = true;
+        }
+        if context.update_vector_element_kind(self.target, self.element_kind) {
+            progress = true;
+        }
+        if progress {
+            ConstraintState::Progress
+        } else {
+            ConstraintState::Stable
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1419,6 +2556,21 @@ mod tests {
         assert_eq!(y_binding.value_kind, ValueKind::Number);
     }
 
+    #[test]
+    fn numeric_assoc_keys_keep_map_locals() {
+        let expr = parse_expr("(let [m {1 2} n (assoc m 5 7)] n)");
+        let summary = run_type_inference(std::slice::from_ref(&expr)).unwrap();
+        let analysis = summary.function(&FunctionKey::Program).unwrap();
+        for binding in &analysis.local_bindings {
+            assert_eq!(summary.binding(*binding).unwrap().value_kind, ValueKind::Map);
+        }
+
+        let shift = parse_expr("(defn shift [v] (assoc v 0 1))");
+        let summary = run_type_inference(std::slice::from_ref(&shift)).unwrap();
+        let analysis = summary.function(&FunctionKey::Named("shift".to_string())).unwrap();
+        assert_eq!(summary.binding(analysis.parameter_bindings[0]).unwrap().value_kind, ValueKind::Vector);
+    }
+
     #[test]
     fn annotates_function_returns_from_literals() {
         let mut domain = 0;
@@ -1431,6 +2583,47 @@ mod tests {
         assert_eq!(binding.value_kind, ValueKind::Number);
     }
 
+    #[test]
+    fn assigns_vector_kind_to_rest_parameter() {
+        let log = parse_expr("(defn log [level & parts] level)");
+        let call = parse_expr("(defn run [] (log 1 \"a\" \"b\"))");
+        let summary = run_type_inference(&[log, call]).unwrap();
+        let analysis = summary.function(&FunctionKey::Named("log".to_string())).unwrap();
+        assert!(analysis.variadic);
+        assert_eq!(analysis.parameter_bindings.len(), 2);
+
+        let level = summary.binding(analysis.parameter_bindings[0]).unwrap();
+        assert_eq!(level.value_kind, ValueKind::Number);
+
+        let rest = summary.binding(analysis.parameter_bindings[1]).unwrap();
+        match &rest.owner {
+            BindingOwner::Parameter { name, position, .. } => {
+                assert_eq!(name, "parts");
+                assert_eq!(*position, 1);
+            }
+            other => panic!("expected parameter binding, got {:?}", other),
+        }
+        assert_eq!(rest.value_kind, ValueKind::Vector);
+    }
+
+    #[test]
+    fn analyses_each_arity_separately() {
+        let f = parse_expr("(defn f ([x] (f x \"s\")) ([x y] y))");
+        let summary = run_type_inference(&[f]).unwrap();
+
+        let one = summary.function(&FunctionKey::Named("f/1".to_string())).unwrap();
+        assert_eq!(one.parameter_bindings.len(), 1);
+        let two = summary.function(&FunctionKey::Named("f/2".to_string())).unwrap();
+        assert_eq!(two.parameter_bindings.len(), 2);
+        assert!(summary.function(&FunctionKey::Named("f".to_string())).is_none());
+
+        // The self call in the one-argument arity resolves to the two-argument arity
+        let second = summary.binding(two.parameter_bindings[1]).unwrap();
+        assert_eq!(second.value_kind, ValueKind::String);
+        let one_return = summary.binding(one.return_binding.expect("missing return binding")).unwrap();
+        assert_eq!(one_return.value_kind, ValueKind::String);
+    }
+
     #[test]
     fn propagates_function_call_results() {
         let make = parse_expr("(defn make [] (str \"x\"))");
@@ -1515,6 +2708,38 @@ mod tests {
         assert_eq!(trimmed_binding.set_element_kind, Some(ValueKind::Number));
     }
 
+    #[test]
+    fn subs_of_a_vector_is_a_vector() {
+        let expr = parse_expr("(defn slices [] (let [v [1 2 3] tail (subs v 1) s \"abc\" text (subs s 1)] tail))");
+        let summary = run_type_inference(std::slice::from_ref(&expr)).unwrap();
+        let key = FunctionKey::Named("slices".to_string());
+        let analysis = summary.function(&key).unwrap();
+        let locals = &analysis.local_bindings;
+        let tail = summary.binding(locals[1]).unwrap();
+        assert_eq!(tail.value_kind, ValueKind::Vector);
+        assert_eq!(tail.vector_element_kind, Some(ValueKind::Number));
+        assert_eq!(summary.binding(locals[0]).unwrap().value_kind, ValueKind::Vector);
+        assert_eq!(summary.binding(locals[3]).unwrap().value_kind, ValueKind::String);
+    }
+
+    #[test]
+    fn set_algebra_tracks_member_kinds() {
+        let expr = parse_expr("(defn combine [] (let [s #{1 2} u (union s #{3}) d (difference #{:a} s) c (conj s 4) ok (subset? s u)] u))");
+        let summary = run_type_inference(std::slice::from_ref(&expr)).unwrap();
+        let key = FunctionKey::Named("combine".to_string());
+        let analysis = summary.function(&key).unwrap();
+        let locals = &analysis.local_bindings;
+        let union = summary.binding(locals[1]).unwrap();
+        assert_eq!(union.value_kind, ValueKind::Set);
+        assert_eq!(union.set_element_kind, Some(ValueKind::Number));
+        let difference = summary.binding(locals[2]).unwrap();
+        assert_eq!(difference.set_element_kind, Some(ValueKind::Keyword));
+        let conj = summary.binding(locals[3]).unwrap();
+        assert_eq!(conj.value_kind, ValueKind::Set);
+        assert_eq!(conj.set_element_kind, Some(ValueKind::Number));
+        assert_eq!(summary.binding(locals[4]).unwrap().value_kind, ValueKind::Boolean);
+    }
+
     #[test]
     fn vector_literal_metadata_is_recorded() {
         let expr = parse_expr("(defn make [] [1 2 3])");
@@ -1551,6 +2776,50 @@ mod tests {
         assert_eq!(binding.heap_ownership, HeapOwnership::Owned);
     }
 
+    #[test]
+    fn sequence_callbacks_receive_collection_items() {
+        let expressions = [
+            parse_expr("(defn shout [s] (str s \"!\"))"),
+            parse_expr("(defn total [xs] (count xs))"),
+            parse_expr("(defn -main [] (let [letters \"ab\"] (total (map shout letters))))"),
+        ];
+        let summary = run_type_inference(&expressions).unwrap();
+
+        let shout = summary.function(&FunctionKey::Named("shout".to_string())).unwrap();
+        let param = summary.binding(shout.parameter_bindings[0]).unwrap();
+        assert_eq!(param.value_kind, ValueKind::String);
+        assert_eq!(param.heap_ownership, HeapOwnership::Borrowed);
+
+        let total = summary.function(&FunctionKey::Named("total".to_string())).unwrap();
+        let param = summary.binding(total.parameter_bindings[0]).unwrap();
+        assert_eq!(param.value_kind, ValueKind::List);
+    }
+
+    #[test]
+    fn record_types_hold_only_when_every_assignment_agrees() {
+        let expressions = [
+            parse_expr("(defrecord Point [x y])"),
+            parse_expr("(defn norm [p] (+ (:x p) (:y p)))"),
+            parse_expr("(defn shift [p] (assoc p :x 0))"),
+            parse_expr("(defn loose [p] (:x p))"),
+            parse_expr("(defn passed [p] (:x p))"),
+            parse_expr("(defn -main [] (let [p (->Point 1 2) q (shift p)] (+ (norm q) (loose p) (loose {:x 1}) (count (map passed [p])))))"),
+        ];
+        let summary = run_type_inference(&expressions).unwrap();
+        let parameter = |name: &str| {
+            let analysis = summary.function(&FunctionKey::Named(name.to_string())).unwrap();
+            summary.binding_record_type(analysis.parameter_bindings[0])
+        };
+
+        assert_eq!(parameter("norm"), Some("Point"));
+        assert_eq!(parameter("shift"), Some("Point"));
+        let shift = summary.function(&FunctionKey::Named("shift".to_string())).unwrap();
+        assert_eq!(summary.binding_record_type(shift.return_binding.unwrap()), Some("Point"));
+        // A plain map argument, or a call inference cannot see, may not be a record
+        assert_eq!(parameter("loose"), None);
+        assert_eq!(parameter("passed"), None);
+    }
+
     #[test]
     fn solver_applies_constraints_until_stable() {
         let mut functions = HashMap::new();
@@ -1561,10 +2830,12 @@ mod tests {
             owner: BindingOwner::Return { function: FunctionKey::program() },
             ast_id: AstId::root(),
             value_kind: ValueKind::Any,
+            mixed_kind: false,
             heap_ownership: HeapOwnership::None,
             map_value_types: None,
             set_element_kind: None,
             vector_element_kind: None,
+            record_type: RecordType::Unknown,
         }];
         let constraints: Vec<Box<dyn Constraint>> = vec![Box::new(TestConstraint { id, fired: false })];
         let graph = BindingGraph { nodes, functions, constraints };
@@ -9,11 +9,23 @@
 ///    be inserted, plus which slots are guaranteed to be freed along *all* paths exiting the
 ///    region. For straight-line code we just track the last consumer; for branches we recurse into
 ///    the then/else blocks, taking the intersection of their `freed_everywhere` sets.
+///    `PushHandler` regions are planned like branches: the protected body and the catch block are
+///    the two paths, and the body never frees a slot the catch block reads. A `JumpTable` splits
+///    into one path per arm.
 /// 2. `apply_liveness_plan` rewrites the IR, splicing in the frees and patching jump offsets.
 ///
+/// A slot whose last use is the collection argument of a runtime helper with an in-place variant
+/// (`IN_PLACE_VARIANTS`, e.g. `_vector_conj`) is handed to that variant instead of being freed:
+/// the helper reuses the dead value's block and the slot is cleared after the call. Cloning a dead
+/// vector, to return it or pass it to a function that takes over its argument, hands it over the
+/// same way. A slot whose last use is the collection argument of a helper that walks it in order
+/// (`LAST_READ_VARIANTS`, e.g. `_seq_nth`) is still freed after the call, but the call is renamed
+/// to a variant that lets a lazy sequence drop its items as the walk passes them.
+///
 /// Any slots still owned after liveness gets a plan are freed by the surrounding scope
 /// (e.g. `compile_let`), but most of the work happens here so we avoid double-frees and ensure
 /// borrowed values are not released prematurely.
+use super::ValueKind;
 use crate::ir::IRInstruction;
 use std::collections::{HashMap, HashSet};
 
@@ -21,26 +33,76 @@ use std::collections::{HashMap, HashSet};
 pub struct LivenessPlan {
     pub insert_after: HashMap<usize, Vec<usize>>, // instruction index -> slots to free after executing it
     pub freed_everywhere: HashSet<usize>,         // slots guaranteed freed along all paths exiting the analysed range
+    pub handoffs: HashMap<usize, usize>,          // instruction index -> slot consumed by the in-place variant of that call
+    pub last_reads: HashSet<usize>,               // instruction indices of calls renamed to their last-read variant
+}
+
+impl LivenessPlan {
+    pub fn is_empty(&self) -> bool {
+        self.insert_after.is_empty() && self.handoffs.is_empty() && self.last_reads.is_empty()
+    }
+}
+
+/// Runtime helpers that copy their first argument, paired with a variant that takes it over
+const IN_PLACE_VARIANTS: &[(&str, &str)] = &[
+    ("_vector_conj", "_vector_conj_owned"),
+    ("_vector_assoc", "_vector_assoc_owned"),
+    ("_vector_pop", "_vector_pop_owned"),
+    ("_vector_clone", "_vector_clone_owned"),
+];
+
+fn in_place_variant(name: &str) -> Option<&'static str> {
+    IN_PLACE_VARIANTS.iter().find(|(copying, _)| *copying == name).map(|(_, in_place)| *in_place)
+}
+
+/// Runtime helpers that walk a collection argument in order, with the argument's position (its tag
+/// follows it), paired with a variant for the last read of a lazy sequence
+const LAST_READ_VARIANTS: &[(&str, usize, &str)] = &[
+    ("_seq_nth", 0, "_seq_nth_last_read"),
+    ("_seq_reduce", 3, "_seq_reduce_last_read"),
+    ("_seq_reduce_first", 2, "_seq_reduce_first_last_read"),
+];
+
+fn last_read_variant(name: &str) -> Option<(usize, &'static str)> {
+    LAST_READ_VARIANTS.iter().find(|(reading, _, _)| *reading == name).map(|(_, position, last_read)| (*position, *last_read))
 }
 
 pub fn compute_liveness_plan(instructions: &[IRInstruction], tracked_slots: &HashSet<usize>) -> LivenessPlan {
-    plan_range(instructions, tracked_slots, 0, instructions.len())
+    compute_liveness_plan_from(instructions, tracked_slots, 0)
+}
+
+/// `compute_liveness_plan` for slots that only hold their value from `start` on, e.g. a `let`
+/// binding stored there; earlier reads of the same slot number belong to an older value.
+pub fn compute_liveness_plan_from(instructions: &[IRInstruction], tracked_slots: &HashSet<usize>, start: usize) -> LivenessPlan {
+    plan_range(instructions, tracked_slots, start, instructions.len())
 }
 
 pub fn apply_liveness_plan<F>(original: Vec<IRInstruction>, plan: &LivenessPlan, mut emit_free: F) -> Vec<IRInstruction>
 where
     F: FnMut(&mut Vec<IRInstruction>, usize),
 {
-    if plan.insert_after.is_empty() {
+    if plan.is_empty() {
         return original;
     }
 
-    let mut new_instructions = Vec::with_capacity(original.len() + plan.insert_after.len());
+    let mut new_instructions = Vec::with_capacity(original.len() + plan.insert_after.len() + plan.handoffs.len() * 2);
     let mut index_map = Vec::with_capacity(original.len());
 
     for (idx, inst) in original.into_iter().enumerate() {
         index_map.push(new_instructions.len());
-        new_instructions.push(inst);
+        match (inst, plan.handoffs.get(&idx)) {
+            (IRInstruction::RuntimeCall(name, arg_count), Some(&slot)) => {
+                let in_place = in_place_variant(&name).map_or(name, str::to_string);
+                new_instructions.push(IRInstruction::RuntimeCall(in_place, arg_count));
+                new_instructions.push(IRInstruction::Push(0));
+                new_instructions.push(IRInstruction::StoreLocal(slot));
+            }
+            (IRInstruction::RuntimeCall(name, arg_count), None) if plan.last_reads.contains(&idx) => {
+                let last_read = last_read_variant(&name).map_or(name, |(_, variant)| variant.to_string());
+                new_instructions.push(IRInstruction::RuntimeCall(last_read, arg_count));
+            }
+            (inst, _) => new_instructions.push(inst),
+        }
         if let Some(slots) = plan.insert_after.get(&idx) {
             for slot in slots {
                 emit_free(&mut new_instructions, *slot);
@@ -51,16 +113,19 @@ where
     let original_len = index_map.len();
     let final_len = new_instructions.len();
 
+    let remap = |target: &mut usize| {
+        if *target == original_len {
+            *target = final_len;
+        } else if let Some(&mapped) = index_map.get(*target) {
+            *target = mapped;
+        }
+    };
+
     // Adjust jump targets to account for inserted instructions
     for inst in &mut new_instructions {
         match inst {
-            IRInstruction::Jump(target) | IRInstruction::JumpIfZero(target) => {
-                if *target == original_len {
-                    *target = final_len;
-                } else if let Some(&mapped) = index_map.get(*target) {
-                    *target = mapped;
-                }
-            }
+            IRInstruction::Jump(target) | IRInstruction::JumpIfZero(target) | IRInstruction::PushHandler(target) => remap(target),
+            IRInstruction::JumpTable(targets) => targets.iter_mut().for_each(remap),
             _ => {}
         }
     }
@@ -73,6 +138,13 @@ fn plan_range(instructions: &[IRInstruction], tracked_slots: &HashSet<usize>, st
         return LivenessPlan::default();
     }
 
+    if let Some((table_idx, IRInstruction::JumpTable(targets))) = find_branch(instructions, start, end).map(|(idx, _)| (idx, &instructions[idx])) {
+        if let Some(plan) = plan_table(instructions, tracked_slots, start, end, table_idx, targets) {
+            return plan;
+        }
+        return plan_linear_range(instructions, tracked_slots, start, end);
+    }
+
     if let Some((jump_if_idx, else_start)) = find_branch(instructions, start, end) {
         let jump_idx = else_start.saturating_sub(1);
         if jump_idx >= end {
@@ -82,32 +154,41 @@ fn plan_range(instructions: &[IRInstruction], tracked_slots: &HashSet<usize>, st
         if let IRInstruction::Jump(end_pos) = instructions[jump_idx] {
             let mut result = LivenessPlan::default();
 
-            let prefix_plan = plan_range(instructions, tracked_slots, start, jump_if_idx + 1);
+            // The condition must not release slots that the branches or the code after them read
+            let prefix_plan = plan_range(instructions, &not_used_in(instructions, tracked_slots, jump_if_idx + 1, end), start, jump_if_idx + 1);
             merge_plan(&mut result, prefix_plan.clone(), true);
 
             let remaining_after_prefix: HashSet<usize> = tracked_slots.iter().filter(|slot| !result.freed_everywhere.contains(slot)).copied().collect();
 
-            if !remaining_after_prefix.is_empty() {
-                let then_plan = plan_range(instructions, &remaining_after_prefix, jump_if_idx + 1, else_start);
+            // Slots read after the branch stay alive through it
+            let branch_slots = not_used_in(instructions, &remaining_after_prefix, end_pos, end);
+
+            if !branch_slots.is_empty() {
+                // A handler's landing can run after any prefix of the protected body, so the body
+                // must not release slots that the catch block still reads.
+                let then_slots: HashSet<usize> = if matches!(instructions[jump_if_idx], IRInstruction::PushHandler(_)) {
+                    let catch_used = collect_slot_usage(instructions, &branch_slots, else_start, end_pos);
+                    branch_slots.difference(&catch_used).copied().collect()
+                } else {
+                    branch_slots.clone()
+                };
+                let then_plan = plan_range(instructions, &then_slots, jump_if_idx + 1, else_start);
                 merge_plan(&mut result, then_plan.clone(), false);
 
-                let else_plan = plan_range(instructions, &remaining_after_prefix, else_start, end_pos);
+                let else_plan = plan_range(instructions, &branch_slots, else_start, end_pos);
                 merge_plan(&mut result, else_plan.clone(), false);
 
                 let branch_freed: HashSet<usize> = then_plan.freed_everywhere.intersection(&else_plan.freed_everywhere).copied().collect();
 
                 result.freed_everywhere.extend(branch_freed.iter().copied());
+            }
 
-                let remaining_after_branch: HashSet<usize> = remaining_after_prefix.into_iter().filter(|slot| !result.freed_everywhere.contains(slot)).collect();
-
-                if !remaining_after_branch.is_empty() {
-                    let suffix_plan = plan_range(instructions, &remaining_after_branch, end_pos, end);
-                    merge_plan(&mut result, suffix_plan, true);
-                }
-            } else if end_pos < end {
-                let suffix_plan = plan_range(instructions, &HashSet::new(), end_pos, end);
+            let remaining_after_branch: HashSet<usize> = remaining_after_prefix.into_iter().filter(|slot| !result.freed_everywhere.contains(slot)).collect();
+            if !remaining_after_branch.is_empty() && end_pos < end {
+                let suffix_plan = plan_range(instructions, &remaining_after_branch, end_pos, end);
                 merge_plan(&mut result, suffix_plan, true);
             }
+            free_loaded_before_branch(&mut result, instructions, &remaining_after_branch, start, jump_if_idx, end_pos, end);
 
             return result;
         }
@@ -129,10 +210,18 @@ fn plan_linear_range(instructions: &[IRInstruction], tracked_slots: &HashSet<usi
         return plan;
     }
 
-    let last_use_map = collect_last_uses_straight_line(instructions, &slots_used, start, end);
+    let (last_use_map, variant_candidates) = collect_last_uses_straight_line(instructions, &slots_used, start, end);
 
     for (slot, idx) in last_use_map {
-        plan.insert_after.entry(idx).or_default().push(slot);
+        let candidate = variant_candidates.get(&idx) == Some(&slot);
+        if candidate && in_place_variant(call_name(&instructions[idx])).is_some() {
+            plan.handoffs.insert(idx, slot);
+        } else {
+            if candidate {
+                plan.last_reads.insert(idx);
+            }
+            plan.insert_after.entry(idx).or_default().push(slot);
+        }
         plan.freed_everywhere.insert(slot);
     }
 
@@ -143,6 +232,8 @@ fn merge_plan(target: &mut LivenessPlan, other: LivenessPlan, sequential: bool)
     for (idx, slots) in other.insert_after {
         target.insert_after.entry(idx).or_default().extend(slots);
     }
+    target.handoffs.extend(other.handoffs);
+    target.last_reads.extend(other.last_reads);
     if sequential {
         target.freed_everywhere.extend(other.freed_everywhere);
     }
@@ -150,15 +241,139 @@ fn merge_plan(target: &mut LivenessPlan, other: LivenessPlan, sequential: bool)
 
 fn find_branch(instructions: &[IRInstruction], start: usize, end: usize) -> Option<(usize, usize)> {
     for idx in start..end {
-        if let IRInstruction::JumpIfZero(target) = instructions[idx] {
-            if target > idx && target <= end {
-                return Some((idx, target));
-            }
+        match &instructions[idx] {
+            IRInstruction::JumpIfZero(target) | IRInstruction::PushHandler(target) if *target > idx && *target <= end => return Some((idx, *target)),
+            IRInstruction::JumpTable(_) => return Some((idx, idx + 1)),
+            _ => {}
         }
     }
     None
 }
 
+/// Plan a `JumpTable` dispatch. The fall-through code after the table is the default arm and
+/// ends with a `Jump` past the last arm; every table target starts another arm. Each arm is
+/// planned like a branch, so only slots freed in all of them count as freed everywhere.
+fn plan_table(instructions: &[IRInstruction], tracked_slots: &HashSet<usize>, start: usize, end: usize, table_idx: usize, targets: &[usize]) -> Option<LivenessPlan> {
+    let default_start = table_idx + 1;
+    let mut arm_starts: Vec<usize> = targets.iter().copied().chain(std::iter::once(default_start)).collect();
+    arm_starts.sort_unstable();
+    arm_starts.dedup();
+
+    if arm_starts[0] != default_start {
+        return None;
+    }
+    let end_pos = match arm_starts.get(1) {
+        Some(first_arm) => match instructions[first_arm - 1] {
+            IRInstruction::Jump(end_pos) => end_pos,
+            _ => return None,
+        },
+        None => return None,
+    };
+    if end_pos > end || arm_starts.iter().any(|arm_start| *arm_start >= end_pos) {
+        return None;
+    }
+
+    let mut result = LivenessPlan::default();
+    let prefix_plan = plan_range(instructions, &not_used_in(instructions, tracked_slots, table_idx + 1, end), start, table_idx + 1);
+    merge_plan(&mut result, prefix_plan, true);
+
+    let remaining_after_prefix: HashSet<usize> = tracked_slots.iter().filter(|slot| !result.freed_everywhere.contains(slot)).copied().collect();
+    let arm_slots = not_used_in(instructions, &remaining_after_prefix, end_pos, end);
+
+    let mut arm_freed: Option<HashSet<usize>> = None;
+    for (idx, arm_start) in arm_starts.iter().enumerate() {
+        let arm_end = arm_starts.get(idx + 1).copied().unwrap_or(end_pos);
+        let arm_plan = plan_range(instructions, &arm_slots, *arm_start, arm_end);
+        let freed = arm_plan.freed_everywhere.clone();
+        merge_plan(&mut result, arm_plan, false);
+        arm_freed = Some(match arm_freed {
+            Some(previous) => previous.intersection(&freed).copied().collect(),
+            None => freed,
+        });
+    }
+    result.freed_everywhere.extend(arm_freed.unwrap_or_default());
+
+    let remaining_after_table: HashSet<usize> = remaining_after_prefix.into_iter().filter(|slot| !result.freed_everywhere.contains(slot)).collect();
+    if end_pos < end {
+        let suffix_plan = plan_range(instructions, &remaining_after_table, end_pos, end);
+        merge_plan(&mut result, suffix_plan, true);
+    }
+    free_loaded_before_branch(&mut result, instructions, &remaining_after_table, start, table_idx, end_pos, end);
+
+    Some(result)
+}
+
+/// Free the slots of `remaining` whose value was loaded before the branch at `branch_idx` and is
+/// consumed after it with no later read, e.g. an argument ahead of an `if` among a call's
+/// arguments. Neither the prefix nor the suffix plan sees both the load and the consumer, so
+/// without this such a slot is never freed.
+fn free_loaded_before_branch(result: &mut LivenessPlan, instructions: &[IRInstruction], remaining: &HashSet<usize>, start: usize, branch_idx: usize, end_pos: usize, end: usize) {
+    let read_later = collect_slot_usage(instructions, remaining, branch_idx, end);
+    let pending: HashSet<usize> = remaining.iter().filter(|slot| !result.freed_everywhere.contains(slot) && !read_later.contains(slot)).copied().collect();
+    if pending.is_empty() {
+        return;
+    }
+    for (slot, idx) in consumers_on_first_path(instructions, &pending, start, branch_idx, end) {
+        if idx >= end_pos {
+            result.insert_after.entry(idx).or_default().push(slot);
+            result.freed_everywhere.insert(slot);
+        }
+    }
+}
+
+/// The instructions consuming the values of `slots` loaded before `branch_idx`, found by following
+/// the first arm of every branch from `start`; all arms leave the stack alike, so the consumers
+/// after a branch are the same whichever arm runs.
+fn consumers_on_first_path(instructions: &[IRInstruction], slots: &HashSet<usize>, start: usize, branch_idx: usize, end: usize) -> HashMap<usize, usize> {
+    let mut stack: Vec<Option<usize>> = Vec::new();
+    let mut consumers = HashMap::new();
+    let mut idx = start;
+    while idx < end {
+        let (pops, pushes) = match instructions[idx] {
+            IRInstruction::LoadLocal(slot) | IRInstruction::PushLocalAddress(slot) => {
+                stack.push((idx < branch_idx && slots.contains(&slot)).then_some(slot));
+                (0, 0)
+            }
+            IRInstruction::LoadParam(_) | IRInstruction::Push(_) | IRInstruction::PushString(_) | IRInstruction::PushFunction(_) | IRInstruction::Allocate(_) => (0, 1),
+            IRInstruction::StoreLocal(_) | IRInstruction::JumpIfZero(_) | IRInstruction::JumpTable(_) | IRInstruction::Return => (1, 0),
+            IRInstruction::RuntimeCall(_, arg_count) | IRInstruction::Call(_, arg_count) => (arg_count, 1),
+            IRInstruction::Add
+            | IRInstruction::Sub
+            | IRInstruction::Mul
+            | IRInstruction::Div
+            | IRInstruction::UncheckedAdd
+            | IRInstruction::UncheckedSub
+            | IRInstruction::UncheckedMul
+            | IRInstruction::Equal
+            | IRInstruction::Less
+            | IRInstruction::Greater
+            | IRInstruction::LessEqual
+            | IRInstruction::GreaterEqual => (2, 1),
+            IRInstruction::Not | IRInstruction::Free | IRInstruction::LoadField(_) => (1, 1),
+            IRInstruction::Jump(target) if target > idx => {
+                idx = target;
+                continue;
+            }
+            IRInstruction::Jump(_) | IRInstruction::PushHandler(_) | IRInstruction::PopHandler => (0, 0),
+            IRInstruction::FreeLocal(_) | IRInstruction::FreeLocalWithRuntime(_, _) | IRInstruction::DefineFunction(_, _, _) | IRInstruction::InitHeap => (0, 0),
+        };
+        for _ in 0..pops {
+            if let Some(Some(slot)) = stack.pop() {
+                consumers.insert(slot, idx);
+            }
+        }
+        stack.extend(std::iter::repeat_n(None, pushes));
+        idx += 1;
+    }
+    consumers
+}
+
+/// Tracked slots that are not read in `start..end`, i.e. that may be freed before that range
+fn not_used_in(instructions: &[IRInstruction], tracked: &HashSet<usize>, start: usize, end: usize) -> HashSet<usize> {
+    let used = collect_slot_usage(instructions, tracked, start, end);
+    tracked.difference(&used).copied().collect()
+}
+
 fn collect_slot_usage(instructions: &[IRInstruction], tracked: &HashSet<usize>, start: usize, end: usize) -> HashSet<usize> {
     let mut used = HashSet::new();
     for idx in start..end {
@@ -178,12 +393,16 @@ fn collect_slot_usage(instructions: &[IRInstruction], tracked: &HashSet<usize>,
 enum StackEntry {
     LocalValue(usize),
     LocalAddress(usize),
+    Constant(i64),
     Other,
 }
 
-fn collect_last_uses_straight_line(instructions: &[IRInstruction], tracked: &HashSet<usize>, start: usize, end: usize) -> HashMap<usize, usize> {
+/// The last consuming instruction of each tracked slot, plus the calls with an in-place or
+/// last-read variant whose collection argument is a tracked slot passed nowhere else in that call
+fn collect_last_uses_straight_line(instructions: &[IRInstruction], tracked: &HashSet<usize>, start: usize, end: usize) -> (HashMap<usize, usize>, HashMap<usize, usize>) {
     let mut stack: Vec<StackEntry> = Vec::new();
     let mut last_use: HashMap<usize, usize> = HashMap::new();
+    let mut variant_candidates: HashMap<usize, usize> = HashMap::new();
 
     for offset in 0..(end - start) {
         let idx = start + offset;
@@ -202,12 +421,31 @@ fn collect_last_uses_straight_line(instructions: &[IRInstruction], tracked: &Has
                     stack.push(StackEntry::Other);
                 }
             }
-            IRInstruction::LoadParam(_) | IRInstruction::Push(_) | IRInstruction::PushString(_) | IRInstruction::Allocate(_) => {
+            IRInstruction::Push(value) => stack.push(StackEntry::Constant(value)),
+            IRInstruction::LoadParam(_) | IRInstruction::PushString(_) | IRInstruction::PushFunction(_) | IRInstruction::Allocate(_) => {
                 stack.push(StackEntry::Other);
             }
-            IRInstruction::StoreLocal(_) => {
+            IRInstruction::StoreLocal(_) | IRInstruction::JumpTable(_) => {
                 stack.pop();
             }
+            IRInstruction::RuntimeCall(ref name, arg_count) if arg_count > 0 && collection_position(name).is_some_and(|position| position < arg_count) => {
+                let args = stack.split_off(stack.len().saturating_sub(arg_count));
+                let position = collection_position(name).unwrap_or(0);
+                // A last read only matters to a collection tagged as a lazy sequence
+                let lazy_tag = matches!(args.get(position + 1), Some(StackEntry::Constant(tag)) if *tag == ValueKind::LazySeq.runtime_tag());
+                if let Some(StackEntry::LocalValue(slot)) = args.get(position).copied() {
+                    let passed_once = !args
+                        .iter()
+                        .enumerate()
+                        .any(|(index, entry)| index != position && matches!(entry, StackEntry::LocalValue(other) | StackEntry::LocalAddress(other) if *other == slot));
+                    if passed_once && (lazy_tag || in_place_variant(name).is_some()) {
+                        variant_candidates.insert(idx, slot);
+                    }
+                }
+                stack.extend(args);
+                consume_stack_entries(&mut stack, arg_count, &mut last_use, idx, tracked);
+                stack.push(StackEntry::Other);
+            }
             IRInstruction::RuntimeCall(_, arg_count) | IRInstruction::Call(_, arg_count) => {
                 consume_stack_entries(&mut stack, arg_count, &mut last_use, idx, tracked);
                 stack.push(StackEntry::Other);
@@ -216,6 +454,9 @@ fn collect_last_uses_straight_line(instructions: &[IRInstruction], tracked: &Has
             | IRInstruction::Sub
             | IRInstruction::Mul
             | IRInstruction::Div
+            | IRInstruction::UncheckedAdd
+            | IRInstruction::UncheckedSub
+            | IRInstruction::UncheckedMul
             | IRInstruction::Equal
             | IRInstruction::Less
             | IRInstruction::Greater
@@ -224,11 +465,11 @@ fn collect_last_uses_straight_line(instructions: &[IRInstruction], tracked: &Has
                 consume_stack_entries(&mut stack, 2, &mut last_use, idx, tracked);
                 stack.push(StackEntry::Other);
             }
-            IRInstruction::Not | IRInstruction::Free => {
+            IRInstruction::Not | IRInstruction::Free | IRInstruction::LoadField(_) => {
                 consume_stack_entries(&mut stack, 1, &mut last_use, idx, tracked);
                 stack.push(StackEntry::Other);
             }
-            IRInstruction::Jump(_) | IRInstruction::JumpIfZero(_) => {}
+            IRInstruction::Jump(_) | IRInstruction::JumpIfZero(_) | IRInstruction::PushHandler(_) | IRInstruction::PopHandler => {}
             IRInstruction::Return => {
                 stack.pop();
             }
@@ -236,7 +477,27 @@ fn collect_last_uses_straight_line(instructions: &[IRInstruction], tracked: &Has
         }
     }
 
-    last_use
+    // A value still on the stack is consumed after the range (e.g. an argument loaded before a
+    // branch), so its slot must outlive it
+    for entry in stack {
+        if let StackEntry::LocalValue(slot) | StackEntry::LocalAddress(slot) = entry {
+            last_use.remove(&slot);
+        }
+    }
+
+    (last_use, variant_candidates)
+}
+
+/// Where the collection argument of a helper with an in-place or last-read variant goes
+fn collection_position(name: &str) -> Option<usize> {
+    in_place_variant(name).map(|_| 0).or_else(|| last_read_variant(name).map(|(position, _)| position))
+}
+
+fn call_name(instruction: &IRInstruction) -> &str {
+    match instruction {
+        IRInstruction::RuntimeCall(name, _) => name,
+        _ => "",
+    }
 }
 
 fn consume_stack_entries(stack: &mut Vec<StackEntry>, count: usize, last_use: &mut HashMap<usize, usize>, idx: usize, tracked: &HashSet<usize>) {
@@ -247,7 +508,7 @@ fn consume_stack_entries(stack: &mut Vec<StackEntry>, count: usize, last_use: &m
                     last_use.insert(slot, idx);
                 }
             }
-            Some(StackEntry::Other) | None => {}
+            Some(StackEntry::Constant(_)) | Some(StackEntry::Other) | None => {}
         }
     }
 }
@@ -292,6 +553,182 @@ mod tests {
         assert!(plan.freed_everywhere.is_empty());
     }
 
+    #[test]
+    fn argument_loaded_before_branch_is_freed_after_its_call() {
+        // (f (rest xs) (if c 1 2)) with the rest in slot 0
+        let instructions = vec![
+            IRInstruction::LoadLocal(0),
+            IRInstruction::LoadParam(0),
+            IRInstruction::JumpIfZero(5),
+            IRInstruction::Push(1),
+            IRInstruction::Jump(6),
+            IRInstruction::Push(2),
+            IRInstruction::Call("f".to_string(), 2),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        assert_eq!(plan.insert_after.get(&6).map(|slots| slots.as_slice()), Some(&[0][..]));
+        assert!(plan.freed_everywhere.contains(&0));
+    }
+
+    #[test]
+    fn handler_body_keeps_slots_read_by_catch() {
+        let instructions = vec![
+            IRInstruction::PushHandler(5),
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("foo".to_string(), 1),
+            IRInstruction::PopHandler,
+            IRInstruction::Jump(8),
+            IRInstruction::StoreLocal(2),
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("bar".to_string(), 1),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        assert!(!plan.insert_after.contains_key(&2));
+        assert_eq!(plan.insert_after.get(&7).map(|slots| slots.as_slice()), Some(&[0][..]));
+        assert!(plan.freed_everywhere.is_empty());
+    }
+
+    #[test]
+    fn condition_keeps_slots_read_by_branches() {
+        let instructions = vec![
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("foo".to_string(), 1),
+            IRInstruction::JumpIfZero(5),
+            IRInstruction::Push(1),
+            IRInstruction::Jump(7),
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("bar".to_string(), 1),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        assert!(!plan.insert_after.contains_key(&1));
+        assert_eq!(plan.insert_after.get(&6).map(|slots| slots.as_slice()), Some(&[0][..]));
+    }
+
+    #[test]
+    fn jump_table_frees_per_arm() {
+        let instructions = vec![
+            IRInstruction::Push(0),
+            IRInstruction::JumpTable(vec![4, 7]),
+            IRInstruction::Push(0),
+            IRInstruction::Jump(10),
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("foo".to_string(), 1),
+            IRInstruction::Jump(10),
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("bar".to_string(), 1),
+            IRInstruction::Jump(10),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        assert_eq!(plan.insert_after.get(&5).map(|slots| slots.as_slice()), Some(&[0][..]));
+        assert_eq!(plan.insert_after.get(&8).map(|slots| slots.as_slice()), Some(&[0][..]));
+        // The default arm never touches the slot, so it is not freed on every path
+        assert!(plan.freed_everywhere.is_empty());
+    }
+
+    #[test]
+    fn dead_vector_is_handed_to_in_place_variant() {
+        let instructions = vec![
+            IRInstruction::LoadLocal(0),
+            IRInstruction::Push(1),
+            IRInstruction::Push(1),
+            IRInstruction::RuntimeCall("_vector_conj".to_string(), 3),
+            IRInstruction::LoadLocal(1),
+            IRInstruction::LoadLocal(1),
+            IRInstruction::Push(1),
+            IRInstruction::RuntimeCall("_vector_conj".to_string(), 3),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0, 1].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        assert_eq!(plan.handoffs.get(&3), Some(&0));
+        // Slot 1 is also the appended value, so it is freed after a copying call instead
+        assert!(!plan.handoffs.contains_key(&7));
+        assert_eq!(plan.insert_after.get(&7).map(|slots| slots.as_slice()), Some(&[1][..]));
+        assert!(plan.freed_everywhere.contains(&0));
+
+        let rewritten = apply_liveness_plan(instructions, &plan, |insts, slot| insts.push(IRInstruction::FreeLocal(slot)));
+        assert_eq!(rewritten[3], IRInstruction::RuntimeCall("_vector_conj_owned".to_string(), 3));
+        assert_eq!(rewritten[4], IRInstruction::Push(0));
+        assert_eq!(rewritten[5], IRInstruction::StoreLocal(0));
+        assert_eq!(rewritten[9], IRInstruction::RuntimeCall("_vector_conj".to_string(), 3));
+        assert_eq!(rewritten[10], IRInstruction::FreeLocal(1));
+    }
+
+    #[test]
+    fn copy_of_dead_vector_becomes_a_handoff() {
+        let instructions = vec![
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("_vector_clone".to_string(), 1),
+            IRInstruction::Call("grow".to_string(), 1),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        assert_eq!(plan.handoffs.get(&1), Some(&0));
+
+        let rewritten = apply_liveness_plan(instructions, &plan, |insts, slot| insts.push(IRInstruction::FreeLocal(slot)));
+        assert_eq!(rewritten[1], IRInstruction::RuntimeCall("_vector_clone_owned".to_string(), 1));
+        assert!(!rewritten.contains(&IRInstruction::FreeLocal(0)));
+    }
+
+    #[test]
+    fn last_read_of_lazy_slot_streams() {
+        let nth = |slot, tag| {
+            vec![
+                IRInstruction::LoadLocal(slot),
+                IRInstruction::Push(tag),
+                IRInstruction::Push(5),
+                IRInstruction::Push(0),
+                IRInstruction::Push(0),
+                IRInstruction::RuntimeCall("_seq_nth".to_string(), 5),
+            ]
+        };
+        let lazy = ValueKind::LazySeq.runtime_tag();
+        let mut instructions = nth(0, lazy);
+        instructions.extend(nth(0, lazy));
+        instructions.extend(nth(1, ValueKind::Vector.runtime_tag()));
+        instructions.push(IRInstruction::Return);
+        let tracked: HashSet<usize> = [0, 1].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        // Only the second read of the sequence is its last; a vector has nothing to stream
+        assert_eq!(plan.last_reads, [11].into_iter().collect());
+        assert_eq!(plan.insert_after.get(&11).map(|slots| slots.as_slice()), Some(&[0][..]));
+
+        let rewritten = apply_liveness_plan(instructions, &plan, |insts, slot| insts.push(IRInstruction::FreeLocal(slot)));
+        assert_eq!(rewritten[5], IRInstruction::RuntimeCall("_seq_nth".to_string(), 5));
+        assert_eq!(rewritten[11], IRInstruction::RuntimeCall("_seq_nth_last_read".to_string(), 5));
+        assert_eq!(rewritten[12], IRInstruction::FreeLocal(0));
+        assert_eq!(rewritten[18], IRInstruction::RuntimeCall("_seq_nth".to_string(), 5));
+    }
+
+    #[test]
+    fn condition_keeps_slots_still_on_the_stack() {
+        let instructions = vec![
+            IRInstruction::LoadLocal(0),
+            IRInstruction::LoadLocal(0),
+            IRInstruction::RuntimeCall("foo".to_string(), 1),
+            IRInstruction::JumpIfZero(6),
+            IRInstruction::Push(1),
+            IRInstruction::Jump(7),
+            IRInstruction::Push(0),
+            IRInstruction::RuntimeCall("bar".to_string(), 2),
+            IRInstruction::Return,
+        ];
+        let tracked: HashSet<usize> = [0].into_iter().collect();
+        let plan = compute_liveness_plan(&instructions, &tracked);
+        // The first load is only consumed by `bar`, so the condition must not free the slot
+        assert!(!plan.insert_after.contains_key(&2));
+        assert_eq!(plan.insert_after.get(&7).map(|slots| slots.as_slice()), Some(&[0][..]));
+    }
+
     #[test]
     fn unused_tracked_slots_yield_empty_plan() {
         let instructions = vec![IRInstruction::Push(1), IRInstruction::Return];
@@ -0,0 +1,221 @@
+/// Map operations: keys, vals, merge, merge-with, select-keys, zipmap, find and map-invert
+///
+/// Each form lowers to a `_map_*` runtime helper that builds a new map, list or `[key value]`
+/// vector sharing the keys and values of its arguments, so the result keeps the retained slots of
+/// its sources alive and owned arguments are released after the call. Nil is accepted wherever a
+/// map is, as an empty map.
+///
+/// Literal key types follow the values: `merge` keeps what is known about the last map (whose
+/// entries win) and `select-keys` what is known about the selected literal keys.
+use super::{
+    builtins::{literal_map_key, resolve_map_key_kind, resolve_value_kind},
+    compile_node, extend_with_offset,
+    sequences::{compile_source, resolve_callback, shared_result},
+    slots::SlotTracker,
+    types::map_value_type,
+    CompileContext, CompileError, CompileResult, MapKeyLiteral, MapValueTypes, RetainedSlot, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Compile a map argument onto the stack, returning its literal key types. An owned map is
+/// released by `tracker` after its last use; the slots keeping its entries alive move into
+/// `retained_slots`.
+fn compile_map_source(
+    op: &str,
+    node: &Node,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<Option<MapValueTypes>, CompileError> {
+    let mut result = compile_node(node, context, program)?;
+    let kind = resolve_value_kind(node, result.kind, context);
+    if !matches!(kind, ValueKind::Map | ValueKind::Nil | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression(format!("{} requires a map or nil", op)));
+    }
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+    retained_slots.extend(result.take_retained_slots());
+    tracker.track_if_owned(instructions, context, result.heap_ownership, ValueKind::Map);
+    Ok(result.map_value_types)
+}
+
+/// Compile keys or vals (a list of a map's keys or values, in entry order)
+pub(super) fn compile_map_column(op: &str, runtime: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError(op.to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    compile_map_source(op, &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots))
+}
+
+/// Compile map-invert (a map from each value to its key)
+pub(super) fn compile_map_invert(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("map-invert".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    compile_map_source("map-invert", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::RuntimeCall("_map_invert".to_string(), 1));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots))
+}
+
+/// Compile merge (later maps' entries replace earlier ones; a single map is returned as it is)
+pub(super) fn compile_merge(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    match args {
+        [] => return Ok(CompileResult::with_instructions(vec![IRInstruction::Push(0)], ValueKind::Nil)),
+        [single] => return compile_node(single, context, program),
+        _ => {}
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    compile_map_source("merge", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let mut map_value_types = None;
+    for (index, map) in args[1..].iter().enumerate() {
+        if index > 0 {
+            tracker.track_owned(&mut instructions, context, ValueKind::Map);
+        }
+        map_value_types = compile_map_source("merge", map, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+        instructions.push(IRInstruction::RuntimeCall("_map_merge".to_string(), 2));
+    }
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    // Keys of earlier maps the last one lacks keep their own values, whatever it holds otherwise
+    let map_value_types = map_value_types.map(|mut types| {
+        types.remove(&MapKeyLiteral::Every);
+        types
+    });
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots).with_map_value_types(map_value_types.filter(|types| !types.is_empty())))
+}
+
+/// Compile merge-with (like merge, but values of keys present in both maps are combined by a
+/// two-argument `defn` function returning numbers or booleans)
+pub(super) fn compile_merge_with(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() < 2 {
+        return Err(CompileError::ArityError("merge-with".to_string(), 2, args.len()));
+    }
+    let callback = resolve_callback("merge-with", &args[0], &[None, None], context)?;
+    if callback.result_kind.is_heap_kind() || callback.result_kind == ValueKind::Any {
+        return Err(CompileError::InvalidExpression("merge-with requires a callback returning numbers or booleans".to_string()));
+    }
+    if args.len() == 2 {
+        return compile_node(&args[1], context, program);
+    }
+
+    // Every merge takes the callback beneath its two maps, so all of them are pushed up front
+    let mut instructions: Vec<IRInstruction> = (2..args.len()).map(|_| IRInstruction::PushFunction(callback.symbol.clone())).collect();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    compile_map_source("merge-with", &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    for (index, map) in args[2..].iter().enumerate() {
+        if index > 0 {
+            tracker.track_owned(&mut instructions, context, ValueKind::Map);
+        }
+        compile_map_source("merge-with", map, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+        instructions.push(IRInstruction::Push(callback.result_mode()));
+        instructions.push(IRInstruction::RuntimeCall("_map_merge_with".to_string(), 4));
+    }
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots))
+}
+
+/// Compile select-keys (the entries of a map whose keys appear in a collection)
+pub(super) fn compile_select_keys(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("select-keys".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let map_value_types = compile_map_source("select-keys", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let keys = compile_source("select-keys", &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::Push(keys.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_map_select_keys".to_string(), 3));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let selected: Option<Vec<MapKeyLiteral>> = match &args[1] {
+        Node::Vector { root } => root.iter().map(literal_map_key).collect(),
+        _ => None,
+    };
+    let map_value_types = map_value_types.zip(selected).map(|(types, selected)| {
+        types
+            .into_iter()
+            .filter(|(key, _)| match key {
+                MapKeyLiteral::Path(path) => path.first().is_some_and(|first| selected.contains(first)),
+                key => selected.contains(key),
+            })
+            .collect::<MapValueTypes>()
+    });
+
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots).with_map_value_types(map_value_types.filter(|types| !types.is_empty())))
+}
+
+/// Compile zipmap (a map pairing each key with the value at the same position)
+pub(super) fn compile_zipmap(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("zipmap".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    for coll in args {
+        let source = compile_source("zipmap", coll, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+        instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    }
+    instructions.push(IRInstruction::RuntimeCall("_map_zipmap".to_string(), 4));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots))
+}
+
+/// Compile find (the `[key value]` entry of a key, or nil)
+pub(super) fn compile_find(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("find".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let map_value_types = compile_map_source("find", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+
+    let mut key_result = compile_node(&args[1], context, program)?;
+    let key_kind = resolve_map_key_kind(&args[1], key_result.kind, context)?;
+    extend_with_offset(&mut instructions, std::mem::take(&mut key_result.instructions));
+    tracker.track_if_owned(&mut instructions, context, key_result.heap_ownership, key_kind);
+    key_result.free_retained_slots(&mut instructions, context);
+    instructions.push(IRInstruction::Push(key_kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_map_find".to_string(), 3));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    // A value of known type makes the entry uniform only when it matches the key's type
+    let value_kind = map_value_types.and_then(|types| map_value_type(&types, literal_map_key(&args[1]).as_ref()));
+    let element_kind = value_kind.filter(|kind| *kind == key_kind);
+
+    Ok(shared_result(instructions, ValueKind::Vector, retained_slots).with_vector_element_kind(element_kind))
+}
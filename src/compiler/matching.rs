@@ -0,0 +1,443 @@
+/// Pattern matching - match rewritten into a decision tree
+///
+/// `expand_program` rewrites every `match` before inference sees the program. Each clause's
+/// pattern flattens into a row of tests on occurrences, the target and the parts of it reached
+/// by `get` (vector items and map values) or `subs` (the rest of a vector). The tree takes the
+/// first row's first test, branches on it, and keeps in each branch only the rows that test can
+/// still leave matching, dropping the tests it settles:
+/// - literal tests on one occurrence become a `case` on it when the literals allow, else `=` tests
+/// - a vector or map pattern tests the occurrence's shape with `match shape?`, which the
+///   compiler settles from its inferred type, then its `count` and, with `match key?` at run
+///   time, the keys it must contain
+/// - each occurrence is bound by `let` where it is first tested, so later tests reuse it
+///
+/// A row with no tests left is a leaf binding its pattern's symbols around its guard and body; a
+/// failing guard continues with the rows after it. A clause that never becomes a leaf can never
+/// run, so it is reported as an unreachable clause warning.
+use super::{builtins::resolve_value_kind, compile_node, CompileContext, CompileError, CompileResult, ValueKind};
+use crate::ast::{forms, patterns, patterns::Pattern, Node, Primitive};
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Operator testing whether a value has a pattern's shape. No source symbol can spell it.
+/// `(match shape? value :vector ancestor :map ...)` also names each ancestor occurrence the value
+/// was reached through, with the shape the path assumed for it.
+pub(super) const SHAPE_TEST: &str = "match shape?";
+
+/// Operator testing at run time whether a map holds a key, as `contains?` without its folding
+pub(super) const KEY_TEST: &str = "match key?";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Shape {
+    Vector,
+    Map,
+}
+
+impl Shape {
+    fn keyword(self) -> Node {
+        let name = match self {
+            Shape::Vector => "vector",
+            Shape::Map => "map",
+        };
+        Node::new_keyword_from_raw(name.to_string())
+    }
+}
+
+/// How an occurrence is reached from its parent
+#[derive(Clone, PartialEq)]
+enum Access {
+    Index(usize),
+    Rest(usize),
+    Key(Node),
+}
+
+struct Occurrence {
+    parent: usize,
+    access: Access,
+    name: Node,
+}
+
+#[derive(Clone, PartialEq)]
+enum Test {
+    Shape(Shape),
+    Count { len: usize, exact: bool },
+    Key(Node),
+    Equals(Node),
+}
+
+#[derive(Clone)]
+struct Row<'a> {
+    clause: usize,
+    tests: Vec<(usize, Test)>,
+    bindings: Vec<(String, usize)>,
+    guard: Option<&'a Node>,
+    body: &'a Node,
+}
+
+struct TreeBuilder<'c> {
+    occurrences: Vec<Occurrence>, // The target is occurrence 0
+    counter: &'c mut usize,
+    reached: Vec<bool>,
+}
+
+/// Rewrite every `match` in the program into a decision tree, collecting a warning for each
+/// clause that can never run
+pub(super) fn expand_program(forms: Vec<Node>, warnings: &mut Vec<String>) -> Result<Vec<Node>, CompileError> {
+    let mut counter = 0;
+    forms
+        .iter()
+        .map(|form| expand_node(form, &mut counter, warnings))
+        .collect::<Result<_, _>>()
+        .map_err(CompileError::InvalidExpression)
+}
+
+fn expand_node(node: &Node, counter: &mut usize, warnings: &mut Vec<String>) -> Result<Node, String> {
+    let mut expand_all = |nodes: &[Node]| nodes.iter().map(|child| expand_node(child, counter, warnings)).collect::<Result<Vec<_>, _>>();
+    match node {
+        Node::List { root } => match root.first() {
+            // Quoted forms are data
+            Some(Node::Symbol { value }) if value == "quote" || value == "syntax-quote" => Ok(node.clone()),
+            Some(Node::Symbol { value }) if value == "match" => {
+                let expanded = expand_match(&root[1..], counter, warnings)?;
+                expand_node(&expanded, counter, warnings)
+            }
+            _ => Ok(list(expand_all(root)?)),
+        },
+        Node::Vector { root } => Ok(Node::Vector { root: expand_all(root)? }),
+        Node::Set { root } => Ok(Node::Set { root: expand_all(root)? }),
+        Node::Map { entries } => Ok(Node::Map {
+            entries: entries
+                .iter()
+                .map(|(key, value)| Ok((expand_node(key, counter, warnings)?, expand_node(value, counter, warnings)?)))
+                .collect::<Result<_, String>>()?,
+        }),
+        Node::Primitive { .. } | Node::Symbol { .. } => Ok(node.clone()),
+    }
+}
+
+/// The decision tree of one match form. Its guards and bodies still hold unexpanded matches.
+fn expand_match(args: &[Node], counter: &mut usize, warnings: &mut Vec<String>) -> Result<Node, String> {
+    let form = patterns::match_clauses(args)?;
+    // A symbol target is read directly unless a pattern rebinds its name
+    let (target, binding) = match form.target {
+        Node::Symbol { value } if !patterns::binds_name(&form, value) => (form.target.clone(), None),
+        _ => {
+            let temp = gensym(counter);
+            (temp.clone(), Some(temp))
+        }
+    };
+
+    let mut builder = TreeBuilder {
+        occurrences: vec![Occurrence {
+            parent: 0,
+            access: Access::Index(0),
+            name: target,
+        }],
+        counter,
+        reached: vec![false; form.clauses.len()],
+    };
+    let rows = form
+        .clauses
+        .iter()
+        .enumerate()
+        .map(|(clause, match_clause)| {
+            let mut row = Row {
+                clause,
+                tests: Vec::new(),
+                bindings: Vec::new(),
+                guard: match_clause.guard,
+                body: match_clause.body,
+            };
+            builder.flatten(&match_clause.pattern, 0, &mut row);
+            row
+        })
+        .collect();
+    let tree = builder.build(rows, &[0]);
+
+    for (clause, reached) in builder.reached.iter().enumerate() {
+        if !reached {
+            warnings.push(format!("match clause {} is unreachable: earlier clauses match every value it matches", clause + 1));
+        }
+    }
+    Ok(match binding {
+        Some(temp) => let_form(vec![temp, form.target.clone()], tree),
+        None => tree,
+    })
+}
+
+impl<'a> TreeBuilder<'_> {
+    /// The occurrence reached from `parent` by `access`, shared by every row that reaches it
+    fn occurrence(&mut self, parent: usize, access: Access) -> usize {
+        // The target has no parent, so it is never reused as a child
+        let existing = (1..self.occurrences.len()).find(|&index| self.occurrences[index].parent == parent && self.occurrences[index].access == access);
+        if let Some(index) = existing {
+            return index;
+        }
+        let name = gensym(self.counter);
+        self.occurrences.push(Occurrence { parent, access, name });
+        self.occurrences.len() - 1
+    }
+
+    /// Append the tests and bindings matching `pattern` against occurrence `at` makes
+    fn flatten(&mut self, pattern: &Pattern, at: usize, row: &mut Row<'a>) {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Bind(name) => row.bindings.push((name.clone(), at)),
+            Pattern::Literal(literal) => row.tests.push((at, Test::Equals(literal.clone()))),
+            Pattern::Vector { items, rest } => {
+                row.tests.push((at, Test::Shape(Shape::Vector)));
+                if rest.is_none() || !items.is_empty() {
+                    row.tests.push((
+                        at,
+                        Test::Count {
+                            len: items.len(),
+                            exact: rest.is_none(),
+                        },
+                    ));
+                }
+                for (index, item) in items.iter().enumerate() {
+                    let child = self.occurrence(at, Access::Index(index));
+                    self.flatten(item, child, row);
+                }
+                if let Some(rest) = rest {
+                    let child = self.occurrence(at, Access::Rest(items.len()));
+                    self.flatten(rest, child, row);
+                }
+            }
+            Pattern::Map(entries) => {
+                row.tests.push((at, Test::Shape(Shape::Map)));
+                for (key, value) in entries {
+                    row.tests.push((at, Test::Key(key.clone())));
+                    let child = self.occurrence(at, Access::Key(key.clone()));
+                    self.flatten(value, child, row);
+                }
+            }
+        }
+    }
+
+    fn build(&mut self, rows: Vec<Row<'a>>, bound: &[usize]) -> Node {
+        let Some(first) = rows.first() else {
+            return forms::no_matching_clause();
+        };
+        let Some((at, test)) = first.tests.first().cloned() else {
+            return self.leaf(rows, bound);
+        };
+        // The occurrence's parent was tested before it, so it is already bound
+        if !bound.contains(&at) {
+            let value = self.reference(at, bound);
+            let name = self.occurrences[at].name.clone();
+            let bound: Vec<usize> = bound.iter().copied().chain([at]).collect();
+            return let_form(vec![name, value], self.build(rows, &bound));
+        }
+
+        let name = self.occurrences[at].name.clone();
+        match test {
+            Test::Equals(_) => self.switch(rows, at, bound),
+            Test::Shape(shape) => {
+                let mut root = vec![symbol(SHAPE_TEST), name, shape.keyword()];
+                let mut child = at;
+                while child != 0 {
+                    let parent = self.occurrences[child].parent;
+                    let parent_shape = if matches!(self.occurrences[child].access, Access::Key(_)) { Shape::Map } else { Shape::Vector };
+                    root.extend([self.occurrences[parent].name.clone(), parent_shape.keyword()]);
+                    child = parent;
+                }
+                let matched = specialize(&rows, at, |test| match test {
+                    Test::Shape(other) => Some(*other == shape),
+                    Test::Equals(_) => Some(false),
+                    _ => None,
+                });
+                let unmatched = specialize(&rows, at, |test| match test {
+                    Test::Shape(other) if *other == shape => Some(false),
+                    _ => None,
+                });
+                self.branch(list(root), matched, unmatched, bound)
+            }
+            Test::Count { len, exact } => {
+                let count = list(vec![symbol("count"), name]);
+                let test = list(vec![symbol(if exact { "=" } else { ">=" }), count, Node::new_number(len)]);
+                // Whether a row needing `other_len` items (at least that many unless `other_exact`)
+                // still matches when the test passes, or fails
+                let matched = specialize(&rows, at, |test| match *test {
+                    Test::Count { len: other_len, exact: other_exact } => match (exact, other_exact) {
+                        (true, true) => Some(other_len == len),
+                        (true, false) => Some(other_len <= len),
+                        (false, true) if other_len < len => Some(false),
+                        (false, false) if other_len <= len => Some(true),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+                let unmatched = specialize(&rows, at, |test| match *test {
+                    Test::Count { len: other_len, exact: other_exact } => match (exact, other_exact) {
+                        (true, true) if other_len == len => Some(false),
+                        (false, _) if other_len >= len => Some(false),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+                self.branch(test, matched, unmatched, bound)
+            }
+            Test::Key(key) => {
+                let test = list(vec![symbol(KEY_TEST), name, key.clone()]);
+                let matched = specialize(&rows, at, |test| match test {
+                    Test::Key(other) if *other == key => Some(true),
+                    _ => None,
+                });
+                let unmatched = specialize(&rows, at, |test| match test {
+                    Test::Key(other) if *other == key => Some(false),
+                    _ => None,
+                });
+                self.branch(test, matched, unmatched, bound)
+            }
+        }
+    }
+
+    fn branch(&mut self, test: Node, matched: Vec<Row<'a>>, unmatched: Vec<Row<'a>>, bound: &[usize]) -> Node {
+        let then = self.build(matched, bound);
+        let otherwise = self.build(unmatched, bound);
+        list(vec![symbol("if"), test, then, otherwise])
+    }
+
+    /// Dispatch on the literals the rows compare occurrence `at` with, through a `case` when they
+    /// are all numbers or all keywords and strings
+    fn switch(&mut self, rows: Vec<Row<'a>>, at: usize, bound: &[usize]) -> Node {
+        let mut literals: Vec<Node> = Vec::new();
+        for (_, test) in rows.iter().flat_map(|row| row.tests.iter().filter(|(occurrence, _)| *occurrence == at)) {
+            if let Test::Equals(literal) = test {
+                if !literals.contains(literal) {
+                    literals.push(literal.clone());
+                }
+            }
+        }
+
+        let arms: Vec<(Node, Node)> = literals
+            .iter()
+            .map(|literal| {
+                let matched = specialize(&rows, at, |test| match test {
+                    Test::Equals(other) => Some(other == literal),
+                    Test::Shape(_) => Some(false),
+                    _ => None,
+                });
+                (literal.clone(), self.build(matched, bound))
+            })
+            .collect();
+        let unmatched = specialize(&rows, at, |test| matches!(test, Test::Equals(_)).then_some(false));
+        let default = self.build(unmatched, bound);
+
+        let name = self.occurrences[at].name.clone();
+        let numeric = literals.iter().all(|literal| matches!(literal, Node::Primitive { value: Primitive::Number(_) }));
+        let textual = literals.iter().all(|literal| {
+            matches!(
+                literal,
+                Node::Primitive {
+                    value: Primitive::Keyword(_) | Primitive::String(_)
+                }
+            )
+        });
+        if numeric || textual {
+            let mut root = vec![symbol("case"), name];
+            for (literal, arm) in arms {
+                root.extend([literal, arm]);
+            }
+            root.push(default);
+            return list(root);
+        }
+        arms.into_iter().rev().fold(default, |otherwise, (literal, arm)| {
+            list(vec![symbol("if"), list(vec![symbol("="), name.clone(), literal]), arm, otherwise])
+        })
+    }
+
+    /// The first row matched: bind its symbols around its guard and body
+    fn leaf(&mut self, rows: Vec<Row<'a>>, bound: &[usize]) -> Node {
+        let row = &rows[0];
+        self.reached[row.clause] = true;
+        let bindings: Vec<Node> = row.bindings.iter().flat_map(|(name, at)| [symbol(name), self.reference(*at, bound)]).collect();
+        let scoped = |node: &Node| if bindings.is_empty() { node.clone() } else { let_form(bindings.clone(), node.clone()) };
+        match row.guard {
+            None => scoped(row.body),
+            Some(guard) => {
+                let (test, body) = (scoped(guard), scoped(row.body));
+                let otherwise = self.build(rows[1..].to_vec(), bound);
+                list(vec![symbol("if"), test, body, otherwise])
+            }
+        }
+    }
+
+    /// The symbol of a bound occurrence, else the lookup reaching it from its parent
+    fn reference(&self, at: usize, bound: &[usize]) -> Node {
+        let occurrence = &self.occurrences[at];
+        if bound.contains(&at) {
+            return occurrence.name.clone();
+        }
+        let parent = self.reference(occurrence.parent, bound);
+        match &occurrence.access {
+            Access::Index(index) => list(vec![symbol("get"), parent, Node::new_number(*index)]),
+            Access::Rest(start) => list(vec![symbol("subs"), parent, Node::new_number(*start)]),
+            Access::Key(key) => list(vec![symbol("get"), parent, key.clone()]),
+        }
+    }
+}
+
+/// The rows still possible once a test on occurrence `at` settled: `decide` tells for each of a
+/// row's tests on `at` whether it now holds (and is dropped), fails (dropping the row), or is
+/// still open
+fn specialize<'a>(rows: &[Row<'a>], at: usize, decide: impl Fn(&Test) -> Option<bool>) -> Vec<Row<'a>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut tests = Vec::with_capacity(row.tests.len());
+            for (occurrence, test) in &row.tests {
+                match (*occurrence == at).then(|| decide(test)).flatten() {
+                    Some(true) => {}
+                    Some(false) => return None,
+                    None => tests.push((*occurrence, test.clone())),
+                }
+            }
+            Some(Row { tests, ..row.clone() })
+        })
+        .collect()
+}
+
+/// Compile `(match shape? value :shape ancestor :shape ...)` to a constant from the value's
+/// inferred type. A path whose ancestors do not have the shapes it assumed never runs, so its
+/// test is false whatever the value's type.
+pub(super) fn compile_shape_test(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() < 2 || args.len() % 2 != 0 {
+        return Err(CompileError::ArityError(SHAPE_TEST.to_string(), 2, args.len()));
+    }
+    let mut kinds = Vec::with_capacity(args.len() / 2);
+    for pair in args.chunks(2) {
+        let expected = match &pair[1] {
+            Node::Primitive { value: Primitive::Keyword(shape) } if shape == "vector" => ValueKind::Vector,
+            _ => ValueKind::Map,
+        };
+        let mut result = compile_node(&pair[0], context, program)?;
+        let mut discarded = std::mem::take(&mut result.instructions);
+        result.free_retained_slots(&mut discarded, context);
+        kinds.push((resolve_value_kind(&pair[0], result.kind, context), expected));
+    }
+
+    let dead = kinds[1..].iter().any(|(kind, expected)| kind != expected);
+    let (kind, expected) = kinds[0];
+    if !dead && kind == ValueKind::Any {
+        return Err(CompileError::InvalidExpression("match: vector and map patterns need a value whose type inference can tell".to_string()));
+    }
+    Ok(CompileResult::with_instructions(vec![IRInstruction::Push(i64::from(!dead && kind == expected))], ValueKind::Boolean))
+}
+
+fn let_form(bindings: Vec<Node>, body: Node) -> Node {
+    list(vec![symbol("let"), Node::Vector { root: bindings }, body])
+}
+
+fn gensym(counter: &mut usize) -> Node {
+    let generated = symbol(&format!("match__{}", counter));
+    *counter += 1;
+    generated
+}
+
+fn symbol(name: &str) -> Node {
+    Node::Symbol { value: name.to_string() }
+}
+
+fn list(nodes: Vec<Node>) -> Node {
+    Node::new_list_from_raw(nodes)
+}
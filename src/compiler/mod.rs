@@ -1,5 +1,7 @@
+mod atoms;
 mod bindings;
 mod builtins;
+mod case;
 /// Compiler module - compiles AST nodes to IR
 ///
 /// This module is organized into:
@@ -7,20 +9,40 @@ mod builtins;
 /// - expressions: Arithmetic, comparisons, conditionals, and logical operations
 /// - functions: Function definitions (defn) and function calls
 /// - bindings: Variable bindings (let expressions)
+/// - case: Constant dispatch for case through jump tables
+/// - exceptions: throw, try/catch/finally and ex-info
+/// - sequences: map/filter/reduce and the rest of the higher-order sequence library
+/// - vectors: conj, peek, pop, subvec and assoc by index, updating dead vectors in place
+/// - maps: keys, vals, merge, merge-with, select-keys, zipmap, find and map-invert
+/// - sets: union, intersection, difference, subset?, superset? and select
+/// - atoms: atom, deref, reset!, compare-and-set! and add-watch over reference-counted runtime cells
+/// - records: defrecord, ->Name constructors and field reads at fixed offsets
+/// - matching: match, rewritten into a decision tree of if/case/let over count, key checks and get
+/// - polymorphism: protocols and multimethods, rewritten into functions and dispatch tables
 /// - slots: Slot tracking utilities for temporary local variables
 mod context;
+mod exceptions;
 mod expressions;
 mod functions;
 mod inference;
 mod liveness;
+mod maps;
+mod matching;
+mod polymorphism;
+mod records;
+mod sequences;
+mod sets;
 mod slots;
+mod sorted;
 mod types;
+mod vectors;
 
 pub use context::CompileContext;
 pub use types::{CompileResult, HeapOwnership, MapKeyLiteral, MapValueTypes, RetainedSlot, ValueKind};
 
-use crate::ast::Node;
-use crate::ir::{FunctionInfo, IRInstruction, IRProgram};
+use crate::ast::{destructure, forms, Node};
+use crate::evaluator::MacroExpander;
+use crate::ir::{IRInstruction, IRProgram};
 use inference::run_type_inference;
 
 /// Determine if a symbol refers to a heap-allocated local variable in the current context.
@@ -37,9 +59,26 @@ pub enum CompileError {
     DuplicateFunction(String),
 }
 
+/// Expand macros, then rewrite derived forms, match and destructuring up front so inference and
+/// compilation see the same plain bindings
+fn prepare_forms(expressions: &[Node], warnings: &mut Vec<String>) -> Result<Vec<Node>, CompileError> {
+    let expanded = MacroExpander::new()
+        .expand_program(expressions)
+        .map_err(|error| CompileError::InvalidExpression(format!("macro expansion failed: {:?}", error)))?;
+    matching::expand_program(polymorphism::expand_program(expanded)?, warnings)?
+        .iter()
+        .map(|expr| forms::expand(expr).and_then(|expanded| destructure::desugar(&expanded)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CompileError::InvalidExpression)
+}
+
 /// Compile a single expression to IR
 pub fn compile_to_ir(node: &Node) -> Result<IRProgram, CompileError> {
     let mut program = IRProgram::new();
+    let prepared = prepare_forms(std::slice::from_ref(node), &mut program.warnings)?;
+    // A lone defmacro leaves nothing to run
+    let nil = Node::Symbol { value: "nil".to_string() };
+    let node = prepared.last().unwrap_or(&nil);
     let mut context = CompileContext::new();
     let inference = run_type_inference(std::slice::from_ref(node))?;
     context.set_type_inference(inference);
@@ -55,46 +94,39 @@ pub fn compile_to_ir(node: &Node) -> Result<IRProgram, CompileError> {
 /// Compile a program (multiple top-level expressions) to IR
 pub fn compile_program(expressions: &[Node]) -> Result<IRProgram, CompileError> {
     let mut program = IRProgram::new();
+    let prepared = prepare_forms(expressions, &mut program.warnings)?;
+    let expressions = prepared.as_slice();
     let mut context = CompileContext::new();
     let inference = run_type_inference(expressions)?;
     context.set_type_inference(inference);
     context.hydrate_from_inference();
     let mut emitted_toplevel_code = false;
 
-    // First pass: find all function definitions
+    // First pass: find all record, protocol and function definitions
     for expr in expressions {
         if let Node::List { root } = expr {
             if !root.is_empty() {
                 if let Node::Symbol { value } = &root[0] {
+                    if value == "defrecord" {
+                        let (name, fields) = records::defrecord_fields(&root[1..])?;
+                        context.add_record(&name, fields)?;
+                    }
+                    if value == "defprotocol" {
+                        let protocol = polymorphism::defprotocol_methods(&root[1..])?;
+                        context.add_protocol(&protocol);
+                    }
                     if value == "defn" {
                         // Register function in context but don't compile yet
-                        if root.len() != 4 {
-                            return Err(CompileError::ArityError("defn".to_string(), 3, root.len() - 1));
-                        }
-
-                        let func_name = match &root[1] {
-                            Node::Symbol { value } => value.clone(),
-                            _ => return Err(CompileError::InvalidExpression("Function name must be a symbol".to_string())),
-                        };
-
-                        let params = match &root[2] {
-                            Node::Vector { root } => root,
-                            _ => return Err(CompileError::InvalidExpression("Function parameters must be a vector".to_string())),
-                        };
-
-                        let func_info = FunctionInfo {
-                            name: func_name.clone(),
-                            param_count: params.len(),
-                            start_address: 0, // Will be set during compilation
-                            local_count: 0,
-                        };
-                        context.add_function(func_name, func_info)?;
+                        let (func_name, clauses) = functions::defn_clauses(&root[1..])?;
+                        functions::register_defn(&func_name, &clauses, &mut context)?;
                     }
                 }
             }
         }
     }
 
+    functions::mark_consumed_parameters(expressions, &mut context)?;
+
     // Prime function metadata (return types/ownership) by compiling each defn in a throwaway context
     // before any non-defn expressions run. This ensures early call sites (e.g. in other defns)
     // can infer accurate types even though full function compilation happens later.
@@ -104,10 +136,7 @@ pub fn compile_program(expressions: &[Node]) -> Result<IRProgram, CompileError>
         if let Node::List { root } = expr {
             if let Some(Node::Symbol { value }) = root.first() {
                 if value == "defn" {
-                    // Skip malformed defns here; they'll be reported in the main compilation loop.
-                    if root.len() == 4 {
-                        functions::compile_defn(&root[1..], &mut metadata_context, &mut metadata_program)?;
-                    }
+                    functions::compile_defn(&root[1..], &mut metadata_context, &mut metadata_program)?;
                 }
             }
         }
@@ -127,6 +156,9 @@ pub fn compile_program(expressions: &[Node]) -> Result<IRProgram, CompileError>
                         pending_defns.push(root.clone());
                         continue;
                     }
+                    if value == "defrecord" || value == "defprotocol" {
+                        continue;
+                    }
                 }
             }
         }
@@ -144,22 +176,23 @@ pub fn compile_program(expressions: &[Node]) -> Result<IRProgram, CompileError>
 
     // Compile functions in reverse order so parameter inference from later call sites is available.
     for defn in pending_defns.into_iter().rev() {
-        let (mut instructions, func_info) = functions::compile_defn(&defn[1..], &mut context, &mut program)?;
-        let start_address = program.len();
+        for (mut instructions, func_info) in functions::compile_defn(&defn[1..], &mut context, &mut program)? {
+            let start_address = program.len();
 
-        if let IRInstruction::DefineFunction(ref name, ref params, _) = instructions[0] {
-            instructions[0] = IRInstruction::DefineFunction(name.clone(), *params, start_address);
-        }
+            if let IRInstruction::DefineFunction(ref name, ref params, _) = instructions[0] {
+                instructions[0] = IRInstruction::DefineFunction(name.clone(), *params, start_address);
+            }
 
-        let updated_func_info = crate::ir::FunctionInfo {
-            name: func_info.name,
-            param_count: func_info.param_count,
-            start_address,
-            local_count: func_info.local_count,
-        };
+            let updated_func_info = crate::ir::FunctionInfo {
+                name: func_info.name,
+                param_count: func_info.param_count,
+                start_address,
+                local_count: func_info.local_count,
+            };
 
-        append_with_offset(&mut program, instructions);
-        program.add_function(updated_func_info);
+            append_with_offset(&mut program, instructions);
+            program.add_function(updated_func_info);
+        }
     }
 
     if context.get_function("-main").is_some() {
@@ -179,6 +212,8 @@ fn append_with_offset(program: &mut IRProgram, instructions: Vec<IRInstruction>)
         let adjusted = match instruction {
             IRInstruction::Jump(target) => IRInstruction::Jump(base + target),
             IRInstruction::JumpIfZero(target) => IRInstruction::JumpIfZero(base + target),
+            IRInstruction::PushHandler(target) => IRInstruction::PushHandler(base + target),
+            IRInstruction::JumpTable(targets) => IRInstruction::JumpTable(targets.into_iter().map(|target| base + target).collect()),
             other => other,
         };
         program.add_instruction(adjusted);
@@ -193,9 +228,10 @@ pub(super) fn extend_with_offset(target: &mut Vec<IRInstruction>, mut new_instru
     let base = target.len();
     if base != 0 {
         new_instructions.iter_mut().for_each(|instruction| match instruction {
-            IRInstruction::Jump(target_idx) | IRInstruction::JumpIfZero(target_idx) => {
+            IRInstruction::Jump(target_idx) | IRInstruction::JumpIfZero(target_idx) | IRInstruction::PushHandler(target_idx) => {
                 *target_idx += base;
             }
+            IRInstruction::JumpTable(targets) => targets.iter_mut().for_each(|target_idx| *target_idx += base),
             _ => {}
         });
     }
@@ -220,11 +256,13 @@ pub(crate) fn compile_node(node: &Node, context: &mut CompileContext, program: &
                 let map_value_types = context.get_parameter_map_value_types(value).cloned();
                 let set_element_kind = context.get_parameter_set_element_kind(value);
                 let vector_element_kind = context.get_parameter_vector_element_kind(value);
+                let record_type = context.get_parameter_record_type(value).map(str::to_string);
                 Ok(CompileResult::with_instructions(vec![IRInstruction::LoadParam(slot)], kind)
                     .with_heap_ownership(ownership)
                     .with_map_value_types(map_value_types)
                     .with_set_element_kind(set_element_kind)
-                    .with_vector_element_kind(vector_element_kind))
+                    .with_vector_element_kind(vector_element_kind)
+                    .with_record_type(record_type))
             } else if let Some(slot) = context.get_variable(value) {
                 let kind = context.get_variable_type(value).unwrap_or(ValueKind::Any);
                 let ownership = if kind.is_heap_kind() && context.is_heap_allocated(value) {
@@ -235,11 +273,13 @@ pub(crate) fn compile_node(node: &Node, context: &mut CompileContext, program: &
                 let map_value_types = context.get_variable_map_value_types(value).cloned();
                 let set_element_kind = context.get_variable_set_element_kind(value);
                 let vector_element_kind = context.get_variable_vector_element_kind(value);
+                let record_type = context.get_variable_record_type(value).map(str::to_string);
                 Ok(CompileResult::with_instructions(vec![IRInstruction::LoadLocal(slot)], kind)
                     .with_heap_ownership(ownership)
                     .with_map_value_types(map_value_types)
                     .with_set_element_kind(set_element_kind)
-                    .with_vector_element_kind(vector_element_kind))
+                    .with_vector_element_kind(vector_element_kind)
+                    .with_record_type(record_type))
             } else {
                 Err(CompileError::UndefinedVariable(value.clone()))
             }
@@ -269,18 +309,27 @@ fn compile_list(nodes: &[Node], context: &mut CompileContext, program: &mut IRPr
             "-" => expressions::compile_arithmetic_op(args, context, program, IRInstruction::Sub, "-"),
             "*" => expressions::compile_arithmetic_op(args, context, program, IRInstruction::Mul, "*"),
             "/" => expressions::compile_arithmetic_op(args, context, program, IRInstruction::Div, "/"),
+            "unchecked-add" => expressions::compile_arithmetic_op(args, context, program, IRInstruction::UncheckedAdd, "unchecked-add"),
+            "unchecked-subtract" => expressions::compile_arithmetic_op(args, context, program, IRInstruction::UncheckedSub, "unchecked-subtract"),
+            "unchecked-multiply" => expressions::compile_arithmetic_op(args, context, program, IRInstruction::UncheckedMul, "unchecked-multiply"),
             "=" => expressions::compile_comparison_op(args, context, program, IRInstruction::Equal, "="),
+            "compare" => expressions::compile_compare(args, context, program),
+            "hash" => expressions::compile_hash(args, context, program),
             "<" => expressions::compile_comparison_op(args, context, program, IRInstruction::Less, "<"),
             ">" => expressions::compile_comparison_op(args, context, program, IRInstruction::Greater, ">"),
             "<=" => expressions::compile_comparison_op(args, context, program, IRInstruction::LessEqual, "<="),
             ">=" => expressions::compile_comparison_op(args, context, program, IRInstruction::GreaterEqual, ">="),
             "if" => expressions::compile_if(args, context, program),
+            "case" => case::compile_case(args, context, program),
             "and" => expressions::compile_logical_and(args, context, program),
             "or" => expressions::compile_logical_or(args, context, program),
             "not" => expressions::compile_logical_not(args, context, program),
             "let" => bindings::compile_let(args, context, program),
             "defn" => {
-                let (instructions, _) = functions::compile_defn(args, context, program)?;
+                let mut instructions = Vec::new();
+                for (clause_instructions, _) in functions::compile_defn(args, context, program)? {
+                    extend_with_offset(&mut instructions, clause_instructions);
+                }
                 Ok(CompileResult::with_instructions(instructions, ValueKind::Nil))
             }
             "count" => builtins::compile_count(args, context, program),
@@ -288,19 +337,87 @@ fn compile_list(nodes: &[Node], context: &mut CompileContext, program: &mut IRPr
             "subs" => builtins::compile_subs(args, context, program),
             "str" => builtins::compile_str(args, context, program),
             "vec" => builtins::compile_vector_literal(args, context, program),
+            "quote" => builtins::compile_quote(args, context, program),
+            "list" => builtins::compile_list_literal(args, context, program),
+            "cons" => builtins::compile_cons(args, context, program),
+            "first" => builtins::compile_first(args, context, program),
+            "rest" => builtins::compile_rest(args, context, program),
+            "next" => sequences::compile_next(args, context, program),
+            "seq" => sequences::compile_seq(args, context, program),
+            "nth" => sequences::compile_nth(args, context, program),
+            "last" => sequences::compile_last(args, context, program),
+            "empty?" => sequences::compile_is_empty(args, context, program),
+            "list?" => builtins::compile_is_list(args, context, program),
+            "symbol" => builtins::compile_symbol(args, context, program),
             "set" => builtins::compile_set_literal(args, context, program),
             "hash-map" => builtins::compile_hash_map(args, context, program),
             "assoc" => builtins::compile_assoc(args, context, program),
             "dissoc" => builtins::compile_dissoc(args, context, program),
             "disj" => builtins::compile_disj(args, context, program),
             "contains?" => builtins::compile_contains(args, context, program),
-            op => {
-                if let Some(func_info) = context.get_function(op) {
-                    functions::compile_function_call(op, args, context, program, func_info.param_count)
-                } else {
-                    Err(CompileError::UnsupportedOperation(op.to_string()))
-                }
-            }
+            "map" => sequences::compile_map(args, context, program),
+            "filter" => sequences::compile_filter(args, true, context, program),
+            "remove" => sequences::compile_filter(args, false, context, program),
+            "reduce" => sequences::compile_reduce(args, context, program),
+            "every?" => sequences::compile_every(args, context, program),
+            "some" => sequences::compile_some(args, context, program),
+            "range" => sequences::compile_range(args, context, program),
+            "into" => sequences::compile_into(args, context, program),
+            "take" => sequences::compile_take(args, true, context, program),
+            "drop" => sequences::compile_take(args, false, context, program),
+            "concat" => sequences::compile_concat(args, context, program),
+            "reverse" => sequences::compile_reverse(args, context, program),
+            "sort" => sequences::compile_sort(args, context, program),
+            "sort-by" => sequences::compile_sort_by(args, context, program),
+            op @ ("sorted-map" | "sorted-set" | "sorted-map-by" | "sorted-set-by") => sorted::compile_sorted_collection(op, args, context, program),
+            op @ ("subseq" | "rsubseq") => sorted::compile_subseq(op, args, context, program),
+            "conj" => vectors::compile_conj(args, context, program),
+            "peek" => vectors::compile_peek(args, context, program),
+            "pop" => vectors::compile_pop(args, context, program),
+            "subvec" => vectors::compile_subvec(args, context, program),
+            "frequencies" => sequences::compile_frequencies(args, context, program),
+            "group-by" => sequences::compile_group_by(args, context, program),
+            "keys" => maps::compile_map_column("keys", "_map_keys", args, context, program),
+            "vals" => maps::compile_map_column("vals", "_map_vals", args, context, program),
+            "merge" => maps::compile_merge(args, context, program),
+            "merge-with" => maps::compile_merge_with(args, context, program),
+            "select-keys" => maps::compile_select_keys(args, context, program),
+            "zipmap" => maps::compile_zipmap(args, context, program),
+            "find" => maps::compile_find(args, context, program),
+            "map-invert" => maps::compile_map_invert(args, context, program),
+            "union" => sets::compile_set_operation("union", "_set_union", args, context, program),
+            "intersection" => sets::compile_set_operation("intersection", "_set_intersection", args, context, program),
+            "difference" => sets::compile_set_operation("difference", "_set_difference", args, context, program),
+            "subset?" => sets::compile_set_predicate("subset?", "_set_subset", args, context, program),
+            "superset?" => sets::compile_set_predicate("superset?", "_set_superset", args, context, program),
+            "select" => sets::compile_select(args, context, program),
+            "iterate" => sequences::compile_iterate(args, context, program),
+            "repeat" => sequences::compile_repeat(args, context, program),
+            "cycle" => sequences::compile_cycle(args, context, program),
+            "atom" => atoms::compile_atom(args, context, program),
+            "deref" => atoms::compile_deref(args, context, program),
+            "reset!" => atoms::compile_reset(args, context, program),
+            "compare-and-set!" => atoms::compile_compare_and_set(args, context, program),
+            "add-watch" => atoms::compile_add_watch(args, context, program),
+            "throw" => exceptions::compile_throw(args, context, program),
+            "try" => exceptions::compile_try(args, context, program),
+            "ex-info" => exceptions::compile_ex_info(args, context, program),
+            "ex-message" => exceptions::compile_ex_message(args, context, program),
+            "ex-data" => exceptions::compile_ex_data(args, context, program),
+            "ex-cause" => exceptions::compile_ex_cause(args, context, program),
+            "defrecord" => records::compile_defrecord(args, context),
+            op if records::constructor_record(op, context).is_some() => records::compile_record_constructor(&op["->".len()..], args, context, program),
+            "defprotocol" => polymorphism::compile_defprotocol(args),
+            polymorphism::RECORD_TYPE => polymorphism::compile_record_type(args, context, program),
+            polymorphism::VALUE_TYPE => polymorphism::compile_value_type(args, context, program),
+            matching::SHAPE_TEST => matching::compile_shape_test(args, context, program),
+            matching::KEY_TEST => builtins::compile_runtime_contains(args, context, program),
+            op if context.get_variable(op).is_some() || context.get_parameter(op).is_some() => builtins::compile_collection_call(op, args, context, program),
+            op if context.get_protocol(op).is_some() => polymorphism::compile_protocol_call(op, args, context, program),
+            op => match functions::resolve_call_target(op, args.len(), context)? {
+                Some((symbol, param_count)) => functions::compile_function_call(&symbol, args, context, program, param_count),
+                None => Err(CompileError::UnsupportedOperation(op.to_string())),
+            },
         },
         _ => Err(CompileError::InvalidExpression("First element must be a symbol".to_string())),
     }
@@ -309,8 +426,9 @@ fn compile_list(nodes: &[Node], context: &mut CompileContext, program: &mut IRPr
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::{AstParser, AstParserTrt};
+    use crate::ast::{parse_file, AstParser, AstParserTrt};
     use crate::compiler::inference::run_type_inference;
+    use crate::ir::FunctionInfo;
 
     fn compile_expression(input: &str) -> Result<IRProgram, CompileError> {
         let ast = AstParser::parse_sexp_new_domain(input.as_bytes(), &mut 0);
@@ -651,6 +769,266 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn set_algebra_calls_set_runtime() {
+        let program = compile_expression("(count (union (conj #{1} 2) (intersection #{1 2} #{2}) (difference #{3} #{1})))").unwrap();
+        let calls: Vec<&str> = program
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                IRInstruction::RuntimeCall(name, _) if name.starts_with("_set_") => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        for runtime in ["_set_conj", "_set_union", "_set_intersection", "_set_difference"] {
+            assert!(calls.contains(&runtime), "missing {}", runtime);
+        }
+        assert!(matches!(compile_expression("(union #{1} [2])"), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn collection_calls_lower_to_lookups() {
+        let program = compile_expression("(let [config {:port 8080} roles #{1 2}] (+ (config :port) (:port config 0) (roles 2 0)))").unwrap();
+        let calls: Vec<&str> = program
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                IRInstruction::RuntimeCall(name, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls.iter().filter(|name| **name == "_map_get").count(), 2);
+        assert!(calls.contains(&"_set_contains"));
+        assert!(matches!(compile_expression("(let [n 1] (n 2))"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(let [roles #{1}] (roles 1 2 3))"), Err(CompileError::ArityError(..))));
+    }
+
+    #[test]
+    fn collections_are_map_keys_and_set_members() {
+        assert!(compile_expression("(get {[0 0] 1 [0 1] 2} [0 1])").is_ok());
+        assert!(compile_expression("(contains? (conj #{[1 2]} [3 4] #{5}) [3 4])").is_ok());
+        assert!(compile_expression("(count (frequencies [[1 2] [1 2]]))").is_ok());
+        assert!(matches!(compile_expression("(conj #{1} (range))"), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn structural_equality_uses_value_runtime() {
+        let calls = |source: &str| {
+            compile_expression(source)
+                .unwrap()
+                .instructions
+                .into_iter()
+                .filter_map(|inst| match inst {
+                    IRInstruction::RuntimeCall(name, _) if name.starts_with("_value_") || name == "_string_equals" => Some(name),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(calls("(= [1 2] [1 2])"), vec!["_value_equals"]);
+        assert_eq!(calls("(not= {:a 1} {:a 2})"), vec!["_value_equals"]);
+        assert_eq!(calls("(= (get {:a :b} :a) :b)"), vec!["_string_equals"]);
+        assert!(calls("(= 1 2)").is_empty());
+        assert!(calls("(= [1] nil)").is_empty());
+        assert_eq!(calls("(compare \"a\" \"b\")"), vec!["_value_compare"]);
+        assert_eq!(calls("(hash #{1})"), vec!["_value_hash"]);
+    }
+
+    #[test]
+    fn sort_calls_runtime_with_optional_callbacks() {
+        let expressions =
+            parse_file("(defn desc [a b] (> a b))\n(defn size [s] (count s))\n(defn -main [] (+ (first (sort desc [3 1 2])) (count (sort-by size [\"bb\" \"a\"])) (first (sort [2 1]))))").unwrap();
+        let program = compile_program(&expressions).unwrap();
+        let sorts = program
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                IRInstruction::RuntimeCall(name, args) if name.starts_with("_seq_sort") => Some((name.as_str(), *args)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sorts, vec![("_seq_sort", 4), ("_seq_sort_by", 6), ("_seq_sort", 4)]);
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::PushFunction(name) if name == "desc")));
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::PushFunction(name) if name == "size")));
+
+        assert!(matches!(compile_expression("(sort)"), Err(CompileError::ArityError(_, 2, 0))));
+        assert!(matches!(compile_expression("(sort-by [1])"), Err(CompileError::ArityError(_, 3, 1))));
+    }
+
+    #[test]
+    fn sorted_collections_call_tree_runtime() {
+        let expressions =
+            parse_file("(defn desc [a b] (> a b))\n(defn -main [] (+ (count (sorted-map 2 :b 1 :a)) (first (subseq (sorted-set-by desc 3 1 2) > 2)) (count (rsubseq (sorted-set 1 2) >= 1 < 2))))")
+                .unwrap();
+        let program = compile_program(&expressions).unwrap();
+        let calls = program
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                IRInstruction::RuntimeCall(name, args) if name.starts_with("_sorted") => Some((name.as_str(), *args)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            vec![("_sorted_map_from", 3), ("_sorted_set_from", 3), ("_sorted_subseq", 6), ("_sorted_set_from", 3), ("_sorted_subseq", 6)]
+        );
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::PushFunction(name) if name == "desc")));
+
+        assert!(matches!(compile_expression("(sorted-map-by)"), Err(CompileError::ArityError(_, 1, 0))));
+        assert!(matches!(compile_expression("(subseq (sorted-set 1) = 1)"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(subseq (sorted-set 1))"), Err(CompileError::ArityError(_, 5, 1))));
+    }
+
+    #[test]
+    fn record_fields_load_at_fixed_offsets() {
+        let fields = |source: &str| {
+            let program = compile_program(&parse_file(source).unwrap()).unwrap();
+            assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 5) if name == "_record_create")));
+            program
+                .instructions
+                .iter()
+                .filter_map(|inst| match inst {
+                    IRInstruction::LoadField(offset) => Some(*offset),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let records_only = "(defrecord Point [x y])\n(defn norm [p] (+ (:x p) (get p :y)))\n(defn -main [] (norm (assoc (->Point 3 4) :x 1)))";
+        assert_eq!(fields(records_only), vec![32, 48]);
+
+        // A plain map reaching the parameter, or a key outside the record, falls back to lookups
+        let mixed = "(defrecord Point [x y])\n(defn norm [p] (+ (:x p) (:y p)))\n(defn -main [] (+ (norm (->Point 3 4)) (norm {:x 1 :y 2}) (:x (assoc (->Point 1 2) :z 3))))";
+        assert_eq!(fields(mixed), Vec::<usize>::new());
+
+        let wrong_arity = parse_file("(defrecord Point [x y])\n(defn -main [] (->Point 1))").unwrap();
+        assert!(matches!(compile_program(&wrong_arity), Err(CompileError::ArityError(_, 2, 1))));
+    }
+
+    #[test]
+    fn protocol_calls_dispatch_statically_unless_the_record_type_is_unknown() {
+        let calls = |source: &str| {
+            let program = compile_program(&parse_file(source).unwrap()).unwrap();
+            // Calls made inside the generated dispatchers are left out
+            let mut in_dispatcher = false;
+            let mut called: Vec<String> = program
+                .instructions
+                .iter()
+                .filter_map(|inst| match inst {
+                    IRInstruction::DefineFunction(name, _, _) => {
+                        in_dispatcher = name.starts_with("area by ");
+                        None
+                    }
+                    IRInstruction::Call(name, _) if !in_dispatcher && name.starts_with("area") => Some(name.clone()),
+                    _ => None,
+                })
+                .collect();
+            called.sort();
+            called.dedup();
+            (
+                called,
+                program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_record_type_id")),
+            )
+        };
+        let declarations =
+            "(defprotocol Shape (area [this]))\n(defrecord Square [side])\n(defrecord Circle [r])\n(extend-type Square Shape (area [s] (:side s)))\n(extend-type Number Shape (area [n] n))";
+
+        let known = format!("{}\n(defn -main [] (+ (area (->Square 2)) (area 3)))", declarations);
+        assert_eq!(calls(&known), (vec!["area for Number".to_string(), "area for Square".to_string()], true));
+        // Either record can reach `twice`, so it goes through the table on the runtime type id
+        let mixed = format!("{}\n(defn twice [s] (* 2 (area s)))\n(defn -main [] (+ (twice (->Square 2)) (twice (->Circle 1))))", declarations);
+        assert!(calls(&mixed).0.contains(&"area by record type/1".to_string()));
+        // An element of a mixed vector has no static kind, so the call dispatches on its runtime tag
+        let untyped = format!("{}\n(defn -main [] (area (get [(->Square 2) 2] 1)))", declarations);
+        assert_eq!(calls(&untyped).0, vec!["area by type/1".to_string()]);
+        let program = compile_program(&parse_file(&untyped).unwrap()).unwrap();
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_value_tag")));
+
+        let unknown_type = parse_file("(defprotocol Shape (area [this]))\n(extend-type Triangle Shape (area [t] 0))").unwrap();
+        assert!(matches!(compile_program(&unknown_type), Err(CompileError::InvalidExpression(_))));
+        let variadic = parse_file("(defmulti f :k)\n(defmethod f :a [& xs] 0)\n(defn -main [] 0)").unwrap();
+        assert!(matches!(compile_program(&variadic), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn match_compiles_to_a_decision_tree_and_warns_about_unreachable_clauses() {
+        let compile = |source: &str| compile_program(&parse_file(source).unwrap());
+        let runtime_calls = |program: &IRProgram, runtime: &str| {
+            program
+                .instructions
+                .iter()
+                .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, _) if name == runtime))
+                .count()
+        };
+
+        // Keyword literals dispatch through a case jump table, keys through contains?
+        let keywords = compile("(defn f [k] (match k :a 1 :b 2 _ 3))\n(defn -main [] (f :a))").unwrap();
+        assert!(keywords.instructions.iter().any(|inst| matches!(inst, IRInstruction::JumpTable(_))));
+        assert!(keywords.warnings.is_empty());
+        let maps = compile("(defn -main [] (match {:k 1} {:j v} v _ 0))").unwrap();
+        assert_eq!(runtime_calls(&maps, "_map_contains"), 1);
+        // Maps of other shapes reach the same function, so even a key some caller has is checked
+        let shapes = compile("(defn f [m] (match m {:a a} 1 _ 0))\n(defn -main [] (+ (f {:a 1}) (f {:b 2})))").unwrap();
+        assert_eq!(runtime_calls(&shapes, "_map_contains"), 1);
+        // Both clauses test the count of the same vector, and only the first binds its item
+        let vectors = compile("(defn f [v] (match v [x] x [x y] (+ x y) _ 0))\n(defn -main [] (f [1 2]))").unwrap();
+        assert_eq!(runtime_calls(&vectors, "_vector_count"), 2);
+
+        let unreachable = compile("(defn -main [] (match 3 x x 2 1 [a] a))").unwrap();
+        assert_eq!(
+            unreachable.warnings,
+            vec![
+                "match clause 2 is unreachable: earlier clauses match every value it matches".to_string(),
+                "match clause 3 is unreachable: earlier clauses match every value it matches".to_string(),
+            ]
+        );
+        // A guard can fail, so the clauses after it stay reachable
+        assert!(compile("(defn -main [] (match 3 x :when (> x 1) x _ 0))").unwrap().warnings.is_empty());
+
+        assert!(matches!(compile("(defn f [x] (match x [a] a _ 0))\n(defn -main [] 0)"), Err(CompileError::InvalidExpression(_))));
+        // Callers passing a number and a vector leave the parameter untyped instead of never settling
+        let mixed = "(defn f [x] (match x [a] a n n))\n(defn -main [] (+ (f 5) (f [1])))";
+        assert!(matches!(compile(mixed), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile("(defn -main [] (match 3 [a a] a))"), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn atoms_type_their_values_and_release_what_they_replace() {
+        let compile = |source: &str| compile_program(&parse_file(source).unwrap());
+        let calls = |program: &IRProgram, runtime: &str| {
+            program
+                .instructions
+                .iter()
+                .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, _) if name == runtime))
+                .count()
+        };
+
+        // The atom's value kind picks the runtime for the deref'd string, and both atoms are released
+        let strings = compile("(defn -main [] (let [a (atom \"x\") b a s (swap! a str \"y\")] (count @b)))").unwrap();
+        assert_eq!(calls(&strings, "_atom_reset"), 1);
+        assert_eq!(calls(&strings, "_string_count"), 1);
+        assert_eq!(calls(&strings, "_atom_retain"), 2);
+        let released = strings
+            .instructions
+            .iter()
+            .filter(|inst| matches!(inst, IRInstruction::FreeLocalWithRuntime(_, name) if name == "_atom_free"))
+            .count();
+        assert_eq!(released, 3);
+
+        // Watches are defn functions of four arguments that see the atom's value kind
+        let watched = compile("(defn w [k r old new] (count new))\n(defn -main [] (let [a (atom \"x\") b (add-watch a :k w)] (count (reset! b \"yz\"))))").unwrap();
+        assert_eq!(calls(&watched, "_atom_add_watch"), 1);
+        assert_eq!(calls(&watched, "_string_count"), 2);
+
+        assert!(matches!(compile("(defn -main [] (let [a (atom 1)] (reset! a \"x\")))"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile("(defn -main [] (deref 1))"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(
+            compile("(defn w [k r] 0)\n(defn -main [] (add-watch (atom 1) :k w))"),
+            Err(CompileError::InvalidExpression(_))
+        ));
+    }
+
     #[test]
     fn count_get_on_map_literal_uses_set_runtime() {
         let program = compile_expression("(count (get {:nums #{1 2 3}} :nums))").unwrap();
@@ -944,6 +1322,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compile_unchecked_arithmetic() {
+        let program = compile_expression("(unchecked-multiply (unchecked-add 1 2) (unchecked-subtract 5 3))").unwrap();
+        assert_eq!(
+            program.instructions,
+            vec![
+                IRInstruction::Push(1),
+                IRInstruction::Push(2),
+                IRInstruction::UncheckedAdd,
+                IRInstruction::Push(5),
+                IRInstruction::Push(3),
+                IRInstruction::UncheckedSub,
+                IRInstruction::UncheckedMul,
+                IRInstruction::Return
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_if_true() {
         let program = compile_expression("(if (> 5 3) 42 0)").unwrap();
@@ -1019,6 +1415,221 @@ mod tests {
         assert!(!program.instructions.is_empty());
     }
 
+    #[test]
+    fn test_compile_case_dispatch() {
+        let dense = compile_expression("(case 3 1 :a (2 3) :b 5 :c :d)").unwrap();
+        let table = dense.instructions.iter().find_map(|inst| match inst {
+            IRInstruction::JumpTable(targets) => Some(targets.clone()),
+            _ => None,
+        });
+        // Entries cover 1..=5, with the gap at 4 going to the default arm
+        let targets = table.expect("dense integer case should use a jump table");
+        assert_eq!(targets.len(), 5);
+        assert_eq!(targets[1], targets[2]);
+        assert_ne!(targets[3], targets[0]);
+
+        let keywords = compile_expression("(case :b :a 1 :b 2 0)").unwrap();
+        assert!(keywords.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 2) if name == "_string_hash")));
+        assert!(keywords.instructions.iter().any(|inst| matches!(inst, IRInstruction::JumpTable(_))));
+        // An untyped target may hold a number, so it is checked before it is hashed
+        let checks_text = |program: &IRProgram| {
+            program
+                .instructions
+                .iter()
+                .any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_string_readable"))
+        };
+        assert!(!checks_text(&keywords));
+        assert!(checks_text(&compile_expression("(case (get [5 :b] 0) :a 1 :b 2 9)").unwrap()));
+
+        let sparse = compile_expression("(case 7 1 :a 100000 :b :c)").unwrap();
+        assert!(!sparse.instructions.iter().any(|inst| matches!(inst, IRInstruction::JumpTable(_))));
+
+        assert!(matches!(compile_expression("(case 1 1 :a :b 2)"), Err(CompileError::InvalidExpression(_))));
+
+        // A string target is never compared against keyword constants, whatever their text
+        let compares = |source: &str| {
+            compile_expression(source)
+                .unwrap()
+                .instructions
+                .iter()
+                .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 2) if name == "_string_equals"))
+                .count()
+        };
+        assert_eq!(compares("(case \":a\" :a 1 \"b\" 2 3)"), 1);
+        assert_eq!(compares("(case :a :a 1 \"b\" 2 3)"), 1);
+        assert!(compile_expression("(case (get [nil :a] 0) nil 0 :a 1 2)").is_ok());
+    }
+
+    #[test]
+    fn test_compile_derived_forms() {
+        assert!(compile_expression("(if (> 2 1) 5)").is_ok());
+        assert!(compile_expression("(cond (< 1 2) 1 :else 2)").is_ok());
+        assert!(compile_expression("(when-let [x (+ 1 2)] (-> x (* 2) (+ 1)))").is_ok());
+        assert!(matches!(compile_expression("(if 1)"), Err(CompileError::ArityError(_, 3, 1))));
+        assert!(matches!(compile_expression("(when 1)"), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn test_compile_macros() {
+        let with_macros = parse_file("(defmacro unless [c t e] `(if ~c ~e ~t))\n(defmacro twice [x] `(let [v# ~x] (+ v# v#)))\n(defn -main [] (unless false (twice 3) 0))").unwrap();
+        let expanded = parse_file("(defn -main [] (if false 0 (let [v 3] (+ v v))))").unwrap();
+        let program = compile_program(&with_macros).unwrap();
+        assert_eq!(program.instructions, compile_program(&expanded).unwrap().instructions);
+        assert!(program.functions.iter().all(|f| f.name != "unless" && f.name != "twice"));
+        assert!(matches!(compile_expression("(let [x 1] (defmacro m [] 1))"), Err(CompileError::UnsupportedOperation(_))));
+    }
+
+    #[test]
+    fn test_compile_quoted_lists() {
+        let program = compile_expression("(count '(a b a))").unwrap();
+        // Quoted symbols are rodata strings stored without a clone; the list itself is built at runtime
+        assert!(program.instructions.iter().all(|inst| !matches!(inst, IRInstruction::RuntimeCall(name, _) if name == "_string_clone")));
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 3) if name == "_vector_create")));
+        assert_eq!(program.string_literals, vec!["a".to_string(), "b".to_string()]);
+
+        let expressions = parse_file("(defn second-item [xs] (first (rest xs)))\n(defn -main [] (if (= (second-item (cons 'a '(b))) 'b) 0 1))").unwrap();
+        let program = compile_program(&expressions).unwrap();
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 3) if name == "_list_cons")));
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_list_rest")));
+        // The parameter is inferred to be a list of symbols, so `=` compares names
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 2) if name == "_string_equals")));
+
+        assert!(matches!(compile_expression("(first 1)"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(symbol 1)"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(quote a b)"), Err(CompileError::ArityError(_, 1, 2))));
+    }
+
+    #[test]
+    fn test_compile_sequence_library() {
+        let expressions = parse_file("(defn shout [s] (str s \"!\"))\n(defn add ([] 0) ([a b] (+ a b)))\n(defn -main [] (+ (count (map shout \"ab\")) (reduce add [])))").unwrap();
+        let program = compile_program(&expressions).unwrap();
+        // Callbacks are passed by address; the empty reduce falls back to the zero-argument arity
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::PushFunction(name) if name == "shout")));
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::PushFunction(name) if name == "add/0")));
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 2) if name == "_seq_items")));
+        // The mapped strings are owned by the result, which lives in a slot freed with `_vector_free`
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_vector_clone")));
+        assert!(program
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 5) if name == "_seq_reduce_first")));
+
+        let program = compile_expression("(count (concat [1] (range 3) (take 1 '(4 5))))").unwrap();
+        let concats = program
+            .instructions
+            .iter()
+            .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 4) if name == "_seq_concat"))
+            .count();
+        assert_eq!(concats, 4);
+
+        assert!(matches!(compile_expression("(map 1 [1])"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(map missing [1])"), Err(CompileError::UndefinedVariable(_))));
+        assert!(matches!(compile_expression("(range 1 2 3 4)"), Err(CompileError::ArityError(..))));
+        assert!(matches!(compile_expression("(into 1 [1])"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(into {} [1 2])"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(frequencies [(range)])"), Err(CompileError::InvalidExpression(_))));
+        let wrong_arity = parse_file("(defn pair [a b] a)\n(defn -main [] (count (map pair [1])))").unwrap();
+        assert!(matches!(compile_program(&wrong_arity), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn test_compile_lazy_sequences() {
+        let calls = |program: &IRProgram, runtime: &str| {
+            program
+                .instructions
+                .iter()
+                .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, _) if name == runtime))
+                .count()
+        };
+
+        let expressions = parse_file("(defn twice [x] (* x 2))\n(defn -main [] (count (take 3 (map twice (iterate twice 1)))))").unwrap();
+        let program = compile_program(&expressions).unwrap();
+        // Lazy sources stay lazy through map and take
+        assert_eq!(calls(&program, "_lazy_iterate"), 1);
+        assert_eq!(calls(&program, "_lazy_map"), 1);
+        assert_eq!(calls(&program, "_lazy_take"), 1);
+        assert_eq!(calls(&program, "_lazy_count"), 1);
+        assert!(program
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, IRInstruction::FreeLocalWithRuntime(_, name) if name == "_lazy_free")));
+
+        let program = compile_expression("(first (drop 2 (range)))").unwrap();
+        assert_eq!(calls(&program, "_lazy_range"), 1);
+        assert_eq!(calls(&program, "_lazy_drop"), 1);
+        assert_eq!(calls(&program, "_lazy_first"), 1);
+
+        // A temporary read once lets its items go as it is realized; a local may be read again
+        let program = compile_expression("(nth (range) 5)").unwrap();
+        assert_eq!(calls(&program, "_lazy_stream"), 1);
+        let program = compile_expression("(let [s (range)] (+ (nth s 5) (nth s 1)))").unwrap();
+        assert_eq!(calls(&program, "_lazy_stream"), 0);
+        // until its last read
+        assert_eq!(calls(&program, "_seq_nth"), 1);
+        assert_eq!(calls(&program, "_seq_nth_last_read"), 1);
+
+        // A fold to a number walks the sequence instead of realizing it
+        let expressions = parse_file("(defn step [acc x] (+ acc x))\n(defn -main [] (reduce step 0 (take 5 (range))))").unwrap();
+        let program = compile_program(&expressions).unwrap();
+        assert_eq!(calls(&program, "_lazy_items"), 0);
+        assert_eq!(calls(&program, "_seq_reduce_last_read"), 1);
+
+        let program = compile_expression("(count (take 4 (cycle [1 2])))").unwrap();
+        assert_eq!(calls(&program, "_lazy_cycle"), 1);
+        let program = compile_expression("(count (repeat 3 \"a\"))").unwrap();
+        assert_eq!(calls(&program, "_lazy_repeat"), 1);
+        assert_eq!(calls(&program, "_lazy_count"), 1);
+
+        assert!(matches!(compile_expression("(repeat)"), Err(CompileError::ArityError(..))));
+        assert!(matches!(compile_expression("(iterate 1 2)"), Err(CompileError::InvalidExpression(_))));
+    }
+
+    #[test]
+    fn test_compile_seq_functions() {
+        let calls = |program: &IRProgram, runtime: &str| {
+            program
+                .instructions
+                .iter()
+                .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, _) if name == runtime))
+                .count()
+        };
+
+        let program = compile_expression("(let [s #{1 2}] (if (empty? s) 0 (first s)))").unwrap();
+        assert_eq!(calls(&program, "_seq_empty"), 1);
+        assert_eq!(calls(&program, "_seq_first"), 1);
+
+        let program = compile_expression("(count (next \"abc\"))").unwrap();
+        assert_eq!(calls(&program, "_seq_next"), 1);
+        let program = compile_expression("(+ (nth [1 2] 5 0) (last [1 2]))").unwrap();
+        assert_eq!(calls(&program, "_seq_nth"), 1);
+        assert_eq!(calls(&program, "_seq_last"), 1);
+        let program = compile_expression("(count (seq (range 3)))").unwrap();
+        assert_eq!(calls(&program, "_seq_of"), 1);
+
+        assert!(matches!(compile_expression("(nth [1])"), Err(CompileError::ArityError(..))));
+    }
+
+    #[test]
+    fn test_compile_let_destructuring() {
+        let program = compile_expression("(let [{:keys [a b] :or {b 2}} {:a 1}] (+ a b))").unwrap();
+        // Each key becomes a map lookup, with the :or default passed to get
+        let lookups = program
+            .instructions
+            .iter()
+            .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, _) if name == "_map_get"))
+            .count();
+        assert_eq!(lookups, 2);
+
+        let expressions = vec![
+            AstParser::parse_sexp_new_domain("(defn sum-pair [[a b]] (+ a b))".as_bytes(), &mut 0),
+            AstParser::parse_sexp_new_domain("(sum-pair [1 2])".as_bytes(), &mut 0),
+        ];
+        let program = compile_program(&expressions).unwrap();
+        assert!(program.functions.iter().any(|f| f.name == "sum-pair" && f.param_count == 1));
+
+        assert!(matches!(compile_expression("(let [[a &] [1]] a)"), Err(CompileError::InvalidExpression(_))));
+    }
+
     #[test]
     fn test_compile_let_error_cases() {
         // Wrong arity
@@ -1080,6 +1691,72 @@ mod tests {
         assert!(program.instructions.contains(&IRInstruction::Push(5)));
     }
 
+    #[test]
+    fn test_compile_variadic_function_call() {
+        let expressions = vec![
+            AstParser::parse_sexp_new_domain("(defn total [base & parts] (+ base (count parts)))".as_bytes(), &mut 0),
+            AstParser::parse_sexp_new_domain("(total 1 2 3)".as_bytes(), &mut 0),
+        ];
+
+        let program = compile_program(&expressions).unwrap();
+
+        assert!(program.instructions.iter().any(|inst| matches!(
+            inst,
+            IRInstruction::DefineFunction(name, param_count, _)
+            if name == "total" && *param_count == 2
+        )));
+
+        // Extra arguments are packed into a vector before the call
+        let create_pos = program
+            .instructions
+            .iter()
+            .position(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 3) if name == "_vector_create"))
+            .expect("expected rest arguments to be packed into a vector");
+        let call_pos = program
+            .instructions
+            .iter()
+            .position(|inst| matches!(inst, IRInstruction::Call(name, 2) if name == "total"))
+            .expect("expected call with fixed argument and rest vector");
+        assert!(create_pos < call_pos);
+
+        let too_few = vec![
+            AstParser::parse_sexp_new_domain("(defn total [base & parts] base)".as_bytes(), &mut 0),
+            AstParser::parse_sexp_new_domain("(total)".as_bytes(), &mut 0),
+        ];
+        assert!(matches!(compile_program(&too_few), Err(CompileError::ArityError(_, 1, 0))));
+    }
+
+    #[test]
+    fn test_compile_multi_arity_function() {
+        let expressions = vec![
+            AstParser::parse_sexp_new_domain("(defn f ([x] (f x 10)) ([x y] (+ x y)))".as_bytes(), &mut 0),
+            AstParser::parse_sexp_new_domain("(f 1)".as_bytes(), &mut 0),
+        ];
+
+        let program = compile_program(&expressions).unwrap();
+
+        // Each arity becomes its own function under a mangled symbol
+        assert!(program.functions.iter().any(|f| f.name == "f/1" && f.param_count == 1));
+        assert!(program.functions.iter().any(|f| f.name == "f/2" && f.param_count == 2));
+        assert!(!program.functions.iter().any(|f| f.name == "f"));
+
+        // Call sites resolve to the matching arity statically
+        assert!(program.instructions.contains(&IRInstruction::Call("f/1".to_string(), 1)));
+        assert!(program.instructions.contains(&IRInstruction::Call("f/2".to_string(), 2)));
+
+        let unsupported = vec![
+            AstParser::parse_sexp_new_domain("(defn f ([x] x) ([x y] y))".as_bytes(), &mut 0),
+            AstParser::parse_sexp_new_domain("(f 1 2 3)".as_bytes(), &mut 0),
+        ];
+        match compile_program(&unsupported) {
+            Err(CompileError::ArityError(op, 2, 3)) => assert_eq!(op, "f (available arities: 1, 2)"),
+            other => panic!("expected arity error, got {:?}", other),
+        }
+
+        assert!(matches!(compile_expression("(defn f ([x] x) ([y] y))"), Err(CompileError::InvalidExpression(_))));
+        assert!(matches!(compile_expression("(defn f ([x & xs] x) ([a b c] a))"), Err(CompileError::InvalidExpression(_))));
+    }
+
     #[test]
     fn test_clone_returned_local_string() {
         let program = compile_expression("(let [s (str \"a\" \"b\")] s)").unwrap();
@@ -1137,6 +1814,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_vector_parameter_grown_through_recursion_is_taken_over() {
+        let expressions = parse_file("(defn grow [v n] (if (= n 0) v (grow (conj v n) (- n 1)))) (defn -main [] (let [start [0]] (count (grow start 3))))").unwrap();
+        let program = compile_program(&expressions).unwrap();
+        let instructions = &program.instructions;
+
+        let grow_start = instructions
+            .iter()
+            .position(|inst| matches!(inst, IRInstruction::DefineFunction(name, 2, _) if name == "grow"))
+            .expect("expected grow");
+        let grow_body = &instructions[grow_start..];
+        // The parameter moves into a local slot, updated in place and handed back as the result
+        assert!(matches!(&grow_body[1..3], [IRInstruction::LoadParam(0), IRInstruction::StoreLocal(_)]), "{:?}", grow_body);
+        assert!(
+            grow_body.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 3) if name == "_vector_conj_owned")),
+            "{:?}",
+            grow_body
+        );
+        assert!(
+            grow_body.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_vector_clone_owned")),
+            "{:?}",
+            grow_body
+        );
+
+        // The caller's dead local is handed over rather than copied, and only the result is freed
+        let call_pos = instructions
+            .iter()
+            .position(|inst| matches!(inst, IRInstruction::Call(name, 2) if name == "grow"))
+            .expect("expected call to grow");
+        let before_call = &instructions[..call_pos];
+        assert!(
+            before_call.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_vector_clone_owned")),
+            "{:?}",
+            before_call
+        );
+        assert!(
+            !before_call.iter().any(|inst| matches!(inst, IRInstruction::FreeLocalWithRuntime(_, name) if name == "_vector_free")),
+            "{:?}",
+            before_call
+        );
+    }
+
     #[test]
     fn test_compile_program_top_level_expression_emits_return() {
         let expressions = vec![AstParser::parse_sexp_new_domain("(let [a 1] (+ a 2))".as_bytes(), &mut 0)];
@@ -1176,4 +1895,79 @@ mod tests {
         // Non-symbol parameter
         assert!(matches!(compile_expression("(defn add [x 123] (+ x 123))"), Err(CompileError::InvalidExpression(_))));
     }
+
+    #[test]
+    fn test_compile_try_catch_installs_handler() {
+        let program = compile_expression("(try (throw (ex-info \"boom\" {:n 1})) (catch Exception e (count (ex-message e))))").unwrap();
+        let landing = program.instructions.iter().find_map(|inst| match inst {
+            IRInstruction::PushHandler(landing) => Some(*landing),
+            _ => None,
+        });
+        let landing = landing.expect("expected handler to be installed");
+        assert!(matches!(program.instructions[landing], IRInstruction::StoreLocal(_)));
+        assert!(program.instructions[..landing].iter().any(|inst| matches!(inst, IRInstruction::PopHandler)));
+        assert!(program
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_exception_throw")));
+        assert!(program
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, IRInstruction::FreeLocalWithRuntime(_, name) if name == "_exception_free")));
+    }
+
+    #[test]
+    fn test_compile_catch_dispatches_on_class() {
+        let count_calls = |source: &str, runtime: &str| {
+            let program = compile_expression(source).unwrap();
+            program
+                .instructions
+                .iter()
+                .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == runtime))
+                .count()
+        };
+        let both = "(try (throw {:kind :plain}) (catch ExceptionInfo e 1) (catch :default e 2))";
+        assert_eq!(count_calls(both, "_exception_is_info"), 1);
+        assert_eq!(count_calls(both, "_exception_throw"), 1);
+        let info_only = "(try (throw {:kind :plain}) (catch ExceptionInfo e 1))";
+        assert_eq!(count_calls(info_only, "_exception_is_info"), 1);
+        assert_eq!(count_calls(info_only, "_exception_throw"), 2);
+        let shadowed = "(try (throw {:kind :plain}) (catch Exception e 1) (catch ExceptionInfo e 2))";
+        assert_eq!(count_calls(shadowed, "_exception_is_info"), 0);
+    }
+
+    #[test]
+    fn test_compile_finally_rethrows_after_cleanup() {
+        let program = compile_expression("(try (str \"a\") (finally (str \"b\")))").unwrap();
+        let rethrows = program
+            .instructions
+            .iter()
+            .filter(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_exception_throw"))
+            .count();
+        assert_eq!(rethrows, 1);
+        assert!(program.instructions.iter().any(|inst| matches!(inst, IRInstruction::PushHandler(_))));
+    }
+
+    #[test]
+    fn test_if_with_throw_takes_other_branch_kind() {
+        let mut context = CompileContext::new();
+        let mut program = IRProgram::new();
+        let node = AstParser::parse_sexp_new_domain(b"(if true (str \"ok\") (throw (ex-info \"bad\" {})))", &mut 0);
+        let result = compile_node(&node, &mut context, &mut program).unwrap();
+        assert_eq!(result.kind, ValueKind::String);
+        assert_eq!(result.heap_ownership, HeapOwnership::Owned);
+        assert!(!result.diverges);
+    }
+
+    #[test]
+    fn test_throw_takes_any_value() {
+        let program = compile_expression("(try (throw 42) (catch Exception e (+ e 1)))").unwrap();
+        assert!(program
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_exception_throw")));
+        // A value read out of the exception data has no static kind, so it is counted by its runtime tag
+        let counted = compile_expression("(try (throw (ex-info \"x\" {:acc [1 2 3]})) (catch Exception e (count (get (ex-data e) :acc))))").unwrap();
+        assert!(counted.instructions.iter().any(|inst| matches!(inst, IRInstruction::RuntimeCall(name, 1) if name == "_value_count")));
+    }
 }
@@ -0,0 +1,562 @@
+/// Protocols and multimethods: defprotocol, extend-type, defmulti and defmethod
+///
+/// `expand_program` rewrites them into plain functions before inference sees the program. Each
+/// method an `extend-type` implements becomes a function named `area for Point`. A protocol call
+/// picks its implementation at compile time from the kind of its first argument, and from its
+/// record type when inference proves one; a map of unknown record type calls `area by record
+/// type`, which jumps through a `case` table on the record's runtime type id to the record's own
+/// implementation or the map fallback. A first argument of unknown kind calls `area by type`,
+/// which does the same on the value's runtime tag. A multimethod becomes a function of its name that computes
+/// the dispatch value and jumps through a `case` table to the function built for each method.
+///
+/// Type names follow the interpreter: record names, `nil`, `Number`, `Boolean`, `String`,
+/// `Keyword`, `Symbol`, `Vector`, `List`, `Map` and `Set`, with `Object` implementing a method for
+/// every type without its own implementation.
+use super::{compile_node, functions, CompileContext, CompileError, CompileResult, ValueKind};
+use crate::ast::{Node, Primitive};
+use crate::ir::{IRInstruction, IRProgram};
+use std::collections::HashMap;
+
+/// Operator reading a map's record type id, 0 for other maps. No source symbol can spell it.
+pub(super) const RECORD_TYPE: &str = "record type of";
+
+/// Operator reading an untyped value's runtime tag. No source symbol can spell it.
+pub(super) const VALUE_TYPE: &str = "value type of";
+
+/// Type names `extend-type` accepts besides record names
+const BUILT_IN_TYPES: &[&str] = &["nil", "Number", "Boolean", "String", "Keyword", "Symbol", "Vector", "List", "Map", "Set", "Function", "Object"];
+
+/// The methods a protocol declares, each with the argument counts of its arities
+pub(super) struct Protocol {
+    pub name: String,
+    pub methods: Vec<(String, Vec<usize>)>,
+}
+
+/// Parse `(defprotocol Name (method [params] ...) ...)`, skipping docstrings
+pub(super) fn defprotocol_methods(args: &[Node]) -> Result<Protocol, CompileError> {
+    let Some((Node::Symbol { value: name }, specs)) = args.split_first() else {
+        return Err(CompileError::InvalidExpression("defprotocol requires a symbol as first argument".to_string()));
+    };
+    let mut methods: Vec<(String, Vec<usize>)> = Vec::new();
+    for spec in specs.iter().filter(|spec| !matches!(spec, Node::Primitive { .. })) {
+        let Some((Node::Symbol { value: method }, vectors)) = (match spec {
+            Node::List { root } => root.split_first(),
+            _ => None,
+        }) else {
+            return Err(CompileError::InvalidExpression(format!("defprotocol {}: methods must be lists of a name and parameter vectors", name)));
+        };
+        let mut counts = Vec::new();
+        for params in vectors.iter().filter(|node| !matches!(node, Node::Primitive { .. })) {
+            let parameters = functions::parse_parameters(params)?;
+            if parameters.names.is_empty() || parameters.rest.is_some() {
+                return Err(CompileError::InvalidExpression(format!(
+                    "defprotocol {}: {} takes parameter vectors of at least one fixed parameter",
+                    name, method
+                )));
+            }
+            if counts.contains(&parameters.names.len()) {
+                return Err(CompileError::InvalidExpression(format!(
+                    "defprotocol {}: {} declares more than one arity taking {} arguments",
+                    name,
+                    method,
+                    parameters.names.len()
+                )));
+            }
+            counts.push(parameters.names.len());
+        }
+        if counts.is_empty() {
+            return Err(CompileError::InvalidExpression(format!("defprotocol {}: {} declares no parameter vector", name, method)));
+        }
+        methods.push((method.clone(), counts));
+    }
+    Ok(Protocol { name: name.clone(), methods })
+}
+
+/// Name of the function implementing `method` for `type_name`
+pub(super) fn implementation_name(method: &str, type_name: &str) -> String {
+    format!("{} for {}", method, type_name)
+}
+
+/// Name of the function choosing a record's implementation of `method` at run time
+pub(super) fn record_dispatcher_name(method: &str) -> String {
+    format!("{} by record type", method)
+}
+
+/// Name of the function choosing the implementation of `method` from its first argument's
+/// runtime tag
+pub(super) fn type_dispatcher_name(method: &str) -> String {
+    format!("{} by type", method)
+}
+
+/// The type names whose implementations a value of runtime tag `tag` may take, most specific
+/// first. The runtime cannot tell lists from vectors, hash sets from maps or `false` from nil.
+fn tag_type_names(tag: u8) -> &'static [&'static str] {
+    match tag {
+        0 => &["nil"],
+        1 => &["Number"],
+        3 => &["String"],
+        4 => &["Vector", "List"],
+        5 => &["Map", "Set"],
+        6 => &["Keyword"],
+        7 => &["Set"],
+        10 => &["List"],
+        _ => &[],
+    }
+}
+
+/// Runtime tags `tag_type_names` knows, the order the `by type` dispatchers test them in
+const DISPATCH_TAGS: [u8; 8] = [0, 1, 3, 4, 5, 6, 7, 10];
+
+/// The type name protocol dispatch sees for values of `kind`, or None when the kind is unknown
+fn kind_type_name(kind: ValueKind) -> Option<&'static str> {
+    match kind {
+        ValueKind::Any => None,
+        ValueKind::Number => Some("Number"),
+        ValueKind::Boolean => Some("Boolean"),
+        ValueKind::String => Some("String"),
+        ValueKind::Keyword => Some("Keyword"),
+        ValueKind::Symbol => Some("Symbol"),
+        ValueKind::Vector => Some("Vector"),
+        ValueKind::List | ValueKind::LazySeq => Some("List"),
+        ValueKind::Map => Some("Map"),
+        ValueKind::Set => Some("Set"),
+        ValueKind::Atom => Some("Atom"),
+        ValueKind::Nil => Some("nil"),
+    }
+}
+
+/// The function a call to `method` runs when its first argument has `kind`, holding records of
+/// type `record` if that is known; `defined` tells which functions the program has. Err carries
+/// the type name to report when no implementation applies, None when `kind` is unknown and the
+/// method has no implementation to choose from at run time.
+pub(super) fn dispatch_target(method: &str, kind: ValueKind, record: Option<&str>, defined: impl Fn(&str) -> bool) -> Result<String, Option<String>> {
+    let Some(type_name) = kind_type_name(kind) else {
+        return Some(type_dispatcher_name(method)).filter(|name| defined(name)).ok_or(None);
+    };
+    let record = record.filter(|_| kind == ValueKind::Map);
+    let mut candidates = Vec::new();
+    match record {
+        Some(record) => candidates.push(implementation_name(method, record)),
+        None if kind == ValueKind::Map => candidates.push(record_dispatcher_name(method)),
+        None => {}
+    }
+    candidates.push(implementation_name(method, type_name));
+    candidates.push(implementation_name(method, "Object"));
+    candidates.into_iter().find(|candidate| defined(candidate)).ok_or_else(|| Some(record.unwrap_or(type_name).to_string()))
+}
+
+fn no_implementation_message(method: &str, protocol: &str, type_name: &str) -> String {
+    format!("No implementation of method: {} of protocol: {} found for: {}", method, protocol, type_name)
+}
+
+fn symbol(value: &str) -> Node {
+    Node::Symbol { value: value.to_string() }
+}
+
+fn list(root: Vec<Node>) -> Node {
+    Node::List { root }
+}
+
+/// `(throw (ex-info message {}))`
+fn throw_message(message: Node) -> Node {
+    list(vec![symbol("throw"), list(vec![symbol("ex-info"), message, Node::Map { entries: Vec::new() }])])
+}
+
+/// Parameter names of the functions this module generates
+fn arguments(count: usize) -> Vec<Node> {
+    (0..count).map(|index| symbol(&format!("argument {}", index))).collect()
+}
+
+/// The argument counts a function defined by `clauses` (a `defn` after its name) accepts, all fixed
+fn fixed_arities(form: &str, clauses: &[Node]) -> Result<Vec<usize>, CompileError> {
+    let vectors: Vec<&Node> = match clauses.first() {
+        Some(Node::List { .. }) => clauses
+            .iter()
+            .map(|clause| if let Node::List { root } = clause { root.first().unwrap_or(clause) } else { clause })
+            .collect(),
+        Some(params) if clauses.len() == 2 => vec![params],
+        _ => return Err(CompileError::InvalidExpression(format!("{} expects a parameter vector and a body", form))),
+    };
+    vectors
+        .into_iter()
+        .map(|params| {
+            let parameters = functions::parse_parameters(params)?;
+            match parameters.rest {
+                Some(_) => Err(CompileError::InvalidExpression(format!("{} cannot take variadic parameters", form))),
+                None => Ok(parameters.names.len()),
+            }
+        })
+        .collect()
+}
+
+struct Multimethod {
+    name: String,
+    dispatch: Node,
+    methods: Vec<(Node, Vec<Node>)>, // Dispatch value and the method's defn clauses
+}
+
+/// Rewrite extend-type, defmulti and defmethod into the functions that implement them. The
+/// defprotocol forms stay for the compiler to register their methods.
+pub(super) fn expand_program(forms: Vec<Node>) -> Result<Vec<Node>, CompileError> {
+    let mut records: Vec<String> = Vec::new();
+    let mut protocols: HashMap<String, Protocol> = HashMap::new();
+    let mut method_protocols: HashMap<String, String> = HashMap::new();
+    // Implementation functions by name, with their argument counts and position in `expanded`
+    let mut implementations: HashMap<String, (Vec<usize>, usize)> = HashMap::new();
+    let mut implemented_types: Vec<(String, String)> = Vec::new();
+    let mut multimethods: Vec<Multimethod> = Vec::new();
+    let mut expanded: Vec<Node> = Vec::new();
+
+    for form in forms {
+        let (head, args) = match &form {
+            Node::List { root } => match root.split_first() {
+                Some((Node::Symbol { value }, args)) => (value.as_str(), args),
+                _ => ("", &root[..0]),
+            },
+            _ => ("", &[][..]),
+        };
+        match head {
+            "defrecord" => {
+                if let Some(Node::Symbol { value }) = args.first() {
+                    if !records.contains(value) {
+                        records.push(value.clone());
+                    }
+                }
+                expanded.push(form);
+            }
+            "defprotocol" => {
+                let protocol = defprotocol_methods(args)?;
+                for (method, _) in &protocol.methods {
+                    method_protocols.insert(method.clone(), protocol.name.clone());
+                }
+                protocols.insert(protocol.name.clone(), protocol);
+                expanded.push(form);
+            }
+            "extend-type" => {
+                let Some((Node::Symbol { value: type_name }, specs)) = args.split_first() else {
+                    return Err(CompileError::InvalidExpression("extend-type requires a type name as first argument".to_string()));
+                };
+                if !BUILT_IN_TYPES.contains(&type_name.as_str()) && !records.contains(type_name) {
+                    return Err(CompileError::InvalidExpression(format!("extend-type: unknown type {}", type_name)));
+                }
+                let mut protocol: Option<&Protocol> = None;
+                for spec in specs {
+                    match spec {
+                        Node::Symbol { value } => {
+                            protocol = Some(
+                                protocols
+                                    .get(value)
+                                    .ok_or_else(|| CompileError::InvalidExpression(format!("extend-type: {} is not a protocol", value)))?,
+                            );
+                        }
+                        Node::List { root } => {
+                            let Some((Node::Symbol { value: method }, clauses)) = root.split_first() else {
+                                return Err(CompileError::InvalidExpression(format!(
+                                    "extend-type {}: method implementations must start with the method name",
+                                    type_name
+                                )));
+                            };
+                            let Some(protocol) = protocol else {
+                                return Err(CompileError::InvalidExpression(format!("extend-type {}: {} is not preceded by a protocol", type_name, method)));
+                            };
+                            let Some((_, declared)) = protocol.methods.iter().find(|(name, _)| name == method) else {
+                                return Err(CompileError::InvalidExpression(format!(
+                                    "extend-type {}: {} is not a method of protocol {}",
+                                    type_name, method, protocol.name
+                                )));
+                            };
+                            let form_name = format!("extend-type {} {}", type_name, method);
+                            let counts = fixed_arities(&form_name, clauses)?;
+                            if let Some(count) = counts.iter().find(|count| !declared.contains(count)) {
+                                return Err(CompileError::InvalidExpression(format!(
+                                    "{}: protocol {} declares no arity taking {} arguments",
+                                    form_name, protocol.name, count
+                                )));
+                            }
+
+                            // A later implementation for the same type replaces the earlier one
+                            let name = implementation_name(method, type_name);
+                            let defn = list([symbol("defn"), symbol(&name)].into_iter().chain(clauses.iter().cloned()).collect());
+                            match implementations.get_mut(&name) {
+                                Some((existing, position)) => {
+                                    *existing = counts;
+                                    expanded[*position] = defn;
+                                }
+                                None => {
+                                    implementations.insert(name, (counts, expanded.len()));
+                                    implemented_types.push((method.clone(), type_name.clone()));
+                                    expanded.push(defn);
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(CompileError::InvalidExpression(format!(
+                                "extend-type {}: expected a protocol name or a method implementation",
+                                type_name
+                            )))
+                        }
+                    }
+                }
+            }
+            "defmulti" => {
+                let args: Vec<&Node> = args.iter().filter(|arg| !matches!(arg, Node::Primitive { value: Primitive::String(_) })).collect();
+                let [Node::Symbol { value: name }, dispatch] = args[..] else {
+                    return Err(CompileError::InvalidExpression("defmulti expects a name and a dispatch function".to_string()));
+                };
+                multimethods.retain(|multimethod| multimethod.name != *name);
+                multimethods.push(Multimethod {
+                    name: name.clone(),
+                    dispatch: dispatch.clone(),
+                    methods: Vec::new(),
+                });
+            }
+            "defmethod" => {
+                let Some((Node::Symbol { value: name }, [dispatch_value, clauses @ ..])) = args.split_first() else {
+                    return Err(CompileError::InvalidExpression(
+                        "defmethod expects a multimethod name, a dispatch value and a function body".to_string(),
+                    ));
+                };
+                let Some(multimethod) = multimethods.iter_mut().find(|multimethod| multimethod.name == *name) else {
+                    return Err(CompileError::InvalidExpression(format!("defmethod: {} is not a multimethod", name)));
+                };
+                multimethod.methods.retain(|(value, _)| value != dispatch_value);
+                multimethod.methods.push((dispatch_value.clone(), clauses.to_vec()));
+            }
+            _ => expanded.push(form),
+        }
+    }
+
+    // Methods implemented for some record get a function choosing among them by record type
+    let has_implementation = |name: &str, count: usize| implementations.get(name).is_some_and(|(counts, _)| counts.contains(&count));
+    let mut dispatched: Vec<&String> = Vec::new();
+    for (method, type_name) in &implemented_types {
+        if records.contains(type_name) && !dispatched.contains(&method) {
+            dispatched.push(method);
+        }
+    }
+    for method in dispatched {
+        let Some(protocol) = method_protocols.get(method).and_then(|protocol| protocols.get(protocol)) else {
+            continue;
+        };
+        let Some((_, counts)) = protocol.methods.iter().find(|(name, _)| name == method) else {
+            continue;
+        };
+        let fallback = |type_name: &str, params: &[Node], count: usize| {
+            ["Map", "Object"]
+                .iter()
+                .map(|fallback| implementation_name(method, fallback))
+                .find(|name| has_implementation(name, count))
+                .map(|name| list(std::iter::once(symbol(&name)).chain(params.iter().cloned()).collect()))
+                .unwrap_or_else(|| {
+                    throw_message(Node::Primitive {
+                        value: Primitive::String(no_implementation_message(method, &protocol.name, type_name)),
+                    })
+                })
+        };
+        let clauses = counts.iter().map(|count| {
+            let params = arguments(*count);
+            let mut body = vec![symbol("case"), list(vec![symbol(RECORD_TYPE), params[0].clone()])];
+            for (index, record) in records.iter().enumerate() {
+                let name = implementation_name(method, record);
+                let call = if has_implementation(&name, *count) {
+                    list(std::iter::once(symbol(&name)).chain(params.iter().cloned()).collect())
+                } else {
+                    fallback(record, &params, *count)
+                };
+                body.push(Node::new_number(index + 1));
+                body.push(call);
+            }
+            body.push(fallback("Map", &params, *count));
+            list(vec![Node::Vector { root: params }, list(body)])
+        });
+        expanded.push(list([symbol("defn"), symbol(&record_dispatcher_name(method))].into_iter().chain(clauses).collect()));
+    }
+
+    // Every implemented method gets a function choosing by runtime tag, for untyped first arguments
+    let mut implemented: Vec<&String> = Vec::new();
+    for (method, _) in &implemented_types {
+        if !implemented.contains(&method) {
+            implemented.push(method);
+        }
+    }
+    for method in implemented {
+        let Some(protocol) = method_protocols.get(method).and_then(|protocol| protocols.get(protocol)) else {
+            continue;
+        };
+        let Some((_, counts)) = protocol.methods.iter().find(|(name, _)| name == method) else {
+            continue;
+        };
+        let by_record = implemented_types.iter().any(|(name, type_name)| name == method && records.contains(type_name));
+        let clauses = counts.iter().map(|count| {
+            let params = arguments(*count);
+            let call = |name: String| list(std::iter::once(symbol(&name)).chain(params.iter().cloned()).collect());
+            let choose = |type_names: &[&str], reported: &str| {
+                type_names
+                    .iter()
+                    .chain(["Object"].iter())
+                    .map(|type_name| implementation_name(method, type_name))
+                    .find(|name| has_implementation(name, *count))
+                    .map(call)
+                    .unwrap_or_else(|| {
+                        throw_message(Node::Primitive {
+                            value: Primitive::String(no_implementation_message(method, &protocol.name, reported)),
+                        })
+                    })
+            };
+            let mut body = vec![symbol("case"), list(vec![symbol(VALUE_TYPE), params[0].clone()])];
+            for tag in DISPATCH_TAGS {
+                let type_names = tag_type_names(tag);
+                body.push(Node::new_number(tag as usize));
+                body.push(if tag == 5 && by_record {
+                    call(record_dispatcher_name(method))
+                } else {
+                    choose(type_names, type_names[0])
+                });
+            }
+            body.push(choose(&[], "Object"));
+            list(vec![Node::Vector { root: params.clone() }, list(body)])
+        });
+        expanded.push(list([symbol("defn"), symbol(&type_dispatcher_name(method))].into_iter().chain(clauses).collect()));
+    }
+
+    for multimethod in multimethods {
+        expanded.extend(expand_multimethod(multimethod)?);
+    }
+    Ok(expanded)
+}
+
+/// The functions of a multimethod: one per method, and one of its own name that computes the
+/// dispatch value and calls the matching method
+fn expand_multimethod(multimethod: Multimethod) -> Result<Vec<Node>, CompileError> {
+    let Multimethod { name, dispatch, methods } = multimethod;
+    let mut count = None;
+    for (_, clauses) in &methods {
+        let counts = fixed_arities(&format!("defmethod {}", name), clauses)?;
+        match (counts.as_slice(), count) {
+            ([single], None) => count = Some(*single),
+            ([single], Some(expected)) if *single == expected => {}
+            _ => return Err(CompileError::InvalidExpression(format!("defmethod {}: every method must take the same fixed parameters", name))),
+        }
+    }
+    let params = arguments(count.unwrap_or(1));
+    let dispatch_call = match &dispatch {
+        Node::Primitive { value: Primitive::Keyword(_) } if params.len() == 1 => list(vec![symbol("get"), params[0].clone(), dispatch.clone()]),
+        Node::Symbol { value } => list(std::iter::once(symbol(value)).chain(params.iter().cloned()).collect()),
+        _ => {
+            return Err(CompileError::InvalidExpression(format!(
+                "defmulti {}: the dispatch function must be a keyword or a named function",
+                name
+            )))
+        }
+    };
+
+    let dispatch_value = symbol("dispatch value");
+    let mut functions = Vec::new();
+    let mut arms = Vec::new();
+    let mut default = None;
+    for (index, (value, clauses)) in methods.into_iter().enumerate() {
+        let method_name = format!("{} method {}", name, index);
+        functions.push(list([symbol("defn"), symbol(&method_name)].into_iter().chain(clauses).collect()));
+        let call = list(std::iter::once(symbol(&method_name)).chain(params.iter().cloned()).collect());
+        match value {
+            Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == "default" => default = Some(call),
+            value => arms.push((value, call)),
+        }
+    }
+    let default = default.unwrap_or_else(|| {
+        let message = format!("No method in multimethod '{}' for dispatch value: ", name);
+        throw_message(list(vec![symbol("str"), Node::Primitive { value: Primitive::String(message) }, dispatch_value.clone()]))
+    });
+
+    // Constants case can jump on take a table; any other dispatch values are compared in turn
+    let numeric = arms.iter().all(|(value, _)| matches!(value, Node::Primitive { value: Primitive::Number(_) }));
+    let textual = arms.iter().all(|(value, _)| {
+        matches!(
+            value,
+            Node::Primitive {
+                value: Primitive::Keyword(_) | Primitive::String(_)
+            }
+        )
+    });
+    let body = if arms.is_empty() {
+        default
+    } else if numeric || textual {
+        let mut root = vec![symbol("case"), dispatch_value.clone()];
+        for (value, call) in arms {
+            root.extend([value, call]);
+        }
+        root.push(default);
+        list(root)
+    } else {
+        arms.into_iter().rev().fold(default, |otherwise, (value, call)| {
+            list(vec![symbol("if"), list(vec![symbol("="), dispatch_value.clone(), value]), call, otherwise])
+        })
+    };
+    let body = list(vec![
+        symbol("let"),
+        Node::Vector {
+            root: vec![dispatch_value, dispatch_call],
+        },
+        body,
+    ]);
+    functions.push(list(vec![symbol("defn"), symbol(&name), Node::Vector { root: params }, body]));
+    Ok(functions)
+}
+
+/// A defprotocol evaluates to nil; the compiler registers its methods before compiling anything
+pub(super) fn compile_defprotocol(args: &[Node]) -> Result<CompileResult, CompileError> {
+    defprotocol_methods(args)?;
+    Ok(CompileResult::with_instructions(vec![IRInstruction::Push(0)], ValueKind::Nil))
+}
+
+/// Compile `(record-type-of map)` through `_record_type_id`
+pub(super) fn compile_record_type(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let [map] = args else {
+        return Err(CompileError::ArityError(RECORD_TYPE.to_string(), 1, args.len()));
+    };
+    let mut map_result = compile_node(map, context, program)?;
+    let mut instructions = std::mem::take(&mut map_result.instructions);
+    instructions.push(IRInstruction::RuntimeCall("_record_type_id".to_string(), 1));
+    map_result.free_retained_slots(&mut instructions, context);
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Number))
+}
+
+/// Compile `(value-type-of value)` through `_value_tag`
+pub(super) fn compile_value_type(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let [value] = args else {
+        return Err(CompileError::ArityError(VALUE_TYPE.to_string(), 1, args.len()));
+    };
+    let mut value_result = compile_node(value, context, program)?;
+    let mut instructions = std::mem::take(&mut value_result.instructions);
+    instructions.push(IRInstruction::RuntimeCall("_value_tag".to_string(), 1));
+    value_result.free_retained_slots(&mut instructions, context);
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Number))
+}
+
+/// Compile a call to a protocol method, calling the implementation for its first argument's type
+pub(super) fn compile_protocol_call(method: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let protocol = context.get_protocol(method).unwrap_or_default().to_string();
+    let Some((first, rest)) = args.split_first() else {
+        return Err(CompileError::ArityError(method.to_string(), 1, 0));
+    };
+    let mut first_result = compile_node(first, context, program)?;
+    let defined = |name: &str| context.get_function(name).is_some() || context.get_function_arities(name).is_some();
+    match dispatch_target(method, first_result.kind, first_result.record_type.as_deref(), defined) {
+        Ok(target) => match functions::resolve_call_target(&target, args.len(), context)? {
+            Some((symbol, param_count)) => functions::compile_call(&symbol, Some(first_result), rest, context, program, param_count),
+            None => Err(CompileError::UnsupportedOperation(target)),
+        },
+        Err(Some(type_name)) => {
+            // The argument is never used, so only its slots need releasing
+            first_result.free_retained_slots(&mut Vec::new(), context);
+            let message = Node::Primitive {
+                value: Primitive::String(no_implementation_message(method, &protocol, &type_name)),
+            };
+            compile_node(&throw_message(message), context, program)
+        }
+        Err(None) => Err(CompileError::UnsupportedOperation(format!(
+            "{}: cannot choose an implementation for a first argument of unknown type",
+            method
+        ))),
+    }
+}
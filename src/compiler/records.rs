@@ -0,0 +1,208 @@
+/// Records: defrecord, ->Name constructors and field reads at fixed offsets
+///
+/// `defrecord` registers a layout naming the record's fields in declaration order, and the
+/// `->Name` constructor it declares builds the record through `_record_create`. Records are `Map`
+/// values every map form accepts; the compiler only needs to know an expression's record type to
+/// read a field with a literal keyword straight from its offset instead of hashing the key.
+///
+/// An expression carries a record type only when every value it can produce is a record of that
+/// type: constructor calls, `assoc` of declared fields onto such a record, and locals, parameters
+/// and function results that type inference proves always hold one.
+use super::{
+    builtins::{ensure_owned_on_stack, literal_map_key, resolve_value_kind},
+    compile_node, extend_with_offset,
+    slots::SlotTracker,
+    types::{nested_map_value_types, set_map_value_type},
+    CompileContext, CompileError, CompileResult, HeapOwnership, MapKeyLiteral, MapValueTypes, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Byte offset of the first field: the runtime's record header holds the length, flags, type id
+/// and layout string
+const FIELD_BASE: usize = 32;
+/// Each field is a value word followed by its tag word
+const FIELD_SIZE: usize = 16;
+
+/// The fields of a record type and the id the runtime tells it apart by
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordLayout {
+    pub type_id: usize,
+    pub fields: Vec<String>,
+}
+
+impl RecordLayout {
+    /// Position of the field a literal keyword names
+    pub fn field_index(&self, key: &Node) -> Option<usize> {
+        match literal_map_key(key)? {
+            MapKeyLiteral::Keyword(name) => self.fields.iter().position(|field| *field == name),
+            _ => None,
+        }
+    }
+}
+
+/// Split `(defrecord Name [field ...])` into the record's name and field names
+pub(super) fn defrecord_fields(args: &[Node]) -> Result<(String, Vec<String>), CompileError> {
+    let [Node::Symbol { value: name }, Node::Vector { root }] = args else {
+        return Err(CompileError::InvalidExpression("defrecord expects a name and a vector of fields".to_string()));
+    };
+    let fields = root
+        .iter()
+        .map(|field| match field {
+            Node::Symbol { value } => Ok(value.clone()),
+            _ => Err(CompileError::InvalidExpression(format!("defrecord {} fields must be symbols", name))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((name.clone(), fields))
+}
+
+/// Register a record layout; the definition itself evaluates to nil
+pub(super) fn compile_defrecord(args: &[Node], context: &mut CompileContext) -> Result<CompileResult, CompileError> {
+    let (name, fields) = defrecord_fields(args)?;
+    context.add_record(&name, fields)?;
+    Ok(CompileResult::with_instructions(vec![IRInstruction::Push(0)], ValueKind::Nil))
+}
+
+/// The record type a `->Name` constructor builds, if `op` is one
+pub(super) fn constructor_record<'a>(op: &'a str, context: &CompileContext) -> Option<&'a str> {
+    op.strip_prefix("->").filter(|name| context.get_record(name).is_some())
+}
+
+/// Compile `(->Name value ...)`, taking one value per field in declaration order
+pub(super) fn compile_record_constructor(name: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let layout = context.get_record(name).cloned().ok_or_else(|| CompileError::UndefinedVariable(format!("->{}", name)))?;
+    if args.len() != layout.fields.len() {
+        return Err(CompileError::ArityError(format!("->{}", name), layout.fields.len(), args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut map_value_types = MapValueTypes::new();
+    let count = layout.fields.len();
+
+    let value_slots = context.allocate_contiguous_temp_slots(count);
+    let mut ordered_value_slots = value_slots.clone();
+    ordered_value_slots.sort_unstable();
+    ordered_value_slots.reverse();
+
+    let tag_slots = context.allocate_contiguous_temp_slots(count);
+    let mut ordered_tag_slots = tag_slots.clone();
+    ordered_tag_slots.sort_unstable();
+    ordered_tag_slots.reverse();
+
+    for (idx, (field, value_node)) in layout.fields.iter().zip(args).enumerate() {
+        let value_slot = ordered_value_slots[idx];
+        let tag_slot = ordered_tag_slots[idx];
+
+        let mut value_result = compile_node(value_node, context, program)?;
+        let value_kind = resolve_value_kind(value_node, value_result.kind, context);
+        let value_instructions = std::mem::take(&mut value_result.instructions);
+        extend_with_offset(&mut instructions, value_instructions);
+        ensure_owned_on_stack(&mut instructions, value_kind, &mut value_result.heap_ownership);
+        instructions.push(IRInstruction::StoreLocal(value_slot));
+        instructions.push(IRInstruction::Push(value_kind.runtime_tag()));
+        instructions.push(IRInstruction::StoreLocal(tag_slot));
+        if value_kind.is_heap_kind() {
+            instructions.push(IRInstruction::LoadLocal(value_slot));
+            instructions.push(IRInstruction::LoadLocal(tag_slot));
+            instructions.push(IRInstruction::RuntimeCall("_map_value_clone".to_string(), 2));
+            instructions.push(IRInstruction::StoreLocal(value_slot));
+        }
+        value_result.free_retained_slots(&mut instructions, context);
+
+        if value_kind != ValueKind::Any {
+            set_map_value_type(&mut map_value_types, MapKeyLiteral::Keyword(field.clone()), value_kind, value_result.map_value_types.as_ref());
+        }
+    }
+
+    let layout_string = std::iter::once(name.to_string())
+        .chain(layout.fields.iter().map(|field| format!(":{}", field)))
+        .collect::<Vec<_>>()
+        .join("\0");
+    instructions.push(IRInstruction::Push(layout.type_id as i64));
+    instructions.push(IRInstruction::PushString(program.add_string(layout_string)));
+    match (ordered_value_slots.first(), ordered_tag_slots.first()) {
+        (Some(values), Some(tags)) => {
+            instructions.push(IRInstruction::PushLocalAddress(*values));
+            instructions.push(IRInstruction::PushLocalAddress(*tags));
+        }
+        _ => {
+            instructions.push(IRInstruction::Push(0));
+            instructions.push(IRInstruction::Push(0));
+        }
+    }
+    instructions.push(IRInstruction::Push(count as i64));
+    instructions.push(IRInstruction::RuntimeCall("_record_create".to_string(), 5));
+
+    value_slots.into_iter().for_each(|slot| context.release_temp_slot(slot));
+    tag_slots.into_iter().for_each(|slot| context.release_temp_slot(slot));
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Map)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_map_value_types(Some(map_value_types).filter(|types| !types.is_empty()))
+        .with_record_type(Some(name.to_string())))
+}
+
+/// The field a `(get record :field)` on a known record type reads, if the key names one
+pub(super) fn field_get_index(target: &CompileResult, args: &[Node], context: &CompileContext) -> Option<usize> {
+    let [_, key] = args else {
+        return None;
+    };
+    let layout = context.get_record(target.record_type.as_deref()?)?;
+    layout.field_index(key)
+}
+
+/// Read field `index` of a compiled record expression. A field known to hold a scalar is a single
+/// load; anything else loads the value and its tag and clones it like `_map_get` results.
+pub(super) fn compile_field_get(mut target: CompileResult, index: usize, context: &mut CompileContext) -> CompileResult {
+    let layout = target.record_type.as_deref().and_then(|name| context.get_record(name));
+    let key = layout.map(|layout| MapKeyLiteral::Keyword(layout.fields[index].clone()));
+    let field_kind = key
+        .as_ref()
+        .zip(target.map_value_types.as_ref())
+        .and_then(|(key, types)| types.get(key).copied())
+        .unwrap_or(ValueKind::Any);
+    let nested_types = match field_kind {
+        ValueKind::Map => key.as_ref().zip(target.map_value_types.as_ref()).and_then(|(key, types)| nested_map_value_types(types, key)),
+        _ => None,
+    };
+
+    let offset = FIELD_BASE + index * FIELD_SIZE;
+    let mut instructions = std::mem::take(&mut target.instructions);
+    let mut tracker = SlotTracker::new();
+    let owned_slot = tracker.track_if_owned(&mut instructions, context, target.heap_ownership, ValueKind::Map);
+
+    let ownership = if field_kind != ValueKind::Any && !field_kind.is_heap_kind() {
+        instructions.push(IRInstruction::LoadField(offset));
+        HeapOwnership::None
+    } else {
+        let slot = owned_slot.unwrap_or_else(|| {
+            let slot = context.allocate_temp_slot();
+            instructions.push(IRInstruction::StoreLocal(slot));
+            instructions.push(IRInstruction::LoadLocal(slot));
+            slot
+        });
+        instructions.push(IRInstruction::LoadField(offset));
+        instructions.push(IRInstruction::LoadLocal(slot));
+        instructions.push(IRInstruction::LoadField(offset + 8));
+        instructions.push(IRInstruction::RuntimeCall("_map_value_clone".to_string(), 2));
+        if owned_slot.is_none() {
+            context.release_temp_slot(slot);
+        }
+        HeapOwnership::Owned
+    };
+
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+    target.free_retained_slots(&mut instructions, context);
+
+    CompileResult::with_instructions(instructions, field_kind)
+        .with_heap_ownership(ownership)
+        .with_map_value_types(nested_types)
+}
+
+/// The record type `(assoc record key value ...)` keeps: only literal keywords naming the
+/// record's own fields leave it a record
+pub(super) fn assoc_record_type(record: Option<String>, args: &[Node], context: &CompileContext) -> Option<String> {
+    let layout = context.get_record(record.as_deref()?)?;
+    let keeps_fields = args[1..].iter().step_by(2).all(|key| layout.field_index(key).is_some());
+    record.filter(|_| keeps_fields)
+}
@@ -0,0 +1,956 @@
+/// Higher-order sequence library: map, filter, remove, reduce, range, into, every?, some, take,
+/// drop, concat, reverse, sort, sort-by, frequencies and group-by, plus the lazy sequences built by iterate, repeat, cycle
+/// and the zero-argument range, and the seq functions next, seq, nth, last and empty? (with first
+/// and rest over sets, strings, maps and lazy sequences)
+///
+/// Every form lowers to a `_seq_*` runtime helper. Callbacks must name a `defn` function and are
+/// passed by address; the runtime learns how to treat their results from a `result_mode` (the result
+/// tag plus an owned bit). Strings and maps are first expanded with `_seq_items` into a list that
+/// owns their characters or `[key value]` entries, so the helpers only ever see borrowed items.
+///
+/// Results that share items keep the retained slots of their sources alive. When a result owns
+/// callback results (a `map` over an owning callback, every `group-by`), the runtime marks it as
+/// owning its elements; that value lives in a retained slot and the program gets a shallow clone, so
+/// the elements are released together with the slot.
+///
+/// Lazy sequences are reference-counted runtime values (`_lazy_*`) that clone whatever they need,
+/// so they are released like any other owned heap value. `map`, `filter`, `remove`, `take` and
+/// `drop` over a lazy sequence build another one, and `reduce` with a callback returning scalars
+/// walks it in place; every other consumer realizes the whole sequence first.
+///
+/// The seq functions use the runtime seq protocol (`_seq_first`, `_seq_next`, ...), which walks any
+/// tagged collection. Items they return are borrowed, so an owned lazy sequence (or the list a
+/// string or map was expanded into) stays in a retained slot of the result.
+use super::{
+    builtins::{clone_runtime_for_kind, free_retained_slot, resolve_value_kind, track_heap_slot},
+    compile_node,
+    expressions::dedup_retained_slots,
+    extend_with_offset,
+    functions::resolve_call_target,
+    slots::SlotTracker,
+    CompileContext, CompileError, CompileResult, HeapOwnership, MapKeyLiteral, MapValueTypes, RetainedSlot, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Mirrors `RESULT_OWNED` in the runtime sequence helpers
+const RESULT_OWNED: i64 = 0x100;
+
+/// A `defn` function passed as a callback, resolved for the arity the helper calls it with
+pub(super) struct Callback {
+    pub(super) symbol: String,
+    pub(super) result_kind: ValueKind,
+    result_ownership: HeapOwnership,
+}
+
+impl Callback {
+    fn owns_results(&self) -> bool {
+        self.result_kind.is_heap_kind() && self.result_ownership == HeapOwnership::Owned
+    }
+
+    pub(super) fn result_mode(&self) -> i64 {
+        let owned = if self.owns_results() { RESULT_OWNED } else { 0 };
+        self.result_kind.runtime_tag() | owned
+    }
+
+    fn result_ownership(&self) -> HeapOwnership {
+        if self.result_kind.is_heap_kind() {
+            self.result_ownership
+        } else {
+            HeapOwnership::None
+        }
+    }
+}
+
+/// Resolve the function named by `node` for `arity` arguments and record its parameter kinds
+pub(super) fn resolve_callback(op: &str, node: &Node, param_kinds: &[Option<ValueKind>], context: &mut CompileContext) -> Result<Callback, CompileError> {
+    let name = match node {
+        Node::Symbol { value } if context.get_variable(value).is_none() && context.get_parameter(value).is_none() => value,
+        _ => return Err(CompileError::InvalidExpression(format!("{} requires the name of a defn function as its callback", op))),
+    };
+
+    let arity = param_kinds.len();
+    let Some((symbol, param_count)) = resolve_call_target(name, arity, context)? else {
+        return Err(CompileError::UndefinedVariable(name.clone()));
+    };
+    if param_count != arity || context.is_function_variadic(&symbol) {
+        return Err(CompileError::InvalidExpression(format!("{} callback {} must take exactly {} argument(s)", op, name, arity)));
+    }
+
+    param_kinds.iter().enumerate().for_each(|(index, kind)| {
+        if let Some(kind) = kind {
+            context.record_function_parameter_type(&symbol, index, *kind);
+        }
+    });
+
+    Ok(Callback {
+        result_kind: context.get_function_return_type(&symbol).unwrap_or(ValueKind::Any),
+        result_ownership: context.get_function_return_ownership(&symbol).unwrap_or(HeapOwnership::None),
+        symbol,
+    })
+}
+
+/// Resolve the zero-argument arity `reduce` calls for an empty collection, if the callback has one
+fn resolve_empty_callback(name: &str, context: &CompileContext) -> Option<String> {
+    match resolve_call_target(name, 0, context) {
+        Ok(Some((symbol, 0))) if !context.is_function_variadic(&symbol) => Some(symbol),
+        _ => None,
+    }
+}
+
+/// A collection argument as the helpers see it: a vector, list or set (strings and maps are
+/// expanded into an owning list first) together with the kind of its items
+pub(super) struct Source {
+    pub(super) kind: ValueKind,
+    item_kind: Option<ValueKind>,
+}
+
+/// How a collection argument that turns out to be a lazy sequence is passed on
+#[derive(Clone, Copy, PartialEq)]
+enum LazySource {
+    /// Realize it into a list first
+    Realize,
+    /// Pass it on as it is; a lazy result retains what it needs
+    Keep,
+    /// Pass it on and keep it alive with the result, which borrows its items
+    Retain,
+}
+
+/// Compile a collection argument, leaving it on the stack. Owned collections are released by
+/// `tracker` after their last use; the slots keeping their items alive move into `retained_slots`.
+/// Lazy sequences are realized into a list.
+pub(super) fn compile_source(
+    op: &str,
+    node: &Node,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<Source, CompileError> {
+    let result = compile_node(node, context, program)?;
+    prepare_source(op, node, result, instructions, tracker, retained_slots, context, LazySource::Realize)
+}
+
+/// Like `compile_source`, but a lazy sequence stays lazy
+fn compile_lazy_source(
+    op: &str,
+    node: &Node,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<Source, CompileError> {
+    let result = compile_node(node, context, program)?;
+    prepare_source(op, node, result, instructions, tracker, retained_slots, context, LazySource::Keep)
+}
+
+/// Put an already compiled collection argument on the stack in the form the helpers walk
+#[allow(clippy::too_many_arguments)]
+fn prepare_source(
+    op: &str,
+    node: &Node,
+    mut result: CompileResult,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    lazy: LazySource,
+) -> Result<Source, CompileError> {
+    let kind = resolve_value_kind(node, result.kind, context);
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+    retained_slots.extend(result.take_retained_slots());
+
+    // Like `first` and `rest`, a value of unknown type is walked as a vector or list
+    let kind = if kind == ValueKind::Any { ValueKind::List } else { kind };
+    let item_kind = match kind {
+        ValueKind::Vector | ValueKind::List | ValueKind::LazySeq => result.vector_element_kind,
+        ValueKind::Set => result.set_element_kind,
+        ValueKind::Nil => None,
+        ValueKind::String => Some(ValueKind::String),
+        ValueKind::Map => Some(ValueKind::Vector),
+        _ => return Err(CompileError::InvalidExpression(format!("{} requires a vector, list, set, map, string, or nil", op))),
+    }
+    .filter(|kind| *kind != ValueKind::Any);
+
+    if kind == ValueKind::LazySeq && lazy == LazySource::Retain {
+        if result.heap_ownership == HeapOwnership::Owned {
+            // Nothing else reads a temporary, so its items can go once realization passes them
+            instructions.push(IRInstruction::RuntimeCall("_lazy_stream".to_string(), 1));
+            let slot = context.allocate_temp_slot();
+            instructions.push(IRInstruction::StoreLocal(slot));
+            instructions.push(IRInstruction::LoadLocal(slot));
+            track_heap_slot(retained_slots, slot, kind, None, Vec::new());
+        }
+        return Ok(Source { kind, item_kind });
+    }
+
+    tracker.track_if_owned(instructions, context, result.heap_ownership, kind);
+
+    match kind {
+        ValueKind::String | ValueKind::Map => {
+            instructions.push(IRInstruction::Push(kind.runtime_tag()));
+            instructions.push(IRInstruction::RuntimeCall("_seq_items".to_string(), 2));
+        }
+        ValueKind::LazySeq if lazy == LazySource::Realize => instructions.push(IRInstruction::RuntimeCall("_lazy_items".to_string(), 1)),
+        _ => return Ok(Source { kind, item_kind }),
+    }
+    let items_slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(items_slot));
+    instructions.push(IRInstruction::LoadLocal(items_slot));
+    track_heap_slot(retained_slots, items_slot, ValueKind::List, None, Vec::new());
+
+    Ok(Source { kind: ValueKind::List, item_kind })
+}
+
+/// Compile a numeric argument onto the stack
+pub(super) fn compile_number(op: &str, node: &Node, instructions: &mut Vec<IRInstruction>, context: &mut CompileContext, program: &mut IRProgram) -> Result<(), CompileError> {
+    let mut result = compile_node(node, context, program)?;
+    let kind = resolve_value_kind(node, result.kind, context);
+    if !matches!(kind, ValueKind::Number | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression(format!("{} requires numeric arguments", op)));
+    }
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+    result.free_retained_slots(instructions, context);
+    Ok(())
+}
+
+/// Keep a runtime result that owns its elements in a retained slot and continue with a shallow clone
+pub(super) fn adopt_owning_result(instructions: &mut Vec<IRInstruction>, retained_slots: &mut Vec<RetainedSlot>, kind: ValueKind, context: &mut CompileContext) {
+    let slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(slot));
+    instructions.push(IRInstruction::LoadLocal(slot));
+    if let Some(runtime) = clone_runtime_for_kind(kind) {
+        instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
+    }
+    track_heap_slot(retained_slots, slot, kind, None, Vec::new());
+}
+
+/// Finish a form whose result shares items with its sources
+pub(super) fn shared_result(instructions: Vec<IRInstruction>, kind: ValueKind, mut retained_slots: Vec<RetainedSlot>) -> CompileResult {
+    dedup_retained_slots(&mut retained_slots);
+    CompileResult::with_instructions(instructions, kind)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_retained_slots(retained_slots)
+}
+
+/// Finish a form producing a new lazy sequence, which owns everything it refers to
+fn lazy_result(instructions: Vec<IRInstruction>, item_kind: Option<ValueKind>) -> CompileResult {
+    CompileResult::with_instructions(instructions, ValueKind::LazySeq)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(item_kind)
+}
+
+/// Finish a form whose result does not reference the items of its sources
+pub(super) fn release_sources(instructions: &mut Vec<IRInstruction>, retained_slots: Vec<RetainedSlot>, context: &mut CompileContext) {
+    retained_slots.into_iter().for_each(|slot| free_retained_slot(slot, instructions, context));
+}
+
+fn callback_name(node: &Node) -> &str {
+    match node {
+        Node::Symbol { value } => value,
+        _ => "",
+    }
+}
+
+/// Compile map (apply a one-argument function to every item, producing a list)
+pub(super) fn compile_map(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("map".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_lazy_source("map", &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let callback = resolve_callback("map", &args[0], &[source.item_kind], context)?;
+    let element_kind = Some(callback.result_kind).filter(|kind| *kind != ValueKind::Any);
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    if source.kind == ValueKind::LazySeq {
+        call.push(IRInstruction::Push(callback.result_mode()));
+        call.push(IRInstruction::RuntimeCall("_lazy_map".to_string(), 3));
+        let mut instructions = tracker.apply_liveness_and_release(call, context);
+        release_sources(&mut instructions, retained_slots, context);
+        return Ok(lazy_result(instructions, element_kind));
+    }
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(callback.result_mode()));
+    call.push(IRInstruction::RuntimeCall("_seq_map".to_string(), 4));
+    let mut instructions = tracker.apply_liveness_and_release(call, context);
+
+    if callback.owns_results() {
+        adopt_owning_result(&mut instructions, &mut retained_slots, ValueKind::List, context);
+    }
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(element_kind))
+}
+
+/// Compile filter (`keep` = true) and remove (`keep` = false)
+pub(super) fn compile_filter(args: &[Node], keep: bool, context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let op = if keep { "filter" } else { "remove" };
+    if args.len() != 2 {
+        return Err(CompileError::ArityError(op.to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_lazy_source(op, &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let callback = resolve_callback(op, &args[0], &[source.item_kind], context)?;
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    if source.kind == ValueKind::LazySeq {
+        call.push(IRInstruction::Push(callback.result_mode()));
+        call.push(IRInstruction::Push(keep as i64));
+        call.push(IRInstruction::RuntimeCall("_lazy_filter".to_string(), 4));
+        let mut instructions = tracker.apply_liveness_and_release(call, context);
+        release_sources(&mut instructions, retained_slots, context);
+        return Ok(lazy_result(instructions, source.item_kind));
+    }
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(callback.result_mode()));
+    call.push(IRInstruction::Push(keep as i64));
+    call.push(IRInstruction::RuntimeCall("_seq_filter".to_string(), 5));
+    let instructions = tracker.apply_liveness_and_release(call, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(source.item_kind))
+}
+
+/// The kind of a reduce result: the accumulator may come from the callback or from `seed_kind`
+/// (the initial value, or an item when there is none), so heap kinds have to agree
+fn reduce_result_kind(seed_kind: Option<ValueKind>, callback: &Callback) -> Result<ValueKind, CompileError> {
+    let result_kind = callback.result_kind;
+    match seed_kind {
+        None => Ok(result_kind),
+        Some(seed) if seed == result_kind => Ok(result_kind),
+        Some(seed) if !seed.is_heap_kind() && !result_kind.is_heap_kind() => Ok(ValueKind::Any),
+        Some(_) => Err(CompileError::InvalidExpression(
+            "reduce requires the initial value and the callback result to have the same type".to_string(),
+        )),
+    }
+}
+
+/// Compile reduce, with or without an initial value. When the callback result cannot share items
+/// with the collection, a lazy collection is walked without realizing it and the sources go as
+/// soon as the fold is done.
+pub(super) fn compile_reduce(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(CompileError::ArityError("reduce".to_string(), 3, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let (seed_kind, source, callback) = if args.len() == 3 {
+        let mut init_result = compile_node(&args[1], context, program)?;
+        let init_kind = resolve_value_kind(&args[1], init_result.kind, context);
+        extend_with_offset(&mut instructions, std::mem::take(&mut init_result.instructions));
+        retained_slots.extend(init_result.take_retained_slots());
+        tracker.track_if_owned(&mut instructions, context, init_result.heap_ownership, init_kind);
+        instructions.push(IRInstruction::Push(init_kind.runtime_tag()));
+
+        let lazy = reduce_lazy_source(callback_name(&args[0]), context);
+        let source_result = compile_node(&args[2], context, program)?;
+        let source = prepare_source("reduce", &args[2], source_result, &mut instructions, &mut tracker, &mut retained_slots, context, lazy)?;
+        let init_param = Some(init_kind).filter(|kind| *kind != ValueKind::Any);
+        let callback = resolve_callback("reduce", &args[0], &[init_param, source.item_kind], context)?;
+        (init_param, source, callback)
+    } else {
+        // An empty collection without an initial value calls the zero-argument arity, if any
+        match resolve_empty_callback(callback_name(&args[0]), context) {
+            Some(symbol) => instructions.push(IRInstruction::PushFunction(symbol)),
+            None => instructions.push(IRInstruction::Push(0)),
+        }
+        let lazy = reduce_lazy_source(callback_name(&args[0]), context);
+        let source_result = compile_node(&args[1], context, program)?;
+        let source = prepare_source("reduce", &args[1], source_result, &mut instructions, &mut tracker, &mut retained_slots, context, lazy)?;
+        let callback = resolve_callback("reduce", &args[0], &[source.item_kind, source.item_kind], context)?;
+        (source.item_kind, source, callback)
+    };
+
+    let kind = reduce_result_kind(seed_kind, &callback)?;
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(callback.result_mode()));
+    if args.len() == 3 {
+        call.push(IRInstruction::RuntimeCall("_seq_reduce".to_string(), 6));
+    } else {
+        call.push(IRInstruction::RuntimeCall("_seq_reduce_first".to_string(), 5));
+    }
+    let mut instructions = tracker.apply_liveness_and_release(call, context);
+
+    if is_scalar_kind(callback.result_kind) {
+        release_sources(&mut instructions, retained_slots, context);
+        return Ok(CompileResult::with_instructions(instructions, kind));
+    }
+
+    let ownership = if kind.is_heap_kind() { HeapOwnership::Owned } else { HeapOwnership::None };
+    let mut result = shared_result(instructions, kind, retained_slots).with_heap_ownership(ownership);
+    if kind == callback.result_kind {
+        result = result
+            .with_map_value_types(context.get_function_return_map_value_types(&callback.symbol).cloned())
+            .with_set_element_kind(context.get_function_return_set_element_kind(&callback.symbol))
+            .with_vector_element_kind(context.get_function_return_vector_element_kind(&callback.symbol));
+    }
+    Ok(result)
+}
+
+/// A value of a known kind that never lives on the heap, so it cannot hold on to anything
+fn is_scalar_kind(kind: ValueKind) -> bool {
+    kind != ValueKind::Any && !kind.is_heap_kind()
+}
+
+/// How `reduce` takes a lazy collection: kept lazy when the callback is known to return scalars, so
+/// that a temporary, read for the last time by the fold, frees its items as it goes
+fn reduce_lazy_source(callback: &str, context: &CompileContext) -> LazySource {
+    match resolve_call_target(callback, 2, context) {
+        Ok(Some((symbol, _))) if context.get_function_return_type(&symbol).is_some_and(is_scalar_kind) => LazySource::Keep,
+        _ => LazySource::Realize,
+    }
+}
+
+/// Compile every? (true when the predicate holds for every item)
+pub(super) fn compile_every(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_search("every?", "_seq_every", args, context, program)
+}
+
+/// Compile some (the first truthy predicate result, or nil)
+pub(super) fn compile_some(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    compile_search("some", "_seq_some", args, context, program)
+}
+
+fn compile_search(op: &str, runtime: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError(op.to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_source(op, &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let callback = resolve_callback(op, &args[0], &[source.item_kind], context)?;
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(callback.result_mode()));
+    call.push(IRInstruction::RuntimeCall(runtime.to_string(), 4));
+    let mut instructions = tracker.apply_liveness_and_release(call, context);
+    release_sources(&mut instructions, retained_slots, context);
+
+    if op == "every?" {
+        return Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean));
+    }
+    Ok(CompileResult::with_instructions(instructions, callback.result_kind).with_heap_ownership(callback.result_ownership()))
+}
+
+/// Compile range: (range), (range end), (range start end) or (range start end step)
+pub(super) fn compile_range(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.is_empty() {
+        // (range) counts up from zero without end
+        let instructions = vec![IRInstruction::Push(0), IRInstruction::Push(1), IRInstruction::RuntimeCall("_lazy_range".to_string(), 2)];
+        return Ok(lazy_result(instructions, Some(ValueKind::Number)));
+    }
+    if args.len() > 3 {
+        return Err(CompileError::ArityError("range".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    if args.len() == 1 {
+        instructions.push(IRInstruction::Push(0));
+    }
+    for arg in args {
+        compile_number("range", arg, &mut instructions, context, program)?;
+    }
+    if args.len() < 3 {
+        instructions.push(IRInstruction::Push(1));
+    }
+    instructions.push(IRInstruction::RuntimeCall("_seq_range".to_string(), 3));
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::List)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(Some(ValueKind::Number)))
+}
+
+/// Compile into (add the items of a collection to a vector, list, set, or map)
+pub(super) fn compile_into(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("into".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let mut target_result = compile_node(&args[0], context, program)?;
+    let target_kind = resolve_value_kind(&args[0], target_result.kind, context);
+    if !matches!(target_kind, ValueKind::Vector | ValueKind::List | ValueKind::Set | ValueKind::Map | ValueKind::Nil) {
+        return Err(CompileError::InvalidExpression("into requires a vector, list, set, map, or nil target".to_string()));
+    }
+    extend_with_offset(&mut instructions, std::mem::take(&mut target_result.instructions));
+    retained_slots.extend(target_result.take_retained_slots());
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, target_kind);
+    instructions.push(IRInstruction::Push(target_kind.runtime_tag()));
+
+    let source = compile_source("into", &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    if target_kind == ValueKind::Map && !matches!(source.item_kind, None | Some(ValueKind::Vector) | Some(ValueKind::List)) {
+        return Err(CompileError::InvalidExpression("into a map requires [key value] entries".to_string()));
+    }
+
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_seq_into".to_string(), 4));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let (kind, existing_kind) = match target_kind {
+        ValueKind::Nil => (ValueKind::List, source.item_kind),
+        ValueKind::Set => (ValueKind::Set, target_result.set_element_kind),
+        _ => (target_kind, target_result.vector_element_kind),
+    };
+    let element_kind = match (existing_kind, source.item_kind) {
+        (_, None) => None,
+        (existing, Some(item)) if target_kind == ValueKind::Nil || existing == Some(item) => Some(item),
+        _ => None,
+    };
+
+    let result = shared_result(instructions, kind, retained_slots);
+    Ok(match kind {
+        ValueKind::Map => result,
+        ValueKind::Set => result.with_set_element_kind(element_kind),
+        _ => result.with_vector_element_kind(element_kind),
+    })
+}
+
+/// Compile take (`from_front` = true) and drop (`from_front` = false)
+pub(super) fn compile_take(args: &[Node], from_front: bool, context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let (op, runtime) = if from_front { ("take", "_seq_take") } else { ("drop", "_seq_drop") };
+    if args.len() != 2 {
+        return Err(CompileError::ArityError(op.to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    compile_number(op, &args[0], &mut instructions, context, program)?;
+    let source = compile_lazy_source(op, &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    if source.kind == ValueKind::LazySeq {
+        let runtime = if from_front { "_lazy_take" } else { "_lazy_drop" };
+        instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 2));
+        let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+        release_sources(&mut instructions, retained_slots, context);
+        return Ok(lazy_result(instructions, source.item_kind));
+    }
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 3));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(source.item_kind))
+}
+
+/// Compile concat (the items of every argument in order, as a list)
+pub(super) fn compile_concat(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let mut instructions = vec![IRInstruction::Push(0), IRInstruction::Push(ValueKind::Nil.runtime_tag())];
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+    let mut element_kind: Option<Option<ValueKind>> = None;
+
+    // Fold the parts pairwise; each intermediate list is released once the next one is built
+    for arg in args {
+        let source = compile_source("concat", arg, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+        instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+        instructions.push(IRInstruction::RuntimeCall("_seq_concat".to_string(), 4));
+        tracker.track_owned(&mut instructions, context, ValueKind::List);
+        instructions.push(IRInstruction::Push(ValueKind::List.runtime_tag()));
+
+        element_kind = match element_kind {
+            None => Some(source.item_kind),
+            Some(existing) if existing == source.item_kind => Some(existing),
+            Some(_) => Some(None),
+        };
+    }
+
+    // Drop the trailing tag and hand over a fresh copy of the final list
+    instructions.push(IRInstruction::Push(0));
+    instructions.push(IRInstruction::Push(ValueKind::Nil.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_seq_concat".to_string(), 4));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(element_kind.flatten()))
+}
+
+/// Compile reverse (the items in reverse order, as a list sharing them)
+pub(super) fn compile_reverse(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("reverse".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_source("reverse", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_seq_reverse".to_string(), 2));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(source.item_kind))
+}
+
+/// Compile sort (the items in ascending `compare` order, or by a two-argument comparator, as a list
+/// sharing them)
+pub(super) fn compile_sort(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let (comparator, coll) = match args {
+        [coll] => (None, coll),
+        [comparator, coll] => (Some(comparator), coll),
+        _ => return Err(CompileError::ArityError("sort".to_string(), 2, args.len())),
+    };
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_source("sort", coll, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let comparator = comparator.map(|node| resolve_callback("sort", node, &[source.item_kind, source.item_kind], context)).transpose()?;
+
+    let mut call = vec![push_optional_callback(comparator.as_ref())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(comparator.as_ref().map_or(0, Callback::result_mode)));
+    call.push(IRInstruction::RuntimeCall("_seq_sort".to_string(), 4));
+    let instructions = tracker.apply_liveness_and_release(call, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(source.item_kind))
+}
+
+/// Compile sort-by (the items ordered by the results of a key function, compared like sort does)
+pub(super) fn compile_sort_by(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let (key_function, comparator, coll) = match args {
+        [key_function, coll] => (key_function, None, coll),
+        [key_function, comparator, coll] => (key_function, Some(comparator), coll),
+        _ => return Err(CompileError::ArityError("sort-by".to_string(), 3, args.len())),
+    };
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_source("sort-by", coll, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let key_function = resolve_callback("sort-by", key_function, &[source.item_kind], context)?;
+    let key_kind = Some(key_function.result_kind).filter(|kind| *kind != ValueKind::Any);
+    let comparator = comparator.map(|node| resolve_callback("sort-by", node, &[key_kind, key_kind], context)).transpose()?;
+
+    let mut call = vec![IRInstruction::PushFunction(key_function.symbol.clone()), push_optional_callback(comparator.as_ref())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(key_function.result_mode()));
+    call.push(IRInstruction::Push(comparator.as_ref().map_or(0, Callback::result_mode)));
+    call.push(IRInstruction::RuntimeCall("_seq_sort_by".to_string(), 6));
+    let instructions = tracker.apply_liveness_and_release(call, context);
+
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(source.item_kind))
+}
+
+/// Push a callback's address, or 0 for the runtime's default behaviour
+fn push_optional_callback(callback: Option<&Callback>) -> IRInstruction {
+    callback.map_or(IRInstruction::Push(0), |callback| IRInstruction::PushFunction(callback.symbol.clone()))
+}
+
+/// Whether values of `kind` can be map keys in compiled code
+fn is_key_kind(kind: ValueKind) -> bool {
+    kind == ValueKind::Any || kind.is_key_kind()
+}
+
+/// Compile frequencies (a map from each distinct item to its count)
+pub(super) fn compile_frequencies(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("frequencies".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_source("frequencies", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    if !is_key_kind(source.item_kind.unwrap_or(ValueKind::Any)) {
+        return Err(CompileError::InvalidExpression("frequencies requires items that can be map keys".to_string()));
+    }
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_seq_frequencies".to_string(), 2));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let counts = MapValueTypes::from([(MapKeyLiteral::Every, ValueKind::Number)]);
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots).with_map_value_types(Some(counts)))
+}
+
+/// Compile group-by (a map from each callback result to the vector of items producing it)
+pub(super) fn compile_group_by(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("group-by".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = compile_source("group-by", &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let callback = resolve_callback("group-by", &args[0], &[source.item_kind], context)?;
+    if !is_key_kind(callback.result_kind) {
+        return Err(CompileError::InvalidExpression("group-by requires a callback returning values that can be map keys".to_string()));
+    }
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(source.kind.runtime_tag()));
+    call.push(IRInstruction::Push(callback.result_mode()));
+    call.push(IRInstruction::RuntimeCall("_seq_group_by".to_string(), 4));
+    let mut instructions = tracker.apply_liveness_and_release(call, context);
+    adopt_owning_result(&mut instructions, &mut retained_slots, ValueKind::Map, context);
+
+    // Every group is a vector, whatever key it is stored under
+    let groups = MapValueTypes::from([(MapKeyLiteral::Every, ValueKind::Vector)]);
+    Ok(shared_result(instructions, ValueKind::Map, retained_slots).with_map_value_types(Some(groups)))
+}
+
+/// Compile iterate (the lazy sequence x, (f x), (f (f x)), ...)
+pub(super) fn compile_iterate(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("iterate".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+
+    // The sequence keeps its own copy of the seed
+    let mut seed_result = compile_node(&args[1], context, program)?;
+    let seed_kind = resolve_value_kind(&args[1], seed_result.kind, context);
+    extend_with_offset(&mut instructions, std::mem::take(&mut seed_result.instructions));
+    tracker.track_if_owned(&mut instructions, context, seed_result.heap_ownership, seed_kind);
+    instructions.push(IRInstruction::Push(seed_kind.runtime_tag()));
+
+    let seed_param = Some(seed_kind).filter(|kind| *kind != ValueKind::Any);
+    let callback = resolve_callback("iterate", &args[0], &[seed_param], context)?;
+    if seed_kind.is_heap_kind() && callback.result_kind != seed_kind {
+        return Err(CompileError::InvalidExpression("iterate requires a callback returning the same type as the seed".to_string()));
+    }
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(callback.result_mode()));
+    call.push(IRInstruction::RuntimeCall("_lazy_iterate".to_string(), 4));
+    let mut instructions = tracker.apply_liveness_and_release(call, context);
+    seed_result.free_retained_slots(&mut instructions, context);
+
+    let item_kind = seed_param.filter(|kind| *kind == callback.result_kind);
+    Ok(lazy_result(instructions, item_kind))
+}
+
+/// Compile repeat: (repeat x) without end, or (repeat n x)
+pub(super) fn compile_repeat(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(CompileError::ArityError("repeat".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+
+    if args.len() == 2 {
+        compile_number("repeat", &args[0], &mut instructions, context, program)?;
+        instructions.push(IRInstruction::Push(1));
+    } else {
+        instructions.push(IRInstruction::Push(0));
+        instructions.push(IRInstruction::Push(0));
+    }
+
+    // The sequence keeps its own copy of the value
+    let value_node = &args[args.len() - 1];
+    let mut value_result = compile_node(value_node, context, program)?;
+    let value_kind = resolve_value_kind(value_node, value_result.kind, context);
+    extend_with_offset(&mut instructions, std::mem::take(&mut value_result.instructions));
+    tracker.track_if_owned(&mut instructions, context, value_result.heap_ownership, value_kind);
+    instructions.push(IRInstruction::Push(value_kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_lazy_repeat".to_string(), 4));
+    let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+    value_result.free_retained_slots(&mut instructions, context);
+
+    Ok(lazy_result(instructions, Some(value_kind).filter(|kind| *kind != ValueKind::Any)))
+}
+
+/// Compile cycle (the items of a collection repeated without end)
+pub(super) fn compile_cycle(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("cycle".to_string(), 1, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    // The sequence keeps its own copies of the items
+    let source = compile_source("cycle", &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_lazy_cycle".to_string(), 2));
+    let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+    release_sources(&mut instructions, retained_slots, context);
+
+    Ok(lazy_result(instructions, source.item_kind))
+}
+
+/// Finish a lookup whose item is borrowed from a collection that `retained_slots` keeps alive
+fn borrowed_item(instructions: Vec<IRInstruction>, item_kind: Option<ValueKind>, mut retained_slots: Vec<RetainedSlot>) -> CompileResult {
+    dedup_retained_slots(&mut retained_slots);
+    let kind = item_kind.unwrap_or(ValueKind::Any);
+    let ownership = if kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+    CompileResult::with_instructions(instructions, kind).with_heap_ownership(ownership).with_retained_slots(retained_slots)
+}
+
+/// Read one item of an already compiled collection through the seq protocol (`_seq_first`,
+/// `_seq_last`)
+fn compile_protocol_item(op: &str, runtime: &str, node: &Node, target_result: CompileResult, context: &mut CompileContext) -> Result<CompileResult, CompileError> {
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = prepare_source(op, node, target_result, &mut instructions, &mut tracker, &mut retained_slots, context, LazySource::Retain)?;
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 2));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(borrowed_item(instructions, source.item_kind, retained_slots))
+}
+
+/// Compile first over a set, or over a string or map expanded into its items
+pub(super) fn compile_first_item(node: &Node, target_result: CompileResult, context: &mut CompileContext) -> Result<CompileResult, CompileError> {
+    compile_protocol_item("first", "_seq_first", node, target_result, context)
+}
+
+/// Compile the items of an already compiled collection as a sequence: `_seq_rest` (everything
+/// after the first item), `_seq_next` (the same, but nil instead of empty) or `_seq_of` (every item,
+/// nil when there are none). Lazy sequences give lazy results.
+pub(super) fn compile_protocol_items(op: &str, runtime: &str, node: &Node, target_result: CompileResult, context: &mut CompileContext) -> Result<CompileResult, CompileError> {
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let source = prepare_source(op, node, target_result, &mut instructions, &mut tracker, &mut retained_slots, context, LazySource::Keep)?;
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 2));
+    let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    if source.kind == ValueKind::LazySeq {
+        release_sources(&mut instructions, retained_slots, context);
+        return Ok(lazy_result(instructions, source.item_kind));
+    }
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(source.item_kind))
+}
+
+/// Compile seq (the items of any collection as a list, or nil when there are none)
+pub(super) fn compile_seq(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("seq".to_string(), 1, args.len()));
+    }
+
+    let target_result = compile_node(&args[0], context, program)?;
+    compile_protocol_items("seq", "_seq_of", &args[0], target_result, context)
+}
+
+/// Compile next (everything after the first item, or nil when nothing is left)
+pub(super) fn compile_next(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("next".to_string(), 1, args.len()));
+    }
+
+    let target_result = compile_node(&args[0], context, program)?;
+    compile_protocol_items("next", "_seq_next", &args[0], target_result, context)
+}
+
+/// Compile last (the final item, nil for an empty collection)
+pub(super) fn compile_last(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("last".to_string(), 1, args.len()));
+    }
+
+    let target_result = compile_node(&args[0], context, program)?;
+    compile_protocol_item("last", "_seq_last", &args[0], target_result, context)
+}
+
+/// Compile nth (the item at an index; out of range gives the default, or throws without one)
+pub(super) fn compile_nth(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(CompileError::ArityError("nth".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let target_result = compile_node(&args[0], context, program)?;
+    let source = prepare_source("nth", &args[0], target_result, &mut instructions, &mut tracker, &mut retained_slots, context, LazySource::Retain)?;
+    instructions.push(IRInstruction::Push(source.kind.runtime_tag()));
+    compile_number("nth", &args[1], &mut instructions, context, program)?;
+
+    let default_kind = match args.get(2) {
+        Some(default) => {
+            let mut default_result = compile_node(default, context, program)?;
+            let kind = resolve_value_kind(default, default_result.kind, context);
+            extend_with_offset(&mut instructions, std::mem::take(&mut default_result.instructions));
+            retained_slots.extend(default_result.take_retained_slots());
+            // The result may be the default itself, so an owned default lives as long as the result
+            if default_result.heap_ownership == HeapOwnership::Owned {
+                let slot = context.allocate_temp_slot();
+                instructions.push(IRInstruction::StoreLocal(slot));
+                instructions.push(IRInstruction::LoadLocal(slot));
+                track_heap_slot(&mut retained_slots, slot, kind, None, Vec::new());
+            }
+            instructions.push(IRInstruction::Push(1));
+            Some(kind)
+        }
+        None => {
+            instructions.push(IRInstruction::Push(0));
+            instructions.push(IRInstruction::Push(0));
+            None
+        }
+    };
+    instructions.push(IRInstruction::RuntimeCall("_seq_nth".to_string(), 5));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let item_kind = match default_kind {
+        None | Some(ValueKind::Nil) => source.item_kind,
+        Some(kind) if source.item_kind == Some(kind) => Some(kind),
+        Some(_) => None,
+    };
+    Ok(borrowed_item(instructions, item_kind, retained_slots))
+}
+
+/// Compile empty? (whether a collection, string or lazy sequence has no items)
+pub(super) fn compile_is_empty(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("empty?".to_string(), 1, args.len()));
+    }
+
+    let mut result = compile_node(&args[0], context, program)?;
+    let kind = match resolve_value_kind(&args[0], result.kind, context) {
+        ValueKind::Any => ValueKind::List,
+        kind @ (ValueKind::Vector | ValueKind::List | ValueKind::Set | ValueKind::Map | ValueKind::String | ValueKind::LazySeq | ValueKind::Nil) => kind,
+        _ => return Err(CompileError::InvalidExpression("empty? requires a collection, string, or nil".to_string())),
+    };
+
+    let mut instructions = std::mem::take(&mut result.instructions);
+    let mut tracker = SlotTracker::new();
+    tracker.track_if_owned(&mut instructions, context, result.heap_ownership, kind);
+    instructions.push(IRInstruction::Push(kind.runtime_tag()));
+    instructions.push(IRInstruction::RuntimeCall("_seq_empty".to_string(), 2));
+    let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+    result.free_retained_slots(&mut instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean))
+}
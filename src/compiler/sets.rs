@@ -0,0 +1,115 @@
+/// Set algebra: union, intersection, difference, subset?, superset? and select
+///
+/// Each form lowers to a `_set_*` runtime helper that walks the set layout directly, building the
+/// result set in one pass instead of adding members one `conj` at a time. Results share their
+/// members with the arguments, so they keep the retained slots of their sources alive; nil is
+/// accepted wherever a set is, as an empty set.
+///
+/// Member kinds follow the arguments: `intersection`, `difference` and `select` keep the first
+/// set's, and `union` keeps a kind only when every set agrees on it.
+use super::{
+    builtins::{compile_set_literal, resolve_value_kind},
+    compile_node, extend_with_offset,
+    sequences::{release_sources, resolve_callback, shared_result},
+    slots::SlotTracker,
+    CompileContext, CompileError, CompileResult, RetainedSlot, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Compile a set argument onto the stack, returning its member kind (`Any` when unknown). An
+/// owned set is released by `tracker` after its last use; the slots keeping its members alive
+/// move into `retained_slots`.
+fn compile_set_source(
+    op: &str,
+    node: &Node,
+    instructions: &mut Vec<IRInstruction>,
+    tracker: &mut SlotTracker,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<ValueKind, CompileError> {
+    let mut result = compile_node(node, context, program)?;
+    let kind = resolve_value_kind(node, result.kind, context);
+    if !matches!(kind, ValueKind::Set | ValueKind::Nil | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression(format!("{} requires sets", op)));
+    }
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+    retained_slots.extend(result.take_retained_slots());
+    tracker.track_if_owned(instructions, context, result.heap_ownership, ValueKind::Set);
+    Ok(result.set_element_kind.unwrap_or(ValueKind::Any))
+}
+
+/// Compile union, intersection or difference, folding the sets pairwise from the left. `(union)`
+/// is an empty set and a single set is returned as it is.
+pub(super) fn compile_set_operation(op: &str, runtime: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    match args {
+        [] if op == "union" => return compile_set_literal(&[], context, program),
+        [] => return Err(CompileError::ArityError(op.to_string(), 1, 0)),
+        [single] => return compile_node(single, context, program),
+        _ => {}
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let mut element_kind = compile_set_source(op, &args[0], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    for (index, set) in args[1..].iter().enumerate() {
+        if index > 0 {
+            tracker.track_owned(&mut instructions, context, ValueKind::Set);
+        }
+        let kind = compile_set_source(op, set, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+        if op == "union" && kind != element_kind {
+            element_kind = ValueKind::Any;
+        }
+        instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 2));
+    }
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let element_kind = Some(element_kind).filter(|kind| *kind != ValueKind::Any);
+    Ok(shared_result(instructions, ValueKind::Set, retained_slots).with_set_element_kind(element_kind))
+}
+
+/// Compile subset? or superset? (whether every member of one set is in the other)
+pub(super) fn compile_set_predicate(op: &str, runtime: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError(op.to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    for set in args {
+        compile_set_source(op, set, &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    }
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 2));
+    let mut instructions = tracker.apply_liveness_and_release(instructions, context);
+    release_sources(&mut instructions, retained_slots, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Boolean))
+}
+
+/// Compile select (the members of a set for which a one-argument `defn` predicate is truthy)
+pub(super) fn compile_select(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 {
+        return Err(CompileError::ArityError("select".to_string(), 2, args.len()));
+    }
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots = Vec::new();
+
+    let element_kind = compile_set_source("select", &args[1], &mut instructions, &mut tracker, &mut retained_slots, context, program)?;
+    let element_kind = Some(element_kind).filter(|kind| *kind != ValueKind::Any);
+    let callback = resolve_callback("select", &args[0], &[element_kind], context)?;
+
+    let mut call = vec![IRInstruction::PushFunction(callback.symbol.clone())];
+    extend_with_offset(&mut call, instructions);
+    call.push(IRInstruction::Push(callback.result_mode()));
+    call.push(IRInstruction::RuntimeCall("_set_select".to_string(), 3));
+    let instructions = tracker.apply_liveness_and_release(call, context);
+
+    Ok(shared_result(instructions, ValueKind::Set, retained_slots).with_set_element_kind(element_kind))
+}
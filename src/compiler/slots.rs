@@ -2,7 +2,7 @@
 ///
 /// This module provides a centralized way to allocate, track, and release temporary slots
 /// used during code generation, along with liveness-aware freeing.
-use super::{CompileContext, HeapOwnership, RetainedSlot, ValueKind};
+use super::{CompileContext, HeapOwnership, ValueKind};
 use crate::compiler::builtins::emit_free_for_slot;
 use crate::compiler::liveness::{apply_liveness_plan, compute_liveness_plan};
 use crate::ir::IRInstruction;
@@ -53,17 +53,6 @@ impl SlotTracker {
         self.slot_kinds.insert(slot, kind);
     }
 
-    /// Register a retained slot for tracking without allocating a new one.
-    pub fn track_retained(&mut self, slot: &RetainedSlot) {
-        self.tracked_slots.insert(slot.slot);
-        self.slot_kinds.insert(slot.slot, slot.kind);
-    }
-
-    /// Register multiple retained slots for tracking.
-    pub fn track_retained_slots(&mut self, slots: &[RetainedSlot]) {
-        slots.iter().for_each(|slot| self.track_retained(slot));
-    }
-
     /// Remove a slot from liveness tracking (useful when manually freeing).
     pub fn untrack(&mut self, slot: usize) {
         self.tracked_slots.remove(&slot);
@@ -0,0 +1,118 @@
+/// Sorted collections: sorted-map, sorted-set, their -by variants, subseq and rsubseq
+///
+/// A sorted collection is built like the matching literal and then moved into a balanced tree by
+/// `_sorted_map_from` or `_sorted_set_from`, keyed by `compare` or by a comparator function. The
+/// tree keeps the map and set layouts' header, so the results stay `Map` and `Set` values that
+/// every other map and set form accepts, and they keep the literal's ownership and key types.
+///
+/// Range queries name their tests as literal `<`, `<=`, `>` or `>=` symbols, which lower to bound
+/// kinds packed above the bound key's tag; `>` and `>=` bound the start of the range and `<` and
+/// `<=` its end.
+use super::{
+    builtins::{compile_hash_map, compile_set_literal, resolve_map_key_kind, resolve_value_kind},
+    compile_node, extend_with_offset,
+    sequences::{resolve_callback, shared_result},
+    slots::SlotTracker,
+    CompileContext, CompileError, CompileResult, RetainedSlot, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// `_sorted_subseq` bound kinds, packed above the bound key's tag
+const BOUND_EXCLUSIVE: i64 = 1;
+const BOUND_INCLUSIVE: i64 = 2;
+
+/// Compile sorted-map, sorted-set, sorted-map-by or sorted-set-by
+pub(super) fn compile_sorted_collection(op: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let (comparator, entries) = match (op.ends_with("-by"), args.split_first()) {
+        (false, _) => (None, args),
+        (true, Some((comparator, entries))) => (Some(comparator), entries),
+        (true, None) => return Err(CompileError::ArityError(op.to_string(), 1, 0)),
+    };
+
+    let (mut literal, runtime) = if op.starts_with("sorted-map") {
+        (compile_hash_map(entries, context, program)?, "_sorted_map_from")
+    } else {
+        (compile_set_literal(entries, context, program)?, "_sorted_set_from")
+    };
+    // Set members are known from the literal; map keys only from type inference
+    let key_kind = literal.set_element_kind.filter(|kind| *kind != ValueKind::Any);
+    let comparator = comparator.map(|node| resolve_callback(op, node, &[key_kind, key_kind], context)).transpose()?;
+
+    let mut instructions = vec![comparator.as_ref().map_or(IRInstruction::Push(0), |callback| IRInstruction::PushFunction(callback.symbol.clone()))];
+    extend_with_offset(&mut instructions, std::mem::take(&mut literal.instructions));
+    instructions.push(IRInstruction::Push(comparator.as_ref().map_or(0, |callback| callback.result_mode())));
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 3));
+    literal.instructions = instructions;
+    Ok(literal)
+}
+
+/// The kind packed into a bound for a literal test symbol, and whether it bounds the start
+fn bound_test(op: &str, node: &Node) -> Result<(i64, bool), CompileError> {
+    match node {
+        Node::Symbol { value } if value == ">" => Ok((BOUND_EXCLUSIVE, true)),
+        Node::Symbol { value } if value == ">=" => Ok((BOUND_INCLUSIVE, true)),
+        Node::Symbol { value } if value == "<" => Ok((BOUND_EXCLUSIVE, false)),
+        Node::Symbol { value } if value == "<=" => Ok((BOUND_INCLUSIVE, false)),
+        _ => Err(CompileError::InvalidExpression(format!("{} tests must be one of <, <=, > or >=", op))),
+    }
+}
+
+/// Compile a bound key onto the stack followed by its packed bound
+fn compile_bound(bound: Option<(&Node, i64)>, instructions: &mut Vec<IRInstruction>, tracker: &mut SlotTracker, context: &mut CompileContext, program: &mut IRProgram) -> Result<(), CompileError> {
+    let Some((key, kind)) = bound else {
+        instructions.push(IRInstruction::Push(0));
+        instructions.push(IRInstruction::Push(0));
+        return Ok(());
+    };
+
+    let mut key_result = compile_node(key, context, program)?;
+    let key_kind = resolve_map_key_kind(key, key_result.kind, context)?;
+    extend_with_offset(instructions, std::mem::take(&mut key_result.instructions));
+    tracker.track_if_owned(instructions, context, key_result.heap_ownership, key_kind);
+    key_result.free_retained_slots(instructions, context);
+    instructions.push(IRInstruction::Push(kind << 8 | key_kind.runtime_tag()));
+    Ok(())
+}
+
+/// Compile subseq or rsubseq (the entries of a sorted map, or members of a sorted set, within one
+/// or two bounds, as a list in ascending or descending order)
+pub(super) fn compile_subseq(op: &str, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let (coll, start, end) = match args {
+        [coll, test, key] => match bound_test(op, test)? {
+            (kind, true) => (coll, Some((key, kind)), None),
+            (kind, false) => (coll, None, Some((key, kind))),
+        },
+        [coll, start_test, start_key, end_test, end_key] => match (bound_test(op, start_test)?, bound_test(op, end_test)?) {
+            ((start_kind, true), (end_kind, false)) => (coll, Some((start_key, start_kind)), Some((end_key, end_kind))),
+            _ => return Err(CompileError::InvalidExpression(format!("{} takes a > or >= start test and a < or <= end test", op))),
+        },
+        _ => return Err(CompileError::ArityError(op.to_string(), 5, args.len())),
+    };
+
+    let mut instructions = Vec::new();
+    let mut tracker = SlotTracker::new();
+    let mut retained_slots: Vec<RetainedSlot> = Vec::new();
+
+    let mut result = compile_node(coll, context, program)?;
+    let kind = resolve_value_kind(coll, result.kind, context);
+    if !matches!(kind, ValueKind::Map | ValueKind::Set | ValueKind::Nil | ValueKind::Any) {
+        return Err(CompileError::InvalidExpression(format!("{} requires a sorted map or set", op)));
+    }
+    extend_with_offset(&mut instructions, std::mem::take(&mut result.instructions));
+    retained_slots.extend(result.take_retained_slots());
+    tracker.track_if_owned(&mut instructions, context, result.heap_ownership, if kind == ValueKind::Set { ValueKind::Set } else { ValueKind::Map });
+
+    compile_bound(start, &mut instructions, &mut tracker, context, program)?;
+    compile_bound(end, &mut instructions, &mut tracker, context, program)?;
+    instructions.push(IRInstruction::Push((op == "rsubseq") as i64));
+    instructions.push(IRInstruction::RuntimeCall("_sorted_subseq".to_string(), 6));
+    let instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let item_kind = match kind {
+        ValueKind::Map => Some(ValueKind::Vector),
+        ValueKind::Set => result.set_element_kind.filter(|kind| *kind != ValueKind::Any),
+        _ => None,
+    };
+    Ok(shared_result(instructions, ValueKind::List, retained_slots).with_vector_element_kind(item_kind))
+}
@@ -10,6 +10,11 @@ pub enum MapKeyLiteral {
     Number(i64),
     Boolean(bool),
     Nil,
+    /// Literal keys leading into nested maps; only used as a `MapValueTypes` entry
+    Path(Vec<MapKeyLiteral>),
+    /// The kind shared by every value without an entry of its own, for maps built with computed
+    /// keys; only used as a `MapValueTypes` entry
+    Every,
 }
 
 const TAG_NIL: i64 = 0;
@@ -20,6 +25,10 @@ const TAG_VECTOR: i64 = 4;
 const TAG_MAP: i64 = 5;
 const TAG_KEYWORD: i64 = 6;
 const TAG_SET: i64 = 7;
+const TAG_LIST: i64 = 8;
+const TAG_SYMBOL: i64 = 9;
+const TAG_LAZY_SEQ: i64 = 10;
+const TAG_ATOM: i64 = 11;
 const TAG_ANY: i64 = 0xff;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -32,16 +41,34 @@ pub enum ValueKind {
     Vector,
     Map,
     Set,
+    List,
+    Symbol,
+    LazySeq,
+    Atom,
     Nil,
 }
 
 impl ValueKind {
     pub fn is_heap_kind(self) -> bool {
-        matches!(self, ValueKind::String | ValueKind::Vector | ValueKind::Map | ValueKind::Set)
+        matches!(
+            self,
+            ValueKind::String | ValueKind::Vector | ValueKind::Map | ValueKind::Set | ValueKind::List | ValueKind::Symbol | ValueKind::LazySeq | ValueKind::Atom
+        )
+    }
+
+    /// Whether values of this kind can be map keys or set members; collections compare structurally
+    pub fn is_key_kind(self) -> bool {
+        matches!(
+            self,
+            ValueKind::Number | ValueKind::Boolean | ValueKind::String | ValueKind::Keyword | ValueKind::Vector | ValueKind::Map | ValueKind::Set | ValueKind::List | ValueKind::Nil
+        )
     }
 
     pub fn is_heap_clone_kind(self) -> bool {
-        matches!(self, ValueKind::String | ValueKind::Keyword | ValueKind::Vector | ValueKind::Map | ValueKind::Set)
+        matches!(
+            self,
+            ValueKind::String | ValueKind::Keyword | ValueKind::Vector | ValueKind::Map | ValueKind::Set | ValueKind::List | ValueKind::Symbol | ValueKind::LazySeq | ValueKind::Atom
+        )
     }
 
     pub fn runtime_tag(self) -> i64 {
@@ -54,6 +81,10 @@ impl ValueKind {
             ValueKind::Vector => TAG_VECTOR,
             ValueKind::Map => TAG_MAP,
             ValueKind::Set => TAG_SET,
+            ValueKind::List => TAG_LIST,
+            ValueKind::Symbol => TAG_SYMBOL,
+            ValueKind::LazySeq => TAG_LAZY_SEQ,
+            ValueKind::Atom => TAG_ATOM,
             ValueKind::Any => TAG_ANY,
         }
     }
@@ -86,8 +117,57 @@ pub struct RetainedSlot {
     pub dependents: Vec<RetainedSlot>,
 }
 
+/// Value kinds of a map's literal keys. A value that is itself a map also contributes its entries
+/// under `MapKeyLiteral::Path` keys, so lookups through nested maps keep their types.
 pub type MapValueTypes = HashMap<MapKeyLiteral, ValueKind>;
 
+/// The entries of `types` describing the map stored under `key`
+pub fn nested_map_value_types(types: &MapValueTypes, key: &MapKeyLiteral) -> Option<MapValueTypes> {
+    let nested: MapValueTypes = types
+        .iter()
+        .filter_map(|(entry, kind)| match entry {
+            MapKeyLiteral::Path(path) if path.first() == Some(key) => {
+                let rest = &path[1..];
+                let entry = if rest.len() == 1 { rest[0].clone() } else { MapKeyLiteral::Path(rest.to_vec()) };
+                Some((entry, *kind))
+            }
+            _ => None,
+        })
+        .collect();
+    (!nested.is_empty()).then_some(nested)
+}
+
+/// The kind stored under `key`, falling back to the kind every value shares. A key that is not a
+/// literal only finds the shared kind.
+pub fn map_value_type(types: &MapValueTypes, key: Option<&MapKeyLiteral>) -> Option<ValueKind> {
+    key.and_then(|key| types.get(key)).or_else(|| types.get(&MapKeyLiteral::Every)).copied()
+}
+
+/// Record the kind stored under `key`, replacing anything known about its previous value. `nested`
+/// describes the value when it is itself a map.
+pub fn set_map_value_type(types: &mut MapValueTypes, key: MapKeyLiteral, kind: ValueKind, nested: Option<&MapValueTypes>) {
+    remove_map_value_type(types, &key);
+    if kind == ValueKind::Any {
+        return;
+    }
+    if kind == ValueKind::Map {
+        for (entry, entry_kind) in nested.into_iter().flatten() {
+            let mut path = vec![key.clone()];
+            match entry {
+                MapKeyLiteral::Path(rest) => path.extend(rest.iter().cloned()),
+                other => path.push(other.clone()),
+            }
+            types.insert(MapKeyLiteral::Path(path), *entry_kind);
+        }
+    }
+    types.insert(key, kind);
+}
+
+/// Forget the kind stored under `key`, along with any nested map entries beneath it
+pub fn remove_map_value_type(types: &mut MapValueTypes, key: &MapKeyLiteral) {
+    types.retain(|entry, _| entry != key && !matches!(entry, MapKeyLiteral::Path(path) if path.first() == Some(key)));
+}
+
 pub struct CompileResult {
     pub instructions: Vec<IRInstruction>,
     pub kind: ValueKind,
@@ -95,7 +175,9 @@ pub struct CompileResult {
     pub map_value_types: Option<MapValueTypes>,
     pub set_element_kind: Option<ValueKind>,
     pub vector_element_kind: Option<ValueKind>,
+    pub record_type: Option<String>, // the `defrecord` type every value of this expression has
     pub retained_slots: Vec<RetainedSlot>,
+    pub diverges: bool, // control never reaches the end of the instructions (e.g. `throw`)
 }
 
 impl CompileResult {
@@ -107,7 +189,9 @@ impl CompileResult {
             map_value_types: None,
             set_element_kind: None,
             vector_element_kind: None,
+            record_type: None,
             retained_slots: Vec::new(),
+            diverges: false,
         }
     }
 
@@ -131,11 +215,21 @@ impl CompileResult {
         self
     }
 
+    pub fn with_record_type(mut self, record: Option<String>) -> Self {
+        self.record_type = record;
+        self
+    }
+
     pub fn with_retained_slots(mut self, slots: Vec<RetainedSlot>) -> Self {
         self.retained_slots = slots;
         self
     }
 
+    pub fn with_diverges(mut self, diverges: bool) -> Self {
+        self.diverges = diverges;
+        self
+    }
+
     pub fn take_retained_slots(&mut self) -> Vec<RetainedSlot> {
         std::mem::take(&mut self.retained_slots)
     }
@@ -0,0 +1,229 @@
+/// Vector operations: conj, peek, pop, subvec and assoc by index
+///
+/// Vectors grow at the back and lists at the front, so `conj` lowers to `_vector_conj` or
+/// `_list_conj` and `peek`/`pop` work at the matching end; `conj` onto a set lowers to `_set_conj`. The vector helpers copy their argument;
+/// when liveness proves the old vector is dead after the call, the planner switches to the
+/// in-place variant (`_vector_conj_owned`, ...), which reuses the owned block and its spare
+/// capacity. Like the elements of a vector literal, added heap values stay alive in retained slots
+/// of the result.
+use super::{
+    builtins::{ensure_owned_on_stack, resolve_value_kind, retains_slot, track_heap_slot},
+    compile_node,
+    expressions::dedup_retained_slots,
+    extend_with_offset,
+    sequences::compile_number,
+    slots::SlotTracker,
+    CompileContext, CompileError, CompileResult, HeapOwnership, RetainedSlot, ValueKind,
+};
+use crate::ast::Node;
+use crate::ir::{IRInstruction, IRProgram};
+
+/// Compile a value added to a collection and push it with its tag
+fn push_added_value(
+    node: &Node,
+    instructions: &mut Vec<IRInstruction>,
+    retained_slots: &mut Vec<RetainedSlot>,
+    context: &mut CompileContext,
+    program: &mut IRProgram,
+) -> Result<ValueKind, CompileError> {
+    let mut result = compile_node(node, context, program)?;
+    let kind = resolve_value_kind(node, result.kind, context);
+    extend_with_offset(instructions, std::mem::take(&mut result.instructions));
+
+    let rodata_symbol = kind == ValueKind::Symbol && result.heap_ownership == HeapOwnership::None;
+    if !rodata_symbol {
+        ensure_owned_on_stack(instructions, kind, &mut result.heap_ownership);
+    }
+    let slot = context.allocate_temp_slot();
+    instructions.push(IRInstruction::StoreLocal(slot));
+    let dependents = result.take_retained_slots();
+    if !rodata_symbol {
+        track_heap_slot(retained_slots, slot, kind, None, dependents);
+    }
+    instructions.push(IRInstruction::LoadLocal(slot));
+    instructions.push(IRInstruction::Push(kind.runtime_tag()));
+
+    if !retains_slot(retained_slots, slot) {
+        context.release_temp_slot(slot);
+    }
+    Ok(kind)
+}
+
+/// Element kind after adding an item of `added`; `None` while the collection is known to be empty
+fn merge_element_kind(current: Option<Option<ValueKind>>, added: ValueKind) -> Option<Option<ValueKind>> {
+    match current {
+        None => Some(Some(added)),
+        Some(Some(existing)) if existing == added => Some(Some(existing)),
+        Some(_) => Some(None),
+    }
+}
+
+fn is_empty_literal(node: &Node, kind: ValueKind) -> bool {
+    kind == ValueKind::Nil || matches!(node, Node::Vector { root } | Node::Set { root } if root.is_empty())
+}
+
+/// Compile conj (add items to the back of a vector, the front of a list or nil, or to a set)
+pub(super) fn compile_conj(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.is_empty() {
+        return Err(CompileError::ArityError("conj".to_string(), 1, 0));
+    }
+
+    let mut base_result = compile_node(&args[0], context, program)?;
+    if args.len() == 1 {
+        return Ok(base_result);
+    }
+    let base_kind = resolve_value_kind(&args[0], base_result.kind, context);
+    let (runtime, kind) = match base_kind {
+        ValueKind::Vector => ("_vector_conj", ValueKind::Vector),
+        ValueKind::List | ValueKind::Nil => ("_list_conj", ValueKind::List),
+        ValueKind::Set => ("_set_conj", ValueKind::Set),
+        _ => return Err(CompileError::InvalidExpression("conj requires a vector, list, set, or nil".to_string())),
+    };
+
+    let mut instructions = std::mem::take(&mut base_result.instructions);
+    let mut retained_slots = base_result.take_retained_slots();
+    let mut tracker = SlotTracker::new();
+    let mut ownership = base_result.heap_ownership;
+    let base_element_kind = if kind == ValueKind::Set { base_result.set_element_kind } else { base_result.vector_element_kind };
+    let mut element_kind = if is_empty_literal(&args[0], base_kind) { None } else { Some(base_element_kind) };
+
+    for value in &args[1..] {
+        // Each intermediate collection is dead once the next item is added, so it is reused in place
+        tracker.track_if_owned(&mut instructions, context, ownership, kind);
+        let value_kind = push_added_value(value, &mut instructions, &mut retained_slots, context, program)?;
+        if kind == ValueKind::Set && !value_kind.is_key_kind() {
+            return Err(CompileError::InvalidExpression(
+                "set members must be numbers, booleans, strings, keywords, nil, or collections of them".to_string(),
+            ));
+        }
+        instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 3));
+        ownership = HeapOwnership::Owned;
+        element_kind = merge_element_kind(element_kind, value_kind);
+    }
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+    dedup_retained_slots(&mut retained_slots);
+
+    let element_kind = element_kind.flatten().filter(|kind| *kind != ValueKind::Any);
+    let result = CompileResult::with_instructions(instructions, kind)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_retained_slots(retained_slots);
+    if kind == ValueKind::Set {
+        Ok(result.with_set_element_kind(element_kind))
+    } else {
+        Ok(result.with_vector_element_kind(element_kind))
+    }
+}
+
+/// Compile assoc on a vector (replace the item at each index; the length appends)
+pub(super) fn compile_vector_assoc(mut base_result: CompileResult, args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    let mut instructions = std::mem::take(&mut base_result.instructions);
+    let mut retained_slots = base_result.take_retained_slots();
+    let mut tracker = SlotTracker::new();
+    let mut ownership = base_result.heap_ownership;
+    let mut element_kind = if is_empty_literal(&args[0], ValueKind::Vector) {
+        None
+    } else {
+        Some(base_result.vector_element_kind)
+    };
+
+    for pair in args[1..].chunks(2) {
+        tracker.track_if_owned(&mut instructions, context, ownership, ValueKind::Vector);
+        compile_number("assoc", &pair[0], &mut instructions, context, program)?;
+        let value_kind = push_added_value(&pair[1], &mut instructions, &mut retained_slots, context, program)?;
+        instructions.push(IRInstruction::RuntimeCall("_vector_assoc".to_string(), 4));
+        ownership = HeapOwnership::Owned;
+        element_kind = merge_element_kind(element_kind, value_kind);
+    }
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+    dedup_retained_slots(&mut retained_slots);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Vector)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(element_kind.flatten().filter(|kind| *kind != ValueKind::Any))
+        .with_retained_slots(retained_slots))
+}
+
+/// Compile peek (the last item of a vector or the first of a list, nil when empty)
+pub(super) fn compile_peek(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("peek".to_string(), 1, args.len()));
+    }
+
+    let mut target_result = compile_node(&args[0], context, program)?;
+    let kind = resolve_value_kind(&args[0], target_result.kind, context);
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let mut tracker = SlotTracker::new();
+
+    // Items outlive the collection, so an owned target is released right after the lookup
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, kind);
+    match kind {
+        ValueKind::Vector => instructions.push(IRInstruction::RuntimeCall("_vector_peek".to_string(), 1)),
+        ValueKind::List | ValueKind::Nil => {
+            instructions.push(IRInstruction::Push(kind.runtime_tag()));
+            instructions.push(IRInstruction::RuntimeCall("_seq_first".to_string(), 2));
+        }
+        _ => return Err(CompileError::InvalidExpression("peek requires a vector, list, or nil".to_string())),
+    }
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    let element_kind = target_result.vector_element_kind.unwrap_or(ValueKind::Any);
+    let ownership = if element_kind.is_heap_kind() { HeapOwnership::Borrowed } else { HeapOwnership::None };
+    Ok(CompileResult::with_instructions(instructions, element_kind)
+        .with_heap_ownership(ownership)
+        .with_retained_slots(target_result.take_retained_slots()))
+}
+
+/// Compile pop (a vector without its last item or a list without its first; nil stays nil)
+pub(super) fn compile_pop(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 1 {
+        return Err(CompileError::ArityError("pop".to_string(), 1, args.len()));
+    }
+
+    let mut target_result = compile_node(&args[0], context, program)?;
+    let kind = resolve_value_kind(&args[0], target_result.kind, context);
+    let runtime = match kind {
+        ValueKind::Vector => "_vector_pop",
+        ValueKind::List => "_list_pop",
+        ValueKind::Nil => return Ok(target_result),
+        _ => return Err(CompileError::InvalidExpression("pop requires a vector, list, or nil".to_string())),
+    };
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let mut tracker = SlotTracker::new();
+
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, kind);
+    instructions.push(IRInstruction::RuntimeCall(runtime.to_string(), 1));
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, kind)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(target_result.vector_element_kind)
+        .with_retained_slots(target_result.take_retained_slots()))
+}
+
+/// Compile subvec (the items of a vector from start up to end, or to its end)
+pub(super) fn compile_subvec(args: &[Node], context: &mut CompileContext, program: &mut IRProgram) -> Result<CompileResult, CompileError> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(CompileError::ArityError("subvec".to_string(), 2, args.len()));
+    }
+
+    let mut target_result = compile_node(&args[0], context, program)?;
+    if resolve_value_kind(&args[0], target_result.kind, context) != ValueKind::Vector {
+        return Err(CompileError::InvalidExpression("subvec requires a vector".to_string()));
+    }
+    let mut instructions = std::mem::take(&mut target_result.instructions);
+    let mut tracker = SlotTracker::new();
+
+    tracker.track_if_owned(&mut instructions, context, target_result.heap_ownership, ValueKind::Vector);
+    compile_number("subvec", &args[1], &mut instructions, context, program)?;
+    match args.get(2) {
+        Some(end) => compile_number("subvec", end, &mut instructions, context, program)?,
+        None => instructions.push(IRInstruction::Push(-1)),
+    }
+    instructions.push(IRInstruction::RuntimeCall("_vector_subvec".to_string(), 3));
+    instructions = tracker.apply_liveness_and_release(instructions, context);
+
+    Ok(CompileResult::with_instructions(instructions, ValueKind::Vector)
+        .with_heap_ownership(HeapOwnership::Owned)
+        .with_vector_element_kind(target_result.vector_element_kind)
+        .with_retained_slots(target_result.take_retained_slots()))
+}
@@ -0,0 +1,117 @@
+use super::{primitives, special_forms, Environment, EvalError, Value};
+/// Atoms - atom, deref, reset!, compare-and-set! and add-watch; swap! is derived from reset!
+///
+/// An atom is a shared cell holding one value. Clones of an atom are the same atom, so a value
+/// stored through one is seen through every other, and atoms compare equal only to themselves.
+/// Watches run after each change with the watch key, the atom, and the old and new values.
+use crate::ast::Node;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub struct Atom(Rc<RefCell<AtomState>>);
+
+struct AtomState {
+    value: Value,
+    watches: Vec<(Value, Value)>, // key and watch function, in the order they were added
+}
+
+impl Atom {
+    /// The atom's current value
+    pub fn value(&self) -> Value {
+        self.0.borrow().value.clone()
+    }
+
+    /// Store a new value, then call every watch with the value it replaced
+    fn replace(&self, value: Value) -> Result<(), EvalError> {
+        // The cell is not borrowed while watches run, so a watch may read or change the atom
+        let (old, watches) = {
+            let mut state = self.0.borrow_mut();
+            (std::mem::replace(&mut state.value, value.clone()), state.watches.clone())
+        };
+        for (key, watch) in watches {
+            special_forms::apply_function(watch, vec![key, Value::Atom(self.clone()), old.clone(), value.clone()])?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Atom {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Atom")
+    }
+}
+
+fn eval_args(op_name: &str, args: &[Node], expected: usize, env: &mut Environment) -> Result<Vec<Value>, EvalError> {
+    if args.len() != expected {
+        return Err(EvalError::ArityError(op_name.to_string(), expected, args.len()));
+    }
+    args.iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect()
+}
+
+fn expect_atom(op_name: &str, value: &Value) -> Result<Atom, EvalError> {
+    match value {
+        Value::Atom(atom) => Ok(atom.clone()),
+        _ => Err(EvalError::TypeError(format!("{}: first argument must be an atom", op_name))),
+    }
+}
+
+/// atom - A new atom holding the given value
+pub fn eval_atom(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let value = eval_args("atom", args, 1, env)?.remove(0);
+    Ok(Value::Atom(Atom(Rc::new(RefCell::new(AtomState { value, watches: Vec::new() })))))
+}
+
+/// deref - The current value of an atom; `@a` reads as `(deref a)`
+pub fn eval_deref(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let values = eval_args("deref", args, 1, env)?;
+    Ok(expect_atom("deref", &values[0])?.value())
+}
+
+/// reset! - Store a value in an atom, returning the value
+pub fn eval_reset(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let mut values = eval_args("reset!", args, 2, env)?;
+    let atom = expect_atom("reset!", &values[0])?;
+    let value = values.remove(1);
+    atom.replace(value.clone())?;
+    Ok(value)
+}
+
+/// compare-and-set! - Store a value only if the atom's current value is `=` to the expected one;
+/// returns whether it was stored
+pub fn eval_compare_and_set(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let mut values = eval_args("compare-and-set!", args, 3, env)?;
+    let atom = expect_atom("compare-and-set!", &values[0])?;
+    let value = values.remove(2);
+    if !primitives::values_equal(atom.value(), values.remove(1))? {
+        return Ok(Value::Boolean(false));
+    }
+    atom.replace(value)?;
+    Ok(Value::Boolean(true))
+}
+
+/// add-watch - Call a function of key, atom, old and new value after each change to an atom; a
+/// watch added again under the same key replaces the earlier one. Returns the atom
+pub fn eval_add_watch(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let values = eval_args("add-watch", args, 3, env)?;
+    let atom = expect_atom("add-watch", &values[0])?;
+    let (key, watch) = (values[1].clone(), values[2].clone());
+    if !matches!(watch, Value::Function { .. }) {
+        return Err(EvalError::TypeError("add-watch: third argument must be a function".to_string()));
+    }
+
+    let mut state = atom.0.borrow_mut();
+    match state.watches.iter_mut().find(|(existing, _)| *existing == key) {
+        Some(entry) => entry.1 = watch,
+        None => state.watches.push((key, watch)),
+    }
+    drop(state);
+    Ok(Value::Atom(atom))
+}
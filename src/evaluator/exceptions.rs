@@ -0,0 +1,221 @@
+use super::{Environment, EvalError, MapKey, PersistentMap, Value};
+/// Exceptions - throw, try/catch/finally, ex-info and its accessors
+///
+/// `ex-info` values are plain maps carrying `:message` and `:data` (plus an optional `:cause`),
+/// which keeps them printable and lets both backends share one representation. Evaluation errors
+/// raised by the interpreter itself (type errors, arity errors, ...) can be caught as well; they are
+/// surfaced to `catch` as a map holding only `:message`, so `ExceptionInfo` clauses skip them.
+use crate::ast::Node;
+
+const MESSAGE_KEY: &str = "message";
+const DATA_KEY: &str = "data";
+const CAUSE_KEY: &str = "cause";
+
+struct CatchClause<'a> {
+    class: &'a Node,
+    binding: &'a str,
+    body: &'a [Node],
+}
+
+struct TryForm<'a> {
+    body: &'a [Node],
+    catches: Vec<CatchClause<'a>>,
+    finally: Option<&'a [Node]>,
+}
+
+/// Build an ex-info map: `(ex-info msg data)` or `(ex-info msg data cause)`
+pub fn eval_ex_info(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(EvalError::ArityError("ex-info".to_string(), 2, args.len()));
+    }
+
+    let message = match crate::evaluator::eval_with_env(&args[0], env)? {
+        Value::String(message) => message,
+        _ => return Err(EvalError::TypeError("ex-info message must be a string".to_string())),
+    };
+
+    let data = crate::evaluator::eval_with_env(&args[1], env)?;
+    if !matches!(data, Value::Map(_) | Value::Nil) {
+        return Err(EvalError::TypeError("ex-info data must be a map or nil".to_string()));
+    }
+
+    let mut entries = PersistentMap::new();
+    entries.insert(MapKey::Keyword(MESSAGE_KEY.to_string()), Value::String(message));
+    entries.insert(MapKey::Keyword(DATA_KEY.to_string()), data);
+
+    if let Some(cause_node) = args.get(2) {
+        let cause = crate::evaluator::eval_with_env(cause_node, env)?;
+        entries.insert(MapKey::Keyword(CAUSE_KEY.to_string()), cause);
+    }
+
+    Ok(Value::Map(entries))
+}
+
+/// ex-message - the message of an exception value, or nil
+pub fn eval_ex_message(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    eval_exception_field(args, env, "ex-message", MESSAGE_KEY)
+}
+
+/// ex-data - the data map of an ex-info value, or nil
+pub fn eval_ex_data(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    eval_exception_field(args, env, "ex-data", DATA_KEY)
+}
+
+/// ex-cause - the cause attached to an ex-info value, or nil
+pub fn eval_ex_cause(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    eval_exception_field(args, env, "ex-cause", CAUSE_KEY)
+}
+
+fn eval_exception_field(args: &[Node], env: &mut Environment, op_name: &str, key: &str) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError(op_name.to_string(), 1, args.len()));
+    }
+
+    let value = crate::evaluator::eval_with_env(&args[0], env)?;
+    Ok(exception_field(&value, key).cloned().unwrap_or(Value::Nil))
+}
+
+fn exception_field<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(entries) => entries.get(&MapKey::Keyword(key.to_string())),
+        _ => None,
+    }
+}
+
+/// Whether a value was produced by `ex-info`
+pub(crate) fn is_ex_info(value: &Value) -> bool {
+    matches!(exception_field(value, MESSAGE_KEY), Some(Value::String(_))) && exception_field(value, DATA_KEY).is_some()
+}
+
+/// throw - raise a value as an exception
+pub fn eval_throw(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("throw".to_string(), 1, args.len()));
+    }
+
+    let value = crate::evaluator::eval_with_env(&args[0], env)?;
+    Err(EvalError::Thrown(value))
+}
+
+/// try - evaluate body forms, routing exceptions to the first matching catch clause.
+/// A finally block always runs; an error raised from it replaces the original outcome.
+pub fn eval_try(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let form = parse_try(args)?;
+
+    let outcome = match eval_body(form.body, env) {
+        Err(error) => handle_error(error, &form.catches, env),
+        ok => ok,
+    };
+
+    if let Some(finally_body) = form.finally {
+        eval_body(finally_body, env)?;
+    }
+
+    outcome
+}
+
+fn parse_try(args: &[Node]) -> Result<TryForm<'_>, EvalError> {
+    let clause_start = args.iter().position(|node| clause_name(node).is_some()).unwrap_or(args.len());
+    let mut form = TryForm {
+        body: &args[..clause_start],
+        catches: Vec::new(),
+        finally: None,
+    };
+
+    for (idx, clause) in args[clause_start..].iter().enumerate() {
+        let Node::List { root } = clause else {
+            return Err(EvalError::InvalidOperation("try body forms must precede catch and finally clauses".to_string()));
+        };
+
+        match clause_name(clause) {
+            Some("catch") => {
+                if form.finally.is_some() {
+                    return Err(EvalError::InvalidOperation("finally must be the last clause in try".to_string()));
+                }
+                if root.len() < 3 {
+                    return Err(EvalError::ArityError("catch".to_string(), 2, root.len() - 1));
+                }
+                let binding = match &root[2] {
+                    Node::Symbol { value } => value.as_str(),
+                    _ => return Err(EvalError::TypeError("catch binding must be a symbol".to_string())),
+                };
+                validate_catch_class(&root[1])?;
+                form.catches.push(CatchClause {
+                    class: &root[1],
+                    binding,
+                    body: &root[3..],
+                });
+            }
+            Some("finally") => {
+                if clause_start + idx != args.len() - 1 {
+                    return Err(EvalError::InvalidOperation("finally must be the last clause in try".to_string()));
+                }
+                form.finally = Some(&root[1..]);
+            }
+            _ => return Err(EvalError::InvalidOperation("try body forms must precede catch and finally clauses".to_string())),
+        }
+    }
+
+    Ok(form)
+}
+
+fn clause_name(node: &Node) -> Option<&str> {
+    match node {
+        Node::List { root } => match root.first() {
+            Some(Node::Symbol { value }) if value == "catch" || value == "finally" => Some(value.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn validate_catch_class(class: &Node) -> Result<(), EvalError> {
+    match class {
+        Node::Symbol { value } if matches!(value.as_str(), "Exception" | "Throwable" | "Object" | "ExceptionInfo") => Ok(()),
+        Node::Primitive {
+            value: crate::ast::Primitive::Keyword(keyword),
+        } if keyword == "default" => Ok(()),
+        _ => Err(EvalError::TypeError("catch expects Exception, Throwable, Object, ExceptionInfo, or :default".to_string())),
+    }
+}
+
+fn catch_matches(class: &Node, value: &Value) -> bool {
+    match class {
+        Node::Symbol { value: name } if name == "ExceptionInfo" => is_ex_info(value),
+        _ => true,
+    }
+}
+
+fn handle_error(error: EvalError, catches: &[CatchClause<'_>], env: &mut Environment) -> Result<Value, EvalError> {
+    let thrown = match error {
+        EvalError::Thrown(value) => value,
+        other => error_to_value(&other),
+    };
+
+    match catches.iter().find(|clause| catch_matches(clause.class, &thrown)) {
+        Some(clause) => {
+            let mut catch_env = env.clone();
+            catch_env.insert(clause.binding.to_string(), thrown);
+            eval_body(clause.body, &mut catch_env)
+        }
+        None => Err(EvalError::Thrown(thrown)),
+    }
+}
+
+/// Surface an interpreter error to `catch` as a message-only map
+fn error_to_value(error: &EvalError) -> Value {
+    let message = match error {
+        EvalError::UndefinedSymbol(symbol) => format!("Unable to resolve symbol: {}", symbol),
+        EvalError::InvalidOperation(msg) | EvalError::TypeError(msg) | EvalError::ArithmeticError(msg) => msg.clone(),
+        EvalError::ArityError(op, expected, actual) => format!("Wrong number of args ({}) passed to {}, expected {}", actual, op, expected),
+        EvalError::Thrown(value) => return value.clone(),
+    };
+
+    let mut entries = PersistentMap::new();
+    entries.insert(MapKey::Keyword(MESSAGE_KEY.to_string()), Value::String(message));
+    Value::Map(entries)
+}
+
+fn eval_body(body: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    body.iter().try_fold(Value::Nil, |_, node| crate::evaluator::eval_with_env(node, env))
+}
@@ -0,0 +1,278 @@
+use super::{primitives, special_forms, Environment, EvalError, Value};
+/// Lazy sequences - lazy-seq, iterate, repeat, cycle and the zero-argument range, plus the lazy map,
+/// filter, remove and take used over them
+///
+/// A lazy sequence is a shared cell holding either a pending step or its realized first item and
+/// rest. Realizing a cell replaces the step with its result, so every clone of a sequence sees the
+/// same items and callbacks run once per item.
+use crate::ast::Node;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub struct LazySeq(Rc<RefCell<LazyState>>);
+
+enum LazyState {
+    Pending(Step),
+    Realized(Option<(Value, Value)>), // first item and the rest of the sequence; None when empty
+}
+
+/// The work that produces the next cell of a sequence
+enum Step {
+    Body { body: Node, env: Environment },
+    Iterate { function: Value, previous: Value },
+    Repeat { value: Value, remaining: Option<usize> },
+    Cycle { items: Rc<Vec<Value>>, index: usize },
+    Range { next: isize },
+    Map { function: Value, source: Value },
+    Filter { function: Value, source: Value, keep: bool },
+    Take { count: usize, source: Value },
+}
+
+impl LazySeq {
+    fn pending(step: Step) -> Value {
+        Value::LazySeq(LazySeq(Rc::new(RefCell::new(LazyState::Pending(step)))))
+    }
+
+    /// A sequence whose first cell is already known (used by `cons` onto a lazy sequence)
+    pub(super) fn realized(first: Value, rest: Value) -> Value {
+        Value::LazySeq(LazySeq(Rc::new(RefCell::new(LazyState::Realized(Some((first, rest)))))))
+    }
+
+    /// The first item and the rest of the sequence, realizing the first cell if needed
+    pub(super) fn first_and_rest(&self) -> Result<Option<(Value, Value)>, EvalError> {
+        let step = match &mut *self.0.borrow_mut() {
+            LazyState::Realized(cell) => return Ok(cell.clone()),
+            state => match std::mem::replace(state, LazyState::Realized(None)) {
+                LazyState::Pending(step) => step,
+                LazyState::Realized(_) => unreachable!("realized cells return above"),
+            },
+        };
+
+        // The cell is not borrowed while the step runs, so the step may read other sequences
+        match step.run() {
+            Ok(cell) => {
+                *self.0.borrow_mut() = LazyState::Realized(cell.clone());
+                Ok(cell)
+            }
+            Err(err) => {
+                *self.0.borrow_mut() = LazyState::Pending(step);
+                Err(err)
+            }
+        }
+    }
+
+    /// Every item of the sequence; does not return for an unbounded one
+    pub fn items(&self) -> Result<Vec<Value>, EvalError> {
+        let mut items = Vec::new();
+        let mut current = Value::LazySeq(self.clone());
+        while let Some((first, rest)) = first_and_rest(current, "lazy-seq")? {
+            items.push(first);
+            current = rest;
+        }
+        Ok(items)
+    }
+
+    /// Take the rest of a realized cell that nothing else refers to
+    fn take_unshared_rest(&self) -> Option<Value> {
+        if Rc::strong_count(&self.0) != 1 {
+            return None;
+        }
+        match &mut *self.0.try_borrow_mut().ok()? {
+            LazyState::Realized(Some((_, rest))) => Some(std::mem::replace(rest, Value::Nil)),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for LazySeq {
+    fn drop(&mut self) {
+        // Unlink realized cells one at a time so dropping a long sequence does not recurse
+        let mut rest = self.take_unshared_rest();
+        while let Some(Value::LazySeq(seq)) = rest {
+            rest = seq.take_unshared_rest();
+        }
+    }
+}
+
+impl PartialEq for LazySeq {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for LazySeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LazySeq")
+    }
+}
+
+impl Step {
+    fn run(&self) -> Result<Option<(Value, Value)>, EvalError> {
+        match self {
+            Step::Body { body, env } => first_and_rest(crate::evaluator::eval_with_env(body, &mut env.clone())?, "lazy-seq"),
+            Step::Iterate { function, previous } => {
+                let next = special_forms::apply_function(function.clone(), vec![previous.clone()])?;
+                let rest = LazySeq::pending(Step::Iterate {
+                    function: function.clone(),
+                    previous: next.clone(),
+                });
+                Ok(Some((next, rest)))
+            }
+            Step::Repeat { value, remaining } => {
+                if *remaining == Some(0) {
+                    return Ok(None);
+                }
+                let rest = LazySeq::pending(Step::Repeat {
+                    value: value.clone(),
+                    remaining: remaining.map(|count| count - 1),
+                });
+                Ok(Some((value.clone(), rest)))
+            }
+            Step::Cycle { items, index } => {
+                if items.is_empty() {
+                    return Ok(None);
+                }
+                let rest = LazySeq::pending(Step::Cycle {
+                    items: Rc::clone(items),
+                    index: (index + 1) % items.len(),
+                });
+                Ok(Some((items[*index].clone(), rest)))
+            }
+            Step::Range { next } => {
+                // The sequence ends rather than overflowing
+                let rest = match next.checked_add(1) {
+                    Some(after) => LazySeq::pending(Step::Range { next: after }),
+                    None => Value::Nil,
+                };
+                Ok(Some((Value::Number(*next), rest)))
+            }
+            Step::Map { function, source } => {
+                let Some((first, rest)) = first_and_rest(source.clone(), "map")? else {
+                    return Ok(None);
+                };
+                let mapped = special_forms::apply_function(function.clone(), vec![first])?;
+                Ok(Some((mapped, map(function.clone(), rest))))
+            }
+            Step::Filter { function, source, keep } => {
+                let mut current = source.clone();
+                while let Some((first, rest)) = first_and_rest(current, "filter")? {
+                    let verdict = special_forms::apply_function(function.clone(), vec![first.clone()])?;
+                    if primitives::is_truthy(&verdict) == *keep {
+                        return Ok(Some((first, filter(function.clone(), rest, *keep))));
+                    }
+                    current = rest;
+                }
+                Ok(None)
+            }
+            Step::Take { count, source } => {
+                if *count == 0 {
+                    return Ok(None);
+                }
+                let Some((first, rest)) = first_and_rest(source.clone(), "take")? else {
+                    return Ok(None);
+                };
+                Ok(Some((first, take(count - 1, rest))))
+            }
+        }
+    }
+}
+
+/// Split a collection into its first item and the rest; None when it is empty
+pub(super) fn first_and_rest(value: Value, op_name: &str) -> Result<Option<(Value, Value)>, EvalError> {
+    match value {
+        Value::LazySeq(seq) => seq.first_and_rest(),
+        other => {
+            let mut items = super::sequences::items(other, op_name)?.into_iter();
+            Ok(items.next().map(|first| (first, Value::List(items.collect()))))
+        }
+    }
+}
+
+/// A value with lazy sequences realized into lists, for comparing and printing
+pub(super) fn realize(value: Value) -> Result<Value, EvalError> {
+    match value {
+        Value::LazySeq(seq) => Ok(Value::List(seq.items()?)),
+        other => Ok(other),
+    }
+}
+
+pub(super) fn map(function: Value, source: Value) -> Value {
+    LazySeq::pending(Step::Map { function, source })
+}
+
+pub(super) fn filter(function: Value, source: Value, keep: bool) -> Value {
+    LazySeq::pending(Step::Filter { function, source, keep })
+}
+
+pub(super) fn take(count: usize, source: Value) -> Value {
+    LazySeq::pending(Step::Take { count, source })
+}
+
+/// Everything after the first `count` items, realizing only the skipped ones
+pub(super) fn drop(count: usize, source: Value) -> Result<Value, EvalError> {
+    let mut current = source;
+    for _ in 0..count {
+        match first_and_rest(current, "drop")? {
+            Some((_, rest)) => current = rest,
+            None => return Ok(Value::List(Vec::new())),
+        }
+    }
+    Ok(current)
+}
+
+/// (range) - Every number from zero up
+pub(super) fn unbounded_range() -> Value {
+    LazySeq::pending(Step::Range { next: 0 })
+}
+
+/// lazy-seq - Defer evaluating a body that produces a sequence until its items are needed
+pub fn eval_lazy_seq(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("lazy-seq".to_string(), 1, args.len()));
+    }
+
+    Ok(LazySeq::pending(Step::Body {
+        body: args[0].clone(),
+        env: env.clone(),
+    }))
+}
+
+/// iterate - The unbounded sequence x, (f x), (f (f x)), ...
+pub fn eval_iterate(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("iterate".to_string(), 2, args.len()));
+    }
+
+    let function = match crate::evaluator::eval_with_env(&args[0], env)? {
+        function @ Value::Function { .. } => function,
+        _ => return Err(EvalError::TypeError("iterate: first argument must be a function".to_string())),
+    };
+    let seed = crate::evaluator::eval_with_env(&args[1], env)?;
+    let rest = LazySeq::pending(Step::Iterate { function, previous: seed.clone() });
+    Ok(LazySeq::realized(seed, rest))
+}
+
+/// repeat - A value repeated without end, or n times
+pub fn eval_repeat(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let (remaining, value) = match args {
+        [value] => (None, crate::evaluator::eval_with_env(value, env)?),
+        [count, value] => match crate::evaluator::eval_with_env(count, env)? {
+            Value::Number(n) => (Some(n.max(0) as usize), crate::evaluator::eval_with_env(value, env)?),
+            _ => return Err(EvalError::TypeError("repeat: count must be a number".to_string())),
+        },
+        _ => return Err(EvalError::ArityError("repeat".to_string(), 2, args.len())),
+    };
+    Ok(LazySeq::pending(Step::Repeat { value, remaining }))
+}
+
+/// cycle - The items of a collection repeated without end
+pub fn eval_cycle(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("cycle".to_string(), 1, args.len()));
+    }
+
+    let items = super::sequences::eval_items(&args[0], env, "cycle")?;
+    Ok(LazySeq::pending(Step::Cycle { items: Rc::new(items), index: 0 }))
+}
@@ -0,0 +1,400 @@
+use super::{records, sorted, special_forms, Environment, EvalError, MapKey, PersistentMap, Value};
+/// Macros - defmacro, quote, syntax-quote and the macroexpansion phase
+///
+/// Macros are expanded before evaluation and before `compile_program`: every top-level
+/// `defmacro` is evaluated by the interpreter into an expander function, and later calls to it
+/// are replaced by the code that function returns. Arguments reach the expander unevaluated,
+/// as data (lists, symbols, vectors, ...), and the data it returns is read back as code.
+/// - `quote` turns a form into data without evaluating it
+/// - syntax-quote (`` `form ``) builds data from a template, evaluating `~x` and splicing `~@xs`;
+///   symbols ending in `#` are replaced by a fresh name shared across one template
+/// - `macroexpand` and `macroexpand-1` are resolved during expansion, so their argument must
+///   be a literal `(quote form)`
+use crate::ast::{Node, Primitive};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Expansions of a single form before it is reported as non-terminating
+const MAX_EXPANSION_STEPS: usize = 1000;
+
+static GENSYM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Macros defined so far, plus the top-level functions their bodies may call
+#[derive(Default)]
+pub struct MacroExpander {
+    macros: HashMap<String, Value>,
+    env: Environment,
+}
+
+impl MacroExpander {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expand top-level forms in order. `defmacro` forms define macros for the forms after
+    /// them and are dropped from the result.
+    pub fn expand_program(&mut self, forms: &[Node]) -> Result<Vec<Node>, EvalError> {
+        let mut expanded = Vec::with_capacity(forms.len());
+        for form in forms {
+            if let Some(args) = form_args(form, "defmacro") {
+                self.define(args)?;
+                continue;
+            }
+
+            let form = self.expand(form)?;
+            if let Some(args) = form_args(&form, "defn") {
+                // Helpers the interpreter cannot define are simply not callable from macro bodies
+                let _ = special_forms::eval_defn(args, &mut self.env);
+            }
+            expanded.push(form);
+        }
+        Ok(expanded)
+    }
+
+    /// Expand a form until its head is no longer a macro call, then expand its subforms
+    pub fn expand(&self, node: &Node) -> Result<Node, EvalError> {
+        self.expand_in(node, &HashSet::new())
+    }
+
+    /// Expand a form where `locals` are bound; a local shadows the macro of the same name
+    fn expand_in(&self, node: &Node, locals: &HashSet<String>) -> Result<Node, EvalError> {
+        let expanded = self.expand_head(node, locals)?;
+        self.expand_subforms(&expanded, locals)
+    }
+
+    fn expand_all(&self, nodes: &[Node], locals: &HashSet<String>) -> Result<Vec<Node>, EvalError> {
+        nodes.iter().map(|child| self.expand_in(child, locals)).collect()
+    }
+
+    fn define(&mut self, args: &[Node]) -> Result<(), EvalError> {
+        let Some(Node::Symbol { value: name }) = args.first() else {
+            return Err(EvalError::TypeError("defmacro requires a symbol as first argument".to_string()));
+        };
+
+        // Macro bodies may use macros defined before them
+        let args = args.iter().map(|arg| self.expand(arg)).collect::<Result<Vec<_>, _>>()?;
+        let expander = special_forms::eval_defn(&args, &mut self.env.clone())?;
+        self.macros.insert(name.clone(), expander);
+        Ok(())
+    }
+
+    /// Apply the macro named by the head of `node` once; `None` when it is not a macro call
+    fn expand_1(&self, node: &Node, locals: &HashSet<String>) -> Result<Option<Node>, EvalError> {
+        let Node::List { root } = node else {
+            return Ok(None);
+        };
+        let Some(expander) = (match root.first() {
+            Some(Node::Symbol { value }) if !locals.contains(value) => self.macros.get(value),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+
+        let args = root[1..].iter().map(quote_node).collect::<Result<Vec<_>, _>>()?;
+        let expansion = special_forms::apply_function(expander.clone(), args)?;
+        value_to_node(&expansion).map(Some)
+    }
+
+    fn expand_head(&self, node: &Node, locals: &HashSet<String>) -> Result<Node, EvalError> {
+        let mut current = node.clone();
+        for _ in 0..MAX_EXPANSION_STEPS {
+            match self.expand_1(&current, locals)? {
+                Some(next) => current = next,
+                None => return Ok(current),
+            }
+        }
+        Err(EvalError::InvalidOperation(format!("macro expansion of {:?} did not terminate", node)))
+    }
+
+    fn expand_subforms(&self, node: &Node, locals: &HashSet<String>) -> Result<Node, EvalError> {
+        match node {
+            Node::List { root } => match root.first() {
+                Some(Node::Symbol { value }) if value == "quote" => Ok(node.clone()),
+                Some(Node::Symbol { value }) if value == "syntax-quote" => self.expand_template(node, locals),
+                Some(Node::Symbol { value }) if value == "macroexpand" || value == "macroexpand-1" => self.expand_debug_call(value, &root[1..], locals),
+                Some(Node::Symbol { value }) if matches!(value.as_str(), "let" | "if-let" | "when-let") && matches!(root.get(1), Some(Node::Vector { .. })) => self.expand_let(root, locals),
+                Some(Node::Symbol { value }) if value == "fn" || value == "defn" => self.expand_fn(root, locals),
+                Some(Node::Symbol { value }) if value == "catch" && root.len() >= 3 => {
+                    let mut scope = locals.clone();
+                    bound_names(&root[2], &mut scope);
+                    let mut expanded = root[..3].to_vec();
+                    expanded.extend(self.expand_all(&root[3..], &scope)?);
+                    Ok(Node::List { root: expanded })
+                }
+                _ => Ok(Node::List { root: self.expand_all(root, locals)? }),
+            },
+            Node::Vector { root } => Ok(Node::Vector { root: self.expand_all(root, locals)? }),
+            Node::Set { root } => Ok(Node::Set { root: self.expand_all(root, locals)? }),
+            Node::Map { entries } => Ok(Node::Map {
+                entries: entries
+                    .iter()
+                    .map(|(key, value)| Ok((self.expand_in(key, locals)?, self.expand_in(value, locals)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            }),
+            Node::Primitive { .. } | Node::Symbol { .. } => Ok(node.clone()),
+        }
+    }
+
+    /// Each binding's value sees the names bound before it, and the body sees them all. The
+    /// else branch of `if-let` runs without the binding.
+    fn expand_let(&self, root: &[Node], locals: &HashSet<String>) -> Result<Node, EvalError> {
+        let Node::Vector { root: bindings } = &root[1] else {
+            unreachable!("expand_let requires a binding vector");
+        };
+
+        let mut scope = locals.clone();
+        let mut expanded_bindings = Vec::with_capacity(bindings.len());
+        for pair in bindings.chunks(2) {
+            expanded_bindings.push(pair[0].clone());
+            if let Some(value) = pair.get(1) {
+                expanded_bindings.push(self.expand_in(value, &scope)?);
+            }
+            bound_names(&pair[0], &mut scope);
+        }
+
+        let mut expanded = vec![root[0].clone(), Node::Vector { root: expanded_bindings }];
+        let is_if_let = matches!(&root[0], Node::Symbol { value } if value == "if-let");
+        for (idx, child) in root[2..].iter().enumerate() {
+            let child_locals = if is_if_let && idx > 0 { locals } else { &scope };
+            expanded.push(self.expand_in(child, child_locals)?);
+        }
+        Ok(Node::List { root: expanded })
+    }
+
+    /// Parameters shadow macros in the body of their arity
+    fn expand_fn(&self, root: &[Node], locals: &HashSet<String>) -> Result<Node, EvalError> {
+        let mut expanded = vec![root[0].clone()];
+        let mut rest = &root[1..];
+        while let Some(head @ (Node::Symbol { .. } | Node::Primitive { .. })) = rest.first() {
+            expanded.push(head.clone());
+            rest = &rest[1..];
+        }
+
+        let expand_arity = |arity: &[Node]| -> Result<Vec<Node>, EvalError> {
+            let mut scope = locals.clone();
+            bound_names(&arity[0], &mut scope);
+            let mut expanded = vec![arity[0].clone()];
+            expanded.extend(self.expand_all(&arity[1..], &scope)?);
+            Ok(expanded)
+        };
+        match rest.first() {
+            Some(Node::Vector { .. }) => expanded.extend(expand_arity(rest)?),
+            _ => {
+                for clause in rest {
+                    expanded.push(match clause {
+                        Node::List { root: arity } if matches!(arity.first(), Some(Node::Vector { .. })) => Node::List { root: expand_arity(arity)? },
+                        _ => self.expand_in(clause, locals)?,
+                    });
+                }
+            }
+        }
+        Ok(Node::List { root: expanded })
+    }
+
+    /// Inside a syntax-quote template only the unquoted expressions are code
+    fn expand_template(&self, node: &Node, locals: &HashSet<String>) -> Result<Node, EvalError> {
+        match node {
+            Node::List { root } => match root.as_slice() {
+                [Node::Symbol { value }, expr] if value == "unquote" || value == "unquote-splicing" => Ok(Node::List {
+                    root: vec![root[0].clone(), self.expand_in(expr, locals)?],
+                }),
+                _ => Ok(Node::List {
+                    root: root.iter().map(|child| self.expand_template(child, locals)).collect::<Result<_, _>>()?,
+                }),
+            },
+            Node::Vector { root } => Ok(Node::Vector {
+                root: root.iter().map(|child| self.expand_template(child, locals)).collect::<Result<_, _>>()?,
+            }),
+            Node::Set { root } => Ok(Node::Set {
+                root: root.iter().map(|child| self.expand_template(child, locals)).collect::<Result<_, _>>()?,
+            }),
+            Node::Map { entries } => Ok(Node::Map {
+                entries: entries
+                    .iter()
+                    .map(|(key, value)| Ok((self.expand_template(key, locals)?, self.expand_template(value, locals)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            }),
+            Node::Primitive { .. } | Node::Symbol { .. } => Ok(node.clone()),
+        }
+    }
+
+    /// `macroexpand-1` expands the quoted form's head once, `macroexpand` until it is no longer a macro call
+    fn expand_debug_call(&self, name: &str, args: &[Node], locals: &HashSet<String>) -> Result<Node, EvalError> {
+        let form = match args {
+            [Node::List { root }] if root.len() == 2 && matches!(&root[0], Node::Symbol { value } if value == "quote") => &root[1],
+            [_] => return Err(EvalError::TypeError(format!("{} requires a quoted form", name))),
+            _ => return Err(EvalError::ArityError(name.to_string(), 1, args.len())),
+        };
+
+        let expanded = if name == "macroexpand-1" {
+            self.expand_1(form, locals)?.unwrap_or_else(|| form.clone())
+        } else {
+            self.expand_head(form, locals)?
+        };
+        Ok(Node::List {
+            root: vec![Node::Symbol { value: "quote".to_string() }, expanded],
+        })
+    }
+}
+
+/// Evaluate quote: the form itself, as data
+pub fn eval_quote(args: &[Node]) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("quote".to_string(), 1, args.len()));
+    }
+    quote_node(&args[0])
+}
+
+/// Evaluate syntax-quote: the template as data, with unquoted expressions evaluated
+pub fn eval_syntax_quote(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("syntax-quote".to_string(), 1, args.len()));
+    }
+    syntax_quote(&args[0], env, &mut HashMap::new())
+}
+
+fn syntax_quote(node: &Node, env: &mut Environment, gensyms: &mut HashMap<String, String>) -> Result<Value, EvalError> {
+    match node {
+        Node::Symbol { value } if value.len() > 1 && value.ends_with('#') => {
+            let generated = gensyms
+                .entry(value.clone())
+                .or_insert_with(|| format!("{}__{}__auto", &value[..value.len() - 1], GENSYM_COUNTER.fetch_add(1, Ordering::Relaxed)));
+            Ok(Value::Symbol(generated.clone()))
+        }
+        Node::List { root } => match root.as_slice() {
+            [Node::Symbol { value }, expr] if value == "unquote" => crate::evaluator::eval_with_env(expr, env),
+            [Node::Symbol { value }, _] if value == "unquote-splicing" => Err(EvalError::InvalidOperation("unquote-splicing used outside of a list".to_string())),
+            _ => Ok(Value::List(syntax_quote_items(root, env, gensyms)?)),
+        },
+        Node::Vector { root } => Ok(Value::Vector(syntax_quote_items(root, env, gensyms)?.into())),
+        Node::Set { root } => {
+            let members = syntax_quote_items(root, env, gensyms)?;
+            Ok(Value::Set(members.iter().map(MapKey::try_from_value).collect::<Result<_, _>>()?))
+        }
+        Node::Map { entries } => {
+            let mut map = PersistentMap::new();
+            for (key, value) in entries {
+                let key = MapKey::try_from_value(&syntax_quote(key, env, gensyms)?)?;
+                map.insert(key, syntax_quote(value, env, gensyms)?);
+            }
+            Ok(Value::Map(map))
+        }
+        Node::Primitive { .. } | Node::Symbol { .. } => quote_node(node),
+    }
+}
+
+/// Template items, splicing the elements of each `~@` collection in place
+fn syntax_quote_items(nodes: &[Node], env: &mut Environment, gensyms: &mut HashMap<String, String>) -> Result<Vec<Value>, EvalError> {
+    let mut items = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match node {
+            Node::List { root } if matches!(root.as_slice(), [Node::Symbol { value }, _] if value == "unquote-splicing") => match crate::evaluator::eval_with_env(&root[1], env)? {
+                Value::List(spliced) => items.extend(spliced),
+                Value::Vector(spliced) => items.extend(spliced),
+                Value::LazySeq(seq) => items.extend(seq.items()?),
+                Value::Nil => {}
+                _ => return Err(EvalError::TypeError("unquote-splicing requires a list or vector".to_string())),
+            },
+            _ => items.push(syntax_quote(node, env, gensyms)?),
+        }
+    }
+    Ok(items)
+}
+
+/// Turn a form into the data it denotes
+pub fn quote_node(node: &Node) -> Result<Value, EvalError> {
+    match node {
+        Node::Primitive { value } => Ok(match value {
+            Primitive::Number(n) => Value::Number(*n as isize),
+            Primitive::Boolean(b) => Value::Boolean(*b),
+            Primitive::String(s) => Value::String(s.clone()),
+            Primitive::Keyword(k) => Value::Keyword(k.clone()),
+        }),
+        Node::Symbol { value } if value == "nil" => Ok(Value::Nil),
+        Node::Symbol { value } => Ok(Value::Symbol(value.clone())),
+        Node::List { root } => Ok(Value::List(root.iter().map(quote_node).collect::<Result<_, _>>()?)),
+        Node::Vector { root } => Ok(Value::Vector(root.iter().map(quote_node).collect::<Result<_, _>>()?)),
+        Node::Set { root } => Ok(Value::Set(root.iter().map(|member| MapKey::try_from_value(&quote_node(member)?)).collect::<Result<_, _>>()?)),
+        Node::Map { entries } => Ok(Value::Map(
+            entries
+                .iter()
+                .map(|(key, value)| Ok((MapKey::try_from_value(&quote_node(key)?)?, quote_node(value)?)))
+                .collect::<Result<_, EvalError>>()?,
+        )),
+    }
+}
+
+/// Read data returned by a macro back as code
+pub fn value_to_node(value: &Value) -> Result<Node, EvalError> {
+    match value {
+        Value::Number(n) if *n >= 0 => Ok(Node::new_number(*n as usize)),
+        // The reader has no negative literals, so spell them as a subtraction
+        Value::Number(n) => Ok(Node::new_list_from_raw(vec![
+            Node::Symbol { value: "-".to_string() },
+            Node::new_number(0),
+            Node::new_number(n.unsigned_abs()),
+        ])),
+        Value::Boolean(b) => Ok(Node::new_boolean(*b)),
+        Value::String(s) => Ok(Node::Primitive { value: Primitive::String(s.clone()) }),
+        Value::Keyword(k) => Ok(Node::new_keyword_from_raw(k.clone())),
+        Value::Nil => Ok(Node::Symbol { value: "nil".to_string() }),
+        Value::Symbol(s) => Ok(Node::Symbol { value: s.clone() }),
+        Value::List(items) => Ok(Node::new_list_from_raw(items.iter().map(value_to_node).collect::<Result<_, _>>()?)),
+        Value::LazySeq(seq) => Ok(Node::new_list_from_raw(seq.items()?.iter().map(value_to_node).collect::<Result<_, _>>()?)),
+        Value::Vector(items) => Ok(Node::new_vector_from_raw(items.iter().map(value_to_node).collect::<Result<_, _>>()?)),
+        Value::Set(members) => {
+            // Sorted so the same expansion always produces the same code
+            let mut members: Vec<Node> = members.iter().map(map_key_to_node).collect();
+            members.sort_by_key(|member| format!("{:?}", member));
+            Ok(Node::new_set_from_raw(members))
+        }
+        Value::Map(entries) => {
+            let mut pairs = entries
+                .iter()
+                .map(|(key, value)| Ok((map_key_to_node(key), value_to_node(value)?)))
+                .collect::<Result<Vec<_>, EvalError>>()?;
+            pairs.sort_by_key(|(key, _)| format!("{:?}", key));
+            Ok(Node::new_map_from_raw(pairs))
+        }
+        // Read back as the hash literal holding the same entries
+        Value::SortedMap(_) | Value::SortedSet(_) => value_to_node(&sorted::unsorted(value.clone())?),
+        Value::Record(_) => value_to_node(&records::widen(value.clone())),
+        Value::Function { .. } => Err(EvalError::TypeError("macro expansion cannot contain a function value".to_string())),
+        Value::Atom(_) => Err(EvalError::TypeError("macro expansion cannot contain an atom".to_string())),
+    }
+}
+
+fn map_key_to_node(key: &MapKey) -> Node {
+    value_to_node(&key.clone().into_value()).expect("map keys are always valid code")
+}
+
+/// Add the names a binding pattern introduces to `names`
+fn bound_names(pattern: &Node, names: &mut HashSet<String>) {
+    match pattern {
+        Node::Symbol { value } if value != "&" => {
+            names.insert(value.clone());
+        }
+        Node::Vector { root } => root.iter().for_each(|element| bound_names(element, names)),
+        Node::Map { entries } => {
+            for (key, value) in entries {
+                match key {
+                    Node::Primitive { value: Primitive::Keyword(keyword) } if keyword == "or" => {}
+                    Node::Primitive { value: Primitive::Keyword(_) } => bound_names(value, names),
+                    _ => bound_names(key, names),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Arguments of a `(head ...)` form
+fn form_args<'a>(node: &'a Node, head: &str) -> Option<&'a [Node]> {
+    match node {
+        Node::List { root } => match root.first() {
+            Some(Node::Symbol { value }) if value == head => Some(&root[1..]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
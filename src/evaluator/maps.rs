@@ -0,0 +1,157 @@
+use super::{primitives, sequences, sorted, special_forms, vectors, Environment, EvalError, MapKey, PersistentMap, Value};
+/// Map operations - keys, vals, merge, merge-with, select-keys, zipmap, find and map-invert
+///
+/// Nil stands in for an empty map. keys and vals list the entries in the order maps print, which
+/// for a sorted map is key order. merge and merge-with keep the first map's kind, so merging into a
+/// sorted map stays sorted; the other functions build hash maps.
+use crate::ast::Node;
+
+fn eval_map_arg(node: &Node, env: &mut Environment, op_name: &str) -> Result<PersistentMap<MapKey, Value>, EvalError> {
+    match crate::evaluator::eval_with_env(node, env)? {
+        Value::Map(entries) => Ok(entries),
+        Value::SortedMap(tree) => sorted::hash_entries(&tree),
+        Value::Record(record) => Ok(record.to_map()),
+        Value::Nil => Ok(PersistentMap::new()),
+        _ => Err(EvalError::TypeError(format!("{}: argument must be a map or nil", op_name))),
+    }
+}
+
+/// keys / vals - The keys or values of a map as a list
+pub fn eval_map_column(args: &[Node], env: &mut Environment, values: bool) -> Result<Value, EvalError> {
+    let op_name = if values { "vals" } else { "keys" };
+    if args.len() != 1 {
+        return Err(EvalError::ArityError(op_name.to_string(), 1, args.len()));
+    }
+
+    let map = crate::evaluator::eval_with_env(&args[0], env)?;
+    if let Value::SortedMap(tree) = map {
+        let column = tree.iter().map(|(key, value)| if values { value.clone() } else { key.clone() });
+        return Ok(Value::List(column.collect()));
+    }
+    if let Value::Record(record) = map {
+        let column = record.iter().map(|(field, value)| if values { value.clone() } else { Value::Keyword(field.to_string()) });
+        return Ok(Value::List(column.collect()));
+    }
+
+    let mut pairs: Vec<(MapKey, Value)> = match map {
+        Value::Map(entries) => entries.into_iter().collect(),
+        Value::Nil => Vec::new(),
+        _ => return Err(EvalError::TypeError(format!("{}: argument must be a map or nil", op_name))),
+    };
+    pairs.sort_by_key(|(key, _)| primitives::map_key_to_string(key));
+    let column = pairs.into_iter().map(|(key, value)| if values { value } else { key.into_value() });
+    Ok(Value::List(column.collect()))
+}
+
+/// merge / merge-with - Later maps' entries replace earlier ones, or are combined with the
+/// function when one is given; merging only nils gives nil
+fn merge_maps(maps: &[Node], function: Option<Value>, env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    let mut merged: Option<Value> = None;
+    for node in maps {
+        let entries: Vec<(Value, Value)> = match crate::evaluator::eval_with_env(node, env)? {
+            Value::Nil => continue,
+            map if merged.is_none() && matches!(map, Value::Map(_) | Value::SortedMap(_) | Value::Record(_)) => {
+                merged = Some(map);
+                continue;
+            }
+            Value::Map(entries) => entries.into_iter().map(|(key, value)| (key.into_value(), value)).collect(),
+            Value::SortedMap(tree) => tree.iter().cloned().collect(),
+            Value::Record(record) => record.iter().map(|(field, value)| (Value::Keyword(field.to_string()), value.clone())).collect(),
+            _ => return Err(EvalError::TypeError(format!("{}: arguments must be maps or nil", op_name))),
+        };
+        let mut target = merged.take().unwrap_or(Value::Nil);
+        for (key, value) in entries {
+            let value = match (lookup(&target, &key)?, &function) {
+                (Some(existing), Some(function)) => special_forms::apply_function(function.clone(), vec![existing, value])?,
+                _ => value,
+            };
+            target = vectors::assoc_entry(target, key, value, op_name)?;
+        }
+        merged = Some(target);
+    }
+    Ok(merged.unwrap_or(Value::Nil))
+}
+
+/// The value stored under a key of a hash or sorted map or a record
+fn lookup(map: &Value, key: &Value) -> Result<Option<Value>, EvalError> {
+    match map {
+        Value::Map(entries) => Ok(entries.get(&MapKey::try_from_value(key)?).cloned()),
+        Value::SortedMap(tree) => Ok(tree.get(key)?.map(|(_, value)| value.clone())),
+        Value::Record(record) => Ok(record.get(&MapKey::try_from_value(key)?).cloned()),
+        _ => Ok(None),
+    }
+}
+
+pub fn eval_merge(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    merge_maps(args, None, env, "merge")
+}
+
+pub fn eval_merge_with(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.is_empty() {
+        return Err(EvalError::ArityError("merge-with".to_string(), 1, 0));
+    }
+    let function = sequences::eval_function(&args[0], env, "merge-with")?;
+    merge_maps(&args[1..], Some(function), env, "merge-with")
+}
+
+/// select-keys - The entries of a map whose keys appear in a collection
+pub fn eval_select_keys(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("select-keys".to_string(), 2, args.len()));
+    }
+
+    let mut entries = eval_map_arg(&args[0], env, "select-keys")?;
+    let mut selected = PersistentMap::new();
+    for key in sequences::eval_items(&args[1], env, "select-keys")? {
+        let key = MapKey::try_from_value(&key)?;
+        if let Some(value) = entries.remove(&key) {
+            selected.insert(key, value);
+        }
+    }
+    Ok(Value::Map(selected))
+}
+
+/// zipmap - A map pairing each key with the value at the same position, up to the shorter one
+pub fn eval_zipmap(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("zipmap".to_string(), 2, args.len()));
+    }
+
+    let keys = sequences::eval_items(&args[0], env, "zipmap")?;
+    let values = sequences::eval_items(&args[1], env, "zipmap")?;
+    let mut zipped = PersistentMap::new();
+    for (key, value) in keys.iter().zip(values) {
+        zipped.insert(MapKey::try_from_value(key)?, value);
+    }
+    Ok(Value::Map(zipped))
+}
+
+/// find - The `[key value]` entry of a key, or nil
+pub fn eval_find(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("find".to_string(), 2, args.len()));
+    }
+
+    let map = crate::evaluator::eval_with_env(&args[0], env)?;
+    let key = crate::evaluator::eval_with_env(&args[1], env)?;
+    if !matches!(map, Value::Map(_) | Value::SortedMap(_) | Value::Record(_) | Value::Nil) {
+        return Err(EvalError::TypeError("find: argument must be a map or nil".to_string()));
+    }
+    match lookup(&map, &key)? {
+        Some(value) => Ok(Value::Vector(vec![key, value].into())),
+        None => Ok(Value::Nil),
+    }
+}
+
+/// map-invert - A map from each value to its key
+pub fn eval_map_invert(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("map-invert".to_string(), 1, args.len()));
+    }
+
+    let mut inverted = PersistentMap::new();
+    for (key, value) in eval_map_arg(&args[0], env, "map-invert")? {
+        inverted.insert(MapKey::try_from_value(&value)?, key.into_value());
+    }
+    Ok(Value::Map(inverted))
+}
@@ -0,0 +1,76 @@
+use super::{primitives, Environment, EvalError, MapKey, Value};
+/// Pattern matching - match, tried clause by clause against the target value
+///
+/// Vector patterns match vectors, map patterns match hash maps, sorted maps and records. A clause
+/// whose pattern matches binds its symbols for its guard and body; when its guard is falsy the
+/// next clause is tried, and a value no clause matches throws "No matching clause" like `case`.
+use crate::ast::{forms, patterns, patterns::Pattern, Node};
+
+/// Evaluate a match expression
+pub fn eval_match(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let form = patterns::match_clauses(args).map_err(EvalError::TypeError)?;
+    let target = crate::evaluator::eval_with_env(form.target, env)?;
+
+    for clause in &form.clauses {
+        let mut bindings = Vec::new();
+        if !bind(&clause.pattern, &target, &mut bindings)? {
+            continue;
+        }
+        let mut scope = env.clone();
+        scope.extend(bindings);
+        if let Some(guard) = clause.guard {
+            if !primitives::is_truthy(&crate::evaluator::eval_with_env(guard, &mut scope)?) {
+                continue;
+            }
+        }
+        return crate::evaluator::eval_with_env(clause.body, &mut scope);
+    }
+    crate::evaluator::eval_with_env(&forms::no_matching_clause(), env)
+}
+
+/// Whether `value` matches `pattern`, collecting the bindings it makes
+fn bind(pattern: &Pattern, value: &Value, bindings: &mut Vec<(String, Value)>) -> Result<bool, EvalError> {
+    match pattern {
+        Pattern::Wildcard => Ok(true),
+        Pattern::Bind(name) => {
+            bindings.push((name.clone(), value.clone()));
+            Ok(true)
+        }
+        // Literals evaluate without the surrounding bindings
+        Pattern::Literal(literal) => Ok(crate::evaluator::eval_with_env(literal, &mut Environment::new())? == *value),
+        Pattern::Vector { items: patterns, rest } => {
+            let Value::Vector(items) = value else {
+                return Ok(false);
+            };
+            let fits = if rest.is_some() { items.len() >= patterns.len() } else { items.len() == patterns.len() };
+            if !fits {
+                return Ok(false);
+            }
+            for (pattern, item) in patterns.iter().zip(items.iter()) {
+                if !bind(pattern, item, bindings)? {
+                    return Ok(false);
+                }
+            }
+            match rest {
+                Some(rest) => bind(rest, &Value::Vector(items.iter().skip(patterns.len()).cloned().collect()), bindings),
+                None => Ok(true),
+            }
+        }
+        Pattern::Map(entries) => {
+            for (key, pattern) in entries {
+                let key = crate::evaluator::eval_with_env(key, &mut Environment::new())?;
+                let found = match value {
+                    Value::Map(map) => map.get(&MapKey::try_from_value(&key)?).cloned(),
+                    Value::Record(record) => record.get(&MapKey::try_from_value(&key)?).cloned(),
+                    Value::SortedMap(tree) => tree.get(&key)?.map(|(_, value)| value.clone()),
+                    _ => return Ok(false),
+                };
+                match found {
+                    Some(found) if bind(pattern, &found, bindings)? => {}
+                    _ => return Ok(false),
+                }
+            }
+            Ok(matches!(value, Value::Map(_) | Value::Record(_) | Value::SortedMap(_)))
+        }
+    }
+}
@@ -2,19 +2,64 @@
 ///
 /// This module is organized into:
 /// - primitives: Arithmetic, comparison, and logical operations
-/// - special_forms: Special forms (if, let, fn, def, defn)
+/// - special_forms: Special forms (if, case, let, fn, def, defn) and derived conditional/threading forms
+/// - exceptions: throw, try/catch/finally and ex-info
+/// - macros: defmacro, quote/syntax-quote and the macroexpansion phase run before evaluation
+/// - sequences: map/filter/reduce and the rest of the higher-order sequence library, plus the seq
+///   functions (seq, next, nth, last, empty?) shared by every collection
+/// - lazy: lazy sequences (lazy-seq, iterate, repeat, cycle, the unbounded range) realized on demand
+/// - vectors: conj, peek, pop and subvec, plus assoc by vector index
+/// - maps: keys, vals, merge, merge-with, select-keys, zipmap, find and map-invert
+/// - sets: union, intersection, difference, subset?, superset? and select
+/// - sorted: sorted maps and sets kept in key order, with subseq and rsubseq range queries
+/// - records: defrecord types, maps with a fixed set of keyword fields
+/// - polymorphism: protocols dispatching on the type of their first argument, and multimethods
+///   dispatching on the value a function computes from their arguments
+/// - atoms: atom, deref, reset!, compare-and-set! and add-watch, the mutable cells programs keep
+///   state in
+/// - matching: match, trying each clause's pattern and guard against the target in turn
+/// - persistent_map / persistent_vector: the structurally shared hash maps, sets and vectors that
+///   back map, set and vector values, so updates copy only the path they change
+mod atoms;
+mod exceptions;
+mod lazy;
+mod macros;
+mod maps;
+mod matching;
+mod persistent_map;
+mod persistent_vector;
+mod polymorphism;
 mod primitives;
+mod records;
+mod sequences;
+mod sets;
+mod sorted;
 mod special_forms;
+mod values;
+mod vectors;
+
+pub use atoms::Atom;
+pub use lazy::LazySeq;
+pub use macros::MacroExpander;
+pub use persistent_map::{PersistentMap, PersistentSet};
+pub use persistent_vector::PersistentVector;
+pub use records::Record;
+pub use sorted::SortedTree;
 
 use crate::ast::{Node, Primitive};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A value usable as a map key or set member; collections nest and compare structurally
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum MapKey {
     Number(isize),
     Boolean(bool),
     String(String),
     Keyword(String),
+    Vector(Vec<MapKey>),
+    List(Vec<MapKey>),
+    Set(BTreeSet<MapKey>),
+    Map(BTreeMap<MapKey, MapKey>),
     Nil,
 }
 
@@ -24,23 +69,40 @@ pub enum Value {
     Boolean(bool),
     String(String),
     Keyword(String),
-    Vector(Vec<Value>),
-    Set(HashSet<MapKey>),
-    Map(HashMap<MapKey, Value>),
+    Vector(PersistentVector<Value>),
+    Set(PersistentSet<MapKey>),
+    Map(PersistentMap<MapKey, Value>),
+    SortedMap(SortedTree), // Kept in key order by compare or a comparator function
+    SortedSet(SortedTree), // Members are the tree's keys, stored against nil
+    Record(Record),        // Declared by defrecord; a map with a fixed set of keyword fields
+    List(Vec<Value>),      // Quoted lists, the code macros receive and return
+    Symbol(String),
+    LazySeq(LazySeq), // Realized on demand; clones share the realized items
+    Atom(Atom),       // Mutable cell; clones are the same atom
     Nil,
     Function {
-        params: Vec<String>,
-        body: Box<Node>,
-        closure: Environment, // Captured environment
+        name: Option<String>,        // Bound inside the body so `defn` functions can call themselves
+        arities: Vec<FunctionArity>, // Dispatched on argument count
+        closure: Environment,        // Captured environment
     },
 }
 
+/// One parameter list and body of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionArity {
+    pub params: Vec<String>,
+    pub rest_param: Option<String>, // `& rest` binding that collects extra arguments
+    pub body: Box<Node>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum EvalError {
     UndefinedSymbol(String),
     InvalidOperation(String),
     ArityError(String, usize, usize), // operation, expected, actual
     TypeError(String),
+    ArithmeticError(String), // division by zero or overflow in checked arithmetic
+    Thrown(Value),           // value raised by `throw`, unwinds to the nearest matching catch
 }
 
 pub type Environment = HashMap<String, Value>;
@@ -53,14 +115,56 @@ impl MapKey {
             Value::String(s) => Ok(MapKey::String(s.clone())),
             Value::Keyword(k) => Ok(MapKey::Keyword(k.clone())),
             Value::Nil => Ok(MapKey::Nil),
-            _ => Err(EvalError::TypeError("map keys must be numbers, booleans, strings, keywords, or nil".to_string())),
+            Value::Vector(items) => Ok(MapKey::Vector(items.iter().map(MapKey::try_from_value).collect::<Result<_, _>>()?)),
+            Value::List(items) => Ok(MapKey::List(items.iter().map(MapKey::try_from_value).collect::<Result<_, _>>()?)),
+            Value::LazySeq(seq) => Ok(MapKey::List(seq.items()?.iter().map(MapKey::try_from_value).collect::<Result<_, _>>()?)),
+            Value::Set(members) => Ok(MapKey::Set(members.iter().cloned().collect())),
+            Value::Map(entries) => Ok(MapKey::Map(
+                entries.iter().map(|(key, value)| Ok((key.clone(), MapKey::try_from_value(value)?))).collect::<Result<_, EvalError>>()?,
+            )),
+            Value::Record(record) => Ok(MapKey::Map(
+                record
+                    .iter()
+                    .map(|(field, value)| Ok((MapKey::Keyword(field.to_string()), MapKey::try_from_value(value)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            )),
+            Value::SortedSet(tree) => Ok(MapKey::Set(tree.iter().map(|(member, _)| MapKey::try_from_value(member)).collect::<Result<_, _>>()?)),
+            Value::SortedMap(tree) => Ok(MapKey::Map(
+                tree.iter()
+                    .map(|(key, value)| Ok((MapKey::try_from_value(key)?, MapKey::try_from_value(value)?)))
+                    .collect::<Result<_, EvalError>>()?,
+            )),
+            _ => Err(EvalError::TypeError("map keys must be numbers, booleans, strings, keywords, nil, or collections of them".to_string())),
+        }
+    }
+
+    /// The value this key was made from; a realized lazy sequence comes back as a list
+    pub fn into_value(self) -> Value {
+        match self {
+            MapKey::Number(n) => Value::Number(n),
+            MapKey::Boolean(b) => Value::Boolean(b),
+            MapKey::String(s) => Value::String(s),
+            MapKey::Keyword(k) => Value::Keyword(k),
+            MapKey::Vector(items) => Value::Vector(items.into_iter().map(MapKey::into_value).collect()),
+            MapKey::List(items) => Value::List(items.into_iter().map(MapKey::into_value).collect()),
+            MapKey::Set(members) => Value::Set(members.into_iter().collect()),
+            MapKey::Map(entries) => Value::Map(entries.into_iter().map(|(key, value)| (key, value.into_value())).collect()),
+            MapKey::Nil => Value::Nil,
         }
     }
 }
 
 /// Evaluate a node with a fresh environment
 pub fn eval_node(node: &Node) -> Result<Value, EvalError> {
-    eval_with_env(node, &mut Environment::new())
+    eval_program(std::slice::from_ref(node))
+}
+
+/// Macroexpand top-level forms, then evaluate them in order in one environment; the value
+/// of the last form is returned
+fn eval_program(nodes: &[Node]) -> Result<Value, EvalError> {
+    let expanded = MacroExpander::new().expand_program(nodes)?;
+    let mut env = Environment::new();
+    expanded.iter().try_fold(Value::Nil, |_, node| eval_with_env(node, &mut env))
 }
 
 /// Evaluate a node with the given environment
@@ -85,7 +189,11 @@ fn eval_primitive(primitive: &Primitive) -> Result<Value, EvalError> {
 }
 
 fn eval_symbol(symbol: &str, env: &Environment) -> Result<Value, EvalError> {
-    env.get(symbol).cloned().ok_or_else(|| EvalError::UndefinedSymbol(symbol.to_string()))
+    match env.get(symbol) {
+        Some(value) => Ok(value.clone()),
+        None if symbol == "nil" => Ok(Value::Nil),
+        None => Err(EvalError::UndefinedSymbol(symbol.to_string())),
+    }
 }
 
 fn eval_list(nodes: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
@@ -98,30 +206,32 @@ fn eval_list(nodes: &[Node], env: &mut Environment) -> Result<Value, EvalError>
 
     match operator {
         Node::Symbol { value } => match value.as_str() {
-            "+" => primitives::eval_arithmetic_op(args, env, |a, b| a + b, "+"),
-            "-" => primitives::eval_arithmetic_op(args, env, |a, b| a - b, "-"),
-            "*" => primitives::eval_arithmetic_op(args, env, |a, b| a * b, "*"),
-            "/" => primitives::eval_arithmetic_op(
-                args,
-                env,
-                |a, b| {
-                    if b == 0 {
-                        panic!("Division by zero")
-                    } else {
-                        a / b
-                    }
-                },
-                "/",
-            ),
+            "+" => primitives::eval_arithmetic_op(args, env, primitives::checked_add, "+"),
+            "-" => primitives::eval_arithmetic_op(args, env, primitives::checked_sub, "-"),
+            "*" => primitives::eval_arithmetic_op(args, env, primitives::checked_mul, "*"),
+            "/" => primitives::eval_arithmetic_op(args, env, primitives::checked_div, "/"),
+            "unchecked-add" => primitives::eval_arithmetic_op(args, env, |a, b| Ok(a.wrapping_add(b)), "unchecked-add"),
+            "unchecked-subtract" => primitives::eval_arithmetic_op(args, env, |a, b| Ok(a.wrapping_sub(b)), "unchecked-subtract"),
+            "unchecked-multiply" => primitives::eval_arithmetic_op(args, env, |a, b| Ok(a.wrapping_mul(b)), "unchecked-multiply"),
             "=" => primitives::eval_equal(args, env),
+            "compare" => values::eval_compare(args, env),
+            "hash" => values::eval_hash(args, env),
             "<" => primitives::eval_comparison_op(args, env, |a, b| a < b, "<"),
             ">" => primitives::eval_comparison_op(args, env, |a, b| a > b, ">"),
             "<=" => primitives::eval_comparison_op(args, env, |a, b| a <= b, "<="),
             ">=" => primitives::eval_comparison_op(args, env, |a, b| a >= b, ">="),
             "if" => special_forms::eval_if(args, env),
+            "case" => special_forms::eval_case(args, env),
+            "match" => matching::eval_match(args, env),
+            "cond" | "when" | "when-not" | "if-not" | "if-let" | "when-let" | "not=" | "min-key" | "max-key" | "->" | "->>" | "some->" | "as->" | "update" | "get-in" | "assoc-in" | "update-in"
+            | "swap!" => special_forms::eval_derived(value, args, env),
             "and" => primitives::eval_logical_and(args, env),
             "or" => primitives::eval_logical_or(args, env),
             "not" => primitives::eval_logical_not(args, env),
+            "quote" => macros::eval_quote(args),
+            "syntax-quote" => macros::eval_syntax_quote(args, env),
+            "unquote" | "unquote-splicing" => Err(EvalError::InvalidOperation(format!("{} used outside of syntax-quote", value))),
+            "defmacro" => Err(EvalError::InvalidOperation("defmacro is only allowed at the top level".to_string())),
             "let" => special_forms::eval_let(args, env),
             "fn" => special_forms::eval_fn(args, env),
             "def" => special_forms::eval_def(args, env),
@@ -131,12 +241,79 @@ fn eval_list(nodes: &[Node], env: &mut Environment) -> Result<Value, EvalError>
             "get" => primitives::eval_get(args, env),
             "subs" => primitives::eval_subs(args, env),
             "vec" => primitives::eval_vec(args, env),
+            "list" => primitives::eval_new_list(args, env),
+            "cons" => primitives::eval_cons(args, env),
+            "first" => primitives::eval_first(args, env),
+            "rest" => primitives::eval_rest(args, env),
+            "next" => sequences::eval_next(args, env),
+            "seq" => sequences::eval_seq(args, env),
+            "nth" => sequences::eval_nth(args, env),
+            "conj" => vectors::eval_conj(args, env),
+            "peek" => vectors::eval_peek(args, env),
+            "pop" => vectors::eval_pop(args, env),
+            "reverse" => sequences::eval_reverse(args, env),
+            "sort" => values::eval_sort(args, env),
+            "sort-by" => values::eval_sort_by(args, env),
+            "subvec" => vectors::eval_subvec(args, env),
+            "last" => sequences::eval_last(args, env),
+            "empty?" => sequences::eval_is_empty(args, env),
+            "list?" => primitives::eval_is_list(args, env),
+            "symbol" => primitives::eval_new_symbol(args, env),
             "set" => primitives::eval_set(args, env),
             "hash-map" => primitives::eval_hash_map(args, env),
             "assoc" => primitives::eval_assoc(args, env),
             "dissoc" => primitives::eval_dissoc(args, env),
             "disj" => primitives::eval_disj(args, env),
             "contains?" => primitives::eval_contains(args, env),
+            "map" => sequences::eval_map(args, env),
+            "filter" => sequences::eval_filter(args, env, true),
+            "remove" => sequences::eval_filter(args, env, false),
+            "reduce" => sequences::eval_reduce(args, env),
+            "every?" => sequences::eval_every(args, env),
+            "some" => sequences::eval_some(args, env),
+            "range" => sequences::eval_range(args, env),
+            "into" => sequences::eval_into(args, env),
+            "take" => sequences::eval_take(args, env, true),
+            "drop" => sequences::eval_take(args, env, false),
+            "concat" => sequences::eval_concat(args, env),
+            "frequencies" => sequences::eval_frequencies(args, env),
+            "group-by" => sequences::eval_group_by(args, env),
+            "keys" => maps::eval_map_column(args, env, false),
+            "vals" => maps::eval_map_column(args, env, true),
+            "merge" => maps::eval_merge(args, env),
+            "merge-with" => maps::eval_merge_with(args, env),
+            "select-keys" => maps::eval_select_keys(args, env),
+            "zipmap" => maps::eval_zipmap(args, env),
+            "find" => maps::eval_find(args, env),
+            "map-invert" => maps::eval_map_invert(args, env),
+            "union" | "intersection" | "difference" => sets::eval_set_operation(args, env, value),
+            "subset?" | "superset?" => sets::eval_set_predicate(args, env, value),
+            "select" => sets::eval_select(args, env),
+            "sorted-map" | "sorted-set" | "sorted-map-by" | "sorted-set-by" => sorted::eval_sorted_collection(args, env, value),
+            "subseq" | "rsubseq" => sorted::eval_subseq(args, env, value),
+            "defrecord" => records::eval_defrecord(args, env),
+            records::NEW_RECORD => records::eval_new_record(args, env),
+            "defprotocol" => polymorphism::eval_defprotocol(args, env),
+            "extend-type" => polymorphism::eval_extend_type(args, env),
+            polymorphism::PROTOCOL_CALL => polymorphism::eval_protocol_call(args, env),
+            "defmulti" => polymorphism::eval_defmulti(args, env),
+            "defmethod" => polymorphism::eval_defmethod(args, env),
+            polymorphism::MULTIMETHOD_CALL => polymorphism::eval_multimethod_call(args, env),
+            "lazy-seq" => lazy::eval_lazy_seq(args, env),
+            "iterate" => lazy::eval_iterate(args, env),
+            "repeat" => lazy::eval_repeat(args, env),
+            "cycle" => lazy::eval_cycle(args, env),
+            "atom" => atoms::eval_atom(args, env),
+            "deref" => atoms::eval_deref(args, env),
+            "reset!" => atoms::eval_reset(args, env),
+            "compare-and-set!" => atoms::eval_compare_and_set(args, env),
+            "add-watch" => atoms::eval_add_watch(args, env),
+            "throw" => exceptions::eval_throw(args, env),
+            "try" => exceptions::eval_try(args, env),
+            "ex-info" => exceptions::eval_ex_info(args, env),
+            "ex-message" => exceptions::eval_ex_message(args, env),
+            "ex-data" => exceptions::eval_ex_data(args, env),
+            "ex-cause" => exceptions::eval_ex_cause(args, env),
             op => {
                 if let Some(func_value) = env.get(op) {
                     special_forms::eval_function_call(func_value.clone(), args, env)
@@ -153,12 +330,12 @@ fn eval_list(nodes: &[Node], env: &mut Environment) -> Result<Value, EvalError>
 }
 
 fn eval_vector(nodes: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
-    let values: Result<Vec<Value>, EvalError> = nodes.iter().map(|node| eval_with_env(node, env)).collect();
+    let values: Result<PersistentVector<Value>, EvalError> = nodes.iter().map(|node| eval_with_env(node, env)).collect();
     Ok(Value::Vector(values?))
 }
 
 fn eval_map_literal(entries: &[(Node, Node)], env: &mut Environment) -> Result<Value, EvalError> {
-    let map: Result<HashMap<MapKey, Value>, EvalError> = entries
+    let map: Result<PersistentMap<MapKey, Value>, EvalError> = entries
         .iter()
         .map(|(key_node, value_node)| {
             let key_value = eval_with_env(key_node, env)?;
@@ -174,7 +351,6 @@ fn eval_map_literal(entries: &[(Node, Node)], env: &mut Environment) -> Result<V
 mod tests {
     use super::*;
     use crate::ast::{AstParser, AstParserTrt};
-    use std::collections::{HashMap, HashSet};
 
     fn parse_and_eval(input: &str) -> Result<Value, EvalError> {
         let ast = AstParser::parse_sexp_new_domain(input.as_bytes(), &mut 0);
@@ -189,6 +365,25 @@ mod tests {
         assert_eq!(parse_and_eval("(/ 8 2)"), Ok(Value::Number(4)));
     }
 
+    #[test]
+    fn test_arithmetic_errors() {
+        let divide_by_zero = Err(EvalError::ArithmeticError("Divide by zero".to_string()));
+        let overflow = Err(EvalError::ArithmeticError("integer overflow".to_string()));
+        assert_eq!(parse_and_eval("(/ 10 0)"), divide_by_zero);
+        assert_eq!(parse_and_eval("(+ 9223372036854775807 1)"), overflow);
+        assert_eq!(parse_and_eval("(* 4611686018427387904 2)"), overflow);
+        assert_eq!(parse_and_eval("(/ (- 0 9223372036854775807 1) (- 0 1))"), overflow);
+        assert_eq!(parse_and_eval("(try (/ 1 0) (catch Exception e (ex-message e)))"), Ok(Value::String("Divide by zero".to_string())));
+    }
+
+    #[test]
+    fn test_unchecked_arithmetic_wraps() {
+        assert_eq!(parse_and_eval("(unchecked-add 9223372036854775807 1)"), Ok(Value::Number(isize::MIN)));
+        assert_eq!(parse_and_eval("(unchecked-subtract (- 0 9223372036854775807) 2)"), Ok(Value::Number(isize::MAX)));
+        assert_eq!(parse_and_eval("(unchecked-multiply 4611686018427387904 2)"), Ok(Value::Number(isize::MIN)));
+        assert_eq!(parse_and_eval("(unchecked-add 1 2 3)"), Ok(Value::Number(6)));
+    }
+
     #[test]
     fn test_nested_arithmetic() {
         assert_eq!(parse_and_eval("(+ 2 (* 3 4))"), Ok(Value::Number(14)));
@@ -245,7 +440,7 @@ mod tests {
 
     #[test]
     fn test_map_literal_with_keyword_key() {
-        let mut expected = HashMap::new();
+        let mut expected = PersistentMap::new();
         expected.insert(MapKey::Keyword("name".to_string()), Value::String("Ada".to_string()));
         assert_eq!(parse_and_eval("{:name \"Ada\"}"), Ok(Value::Map(expected)));
     }
@@ -257,7 +452,7 @@ mod tests {
 
     #[test]
     fn test_map_literal() {
-        let mut expected = HashMap::new();
+        let mut expected = PersistentMap::new();
         expected.insert(MapKey::String("foo".to_string()), Value::Number(1));
         expected.insert(MapKey::String("bar".to_string()), Value::Boolean(true));
         assert_eq!(parse_and_eval("{\"foo\" 1 \"bar\" true}"), Ok(Value::Map(expected)));
@@ -265,7 +460,7 @@ mod tests {
 
     #[test]
     fn test_hash_map_literal_and_assoc() {
-        let mut expected = HashMap::new();
+        let mut expected = PersistentMap::new();
         expected.insert(MapKey::String("a".to_string()), Value::Number(1));
         expected.insert(MapKey::String("b".to_string()), Value::Number(2));
         assert_eq!(parse_and_eval("(assoc (hash-map \"a\" 1) \"b\" 2)"), Ok(Value::Map(expected)));
@@ -285,7 +480,7 @@ mod tests {
 
     #[test]
     fn test_set_construction_and_count() {
-        let mut expected = HashSet::new();
+        let mut expected = PersistentSet::new();
         expected.insert(MapKey::Number(1));
         expected.insert(MapKey::Number(2));
         expected.insert(MapKey::Number(3));
@@ -298,14 +493,14 @@ mod tests {
         assert_eq!(parse_and_eval("(contains? (set 1 2) 2)"), Ok(Value::Boolean(true)));
         assert_eq!(parse_and_eval("(contains? (set 1 2) 5)"), Ok(Value::Boolean(false)));
 
-        let mut expected = HashSet::new();
+        let mut expected = PersistentSet::new();
         expected.insert(MapKey::Number(1));
         assert_eq!(parse_and_eval("(disj (set 1 2 3) 2 3)"), Ok(Value::Set(expected)));
     }
 
     #[test]
     fn test_disj_nil_is_empty_set() {
-        assert_eq!(parse_and_eval("(disj (set) 1 2)"), Ok(Value::Set(HashSet::new())));
+        assert_eq!(parse_and_eval("(disj (set) 1 2)"), Ok(Value::Set(PersistentSet::new())));
     }
 
     #[test]
@@ -315,7 +510,7 @@ mod tests {
 
     #[test]
     fn test_set_literal_constructs_set() {
-        let mut expected = HashSet::new();
+        let mut expected = PersistentSet::new();
         expected.insert(MapKey::Number(1));
         expected.insert(MapKey::Number(2));
         assert_eq!(parse_and_eval("#{1 2 1}"), Ok(Value::Set(expected)));
@@ -323,14 +518,14 @@ mod tests {
 
     #[test]
     fn test_empty_set_literal() {
-        assert_eq!(parse_and_eval("#{}"), Ok(Value::Set(HashSet::new())));
+        assert_eq!(parse_and_eval("#{}"), Ok(Value::Set(PersistentSet::new())));
     }
 
     #[test]
     fn test_error_cases() {
         assert!(matches!(parse_and_eval("(+ 1)"), Err(EvalError::ArityError(_, 2, 1))));
         assert!(matches!(parse_and_eval("(unknown 1 2)"), Err(EvalError::UndefinedSymbol(_))));
-        assert!(matches!(parse_and_eval("(if 1 2)"), Err(EvalError::ArityError(_, 3, 2))));
+        assert!(matches!(parse_and_eval("(if 1)"), Err(EvalError::ArityError(_, 3, 1))));
         assert!(matches!(parse_and_eval("(not 1 2)"), Err(EvalError::ArityError(_, 1, 2))));
     }
 
@@ -379,6 +574,165 @@ mod tests {
         assert_eq!(parse_and_eval("(let [x 5 y 3] (if (> x y) (+ x y) (* x y)))"), Ok(Value::Number(8)));
     }
 
+    #[test]
+    fn test_let_destructuring() {
+        assert_eq!(parse_and_eval("(let [[a b & more] [1 2 3 4]] (+ a b (count more)))"), Ok(Value::Number(5)));
+        assert_eq!(
+            parse_and_eval("(let [[a & more :as all] [1]] (vec a more (count all)))"),
+            Ok(Value::Vector(vec![Value::Number(1), Value::Vector(vec![].into()), Value::Number(1)].into()))
+        );
+        assert_eq!(
+            parse_and_eval("(let [{:keys [name age] :or {age 0} :as user} {:name \"ann\"}] (vec name age (count user)))"),
+            Ok(Value::Vector(vec![Value::String("ann".to_string()), Value::Number(0), Value::Number(1)].into()))
+        );
+        assert_eq!(parse_and_eval("(let [{[x y] :point} {:point [3 4]}] (* x y))"), Ok(Value::Number(12)));
+        assert_eq!(parse_and_eval("((fn [[a b] {:strs [c]}] (+ a b c)) [1 2] {\"c\" 3})"), Ok(Value::Number(6)));
+        assert!(matches!(parse_and_eval("(let [{:keys x} {}] x)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_conditional_forms() {
+        assert_eq!(parse_and_eval("(if false 1)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(cond (< 5 3) :a (< 5 10) :b :else :c)"), Ok(Value::Keyword("b".to_string())));
+        assert_eq!(parse_and_eval("(cond false 1)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(when (> 2 1) 7)"), Ok(Value::Number(7)));
+        assert_eq!(parse_and_eval("(when-not (> 2 1) 7)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(if-not false 1 2)"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(if-let [[a b] [1 2]] (+ a b) 0)"), Ok(Value::Number(3)));
+        assert_eq!(parse_and_eval("(if-let [x (get {} :a)] x :none)"), Ok(Value::Keyword("none".to_string())));
+        assert_eq!(parse_and_eval("(when-let [x (get {:a 4} :a)] (* x x))"), Ok(Value::Number(16)));
+        assert!(matches!(parse_and_eval("(cond true)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_case() {
+        assert_eq!(parse_and_eval("(case 2 1 :one (2 3) :few :many)"), Ok(Value::Keyword("few".to_string())));
+        assert_eq!(parse_and_eval("(case :b :a 1 :b 2 0)"), Ok(Value::Number(2)));
+        assert_eq!(parse_and_eval("(case \"x\" \"y\" 1 0)"), Ok(Value::Number(0)));
+        assert_eq!(parse_and_eval("(case \":a\" :a 1 \"b\" 2 3)"), Ok(Value::Number(3)));
+        assert_eq!(parse_and_eval("(case nil 1 :one nil :none :other)"), Ok(Value::Keyword("none".to_string())));
+        assert!(matches!(parse_and_eval("(case 9 1 :one)"), Err(EvalError::Thrown(_))));
+        assert!(matches!(parse_and_eval("(case 1 1 :a 1 :b)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_threading_forms() {
+        assert_eq!(parse_and_eval("(-> 10 (- 4) (* 2))"), Ok(Value::Number(12)));
+        assert_eq!(parse_and_eval("(->> 10 (- 4) (* 2))"), Ok(Value::Number(-12)));
+        assert_eq!(parse_and_eval("(as-> 3 n (+ n 1) (* n n))"), Ok(Value::Number(16)));
+        assert_eq!(parse_and_eval("(some-> {:a \"xy\"} (get :a) count)"), Ok(Value::Number(2)));
+        assert_eq!(parse_and_eval("(some-> {} (get :a) count)"), Ok(Value::Nil));
+    }
+
+    fn parse_program_and_eval(input: &str) -> Result<Value, EvalError> {
+        eval_program(&crate::ast::parse_file(input).unwrap())
+    }
+
+    fn symbols(names: &[&str]) -> Vec<Value> {
+        names.iter().map(|name| Value::Symbol(name.to_string())).collect()
+    }
+
+    #[test]
+    fn test_quote_and_syntax_quote() {
+        assert_eq!(parse_and_eval("(quote x)"), Ok(Value::Symbol("x".to_string())));
+        assert_eq!(
+            parse_and_eval("(quote (a [b] nil 1))"),
+            Ok(Value::List(vec![Value::Symbol("a".to_string()), Value::Vector(symbols(&["b"]).into()), Value::Nil, Value::Number(1)]))
+        );
+        assert_eq!(
+            parse_and_eval("(let [x 1 xs [2 3]] `(f ~x ~@xs))"),
+            Ok(Value::List(vec![Value::Symbol("f".to_string()), Value::Number(1), Value::Number(2), Value::Number(3)]))
+        );
+        // Auto-gensyms are fresh, but shared within one template
+        let Ok(Value::List(items)) = parse_and_eval("`(v# v# w#)") else { panic!("expected a list") };
+        assert_eq!(items[0], items[1]);
+        assert_ne!(items[0], items[2]);
+        assert!(matches!(parse_and_eval("`~@[1]"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(unquote x)"), Err(EvalError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_defmacro() {
+        let unless = "(defmacro unless [c t e] `(if ~c ~e ~t))\n";
+        assert_eq!(parse_program_and_eval(&format!("{}(unless (= 1 2) 10 20)", unless)), Ok(Value::Number(10)));
+        // Arguments are not evaluated: the branch not taken never runs
+        assert_eq!(parse_program_and_eval(&format!("{}(unless true (/ 1 0) 3)", unless)), Ok(Value::Number(3)));
+
+        // Generated bindings do not capture the caller's names
+        let twice = "(defmacro add-twice [x y] `(let [v# ~x] (+ v# v# ~y)))\n(let [v 10] (add-twice 1 v))";
+        assert_eq!(parse_program_and_eval(twice), Ok(Value::Number(12)));
+
+        // Recursive, variadic macros that inspect their arguments at expansion time
+        let my_or = "(defmacro my-or [x & more] (if (= (count more) 0) x `(let [v# ~x] (if v# v# (my-or ~@more)))))\n(my-or false nil 7)";
+        assert_eq!(parse_program_and_eval(my_or), Ok(Value::Number(7)));
+
+        // Macro bodies can call functions defined earlier
+        let helper = "(defn negate-form [form] `(- 0 ~form))\n(defmacro neg [x] (negate-form x))\n(neg 5)";
+        assert_eq!(parse_program_and_eval(helper), Ok(Value::Number(-5)));
+
+        // Locals shadow macros of the same name in the scope that binds them
+        let sq = "(defmacro sq [x] `(* ~x ~x))\n";
+        assert_eq!(parse_program_and_eval(&format!("{}(let [sq (fn [a] a)] (sq 3))", sq)), Ok(Value::Number(3)));
+        assert_eq!(parse_program_and_eval(&format!("{}(let [f (fn [sq] (sq 3))] (f (fn [a] (+ a 1))))", sq)), Ok(Value::Number(4)));
+        assert_eq!(parse_program_and_eval(&format!("{}(let [x (sq 3) sq (fn [a] a)] (+ x (sq 3)))", sq)), Ok(Value::Number(12)));
+        assert_eq!(parse_program_and_eval(&format!("{}(if-let [sq nil] 0 (sq 3))", sq)), Ok(Value::Number(9)));
+
+        assert!(matches!(parse_and_eval("(let [x 1] (defmacro m [] 1))"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(
+            parse_program_and_eval("(defmacro loop-forever [] `(loop-forever))\n(loop-forever)"),
+            Err(EvalError::InvalidOperation(_))
+        ));
+    }
+
+    #[test]
+    fn test_macroexpand() {
+        let macros = "(defmacro unless [c t e] `(if ~c ~e ~t))\n(defmacro unless-not [c t e] `(unless (not ~c) ~t ~e))\n";
+        assert_eq!(
+            parse_program_and_eval(&format!("{}(macroexpand-1 (quote (unless-not x 1 2)))", macros)),
+            Ok(Value::List(vec![
+                Value::Symbol("unless".to_string()),
+                Value::List(symbols(&["not", "x"])),
+                Value::Number(1),
+                Value::Number(2)
+            ]))
+        );
+        assert_eq!(
+            parse_program_and_eval(&format!("{}(macroexpand (quote (unless-not x 1 2)))", macros)),
+            Ok(Value::List(vec![
+                Value::Symbol("if".to_string()),
+                Value::List(symbols(&["not", "x"])),
+                Value::Number(2),
+                Value::Number(1)
+            ]))
+        );
+        assert_eq!(
+            parse_program_and_eval("(macroexpand (quote (+ 1 2)))"),
+            Ok(Value::List(vec![Value::Symbol("+".to_string()), Value::Number(1), Value::Number(2)]))
+        );
+        assert!(matches!(parse_and_eval("(macroexpand 1)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_lists_and_symbols() {
+        let number_list = |items: &[isize]| Value::List(items.iter().map(|n| Value::Number(*n)).collect());
+        assert_eq!(parse_and_eval("'(a b)"), Ok(Value::List(symbols(&["a", "b"]))));
+        assert_eq!(parse_and_eval("(list 1 (+ 1 1) 3)"), Ok(number_list(&[1, 2, 3])));
+        assert_eq!(parse_and_eval("(cons 1 '(2 3))"), Ok(number_list(&[1, 2, 3])));
+        assert_eq!(parse_and_eval("(cons 1 [2])"), Ok(number_list(&[1, 2])));
+        assert_eq!(parse_and_eval("(cons 1 nil)"), Ok(number_list(&[1])));
+        assert_eq!(parse_and_eval("(first '(4 5))"), Ok(Value::Number(4)));
+        assert_eq!(parse_and_eval("(first (list))"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(rest [4 5])"), Ok(number_list(&[5])));
+        assert_eq!(parse_and_eval("(rest nil)"), Ok(number_list(&[])));
+        assert_eq!(parse_and_eval("(count '(1 2 3))"), Ok(Value::Number(3)));
+        assert_eq!(parse_and_eval("(list? '(1))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(list? [1])"), Ok(Value::Boolean(false)));
+        assert_eq!(parse_and_eval("(= (symbol \"a\") 'a)"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(str '(a \"b\" 1))"), Ok(Value::String("(a b 1)".to_string())));
+        assert!(matches!(parse_and_eval("(first 1)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(symbol 1)"), Err(EvalError::TypeError(_))));
+    }
+
     #[test]
     fn test_let_error_cases() {
         // Odd number of binding elements
@@ -491,6 +845,80 @@ mod tests {
         assert_eq!(result, Value::Number(7));
     }
 
+    #[test]
+    fn test_defn_variadic_rest() {
+        use super::*;
+        use std::collections::HashMap;
+
+        let mut env = HashMap::new();
+
+        let ast1 = AstParser::parse_sexp_new_domain(b"(defn log [level & parts] (vec level parts))", &mut 0);
+        eval_with_env(&ast1, &mut env).unwrap();
+
+        let ast2 = AstParser::parse_sexp_new_domain(b"(log :info 1 2)", &mut 0);
+        assert_eq!(
+            eval_with_env(&ast2, &mut env).unwrap(),
+            Value::Vector(vec![Value::Keyword("info".to_string()), Value::Vector(vec![Value::Number(1), Value::Number(2)].into())].into())
+        );
+
+        // Rest binding is an empty vector when no extra arguments are supplied
+        let ast3 = AstParser::parse_sexp_new_domain(b"(log :info)", &mut 0);
+        assert_eq!(
+            eval_with_env(&ast3, &mut env).unwrap(),
+            Value::Vector(vec![Value::Keyword("info".to_string()), Value::Vector(vec![].into())].into())
+        );
+
+        let ast4 = AstParser::parse_sexp_new_domain(b"(log)", &mut 0);
+        assert!(matches!(eval_with_env(&ast4, &mut env), Err(EvalError::ArityError(_, 1, 0))));
+
+        assert_eq!(parse_and_eval("((fn [& xs] (count xs)) 1 2 3)"), Ok(Value::Number(3)));
+        assert!(matches!(parse_and_eval("(fn [x &] x)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_defn_multi_arity() {
+        use super::*;
+        use std::collections::HashMap;
+
+        let mut env = HashMap::new();
+
+        let ast1 = AstParser::parse_sexp_new_domain(b"(defn f ([x] (f x 10)) ([x y] (+ x y)))", &mut 0);
+        eval_with_env(&ast1, &mut env).unwrap();
+
+        let ast2 = AstParser::parse_sexp_new_domain(b"(f 1)", &mut 0);
+        assert_eq!(eval_with_env(&ast2, &mut env).unwrap(), Value::Number(11));
+
+        let ast3 = AstParser::parse_sexp_new_domain(b"(f 1 2)", &mut 0);
+        assert_eq!(eval_with_env(&ast3, &mut env).unwrap(), Value::Number(3));
+
+        let ast4 = AstParser::parse_sexp_new_domain(b"(f 1 2 3)", &mut 0);
+        assert!(matches!(eval_with_env(&ast4, &mut env), Err(EvalError::ArityError(_, 2, 3))));
+
+        // Fixed arities win over the variadic one when the count matches exactly
+        let ast5 = AstParser::parse_sexp_new_domain(b"(defn g ([] 0) ([x & xs] (+ x (count xs))))", &mut 0);
+        eval_with_env(&ast5, &mut env).unwrap();
+        let ast6 = AstParser::parse_sexp_new_domain(b"(g)", &mut 0);
+        assert_eq!(eval_with_env(&ast6, &mut env).unwrap(), Value::Number(0));
+        let ast7 = AstParser::parse_sexp_new_domain(b"(g 5 1 1)", &mut 0);
+        assert_eq!(eval_with_env(&ast7, &mut env).unwrap(), Value::Number(7));
+
+        assert!(matches!(parse_and_eval("(defn bad ([x] x) ([y] y))"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(defn bad ([x & xs] x) ([a b c] a))"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(defn bad ([x]) ([y] y))"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_defn_recursion() {
+        use super::*;
+        use std::collections::HashMap;
+
+        let mut env = HashMap::new();
+        let ast1 = AstParser::parse_sexp_new_domain(b"(defn countdown [n] (if (= n 0) 0 (countdown (- n 1))))", &mut 0);
+        eval_with_env(&ast1, &mut env).unwrap();
+        let ast2 = AstParser::parse_sexp_new_domain(b"(countdown 5)", &mut 0);
+        assert_eq!(eval_with_env(&ast2, &mut env).unwrap(), Value::Number(0));
+    }
+
     #[test]
     fn test_defn_with_let() {
         use super::*;
@@ -655,12 +1083,15 @@ mod tests {
 
     #[test]
     fn test_vector_literal() {
-        assert_eq!(parse_and_eval("[1 2 3]"), Ok(Value::Vector(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+        assert_eq!(parse_and_eval("[1 2 3]"), Ok(Value::Vector(vec![Value::Number(1), Value::Number(2), Value::Number(3)].into())));
     }
 
     #[test]
     fn test_vec_function() {
-        assert_eq!(parse_and_eval("(vec 1 2 (+ 1 1))"), Ok(Value::Vector(vec![Value::Number(1), Value::Number(2), Value::Number(2)])));
+        assert_eq!(
+            parse_and_eval("(vec 1 2 (+ 1 1))"),
+            Ok(Value::Vector(vec![Value::Number(1), Value::Number(2), Value::Number(2)].into()))
+        );
     }
 
     #[test]
@@ -678,8 +1109,8 @@ mod tests {
 
     #[test]
     fn test_subs_vector() {
-        assert_eq!(parse_and_eval("(subs [1 2 3 4] 1 3)"), Ok(Value::Vector(vec![Value::Number(2), Value::Number(3)])));
-        assert_eq!(parse_and_eval("(subs [1 2 3 4] 2)"), Ok(Value::Vector(vec![Value::Number(3), Value::Number(4)])));
+        assert_eq!(parse_and_eval("(subs [1 2 3 4] 1 3)"), Ok(Value::Vector(vec![Value::Number(2), Value::Number(3)].into())));
+        assert_eq!(parse_and_eval("(subs [1 2 3 4] 2)"), Ok(Value::Vector(vec![Value::Number(3), Value::Number(4)].into())));
     }
 
     #[test]
@@ -687,4 +1118,527 @@ mod tests {
         assert_eq!(parse_and_eval("(str [1 2])"), Ok(Value::String("[1 2]".to_string())));
         assert_eq!(parse_and_eval("(str (vec))"), Ok(Value::String("[]".to_string())));
     }
+
+    #[test]
+    fn test_ex_info_accessors() {
+        assert_eq!(parse_and_eval("(ex-message (ex-info \"boom\" {:n 5}))"), Ok(Value::String("boom".to_string())));
+        assert_eq!(parse_and_eval("(get (ex-data (ex-info \"boom\" {:n 5})) :n)"), Ok(Value::Number(5)));
+        assert_eq!(parse_and_eval("(ex-data {:a 1})"), Ok(Value::Nil));
+        assert_eq!(
+            parse_and_eval("(ex-message (ex-cause (ex-info \"outer\" {} (ex-info \"inner\" {}))))"),
+            Ok(Value::String("inner".to_string()))
+        );
+        assert!(matches!(parse_and_eval("(ex-info 1 {})"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_throw_unwinds_to_catch() {
+        assert_eq!(parse_and_eval("(try (throw (ex-info \"boom\" {:n 5})) (catch Exception e (get (ex-data e) :n)))"), Ok(Value::Number(5)));
+        assert_eq!(parse_and_eval("(try (+ 1 2) (catch Exception e 0))"), Ok(Value::Number(3)));
+        assert_eq!(parse_and_eval("(try 1 2 3)"), Ok(Value::Number(3)));
+        assert!(matches!(parse_and_eval("(throw (ex-info \"boom\" {}))"), Err(EvalError::Thrown(Value::Map(_)))));
+    }
+
+    #[test]
+    fn test_catch_clause_selection() {
+        assert_eq!(parse_and_eval("(try (throw {:kind :plain}) (catch ExceptionInfo e 1) (catch :default e 2))"), Ok(Value::Number(2)));
+        assert_eq!(parse_and_eval("(try (throw (ex-info \"x\" {})) (catch ExceptionInfo e 1) (catch :default e 2))"), Ok(Value::Number(1)));
+        assert!(matches!(parse_and_eval("(try (throw {:kind :plain}) (catch ExceptionInfo e 1))"), Err(EvalError::Thrown(_))));
+        assert!(matches!(parse_and_eval("(try 1 (catch Banana e 2))"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_catch_interpreter_errors() {
+        assert_eq!(parse_and_eval("(try (+ 1 :a) (catch Exception e (ex-message e)))"), Ok(Value::String("+ requires numbers".to_string())));
+        assert_eq!(parse_and_eval("(try undefined-thing (catch Exception e 7))"), Ok(Value::Number(7)));
+    }
+
+    #[test]
+    fn test_finally_runs_on_every_path() {
+        assert_eq!(parse_and_eval("(let [x 1] (try x (finally 99)))"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(try (throw (ex-info \"a\" {})) (catch Exception e 2) (finally 3))"), Ok(Value::Number(2)));
+        assert!(matches!(parse_and_eval("(try (throw (ex-info \"a\" {})) (finally 3))"), Err(EvalError::Thrown(_))));
+        assert_eq!(
+            parse_and_eval("(try (try 1 (finally (throw (ex-info \"from finally\" {})))) (catch Exception e (ex-message e)))"),
+            Ok(Value::String("from finally".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_sequence_library() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(render("(map (fn [x] (* x x)) [1 2 3])"), text("(1 4 9)"));
+        assert_eq!(render("(filter (fn [x] (> x 1)) '(1 2 3))"), text("(2 3)"));
+        assert_eq!(render("(remove (fn [x] (> x 1)) #{1 2 3})"), text("(1)"));
+        assert_eq!(parse_and_eval("(reduce (fn [a b] (+ a b)) 10 (range 4))"), Ok(Value::Number(16)));
+        assert_eq!(parse_and_eval("(reduce (fn [a b] (+ a b)) [1 2 3])"), Ok(Value::Number(6)));
+        assert_eq!(parse_and_eval("(reduce (fn [] 0) [])"), Ok(Value::Number(0)));
+        assert_eq!(render("(range 5 0 (- 0 2))"), text("(5 3 1)"));
+        assert_eq!(render("(into [0] (take 2 (drop 1 [1 2 3 4])))"), text("[0 2 3]"));
+        assert_eq!(render("(into '(0) [1 2])"), text("(2 1 0)"));
+        assert_eq!(render("(into {} (map (fn [x] [x (* x 10)]) [1 2]))"), text("{1 10 2 20}"));
+        assert_eq!(render("(concat [1] '(2) nil \"ab\")"), text("(1 2 a b)"));
+        assert_eq!(render("(map (fn [entry] (first entry)) {:b 2 :a 1})"), text("(:a :b)"));
+        assert_eq!(parse_and_eval("(every? (fn [x] (> x 0)) [1 2])"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(some (fn [x] (if (> x 1) (* x 10) nil)) [1 2 3])"), Ok(Value::Number(20)));
+        assert_eq!(parse_and_eval("(some (fn [x] false) [1 2])"), Ok(Value::Nil));
+        assert_eq!(render("(frequencies \"abca\")"), text("{\"a\" 2 \"b\" 1 \"c\" 1}"));
+        assert_eq!(render("(group-by (fn [x] (> x 2)) [1 2 3 4])"), text("{false [1 2] true [3 4]}"));
+
+        assert!(matches!(parse_and_eval("(range 1 5 0)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(map 1 [1])"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(map (fn [x] x) 5)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(into {} [1 2])"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_lazy_sequences() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(render("(take 5 (filter (fn [x] (= 0 (- x (* 2 (/ x 2))))) (range)))"), text("(0 2 4 6 8)"));
+        assert_eq!(render("(take 4 (iterate (fn [x] (* x 2)) 1))"), text("(1 2 4 8)"));
+        assert_eq!(render("(take 3 (map (fn [x] (+ x 1)) (drop 10 (range))))"), text("(11 12 13)"));
+        assert_eq!(render("(take 5 (cycle [1 2]))"), text("(1 2 1 2 1)"));
+        assert_eq!(render("(repeat 3 \"a\")"), text("(a a a)"));
+        assert_eq!(render("(take 3 (remove (fn [x] (> x 1)) (cycle [1 2])))"), text("(1 1 1)"));
+        assert_eq!(parse_and_eval("(count (repeat 4 0))"), Ok(Value::Number(4)));
+        assert_eq!(parse_and_eval("(first (rest (range)))"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(= (take 2 (range)) '(0 1))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(reduce (fn [a b] (+ a b)) (take 4 (repeat 5)))"), Ok(Value::Number(20)));
+        assert_eq!(render("(into [] (take 2 (repeat :k)))"), text("[:k :k]"));
+        assert_eq!(render("(lazy-seq nil)"), text("()"));
+
+        // Items are realized once and shared by every reference to the sequence
+        assert_eq!(parse_and_eval("(let [xs (map (fn [x] (* x 3)) (range))] (= (take 3 xs) (take 3 xs)))"), Ok(Value::Boolean(true)));
+
+        assert!(matches!(parse_and_eval("(lazy-seq 1 2)"), Err(EvalError::ArityError(..))));
+        assert!(matches!(parse_and_eval("(first (lazy-seq 5))"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(iterate 1 2)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_seq_functions_over_every_collection() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(parse_and_eval("(first \"abc\")"), text("a"));
+        assert_eq!(render("(first {:a 1})"), text("[:a 1]"));
+        assert_eq!(parse_and_eval("(first #{3 1 2})"), Ok(Value::Number(1)));
+        assert_eq!(render("(rest \"abc\")"), text("(b c)"));
+        assert_eq!(render("(cons 0 #{1 2})"), text("(0 1 2)"));
+        assert_eq!(render("(seq [1 2])"), text("(1 2)"));
+        assert_eq!(parse_and_eval("(seq [])"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(seq \"\")"), Ok(Value::Nil));
+        assert_eq!(render("(next '(1 2 3))"), text("(2 3)"));
+        assert_eq!(parse_and_eval("(next [1])"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(next nil)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(empty? [])"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(empty? {:a 1})"), Ok(Value::Boolean(false)));
+        assert_eq!(parse_and_eval("(empty? nil)"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(empty? (range))"), Ok(Value::Boolean(false)));
+        assert_eq!(parse_and_eval("(nth [10 20 30] 1)"), Ok(Value::Number(20)));
+        assert_eq!(parse_and_eval("(nth \"abc\" 2)"), text("c"));
+        assert_eq!(parse_and_eval("(nth (range) 100)"), Ok(Value::Number(100)));
+        assert_eq!(parse_and_eval("(nth [1] 5 :none)"), Ok(Value::Keyword("none".to_string())));
+        assert_eq!(parse_and_eval("(last '(1 2 3))"), Ok(Value::Number(3)));
+        assert_eq!(render("(last {:a 1 :b 2})"), text("[:b 2]"));
+        assert_eq!(parse_and_eval("(last [])"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(first (next (iterate (fn [x] (* x 3)) 1)))"), Ok(Value::Number(3)));
+
+        assert!(matches!(parse_and_eval("(nth [1] 1)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(nth [1] :a)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(first 5)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(empty? 5)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(seq)"), Err(EvalError::ArityError(..))));
+    }
+
+    #[test]
+    fn test_vector_operations() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(render("(conj [1 2] 3 4)"), text("[1 2 3 4]"));
+        assert_eq!(render("(conj '(2 3) 1 0)"), text("(0 1 2 3)"));
+        assert_eq!(render("(conj nil 1)"), text("(1)"));
+        assert_eq!(parse_and_eval("(peek [1 2 3])"), Ok(Value::Number(3)));
+        assert_eq!(parse_and_eval("(peek '(1 2 3))"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(peek [])"), Ok(Value::Nil));
+        assert_eq!(render("(pop [1 2 3])"), text("[1 2]"));
+        assert_eq!(render("(pop '(1 2 3))"), text("(2 3)"));
+        assert_eq!(render("(assoc [1 2 3] 1 :b)"), text("[1 :b 3]"));
+        assert_eq!(render("(assoc [1 2] 2 3)"), text("[1 2 3]"));
+        assert_eq!(render("(update [1 2 3] 0 (fn [x] (+ x 10)))"), text("[11 2 3]"));
+        assert_eq!(render("(update [1 2 3] 2 + 5 5)"), text("[1 2 13]"));
+        assert_eq!(render("(update {:a 1} :a (fn [x] (* x 2)))"), text("{:a 2}"));
+        assert_eq!(render("(reverse [1 2 3])"), text("(3 2 1)"));
+        assert_eq!(parse_and_eval("(reverse nil)"), Ok(Value::List(vec![])));
+        assert_eq!(render("(subvec [1 2 3 4] 1 3)"), text("[2 3]"));
+        assert_eq!(render("(subvec [1 2 3 4] 2)"), text("[3 4]"));
+        assert_eq!(render("(concat [1] [2 3])"), text("(1 2 3)"));
+
+        assert!(matches!(parse_and_eval("(pop [])"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(assoc [1 2] 3 0)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(subvec [1 2] 1 3)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(subvec [1 2] 2 1)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(conj 5 1)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(update [1] 2 (fn [x] 0))"), Err(EvalError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_map_operations() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(render("(keys {:b 2 :a 1})"), text("(:a :b)"));
+        assert_eq!(render("(vals {:b 2 :a 1})"), text("(1 2)"));
+        assert_eq!(render("(merge {:a 1 :b 2} {:b 3} nil {:c 4})"), text("{:a 1 :b 3 :c 4}"));
+        assert_eq!(parse_and_eval("(merge nil nil)"), Ok(Value::Nil));
+        assert_eq!(render("(merge-with (fn [a b] (+ a b)) {:a 1 :b 2} {:b 10 :c 3})"), text("{:a 1 :b 12 :c 3}"));
+        assert_eq!(render("(select-keys {:a 1 :b 2 :c 3} [:a :c :d])"), text("{:a 1 :c 3}"));
+        assert_eq!(render("(zipmap [:a :b :c] [1 2])"), text("{:a 1 :b 2}"));
+        assert_eq!(render("(find {:a 1} :a)"), text("[:a 1]"));
+        assert_eq!(parse_and_eval("(find {:a 1} :b)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(get-in {:a {:b 5}} [:a :b])"), Ok(Value::Number(5)));
+        assert_eq!(parse_and_eval("(get-in {:a {:b 5}} [:a :c] 9)"), Ok(Value::Number(9)));
+        assert_eq!(parse_and_eval("(get nil :a)"), Ok(Value::Nil));
+        assert_eq!(render("(assoc-in {:a {:b 1}} [:a :c] 2)"), text("{:a {:b 1 :c 2}}"));
+        assert_eq!(render("(assoc-in {} [:a :b] 1)"), text("{:a {:b 1}}"));
+        assert_eq!(render("(update-in {:a {:b 1}} [:a :b] + 10)"), text("{:a {:b 11}}"));
+
+        assert!(matches!(parse_and_eval("(keys [1 2])"), Err(EvalError::TypeError(_))));
+        assert!(parse_and_eval("(get-in {:a 1} [])").is_err());
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(render("(conj #{1 2} 2 3)"), text("#{1 2 3}"));
+        assert_eq!(render("(union #{1 2} #{2 3} nil)"), text("#{1 2 3}"));
+        assert_eq!(render("(union)"), text("#{}"));
+        assert_eq!(render("(intersection #{1 2 3} #{2 3 4} #{3 4})"), text("#{3}"));
+        assert_eq!(render("(difference #{1 2 3} #{2} #{3})"), text("#{1}"));
+        assert_eq!(parse_and_eval("(subset? #{1 2} #{1 2 3})"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(subset? #{1 4} #{1 2 3})"), Ok(Value::Boolean(false)));
+        assert_eq!(parse_and_eval("(superset? #{1 2 3} #{})"), Ok(Value::Boolean(true)));
+        assert_eq!(render("(select (fn [x] (> x 1)) #{1 2 3})"), text("#{2 3}"));
+        assert_eq!(render("(map-invert {:a 1 :b 2})"), text("{1 :a 2 :b}"));
+
+        assert!(matches!(parse_and_eval("(union #{1} [2])"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(intersection)"), Err(EvalError::ArityError(..))));
+        assert!(matches!(parse_and_eval("(subset? #{1})"), Err(EvalError::ArityError(..))));
+    }
+
+    #[test]
+    fn test_collection_calls() {
+        assert_eq!(parse_and_eval("(:name {:name \"ada\"})"), Ok(Value::String("ada".to_string())));
+        assert_eq!(parse_and_eval("(:port {:host \"x\"} 80)"), Ok(Value::Number(80)));
+        assert_eq!(parse_and_eval("(:port nil)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(let [config {:port 8080}] (config :port))"), Ok(Value::Number(8080)));
+        assert_eq!(parse_and_eval("({1 :one} 2 :none)"), Ok(Value::Keyword("none".to_string())));
+        assert_eq!(parse_and_eval("(let [roles #{:admin :ops}] (roles :ops))"), Ok(Value::Keyword("ops".to_string())));
+        assert_eq!(parse_and_eval("(#{1 2} 3)"), Ok(Value::Nil));
+        assert_eq!(parse_and_eval("(#{1 2} 3 false)"), Ok(Value::Boolean(false)));
+        assert_eq!(parse_and_eval("(str (map :id [{:id 1} {:id 2}]))"), Ok(Value::String("(1 2)".to_string())));
+
+        assert!(matches!(parse_and_eval("(:name)"), Err(EvalError::ArityError(..))));
+        assert!(matches!(parse_and_eval("({:a 1} :a 2 3)"), Err(EvalError::ArityError(..))));
+    }
+
+    #[test]
+    fn test_composite_keys() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(parse_and_eval("(get {[0 1] :tile} [0 (+ 0 1)])"), Ok(Value::Keyword("tile".to_string())));
+        assert_eq!(parse_and_eval("(contains? #{[1 2] [3 4]} [3 4])"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(contains? #{#{1 2}} (set 2 1))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(get {{:a [1]} 1} {:a [1]})"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(count (conj #{[1 2]} [1 2] [2 1]))"), Ok(Value::Number(2)));
+        assert_eq!(render("(keys {[1 2] :a})"), text("([1 2])"));
+        assert_eq!(render("(frequencies [[1 2] [1 2] [3]])"), text("{[1 2] 2 [3] 1}"));
+        assert_eq!(render("#{[1 \"a\"]}"), text("#{[1 a]}"));
+
+        assert!(matches!(parse_and_eval("#{(fn [x] x)}"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_hash_and_compare() {
+        assert_eq!(parse_and_eval("(= (hash {:a [1] :b #{2}}) (hash {:b #{2} :a [1]}))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(= (hash (list 1 2)) (hash (map (fn [x] (+ x 1)) [0 1])))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(not= [1 2] [1 2])"), Ok(Value::Boolean(false)));
+        assert_eq!(parse_and_eval("(compare [1 2] [1 2 0])"), Ok(Value::Number(-1)));
+        assert_eq!(parse_and_eval("(compare :b :a)"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(compare nil false)"), Ok(Value::Number(-1)));
+        assert_eq!(parse_and_eval("(compare #{1 2} #{2 1})"), Ok(Value::Number(0)));
+        assert!(matches!(parse_and_eval("(hash (fn [x] x))"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(parse_and_eval("(sort [3 1 2])"), Ok(Value::List(vec![Value::Number(1), Value::Number(2), Value::Number(3)])));
+        assert_eq!(parse_and_eval("(sort (fn [a b] (> a b)) #{1 3 2})"), parse_and_eval("(list 3 2 1)"));
+        assert_eq!(parse_and_eval("(sort (fn [a b] (- b a)) (list 1 3 2))"), parse_and_eval("(list 3 2 1)"));
+        assert_eq!(parse_and_eval("(sort [[1 2] \"b\" 3 nil [1]])"), parse_and_eval("(list nil 3 \"b\" [1] [1 2])"));
+        // Equal keys keep their original order
+        assert_eq!(
+            parse_and_eval("(sort-by (fn [s] (count s)) [\"bb\" \"a\" \"cc\" \"d\"])"),
+            parse_and_eval("(list \"a\" \"d\" \"bb\" \"cc\")")
+        );
+        assert_eq!(
+            parse_and_eval("(sort-by (fn [s] (count s)) (fn [a b] (> a b)) [\"a\" \"bb\" \"c\"])"),
+            parse_and_eval("(list \"bb\" \"a\" \"c\")")
+        );
+        assert_eq!(parse_and_eval("(max-key (fn [s] (count s)) \"ab\" \"xyz\" \"uvw\")"), Ok(Value::String("uvw".to_string())));
+        assert_eq!(parse_and_eval("(min-key (fn [x] (* x x)) 3 (- 0 2) 2)"), Ok(Value::Number(2)));
+        assert!(matches!(parse_and_eval("(sort (fn [a b] nil) [1 2])"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(sort)"), Err(EvalError::ArityError(_, 2, 0))));
+    }
+
+    #[test]
+    fn test_persistent_collections() {
+        // Updates leave the versions they started from intact
+        assert_eq!(
+            parse_and_eval("(let [v (into [] (range 100)) w (assoc v 50 :x)] [(get v 50) (get w 50) (count (conj w 1)) (count v)])"),
+            parse_and_eval("[50 :x 101 100]")
+        );
+        assert_eq!(
+            parse_and_eval("(let [m (zipmap (range 40) (range 40)) n (dissoc m 3)] [(count m) (count n) (get m 3) (get n 3)])"),
+            parse_and_eval("[40 39 3 nil]")
+        );
+        assert_eq!(parse_and_eval("(count (reduce (fn [m i] (assoc m i i)) {} (range 5000)))"), Ok(Value::Number(5000)));
+        // Equality does not depend on the order entries were added in
+        assert_eq!(parse_and_eval("(= (into #{} (range 300)) (into #{} (reverse (range 300))))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(= (reduce (fn [v i] (conj v i)) [] (range 1000)) (into [] (range 1000)))"), Ok(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_sorted_collections() {
+        let render = |input: &str| parse_and_eval(&format!("(str {})", input));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(render("(sorted-map 3 :c 1 :a 2 :b)"), text("{1 :a 2 :b 3 :c}"));
+        assert_eq!(render("(dissoc (assoc (sorted-map 2 :b 1 :a) 0 :z) 1)"), text("{0 :z 2 :b}"));
+        assert_eq!(render("(sorted-set-by (fn [a b] (> a b)) 1 3 2)"), text("#{3 2 1}"));
+        assert_eq!(render("(conj (sorted-set \"b\" \"c\") \"a\")"), text("#{\"a\" \"b\" \"c\"}"));
+        assert_eq!(render("(union (sorted-set 5 1) #{3})"), text("#{1 3 5}"));
+        assert_eq!(render("(sorted-set :a 1 nil)"), text("#{nil 1 :a}"));
+        assert_eq!(render("(merge (sorted-map 2 :b) {1 :a})"), text("{1 :a 2 :b}"));
+        // Earlier versions are untouched by later updates
+        assert_eq!(render("(let [s (sorted-set 1 2) t (conj s 0)] [s t])"), text("[#{1 2} #{0 1 2}]"));
+        // A comparator that treats keys as equal keeps the first key
+        assert_eq!(render("(sorted-map-by (fn [a b] (- (count a) (count b))) \"ab\" 1 \"cd\" 2)"), text("{\"ab\" 2}"));
+
+        assert_eq!(parse_and_eval("(keys (sorted-map :b 2 :a 1))"), parse_and_eval("(list :a :b)"));
+        assert_eq!(parse_and_eval("(subseq (sorted-set 1 2 3 4 5) >= 2 < 4)"), parse_and_eval("(list 2 3)"));
+        assert_eq!(parse_and_eval("(rsubseq (sorted-map 1 :a 2 :b 3 :c) <= 2)"), parse_and_eval("(list [2 :b] [1 :a])"));
+        assert_eq!(parse_and_eval("(subseq nil > 1)"), parse_and_eval("(list)"));
+        assert_eq!(parse_and_eval("((sorted-map :a 1) :a)"), Ok(Value::Number(1)));
+        assert_eq!(parse_and_eval("(get (sorted-map 1 :a) 2 :none)"), Ok(Value::Keyword("none".to_string())));
+        assert_eq!(parse_and_eval("(= (sorted-set 3 1) #{1 3})"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(= (hash (sorted-map 1 2)) (hash {1 2}))"), Ok(Value::Boolean(true)));
+        assert_eq!(parse_and_eval("(count (into (sorted-set) (range 100)))"), Ok(Value::Number(100)));
+
+        assert!(matches!(parse_and_eval("(sorted-map 1)"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(parse_and_eval("(sorted-set-by (fn [a b] nil) 1 2)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(subseq [1 2] > 1)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(subseq (sorted-set 1) = 1)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(subseq (sorted-set 1) < 1 > 0)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_and_eval("(subseq (sorted-set 1))"), Err(EvalError::ArityError(_, 5, 1))));
+    }
+
+    #[test]
+    fn test_records() {
+        let program = |body: &str| parse_program_and_eval(&format!("(defrecord Point [x y])\n{}", body));
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(program("(str (->Point 1 2))"), text("#Point{:x 1 :y 2}"));
+        assert_eq!(program("(str (assoc (->Point 1 2) :y 5))"), text("#Point{:x 1 :y 5}"));
+        assert_eq!(program("(str (assoc (->Point 1 2) :z 3))"), text("{:x 1 :y 2 :z 3}"));
+        assert_eq!(program("(str (into (->Point 1 2) {:x 0}) (merge (->Point 1 2) {:z 3}))"), text("#Point{:x 0 :y 2}{:x 1 :y 2 :z 3}"));
+        assert_eq!(program("(str (let [make ->Point] (make 1 2)))"), text("#Point{:x 1 :y 2}"));
+        assert_eq!(program("(str (seq (->Point 1 2)))"), text("([:x 1] [:y 2])"));
+        assert_eq!(program("((->Point 1 2) :y)"), Ok(Value::Number(2)));
+        assert_eq!(program("(get (->Point 1 2) :z 9)"), Ok(Value::Number(9)));
+        // Records equal only records of the same type, though they hash like the map of their fields
+        assert_eq!(program("(= (->Point 1 2) (->Point 1 2))"), Ok(Value::Boolean(true)));
+        assert_eq!(program("(= (->Point 1 2) {:x 1 :y 2})"), Ok(Value::Boolean(false)));
+        assert_eq!(program("(defrecord Size [x y]) (= (->Point 1 2) (->Size 1 2))"), Ok(Value::Boolean(false)));
+        assert_eq!(program("(= (hash (->Point 1 2)) (hash {:x 1 :y 2}))"), Ok(Value::Boolean(true)));
+        assert_eq!(program("(= (dissoc (->Point 1 2) :x) {:y 2})"), Ok(Value::Boolean(true)));
+
+        assert!(matches!(program("(->Point 1)"), Err(EvalError::ArityError(_, 2, 1))));
+        assert!(matches!(program("(defrecord Bad [:x])"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_protocols() {
+        let program = |body: &str| {
+            parse_program_and_eval(&format!(
+                "(defprotocol Shape (area [this]) (scale [this k]) (describe [this]))
+                 (defrecord Square [side])
+                 (defrecord Circle [r])
+                 (extend-type Square Shape
+                   (area [s] (* (:side s) (:side s)))
+                   (scale [s k] (->Square (* k (:side s))))
+                   (describe [s] (str \"square of area \" (area s))))
+                 (extend-type Circle Shape (area [c] (* 3 (:r c) (:r c))))
+                 (extend-type Number Shape (area [n] n))
+                 {}",
+                body
+            ))
+        };
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(program("(+ (area (->Square 2)) (area (->Circle 1)) (area 5))"), Ok(Value::Number(12)));
+        assert_eq!(program("(area (scale (->Square 2) 3))"), Ok(Value::Number(36)));
+        assert_eq!(program("(describe (->Square 3))"), text("square of area 9"));
+        // Records fall back to implementations for maps, then Object
+        assert_eq!(program("(extend-type Map Shape (area [m] (count m))) (area (->Circle 4))"), Ok(Value::Number(48)));
+        assert_eq!(program("(extend-type Object Shape (area [x] 0)) (+ (area \"s\") (area {:a 1}))"), Ok(Value::Number(0)));
+        // Implementations calling their own method recurse through every implementation
+        assert_eq!(
+            program("(defrecord Stack [top rest]) (extend-type Stack Shape (area [s] (+ (area (:top s)) (area (:rest s))))) (area (->Stack 1 (->Stack (->Square 2) 3)))"),
+            Ok(Value::Number(8))
+        );
+
+        assert_eq!(
+            program("(try (area \"s\") (catch Exception e (ex-message e)))"),
+            text("No implementation of method: area of protocol: Shape found for: String")
+        );
+        assert_eq!(
+            program("(try (describe (->Circle 1)) (catch Exception e (ex-message e)))"),
+            text("No implementation of method: describe of protocol: Shape found for: Circle")
+        );
+        assert!(matches!(program("(extend-type Square Shape (perimeter [s] 4))"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(program("(extend-type Triangle Shape (area [t] 0))"), Err(EvalError::InvalidOperation(_))));
+        assert!(matches!(program("(defprotocol Bad (f []))"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_multimethods() {
+        let program = |body: &str| {
+            parse_program_and_eval(&format!(
+                "(defmulti area :shape)
+                 (defmethod area :square [s] (* (:side s) (:side s)))
+                 (defmethod area :circle [c] (* 3 (:r c) (:r c)))
+                 (defmulti size (fn [x] (if (list? x) :many :one)))
+                 (defmethod size :many [xs] (reduce (fn [a b] (+ a b)) 0 (map size xs)))
+                 (defmethod size :default [x] 1)
+                 {}",
+                body
+            ))
+        };
+        let text = |value: &str| Ok(Value::String(value.to_string()));
+
+        assert_eq!(program("(+ (area {:shape :square :side 2}) (area {:shape :circle :r 1}))"), Ok(Value::Number(7)));
+        assert_eq!(program("(size (quote (1 (2 3) ((4) 5))))"), Ok(Value::Number(5)));
+        assert_eq!(program("(defmethod area :square [s] 0) (area {:shape :square :side 2})"), Ok(Value::Number(0)));
+        assert_eq!(
+            program("(try (area {:shape :triangle}) (catch Exception e (ex-message e)))"),
+            text("No method in multimethod 'area' for dispatch value: :triangle")
+        );
+        assert!(matches!(program("(defmethod perimeter :square [s] 4)"), Err(EvalError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_match() {
+        let program = |body: &str| {
+            parse_program_and_eval(&format!(
+                "(defn describe [x]
+                   (match x
+                     0 :zero
+                     [] :empty
+                     [a] a
+                     [a b & more] (+ a b (count more))
+                     {{:kind :square :side s}} (* s s)
+                     {{:kind _}} :shape
+                     \"s\" :other
+                     n :when (< n 0) :negative
+                     _ :positive))
+                 {}",
+                body
+            ))
+        };
+
+        assert_eq!(program("(describe 0)"), Ok(Value::Keyword("zero".to_string())));
+        assert_eq!(program("(describe (- 0 3))"), Ok(Value::Keyword("negative".to_string())));
+        assert_eq!(program("(describe [])"), Ok(Value::Keyword("empty".to_string())));
+        assert_eq!(program("(+ (describe [4]) (describe [1 2 3 4]))"), Ok(Value::Number(9)));
+        assert_eq!(program("(describe {:kind :square :side 3})"), Ok(Value::Number(9)));
+        assert_eq!(program("(defrecord Circle [kind r]) (describe (->Circle :circle 1))"), Ok(Value::Keyword("shape".to_string())));
+        assert_eq!(program("(list (describe \"s\") (describe 5))"), program("(list :other :positive)"));
+        // Only vectors match vector patterns, and a rest pattern sees the rest as a vector
+        assert_eq!(program("(match (list 1) [a] a _ :other)"), Ok(Value::Keyword("other".to_string())));
+        assert_eq!(program("(match [1 2 3] [_ & [b c]] (+ b c))"), Ok(Value::Number(5)));
+        assert_eq!(program("(match nil nil :nil :else :some)"), Ok(Value::Keyword("nil".to_string())));
+        assert_eq!(
+            program("(try (match 1 2 :two) (catch Exception e (ex-message e)))"),
+            Ok(Value::String("No matching clause".to_string()))
+        );
+        assert!(matches!(program("(match 1 [a & b c] a)"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_atoms() {
+        assert_eq!(parse_program_and_eval("(def counter (atom 0)) (swap! counter + 2) (swap! counter + 3) @counter"), Ok(Value::Number(5)));
+        assert_eq!(parse_program_and_eval("(let [a (atom [1]) _ (reset! a [2 3])] (deref a))"), parse_program_and_eval("[2 3]"));
+        assert_eq!(
+            parse_program_and_eval("(let [a (atom [1]) x (compare-and-set! a [1] :x) y (compare-and-set! a [1] :y)] (list x y @a))"),
+            parse_program_and_eval("(list true false :x)")
+        );
+        // Functions share state through an atom they close over or receive
+        assert_eq!(
+            parse_program_and_eval(
+                "(defn memo-square [cache n]
+                   (if (contains? @cache n)
+                     (get @cache n)
+                     (get (swap! cache assoc n (* n n)) n)))
+                 (let [cache (atom {}) a (memo-square cache 3) b (memo-square cache 4) c (memo-square cache 3)] (list (+ a b c) (count @cache)))"
+            ),
+            parse_program_and_eval("(list 34 2)")
+        );
+        assert_eq!(
+            parse_program_and_eval(
+                "(let [a (atom 1)
+                       log (atom [])
+                       _ (add-watch a :log (fn [k r old new] (swap! log conj [k old new @r])))
+                       _ (add-watch a :log (fn [k r old new] (swap! log conj [old new])))
+                       _ (swap! a + 1)
+                       _ (reset! a 10)]
+                   @log)"
+            ),
+            parse_program_and_eval("[[1 2] [2 10]]")
+        );
+        assert_eq!(parse_program_and_eval("(let [a (atom 1)] (list (= a a) (= a (atom 1))))"), parse_program_and_eval("(list true false)"));
+        assert!(matches!(parse_program_and_eval("(deref 1)"), Err(EvalError::TypeError(_))));
+        assert!(matches!(parse_program_and_eval("(hash (atom 1))"), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_parity_programs() {
+        // The same programs run compiled through tests/programs/run_all.sh
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs/parity");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(parse_program_and_eval(&format!("{}\n(-main)", source)), Ok(Value::Number(0)), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_lazy_seq_recursive_generator() {
+        use super::*;
+        use std::collections::HashMap;
+
+        let mut env = HashMap::new();
+        let define = AstParser::parse_sexp_new_domain(b"(defn naturals [n] (lazy-seq (cons n (naturals (+ n 1)))))", &mut 0);
+        eval_with_env(&define, &mut env).unwrap();
+        let usage = AstParser::parse_sexp_new_domain(b"(str (take 3 (naturals 7)))", &mut 0);
+        assert_eq!(eval_with_env(&usage, &mut env).unwrap(), Value::String("(7 8 9)".to_string()));
+    }
 }
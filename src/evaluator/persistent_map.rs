@@ -0,0 +1,450 @@
+/// Persistent hash map and set - a hash array mapped trie backing the interpreter's maps and sets
+///
+/// Each branch indexes five bits of a key's hash through a 32-bit bitmap, holding only the children
+/// that are present; a child is a single entry, a nested branch, or a bucket of entries whose whole
+/// hashes collide. Branches are reference counted, so cloning a map only bumps the root count, and
+/// an update copies the branches on the path to the changed entry when they are shared, updating
+/// them in place when they are not. Lookups and updates take O(log32 n) steps.
+///
+/// Iteration follows the hash layout; callers that need a stable order sort the entries.
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+#[derive(Clone)]
+enum Entry<K, V> {
+    Leaf(u64, K, V),
+    Collision(u64, Rc<Vec<(K, V)>>),
+    Branch(Rc<Branch<K, V>>),
+}
+
+#[derive(Clone)]
+struct Branch<K, V> {
+    bitmap: u32,
+    children: Vec<Entry<K, V>>,
+}
+
+#[derive(Clone)]
+pub struct PersistentMap<K, V> {
+    len: usize,
+    root: Rc<Branch<K, V>>,
+}
+
+fn hash_of<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The bitmap bit for a hash at the level that consumes bits from `shift`
+fn bit_for(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+impl<K, V> Branch<K, V> {
+    fn empty() -> Self {
+        Branch { bitmap: 0, children: Vec::new() }
+    }
+
+    /// Position among the present children of the child for `bit`
+    fn slot(&self, bit: u32) -> usize {
+        (self.bitmap & (bit - 1)).count_ones() as usize
+    }
+
+    /// A branch at `shift` holding two entries whose hashes differ
+    fn pair(shift: u32, first: (u64, Entry<K, V>), second: (u64, Entry<K, V>)) -> Self {
+        let (first_bit, second_bit) = (bit_for(first.0, shift), bit_for(second.0, shift));
+        if first_bit == second_bit {
+            return Branch {
+                bitmap: first_bit,
+                children: vec![Entry::Branch(Rc::new(Branch::pair(shift + BITS, first, second)))],
+            };
+        }
+        let children = if first_bit < second_bit { vec![first.1, second.1] } else { vec![second.1, first.1] };
+        Branch {
+            bitmap: first_bit | second_bit,
+            children,
+        }
+    }
+}
+
+impl<K: Clone + Eq, V: Clone> Branch<K, V> {
+    fn get(&self, shift: u32, hash: u64, key: &K) -> Option<&V> {
+        let bit = bit_for(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        match &self.children[self.slot(bit)] {
+            Entry::Leaf(leaf_hash, leaf_key, value) => (*leaf_hash == hash && leaf_key == key).then_some(value),
+            Entry::Collision(bucket_hash, bucket) if *bucket_hash == hash => bucket.iter().find(|(bucket_key, _)| bucket_key == key).map(|(_, value)| value),
+            Entry::Collision(..) => None,
+            Entry::Branch(branch) => branch.get(shift + BITS, hash, key),
+        }
+    }
+
+    /// Insert or replace an entry, returning the value it replaced
+    fn insert(&mut self, shift: u32, hash: u64, key: K, value: V) -> Option<V> {
+        let bit = bit_for(hash, shift);
+        let slot = self.slot(bit);
+        if self.bitmap & bit == 0 {
+            self.bitmap |= bit;
+            self.children.insert(slot, Entry::Leaf(hash, key, value));
+            return None;
+        }
+
+        let child = &mut self.children[slot];
+        match child {
+            Entry::Branch(branch) => Rc::make_mut(branch).insert(shift + BITS, hash, key, value),
+            Entry::Leaf(leaf_hash, leaf_key, leaf_value) if *leaf_hash == hash && *leaf_key == key => Some(std::mem::replace(leaf_value, value)),
+            Entry::Leaf(leaf_hash, leaf_key, leaf_value) if *leaf_hash == hash => {
+                *child = Entry::Collision(hash, Rc::new(vec![(leaf_key.clone(), leaf_value.clone()), (key, value)]));
+                None
+            }
+            Entry::Collision(bucket_hash, bucket) if *bucket_hash == hash => {
+                let bucket = Rc::make_mut(bucket);
+                match bucket.iter_mut().find(|(bucket_key, _)| *bucket_key == key) {
+                    Some((_, bucket_value)) => Some(std::mem::replace(bucket_value, value)),
+                    None => {
+                        bucket.push((key, value));
+                        None
+                    }
+                }
+            }
+            Entry::Leaf(other_hash, ..) | Entry::Collision(other_hash, _) => {
+                let other = (*other_hash, child.clone());
+                *child = Entry::Branch(Rc::new(Branch::pair(shift + BITS, other, (hash, Entry::Leaf(hash, key, value)))));
+                None
+            }
+        }
+    }
+
+    /// Remove an entry known to be present, returning its value; a branch left with one entry
+    /// is folded into its parent
+    fn remove(&mut self, shift: u32, hash: u64, key: &K) -> Option<V> {
+        let bit = bit_for(hash, shift);
+        let slot = self.slot(bit);
+        let removed = match &mut self.children[slot] {
+            Entry::Leaf(..) => {
+                self.bitmap &= !bit;
+                match self.children.remove(slot) {
+                    Entry::Leaf(_, _, value) => return Some(value),
+                    _ => unreachable!("the slot held a leaf"),
+                }
+            }
+            Entry::Collision(_, bucket) => {
+                let bucket = Rc::make_mut(bucket);
+                let position = bucket.iter().position(|(bucket_key, _)| bucket_key == key)?;
+                let (_, value) = bucket.remove(position);
+                if let [(last_key, last_value)] = bucket.as_slice() {
+                    self.children[slot] = Entry::Leaf(hash, last_key.clone(), last_value.clone());
+                }
+                return Some(value);
+            }
+            Entry::Branch(branch) => {
+                let branch = Rc::make_mut(branch);
+                let value = branch.remove(shift + BITS, hash, key)?;
+                match branch.children.as_slice() {
+                    [] => {
+                        self.bitmap &= !bit;
+                        self.children.remove(slot);
+                    }
+                    [Entry::Leaf(..) | Entry::Collision(..)] => self.children[slot] = branch.children[0].clone(),
+                    _ => {}
+                }
+                value
+            }
+        };
+        Some(removed)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> PersistentMap<K, V> {
+    pub fn new() -> Self {
+        PersistentMap {
+            len: 0,
+            root: Rc::new(Branch::empty()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.root.get(0, hash_of(key), key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert or replace the entry for `key`, returning the value it replaced
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let replaced = Rc::make_mut(&mut self.root).insert(0, hash_of(&key), key, value);
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        replaced
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        // Checked first so a miss copies nothing
+        if !self.contains_key(key) {
+            return None;
+        }
+        self.len -= 1;
+        Rc::make_mut(&mut self.root).remove(0, hash_of(key), key)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            branches: vec![self.root.children.iter()],
+            bucket: [].iter(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Default for PersistentMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + PartialEq> PartialEq for PersistentMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && (Rc::ptr_eq(&self.root, &other.root) || self.iter().all(|(key, value)| other.get(key) == Some(value)))
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + Eq> Eq for PersistentMap<K, V> {}
+
+impl<K: Clone + Eq + Hash + fmt::Debug, V: Clone + fmt::Debug> fmt::Debug for PersistentMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> FromIterator<(K, V)> for PersistentMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut map = PersistentMap::new();
+        map.extend(entries);
+        map
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Extend<(K, V)> for PersistentMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Borrowing iterator over a `PersistentMap`, depth first through its branches
+pub struct Iter<'a, K, V> {
+    branches: Vec<std::slice::Iter<'a, Entry<K, V>>>,
+    bucket: std::slice::Iter<'a, (K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.bucket.next() {
+                self.remaining -= 1;
+                return Some((key, value));
+            }
+            match self.branches.last_mut()?.next() {
+                None => {
+                    self.branches.pop();
+                }
+                Some(Entry::Leaf(_, key, value)) => {
+                    self.remaining -= 1;
+                    return Some((key, value));
+                }
+                Some(Entry::Collision(_, bucket)) => self.bucket = bucket.iter(),
+                Some(Entry::Branch(branch)) => self.branches.push(branch.children.iter()),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<'a, K: Clone + Eq + Hash, V: Clone> IntoIterator for &'a PersistentMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// Owning iteration clones the entries out of the shared branches
+impl<K: Clone + Eq + Hash, V: Clone> IntoIterator for PersistentMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().map(|(key, value)| (key.clone(), value.clone())).collect::<Vec<_>>().into_iter()
+    }
+}
+
+/// A `PersistentMap` from members to unit values
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct PersistentSet<K: Clone + Eq + Hash> {
+    members: PersistentMap<K, ()>,
+}
+
+impl<K: Clone + Eq + Hash> PersistentSet<K> {
+    pub fn new() -> Self {
+        PersistentSet { members: PersistentMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, member: &K) -> bool {
+        self.members.contains_key(member)
+    }
+
+    /// Add a member, returning whether it was new
+    pub fn insert(&mut self, member: K) -> bool {
+        self.members.insert(member, ()).is_none()
+    }
+
+    /// Remove a member, returning whether it was present
+    pub fn remove(&mut self, member: &K) -> bool {
+        self.members.remove(member).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.members.keys()
+    }
+
+    /// Keep only the members `keep` accepts
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        let rejected: Vec<K> = self.iter().filter(|member| !keep(member)).cloned().collect();
+        for member in &rejected {
+            self.remove(member);
+        }
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.iter().all(|member| other.contains(member))
+    }
+
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+}
+
+impl<K: Clone + Eq + Hash + fmt::Debug> fmt::Debug for PersistentSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Clone + Eq + Hash> FromIterator<K> for PersistentSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(members: I) -> Self {
+        let mut set = PersistentSet::new();
+        set.extend(members);
+        set
+    }
+}
+
+impl<K: Clone + Eq + Hash> Extend<K> for PersistentSet<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, members: I) {
+        for member in members {
+            self.insert(member);
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash> IntoIterator for PersistentSet<K> {
+    type Item = K;
+    type IntoIter = std::vec::IntoIter<K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().cloned().collect::<Vec<_>>().into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hashes to one of four values, forcing collision buckets
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    struct Clashing(u32);
+
+    impl Hash for Clashing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 4).hash(state);
+        }
+    }
+
+    #[test]
+    fn inserts_and_removes_keep_earlier_versions() {
+        let mut map = PersistentMap::new();
+        for key in 0..3000 {
+            map.insert(key, key * 2);
+        }
+        let snapshot = map.clone();
+        assert_eq!(map.insert(7, 0), Some(14));
+        for key in (0..3000).step_by(2) {
+            assert_eq!(map.remove(&key), Some(if key == 0 { 0 } else { key * 2 }));
+        }
+        assert_eq!(map.remove(&2), None);
+        assert_eq!(map.len(), 1500);
+        assert_eq!(map.get(&7), Some(&0));
+        assert_eq!(map.get(&8), None);
+        assert_eq!(map.iter().count(), 1500);
+        // The earlier version is untouched
+        assert_eq!(snapshot.len(), 3000);
+        assert_eq!(snapshot.get(&7), Some(&14));
+        assert!((0..3000).all(|key| snapshot.get(&key) == Some(&(key * 2))));
+        assert_ne!(map, snapshot);
+    }
+
+    #[test]
+    fn colliding_hashes_share_a_bucket() {
+        let mut set: PersistentSet<Clashing> = (0..40).map(Clashing).collect();
+        assert_eq!(set.len(), 40);
+        assert!(!set.insert(Clashing(5)));
+        assert!(set.remove(&Clashing(5)));
+        set.retain(|member| member.0 < 20);
+        assert_eq!(set.len(), 19);
+        assert!(set.contains(&Clashing(1)) && !set.contains(&Clashing(5)) && !set.contains(&Clashing(25)));
+        assert!(set.is_subset(&(0..20).map(Clashing).collect()));
+        assert_eq!(set.iter().count(), 19);
+    }
+}
@@ -0,0 +1,386 @@
+/// Persistent vector - a 32-way trie with a tail, backing the interpreter's vectors
+///
+/// Items live in 32-item leaves under branches of up to 32 children, with the last, partly filled
+/// leaf kept apart as the tail so pushes and pops at the back rarely touch the trie. Nodes are
+/// reference counted: cloning a vector only bumps the root and tail counts, and an update copies
+/// the nodes on the path to the changed item when they are shared, updating them in place when
+/// they are not. Lookups, `set`, `push` and `pop` take O(log32 n) steps.
+use std::fmt;
+use std::ops::Index;
+use std::rc::Rc;
+
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+#[derive(Clone)]
+enum Node<T> {
+    Branch(Vec<Rc<Node<T>>>),
+    Leaf(Vec<T>),
+}
+
+#[derive(Clone)]
+pub struct PersistentVector<T> {
+    len: usize,
+    shift: u32, // Index bits consumed above the leaves
+    root: Rc<Node<T>>,
+    tail: Rc<Vec<T>>,
+}
+
+/// A chain of single-child branches `level` bits above `leaf`
+fn new_path<T>(level: u32, leaf: Rc<Node<T>>) -> Rc<Node<T>> {
+    if level == 0 {
+        leaf
+    } else {
+        Rc::new(Node::Branch(vec![new_path(level - BITS, leaf)]))
+    }
+}
+
+/// Hang a full leaf, holding the items up to `last`, at the end of the trie under `node`
+fn push_tail<T: Clone>(node: &mut Rc<Node<T>>, level: u32, last: usize, leaf: Rc<Node<T>>) {
+    let Node::Branch(children) = Rc::make_mut(node) else {
+        unreachable!("leaves only sit at level zero");
+    };
+    let slot = (last >> level) & MASK;
+    if level == BITS {
+        children.push(leaf);
+    } else if slot < children.len() {
+        push_tail(&mut children[slot], level - BITS, last, leaf);
+    } else {
+        children.push(new_path(level - BITS, leaf));
+    }
+}
+
+/// Drop the last leaf under `node`, which holds the item at `last`; true when `node` is left empty
+fn pop_tail<T: Clone>(node: &mut Rc<Node<T>>, level: u32, last: usize) -> bool {
+    let Node::Branch(children) = Rc::make_mut(node) else {
+        unreachable!("leaves only sit at level zero");
+    };
+    let slot = (last >> level) & MASK;
+    if level == BITS || pop_tail(&mut children[slot], level - BITS, last) {
+        children.pop();
+    }
+    children.is_empty()
+}
+
+impl<T: Clone> PersistentVector<T> {
+    pub fn new() -> Self {
+        PersistentVector {
+            len: 0,
+            shift: BITS,
+            root: Rc::new(Node::Branch(Vec::new())),
+            tail: Rc::new(Vec::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Index of the first item held in the tail
+    fn tail_offset(&self) -> usize {
+        if self.len < WIDTH {
+            0
+        } else {
+            ((self.len - 1) >> BITS) << BITS
+        }
+    }
+
+    /// The leaf, or the tail, holding the item at `index`
+    fn leaf_for(&self, index: usize) -> &[T] {
+        if index >= self.tail_offset() {
+            return &self.tail;
+        }
+        let mut node = &self.root;
+        let mut level = self.shift;
+        loop {
+            match &**node {
+                Node::Branch(children) => node = &children[(index >> level) & MASK],
+                Node::Leaf(items) => return items,
+            }
+            level -= BITS;
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        (index < self.len).then(|| &self.leaf_for(index)[index & MASK])
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// Replace the item at `index`, which must be in bounds
+    pub fn set(&mut self, index: usize, value: T) {
+        assert!(index < self.len, "index {} out of bounds for a vector of {}", index, self.len);
+        let tail_offset = self.tail_offset();
+        if index >= tail_offset {
+            Rc::make_mut(&mut self.tail)[index - tail_offset] = value;
+            return;
+        }
+
+        let mut node = &mut self.root;
+        let mut level = self.shift;
+        loop {
+            match Rc::make_mut(node) {
+                Node::Branch(children) => node = &mut children[(index >> level) & MASK],
+                Node::Leaf(items) => {
+                    items[index & MASK] = value;
+                    return;
+                }
+            }
+            level -= BITS;
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.len - self.tail_offset() < WIDTH {
+            Rc::make_mut(&mut self.tail).push(value);
+            self.len += 1;
+            return;
+        }
+
+        // The tail is full: move it into the trie, adding a level when the root has no room left
+        let leaf = Rc::new(Node::Leaf(Rc::unwrap_or_clone(std::mem::replace(&mut self.tail, Rc::new(vec![value])))));
+        if (self.len >> BITS) > (1 << self.shift) {
+            let old_root = std::mem::replace(&mut self.root, Rc::new(Node::Branch(Vec::new())));
+            self.root = Rc::new(Node::Branch(vec![old_root, new_path(self.shift, leaf)]));
+            self.shift += BITS;
+        } else {
+            push_tail(&mut self.root, self.shift, self.len - 1, leaf);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        if self.len - self.tail_offset() > 1 || self.len == 1 {
+            self.len -= 1;
+            return Rc::make_mut(&mut self.tail).pop();
+        }
+
+        // The tail's last item goes, so the trie's last leaf becomes the new tail
+        let value = Rc::make_mut(&mut self.tail).pop();
+        let last = self.len - 2;
+        self.tail = Rc::new(self.leaf_for(last).to_vec());
+        pop_tail(&mut self.root, self.shift, last);
+        if self.shift > BITS {
+            if let Node::Branch(children) = &*self.root {
+                if children.len() == 1 {
+                    self.root = children[0].clone();
+                    self.shift -= BITS;
+                }
+            }
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            vector: self,
+            front: 0,
+            back: self.len,
+            leaf: &[],
+            leaf_start: 0,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Clone> Default for PersistentVector<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Index<usize> for PersistentVector<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).unwrap_or_else(|| panic!("index {} out of bounds for a vector of {}", index, self.len))
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for PersistentVector<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + Eq> Eq for PersistentVector<T> {}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for PersistentVector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PersistentVector<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let mut vector = PersistentVector::new();
+        vector.extend(items);
+        vector
+    }
+}
+
+impl<T: Clone> Extend<T> for PersistentVector<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            self.push(item);
+        }
+    }
+}
+
+impl<T: Clone> From<Vec<T>> for PersistentVector<T> {
+    fn from(items: Vec<T>) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+/// Borrowing iterator over a `PersistentVector`, walking one leaf at a time from the front
+pub struct Iter<'a, T> {
+    vector: &'a PersistentVector<T>,
+    front: usize,
+    back: usize,
+    leaf: &'a [T],
+    leaf_start: usize,
+}
+
+impl<'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.front >= self.back {
+            return None;
+        }
+        if self.front < self.leaf_start || self.front - self.leaf_start >= self.leaf.len() {
+            self.leaf = self.vector.leaf_for(self.front);
+            self.leaf_start = self.front & !MASK;
+        }
+        let item = &self.leaf[self.front - self.leaf_start];
+        self.front += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.vector.get(self.back)
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: Clone> IntoIterator for &'a PersistentVector<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Owning iterator over a `PersistentVector`; items are cloned out of the shared leaves
+pub struct IntoIter<T> {
+    vector: PersistentVector<T>,
+    front: usize,
+    back: usize,
+}
+
+impl<T: Clone> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.front >= self.back {
+            return None;
+        }
+        self.front += 1;
+        self.vector.get(self.front - 1).cloned()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.front >= self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.vector.get(self.back).cloned()
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for IntoIter<T> {}
+
+impl<T: Clone> IntoIterator for PersistentVector<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter {
+            back: self.len,
+            vector: self,
+            front: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushes_and_pops_across_levels() {
+        let mut vector = PersistentVector::new();
+        for item in 0..5000 {
+            vector.push(item);
+        }
+        assert_eq!(vector.len(), 5000);
+        assert!((0..5000).all(|index| vector[index] == index));
+        assert_eq!(vector.iter().rev().take(2).collect::<Vec<_>>(), vec![&4999, &4998]);
+
+        let snapshot = vector.clone();
+        vector.set(1234, 0);
+        for _ in 0..4000 {
+            vector.pop();
+        }
+        assert_eq!(vector.len(), 1000);
+        assert_eq!(vector.last(), Some(&999));
+        assert_eq!(vector[1234 % 1000], 234);
+        // The earlier version is untouched
+        assert_eq!(snapshot[1234], 1234);
+        assert_eq!(snapshot.into_iter().sum::<usize>(), (0..5000).sum());
+
+        while vector.pop().is_some() {}
+        assert!(vector.is_empty());
+        vector.push(7);
+        assert_eq!(vector.to_vec(), vec![7]);
+    }
+}
@@ -0,0 +1,332 @@
+use super::{primitives, special_forms, Environment, EvalError, FunctionArity, MapKey, PersistentMap, PersistentSet, Value};
+/// Polymorphism - protocols (defprotocol, extend-type) and multimethods (defmulti, defmethod)
+///
+/// A protocol method is a function whose closure holds its implementations keyed by type name:
+/// calls dispatch on the type of their first argument (a record's type, else the built-in type
+/// its value has, else `Object`). A multimethod likewise holds its methods keyed by dispatch
+/// value, choosing one by what its dispatch function returns for the arguments, else `:default`.
+/// Both stay values: `extend-type` and `defmethod` rebind the name to the extended function, so
+/// functions defined afterwards see the new implementations, as with any other rebinding.
+use crate::ast::Node;
+
+/// Operator of the bodies a protocol method's arities run. No source symbol can spell it.
+pub(super) const PROTOCOL_CALL: &str = "protocol call";
+/// Operator of the body a multimethod runs
+pub(super) const MULTIMETHOD_CALL: &str = "multimethod call";
+
+// Closure entries of protocol methods and multimethods, unspellable as symbols
+const IMPLEMENTATIONS: &str = "implementations";
+const DISPATCH_FUNCTION: &str = "dispatch function";
+const ARGUMENTS: &str = "dispatch arguments";
+
+/// Type names `extend-type` accepts besides record names
+const BUILT_IN_TYPES: &[&str] = &["nil", "Number", "Boolean", "String", "Keyword", "Symbol", "Vector", "List", "Map", "Set", "Function", "Atom", "Object"];
+
+/// The type name protocol dispatch sees for a value
+fn type_name(value: &Value) -> &str {
+    match value {
+        Value::Record(record) => record.name(),
+        Value::Nil => "nil",
+        Value::Number(_) => "Number",
+        Value::Boolean(_) => "Boolean",
+        Value::String(_) => "String",
+        Value::Keyword(_) => "Keyword",
+        Value::Symbol(_) => "Symbol",
+        Value::Vector(_) => "Vector",
+        Value::List(_) | Value::LazySeq(_) => "List",
+        Value::Map(_) | Value::SortedMap(_) => "Map",
+        Value::Set(_) | Value::SortedSet(_) => "Set",
+        Value::Function { .. } => "Function",
+        Value::Atom(_) => "Atom",
+    }
+}
+
+fn symbol_name(node: &Node, message: impl FnOnce() -> String) -> Result<&str, EvalError> {
+    match node {
+        Node::Symbol { value } => Ok(value),
+        _ => Err(EvalError::TypeError(message())),
+    }
+}
+
+/// The implementations a protocol method or multimethod holds
+fn implementations(function: &Value) -> Option<&PersistentMap<MapKey, Value>> {
+    match function {
+        Value::Function { closure, .. } => match closure.get(IMPLEMENTATIONS) {
+            Some(Value::Map(entries)) => Some(entries),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `function` with `implementation` added under `key`
+fn with_implementation(function: &Value, key: MapKey, implementation: Value) -> Value {
+    let mut function = function.clone();
+    if let Value::Function { closure, .. } = &mut function {
+        if let Some(Value::Map(entries)) = closure.get_mut(IMPLEMENTATIONS) {
+            entries.insert(key, implementation);
+        }
+    }
+    function
+}
+
+/// Call `implementation` with `own` bound to `name` inside it, so implementations calling the
+/// protocol method or multimethod again reach every implementation it has
+fn call_implementation(implementation: &Value, name: &str, own: Option<&Value>, args: Vec<Value>) -> Result<Value, EvalError> {
+    let mut implementation = implementation.clone();
+    if let (Value::Function { closure, .. }, Some(own)) = (&mut implementation, own) {
+        closure.insert(name.to_string(), own.clone());
+    }
+    special_forms::apply_function(implementation, args)
+}
+
+/// defprotocol - Declare a protocol and bind each of its methods, as in
+/// `(defprotocol Shape (area [this]) (scale [this factor]))`
+pub fn eval_defprotocol(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let Some((name, specs)) = args.split_first() else {
+        return Err(EvalError::ArityError("defprotocol".to_string(), 1, 0));
+    };
+    let protocol = symbol_name(name, || "defprotocol requires a symbol as first argument".to_string())?;
+
+    let mut methods = PersistentSet::new();
+    let mut functions = Vec::new();
+    // Docstrings may follow the name and each method's parameter vectors
+    for spec in specs.iter().filter(|spec| !matches!(spec, Node::Primitive { .. })) {
+        let Node::List { root } = spec else {
+            return Err(EvalError::TypeError(format!("defprotocol {}: methods must be lists of a name and parameter vectors", protocol)));
+        };
+        let method = symbol_name(root.first().unwrap_or(spec), || format!("defprotocol {}: method names must be symbols", protocol))?;
+        let mut arities: Vec<FunctionArity> = Vec::new();
+        for params in root[1..].iter().filter(|node| !matches!(node, Node::Primitive { .. })) {
+            let count = match params {
+                Node::Vector { root } if !root.is_empty() && !root.iter().any(|param| matches!(param, Node::Symbol { value } if value == "&")) => root.len(),
+                _ => {
+                    return Err(EvalError::TypeError(format!(
+                        "defprotocol {}: {} takes parameter vectors of at least one fixed parameter",
+                        protocol, method
+                    )))
+                }
+            };
+            if arities.iter().any(|arity| arity.params.len() == count) {
+                return Err(EvalError::InvalidOperation(format!(
+                    "defprotocol {}: {} declares more than one arity taking {} arguments",
+                    protocol, method, count
+                )));
+            }
+            // The body names the protocol and method, then passes the arguments on
+            let params: Vec<String> = (0..count).map(|index| format!("argument {}", index)).collect();
+            let body = [PROTOCOL_CALL, protocol, method]
+                .into_iter()
+                .chain(params.iter().map(String::as_str))
+                .map(|value| Node::Symbol { value: value.to_string() })
+                .collect();
+            arities.push(FunctionArity {
+                params,
+                rest_param: None,
+                body: Box::new(Node::List { root: body }),
+            });
+        }
+        if arities.is_empty() {
+            return Err(EvalError::TypeError(format!("defprotocol {}: {} declares no parameter vector", protocol, method)));
+        }
+
+        methods.insert(MapKey::Keyword(method.to_string()));
+        let mut closure = Environment::new();
+        closure.insert(IMPLEMENTATIONS.to_string(), Value::Map(PersistentMap::new()));
+        functions.push((
+            method.to_string(),
+            Value::Function {
+                name: Some(method.to_string()),
+                arities,
+                closure,
+            },
+        ));
+    }
+
+    env.extend(functions);
+    let description = [
+        (MapKey::Keyword("name".to_string()), Value::String(protocol.to_string())),
+        (MapKey::Keyword("methods".to_string()), Value::Set(methods)),
+    ];
+    env.insert(protocol.to_string(), Value::Map(description.into_iter().collect()));
+    Ok(Value::Nil)
+}
+
+/// Run the implementation of a protocol method for the type of its first argument
+pub fn eval_protocol_call(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let [protocol, method, arguments @ ..] = args else {
+        return Err(EvalError::ArityError(PROTOCOL_CALL.to_string(), 3, args.len()));
+    };
+    let protocol = symbol_name(protocol, || "protocol calls name their protocol".to_string())?;
+    let method = symbol_name(method, || "protocol calls name their method".to_string())?;
+    let values = arguments.iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect::<Result<Vec<_>, _>>()?;
+
+    let own = env.get(method);
+    let type_name = values.first().map(type_name).unwrap_or("nil");
+    let candidates: &[&str] = match values.first() {
+        Some(Value::Record(_)) => &[type_name, "Map", "Object"],
+        _ => &[type_name, "Object"],
+    };
+    let implementation = own
+        .and_then(implementations)
+        .and_then(|entries| candidates.iter().find_map(|candidate| entries.get(&MapKey::String(candidate.to_string()))))
+        .ok_or_else(|| EvalError::InvalidOperation(format!("No implementation of method: {} of protocol: {} found for: {}", method, protocol, type_name)))?;
+    call_implementation(implementation, method, own, values)
+}
+
+/// extend-type - Implement protocol methods for a type, as in
+/// `(extend-type Circle Shape (area [c] (* 3 (:r c) (:r c))))`
+pub fn eval_extend_type(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let Some((type_node, specs)) = args.split_first() else {
+        return Err(EvalError::ArityError("extend-type".to_string(), 2, 0));
+    };
+    let type_name = symbol_name(type_node, || "extend-type requires a type name as first argument".to_string())?;
+    if !BUILT_IN_TYPES.contains(&type_name) && !env.contains_key(&format!("->{}", type_name)) {
+        return Err(EvalError::InvalidOperation(format!("extend-type: unknown type {}", type_name)));
+    }
+
+    // Each implementation closes over the methods as extended here, so they can call one another
+    let mut protocol: Option<(&str, &PersistentSet<MapKey>)> = None;
+    let mut implemented = Vec::new();
+    for spec in specs {
+        match spec {
+            Node::Symbol { value } => {
+                let methods = match env.get(value) {
+                    Some(Value::Map(description)) => match description.get(&MapKey::Keyword("methods".to_string())) {
+                        Some(Value::Set(methods)) => methods,
+                        _ => return Err(EvalError::InvalidOperation(format!("extend-type: {} is not a protocol", value))),
+                    },
+                    _ => return Err(EvalError::InvalidOperation(format!("extend-type: {} is not a protocol", value))),
+                };
+                protocol = Some((value, methods));
+            }
+            Node::List { root } if root.len() >= 2 => {
+                let method = symbol_name(&root[0], || "extend-type method names must be symbols".to_string())?;
+                let Some((protocol_name, methods)) = protocol else {
+                    return Err(EvalError::InvalidOperation(format!("extend-type {}: {} is not preceded by a protocol", type_name, method)));
+                };
+                if !methods.contains(&MapKey::Keyword(method.to_string())) {
+                    return Err(EvalError::InvalidOperation(format!(
+                        "extend-type {}: {} is not a method of protocol {}",
+                        type_name, method, protocol_name
+                    )));
+                }
+                let arities = special_forms::function_arities(&root[1..])?;
+                if arities.iter().any(|arity| arity.rest_param.is_some()) {
+                    return Err(EvalError::InvalidOperation(format!("extend-type {}: {} cannot take variadic parameters", type_name, method)));
+                }
+                implemented.push((method.to_string(), arities));
+            }
+            _ => return Err(EvalError::TypeError(format!("extend-type {}: expected a protocol name or a method implementation", type_name))),
+        }
+    }
+
+    let extend = |env: &Environment, closure: &Environment| {
+        implemented
+            .iter()
+            .map(|(method, arities)| {
+                let function = env.get(method).filter(|function| implementations(function).is_some());
+                let Some(function) = function else {
+                    return Err(EvalError::InvalidOperation(format!("extend-type {}: {} is no longer bound to a protocol method", type_name, method)));
+                };
+                let implementation = Value::Function {
+                    name: None,
+                    arities: arities.clone(),
+                    closure: closure.clone(),
+                };
+                Ok((method.clone(), with_implementation(function, MapKey::String(type_name.to_string()), implementation)))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let mut closure = env.clone();
+    closure.extend(extend(env, env)?);
+    let extended = extend(env, &closure)?;
+    env.extend(extended);
+    Ok(Value::Nil)
+}
+
+/// defmulti - Bind a multimethod dispatching on what `dispatch` returns for its arguments, as in
+/// `(defmulti area :shape)`
+pub fn eval_defmulti(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    // A docstring may follow the name
+    let args: Vec<&Node> = args
+        .iter()
+        .filter(|arg| {
+            !matches!(
+                arg,
+                Node::Primitive {
+                    value: crate::ast::Primitive::String(_)
+                }
+            )
+        })
+        .collect();
+    let [name, dispatch] = args[..] else {
+        return Err(EvalError::ArityError("defmulti".to_string(), 2, args.len()));
+    };
+    let name = symbol_name(name, || "defmulti requires a symbol as first argument".to_string())?;
+    let dispatch = crate::evaluator::eval_with_env(dispatch, env)?;
+
+    let mut closure = Environment::new();
+    closure.insert(DISPATCH_FUNCTION.to_string(), dispatch);
+    closure.insert(IMPLEMENTATIONS.to_string(), Value::Map(PersistentMap::new()));
+    let body = [MULTIMETHOD_CALL, name].into_iter().map(|value| Node::Symbol { value: value.to_string() }).collect();
+    let function = Value::Function {
+        name: Some(name.to_string()),
+        arities: vec![FunctionArity {
+            params: Vec::new(),
+            rest_param: Some(ARGUMENTS.to_string()),
+            body: Box::new(Node::List { root: body }),
+        }],
+        closure,
+    };
+    env.insert(name.to_string(), function);
+    Ok(Value::Nil)
+}
+
+/// defmethod - Add the method a multimethod runs for one dispatch value, as in
+/// `(defmethod area :circle [c] (* 3 (:r c) (:r c)))`; `:default` catches the rest
+pub fn eval_defmethod(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() < 3 {
+        return Err(EvalError::ArityError("defmethod".to_string(), 4, args.len()));
+    }
+    let name = symbol_name(&args[0], || "defmethod requires a symbol as first argument".to_string())?;
+    let Some(function) = env
+        .get(name)
+        .filter(|function| matches!(function, Value::Function { closure, .. } if closure.contains_key(DISPATCH_FUNCTION)))
+    else {
+        return Err(EvalError::InvalidOperation(format!("defmethod: {} is not a multimethod", name)));
+    };
+    let function = function.clone();
+    let dispatch_value = crate::evaluator::eval_with_env(&args[1], env)?;
+    let method = Value::Function {
+        name: None,
+        arities: special_forms::function_arities(&args[2..])?,
+        closure: env.clone(),
+    };
+    env.insert(name.to_string(), with_implementation(&function, MapKey::try_from_value(&dispatch_value)?, method));
+    Ok(Value::Nil)
+}
+
+/// Run the method a multimethod holds for the dispatch value of its arguments
+pub fn eval_multimethod_call(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let [name] = args else {
+        return Err(EvalError::ArityError(MULTIMETHOD_CALL.to_string(), 1, args.len()));
+    };
+    let name = symbol_name(name, || "multimethod calls name their multimethod".to_string())?;
+    let values: Vec<Value> = match env.get(ARGUMENTS) {
+        Some(Value::Vector(items)) => items.iter().cloned().collect(),
+        _ => Vec::new(),
+    };
+    let dispatch = env.get(DISPATCH_FUNCTION).cloned().unwrap_or(Value::Nil);
+    let dispatch_value = special_forms::apply_function(dispatch, values.clone())?;
+
+    let own = env.get(name);
+    let implementation = own
+        .and_then(implementations)
+        .and_then(|entries| {
+            let own_method = MapKey::try_from_value(&dispatch_value).ok().and_then(|key| entries.get(&key));
+            own_method.or_else(|| entries.get(&MapKey::Keyword("default".to_string())))
+        })
+        .ok_or_else(|| EvalError::InvalidOperation(format!("No method in multimethod '{}' for dispatch value: {}", name, primitives::value_to_string(&dispatch_value))))?;
+    call_implementation(implementation, name, own, values)
+}
@@ -1,12 +1,11 @@
-use super::{Environment, EvalError, MapKey, Value};
+use super::{lazy, sorted, Environment, EvalError, LazySeq, MapKey, PersistentMap, PersistentSet, PersistentVector, Value};
 /// Primitive operations - arithmetic and comparisons
 use crate::ast::Node;
-use std::collections::HashSet;
 
 /// Evaluate arithmetic operations (+, -, *, /)
 pub fn eval_arithmetic_op<F>(args: &[Node], env: &mut Environment, op: F, op_name: &str) -> Result<Value, EvalError>
 where
-    F: Fn(isize, isize) -> isize,
+    F: Fn(isize, isize) -> Result<isize, EvalError>,
 {
     if args.len() < 2 {
         return Err(EvalError::ArityError(op_name.to_string(), 2, args.len()));
@@ -23,13 +22,37 @@ where
         .try_fold(first_num, |acc, arg| {
             let val = crate::evaluator::eval_with_env(arg, env)?;
             match val {
-                Value::Number(n) => Ok(op(acc, n)),
+                Value::Number(n) => op(acc, n),
                 _ => Err(EvalError::TypeError(format!("{} requires numbers", op_name))),
             }
         })
         .map(Value::Number)
 }
 
+/// Checked arithmetic used by `+`, `-`, `*` and `/`; overflow is an error rather than a wrap
+pub fn checked_add(a: isize, b: isize) -> Result<isize, EvalError> {
+    a.checked_add(b).ok_or_else(overflow_error)
+}
+
+pub fn checked_sub(a: isize, b: isize) -> Result<isize, EvalError> {
+    a.checked_sub(b).ok_or_else(overflow_error)
+}
+
+pub fn checked_mul(a: isize, b: isize) -> Result<isize, EvalError> {
+    a.checked_mul(b).ok_or_else(overflow_error)
+}
+
+pub fn checked_div(a: isize, b: isize) -> Result<isize, EvalError> {
+    if b == 0 {
+        return Err(EvalError::ArithmeticError("Divide by zero".to_string()));
+    }
+    a.checked_div(b).ok_or_else(overflow_error)
+}
+
+fn overflow_error() -> EvalError {
+    EvalError::ArithmeticError("integer overflow".to_string())
+}
+
 /// Evaluate equality comparison (supports multiple types)
 pub fn eval_equal(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
     if args.len() != 2 {
@@ -38,6 +61,14 @@ pub fn eval_equal(args: &[Node], env: &mut Environment) -> Result<Value, EvalErr
 
     let left = crate::evaluator::eval_with_env(&args[0], env)?;
     let right = crate::evaluator::eval_with_env(&args[1], env)?;
+    Ok(Value::Boolean(values_equal(left, right)?))
+}
+
+/// Structural equality, as `=` sees it
+pub(super) fn values_equal(left: Value, right: Value) -> Result<bool, EvalError> {
+    // Lazy sequences compare by their items, like lists, and sorted collections by their entries
+    let left = sorted::unsorted(lazy::realize(left)?)?;
+    let right = sorted::unsorted(lazy::realize(right)?)?;
 
     let result = match (left, right) {
         (Value::Number(a), Value::Number(b)) => a == b,
@@ -47,11 +78,15 @@ pub fn eval_equal(args: &[Node], env: &mut Environment) -> Result<Value, EvalErr
         (Value::Vector(a), Value::Vector(b)) => a == b,
         (Value::Set(a), Value::Set(b)) => a == b,
         (Value::Map(a), Value::Map(b)) => a == b,
+        (Value::List(a), Value::List(b)) => a == b,
+        (Value::Symbol(a), Value::Symbol(b)) => a == b,
+        (Value::Record(a), Value::Record(b)) => a == b,
+        (Value::Atom(a), Value::Atom(b)) => a == b, // The same atom, not equal contents
         (Value::Nil, Value::Nil) => true,
         _ => false, // Different types are not equal
     };
 
-    Ok(Value::Boolean(result))
+    Ok(result)
 }
 
 /// Evaluate comparison operations (<, >, <=, >=)
@@ -117,7 +152,7 @@ pub fn eval_logical_not(args: &[Node], env: &mut Environment) -> Result<Value, E
     Ok(Value::Boolean(!is_truthy(&val)))
 }
 
-fn is_truthy(val: &Value) -> bool {
+pub(super) fn is_truthy(val: &Value) -> bool {
     match val {
         Value::Boolean(b) => *b,
         Value::Number(n) => *n != 0,
@@ -128,10 +163,16 @@ fn is_truthy(val: &Value) -> bool {
         Value::Vector(items) => !items.is_empty(),
         Value::Set(entries) => !entries.is_empty(),
         Value::Map(entries) => !entries.is_empty(),
+        Value::SortedMap(tree) | Value::SortedSet(tree) => !tree.is_empty(),
+        Value::Record(record) => !record.is_empty(),
+        Value::List(items) => !items.is_empty(),
+        Value::LazySeq(_) => true, // Truthy without being realized
+        Value::Symbol(_) => true,
+        Value::Atom(_) => true,
     }
 }
 
-fn value_to_string(value: &Value) -> String {
+pub(super) fn value_to_string(value: &Value) -> String {
     match value {
         Value::Number(n) => n.to_string(),
         Value::Boolean(b) => b.to_string(),
@@ -139,6 +180,14 @@ fn value_to_string(value: &Value) -> String {
         Value::Keyword(k) => format!(":{}", k),
         Value::Nil => "nil".to_string(),
         Value::Function { .. } => "#<function>".to_string(),
+        Value::Atom(atom) => format!("#atom[{}]", value_to_string(&atom.value())),
+        Value::Symbol(s) => s.clone(),
+        Value::List(items) => format!("({})", items.iter().map(value_to_string).collect::<Vec<_>>().join(" ")),
+        // Sequences nested in other values are realized here; a failing one prints as a placeholder
+        Value::LazySeq(seq) => match seq.items() {
+            Ok(items) => value_to_string(&Value::List(items)),
+            Err(_) => "#<lazy-seq>".to_string(),
+        },
         Value::Vector(items) => {
             if items.is_empty() {
                 "[]".to_string()
@@ -154,6 +203,18 @@ fn value_to_string(value: &Value) -> String {
                 out
             }
         }
+        // Sorted collections render in their own order
+        Value::SortedSet(tree) => format!("#{{{}}}", tree.iter().map(|(member, _)| render_key(member)).collect::<Vec<_>>().join(" ")),
+        Value::SortedMap(tree) => format!(
+            "{{{}}}",
+            tree.iter().map(|(key, value)| format!("{} {}", render_key(key), value_to_string(value))).collect::<Vec<_>>().join(" ")
+        ),
+        // Records render their fields in declaration order, tagged with the type's name
+        Value::Record(record) => format!(
+            "#{}{{{}}}",
+            record.name(),
+            record.iter().map(|(field, value)| format!(":{} {}", field, value_to_string(value))).collect::<Vec<_>>().join(" ")
+        ),
         Value::Set(entries) => {
             if entries.is_empty() {
                 "#{}".to_string()
@@ -193,14 +254,18 @@ fn value_to_string(value: &Value) -> String {
     }
 }
 
-fn map_key_to_string(key: &MapKey) -> String {
+pub(super) fn map_key_to_string(key: &MapKey) -> String {
     match key {
-        MapKey::Number(n) => n.to_string(),
-        MapKey::Boolean(true) => "true".to_string(),
-        MapKey::Boolean(false) => "false".to_string(),
         MapKey::String(s) => format!("\"{}\"", s),
-        MapKey::Keyword(k) => format!(":{}", k),
-        MapKey::Nil => "nil".to_string(),
+        _ => value_to_string(&key.clone().into_value()),
+    }
+}
+
+/// A sorted collection's key, rendered like a hash map's
+fn render_key(key: &Value) -> String {
+    match key {
+        Value::String(s) => format!("\"{}\"", s),
+        _ => value_to_string(key),
     }
 }
 
@@ -216,7 +281,7 @@ fn resolve_default(default: Option<&Node>, env: &mut Environment) -> Result<Valu
 /// Converts arguments to strings and concatenates them
 pub fn eval_str(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
     let result: Result<String, EvalError> = args.iter().try_fold(String::new(), |mut acc, arg| {
-        let val = crate::evaluator::eval_with_env(arg, env)?;
+        let val = lazy::realize(crate::evaluator::eval_with_env(arg, env)?)?;
         acc.push_str(&value_to_string(&val));
         Ok(acc)
     });
@@ -233,10 +298,14 @@ pub fn eval_count(args: &[Node], env: &mut Environment) -> Result<Value, EvalErr
     match val {
         Value::String(s) => Ok(Value::Number(s.len() as isize)),
         Value::Vector(items) => Ok(Value::Number(items.len() as isize)),
+        Value::List(items) => Ok(Value::Number(items.len() as isize)),
+        Value::LazySeq(seq) => Ok(Value::Number(seq.items()?.len() as isize)),
         Value::Set(entries) => Ok(Value::Number(entries.len() as isize)),
         Value::Map(entries) => Ok(Value::Number(entries.len() as isize)),
+        Value::SortedMap(tree) | Value::SortedSet(tree) => Ok(Value::Number(tree.len() as isize)),
+        Value::Record(record) => Ok(Value::Number(record.len() as isize)),
         Value::Nil => Ok(Value::Number(0)),
-        _ => Err(EvalError::TypeError("count requires a string, vector, map, set, or nil argument".to_string())),
+        _ => Err(EvalError::TypeError("count requires a string, vector, list, map, set, or nil argument".to_string())),
     }
 }
 
@@ -277,6 +346,15 @@ pub fn eval_get(args: &[Node], env: &mut Environment) -> Result<Value, EvalError
                 resolve_default(default, env)
             }
         }
+        (Value::SortedMap(tree), key_value) => match tree.get(&key_value)? {
+            Some((_, found)) => Ok(found.clone()),
+            None => resolve_default(default, env),
+        },
+        (Value::Record(record), key_value) => match record.get(&MapKey::try_from_value(&key_value)?) {
+            Some(found) => Ok(found.clone()),
+            None => resolve_default(default, env),
+        },
+        (Value::Nil, _) => resolve_default(default, env),
         (Value::String(_), _) | (Value::Vector(_), _) => Err(EvalError::TypeError("get: index must be a number".to_string())),
         _ => Err(EvalError::TypeError("get: first argument must be a string, vector, or map".to_string())),
     }
@@ -342,8 +420,7 @@ pub fn eval_subs(args: &[Node], env: &mut Environment) -> Result<Value, EvalErro
         Value::Vector(items) => {
             let len = items.len();
             let (start, end) = compute_range(start_val, args, env, len)?;
-            let slice = items[start..end].to_vec();
-            Ok(Value::Vector(slice))
+            Ok(Value::Vector(items.iter().skip(start).take(end - start).cloned().collect()))
         }
         _ => Err(EvalError::TypeError("subs: first argument must be a string or vector".to_string())),
     }
@@ -351,13 +428,76 @@ pub fn eval_subs(args: &[Node], env: &mut Environment) -> Result<Value, EvalErro
 
 /// vec - Construct a vector from evaluated arguments
 pub fn eval_vec(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
-    let values: Result<Vec<Value>, EvalError> = args.iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect();
+    let values: Result<PersistentVector<Value>, EvalError> = args.iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect();
     Ok(Value::Vector(values?))
 }
 
+/// list - Construct a list from evaluated arguments
+pub fn eval_new_list(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let values: Result<Vec<Value>, EvalError> = args.iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect();
+    Ok(Value::List(values?))
+}
+
+/// cons - Prepend a value to the items of a collection, producing a list
+pub fn eval_cons(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("cons".to_string(), 2, args.len()));
+    }
+
+    let head = crate::evaluator::eval_with_env(&args[0], env)?;
+    let tail = crate::evaluator::eval_with_env(&args[1], env)?;
+    if let Value::LazySeq(_) = tail {
+        // Consing onto a lazy sequence keeps the tail unrealized
+        return Ok(LazySeq::realized(head, tail));
+    }
+    let tail = super::sequences::items(tail, "cons")?;
+    Ok(Value::List(std::iter::once(head).chain(tail).collect()))
+}
+
+/// first - First item of a collection (nil when empty)
+pub fn eval_first(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("first".to_string(), 1, args.len()));
+    }
+
+    let target = crate::evaluator::eval_with_env(&args[0], env)?;
+    Ok(lazy::first_and_rest(target, "first")?.map_or(Value::Nil, |(first, _)| first))
+}
+
+/// rest - Everything after the first item, as a (possibly empty) list or a lazy sequence
+pub fn eval_rest(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("rest".to_string(), 1, args.len()));
+    }
+
+    let target = crate::evaluator::eval_with_env(&args[0], env)?;
+    Ok(lazy::first_and_rest(target, "rest")?.map_or(Value::List(Vec::new()), |(_, rest)| rest))
+}
+
+pub fn eval_is_list(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("list?".to_string(), 1, args.len()));
+    }
+
+    let value = crate::evaluator::eval_with_env(&args[0], env)?;
+    Ok(Value::Boolean(matches!(value, Value::List(_))))
+}
+
+/// symbol - Make a symbol from a string (symbols are returned unchanged)
+pub fn eval_new_symbol(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("symbol".to_string(), 1, args.len()));
+    }
+
+    match crate::evaluator::eval_with_env(&args[0], env)? {
+        Value::String(name) | Value::Symbol(name) => Ok(Value::Symbol(name)),
+        _ => Err(EvalError::TypeError("symbol: argument must be a string or symbol".to_string())),
+    }
+}
+
 /// set - Construct a set from evaluated arguments (duplicates removed)
 pub fn eval_set(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
-    let entries: Result<HashSet<MapKey>, EvalError> = args
+    let entries: Result<PersistentSet<MapKey>, EvalError> = args
         .iter()
         .map(|arg| {
             let value = crate::evaluator::eval_with_env(arg, env)?;
@@ -372,7 +512,7 @@ pub fn eval_hash_map(args: &[Node], env: &mut Environment) -> Result<Value, Eval
         return Err(EvalError::InvalidOperation("hash-map requires key/value pairs".to_string()));
     }
 
-    let entries: Result<std::collections::HashMap<MapKey, Value>, EvalError> = args
+    let entries: Result<PersistentMap<MapKey, Value>, EvalError> = args
         .chunks(2)
         .map(|chunk| {
             let key_val = crate::evaluator::eval_with_env(&chunk[0], env)?;
@@ -393,23 +533,16 @@ pub fn eval_assoc(args: &[Node], env: &mut Environment) -> Result<Value, EvalErr
         return Err(EvalError::InvalidOperation("assoc expects key/value pairs".to_string()));
     }
 
-    let base = crate::evaluator::eval_with_env(&args[0], env)?;
-    let mut entries = match base {
-        Value::Map(map) => map,
-        Value::Nil => std::collections::HashMap::new(),
-        _ => return Err(EvalError::TypeError("assoc: first argument must be a map or nil".to_string())),
-    };
-
+    let mut result = crate::evaluator::eval_with_env(&args[0], env)?;
     let mut idx = 1usize;
     while idx < args.len() {
         let key_val = crate::evaluator::eval_with_env(&args[idx], env)?;
         let value_val = crate::evaluator::eval_with_env(&args[idx + 1], env)?;
-        let key = MapKey::try_from_value(&key_val)?;
-        entries.insert(key, value_val);
+        result = super::vectors::assoc_entry(result, key_val, value_val, "assoc")?;
         idx += 2;
     }
 
-    Ok(Value::Map(entries))
+    Ok(result)
 }
 
 pub fn eval_dissoc(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
@@ -420,15 +553,24 @@ pub fn eval_dissoc(args: &[Node], env: &mut Environment) -> Result<Value, EvalEr
     let base = crate::evaluator::eval_with_env(&args[0], env)?;
     if args.len() == 1 {
         return match base {
-            Value::Map(map) => Ok(Value::Map(map)),
-            Value::Nil => Ok(Value::Map(std::collections::HashMap::new())),
+            map @ (Value::Map(_) | Value::SortedMap(_) | Value::Record(_)) => Ok(map),
+            Value::Nil => Ok(Value::Map(PersistentMap::new())),
             _ => Err(EvalError::TypeError("dissoc: first argument must be a map or nil".to_string())),
         };
     }
 
+    if let Value::SortedMap(mut tree) = base {
+        for key_expr in &args[1..] {
+            tree.remove(&crate::evaluator::eval_with_env(key_expr, env)?)?;
+        }
+        return Ok(Value::SortedMap(tree));
+    }
+
+    // Removing a field leaves a hash map of the remaining entries
     let mut entries = match base {
         Value::Map(map) => map,
-        Value::Nil => std::collections::HashMap::new(),
+        Value::Record(record) => record.to_map(),
+        Value::Nil => PersistentMap::new(),
         _ => return Err(EvalError::TypeError("dissoc: first argument must be a map or nil".to_string())),
     };
 
@@ -450,15 +592,22 @@ pub fn eval_disj(args: &[Node], env: &mut Environment) -> Result<Value, EvalErro
     let base = crate::evaluator::eval_with_env(&args[0], env)?;
     if args.len() == 1 {
         return match base {
-            Value::Set(entries) => Ok(Value::Set(entries)),
-            Value::Nil => Ok(Value::Set(HashSet::new())),
+            set @ (Value::Set(_) | Value::SortedSet(_)) => Ok(set),
+            Value::Nil => Ok(Value::Set(PersistentSet::new())),
             _ => Err(EvalError::TypeError("disj: first argument must be a set or nil".to_string())),
         };
     }
 
+    if let Value::SortedSet(mut tree) = base {
+        for expr in &args[1..] {
+            tree.remove(&crate::evaluator::eval_with_env(expr, env)?)?;
+        }
+        return Ok(Value::SortedSet(tree));
+    }
+
     let mut entries = match base {
         Value::Set(entries) => entries,
-        Value::Nil => HashSet::with_capacity(args.len() - 1),
+        Value::Nil => PersistentSet::new(),
         _ => return Err(EvalError::TypeError("disj: first argument must be a set or nil".to_string())),
     };
 
@@ -489,6 +638,8 @@ pub fn eval_contains(args: &[Node], env: &mut Environment) -> Result<Value, Eval
             let key = MapKey::try_from_value(&key_val)?;
             Ok(Value::Boolean(entries.contains(&key)))
         }
+        Value::SortedMap(tree) | Value::SortedSet(tree) => Ok(Value::Boolean(tree.get(&key_val)?.is_some())),
+        Value::Record(record) => Ok(Value::Boolean(record.get(&MapKey::try_from_value(&key_val)?).is_some())),
         Value::Nil => Ok(Value::Boolean(false)),
         _ => Err(EvalError::TypeError("contains?: first argument must be a map, set, or nil".to_string())),
     }
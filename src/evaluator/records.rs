@@ -0,0 +1,137 @@
+use super::{Environment, EvalError, FunctionArity, MapKey, PersistentMap, Value};
+/// Records - defrecord and the ->Name constructors it declares
+///
+/// A record is a map whose keys are the keyword fields its type declares, holding one value per
+/// field in declaration order. Records go wherever maps do: lookups, `keys`, `merge`, `seq` and the
+/// rest see their fields as entries. `assoc` of a declared field keeps a record; any other update
+/// (a new key, `dissoc`) yields a hash map of the same entries. A record equals only records of the
+/// same type with equal fields, matching the compiled backend.
+use crate::ast::Node;
+use std::rc::Rc;
+
+/// Operator of the constructor bodies `defrecord` builds. No source symbol can spell it, so it
+/// never shadows a user's own functions.
+pub(super) const NEW_RECORD: &str = "new record";
+
+#[derive(Debug, PartialEq)]
+struct RecordLayout {
+    name: String,
+    fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    layout: Rc<RecordLayout>, // Shared with the records `assoc` derives from this one
+    values: Vec<Value>,       // One per field, in declaration order
+}
+
+impl Record {
+    pub fn name(&self) -> &str {
+        &self.layout.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn field_index(&self, key: &MapKey) -> Option<usize> {
+        match key {
+            MapKey::Keyword(name) => self.layout.fields.iter().position(|field| field == name),
+            _ => None,
+        }
+    }
+
+    /// The value of the field `key` names, if it names one
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.field_index(key).map(|index| &self.values[index])
+    }
+
+    /// Field names and values in declaration order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.layout.fields.iter().map(String::as_str).zip(&self.values)
+    }
+
+    /// The record with field `key` set to `value`, or None when `key` is not one of its fields
+    pub fn assoc(&self, key: &MapKey, value: Value) -> Option<Record> {
+        let index = self.field_index(key)?;
+        let mut updated = self.clone();
+        updated.values[index] = value;
+        Some(updated)
+    }
+
+    /// The hash map holding the record's entries
+    pub fn to_map(&self) -> PersistentMap<MapKey, Value> {
+        self.iter().map(|(field, value)| (MapKey::Keyword(field.to_string()), value.clone())).collect()
+    }
+}
+
+/// The hash map holding the same entries as a record; other values pass through
+pub(super) fn widen(value: Value) -> Value {
+    match value {
+        Value::Record(record) => Value::Map(record.to_map()),
+        other => other,
+    }
+}
+
+/// defrecord - Declare a record type and bind its `->Name` constructor, which takes one value
+/// per field in declaration order
+pub fn eval_defrecord(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let [Node::Symbol { value: name }, Node::Vector { root }] = args else {
+        return Err(EvalError::TypeError("defrecord expects a name and a vector of fields".to_string()));
+    };
+    let fields = root
+        .iter()
+        .map(|field| match field {
+            Node::Symbol { value } => Ok(value.clone()),
+            _ => Err(EvalError::TypeError(format!("defrecord {}: fields must be symbols", name))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The constructor body names the record and passes each field's parameter under its own name
+    let body = std::iter::once(NEW_RECORD)
+        .chain(std::iter::once(name.as_str()))
+        .chain(fields.iter().map(String::as_str))
+        .map(|value| Node::Symbol { value: value.to_string() })
+        .collect();
+    let constructor = format!("->{}", name);
+    let function = Value::Function {
+        name: None,
+        arities: vec![FunctionArity {
+            params: fields,
+            rest_param: None,
+            body: Box::new(Node::List { root: body }),
+        }],
+        closure: Environment::new(),
+    };
+    env.insert(constructor, function);
+    Ok(Value::Nil)
+}
+
+/// Build the record a constructor body describes from the field parameters bound in `env`
+pub fn eval_new_record(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let names = args
+        .iter()
+        .map(|arg| match arg {
+            Node::Symbol { value } => Ok(value.clone()),
+            _ => Err(EvalError::TypeError("record constructors take their fields by name".to_string())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some((name, fields)) = names.split_first() else {
+        return Err(EvalError::ArityError(NEW_RECORD.to_string(), 1, 0));
+    };
+    let values = fields
+        .iter()
+        .map(|field| crate::evaluator::eval_with_env(&Node::Symbol { value: field.clone() }, env))
+        .collect::<Result<_, _>>()?;
+    Ok(Value::Record(Record {
+        layout: Rc::new(RecordLayout {
+            name: name.clone(),
+            fields: fields.to_vec(),
+        }),
+        values,
+    }))
+}
@@ -0,0 +1,390 @@
+use super::{lazy, primitives, special_forms, vectors, Environment, EvalError, MapKey, PersistentVector, Value};
+/// Sequence library - map, filter, remove, reduce, range, into, every?, some, take, drop, concat,
+/// reverse, frequencies and group-by, and the seq functions seq, next, nth, last and empty?
+///
+/// Every collection is walked as a sequence of items: vector and list elements in order, set members
+/// and `[key value]` map entries in the order they print, the characters of a string, and nothing
+/// for nil. Functions that build a sequence return a list, except that map, filter, remove, take
+/// and drop over a lazy sequence return another lazy sequence.
+use crate::ast::Node;
+use std::collections::HashMap;
+
+/// Items of a collection in iteration order
+pub(super) fn items(value: Value, op_name: &str) -> Result<Vec<Value>, EvalError> {
+    match value {
+        Value::Vector(items) => Ok(items.to_vec()),
+        Value::List(items) => Ok(items),
+        Value::LazySeq(seq) => seq.items(),
+        Value::Nil => Ok(Vec::new()),
+        Value::String(s) => Ok(s.chars().map(|ch| Value::String(ch.to_string())).collect()),
+        Value::Set(entries) => {
+            let mut keys: Vec<MapKey> = entries.into_iter().collect();
+            keys.sort_by_key(primitives::map_key_to_string);
+            Ok(keys.into_iter().map(MapKey::into_value).collect())
+        }
+        Value::Map(entries) => {
+            let mut pairs: Vec<(MapKey, Value)> = entries.into_iter().collect();
+            pairs.sort_by_key(|(key, _)| primitives::map_key_to_string(key));
+            Ok(pairs.into_iter().map(|(key, value)| Value::Vector(vec![key.into_value(), value].into())).collect())
+        }
+        Value::SortedSet(tree) => Ok(tree.iter().map(|(member, _)| member.clone()).collect()),
+        Value::SortedMap(tree) => Ok(tree.iter().map(|(key, value)| Value::Vector(vec![key.clone(), value.clone()].into())).collect()),
+        Value::Record(record) => Ok(record
+            .iter()
+            .map(|(field, value)| Value::Vector(vec![Value::Keyword(field.to_string()), value.clone()].into()))
+            .collect()),
+        _ => Err(EvalError::TypeError(format!("{}: argument must be a collection, string, or nil", op_name))),
+    }
+}
+
+pub(super) fn eval_items(node: &Node, env: &mut Environment, op_name: &str) -> Result<Vec<Value>, EvalError> {
+    items(crate::evaluator::eval_with_env(node, env)?, op_name)
+}
+
+pub(super) fn eval_function(node: &Node, env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    match crate::evaluator::eval_with_env(node, env)? {
+        function @ (Value::Function { .. } | Value::Keyword(_) | Value::Map(_) | Value::Set(_) | Value::SortedMap(_) | Value::SortedSet(_) | Value::Record(_)) => Ok(function),
+        _ => Err(EvalError::TypeError(format!("{}: first argument must be a function", op_name))),
+    }
+}
+
+fn eval_count(node: &Node, env: &mut Environment, op_name: &str) -> Result<usize, EvalError> {
+    match crate::evaluator::eval_with_env(node, env)? {
+        Value::Number(n) => Ok(n.max(0) as usize),
+        _ => Err(EvalError::TypeError(format!("{}: count must be a number", op_name))),
+    }
+}
+
+/// map - Apply a function to every item
+pub fn eval_map(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("map".to_string(), 2, args.len()));
+    }
+
+    let function = eval_function(&args[0], env, "map")?;
+    let source = crate::evaluator::eval_with_env(&args[1], env)?;
+    if let Value::LazySeq(_) = source {
+        return Ok(lazy::map(function, source));
+    }
+    let results = items(source, "map")?
+        .into_iter()
+        .map(|item| special_forms::apply_function(function.clone(), vec![item]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::List(results))
+}
+
+/// filter / remove - Keep the items the predicate accepts (filter) or rejects (remove)
+pub fn eval_filter(args: &[Node], env: &mut Environment, keep: bool) -> Result<Value, EvalError> {
+    let op_name = if keep { "filter" } else { "remove" };
+    if args.len() != 2 {
+        return Err(EvalError::ArityError(op_name.to_string(), 2, args.len()));
+    }
+
+    let function = eval_function(&args[0], env, op_name)?;
+    let source = crate::evaluator::eval_with_env(&args[1], env)?;
+    if let Value::LazySeq(_) = source {
+        return Ok(lazy::filter(function, source, keep));
+    }
+    let mut kept = Vec::new();
+    for item in items(source, op_name)? {
+        let verdict = special_forms::apply_function(function.clone(), vec![item.clone()])?;
+        if primitives::is_truthy(&verdict) == keep {
+            kept.push(item);
+        }
+    }
+    Ok(Value::List(kept))
+}
+
+/// reduce - Fold the items with a two-argument function, from an initial value or the first item
+pub fn eval_reduce(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(EvalError::ArityError("reduce".to_string(), 3, args.len()));
+    }
+
+    let function = eval_function(&args[0], env, "reduce")?;
+    let (init, items) = if args.len() == 3 {
+        let init = crate::evaluator::eval_with_env(&args[1], env)?;
+        (Some(init), eval_items(&args[2], env, "reduce")?)
+    } else {
+        (None, eval_items(&args[1], env, "reduce")?)
+    };
+
+    let mut items = items.into_iter();
+    let Some(init) = init.or_else(|| items.next()) else {
+        // An empty collection without an initial value calls the function with no arguments
+        return special_forms::apply_function(function, Vec::new());
+    };
+    items.try_fold(init, |acc, item| special_forms::apply_function(function.clone(), vec![acc, item]))
+}
+
+/// every? - True when the predicate holds for every item
+pub fn eval_every(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("every?".to_string(), 2, args.len()));
+    }
+
+    let function = eval_function(&args[0], env, "every?")?;
+    for item in eval_items(&args[1], env, "every?")? {
+        if !primitives::is_truthy(&special_forms::apply_function(function.clone(), vec![item])?) {
+            return Ok(Value::Boolean(false));
+        }
+    }
+    Ok(Value::Boolean(true))
+}
+
+/// some - The first truthy predicate result, or nil
+pub fn eval_some(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("some".to_string(), 2, args.len()));
+    }
+
+    let function = eval_function(&args[0], env, "some")?;
+    for item in eval_items(&args[1], env, "some")? {
+        let result = special_forms::apply_function(function.clone(), vec![item])?;
+        if primitives::is_truthy(&result) {
+            return Ok(result);
+        }
+    }
+    Ok(Value::Nil)
+}
+
+/// range - Numbers from start (default 0) up to, but excluding, end by step (default 1); without
+/// arguments, a lazy sequence of every number from zero up
+pub fn eval_range(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.is_empty() {
+        return Ok(lazy::unbounded_range());
+    }
+    if args.len() > 3 {
+        return Err(EvalError::ArityError("range".to_string(), 1, args.len()));
+    }
+
+    let numbers = args
+        .iter()
+        .map(|arg| match crate::evaluator::eval_with_env(arg, env)? {
+            Value::Number(n) => Ok(n),
+            _ => Err(EvalError::TypeError("range: arguments must be numbers".to_string())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (start, end, step) = match numbers.as_slice() {
+        [end] => (0, *end, 1),
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (*start, *end, *step),
+        _ => unreachable!("range arity checked above"),
+    };
+    if step == 0 {
+        return Err(EvalError::InvalidOperation("range step must not be zero".to_string()));
+    }
+
+    let mut values = Vec::new();
+    let mut current = start;
+    while (step > 0 && current < end) || (step < 0 && current > end) {
+        values.push(Value::Number(current));
+        current = match current.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    Ok(Value::List(values))
+}
+
+/// into - Add the items of a collection to a vector (appended), list (prepended), set, or map
+pub fn eval_into(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("into".to_string(), 2, args.len()));
+    }
+
+    let target = crate::evaluator::eval_with_env(&args[0], env)?;
+    let items = eval_items(&args[1], env, "into")?;
+    match target {
+        Value::Vector(mut values) => {
+            values.extend(items);
+            Ok(Value::Vector(values))
+        }
+        Value::List(values) => Ok(Value::List(items.into_iter().rev().chain(values).collect())),
+        Value::Nil => Ok(Value::List(items.into_iter().rev().collect())),
+        Value::Set(mut entries) => {
+            for item in items {
+                entries.insert(MapKey::try_from_value(&item)?);
+            }
+            Ok(Value::Set(entries))
+        }
+        Value::Map(mut entries) => {
+            for item in items {
+                let (key, value) = map_entry(item)?;
+                entries.insert(MapKey::try_from_value(&key)?, value);
+            }
+            Ok(Value::Map(entries))
+        }
+        Value::SortedSet(mut tree) => {
+            for item in items {
+                tree.insert(item, Value::Nil)?;
+            }
+            Ok(Value::SortedSet(tree))
+        }
+        Value::SortedMap(mut tree) => {
+            for item in items {
+                let (key, value) = map_entry(item)?;
+                tree.insert(key, value)?;
+            }
+            Ok(Value::SortedMap(tree))
+        }
+        record @ Value::Record(_) => items.into_iter().try_fold(record, |target, item| {
+            let (key, value) = map_entry(item)?;
+            vectors::assoc_entry(target, key, value, "into")
+        }),
+        _ => Err(EvalError::TypeError("into: target must be a vector, list, set, map, or nil".to_string())),
+    }
+}
+
+/// take / drop - The first n items (take) or everything after them (drop)
+pub fn eval_take(args: &[Node], env: &mut Environment, from_front: bool) -> Result<Value, EvalError> {
+    let op_name = if from_front { "take" } else { "drop" };
+    if args.len() != 2 {
+        return Err(EvalError::ArityError(op_name.to_string(), 2, args.len()));
+    }
+
+    let count = eval_count(&args[0], env, op_name)?;
+    let source = crate::evaluator::eval_with_env(&args[1], env)?;
+    if let Value::LazySeq(_) = source {
+        return if from_front { Ok(lazy::take(count, source)) } else { lazy::drop(count, source) };
+    }
+    let items = items(source, op_name)?.into_iter();
+    if from_front {
+        Ok(Value::List(items.take(count).collect()))
+    } else {
+        Ok(Value::List(items.skip(count).collect()))
+    }
+}
+
+/// concat - The items of every argument in order
+pub fn eval_concat(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let mut values = Vec::new();
+    for arg in args {
+        values.extend(eval_items(arg, env, "concat")?);
+    }
+    Ok(Value::List(values))
+}
+
+/// reverse - The items of a collection in reverse order, as a list
+pub fn eval_reverse(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("reverse".to_string(), 1, args.len()));
+    }
+
+    let mut items = eval_items(&args[0], env, "reverse")?;
+    items.reverse();
+    Ok(Value::List(items))
+}
+
+/// frequencies - A map from each distinct item to the number of times it occurs
+pub fn eval_frequencies(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("frequencies".to_string(), 1, args.len()));
+    }
+
+    let mut counts: HashMap<MapKey, isize> = HashMap::new();
+    for item in eval_items(&args[0], env, "frequencies")? {
+        *counts.entry(MapKey::try_from_value(&item)?).or_insert(0) += 1;
+    }
+    Ok(Value::Map(counts.into_iter().map(|(key, count)| (key, Value::Number(count))).collect()))
+}
+
+/// group-by - A map from each function result to the vector of items producing it
+pub fn eval_group_by(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("group-by".to_string(), 2, args.len()));
+    }
+
+    let function = eval_function(&args[0], env, "group-by")?;
+    let mut groups: HashMap<MapKey, PersistentVector<Value>> = HashMap::new();
+    for item in eval_items(&args[1], env, "group-by")? {
+        let key = MapKey::try_from_value(&special_forms::apply_function(function.clone(), vec![item.clone()])?)?;
+        groups.entry(key).or_default().push(item);
+    }
+    Ok(Value::Map(groups.into_iter().map(|(key, group)| (key, Value::Vector(group))).collect()))
+}
+
+fn eval_single(args: &[Node], env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError(op_name.to_string(), 1, args.len()));
+    }
+    crate::evaluator::eval_with_env(&args[0], env)
+}
+
+/// seq - The items of a collection as a list (a lazy sequence stays lazy), or nil when there are none
+pub fn eval_seq(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let target = eval_single(args, env, "seq")?;
+    if let Value::LazySeq(_) = target {
+        return Ok(match lazy::first_and_rest(target.clone(), "seq")? {
+            Some(_) => target,
+            None => Value::Nil,
+        });
+    }
+    let items = items(target, "seq")?;
+    Ok(if items.is_empty() { Value::Nil } else { Value::List(items) })
+}
+
+/// next - Everything after the first item, or nil when nothing is left
+pub fn eval_next(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let target = eval_single(args, env, "next")?;
+    let Some((_, rest)) = lazy::first_and_rest(target, "next")? else {
+        return Ok(Value::Nil);
+    };
+    Ok(match lazy::first_and_rest(rest.clone(), "next")? {
+        Some(_) => rest,
+        None => Value::Nil,
+    })
+}
+
+/// nth - The item at an index; out of range gives the default, or an error without one. Lazy
+/// sequences are realized only up to the index.
+pub fn eval_nth(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(EvalError::ArityError("nth".to_string(), 2, args.len()));
+    }
+
+    let target = crate::evaluator::eval_with_env(&args[0], env)?;
+    let index = match crate::evaluator::eval_with_env(&args[1], env)? {
+        Value::Number(n) => n,
+        _ => return Err(EvalError::TypeError("nth: index must be a number".to_string())),
+    };
+    let default = args.get(2).map(|node| crate::evaluator::eval_with_env(node, env)).transpose()?;
+
+    let item = match (target, usize::try_from(index)) {
+        (_, Err(_)) => None,
+        (Value::LazySeq(seq), Ok(index)) => lazy::drop(index, Value::LazySeq(seq)).and_then(|rest| lazy::first_and_rest(rest, "nth"))?.map(|(item, _)| item),
+        (other, Ok(index)) => items(other, "nth")?.into_iter().nth(index),
+    };
+    item.or(default).ok_or_else(|| EvalError::InvalidOperation(format!("nth: index {} out of bounds", index)))
+}
+
+/// The key and value of a `[key value]` entry poured into a map
+fn map_entry(item: Value) -> Result<(Value, Value), EvalError> {
+    match item {
+        Value::Vector(pair) if pair.len() == 2 => Ok((pair[0].clone(), pair[1].clone())),
+        Value::List(pair) if pair.len() == 2 => {
+            let mut pair = pair.into_iter();
+            Ok((pair.next().unwrap_or(Value::Nil), pair.next().unwrap_or(Value::Nil)))
+        }
+        _ => Err(EvalError::TypeError("into a map needs [key value] entries".to_string())),
+    }
+}
+
+/// last - The final item (nil when there is none)
+pub fn eval_last(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let target = eval_single(args, env, "last")?;
+    Ok(items(target, "last")?.pop().unwrap_or(Value::Nil))
+}
+
+/// empty? - Whether a collection or string has no items; a lazy sequence realizes at most one
+pub fn eval_is_empty(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let empty = match eval_single(args, env, "empty?")? {
+        Value::String(s) => s.is_empty(),
+        Value::Map(entries) => entries.is_empty(),
+        Value::Set(entries) => entries.is_empty(),
+        Value::SortedMap(tree) | Value::SortedSet(tree) => tree.is_empty(),
+        Value::Record(record) => record.is_empty(),
+        target => lazy::first_and_rest(target, "empty?")?.is_none(),
+    };
+    Ok(Value::Boolean(empty))
+}
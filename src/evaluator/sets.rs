@@ -0,0 +1,81 @@
+use super::{primitives, sequences, sorted, special_forms, Environment, EvalError, MapKey, PersistentSet, Value};
+/// Set algebra - union, intersection, difference, subset?, superset? and select
+///
+/// Nil stands in for an empty set. A union keeps its first set's kind, so a union onto a sorted
+/// set stays sorted; the other functions build hash sets.
+use crate::ast::Node;
+
+fn eval_set_arg(node: &Node, env: &mut Environment, op_name: &str) -> Result<PersistentSet<MapKey>, EvalError> {
+    match crate::evaluator::eval_with_env(node, env)? {
+        Value::Set(members) => Ok(members),
+        Value::SortedSet(tree) => sorted::hash_members(&tree),
+        Value::Nil => Ok(PersistentSet::new()),
+        _ => Err(EvalError::TypeError(format!("{}: arguments must be sets or nil", op_name))),
+    }
+}
+
+/// union / intersection / difference - Fold the sets from the left; `(union)` is an empty set
+pub fn eval_set_operation(args: &[Node], env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    let Some((first, rest)) = args.split_first() else {
+        if op_name == "union" {
+            return Ok(Value::Set(PersistentSet::new()));
+        }
+        return Err(EvalError::ArityError(op_name.to_string(), 1, 0));
+    };
+
+    let first = crate::evaluator::eval_with_env(first, env)?;
+    if let (Value::SortedSet(tree), "union") = (&first, op_name) {
+        let mut tree = tree.clone();
+        for node in rest {
+            for member in eval_set_arg(node, env, op_name)? {
+                tree.insert(member.into_value(), Value::Nil)?;
+            }
+        }
+        return Ok(Value::SortedSet(tree));
+    }
+
+    let mut result = match first {
+        Value::Set(members) => members,
+        Value::SortedSet(tree) => sorted::hash_members(&tree)?,
+        Value::Nil => PersistentSet::new(),
+        _ => return Err(EvalError::TypeError(format!("{}: arguments must be sets or nil", op_name))),
+    };
+    for node in rest {
+        let other = eval_set_arg(node, env, op_name)?;
+        match op_name {
+            "union" => result.extend(other),
+            "intersection" => result.retain(|member| other.contains(member)),
+            _ => result.retain(|member| !other.contains(member)),
+        }
+    }
+    Ok(Value::Set(result))
+}
+
+/// subset? / superset? - Whether every member of one set is in the other
+pub fn eval_set_predicate(args: &[Node], env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError(op_name.to_string(), 2, args.len()));
+    }
+
+    let left = eval_set_arg(&args[0], env, op_name)?;
+    let right = eval_set_arg(&args[1], env, op_name)?;
+    let contained = if op_name == "subset?" { left.is_subset(&right) } else { left.is_superset(&right) };
+    Ok(Value::Boolean(contained))
+}
+
+/// select - The members of a set for which the predicate is truthy
+pub fn eval_select(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("select".to_string(), 2, args.len()));
+    }
+
+    let function = sequences::eval_function(&args[0], env, "select")?;
+    let mut selected = PersistentSet::new();
+    for member in eval_set_arg(&args[1], env, "select")? {
+        let verdict = special_forms::apply_function(function.clone(), vec![member.clone().into_value()])?;
+        if primitives::is_truthy(&verdict) {
+            selected.insert(member);
+        }
+    }
+    Ok(Value::Set(selected))
+}
@@ -0,0 +1,343 @@
+use super::{sequences, values, Environment, EvalError, MapKey, PersistentMap, PersistentSet, Value};
+/// Sorted collections - sorted-map, sorted-set, sorted-map-by, sorted-set-by, subseq and rsubseq
+///
+/// Sorted maps and sets are persistent AVL trees ordered by `compare`, or by a comparator function
+/// following sort's conventions. An update copies the path to the changed node and shares the
+/// rest, so clones are cheap and earlier versions stay intact. Keys must still be usable as map
+/// keys, which keeps the collections comparable and hashable alongside the unsorted ones; a set's
+/// members are stored as keys with nil values.
+use crate::ast::Node;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+type Link = Option<Rc<TreeNode>>;
+
+#[derive(Debug)]
+struct TreeNode {
+    entry: Rc<(Value, Value)>,
+    left: Link,
+    right: Link,
+    height: usize,
+    size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortedTree {
+    root: Link,
+    comparator: Option<Rc<Value>>,
+}
+
+fn height(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn size(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn create(entry: Rc<(Value, Value)>, left: Link, right: Link) -> Link {
+    Some(Rc::new(TreeNode {
+        height: height(&left).max(height(&right)) + 1,
+        size: size(&left) + size(&right) + 1,
+        entry,
+        left,
+        right,
+    }))
+}
+
+/// `create`, rotating to restore the balance when `left` and `right` differ by two levels
+fn balance(entry: Rc<(Value, Value)>, left: Link, right: Link) -> Link {
+    match (&left, &right) {
+        (Some(outer), _) if outer.height > height(&right) + 1 => match &outer.right {
+            Some(inner) if inner.height > height(&outer.left) => create(
+                inner.entry.clone(),
+                create(outer.entry.clone(), outer.left.clone(), inner.left.clone()),
+                create(entry, inner.right.clone(), right),
+            ),
+            _ => create(outer.entry.clone(), outer.left.clone(), create(entry, outer.right.clone(), right)),
+        },
+        (_, Some(outer)) if outer.height > height(&left) + 1 => match &outer.left {
+            Some(inner) if inner.height > height(&outer.right) => create(
+                inner.entry.clone(),
+                create(entry, left, inner.left.clone()),
+                create(outer.entry.clone(), inner.right.clone(), outer.right.clone()),
+            ),
+            _ => create(outer.entry.clone(), create(entry, left, outer.left.clone()), outer.right.clone()),
+        },
+        _ => create(entry, left, right),
+    }
+}
+
+/// Join two sibling trees, every key of `left` ordering before those of `right`
+fn join(left: Link, right: Link) -> Link {
+    match (&left, &right) {
+        (None, _) => right,
+        (_, None) => left,
+        (_, Some(node)) => {
+            let (first, rest) = remove_first(node);
+            balance(first, left, rest)
+        }
+    }
+}
+
+/// The first entry of a tree and the rest of it
+fn remove_first(node: &TreeNode) -> (Rc<(Value, Value)>, Link) {
+    match &node.left {
+        None => (node.entry.clone(), node.right.clone()),
+        Some(left) => {
+            let (first, rest) = remove_first(left);
+            (first, balance(node.entry.clone(), rest, node.right.clone()))
+        }
+    }
+}
+
+/// One end of a range query: the bound key and whether the range includes it
+pub(super) struct Bound {
+    key: Value,
+    inclusive: bool,
+}
+
+impl SortedTree {
+    pub(super) fn new(comparator: Option<Value>) -> Self {
+        SortedTree {
+            root: None,
+            comparator: comparator.map(Rc::new),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    fn compare(&self, left: &Value, right: &Value) -> Result<Ordering, EvalError> {
+        values::compare_with(self.comparator.as_deref(), left, right)
+    }
+
+    /// The entry stored under a key equal to `key`
+    pub(super) fn get(&self, key: &Value) -> Result<Option<&(Value, Value)>, EvalError> {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            link = match self.compare(key, &node.entry.0)? {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Ok(Some(&node.entry)),
+            };
+        }
+        Ok(None)
+    }
+
+    /// Set `key` to `value`; an equal key already present keeps its place and takes the new value
+    pub(super) fn insert(&mut self, key: Value, value: Value) -> Result<(), EvalError> {
+        MapKey::try_from_value(&key)?;
+        self.root = self.insert_into(&self.root, key, value)?;
+        Ok(())
+    }
+
+    fn insert_into(&self, link: &Link, key: Value, value: Value) -> Result<Link, EvalError> {
+        let Some(node) = link else {
+            return Ok(create(Rc::new((key, value)), None, None));
+        };
+        Ok(match self.compare(&key, &node.entry.0)? {
+            Ordering::Equal => create(Rc::new((node.entry.0.clone(), value)), node.left.clone(), node.right.clone()),
+            Ordering::Less => balance(node.entry.clone(), self.insert_into(&node.left, key, value)?, node.right.clone()),
+            Ordering::Greater => balance(node.entry.clone(), node.left.clone(), self.insert_into(&node.right, key, value)?),
+        })
+    }
+
+    /// Remove the entry stored under `key`, if there is one
+    pub(super) fn remove(&mut self, key: &Value) -> Result<(), EvalError> {
+        if let Some(root) = self.remove_from(&self.root, key)? {
+            self.root = root;
+        }
+        Ok(())
+    }
+
+    /// The tree without `key`, or None when it holds no such key
+    fn remove_from(&self, link: &Link, key: &Value) -> Result<Option<Link>, EvalError> {
+        let Some(node) = link else {
+            return Ok(None);
+        };
+        Ok(match self.compare(key, &node.entry.0)? {
+            Ordering::Equal => Some(join(node.left.clone(), node.right.clone())),
+            Ordering::Less => self.remove_from(&node.left, key)?.map(|left| balance(node.entry.clone(), left, node.right.clone())),
+            Ordering::Greater => self.remove_from(&node.right, key)?.map(|right| balance(node.entry.clone(), node.left.clone(), right)),
+        })
+    }
+
+    /// The entries in key order
+    pub fn iter(&self) -> Iter<'_> {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.descend(&self.root);
+        iter
+    }
+
+    /// The entries from `start` to `end`, in key order or against it
+    pub(super) fn range(&self, start: Option<&Bound>, end: Option<&Bound>, descending: bool) -> Result<Vec<(Value, Value)>, EvalError> {
+        let mut entries = Vec::new();
+        self.collect_range(&self.root, start, end, descending, &mut entries)?;
+        Ok(entries)
+    }
+
+    fn collect_range(&self, link: &Link, start: Option<&Bound>, end: Option<&Bound>, descending: bool, entries: &mut Vec<(Value, Value)>) -> Result<(), EvalError> {
+        let Some(node) = link else {
+            return Ok(());
+        };
+
+        let key = &node.entry.0;
+        let from_start = start.map(|bound| Ok::<_, EvalError>((self.compare(key, &bound.key)?, bound.inclusive))).transpose()?;
+        let to_end = end.map(|bound| Ok::<_, EvalError>((self.compare(key, &bound.key)?, bound.inclusive))).transpose()?;
+        // Keys before this one can only be in range when it lies past the start, and keys after it
+        // when it lies before the end
+        let earlier = from_start.is_none_or(|(ordering, _)| ordering.is_gt());
+        let later = to_end.is_none_or(|(ordering, _)| ordering.is_lt());
+        let inside = from_start.is_none_or(|(ordering, inclusive)| ordering.is_gt() || (inclusive && ordering.is_eq()))
+            && to_end.is_none_or(|(ordering, inclusive)| ordering.is_lt() || (inclusive && ordering.is_eq()));
+
+        let (first, first_open, second, second_open) = if descending {
+            (&node.right, later, &node.left, earlier)
+        } else {
+            (&node.left, earlier, &node.right, later)
+        };
+        if first_open {
+            self.collect_range(first, start, end, descending, entries)?;
+        }
+        if inside {
+            entries.push((key.clone(), node.entry.1.clone()));
+        }
+        if second_open {
+            self.collect_range(second, start, end, descending, entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sorted collections are equal when they hold equal entries, whatever their ordering
+impl PartialEq for SortedTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+/// In-order traversal of a `SortedTree`
+pub struct Iter<'a> {
+    stack: Vec<&'a TreeNode>,
+}
+
+impl<'a> Iter<'a> {
+    fn descend(&mut self, mut link: &'a Link) {
+        while let Some(node) = link {
+            self.stack.push(node);
+            link = &node.left;
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a (Value, Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.descend(&node.right);
+        Some(&node.entry)
+    }
+}
+
+/// The entries of a sorted map, keyed like a hash map's
+pub(super) fn hash_entries(tree: &SortedTree) -> Result<PersistentMap<MapKey, Value>, EvalError> {
+    tree.iter().map(|(key, value)| Ok((MapKey::try_from_value(key)?, value.clone()))).collect()
+}
+
+/// The members of a sorted set, keyed like a hash set's
+pub(super) fn hash_members(tree: &SortedTree) -> Result<PersistentSet<MapKey>, EvalError> {
+    tree.iter().map(|(member, _)| MapKey::try_from_value(member)).collect()
+}
+
+/// The hash map or set holding the same entries as a sorted collection; other values pass through
+pub(super) fn unsorted(value: Value) -> Result<Value, EvalError> {
+    match value {
+        Value::SortedMap(tree) => Ok(Value::Map(hash_entries(&tree)?)),
+        Value::SortedSet(tree) => Ok(Value::Set(hash_members(&tree)?)),
+        other => Ok(other),
+    }
+}
+
+/// sorted-map / sorted-set / sorted-map-by / sorted-set-by - Build a sorted collection from key
+/// and value pairs or members; the -by variants take a comparator first
+pub fn eval_sorted_collection(args: &[Node], env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    let (comparator, items) = match (op_name.ends_with("-by"), args.split_first()) {
+        (false, _) => (None, args),
+        (true, Some((comparator, items))) => (Some(sequences::eval_function(comparator, env, op_name)?), items),
+        (true, None) => return Err(EvalError::ArityError(op_name.to_string(), 1, 0)),
+    };
+
+    let mut tree = SortedTree::new(comparator);
+    if op_name.starts_with("sorted-map") {
+        if items.len() % 2 != 0 {
+            return Err(EvalError::InvalidOperation(format!("{} requires key/value pairs", op_name)));
+        }
+        for pair in items.chunks(2) {
+            let key = crate::evaluator::eval_with_env(&pair[0], env)?;
+            let value = crate::evaluator::eval_with_env(&pair[1], env)?;
+            tree.insert(key, value)?;
+        }
+        Ok(Value::SortedMap(tree))
+    } else {
+        for item in items {
+            tree.insert(crate::evaluator::eval_with_env(item, env)?, Value::Nil)?;
+        }
+        Ok(Value::SortedSet(tree))
+    }
+}
+
+/// Read a literal `<`, `<=`, `>` or `>=` test, returning whether it bounds the start of a range
+/// and whether the bound is inclusive
+fn bound_test(node: &Node, op_name: &str) -> Result<(bool, bool), EvalError> {
+    match node {
+        Node::Symbol { value } if value == ">" => Ok((true, false)),
+        Node::Symbol { value } if value == ">=" => Ok((true, true)),
+        Node::Symbol { value } if value == "<" => Ok((false, false)),
+        Node::Symbol { value } if value == "<=" => Ok((false, true)),
+        _ => Err(EvalError::TypeError(format!("{}: tests must be one of <, <=, > or >=", op_name))),
+    }
+}
+
+/// subseq / rsubseq - The entries of a sorted map (as [key value] vectors) or members of a sorted
+/// set within one or two bounds, ascending or descending
+pub fn eval_subseq(args: &[Node], env: &mut Environment, op_name: &str) -> Result<Value, EvalError> {
+    let (coll, tests) = match args {
+        [coll, test, key] => (coll, vec![(test, key)]),
+        [coll, start_test, start_key, end_test, end_key] => (coll, vec![(start_test, start_key), (end_test, end_key)]),
+        _ => return Err(EvalError::ArityError(op_name.to_string(), 5, args.len())),
+    };
+
+    let (mut start, mut end) = (None, None);
+    for (index, (test, key)) in tests.into_iter().enumerate() {
+        let (starts, inclusive) = bound_test(test, op_name)?;
+        if args.len() == 5 && starts != (index == 0) {
+            return Err(EvalError::TypeError(format!("{}: takes a > or >= start test and a < or <= end test", op_name)));
+        }
+        let bound = Bound {
+            key: crate::evaluator::eval_with_env(key, env)?,
+            inclusive,
+        };
+        *(if starts { &mut start } else { &mut end }) = Some(bound);
+    }
+
+    let descending = op_name == "rsubseq";
+    match crate::evaluator::eval_with_env(coll, env)? {
+        Value::SortedMap(tree) => {
+            let entries = tree.range(start.as_ref(), end.as_ref(), descending)?;
+            Ok(Value::List(entries.into_iter().map(|(key, value)| Value::Vector(vec![key, value].into())).collect()))
+        }
+        Value::SortedSet(tree) => {
+            let members = tree.range(start.as_ref(), end.as_ref(), descending)?;
+            Ok(Value::List(members.into_iter().map(|(member, _)| member).collect()))
+        }
+        Value::Nil => Ok(Value::List(Vec::new())),
+        _ => Err(EvalError::TypeError(format!("{} requires a sorted map or set", op_name))),
+    }
+}
@@ -1,15 +1,23 @@
-use super::{Environment, EvalError, Value};
-/// Special forms - if, let, fn, def, defn
-use crate::ast::Node;
+use super::{Environment, EvalError, FunctionArity, MapKey, Value};
+/// Special forms - if, case, let, fn, def, defn, plus the derived conditional and threading forms
+use crate::ast::{destructure, forms, Node};
 
-/// Evaluate if conditional
+/// Evaluate if conditional; a missing else branch evaluates to nil
 pub fn eval_if(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
-    if args.len() != 3 {
+    if args.len() != 2 && args.len() != 3 {
         return Err(EvalError::ArityError("if".to_string(), 3, args.len()));
     }
 
     let condition = crate::evaluator::eval_with_env(&args[0], env)?;
-    let is_truthy = match condition {
+    if is_truthy(condition) {
+        crate::evaluator::eval_with_env(&args[1], env)
+    } else {
+        args.get(2).map_or(Ok(Value::Nil), |otherwise| crate::evaluator::eval_with_env(otherwise, env))
+    }
+}
+
+fn is_truthy(condition: Value) -> bool {
+    match condition {
         Value::Boolean(b) => b,
         Value::Number(n) => n != 0,
         Value::Nil => false,
@@ -19,24 +27,50 @@ pub fn eval_if(args: &[Node], env: &mut Environment) -> Result<Value, EvalError>
         Value::Vector(items) => !items.is_empty(),
         Value::Set(entries) => !entries.is_empty(),
         Value::Map(entries) => !entries.is_empty(),
-    };
+        Value::SortedMap(tree) | Value::SortedSet(tree) => !tree.is_empty(),
+        Value::Record(record) => !record.is_empty(),
+        Value::List(items) => !items.is_empty(),
+        Value::LazySeq(_) => true, // Truthy without being realized
+        Value::Symbol(_) => true,
+        Value::Atom(_) => true,
+    }
+}
 
-    if is_truthy {
-        crate::evaluator::eval_with_env(&args[1], env)
-    } else {
-        crate::evaluator::eval_with_env(&args[2], env)
+/// Evaluate case: the first clause with a constant equal to the target wins
+pub fn eval_case(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let case = forms::case_clauses(args).map_err(EvalError::TypeError)?;
+    let target = crate::evaluator::eval_with_env(case.target, env)?;
+
+    for (constants, body) in &case.clauses {
+        for constant in constants {
+            // Constants are literals, so they evaluate without the surrounding bindings
+            if crate::evaluator::eval_with_env(constant, &mut Environment::new())? == target {
+                return crate::evaluator::eval_with_env(body, env);
+            }
+        }
+    }
+
+    match case.default {
+        Some(default) => crate::evaluator::eval_with_env(default, env),
+        None => crate::evaluator::eval_with_env(&forms::no_matching_clause(), env),
     }
 }
 
+/// Evaluate a derived form (cond, when, if-let, ->, ...) by expanding it into if/let
+pub fn eval_derived(head: &str, args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let expanded = forms::expand_form(head, args).map_err(EvalError::TypeError)?;
+    crate::evaluator::eval_with_env(&expanded, env)
+}
+
 /// Evaluate let binding expression
 pub fn eval_let(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
     if args.len() != 2 {
         return Err(EvalError::ArityError("let".to_string(), 2, args.len()));
     }
 
-    // Bindings format: [var1 val1 var2 val2 ...]
+    // Bindings format: [var1 val1 var2 val2 ...], with destructuring patterns expanded to plain symbols
     let bindings = match &args[0] {
-        Node::Vector { root } => root,
+        Node::Vector { root } => destructure::expand_let_bindings(root).map_err(EvalError::TypeError)?,
         _ => return Err(EvalError::TypeError("let requires a vector of bindings".to_string())),
     };
 
@@ -70,44 +104,152 @@ pub fn eval_fn(args: &[Node], env: &Environment) -> Result<Value, EvalError> {
         return Err(EvalError::ArityError("fn".to_string(), 2, args.len()));
     }
 
-    // Parameters format: [param1 param2 ...]
-    let params = match &args[0] {
-        Node::Vector { root } => root
-            .iter()
-            .map(|param_node| match param_node {
-                Node::Symbol { value } => Ok(value.clone()),
-                _ => Err(EvalError::TypeError("fn parameters must be symbols".to_string())),
-            })
-            .collect::<Result<Vec<_>, _>>()?,
-        _ => return Err(EvalError::TypeError("fn requires a vector of parameters".to_string())),
+    let (params_node, body) = destructure::expand_params(&args[0], &args[1]).map_err(EvalError::TypeError)?;
+    let (params, rest_param) = parse_params("fn", &params_node)?;
+    let body = Box::new(body);
+
+    Ok(Value::Function {
+        name: None,
+        arities: vec![FunctionArity { params, rest_param, body }],
+        closure: env.clone(),
+    })
+}
+
+/// Parse the `([params] body)` clauses of a multi-arity defn
+fn parse_arities(clauses: &[Node]) -> Result<Vec<FunctionArity>, EvalError> {
+    let mut arities: Vec<FunctionArity> = Vec::new();
+    for clause in clauses {
+        let (params, rest_param, body) = match clause {
+            Node::List { root } if root.len() == 2 => {
+                let (params_node, body) = destructure::expand_params(&root[0], &root[1]).map_err(EvalError::TypeError)?;
+                let (params, rest_param) = parse_params("defn", &params_node)?;
+                (params, rest_param, Box::new(body))
+            }
+            _ => return Err(EvalError::TypeError("defn arity must be a list of a parameter vector and a body".to_string())),
+        };
+
+        if arities.iter().any(|arity| arity.params.len() == params.len() && arity.rest_param.is_none() && rest_param.is_none()) {
+            return Err(EvalError::InvalidOperation(format!("defn has more than one arity taking {} arguments", params.len())));
+        }
+        if rest_param.is_some() && arities.iter().any(|arity| arity.rest_param.is_some()) {
+            return Err(EvalError::InvalidOperation("defn can have at most one variadic arity".to_string()));
+        }
+        arities.push(FunctionArity { params, rest_param, body });
+    }
+
+    if let Some(variadic) = arities.iter().find(|arity| arity.rest_param.is_some()) {
+        if arities.iter().any(|arity| arity.rest_param.is_none() && arity.params.len() > variadic.params.len()) {
+            return Err(EvalError::InvalidOperation("defn fixed arity cannot take more parameters than the variadic arity".to_string()));
+        }
+    }
+
+    Ok(arities)
+}
+
+/// Pick the arity matching `arg_count`, preferring an exact fixed arity over the variadic one
+fn select_arity(arities: &[FunctionArity], arg_count: usize) -> Option<&FunctionArity> {
+    arities
+        .iter()
+        .find(|arity| arity.rest_param.is_none() && arity.params.len() == arg_count)
+        .or_else(|| arities.iter().find(|arity| arity.rest_param.is_some() && arity.params.len() <= arg_count))
+}
+
+/// Parse a parameter vector of the form [param1 param2 ... & rest]
+fn parse_params(form: &str, node: &Node) -> Result<(Vec<String>, Option<String>), EvalError> {
+    let root = match node {
+        Node::Vector { root } => root,
+        _ => return Err(EvalError::TypeError(format!("{} requires a vector of parameters", form))),
     };
 
-    let body = Box::new(args[1].clone());
+    let mut params = Vec::new();
+    let mut iter = root.iter();
+    while let Some(param_node) = iter.next() {
+        match param_node {
+            Node::Symbol { value } if value == "&" => {
+                return match (iter.next(), iter.next()) {
+                    (Some(Node::Symbol { value }), None) if value != "&" => Ok((params, Some(value.clone()))),
+                    _ => Err(EvalError::TypeError(format!("{} & must be followed by exactly one parameter symbol", form))),
+                };
+            }
+            Node::Symbol { value } => params.push(value.clone()),
+            _ => return Err(EvalError::TypeError(format!("{} parameters must be symbols", form))),
+        }
+    }
 
-    Ok(Value::Function { params, body, closure: env.clone() })
+    Ok((params, None))
 }
 
 /// Evaluate function call
 pub fn eval_function_call(func_value: Value, args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let arg_values = args.iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect::<Result<Vec<_>, _>>()?;
+    apply_function(func_value, arg_values)
+}
+
+/// Call a function value with already evaluated arguments
+pub fn apply_function(func_value: Value, args: Vec<Value>) -> Result<Value, EvalError> {
     match func_value {
-        Value::Function { params, body, closure } => {
-            if args.len() != params.len() {
-                return Err(EvalError::ArityError("function call".to_string(), params.len(), args.len()));
-            }
+        Value::Function { name, arities, closure } => {
+            let FunctionArity { params, rest_param, body } = match select_arity(&arities, args.len()) {
+                Some(arity) => arity.clone(),
+                None => {
+                    // Report the closest declared arity as the expected count
+                    let expected = arities.iter().map(|arity| arity.params.len()).min_by_key(|count| count.abs_diff(args.len())).unwrap_or(0);
+                    return Err(EvalError::ArityError("function call".to_string(), expected, args.len()));
+                }
+            };
 
             let mut func_env = closure;
-            params.iter().zip(args.iter()).try_for_each(|(param, arg)| {
-                let arg_value = crate::evaluator::eval_with_env(arg, env)?;
-                func_env.insert(param.clone(), arg_value);
-                Ok::<(), EvalError>(())
-            })?;
+            if let Some(name) = name {
+                let self_value = Value::Function {
+                    name: Some(name.clone()),
+                    arities,
+                    closure: func_env.clone(),
+                };
+                func_env.insert(name, self_value);
+            }
+
+            let mut args = args.into_iter();
+            for param in &params {
+                if let Some(arg) = args.next() {
+                    func_env.insert(param.clone(), arg);
+                }
+            }
+            if let Some(rest_name) = rest_param {
+                func_env.insert(rest_name, Value::Vector(args.collect()));
+            }
 
             crate::evaluator::eval_with_env(&body, &mut func_env)
         }
+        collection @ (Value::Keyword(_) | Value::Map(_) | Value::Set(_) | Value::SortedMap(_) | Value::SortedSet(_) | Value::Record(_)) => apply_lookup(collection, args),
         _ => Err(EvalError::TypeError("Cannot call non-function value".to_string())),
     }
 }
 
+/// Call a keyword, map, record or set: `(:k m d?)` and `(m k d?)` look the key up in the map, and
+/// `(s x d?)` returns `x` when the set contains it; a miss yields the default or nil
+fn apply_lookup(collection: Value, args: Vec<Value>) -> Result<Value, EvalError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(EvalError::ArityError("collection lookup".to_string(), 1, args.len()));
+    }
+    let mut args = args.into_iter();
+    let argument = args.next().unwrap_or(Value::Nil);
+    let default = args.next().unwrap_or(Value::Nil);
+
+    let found = match (collection, argument) {
+        (Value::Keyword(key), Value::Map(entries)) => entries.get(&MapKey::Keyword(key)).cloned(),
+        (Value::Keyword(key), Value::SortedMap(tree)) => tree.get(&Value::Keyword(key))?.map(|(_, value)| value.clone()),
+        (Value::Keyword(key), Value::Record(record)) => record.get(&MapKey::Keyword(key)).cloned(),
+        (Value::Keyword(_), _) => None,
+        (Value::Record(record), key) => record.get(&MapKey::try_from_value(&key)?).cloned(),
+        (Value::SortedMap(tree), key) => tree.get(&key)?.map(|(_, value)| value.clone()),
+        (Value::SortedSet(tree), member) => tree.get(&member)?.map(|(member, _)| member.clone()),
+        (Value::Map(entries), key) => entries.get(&MapKey::try_from_value(&key)?).cloned(),
+        (Value::Set(members), member) => members.contains(&MapKey::try_from_value(&member)?).then_some(member),
+        _ => None,
+    };
+    Ok(found.unwrap_or(default))
+}
+
 /// Evaluate def (variable definition)
 pub fn eval_def(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
     if args.len() != 2 {
@@ -130,39 +272,47 @@ pub fn eval_def(args: &[Node], env: &mut Environment) -> Result<Value, EvalError
 
 /// Evaluate defn (named function definition)
 pub fn eval_defn(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
-    if args.len() < 3 {
+    if args.len() < 3 && !matches!(args.get(1), Some(Node::List { .. })) {
         return Err(EvalError::ArityError("defn".to_string(), 3, args.len()));
     }
 
-    let _name = match &args[0] {
-        Node::Symbol { value } => value,
+    let name = match &args[0] {
+        Node::Symbol { value } => value.clone(),
         _ => return Err(EvalError::TypeError("defn requires a symbol as first argument".to_string())),
     };
 
-    // Parameters format: [param1 param2 ...]
-    let params = match &args[1] {
-        Node::Vector { root } => root
-            .iter()
-            .map(|param_node| match param_node {
-                Node::Symbol { value } => Ok(value.clone()),
-                _ => Err(EvalError::TypeError("defn parameters must be symbols".to_string())),
-            })
-            .collect::<Result<Vec<_>, _>>()?,
-        _ => return Err(EvalError::TypeError("defn requires a vector of parameters".to_string())),
-    };
+    let arities = function_arities(&args[1..])?;
 
-    let body = if args.len() == 3 {
-        Box::new(args[2].clone())
-    } else {
-        // TODO: Multiple body expressions - wrap in an implicit do
-        return Err(EvalError::InvalidOperation("Multiple body expressions not supported yet".to_string()));
+    let func_value = Value::Function {
+        name: Some(name.clone()),
+        arities,
+        closure: env.clone(),
     };
 
-    let func_value = Value::Function { params, body, closure: env.clone() };
-
-    if let Node::Symbol { value: name } = &args[0] {
-        env.insert(name.clone(), func_value.clone());
-    }
+    env.insert(name, func_value.clone());
 
     Ok(func_value)
 }
+
+/// Parse the arities of a defn after its name: either `[params] body` or one or more
+/// `([params] body)` clauses
+pub(super) fn function_arities(clauses: &[Node]) -> Result<Vec<FunctionArity>, EvalError> {
+    if matches!(clauses.first(), Some(Node::List { .. })) {
+        // (defn name ([params] body) ([params] body) ...)
+        parse_arities(clauses)
+    } else {
+        let body = if clauses.len() == 2 {
+            &clauses[1]
+        } else {
+            // TODO: Multiple body expressions - wrap in an implicit do
+            return Err(EvalError::InvalidOperation("Multiple body expressions not supported yet".to_string()));
+        };
+        let (params_node, body) = destructure::expand_params(&clauses[0], body).map_err(EvalError::TypeError)?;
+        let (params, rest_param) = parse_params("defn", &params_node)?;
+        Ok(vec![FunctionArity {
+            params,
+            rest_param,
+            body: Box::new(body),
+        }])
+    }
+}
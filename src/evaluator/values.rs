@@ -0,0 +1,241 @@
+use super::{lazy, sequences, sorted, special_forms, Environment, EvalError, Value};
+/// Structural hashing and ordering - hash, compare, sort and sort-by
+///
+/// Both follow the compiled runtime's algorithm (`_value_hash`, `_value_compare`) and share its
+/// hash helpers, so a program hashes and sorts values the same way under either backend.
+/// Lazy sequences are realized and treated as lists.
+///
+/// Sorting is a stable merge sort. A comparator returning a boolean says whether its first argument
+/// goes first; one returning a number puts the first argument first when it is negative.
+use crate::ast::Node;
+use slisp_runtime::{hash_entry, hash_ordered, hash_scalar, string_hash_bytes, tag_rank};
+use std::cmp::Ordering;
+
+// Runtime tags, as laid out by the compiled backend
+const TAG_NIL: u8 = 0;
+const TAG_NUMBER: u8 = 1;
+const TAG_BOOLEAN: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_VECTOR: u8 = 4;
+const TAG_MAP: u8 = 5;
+const TAG_KEYWORD: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_LIST: u8 = 8;
+const TAG_SYMBOL: u8 = 9;
+
+fn runtime_tag(value: &Value, op_name: &str) -> Result<u8, EvalError> {
+    match value {
+        Value::Nil => Ok(TAG_NIL),
+        Value::Number(_) => Ok(TAG_NUMBER),
+        Value::Boolean(_) => Ok(TAG_BOOLEAN),
+        Value::String(_) => Ok(TAG_STRING),
+        Value::Vector(_) => Ok(TAG_VECTOR),
+        Value::Map(_) | Value::SortedMap(_) | Value::Record(_) => Ok(TAG_MAP),
+        Value::Keyword(_) => Ok(TAG_KEYWORD),
+        Value::Set(_) | Value::SortedSet(_) => Ok(TAG_SET),
+        Value::List(_) | Value::LazySeq(_) => Ok(TAG_LIST),
+        Value::Symbol(_) => Ok(TAG_SYMBOL),
+        Value::Function { .. } => Err(EvalError::TypeError(format!("{}: functions have no structural value", op_name))),
+        Value::Atom(_) => Err(EvalError::TypeError(format!("{}: atoms have no structural value", op_name))),
+    }
+}
+
+/// Structural hash of a value; equal values hash the same
+pub fn value_hash(value: &Value) -> Result<u64, EvalError> {
+    let tag = runtime_tag(value, "hash")?;
+    let hash = match value {
+        Value::Nil => hash_scalar(tag, 0),
+        Value::Number(n) => hash_scalar(tag, *n as i64 as u64),
+        Value::Boolean(flag) => hash_scalar(tag, *flag as u64),
+        Value::String(text) | Value::Symbol(text) => hash_scalar(tag, string_hash_bytes(text.as_bytes())),
+        // Compiled keywords keep their leading colon
+        Value::Keyword(name) => hash_scalar(tag, string_hash_bytes(format!(":{}", name).as_bytes())),
+        Value::Vector(items) => hash_items(tag, items.iter())?,
+        Value::List(items) => hash_items(tag, items.iter())?,
+        Value::LazySeq(seq) => value_hash(&Value::List(seq.items()?))?,
+        Value::Set(members) => members.iter().try_fold(hash_scalar(tag, members.len() as u64), |hash, member| {
+            Ok::<_, EvalError>(hash.wrapping_add(value_hash(&member.clone().into_value())?))
+        })?,
+        Value::Map(entries) => entries.iter().try_fold(hash_scalar(tag, entries.len() as u64), |hash, (key, entry)| {
+            let key = value_hash(&key.clone().into_value())?;
+            Ok::<_, EvalError>(hash.wrapping_add(hash_entry(key, value_hash(entry)?)))
+        })?,
+        Value::SortedSet(tree) => tree
+            .iter()
+            .try_fold(hash_scalar(tag, tree.len() as u64), |hash, (member, _)| Ok::<_, EvalError>(hash.wrapping_add(value_hash(member)?)))?,
+        Value::SortedMap(tree) => tree.iter().try_fold(hash_scalar(tag, tree.len() as u64), |hash, (key, entry)| {
+            Ok::<_, EvalError>(hash.wrapping_add(hash_entry(value_hash(key)?, value_hash(entry)?)))
+        })?,
+        Value::Record(record) => value_hash(&Value::Map(record.to_map()))?,
+        Value::Function { .. } | Value::Atom(_) => unreachable!("functions and atoms are rejected by runtime_tag"),
+    };
+    Ok(hash)
+}
+
+/// Hash of a vector's or list's items, in order
+fn hash_items<'a>(tag: u8, mut items: impl ExactSizeIterator<Item = &'a Value>) -> Result<u64, EvalError> {
+    let len = items.len() as u64;
+    items.try_fold(hash_scalar(tag, len), |hash, item| Ok(hash_ordered(hash, value_hash(item)?)))
+}
+
+/// Compare sequences element by element, a shorter prefix first
+fn compare_items<'a>(mut left: impl Iterator<Item = &'a Value>, mut right: impl Iterator<Item = &'a Value>) -> Result<Ordering, EvalError> {
+    loop {
+        match (left.next(), right.next()) {
+            (Some(left_item), Some(right_item)) => {
+                let ordering = value_compare(left_item, right_item)?;
+                if ordering.is_ne() {
+                    return Ok(ordering);
+                }
+            }
+            (left_item, right_item) => return Ok(left_item.is_some().cmp(&right_item.is_some())),
+        }
+    }
+}
+
+/// Total order over values: kinds in a fixed order (nil, booleans, numbers, strings, keywords,
+/// symbols, lists, vectors, maps, sets), then by value. Sequences compare element by element with
+/// a shorter prefix first; unequal maps and sets order by size, then by hash.
+pub fn value_compare(left: &Value, right: &Value) -> Result<Ordering, EvalError> {
+    let (left_tag, right_tag) = (runtime_tag(left, "compare")?, runtime_tag(right, "compare")?);
+    if left_tag != right_tag {
+        return Ok(tag_rank(left_tag).cmp(&tag_rank(right_tag)));
+    }
+
+    let ordering = match (left, right) {
+        (Value::LazySeq(_), _) | (_, Value::LazySeq(_)) => value_compare(&lazy::realize(left.clone())?, &lazy::realize(right.clone())?)?,
+        (Value::Number(a), Value::Number(b)) => a.cmp(b),
+        (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+        (Value::String(a), Value::String(b)) | (Value::Keyword(a), Value::Keyword(b)) | (Value::Symbol(a), Value::Symbol(b)) => a.cmp(b),
+        (Value::Vector(a), Value::Vector(b)) => compare_items(a.iter(), b.iter())?,
+        (Value::List(a), Value::List(b)) => compare_items(a.iter(), b.iter())?,
+        // Maps and sets share a tag whether sorted or not
+        _ if tag_of_collection(left_tag) => {
+            if sorted::unsorted(left.clone())? == sorted::unsorted(right.clone())? {
+                Ordering::Equal
+            } else {
+                collection_len(left)
+                    .cmp(&collection_len(right))
+                    .then(value_hash(left)?.cmp(&value_hash(right)?))
+                    .then(Ordering::Greater)
+            }
+        }
+        _ => Ordering::Equal,
+    };
+    Ok(ordering)
+}
+
+fn tag_of_collection(tag: u8) -> bool {
+    tag == TAG_MAP || tag == TAG_SET
+}
+
+fn collection_len(value: &Value) -> usize {
+    match value {
+        Value::Map(entries) => entries.len(),
+        Value::Set(members) => members.len(),
+        Value::SortedMap(tree) | Value::SortedSet(tree) => tree.len(),
+        Value::Record(record) => record.len(),
+        _ => 0,
+    }
+}
+
+/// compare - -1, 0 or 1 as the first value orders before, with or after the second
+pub fn eval_compare(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 {
+        return Err(EvalError::ArityError("compare".to_string(), 2, args.len()));
+    }
+
+    let left = crate::evaluator::eval_with_env(&args[0], env)?;
+    let right = crate::evaluator::eval_with_env(&args[1], env)?;
+    Ok(Value::Number(value_compare(&left, &right)? as isize))
+}
+
+/// hash - Structural hash that agrees with `=`
+pub fn eval_hash(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("hash".to_string(), 1, args.len()));
+    }
+
+    let value = crate::evaluator::eval_with_env(&args[0], env)?;
+    Ok(Value::Number(value_hash(&value)? as i64 as isize))
+}
+
+/// Whether `left` goes ahead of `right` under a comparator function, or `compare` without one
+fn sorts_before(comparator: Option<&Value>, left: &Value, right: &Value) -> Result<bool, EvalError> {
+    let Some(comparator) = comparator else {
+        return Ok(value_compare(left, right)?.is_lt());
+    };
+    match special_forms::apply_function(comparator.clone(), vec![left.clone(), right.clone()])? {
+        Value::Boolean(first) => Ok(first),
+        Value::Number(order) => Ok(order < 0),
+        _ => Err(EvalError::TypeError("comparator must return a boolean or a number".to_string())),
+    }
+}
+
+/// Order two keys of a sorted collection by its comparator, or by `compare` without one. A boolean
+/// comparator is asked twice when its first answer is false, to tell equal keys from later ones.
+pub fn compare_with(comparator: Option<&Value>, left: &Value, right: &Value) -> Result<Ordering, EvalError> {
+    let Some(comparator) = comparator else {
+        return value_compare(left, right);
+    };
+    match special_forms::apply_function(comparator.clone(), vec![left.clone(), right.clone()])? {
+        Value::Boolean(true) => Ok(Ordering::Less),
+        Value::Boolean(false) if sorts_before(Some(comparator), right, left)? => Ok(Ordering::Greater),
+        Value::Boolean(false) => Ok(Ordering::Equal),
+        Value::Number(order) => Ok(order.cmp(&0)),
+        _ => Err(EvalError::TypeError("comparator must return a boolean or a number".to_string())),
+    }
+}
+
+/// Stable merge sort of entries by the value `key` picks out of each
+fn merge_sort<T>(mut entries: Vec<T>, key: &impl Fn(&T) -> &Value, comparator: Option<&Value>) -> Result<Vec<T>, EvalError> {
+    if entries.len() <= 1 {
+        return Ok(entries);
+    }
+
+    let right = entries.split_off(entries.len() / 2);
+    let mut left = merge_sort(entries, key, comparator)?.into_iter().peekable();
+    let mut right = merge_sort(right, key, comparator)?.into_iter().peekable();
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(left_entry), Some(right_entry)) = (left.peek(), right.peek()) {
+        // Only a strictly earlier right entry overtakes, which keeps equal keys in order
+        let next = if sorts_before(comparator, key(right_entry), key(left_entry))? {
+            right.next()
+        } else {
+            left.next()
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// sort - The items in ascending `compare` order, or by a comparator, as a list
+pub fn eval_sort(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let (comparator, coll) = match args {
+        [coll] => (None, coll),
+        [comparator, coll] => (Some(sequences::eval_function(comparator, env, "sort")?), coll),
+        _ => return Err(EvalError::ArityError("sort".to_string(), 2, args.len())),
+    };
+
+    let items = sequences::eval_items(coll, env, "sort")?;
+    Ok(Value::List(merge_sort(items, &|item| item, comparator.as_ref())?))
+}
+
+/// sort-by - The items ordered by the results of a key function, compared like sort does
+pub fn eval_sort_by(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    let (key_function, comparator, coll) = match args {
+        [key_function, coll] => (key_function, None, coll),
+        [key_function, comparator, coll] => (key_function, Some(sequences::eval_function(comparator, env, "sort-by")?), coll),
+        _ => return Err(EvalError::ArityError("sort-by".to_string(), 3, args.len())),
+    };
+
+    let key_function = sequences::eval_function(key_function, env, "sort-by")?;
+    let entries = sequences::eval_items(coll, env, "sort-by")?
+        .into_iter()
+        .map(|item| Ok((special_forms::apply_function(key_function.clone(), vec![item.clone()])?, item)))
+        .collect::<Result<Vec<_>, EvalError>>()?;
+    let sorted = merge_sort(entries, &|(key, _): &(Value, Value)| key, comparator.as_ref())?;
+    Ok(Value::List(sorted.into_iter().map(|(_, item)| item).collect()))
+}
@@ -0,0 +1,161 @@
+use super::{sequences, Environment, EvalError, MapKey, PersistentMap, PersistentVector, Value};
+/// Vector operations - conj, peek, pop, assoc by index and subvec
+///
+/// `conj`, `peek` and `pop` work at the efficient end of each collection: the back of a vector and
+/// the front of a list; `conj` also adds members to a set. Indexes handed to `assoc` and `subvec` must lie within the vector,
+/// except that assoc at the length appends.
+use crate::ast::Node;
+
+fn eval_index(value: Value, op_name: &str) -> Result<isize, EvalError> {
+    match value {
+        Value::Number(n) => Ok(n),
+        _ => Err(EvalError::TypeError(format!("{}: index must be a number", op_name))),
+    }
+}
+
+fn out_of_bounds(op_name: &str, index: isize) -> EvalError {
+    EvalError::InvalidOperation(format!("{}: index {} out of bounds", op_name, index))
+}
+
+/// Replace the item at an index of a vector, appending when the index equals its length
+fn assoc_index(mut items: PersistentVector<Value>, index: isize, value: Value, op_name: &str) -> Result<PersistentVector<Value>, EvalError> {
+    match usize::try_from(index) {
+        Ok(position) if position < items.len() => items.set(position, value),
+        Ok(position) if position == items.len() => items.push(value),
+        _ => return Err(out_of_bounds(op_name, index)),
+    }
+    Ok(items)
+}
+
+/// Associate one key with a value in a map, record, nil or vector
+pub(super) fn assoc_entry(base: Value, key: Value, value: Value, op_name: &str) -> Result<Value, EvalError> {
+    match base {
+        Value::Map(mut entries) => {
+            entries.insert(MapKey::try_from_value(&key)?, value);
+            Ok(Value::Map(entries))
+        }
+        Value::SortedMap(mut tree) => {
+            tree.insert(key, value)?;
+            Ok(Value::SortedMap(tree))
+        }
+        // A declared field keeps the record; any other key widens it to a hash map
+        Value::Record(record) => {
+            let map_key = MapKey::try_from_value(&key)?;
+            match record.assoc(&map_key, value.clone()) {
+                Some(updated) => Ok(Value::Record(updated)),
+                None => {
+                    let mut entries = record.to_map();
+                    entries.insert(map_key, value);
+                    Ok(Value::Map(entries))
+                }
+            }
+        }
+        Value::Nil => {
+            let mut entries = PersistentMap::new();
+            entries.insert(MapKey::try_from_value(&key)?, value);
+            Ok(Value::Map(entries))
+        }
+        Value::Vector(items) => {
+            let index = eval_index(key, op_name)?;
+            Ok(Value::Vector(assoc_index(items, index, value, op_name)?))
+        }
+        _ => Err(EvalError::TypeError(format!("{}: first argument must be a map, vector, or nil", op_name))),
+    }
+}
+
+/// conj - Add items at the natural end: the back of a vector, the front of a list or nil, or into a set
+pub fn eval_conj(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.is_empty() {
+        return Err(EvalError::ArityError("conj".to_string(), 1, 0));
+    }
+
+    let target = crate::evaluator::eval_with_env(&args[0], env)?;
+    let values = args[1..].iter().map(|arg| crate::evaluator::eval_with_env(arg, env)).collect::<Result<Vec<_>, _>>()?;
+    match target {
+        Value::Vector(mut items) => {
+            items.extend(values);
+            Ok(Value::Vector(items))
+        }
+        Value::List(_) | Value::LazySeq(_) | Value::Nil => {
+            let mut items = sequences::items(target, "conj")?;
+            for value in values {
+                items.insert(0, value);
+            }
+            Ok(Value::List(items))
+        }
+        Value::Set(mut members) => {
+            for value in values {
+                members.insert(MapKey::try_from_value(&value)?);
+            }
+            Ok(Value::Set(members))
+        }
+        Value::SortedSet(mut tree) => {
+            for value in values {
+                tree.insert(value, Value::Nil)?;
+            }
+            Ok(Value::SortedSet(tree))
+        }
+        _ => Err(EvalError::TypeError("conj: first argument must be a vector, list, set, or nil".to_string())),
+    }
+}
+
+/// peek - The last item of a vector or first item of a list (nil when empty)
+pub fn eval_peek(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("peek".to_string(), 1, args.len()));
+    }
+
+    match crate::evaluator::eval_with_env(&args[0], env)? {
+        Value::Vector(items) => Ok(items.last().cloned().unwrap_or(Value::Nil)),
+        Value::List(items) => Ok(items.into_iter().next().unwrap_or(Value::Nil)),
+        Value::Nil => Ok(Value::Nil),
+        _ => Err(EvalError::TypeError("peek: argument must be a vector, list, or nil".to_string())),
+    }
+}
+
+/// pop - A vector without its last item or a list without its first; empty collections are an error
+pub fn eval_pop(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 1 {
+        return Err(EvalError::ArityError("pop".to_string(), 1, args.len()));
+    }
+
+    match crate::evaluator::eval_with_env(&args[0], env)? {
+        Value::Vector(items) if items.is_empty() => Err(EvalError::InvalidOperation("Can't pop empty vector".to_string())),
+        Value::List(items) if items.is_empty() => Err(EvalError::InvalidOperation("Can't pop empty list".to_string())),
+        Value::Vector(mut items) => {
+            items.pop();
+            Ok(Value::Vector(items))
+        }
+        Value::List(mut items) => {
+            items.remove(0);
+            Ok(Value::List(items))
+        }
+        Value::Nil => Ok(Value::Nil),
+        _ => Err(EvalError::TypeError("pop: argument must be a vector, list, or nil".to_string())),
+    }
+}
+
+/// subvec - The items of a vector from start up to (not including) end
+pub fn eval_subvec(args: &[Node], env: &mut Environment) -> Result<Value, EvalError> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(EvalError::ArityError("subvec".to_string(), 2, args.len()));
+    }
+
+    let items = match crate::evaluator::eval_with_env(&args[0], env)? {
+        Value::Vector(items) => items,
+        _ => return Err(EvalError::TypeError("subvec: first argument must be a vector".to_string())),
+    };
+    let start = eval_index(crate::evaluator::eval_with_env(&args[1], env)?, "subvec")?;
+    let end = match args.get(2) {
+        Some(node) => eval_index(crate::evaluator::eval_with_env(node, env)?, "subvec")?,
+        None => items.len() as isize,
+    };
+
+    if start < 0 || start > items.len() as isize {
+        return Err(out_of_bounds("subvec", start));
+    }
+    if end < start || end > items.len() as isize {
+        return Err(out_of_bounds("subvec", end));
+    }
+    Ok(Value::Vector(items.iter().skip(start as usize).take((end - start) as usize).cloned().collect()))
+}
@@ -5,11 +5,14 @@ pub enum IRInstruction {
     Push(i64),         // Push immediate value
     PushString(usize), // Push string address (index into string table)
 
-    // Arithmetic operations
-    Add, // Pop two values, push sum
-    Sub, // Pop two values, push difference (second - first)
-    Mul, // Pop two values, push product
-    Div, // Pop two values, push quotient (second / first)
+    // Arithmetic operations - overflow and division by zero raise an arithmetic error
+    Add,          // Pop two values, push sum
+    Sub,          // Pop two values, push difference (second - first)
+    Mul,          // Pop two values, push product
+    Div,          // Pop two values, push quotient (second / first)
+    UncheckedAdd, // Pop two values, push sum (wraps on overflow)
+    UncheckedSub, // Pop two values, push difference (wraps on overflow)
+    UncheckedMul, // Pop two values, push product (wraps on overflow)
 
     // Comparison operations
     Equal,        // Pop two values, push 1 if equal, 0 otherwise
@@ -22,8 +25,13 @@ pub enum IRInstruction {
     Not, // Pop one value, push logical NOT
 
     // Control flow
-    JumpIfZero(usize), // Jump to instruction index if top of stack is 0
-    Jump(usize),       // Unconditional jump to instruction index
+    JumpIfZero(usize),     // Jump to instruction index if top of stack is 0
+    Jump(usize),           // Unconditional jump to instruction index
+    JumpTable(Vec<usize>), // Pop an index and jump to its entry; out-of-range indices fall through
+
+    // Exception handling
+    PushHandler(usize), // Install a handler whose landing (instruction index) receives the thrown value
+    PopHandler,         // Remove the innermost handler
 
     // Variable operations
     StoreLocal(usize),       // Pop value and store in local variable slot
@@ -34,6 +42,7 @@ pub enum IRInstruction {
     DefineFunction(String, usize, usize), // (name, param_count, start_address)
     Call(String, usize),                  // (function_name, arg_count)
     LoadParam(usize),                     // Load parameter from current frame
+    PushFunction(String),                 // Push the address of a compiled function (for callbacks)
 
     // Memory allocation
     InitHeap,                            // Initialize heap (mmap syscall to get memory region)
@@ -41,6 +50,7 @@ pub enum IRInstruction {
     Free,                                // Pop address from stack and free it
     FreeLocal(usize),                    // Free local variable at slot without affecting stack
     FreeLocalWithRuntime(usize, String), // Free local slot by calling a specific runtime helper
+    LoadField(usize),                    // Pop an address, push the word stored at that byte offset from it
 
     // Runtime function calls
     RuntimeCall(String, usize), // (function_name, arg_count) - Call a runtime support function
@@ -64,6 +74,7 @@ pub struct IRProgram {
     pub entry_point: Option<String>,  // Name of the main function
     pub string_literals: Vec<String>, // String literals in the program
     pub telemetry_enabled: bool,
+    pub warnings: Vec<String>, // Compile-time warnings, such as unreachable match clauses
 }
 
 impl IRProgram {
@@ -74,6 +85,7 @@ impl IRProgram {
             entry_point: None,
             string_literals: Vec::new(),
             telemetry_enabled: false,
+            warnings: Vec::new(),
         }
     }
 
@@ -99,10 +99,18 @@ fn resolve_runtime_symbol(addresses: &RuntimeAddresses, symbol: &str) -> Option<
         "_string_from_number" => addresses.string_from_number,
         "_string_from_boolean" => addresses.string_from_boolean,
         "_string_equals" => addresses.string_equals,
+        "_string_hash" => addresses.string_hash,
         "_map_value_clone" => addresses.map_value_clone,
         "_map_free" => addresses.map_free,
         "_set_free" => addresses.set_free,
         "_vector_free" => addresses.vector_free,
+        "_exception_push_handler" => addresses.exception_push_handler,
+        "_exception_pop_handler" => addresses.exception_pop_handler,
+        "_exception_throw" => addresses.exception_throw,
+        "_exception_is_info" => addresses.exception_is_info,
+        "_exception_value_clone" => addresses.exception_value_clone,
+        "_exception_free" => addresses.exception_free,
+        "_arithmetic_error" => addresses.arithmetic_error,
         _ => None,
     }
 }
@@ -103,6 +103,9 @@ fn parse_compile_and_execute(input: &str, options: &ReplOptions) -> Result<Execu
         Ok(program) => program,
         Err(error) => return Err(format_compile_error(&error)),
     };
+    for warning in &ir_program.warnings {
+        eprintln!("Warning: {}", warning);
+    }
 
     let target = detect_host_target();
     let artifact = compile_to_executable(&ir_program, target);
@@ -128,8 +131,15 @@ fn format_value(value: &Value) -> String {
             }
         }
         Value::Nil => "nil".to_string(),
-        Value::Function { params, .. } => {
-            format!("#<function/{}>", params.len())
+        Value::Function { arities, .. } => {
+            let counts: Vec<String> = arities
+                .iter()
+                .map(|arity| match arity.rest_param {
+                    Some(_) => format!("{}+", arity.params.len()),
+                    None => arity.params.len().to_string(),
+                })
+                .collect();
+            format!("#<function/{}>", counts.join(","))
         }
         Value::Vector(items) => {
             let mut parts = Vec::with_capacity(items.len());
@@ -138,8 +148,18 @@ fn format_value(value: &Value) -> String {
             }
             format!("[{}]", parts.join(" "))
         }
+        Value::List(items) => {
+            let parts: Vec<String> = items.iter().map(format_value).collect();
+            format!("({})", parts.join(" "))
+        }
+        Value::LazySeq(seq) => match seq.items() {
+            Ok(items) => format_value(&Value::List(items)),
+            Err(_) => "#<lazy-seq>".to_string(),
+        },
+        Value::Symbol(s) => s.clone(),
         Value::Keyword(k) => format!(":{}", k),
         Value::String(s) => format!("\"{}\"", s),
+        Value::Atom(atom) => format!("#atom[{}]", format_value(&atom.value())),
         Value::Set(entries) => {
             if entries.is_empty() {
                 "#{}".to_string()
@@ -149,6 +169,16 @@ fn format_value(value: &Value) -> String {
                 format!("#{{{}}}", members.join(" "))
             }
         }
+        Value::SortedSet(tree) => format!("#{{{}}}", tree.iter().map(|(member, _)| format_value(member)).collect::<Vec<_>>().join(" ")),
+        Value::SortedMap(tree) => format!(
+            "{{{}}}",
+            tree.iter().map(|(key, value)| format!("{} {}", format_value(key), format_value(value))).collect::<Vec<_>>().join(" ")
+        ),
+        Value::Record(record) => format!(
+            "#{}{{{}}}",
+            record.name(),
+            record.iter().map(|(field, value)| format!(":{} {}", field, format_value(value))).collect::<Vec<_>>().join(" ")
+        ),
         Value::Map(entries) => {
             if entries.is_empty() {
                 "{}".to_string()
@@ -163,14 +193,7 @@ fn format_value(value: &Value) -> String {
 }
 
 fn format_map_key(key: &MapKey) -> String {
-    match key {
-        MapKey::Number(n) => n.to_string(),
-        MapKey::Boolean(true) => "true".to_string(),
-        MapKey::Boolean(false) => "false".to_string(),
-        MapKey::String(s) => format!("\"{}\"", s),
-        MapKey::Keyword(k) => format!(":{}", k),
-        MapKey::Nil => "nil".to_string(),
-    }
+    format_value(&key.clone().into_value())
 }
 
 fn format_error(error: &EvalError) -> String {
@@ -181,6 +204,21 @@ fn format_error(error: &EvalError) -> String {
             format!("Arity error in '{}': expected {} arguments, got {}", op, expected, actual)
         }
         EvalError::TypeError(msg) => format!("Type error: {}", msg),
+        EvalError::ArithmeticError(msg) => format!("Arithmetic error: {}", msg),
+        EvalError::Thrown(value) => format_uncaught(value),
+    }
+}
+
+fn format_uncaught(value: &Value) -> String {
+    let message_key = MapKey::Keyword("message".to_string());
+    let data_key = MapKey::Keyword("data".to_string());
+    match value {
+        Value::Map(entries) => match (entries.get(&message_key), entries.get(&data_key)) {
+            (Some(Value::String(msg)), Some(data)) => format!("Uncaught exception: {} {}", msg, format_value(data)),
+            (Some(Value::String(msg)), None) => format!("Uncaught exception: {}", msg),
+            _ => format!("Uncaught exception: {}", format_value(value)),
+        },
+        _ => format!("Uncaught exception: {}", format_value(value)),
     }
 }
 
@@ -8,6 +8,17 @@ const MAP_ANONYMOUS: usize = 0x20;
 const SYS_MMAP: isize = 9;
 const ALLOCATED_BIT: u64 = 0x8000_0000_0000_0000;
 const HEADER_SIZE: usize = 8;
+/// The low half of a block header holds its size; the bits between it and `ALLOCATED_BIT` hold a
+/// label the rest of the runtime may attach to an allocated block, which `_free` clears
+const SIZE_MASK: u64 = 0xFFFF_FFFF;
+const LABEL_SHIFT: u32 = 32;
+const LABEL_MASK: u64 = 0x7FFF_FFFF;
+/// Freed blocks up to this size wait in a list of their own size, so a later request for the same
+/// size reuses one whole instead of carving up a larger block
+const BINNED_LIMIT: usize = 512;
+const BIN_COUNT: usize = BINNED_LIMIT / 8;
+/// The most blocks the heap holds at once, each at least a header and 8 bytes
+pub(crate) const MAX_BLOCKS: usize = HEAP_SIZE / (HEADER_SIZE + 8);
 
 #[repr(C)]
 struct FreeBlock {
@@ -18,6 +29,7 @@ struct FreeBlock {
 static mut HEAP_BASE: *mut u8 = null_mut();
 static mut HEAP_END: *mut u8 = null_mut();
 static mut FREE_LIST_HEAD: *mut FreeBlock = null_mut();
+static mut BINS: [*mut FreeBlock; BIN_COUNT] = [null_mut(); BIN_COUNT];
 static mut HEAP_INITIALIZED: bool = false;
 
 #[inline(always)]
@@ -80,11 +92,18 @@ pub extern "C" fn _allocate(size: u64) -> *mut u8 {
         }
 
         let needed = align_up_8(size as usize);
+        if needed <= BINNED_LIMIT {
+            let binned = take_binned(needed);
+            if !binned.is_null() {
+                return binned;
+            }
+        }
+
         let mut prev: *mut FreeBlock = null_mut();
         let mut current = FREE_LIST_HEAD;
 
         while !current.is_null() {
-            let block_size = (*current).size & !ALLOCATED_BIT;
+            let block_size = (*current).size & SIZE_MASK;
             if block_size >= needed as u64 {
                 let remaining = block_size - needed as u64;
 
@@ -104,6 +123,7 @@ pub extern "C" fn _allocate(size: u64) -> *mut u8 {
                     (*current).next = null_mut();
 
                     let user_ptr = (current as *mut u8).add(HEADER_SIZE);
+                    crate::exceptions::track_alloc(user_ptr);
                     #[cfg(feature = "telemetry")]
                     crate::telemetry::record_alloc(user_ptr, needed as u64);
                     return user_ptr;
@@ -120,6 +140,7 @@ pub extern "C" fn _allocate(size: u64) -> *mut u8 {
                     (*current).next = null_mut();
 
                     let user_ptr = (current as *mut u8).add(HEADER_SIZE);
+                    crate::exceptions::track_alloc(user_ptr);
                     #[cfg(feature = "telemetry")]
                     crate::telemetry::record_alloc(user_ptr, needed as u64);
                     return user_ptr;
@@ -130,10 +151,42 @@ pub extern "C" fn _allocate(size: u64) -> *mut u8 {
             current = (*current).next;
         }
 
+        // As a last resort, serve the request from a binned block of a larger size
+        let mut larger = needed + 8;
+        while larger <= BINNED_LIMIT {
+            let binned = take_binned(larger);
+            if !binned.is_null() {
+                return binned;
+            }
+            larger += 8;
+        }
+
         null_mut()
     }
 }
 
+#[inline(always)]
+fn bin_index(size: usize) -> usize {
+    size / 8 - 1
+}
+
+/// Hand out a freed block of exactly `size` bytes, or null when none is waiting
+unsafe fn take_binned(size: usize) -> *mut u8 {
+    let block = BINS[bin_index(size)];
+    if block.is_null() {
+        return null_mut();
+    }
+    BINS[bin_index(size)] = (*block).next;
+    (*block).size |= ALLOCATED_BIT;
+    (*block).next = null_mut();
+
+    let user_ptr = (block as *mut u8).add(HEADER_SIZE);
+    crate::exceptions::track_alloc(user_ptr);
+    #[cfg(feature = "telemetry")]
+    crate::telemetry::record_alloc(user_ptr, size as u64);
+    user_ptr
+}
+
 /// # Safety
 ///
 /// The caller must ensure that `ptr` either points to memory previously allocated by
@@ -150,11 +203,53 @@ pub unsafe extern "C" fn _free(ptr: *mut u8) {
     }
 
     let block_ptr = ptr.sub(HEADER_SIZE) as *mut FreeBlock;
-    let size = (*block_ptr).size & !ALLOCATED_BIT;
+    let size = (*block_ptr).size & SIZE_MASK;
     (*block_ptr).size = size;
-    (*block_ptr).next = FREE_LIST_HEAD;
-    FREE_LIST_HEAD = block_ptr;
+    if size as usize <= BINNED_LIMIT {
+        (*block_ptr).next = BINS[bin_index(size as usize)];
+        BINS[bin_index(size as usize)] = block_ptr;
+    } else {
+        (*block_ptr).next = FREE_LIST_HEAD;
+        FREE_LIST_HEAD = block_ptr;
+    }
 
     #[cfg(feature = "telemetry")]
     crate::telemetry::record_free(ptr, size);
 }
+
+#[inline(always)]
+unsafe fn header(ptr: *const u8) -> *mut u64 {
+    ptr.sub(HEADER_SIZE) as *mut u64
+}
+
+/// Whether `ptr` is a block `_allocate` handed out and `_free` has not taken back yet
+pub(crate) unsafe fn is_allocated(ptr: *const u8) -> bool {
+    ptr > HEAP_BASE as *const u8 && ptr < HEAP_END as *const u8 && (ptr as usize) % 8 == 0 && *header(ptr) & ALLOCATED_BIT != 0
+}
+
+/// The label of the allocated block at `ptr`, 0 until one is set
+#[inline]
+pub(crate) unsafe fn block_label(ptr: *const u8) -> u32 {
+    ((*header(ptr) >> LABEL_SHIFT) & LABEL_MASK) as u32
+}
+
+/// Replace the label of the allocated block at `ptr`; only the low 31 bits are kept
+#[inline]
+pub(crate) unsafe fn set_block_label(ptr: *const u8, label: u32) {
+    let header = header(ptr);
+    *header = (*header & !(LABEL_MASK << LABEL_SHIFT)) | ((label as u64 & LABEL_MASK) << LABEL_SHIFT);
+}
+
+/// Visit every allocated block in address order. Blocks tile the heap, each header giving the
+/// size of the block after it, and `visit` may free any block, including the one it is given.
+pub(crate) unsafe fn for_each_block(mut visit: impl FnMut(*mut u8)) {
+    let mut block = HEAP_BASE;
+    while !block.is_null() && block < HEAP_END {
+        let size = *(block as *const u64);
+        let next = block.add(HEADER_SIZE + (size & SIZE_MASK) as usize);
+        if size & ALLOCATED_BIT != 0 {
+            visit(block.add(HEADER_SIZE));
+        }
+        block = next;
+    }
+}
@@ -0,0 +1,293 @@
+use core::arch::asm;
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+use crate::exceptions::{adopt_block, adopt_value, mark_block, value_deep_clone, Part, BLOCK_ATOM};
+use crate::map::{map_entry, map_mark_owning};
+use crate::sequence::{discard_result, release_value, ItemBuffer};
+use crate::sorted::is_sorted;
+use crate::value::values_equal;
+use crate::vector::{vector_element, vector_mark_owning};
+use crate::{_allocate, _free, _map_count, _map_value_clone, _vector_count};
+
+// Atoms back `atom`, `deref`, `reset!`, `swap!`, `compare-and-set!` and `add-watch`. An atom is a
+// reference-counted heap cell that owns its current value: storing a value clones it into the
+// cell and releases the value it replaces, and reading one hands the caller its own clone, so a
+// later `reset!` never frees a value the program still holds. Compiled collections may borrow
+// their heap elements from the scope that built them, so these clones are deep and own every
+// level. Compiled code treats `_atom_retain` as the clone and `_atom_free` as the release of an
+// atom, like a lazy sequence.
+//
+// Watches are compiled functions of key, atom, old value and new value, called after every change
+// with the values borrowed. The atom owns a clone of each watch key; adding a watch under a key it
+// already has replaces that watch.
+//
+// An atom may be older than the `try` that changes it, so what it stores is adopted into its
+// exception epoch and outlives the unwinding of that `try`.
+
+const TAG_VECTOR: u8 = 4;
+const TAG_MAP: u8 = 5;
+const TAG_SET: u8 = 7;
+const TAG_LIST: u8 = 8;
+
+struct Atom {
+    refs: u64,
+    value: i64,
+    tag: i64,
+    // Watch keys, owned by the atom, with their tags
+    keys: ItemBuffer,
+    // Watch functions with the `result_mode` of their results, parallel to `keys`
+    watches: ItemBuffer,
+}
+
+/// A clone of `value` that owns everything reachable from it and is released like any other value.
+/// Sorted collections cannot own their entries, so they are shared as `_map_value_clone` does.
+unsafe fn owned_clone(value: i64, tag: i64) -> i64 {
+    let tag = (tag & 0xff) as u8;
+    let collection = match tag {
+        TAG_VECTOR | TAG_LIST => true,
+        TAG_MAP | TAG_SET => !is_sorted(value as *const u8),
+        _ => false,
+    };
+    if value == 0 || !collection {
+        return _map_value_clone(value, tag as i64);
+    }
+
+    let copy = value_deep_clone(value, tag);
+    mark_owning(copy, tag);
+    copy
+}
+
+/// Mark a deep clone, and each collection inside it, as owning its elements.
+unsafe fn mark_owning(value: i64, tag: u8) {
+    if value == 0 {
+        return;
+    }
+
+    match tag {
+        TAG_VECTOR | TAG_LIST => {
+            let mut index = 0usize;
+            while index < _vector_count(value as *const u8) as usize {
+                let (element, element_tag) = vector_element(value as *const u8, index);
+                mark_owning(element, element_tag);
+                index += 1;
+            }
+            vector_mark_owning(value as *mut u8);
+        }
+        TAG_MAP | TAG_SET if !is_sorted(value as *const u8) => {
+            let mut index = 0usize;
+            while index < _map_count(value as *const u8) as usize {
+                let (key, key_tag, entry, entry_tag) = map_entry(value as *const u8, index);
+                mark_owning(key, key_tag);
+                mark_owning(entry, entry_tag);
+                index += 1;
+            }
+            map_mark_owning(value as *mut u8);
+        }
+        _ => {}
+    }
+}
+
+/// Call a compiled watch. Compiled code uses rbx as scratch, so it is saved around the call.
+#[inline(never)]
+unsafe fn invoke_watch(function: i64, key: i64, atom: i64, old: i64, new: i64) -> i64 {
+    let result: i64;
+    asm!(
+        "push rbx",
+        "sub rsp, 8",
+        "call {function}",
+        "add rsp, 8",
+        "pop rbx",
+        function = in(reg) function,
+        in("rdi") key,
+        in("rsi") atom,
+        in("rdx") old,
+        in("rcx") new,
+        lateout("rax") result,
+        clobber_abi("C"),
+    );
+    result
+}
+
+/// Store a clone of `value` in the atom, call the watches, then release the value it replaced.
+unsafe fn replace(atom: *mut Atom, value: i64, tag: i64) {
+    let (old, old_tag) = ((*atom).value, (*atom).tag);
+    (*atom).value = owned_clone(value, tag);
+    (*atom).tag = tag;
+    adopt_value(atom as *const u8, (*atom).value, tag as u8);
+
+    let mut index = 0usize;
+    while index < (*atom).watches.len() {
+        let (key, _) = (*atom).keys.get(index);
+        let (function, result_mode) = (*atom).watches.get(index);
+        // The watch may change the atom again, so it sees its own clone of the new value
+        let new = owned_clone(value, tag);
+        let result = invoke_watch(function, key, atom as i64, old, new);
+        discard_result(result, result_mode);
+        release_value(new, tag as u8);
+        index += 1;
+    }
+
+    release_value(old, old_tag as u8);
+}
+
+/// A new atom holding a clone of `value`.
+///
+/// # Safety
+///
+/// A heap tag requires `value` to be null or point to a managed value of that kind.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_create(value: i64, tag: i64) -> *mut u8 {
+    let atom = _allocate(size_of::<Atom>() as u64) as *mut Atom;
+    if atom.is_null() {
+        return null_mut();
+    }
+    mark_block(atom as *const u8, BLOCK_ATOM);
+    atom.write(Atom {
+        refs: 1,
+        value: owned_clone(value, tag),
+        tag,
+        keys: ItemBuffer::new(),
+        watches: ItemBuffer::new(),
+    });
+    atom as *mut u8
+}
+
+/// Visit the value an atom holds, its watch keys and the blocks keeping its watches.
+///
+/// # Safety
+///
+/// `atom` must be an atom.
+pub(crate) unsafe fn atom_visit_parts(atom: *const u8, visit: &mut dyn FnMut(Part) -> bool) {
+    let state = atom as *const Atom;
+    visit(Part::Value((*state).value, (*state).tag as u8));
+    for index in 0..(*state).keys.len() {
+        let (key, key_tag) = (*state).keys.get(index);
+        visit(Part::Value(key, key_tag as u8));
+    }
+    for block in (*state).keys.blocks().into_iter().chain((*state).watches.blocks()) {
+        visit(Part::Block(block));
+    }
+}
+
+/// Take another reference to an atom, returning it.
+///
+/// # Safety
+///
+/// `atom` must be null or an atom that has not been released.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_retain(atom: *mut u8) -> *mut u8 {
+    if !atom.is_null() {
+        (*(atom as *mut Atom)).refs += 1;
+    }
+    atom
+}
+
+/// Release a reference to an atom; the last one frees its value and watch keys.
+///
+/// # Safety
+///
+/// `atom` must be null or an atom; the caller's reference must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_free(atom: *mut u8) {
+    if atom.is_null() {
+        return;
+    }
+
+    let state = atom as *mut Atom;
+    (*state).refs -= 1;
+    if (*state).refs > 0 {
+        return;
+    }
+
+    release_value((*state).value, (*state).tag as u8);
+    let keys = core::ptr::read(&(*state).keys);
+    let mut index = 0usize;
+    while index < keys.len() {
+        let (key, key_tag) = keys.get(index);
+        release_value(key, key_tag as u8);
+        index += 1;
+    }
+    keys.release();
+    core::ptr::read(&(*state).watches).release();
+    _free(atom);
+}
+
+/// The current value of an atom, cloned so the caller owns heap values; nil for a null atom.
+///
+/// # Safety
+///
+/// `atom` must be null or an atom.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_deref(atom: *mut u8) -> i64 {
+    let state = atom as *mut Atom;
+    if state.is_null() {
+        return 0;
+    }
+    owned_clone((*state).value, (*state).tag)
+}
+
+/// Store a clone of `value` in an atom and call its watches, returning another clone of `value`
+/// that the caller owns.
+///
+/// # Safety
+///
+/// `atom` must be null or an atom, and a heap tag requires `value` to be null or point to a
+/// managed value of that kind.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_reset(atom: *mut u8, value: i64, tag: i64) -> i64 {
+    if !atom.is_null() {
+        replace(atom as *mut Atom, value, tag);
+    }
+    owned_clone(value, tag)
+}
+
+/// Store a clone of `value` in an atom only if its current value equals `expected`; 1 when it was
+/// stored, 0 otherwise.
+///
+/// # Safety
+///
+/// Same requirements as `_atom_reset`, for both `expected` and `value`.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_compare_and_set(atom: *mut u8, expected: i64, expected_tag: i64, value: i64, tag: i64) -> i64 {
+    let state = atom as *mut Atom;
+    if state.is_null() || !values_equal((*state).tag as u8, (*state).value, expected_tag as u8, expected) {
+        return 0;
+    }
+    replace(state, value, tag);
+    1
+}
+
+/// Add a watch called with key, atom, old and new value after each change, replacing any watch
+/// under an equal key. Returns another reference to the atom.
+///
+/// # Safety
+///
+/// `atom` must be null or an atom, a heap tag requires `key` to be null or point to a managed
+/// value of that kind, and `function` must be the address of a compiled function of four
+/// arguments.
+#[no_mangle]
+pub unsafe extern "C" fn _atom_add_watch(atom: *mut u8, key: i64, key_tag: i64, function: i64, result_mode: i64) -> *mut u8 {
+    let state = atom as *mut Atom;
+    if state.is_null() {
+        return null_mut();
+    }
+    let mut index = 0usize;
+    while index < (*state).keys.len() {
+        let (existing, existing_tag) = (*state).keys.get(index);
+        if values_equal(existing_tag as u8, existing, key_tag as u8, key) {
+            (*state).watches.set(index, function, result_mode);
+            return _atom_retain(atom);
+        }
+        index += 1;
+    }
+
+    let key = owned_clone(key, key_tag);
+    (*state).keys.push_tagged(key, key_tag);
+    (*state).watches.push_tagged(function, result_mode);
+    adopt_value(atom, key, key_tag as u8);
+    for block in (*state).keys.blocks().into_iter().chain((*state).watches.blocks()) {
+        adopt_block(atom, block);
+    }
+    _atom_retain(atom)
+}
@@ -0,0 +1,523 @@
+use core::arch::asm;
+
+use crate::allocator::{block_label, for_each_block, is_allocated, set_block_label, MAX_BLOCKS};
+use crate::atom::atom_visit_parts;
+use crate::lazy::{lazy_release_outer, lazy_visit_parts};
+use crate::map::{map_deep_clone, map_deep_free, map_release_outer, map_visit_parts};
+use crate::sorted::release_outer as sorted_release_outer;
+use crate::trie::release_outer as trie_release_outer;
+use crate::value::_value_tag;
+use crate::vector::{vector_deep_clone, vector_deep_free, vector_element, vector_view_release_outer, vector_view_source};
+use crate::{_free, _map_contains, _map_create, _map_get, _string_clone, _string_count, _vector_count};
+
+// Handler frames implement `try` for compiled code. `_exception_push_handler` records the stack
+// state of the enclosing function together with a landing address; `_exception_throw` restores
+// that state and jumps to the landing with the thrown value on top of the stack.
+//
+// Each handler opens a new epoch, and every block allocated while it is the innermost handler
+// carries that epoch in its allocator label and joins the list of blocks allocated under handlers.
+// Unwinding to a handler frees the blocks from its part of the list still allocated in its epoch or
+// a later one - the heap slots of all unwound frames plus temporaries of the interrupted
+// expression - so a caught exception leaves nothing behind, at a cost in the blocks the unwound
+// frames allocated rather than in the size of the heap. A value the body stores
+// into a cell that is older than the handler, an atom or a lazy sequence realizing items, moves
+// into the cell's epoch as it is stored and survives along with the cell. Tries, sorted trees, lazy
+// sequences and vector views hold counted references, so before anything is freed, each unwound
+// block holding one drops the references it holds to blocks that stay. Any value can be thrown;
+// the thrown value is deep-cloned, by the kind `_value_tag` reads off it, before the release so
+// the catch block owns its own copy.
+
+const MAX_HANDLERS: usize = 64;
+const EPOCH_BITS: u32 = 24;
+const EPOCH_MASK: u32 = (1 << EPOCH_BITS) - 1;
+/// Label bit marking a block already met while going through the block list
+const LISTED: u32 = 1 << 30;
+const KIND_MASK: u32 = LISTED - 1;
+
+/// Kinds of blocks, kept in the label above the epoch. The first four and vector views hold counted
+/// references; the rest only let `_value_tag` tell what an untyped value is.
+pub(crate) const BLOCK_MAP: u32 = 1;
+pub(crate) const BLOCK_TRIE_NODE: u32 = 2;
+pub(crate) const BLOCK_SORTED_NODE: u32 = 3;
+pub(crate) const BLOCK_LAZY_SEQ: u32 = 4;
+pub(crate) const BLOCK_VECTOR: u32 = 5;
+pub(crate) const BLOCK_RECORD: u32 = 6;
+pub(crate) const BLOCK_ATOM: u32 = 7;
+pub(crate) const BLOCK_VECTOR_VIEW: u32 = 8;
+
+const SYS_WRITE: isize = 1;
+const SYS_EXIT: isize = 60;
+const STDERR_FD: usize = 2;
+
+const TAG_STRING: u8 = 3;
+const TAG_VECTOR: u8 = 4;
+const TAG_MAP: u8 = 5;
+const TAG_KEYWORD: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_LIST: u8 = 8;
+const TAG_SYMBOL: u8 = 9;
+const TAG_LAZY_SEQ: u8 = 10;
+const TAG_ATOM: u8 = 11;
+const TAG_ANY: u8 = 0xff;
+
+static MESSAGE_KEY: [u8; 9] = *b":message\0";
+static DATA_KEY: [u8; 6] = *b":data\0";
+
+// `_arithmetic_error` kinds; any other kind reports an overflow.
+const ARITHMETIC_DIVIDE_BY_ZERO: i64 = 0;
+
+static DIVIDE_BY_ZERO_MESSAGE: [u8; 15] = *b"Divide by zero\0";
+static OVERFLOW_MESSAGE: [u8; 17] = *b"integer overflow\0";
+
+#[derive(Clone, Copy)]
+struct HandlerFrame {
+    stack_pointer: u64,
+    base_pointer: u64,
+    landing: u64,
+    epoch: u32,
+    /// Where the blocks allocated since the handler was pushed start in `BLOCKS`
+    first_block: usize,
+}
+
+const EMPTY_FRAME: HandlerFrame = HandlerFrame {
+    stack_pointer: 0,
+    base_pointer: 0,
+    landing: 0,
+    epoch: 0,
+    first_block: 0,
+};
+
+static mut HANDLERS: [HandlerFrame; MAX_HANDLERS] = [EMPTY_FRAME; MAX_HANDLERS];
+static mut HANDLER_DEPTH: usize = 0;
+static mut NEXT_EPOCH: u32 = 1;
+static mut TRACKING_SUSPENDED: bool = false;
+
+/// Blocks allocated while a handler is active, in allocation order. Entries go stale as blocks are
+/// freed, and a freed block's address may come back allocated again; blocks never merge, so a stale
+/// entry still points at a block header, and `compact_blocks` drops stale entries and all but the
+/// last of repeated ones. The list is as long as the heap has blocks, so compacting always frees room.
+static mut BLOCKS: [*const u8; MAX_BLOCKS] = [core::ptr::null(); MAX_BLOCKS];
+static mut BLOCK_COUNT: usize = 0;
+
+/// A piece of a value met while walking everything reachable from it
+pub(crate) enum Part {
+    /// A heap block the value is made of; the walk goes below it only when the visitor returns true
+    Block(*const u8),
+    /// A value held inside it, with its tag
+    Value(i64, u8),
+}
+
+#[inline]
+unsafe fn epoch(block: *const u8) -> u32 {
+    block_label(block) & EPOCH_MASK
+}
+
+#[inline]
+unsafe fn set_epoch(block: *const u8, epoch: u32) {
+    set_block_label(block, (block_label(block) & !EPOCH_MASK) | epoch);
+}
+
+/// Label an allocation made while a handler is active with the innermost handler's epoch and add
+/// it to the block list.
+pub(crate) unsafe fn track_alloc(ptr: *mut u8) {
+    if HANDLER_DEPTH == 0 || TRACKING_SUSPENDED || ptr.is_null() {
+        return;
+    }
+    set_epoch(ptr, HANDLERS[HANDLER_DEPTH - 1].epoch);
+    if BLOCK_COUNT == MAX_BLOCKS {
+        compact_blocks();
+    }
+    BLOCKS[BLOCK_COUNT] = ptr;
+    BLOCK_COUNT += 1;
+}
+
+/// Whether the block list entry at `index` is the last one for a block still allocated in an
+/// epoch of `first` or later, marking the block `LISTED` when it is; entries that are not are
+/// cleared. Called from the end of the list backwards, so the last entry for a block counts.
+unsafe fn claim_entry(index: usize, first: u32) -> bool {
+    let block = BLOCKS[index];
+    let claimed = !block.is_null() && is_allocated(block) && block_label(block) & LISTED == 0 && epoch(block) >= first;
+    if claimed {
+        set_block_label(block, block_label(block) | LISTED);
+    } else {
+        BLOCKS[index] = core::ptr::null();
+    }
+    claimed
+}
+
+/// Drop the list entries of blocks freed since or moved out of the epochs of every active handler,
+/// and repeats, moving each handler's start along with its blocks.
+unsafe fn compact_blocks() {
+    let oldest = HANDLERS[0].epoch;
+    for index in (0..BLOCK_COUNT).rev() {
+        claim_entry(index, oldest);
+    }
+    let mut kept = 0;
+    let mut handler = 0;
+    for index in 0..BLOCK_COUNT {
+        while handler < HANDLER_DEPTH && HANDLERS[handler].first_block == index {
+            HANDLERS[handler].first_block = kept;
+            handler += 1;
+        }
+        let block = BLOCKS[index];
+        if !block.is_null() {
+            set_block_label(block, block_label(block) & !LISTED);
+            BLOCKS[kept] = block;
+            kept += 1;
+        }
+    }
+    for frame in &mut HANDLERS[handler..HANDLER_DEPTH] {
+        frame.first_block = kept;
+    }
+    BLOCK_COUNT = kept;
+}
+
+/// Record that `block` holds counted references, so unwinding drops the ones to blocks that stay.
+pub(crate) unsafe fn mark_block(block: *const u8, kind: u32) {
+    if !block.is_null() {
+        set_block_label(block, (kind << EPOCH_BITS) | epoch(block));
+    }
+}
+
+/// The kind `mark_block` gave the allocated block at `block`, 0 when it has none
+pub(crate) unsafe fn block_kind(block: *const u8) -> u32 {
+    (block_label(block) & KIND_MASK) >> EPOCH_BITS
+}
+
+/// Keep `value`, just stored into the block `owner`, for as long as unwinding keeps `owner`.
+pub(crate) unsafe fn adopt_value(owner: *const u8, value: i64, tag: u8) {
+    if let Some(epoch) = adopting_epoch(owner) {
+        adopt(value, tag, epoch);
+    }
+}
+
+/// Keep `block`, just attached to the block `owner`, for as long as unwinding keeps `owner`.
+pub(crate) unsafe fn adopt_block(owner: *const u8, block: *const u8) {
+    if let Some(epoch) = adopting_epoch(owner) {
+        lower(block, epoch);
+    }
+}
+
+/// The epoch of `owner` when an active handler is newer, so what it takes must move back to it
+unsafe fn adopting_epoch(owner: *const u8) -> Option<u32> {
+    if HANDLER_DEPTH == 0 || !is_allocated(owner) {
+        return None;
+    }
+    let epoch = epoch(owner);
+    (epoch < HANDLERS[HANDLER_DEPTH - 1].epoch).then_some(epoch)
+}
+
+/// Move `block` back to `epoch` when it is newer, returning whether it moved
+unsafe fn lower(block: *const u8, epoch: u32) -> bool {
+    if !is_allocated(block) || self::epoch(block) <= epoch {
+        return false;
+    }
+    set_epoch(block, epoch);
+    true
+}
+
+/// Move every block reachable from `value` back to `epoch`. Nodes and cells only refer to blocks at
+/// least as old as themselves once adopted, so the walk stops at one that is old enough already;
+/// collections are filled in place after they are allocated and are always walked.
+unsafe fn adopt(value: i64, tag: u8, epoch: u32) {
+    let block = value as *const u8;
+    if !is_allocated(block) {
+        return;
+    }
+    let moved = lower(block, epoch);
+    let mut visit = |part: Part| match part {
+        Part::Block(block) => lower(block, epoch),
+        Part::Value(value, tag) => {
+            adopt(value, tag, epoch);
+            true
+        }
+    };
+
+    match tag {
+        TAG_VECTOR | TAG_LIST => {
+            if let Some(source) = vector_view_source(block) {
+                lower(source, epoch);
+            }
+            for index in 0.._vector_count(block) as usize {
+                let (element, element_tag) = vector_element(block, index);
+                adopt(element, element_tag, epoch);
+            }
+        }
+        TAG_MAP | TAG_SET => map_visit_parts(block, &mut visit),
+        TAG_LAZY_SEQ if moved => lazy_visit_parts(block, &mut visit),
+        TAG_ATOM if moved => atom_visit_parts(block, &mut visit),
+        _ => {}
+    }
+}
+
+/// Free every block of epoch `first` or later, all of them in the block list from `frame`'s start
+/// on, and cut the list back to that start. Blocks holding counted references first drop the ones
+/// to blocks that stay, which may free those too. Blocks in that part of the list that stay, moved
+/// into an older handler's epoch since, remain listed while a handler is left to unwind to.
+unsafe fn release_epochs_from(frame: &HandlerFrame, remaining_handlers: usize) {
+    let first = frame.epoch;
+    let stays = |block: *const u8| is_allocated(block) && epoch(block) < first;
+    let listed = frame.first_block..BLOCK_COUNT;
+    for index in listed.clone().rev() {
+        claim_entry(index, 0);
+    }
+    for index in listed.clone() {
+        let block = BLOCKS[index];
+        if block.is_null() || epoch(block) < first {
+            continue;
+        }
+        match block_kind(block) {
+            BLOCK_MAP => map_release_outer(block, &stays),
+            BLOCK_TRIE_NODE => trie_release_outer(block, &stays),
+            BLOCK_SORTED_NODE => sorted_release_outer(block, &stays),
+            BLOCK_LAZY_SEQ => lazy_release_outer(block, &stays),
+            BLOCK_VECTOR_VIEW => vector_view_release_outer(block, &stays),
+            _ => {}
+        }
+    }
+    let mut kept = frame.first_block;
+    for index in listed {
+        let block = BLOCKS[index];
+        if block.is_null() || !is_allocated(block) {
+            continue;
+        }
+        if epoch(block) >= first {
+            _free(block as *mut u8);
+            continue;
+        }
+        set_block_label(block, block_label(block) & !LISTED);
+        if remaining_handlers > 0 && epoch(block) >= HANDLERS[0].epoch {
+            BLOCKS[kept] = block;
+            kept += 1;
+        }
+    }
+    BLOCK_COUNT = kept;
+}
+
+/// Give the active handlers the epochs from 1 up and relabel every block to match, once the epoch
+/// counter has used up its bits.
+unsafe fn renumber_epochs() {
+    for_each_block(|block| {
+        let old = epoch(block);
+        let handlers = HANDLERS[..HANDLER_DEPTH].iter().filter(|frame| frame.epoch <= old).count();
+        set_epoch(block, handlers as u32);
+    });
+    for (index, frame) in HANDLERS[..HANDLER_DEPTH].iter_mut().enumerate() {
+        frame.epoch = index as u32 + 1;
+    }
+    NEXT_EPOCH = HANDLER_DEPTH as u32 + 1;
+}
+
+pub(crate) unsafe fn value_deep_clone(value: i64, tag: u8) -> i64 {
+    if value == 0 {
+        return value;
+    }
+
+    match tag {
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => _string_clone(value as *const u8) as i64,
+        TAG_VECTOR | TAG_LIST => vector_deep_clone(value as *const u8) as i64,
+        TAG_MAP | TAG_SET => map_deep_clone(value as *const u8) as i64,
+        _ => value,
+    }
+}
+
+pub(crate) unsafe fn value_deep_free(value: i64, tag: u8) {
+    if value == 0 {
+        return;
+    }
+
+    match tag {
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => _free(value as *mut u8),
+        TAG_VECTOR | TAG_LIST => vector_deep_free(value as *mut u8),
+        TAG_MAP | TAG_SET => map_deep_free(value as *mut u8),
+        _ => {}
+    }
+}
+
+/// # Safety
+///
+/// `stack_pointer` and `base_pointer` must describe the live frame that contains `landing`; the
+/// handler has to be removed with `_exception_pop_handler` before that frame returns.
+#[no_mangle]
+pub unsafe extern "C" fn _exception_push_handler(stack_pointer: u64, base_pointer: u64, landing: u64) {
+    if HANDLER_DEPTH >= MAX_HANDLERS {
+        fatal(b"exception handler stack overflow");
+    }
+
+    if NEXT_EPOCH > EPOCH_MASK {
+        renumber_epochs();
+    }
+
+    HANDLERS[HANDLER_DEPTH] = HandlerFrame {
+        stack_pointer,
+        base_pointer,
+        landing,
+        epoch: NEXT_EPOCH,
+        first_block: BLOCK_COUNT,
+    };
+    HANDLER_DEPTH += 1;
+    NEXT_EPOCH += 1;
+}
+
+#[no_mangle]
+pub extern "C" fn _exception_pop_handler() {
+    unsafe {
+        HANDLER_DEPTH = HANDLER_DEPTH.saturating_sub(1);
+        // The blocks a popped handler listed now belong to the one around it, if any
+        if HANDLER_DEPTH == 0 {
+            BLOCK_COUNT = 0;
+        }
+    }
+}
+
+/// Unwind to the innermost handler, or report the exception and exit when there is none.
+///
+/// # Safety
+///
+/// `exception` may be any value; a heap value must be managed. Every handler on the stack must still
+/// refer to a live frame.
+#[no_mangle]
+pub unsafe extern "C" fn _exception_throw(exception: *mut u8) -> ! {
+    if HANDLER_DEPTH == 0 {
+        report_uncaught(exception);
+        exit(1);
+    }
+
+    let tag = _value_tag(exception as i64) as u8;
+    TRACKING_SUSPENDED = true;
+    let mut payload = value_deep_clone(exception as i64, tag);
+
+    let frame = HANDLERS[HANDLER_DEPTH - 1];
+    HANDLER_DEPTH -= 1;
+    release_epochs_from(&frame, HANDLER_DEPTH);
+    TRACKING_SUSPENDED = false;
+
+    if HANDLER_DEPTH > 0 {
+        // Re-home the payload in the outer handler's epoch so a throw from the catch block still
+        // reclaims it.
+        let tracked = value_deep_clone(payload, tag);
+        value_deep_free(payload, tag);
+        payload = tracked;
+    }
+
+    asm!(
+        "mov rsp, rdi",
+        "mov rbp, rsi",
+        "push rdx",
+        "jmp rcx",
+        in("rdi") frame.stack_pointer,
+        in("rsi") frame.base_pointer,
+        in("rdx") payload as u64,
+        in("rcx") frame.landing,
+        options(noreturn),
+    );
+}
+
+/// Raise the error for a failed checked arithmetic operation as an ex-info style exception.
+///
+/// # Safety
+///
+/// Same requirements as `_exception_throw`: every handler on the stack must still refer to a live
+/// frame.
+#[no_mangle]
+pub unsafe extern "C" fn _arithmetic_error(kind: i64) -> ! {
+    let message: &[u8] = if kind == ARITHMETIC_DIVIDE_BY_ZERO { &DIVIDE_BY_ZERO_MESSAGE } else { &OVERFLOW_MESSAGE };
+    throw_message(message)
+}
+
+/// Throw a `{:message message}` exception; `message` must be NUL-terminated. Like the errors the
+/// interpreter raises itself, it carries no `:data`, so `catch ExceptionInfo` passes it by.
+///
+/// # Safety
+///
+/// Same requirements as `_exception_throw`.
+pub(crate) unsafe fn throw_message(message: &[u8]) -> ! {
+    let keys = [_string_clone(MESSAGE_KEY.as_ptr()) as i64];
+    let key_tags = [TAG_KEYWORD as i64];
+    let values = [_string_clone(message.as_ptr()) as i64];
+    let value_tags = [TAG_STRING as i64];
+
+    let exception = _map_create(keys.as_ptr(), key_tags.as_ptr(), values.as_ptr(), value_tags.as_ptr(), keys.len() as u64);
+    _exception_throw(exception)
+}
+
+/// 1 when an exception is an `ex-info` map, with a string `:message` and a `:data` entry; the
+/// clauses `catch ExceptionInfo` compiles to take only those.
+///
+/// # Safety
+///
+/// `exception` must be a value `_exception_throw` delivered.
+#[no_mangle]
+pub unsafe extern "C" fn _exception_is_info(exception: *const u8) -> i64 {
+    if _value_tag(exception as i64) as u8 != TAG_MAP {
+        return 0;
+    }
+    let mut value = 0i64;
+    let mut tag = 0u8;
+    let message = _map_get(exception, MESSAGE_KEY.as_ptr() as i64, TAG_KEYWORD as i64, &mut value, &mut tag) != 0 && tag == TAG_STRING;
+    (message && _map_contains(exception, DATA_KEY.as_ptr() as i64, TAG_KEYWORD as i64) != 0) as i64
+}
+
+/// Deep-clone a value so it survives the release of the exception it was read from; an untyped
+/// value (`TAG_ANY`) is cloned by the kind `_value_tag` reads off it.
+///
+/// # Safety
+///
+/// Heap tags require `value` to be null or point to a managed value of that kind.
+#[no_mangle]
+pub unsafe extern "C" fn _exception_value_clone(value: i64, tag: i64) -> i64 {
+    let tag = match (tag & 0xff) as u8 {
+        TAG_ANY => _value_tag(value) as u8,
+        tag => tag,
+    };
+    value_deep_clone(value, tag)
+}
+
+/// Release a caught exception and everything it owns.
+///
+/// # Safety
+///
+/// `exception` must be a value `_exception_throw` delivered to a landing.
+#[no_mangle]
+pub unsafe extern "C" fn _exception_free(exception: *mut u8) {
+    value_deep_free(exception as i64, _value_tag(exception as i64) as u8);
+}
+
+unsafe fn report_uncaught(exception: *const u8) {
+    stderr_write(b"Uncaught exception");
+
+    let mut message = 0i64;
+    let mut tag = 0u8;
+    if _value_tag(exception as i64) as u8 == TAG_MAP && _map_get(exception, MESSAGE_KEY.as_ptr() as i64, TAG_KEYWORD as i64, &mut message, &mut tag) != 0 && tag == TAG_STRING && message != 0 {
+        let ptr = message as *const u8;
+        stderr_write(b": ");
+        stderr_write(core::slice::from_raw_parts(ptr, _string_count(ptr) as usize));
+    }
+
+    stderr_write(b"\n");
+}
+
+fn fatal(message: &[u8]) -> ! {
+    stderr_write(message);
+    stderr_write(b"\n");
+    exit(1);
+}
+
+fn stderr_write(bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+
+    unsafe {
+        let _ = crate::syscall6(SYS_WRITE, STDERR_FD, bytes.as_ptr() as usize, bytes.len(), 0, 0, 0);
+    }
+}
+
+fn exit(code: usize) -> ! {
+    unsafe {
+        crate::syscall6(SYS_EXIT, code, 0, 0, 0, 0, 0);
+    }
+    loop {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,580 @@
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+use crate::exceptions::{adopt_block, adopt_value, mark_block, throw_message, Part, BLOCK_LAZY_SEQ};
+use crate::sequence::{discard_result, invoke, mode_owned, mode_tag, release_value, Cursor, ItemBuffer, OUT_OF_MEMORY_MESSAGE};
+use crate::vector::vector_element;
+use crate::{_allocate, _free, _map_value_clone, _vector_free};
+
+// Lazy sequences back `iterate`, `repeat`, `cycle`, the zero-argument `range` and the `map`,
+// `filter`, `remove`, `take` and `drop` calls made over them. A sequence is a reference-counted heap
+// object holding a generator and the items realized so far, in a chain of fixed-size chunks:
+// realization appends to the newest chunk, so every reader of the same sequence sees the same
+// memoized items and callbacks run once per item. Consumers that need every item at once copy
+// them out of the chunks into an ordinary list.
+//
+// A sequence read only in order - by the one derived sequence retaining it, by a single `nth`,
+// `first` or `reduce` of a temporary, or by the last read of a local - frees each chunk once
+// realization has moved past it, so walking far into an unbounded sequence holds one chunk rather
+// than every item before the one asked for.
+//
+// Values a sequence needs (repeated values, the items of a cycled collection, an iterate seed) are
+// cloned into it, and derived sequences retain their source, so a sequence never borrows from the
+// program. Compiled code treats `_lazy_retain` as the clone and `_lazy_free` as the release of the
+// value; the sequence and its chunks go away when the last reference is released. A null sequence
+// (what `next` gives at the end of one) is empty. Running out of heap while realizing throws.
+
+const TAG_NUMBER: i64 = 1;
+const TAG_LIST: u8 = 8;
+const TAG_LAZY_SEQ: u8 = 10;
+
+const GENERATOR_RANGE: u64 = 0;
+const GENERATOR_REPEAT: u64 = 1;
+const GENERATOR_CYCLE: u64 = 2;
+const GENERATOR_ITERATE: u64 = 3;
+const GENERATOR_MAP: u64 = 4;
+const GENERATOR_FILTER: u64 = 5;
+const GENERATOR_DROP: u64 = 6;
+const GENERATOR_TAKE: u64 = 7;
+
+/// Stored-tag bit for chunk items the sequence owns and releases when it is freed.
+const ITEM_OWNED: i64 = 0x100;
+
+/// Items held by one chunk.
+const CHUNK_ITEMS: usize = 32;
+
+/// A run of realized items and the chunk holding the ones after them.
+struct Chunk {
+    next: *mut Chunk,
+    values: [i64; CHUNK_ITEMS],
+    tags: [i64; CHUNK_ITEMS],
+}
+
+struct LazySeq {
+    refs: u64,
+    generator: u64,
+    function: i64,
+    result_mode: i64,
+    source: *mut LazySeq,
+    // Range: the next number. Repeat: the repeated value. Cycle: a list owning the cycled items.
+    value: i64,
+    value_tag: i64,
+    // Range: the step. Repeat: items left (negative when unbounded). Cycle: the cycle length.
+    // Drop: items to skip. Take: items to keep. Filter: 1 to keep matching items, 0 to remove them.
+    count: i64,
+    // Next source item to read (map, filter, drop, take)
+    source_index: usize,
+    done: bool,
+    // `len` items are realized; the chunks from `first` to `last` keep those from `start` on.
+    // `cursor` is the chunk the last lookup ended in, holding the items from `cursor_start`.
+    first: *mut Chunk,
+    last: *mut Chunk,
+    cursor: *mut Chunk,
+    cursor_start: usize,
+    start: usize,
+    len: usize,
+    // Derived sequences retaining this one, and whether the only reference is a single in-order read
+    readers: u64,
+    streaming: bool,
+}
+
+unsafe fn new_seq(generator: u64) -> *mut LazySeq {
+    let seq = _allocate(size_of::<LazySeq>() as u64) as *mut LazySeq;
+    if seq.is_null() {
+        throw_message(&OUT_OF_MEMORY_MESSAGE);
+    }
+    mark_block(seq as *const u8, BLOCK_LAZY_SEQ);
+    seq.write(LazySeq {
+        refs: 1,
+        generator,
+        function: 0,
+        result_mode: 0,
+        source: null_mut(),
+        value: 0,
+        value_tag: 0,
+        count: 0,
+        source_index: 0,
+        done: false,
+        first: null_mut(),
+        last: null_mut(),
+        cursor: null_mut(),
+        cursor_start: 0,
+        start: 0,
+        len: 0,
+        readers: 0,
+        streaming: false,
+    });
+    seq
+}
+
+/// Release the items a chunk owns among its first `count`.
+unsafe fn release_items(chunk: *const Chunk, count: usize) {
+    for index in 0..count {
+        let tag = (*chunk).tags[index];
+        if tag & ITEM_OWNED != 0 {
+            release_value((*chunk).values[index], (tag & 0xff) as u8);
+        }
+    }
+}
+
+/// Add a realized item after the others, starting a new chunk when the newest one is full. The
+/// sequence may be older than an active `try`, so both are kept for as long as it is.
+unsafe fn append(state: &mut LazySeq, value: i64, tag: i64) {
+    let seq = state as *const LazySeq as *const u8;
+    let offset = state.len % CHUNK_ITEMS;
+    if offset == 0 {
+        let chunk = _allocate(size_of::<Chunk>() as u64) as *mut Chunk;
+        if chunk.is_null() {
+            if tag & ITEM_OWNED != 0 {
+                release_value(value, (tag & 0xff) as u8);
+            }
+            throw_message(&OUT_OF_MEMORY_MESSAGE);
+        }
+        (*chunk).next = null_mut();
+        if state.last.is_null() {
+            state.first = chunk;
+            state.cursor = chunk;
+            state.cursor_start = state.len;
+        } else {
+            (*state.last).next = chunk;
+        }
+        state.last = chunk;
+        adopt_block(seq, chunk as *const u8);
+    }
+    if tag & ITEM_OWNED != 0 {
+        adopt_value(seq, value, (tag & 0xff) as u8);
+    }
+    (*state.last).values[offset] = value;
+    (*state.last).tags[offset] = tag;
+    state.len += 1;
+}
+
+/// The value and stored tag of the realized item at `index`, which must still be kept.
+unsafe fn stored(state: &mut LazySeq, index: usize) -> (i64, i64) {
+    if index < state.cursor_start {
+        state.cursor = state.first;
+        state.cursor_start = state.start;
+    }
+    while index >= state.cursor_start + CHUNK_ITEMS {
+        state.cursor = (*state.cursor).next;
+        state.cursor_start += CHUNK_ITEMS;
+    }
+    let offset = index - state.cursor_start;
+    ((*state.cursor).values[offset], (*state.cursor).tags[offset])
+}
+
+/// Whether every reader still to come reads past the items realized so far.
+fn read_in_order(state: &LazySeq) -> bool {
+    state.refs == 1 && (state.streaming || state.readers == 1)
+}
+
+/// Free the chunks holding only items before `index`.
+unsafe fn drop_before(state: &mut LazySeq, index: usize) {
+    while state.start + CHUNK_ITEMS <= index {
+        let chunk = state.first;
+        release_items(chunk, CHUNK_ITEMS);
+        state.first = (*chunk).next;
+        state.start += CHUNK_ITEMS;
+        if state.cursor == chunk {
+            state.cursor = state.first;
+            state.cursor_start = state.start;
+        }
+        _free(chunk as *mut u8);
+    }
+}
+
+/// Stored tag for a value the chunk owns when it lives on the heap.
+unsafe fn owned_item(value: i64, tag: u8) -> (i64, i64) {
+    (_map_value_clone(value, tag as i64), tag as i64 | ITEM_OWNED)
+}
+
+/// Stored tag for a callback result.
+fn result_item(value: i64, result_mode: i64) -> (i64, i64) {
+    let owned = if mode_owned(result_mode) { ITEM_OWNED } else { 0 };
+    (value, mode_tag(result_mode) as i64 | owned)
+}
+
+/// Realize items until the one at `index` is; false when the sequence ends first.
+unsafe fn realize(seq: *mut LazySeq, index: usize) -> bool {
+    if seq.is_null() {
+        return false;
+    }
+    while (*seq).len <= index {
+        if (*seq).done {
+            return false;
+        }
+        match next_item(seq) {
+            Some((value, tag)) => append(&mut *seq, value, tag),
+            None => (*seq).done = true,
+        }
+        // The newest item stays, as the one asked for or the one `iterate` builds on
+        if read_in_order(&*seq) {
+            drop_before(&mut *seq, (*seq).len.saturating_sub(1));
+        }
+    }
+    true
+}
+
+/// The item at `index` with its value tag, realizing it first.
+unsafe fn item_at(seq: *mut LazySeq, index: usize) -> Option<(i64, u8)> {
+    if !realize(seq, index) || index < (*seq).start {
+        return None;
+    }
+    let (value, tag) = stored(&mut *seq, index);
+    Some((value, (tag & 0xff) as u8))
+}
+
+/// The item at `index` with its value tag, borrowed from the sequence; realizes it first.
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence.
+pub(crate) unsafe fn lazy_item(seq: *mut u8, index: usize) -> Option<(i64, u8)> {
+    item_at(seq as *mut LazySeq, index)
+}
+
+/// Produce the next item of `seq` as a value and stored tag.
+unsafe fn next_item(seq: *mut LazySeq) -> Option<(i64, i64)> {
+    let state = &mut *seq;
+    match state.generator {
+        GENERATOR_RANGE => {
+            let current = state.value;
+            match current.checked_add(state.count) {
+                Some(next) => state.value = next,
+                None => state.done = true,
+            }
+            Some((current, TAG_NUMBER))
+        }
+        GENERATOR_REPEAT => {
+            if state.count == 0 {
+                return None;
+            }
+            if state.count > 0 {
+                state.count -= 1;
+            }
+            Some((state.value, state.value_tag))
+        }
+        GENERATOR_CYCLE => {
+            // Every pass borrows from the list of cycled items
+            let len = state.count as usize;
+            if len == 0 {
+                return None;
+            }
+            let (value, tag) = vector_element(state.value as *const u8, state.len % len);
+            Some((value, tag as i64))
+        }
+        GENERATOR_ITERATE => {
+            let newest = state.len - 1;
+            let (previous, _) = stored(state, newest);
+            Some(result_item(invoke(state.function, previous, 0), state.result_mode))
+        }
+        GENERATOR_MAP => {
+            let (value, _) = item_at(state.source, state.source_index)?;
+            state.source_index += 1;
+            Some(result_item(invoke(state.function, value, 0), state.result_mode))
+        }
+        GENERATOR_FILTER => loop {
+            let (value, tag) = item_at(state.source, state.source_index)?;
+            state.source_index += 1;
+            let verdict = invoke(state.function, value, 0);
+            discard_result(verdict, state.result_mode);
+            if (verdict != 0) == (state.count != 0) {
+                return Some((value, tag as i64));
+            }
+        },
+        GENERATOR_DROP => {
+            if (state.source_index as i64) < state.count {
+                state.source_index = state.count as usize;
+            }
+            let (value, tag) = item_at(state.source, state.source_index)?;
+            state.source_index += 1;
+            Some((value, tag as i64))
+        }
+        GENERATOR_TAKE => {
+            if state.source_index as i64 >= state.count {
+                return None;
+            }
+            let (value, tag) = item_at(state.source, state.source_index)?;
+            state.source_index += 1;
+            Some((value, tag as i64))
+        }
+        _ => None,
+    }
+}
+
+/// Copy the items of `seq` into a list that owns clones of them.
+unsafe fn collect(seq: *mut LazySeq) -> *mut u8 {
+    let mut items = ItemBuffer::new();
+    let mut index = 0usize;
+    while let Some((value, tag)) = item_at(seq, index) {
+        items.push(_map_value_clone(value, tag as i64), tag);
+        index += 1;
+    }
+    items.finish(true)
+}
+
+/// # Safety
+///
+/// Always safe to call. Returns the unbounded sequence `start`, `start + step`, ...; release it
+/// with `_lazy_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_range(start: i64, step: i64) -> *mut u8 {
+    let seq = new_seq(GENERATOR_RANGE);
+    (*seq).value = start;
+    (*seq).count = step;
+    seq as *mut u8
+}
+
+/// Repeat `value`, `count` times when `bounded` is 1 and without end otherwise.
+///
+/// # Safety
+///
+/// `value` must be described by `tag`; the sequence keeps its own clone of it.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_repeat(count: i64, bounded: i64, value: i64, tag: i64) -> *mut u8 {
+    let seq = new_seq(GENERATOR_REPEAT);
+    (*seq).value = _map_value_clone(value, tag);
+    (*seq).value_tag = tag & 0xff;
+    (*seq).count = if bounded != 0 { count.max(0) } else { -1 };
+    seq as *mut u8
+}
+
+/// Repeat the items of a collection without end (an empty collection gives an empty sequence).
+///
+/// # Safety
+///
+/// `coll` must be a vector, list, set or null described by `coll_tag`; the sequence keeps clones of
+/// its items.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_cycle(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let mut items = ItemBuffer::new();
+    let mut cursor = Cursor::new(coll, coll_tag);
+    while let Some((value, tag)) = cursor.next() {
+        items.push(_map_value_clone(value, tag as i64), tag);
+    }
+    let len = items.len();
+    let seq = new_seq(GENERATOR_CYCLE);
+    (*seq).value = items.finish(true) as i64;
+    (*seq).count = len as i64;
+    seq as *mut u8
+}
+
+/// The sequence `seed`, `(function seed)`, `(function (function seed))`, ...
+///
+/// # Safety
+///
+/// `function` must be a compiled one-argument function whose results `result_mode` describes, and
+/// `seed` must be described by `seed_tag`; the sequence keeps its own clone of the seed.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_iterate(function: i64, seed: i64, seed_tag: i64, result_mode: i64) -> *mut u8 {
+    let seq = new_seq(GENERATOR_ITERATE);
+    (*seq).function = function;
+    (*seq).result_mode = result_mode;
+    let (value, tag) = owned_item(seed, (seed_tag & 0xff) as u8);
+    append(&mut *seq, value, tag);
+    seq as *mut u8
+}
+
+unsafe fn derived(generator: u64, function: i64, source: *mut u8, result_mode: i64, count: i64) -> *mut u8 {
+    let seq = new_seq(generator);
+    (*seq).function = function;
+    (*seq).result_mode = result_mode;
+    (*seq).source = _lazy_retain(source) as *mut LazySeq;
+    if !source.is_null() {
+        (*(*seq).source).readers += 1;
+    }
+    (*seq).count = count;
+    seq as *mut u8
+}
+
+/// # Safety
+///
+/// `function` must be a compiled one-argument function whose results `result_mode` describes and
+/// `source` a lazy sequence (or null), which the result retains.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_map(function: i64, source: *mut u8, result_mode: i64) -> *mut u8 {
+    derived(GENERATOR_MAP, function, source, result_mode, 0)
+}
+
+/// Keep the items whose predicate result is truthy (`keep` = 1) or falsey (`keep` = 0).
+///
+/// # Safety
+///
+/// Same requirements as `_lazy_map`.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_filter(function: i64, source: *mut u8, result_mode: i64, keep: i64) -> *mut u8 {
+    derived(GENERATOR_FILTER, function, source, result_mode, (keep != 0) as i64)
+}
+
+/// # Safety
+///
+/// `source` must be null or a lazy sequence, which the result retains.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_drop(count: i64, source: *mut u8) -> *mut u8 {
+    derived(GENERATOR_DROP, 0, source, 0, count.max(0))
+}
+
+/// The first `count` items of a sequence.
+///
+/// # Safety
+///
+/// Same requirements as `_lazy_drop`.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_take(count: i64, source: *mut u8) -> *mut u8 {
+    derived(GENERATOR_TAKE, 0, source, 0, count.max(0))
+}
+
+/// Realize every item of a sequence into a list; does not return for an unbounded sequence.
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence. The returned list owns clones of the items; release it with
+/// `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_items(seq: *mut u8) -> *mut u8 {
+    collect(seq as *mut LazySeq)
+}
+
+/// The first item of a sequence (nil when it is empty).
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence. The item is cloned, so the caller owns heap items.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_first(seq: *mut u8) -> i64 {
+    match item_at(seq as *mut LazySeq, 0) {
+        Some((value, tag)) => _map_value_clone(value, tag as i64),
+        None => 0,
+    }
+}
+
+/// Realize every item of a sequence and count them; does not return for an unbounded sequence.
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_count(seq: *mut u8) -> i64 {
+    let seq = seq as *mut LazySeq;
+    if seq.is_null() {
+        return 0;
+    }
+    while realize(seq, (*seq).len) {}
+    (*seq).len as i64
+}
+
+/// Take another reference to a sequence, returning it.
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence that has not been released.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_retain(seq: *mut u8) -> *mut u8 {
+    if !seq.is_null() {
+        (*(seq as *mut LazySeq)).refs += 1;
+    }
+    seq
+}
+
+/// Mark a sequence the caller holds the only reference to and reads once, in order, so that
+/// realizing it keeps just the newest chunk. Returns the sequence.
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence; after the read the caller releases it without reading it
+/// again.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_stream(seq: *mut u8) -> *mut u8 {
+    let state = seq as *mut LazySeq;
+    if !state.is_null() && (*state).refs == 1 {
+        (*state).streaming = true;
+    }
+    seq
+}
+
+/// Visit the chunks of a sequence, the items and values it owns, and the sequence it reads from.
+///
+/// # Safety
+///
+/// `seq` must be a lazy sequence.
+pub(crate) unsafe fn lazy_visit_parts(seq: *const u8, visit: &mut dyn FnMut(Part) -> bool) {
+    let state = seq as *const LazySeq;
+    let mut chunk = (*state).first;
+    let mut start = (*state).start;
+    while !chunk.is_null() {
+        visit(Part::Block(chunk as *const u8));
+        for index in 0..((*state).len - start).min(CHUNK_ITEMS) {
+            let tag = (*chunk).tags[index];
+            if tag & ITEM_OWNED != 0 {
+                visit(Part::Value((*chunk).values[index], (tag & 0xff) as u8));
+            }
+        }
+        chunk = (*chunk).next;
+        start += CHUNK_ITEMS;
+    }
+
+    match (*state).generator {
+        GENERATOR_REPEAT => {
+            visit(Part::Value((*state).value, (*state).value_tag as u8));
+        }
+        GENERATOR_CYCLE => {
+            visit(Part::Value((*state).value, TAG_LIST));
+        }
+        _ => {}
+    }
+    visit(Part::Value((*state).source as i64, TAG_LAZY_SEQ));
+}
+
+/// Drop the reference a sequence holds to its source when `stays` picks it, as unwinding an
+/// exception frees the sequence without releasing it.
+///
+/// # Safety
+///
+/// `seq` must be a lazy sequence.
+pub(crate) unsafe fn lazy_release_outer(seq: *const u8, stays: &dyn Fn(*const u8) -> bool) {
+    let source = (*(seq as *const LazySeq)).source;
+    if stays(source as *const u8) {
+        (*source).readers -= 1;
+        _lazy_free(source as *mut u8);
+    }
+}
+
+/// Release a reference to a sequence; the last one frees the chunks, owned items and the source.
+///
+/// # Safety
+///
+/// `seq` must be null or a lazy sequence; the caller's reference must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn _lazy_free(seq: *mut u8) {
+    if seq.is_null() {
+        return;
+    }
+
+    let state = seq as *mut LazySeq;
+    (*state).refs -= 1;
+    if (*state).refs > 0 {
+        return;
+    }
+
+    let mut chunk = (*state).first;
+    let mut start = (*state).start;
+    while !chunk.is_null() {
+        release_items(chunk, ((*state).len - start).min(CHUNK_ITEMS));
+        let next = (*chunk).next;
+        _free(chunk as *mut u8);
+        chunk = next;
+        start += CHUNK_ITEMS;
+    }
+
+    match (*state).generator {
+        GENERATOR_REPEAT => release_value((*state).value, (*state).value_tag as u8),
+        GENERATOR_CYCLE => _vector_free((*state).value as *mut u8),
+        _ => {}
+    }
+    let source = (*state).source;
+    if !source.is_null() {
+        (*source).readers -= 1;
+    }
+    _lazy_free(source as *mut u8);
+    _free(seq);
+}
@@ -15,17 +15,53 @@ pub use allocator::{_allocate, _free, _heap_init};
 
 mod strings;
 pub use strings::{
-    _string_clone, _string_concat_n, _string_count, _string_equals, _string_from_boolean, _string_from_number, _string_get, _string_normalize, _string_subs, FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL,
+    _string_clone, _string_concat_n, _string_count, _string_equals, _string_from_boolean, _string_from_number, _string_get, _string_hash, _string_normalize, _string_subs, string_hash_bytes,
+    FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL,
 };
 
 mod vector;
-pub use vector::{_vector_clone, _vector_count, _vector_create, _vector_free, _vector_get, _vector_slice, _vector_to_string};
+pub use vector::{
+    _list_conj, _list_cons, _list_pop, _list_rest, _list_to_string, _vector_assoc, _vector_assoc_owned, _vector_clone, _vector_clone_owned, _vector_conj, _vector_conj_owned, _vector_count,
+    _vector_create, _vector_free, _vector_get, _vector_peek, _vector_pop, _vector_pop_owned, _vector_slice, _vector_subvec, _vector_to_string,
+};
 
 mod map;
-pub use map::{_map_assoc, _map_clone, _map_contains, _map_count, _map_create, _map_dissoc, _map_free, _map_get, _map_to_string, _map_value_clone};
+pub use map::{
+    _map_assoc, _map_clone, _map_contains, _map_count, _map_create, _map_dissoc, _map_find, _map_free, _map_get, _map_invert, _map_keys, _map_merge, _map_merge_with, _map_select_keys, _map_to_string,
+    _map_vals, _map_value_clone, _map_zipmap,
+};
 
 mod set;
-pub use set::{_set_clone, _set_contains, _set_count, _set_create, _set_disj, _set_free, _set_to_string};
+pub use set::{
+    _set_clone, _set_conj, _set_contains, _set_count, _set_create, _set_difference, _set_disj, _set_free, _set_intersection, _set_select, _set_subset, _set_superset, _set_to_string, _set_union,
+};
+
+mod value;
+pub use value::{_value_compare, _value_count, _value_equals, _value_hash, _value_tag, hash_entry, hash_ordered, hash_scalar, tag_rank};
+
+mod sequence;
+pub use sequence::{
+    _seq_concat, _seq_drop, _seq_empty, _seq_every, _seq_filter, _seq_first, _seq_frequencies, _seq_group_by, _seq_into, _seq_items, _seq_last, _seq_map, _seq_next, _seq_nth, _seq_nth_last_read,
+    _seq_of, _seq_range, _seq_reduce, _seq_reduce_first, _seq_reduce_first_last_read, _seq_reduce_last_read, _seq_rest, _seq_reverse, _seq_some, _seq_take,
+};
+
+mod sort;
+pub use sort::{_seq_sort, _seq_sort_by};
+
+mod record;
+mod sorted;
+mod trie;
+pub use record::{_record_create, _record_type_id};
+pub use sorted::{_sorted_map_from, _sorted_set_from, _sorted_subseq};
+
+mod lazy;
+pub use lazy::{_lazy_count, _lazy_cycle, _lazy_drop, _lazy_filter, _lazy_first, _lazy_free, _lazy_items, _lazy_iterate, _lazy_map, _lazy_range, _lazy_repeat, _lazy_retain, _lazy_stream, _lazy_take};
+
+mod atom;
+pub use atom::{_atom_add_watch, _atom_compare_and_set, _atom_create, _atom_deref, _atom_free, _atom_reset, _atom_retain};
+
+mod exceptions;
+pub use exceptions::{_arithmetic_error, _exception_free, _exception_is_info, _exception_pop_handler, _exception_push_handler, _exception_throw, _exception_value_clone};
 
 #[cfg(not(feature = "std"))]
 mod memory;
@@ -136,6 +172,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn string_hash_matches_byte_hash() {
+        unsafe {
+            let value = _string_from_number(1234);
+            let bucket = _string_hash(value, 7);
+            assert_eq!(bucket as u64, string_hash_bytes(b"1234") % 7);
+            assert_eq!(_string_hash(core::ptr::null(), 7), 0);
+            _free(value);
+        }
+    }
+
     #[test]
     fn string_equals_handles_null_and_content() {
         unsafe {
@@ -194,6 +241,122 @@ mod tests {
         }
     }
 
+    #[test]
+    fn vector_updates_grow_in_place() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_VECTOR: i64 = 4;
+
+            let values = [1i64, 2];
+            let original = _vector_create(values.as_ptr(), core::ptr::null(), values.len() as u64);
+
+            // A copying conj leaves room, so further owned appends keep the same block
+            let grown = _vector_conj(original, 3, TAG_NUMBER);
+            assert_eq!(_vector_count(original), 2);
+            let appended = _vector_conj_owned(grown, 4, TAG_NUMBER);
+            assert_eq!(appended, grown);
+            assert_eq!(_vector_count(appended), 4);
+            assert_eq!(_vector_peek(appended), 4);
+
+            let mut vec = appended;
+            for value in 5..=20 {
+                vec = _vector_conj_owned(vec, value, TAG_NUMBER);
+            }
+            assert_eq!(_vector_count(vec), 20);
+            let mut out = 0i64;
+            assert_eq!(_vector_get(vec, 19, &mut out), 1);
+            assert_eq!(out, 20);
+
+            let updated = _vector_assoc(vec, 0, 100, TAG_NUMBER);
+            assert_eq!(_vector_get(updated, 0, &mut out), 1);
+            assert_eq!(out, 100);
+            assert_eq!(_vector_get(vec, 0, &mut out), 1);
+            assert_eq!(out, 1);
+            let updated = _vector_assoc_owned(updated, 20, 21, TAG_NUMBER);
+            assert_eq!(_vector_count(updated), 21);
+
+            let popped = _vector_pop(updated);
+            assert_eq!(_vector_count(popped), 20);
+            let popped = _vector_pop_owned(popped);
+            assert_eq!(_vector_peek(popped), 19);
+
+            let middle = _vector_subvec(popped, 1, 3);
+            assert_eq!(_vector_count(middle), 2);
+            let reversed = _seq_reverse(middle, TAG_VECTOR);
+            assert_eq!(_vector_get(reversed, 0, &mut out), 1);
+            assert_eq!(out, 3);
+
+            for ptr in [reversed, middle, popped, updated, vec, original] {
+                _vector_free(ptr);
+            }
+        }
+    }
+
+    #[test]
+    fn list_cons_rest_and_render() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_SYMBOL: i64 = 9;
+
+            let symbol = _string_clone(c"a".as_ptr().cast::<u8>());
+            let tail = _list_cons(2, TAG_NUMBER, core::ptr::null());
+            assert_eq!(_vector_count(tail), 1);
+
+            let list = _list_cons(symbol as i64, TAG_SYMBOL, tail);
+            assert_eq!(_vector_count(list), 2);
+            let mut out = 0i64;
+            assert_eq!(_vector_get(list, 0, &mut out), 1);
+            assert_eq!(out, symbol as i64);
+
+            let rendered = _list_to_string(list);
+            assert_eq!(_string_equals(rendered, c"(a 2)".as_ptr().cast::<u8>()), 1);
+            _free(rendered);
+
+            let rest = _list_rest(list);
+            assert_eq!(_vector_count(rest), 1);
+            assert_eq!(_vector_get(rest, 0, &mut out), 1);
+            assert_eq!(out, 2);
+
+            // Rests are views: a rest of a rest reads from the same items, and each one stays
+            // usable after the lists it was taken from are freed
+            let numbers = [1i64, 2, 3, 4];
+            let tags = [TAG_NUMBER; 4];
+            let vector = _vector_create(numbers.as_ptr(), tags.as_ptr(), 4);
+            let second = _list_rest(vector);
+            let third = _list_rest(second);
+            let fourth = _list_rest(third);
+            _vector_free(vector);
+            _vector_free(second);
+            assert_eq!(_vector_count(third), 2);
+            assert_eq!(_vector_get(third, 0, &mut out), 1);
+            assert_eq!(out, 3);
+            let grown = _vector_conj_owned(third, 5, TAG_NUMBER);
+            assert_eq!(_vector_count(grown), 3);
+            assert_eq!(_vector_get(grown, 2, &mut out), 1);
+            assert_eq!(out, 5);
+            let updated = _vector_assoc_owned(fourth, 0, 9, TAG_NUMBER);
+            assert_eq!(_vector_get(updated, 0, &mut out), 1);
+            assert_eq!(out, 9);
+            assert_eq!(_vector_get(grown, 1, &mut out), 1);
+            assert_eq!(out, 4);
+            _vector_free(updated);
+            _vector_free(grown);
+
+            let empty = _list_rest(tail);
+            assert!(!empty.is_null());
+            assert_eq!(_vector_count(empty), 0);
+            let rendered = _list_to_string(empty);
+            assert_eq!(_string_equals(rendered, c"()".as_ptr().cast::<u8>()), 1);
+            _free(rendered);
+
+            _vector_free(empty);
+            _vector_free(rest);
+            _vector_free(list);
+            _vector_free(tail);
+            _free(symbol);
+        }
+    }
+
     #[test]
     fn map_runtime_roundtrip() {
         unsafe {
@@ -312,6 +475,278 @@ mod tests {
         }
     }
 
+    #[test]
+    fn map_merge_select_and_zip_share_entries() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_VECTOR: i64 = 4;
+
+            let keys = [1i64, 2];
+            let tags = [TAG_NUMBER, TAG_NUMBER];
+            let values = [10i64, 20];
+            let left = _map_create(keys.as_ptr(), tags.as_ptr(), values.as_ptr(), tags.as_ptr(), 2);
+            let single = _map_assoc(core::ptr::null(), 2, TAG_NUMBER, 99, TAG_NUMBER);
+            let right = _map_assoc(single, 3, TAG_NUMBER, 30, TAG_NUMBER);
+
+            let merged = _map_merge(left, right);
+            assert_eq!(_map_count(merged), 3);
+            let mut out_value = 0i64;
+            let mut out_tag = 0u8;
+            assert_eq!(_map_get(merged, 2, TAG_NUMBER, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_value, 99);
+
+            let key_list = _map_keys(merged);
+            assert_eq!(_vector_count(key_list), 3);
+            let value_list = _map_vals(merged);
+            let value_sum: i64 = (0..3)
+                .map(|index| {
+                    assert_eq!(_vector_get(value_list, index, &mut out_value), 1);
+                    out_value
+                })
+                .sum();
+            assert_eq!(value_sum, 10 + 99 + 30);
+
+            let wanted = [3i64, 4, 1];
+            let wanted_tags = [TAG_NUMBER, TAG_NUMBER, TAG_NUMBER];
+            let wanted = _vector_create(wanted.as_ptr(), wanted_tags.as_ptr(), 3);
+            let selected = _map_select_keys(merged, wanted, TAG_VECTOR);
+            assert_eq!(_map_count(selected), 2);
+            assert_eq!(_map_contains(selected, 2, TAG_NUMBER), 0);
+
+            let paired = [10i64, 99, 30];
+            let paired = _vector_create(paired.as_ptr(), wanted_tags.as_ptr(), 3);
+            let zipped = _map_zipmap(wanted, TAG_VECTOR, paired, TAG_VECTOR);
+            assert_eq!(_map_count(zipped), 3);
+            assert_eq!(_map_get(zipped, 1, TAG_NUMBER, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_value, 30);
+
+            let entry = _map_find(zipped, 4, TAG_NUMBER);
+            assert_eq!(_vector_get(entry, 1, &mut out_value), 1);
+            assert_eq!(out_value, 99);
+            assert!(_map_find(zipped, 5, TAG_NUMBER).is_null());
+            assert!(_map_find(core::ptr::null(), 5, TAG_NUMBER).is_null());
+
+            _vector_free(entry);
+            _map_free(zipped);
+            _map_free(selected);
+            _vector_free(paired);
+            _vector_free(wanted);
+            _vector_free(value_list);
+            _vector_free(key_list);
+            _map_free(merged);
+            _map_free(right);
+            _map_free(single);
+            _map_free(left);
+        }
+    }
+
+    #[test]
+    fn records_keep_fields_at_fixed_offsets() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_KEYWORD: i64 = 6;
+
+            let render_text = |map: *mut u8| {
+                let ptr = _map_to_string(map);
+                let text = std::ffi::CStr::from_ptr(ptr as *const i8).to_str().unwrap().to_string();
+                _free(ptr);
+                text
+            };
+            let lookup = |map: *const u8, key: &core::ffi::CStr| {
+                let (mut value, mut value_tag) = (0i64, 0u8);
+                (_map_get(map, key.as_ptr().cast::<u8>() as i64, TAG_KEYWORD, &mut value, &mut value_tag) != 0).then_some(value)
+            };
+
+            let layout = b"Point\0:x\0:y\0";
+            let values = [1i64, 2];
+            let tags = [TAG_NUMBER, TAG_NUMBER];
+            let point = _record_create(3, layout.as_ptr(), values.as_ptr(), tags.as_ptr(), 2);
+            assert_eq!(_map_count(point), 2);
+            assert_eq!((lookup(point, c":x"), lookup(point, c":y"), lookup(point, c":z")), (Some(1), Some(2), None));
+            // The second field's value sits right after the first field's value and tag
+            assert_eq!(*(point.add(32 + 16) as *const i64), 2);
+            assert_eq!(render_text(point), "#Point{:x 1 :y 2}");
+
+            // Setting a field keeps the record, any other key turns it into a hash map
+            let moved = _map_assoc(point, c":x".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, 5, TAG_NUMBER);
+            assert_eq!(render_text(moved), "#Point{:x 5 :y 2}");
+            assert_eq!(lookup(point, c":x"), Some(1));
+            let widened = _map_assoc(point, c":z".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, 3, TAG_NUMBER);
+            assert_eq!(render_text(widened), "{:x 1 :y 2 :z 3}");
+            let trimmed = _map_dissoc(point, c":y".as_ptr().cast::<u8>() as i64, TAG_KEYWORD);
+            assert_eq!(render_text(trimmed), "{:x 1}");
+
+            // Records equal only records of the same type
+            let keys = [c":x".as_ptr().cast::<u8>() as i64, c":y".as_ptr().cast::<u8>() as i64];
+            let key_tags = [TAG_KEYWORD, TAG_KEYWORD];
+            let plain = _map_create(keys.as_ptr(), key_tags.as_ptr(), values.as_ptr(), tags.as_ptr(), 2);
+            let twin = _record_create(3, layout.as_ptr(), values.as_ptr(), tags.as_ptr(), 2);
+            let pair_layout = b"Pair\0:x\0:y\0";
+            let other = _record_create(4, pair_layout.as_ptr(), values.as_ptr(), tags.as_ptr(), 2);
+            assert_eq!(_value_equals(point as i64, twin as i64, 5, 5), 1);
+            assert_eq!(_value_equals(point as i64, plain as i64, 5, 5), 0);
+            assert_eq!(_value_equals(point as i64, other as i64, 5, 5), 0);
+            assert_eq!(_value_hash(point as i64, 5), _value_hash(plain as i64, 5));
+            assert_eq!((_record_type_id(point), _record_type_id(other), _record_type_id(plain)), (3, 4, 0));
+
+            let merged = _map_merge(point, plain);
+            assert_eq!(render_text(merged), "#Point{:x 1 :y 2}");
+
+            for map in [merged, other, twin, plain, trimmed, widened, moved, point] {
+                _map_free(map);
+            }
+        }
+    }
+
+    #[test]
+    fn set_algebra_shares_members() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+
+            let values = [1i64, 2, 3];
+            let tags = [TAG_NUMBER, TAG_NUMBER, TAG_NUMBER];
+            let left = _set_create(values.as_ptr(), tags.as_ptr(), 3);
+            let pair = _set_create(values.as_ptr().add(1), tags.as_ptr(), 2);
+            let right = _set_conj(pair, 4, TAG_NUMBER);
+
+            let union = _set_union(left, right);
+            assert_eq!(_set_count(union), 4);
+            let intersection = _set_intersection(left, right);
+            assert_eq!(_set_count(intersection), 2);
+            assert_eq!(_set_contains(intersection, 1, TAG_NUMBER), 0);
+            let difference = _set_difference(left, right);
+            assert_eq!(_set_count(difference), 1);
+            assert_eq!(_set_contains(difference, 1, TAG_NUMBER), 1);
+
+            assert_eq!(_set_subset(intersection, left), 1);
+            assert_eq!(_set_subset(left, intersection), 0);
+            assert_eq!(_set_superset(union, right), 1);
+            assert_eq!(_set_subset(core::ptr::null(), left), 1);
+            let empty = _set_union(core::ptr::null(), core::ptr::null());
+            assert_eq!(_set_count(empty), 0);
+
+            let keys = [1i64, 2];
+            let map_values = [10i64, 20];
+            let map = _map_create(keys.as_ptr(), tags.as_ptr(), map_values.as_ptr(), tags.as_ptr(), 2);
+            let inverted = _map_invert(map);
+            let mut out_value = 0i64;
+            let mut out_tag = 0u8;
+            assert_eq!(_map_get(inverted, 20, TAG_NUMBER, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_value, 2);
+
+            _map_free(inverted);
+            _map_free(map);
+            _set_free(empty);
+            _set_free(difference);
+            _set_free(intersection);
+            _set_free(union);
+            _set_free(right);
+            _set_free(pair);
+            _set_free(left);
+        }
+    }
+
+    #[test]
+    fn collection_keys_compare_structurally() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_VECTOR: i64 = 4;
+            const TAG_SET: i64 = 7;
+
+            let coords = [0i64, 1];
+            let tags = [TAG_NUMBER, TAG_NUMBER];
+            let key = _vector_create(coords.as_ptr(), tags.as_ptr(), 2);
+            let query = _vector_create(coords.as_ptr(), tags.as_ptr(), 2);
+            let other = _vector_create(coords.as_ptr(), tags.as_ptr(), 1);
+
+            let map = _map_assoc(core::ptr::null(), key as i64, TAG_VECTOR, 7, TAG_NUMBER);
+            let mut out_value = 0i64;
+            let mut out_tag = 0u8;
+            assert_eq!(_map_get(map, query as i64, TAG_VECTOR, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_value, 7);
+            assert_eq!(_map_contains(map, other as i64, TAG_VECTOR), 0);
+
+            let members = [1i64, 2];
+            let left = _set_create(members.as_ptr(), tags.as_ptr(), 2);
+            let reversed = [2i64, 1];
+            let right = _set_create(reversed.as_ptr(), tags.as_ptr(), 2);
+            let nested = _set_conj(core::ptr::null(), left as i64, TAG_SET);
+            assert_eq!(_set_contains(nested, right as i64, TAG_SET), 1);
+            assert_eq!(_set_contains(nested, key as i64, TAG_VECTOR), 0);
+
+            _set_free(nested);
+            _set_free(right);
+            _set_free(left);
+            _map_free(map);
+            _vector_free(other);
+            _vector_free(query);
+            _vector_free(key);
+        }
+    }
+
+    #[test]
+    fn values_compare_and_hash_structurally() {
+        unsafe {
+            const TAG_NIL: i64 = 0;
+            const TAG_NUMBER: i64 = 1;
+            const TAG_STRING: i64 = 3;
+            const TAG_VECTOR: i64 = 4;
+            const TAG_MAP: i64 = 5;
+            const TAG_KEYWORD: i64 = 6;
+            const TAG_SET: i64 = 7;
+
+            let items = [1i64, 2];
+            let tags = [TAG_NUMBER, TAG_NUMBER];
+            let left = _vector_create(items.as_ptr(), tags.as_ptr(), 2);
+            let right = _vector_create(items.as_ptr(), tags.as_ptr(), 2);
+            let prefix = _vector_create(items.as_ptr(), tags.as_ptr(), 1);
+            assert_eq!(_value_equals(left as i64, right as i64, TAG_VECTOR, TAG_VECTOR), 1);
+            assert_eq!(_value_hash(left as i64, TAG_VECTOR), _value_hash(right as i64, TAG_VECTOR));
+            assert_eq!(_value_equals(left as i64, prefix as i64, TAG_VECTOR, TAG_VECTOR), 0);
+            assert_eq!(_value_compare(prefix as i64, left as i64, TAG_VECTOR, TAG_VECTOR), -1);
+            assert_eq!(_value_compare(left as i64, right as i64, TAG_VECTOR, TAG_VECTOR), 0);
+
+            // Maps compare their entries in any order, whatever order they were added in
+            let first_entry = _map_assoc(core::ptr::null(), 1, TAG_NUMBER, left as i64, TAG_VECTOR);
+            let first = _map_assoc(first_entry, 2, TAG_NUMBER, 0, TAG_NIL);
+            let second_entry = _map_assoc(core::ptr::null(), 2, TAG_NUMBER, 0, TAG_NIL);
+            let second = _map_assoc(second_entry, 1, TAG_NUMBER, right as i64, TAG_VECTOR);
+            assert_eq!(_value_equals(first as i64, second as i64, TAG_MAP, TAG_MAP), 1);
+            assert_eq!(_value_hash(first as i64, TAG_MAP), _value_hash(second as i64, TAG_MAP));
+            assert_eq!(_value_compare(first as i64, second as i64, TAG_MAP, TAG_MAP), 0);
+
+            let reversed = [2i64, 1];
+            let set = _set_create(items.as_ptr(), tags.as_ptr(), 2);
+            let other_set = _set_create(reversed.as_ptr(), tags.as_ptr(), 2);
+            assert_eq!(_value_equals(set as i64, other_set as i64, TAG_SET, TAG_SET), 1);
+            assert_eq!(_value_hash(set as i64, TAG_SET), _value_hash(other_set as i64, TAG_SET));
+
+            // Different kinds never compare equal and order by kind
+            let text = _string_from_number(1);
+            assert_eq!(_value_equals(1, text as i64, TAG_NUMBER, TAG_STRING), 0);
+            assert_eq!(_value_compare(0, 1, TAG_NIL, TAG_NUMBER), -1);
+            assert_eq!(_value_compare(text as i64, left as i64, TAG_STRING, TAG_VECTOR), -1);
+            assert_ne!(_value_hash(1, TAG_NUMBER), _value_hash(text as i64, TAG_STRING));
+            assert_eq!(_value_hash(text as i64, TAG_STRING) as u64, hash_scalar(TAG_STRING as u8, string_hash_bytes(b"1")));
+
+            // An untyped word reads back the kind of value it holds; hash sets read as maps
+            let words = [0, 7, left as i64, first as i64, set as i64, text as i64, c":a".as_ptr() as i64];
+            assert_eq!(words.map(|word| _value_tag(word)), [TAG_NIL, TAG_NUMBER, TAG_VECTOR, TAG_MAP, TAG_MAP, TAG_STRING, TAG_KEYWORD]);
+            assert_eq!(words.map(|word| _value_count(word)), [0, 0, 2, 2, 2, 1, 2]);
+
+            _free(text);
+            _set_free(other_set);
+            _set_free(set);
+            _map_free(second);
+            _map_free(second_entry);
+            _map_free(first);
+            _map_free(first_entry);
+            _vector_free(prefix);
+            _vector_free(right);
+            _vector_free(left);
+        }
+    }
+
     #[test]
     fn set_runtime_roundtrip() {
         unsafe {
@@ -347,4 +782,546 @@ mod tests {
             _set_free(set_ptr);
         }
     }
+
+    #[test]
+    fn exception_value_clone_copies_nested_values() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_STRING: i64 = 3;
+            const TAG_MAP: i64 = 5;
+            const TAG_KEYWORD: i64 = 6;
+
+            let message = _string_clone(c"boom".as_ptr().cast::<u8>());
+            let data = _map_assoc(core::ptr::null(), c":n".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, 5, TAG_NUMBER);
+            let with_message = _map_assoc(core::ptr::null(), c":message".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, message as i64, TAG_STRING);
+            let exception = _map_assoc(with_message, c":data".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, data as i64, TAG_MAP);
+
+            let copy = _exception_value_clone(exception as i64, TAG_MAP) as *mut u8;
+            assert!(!copy.is_null());
+            assert_ne!(copy, exception);
+
+            let mut out_value = 0i64;
+            let mut out_tag = 0u8;
+            assert_eq!(_map_get(copy, c":message".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_tag, TAG_STRING as u8);
+            assert_ne!(out_value, message as i64);
+            assert_eq!(_string_equals(out_value as *const u8, c"boom".as_ptr().cast::<u8>()), 1);
+
+            assert_eq!(_map_get(copy, c":data".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_tag, TAG_MAP as u8);
+            assert_ne!(out_value, data as i64);
+            let copied_data = out_value as *const u8;
+            assert_eq!(_map_get(copied_data, c":n".as_ptr().cast::<u8>() as i64, TAG_KEYWORD, &mut out_value, &mut out_tag), 1);
+            assert_eq!(out_value, 5);
+
+            _exception_free(copy);
+            _map_free(exception);
+            _map_free(with_message);
+            _map_free(data);
+            _free(message);
+        }
+    }
+
+    #[test]
+    fn sequence_helpers_call_back_into_functions() {
+        extern "C" fn render(value: i64) -> i64 {
+            _string_from_number(value) as i64
+        }
+        extern "C" fn is_odd(value: i64) -> i64 {
+            value % 2
+        }
+        extern "C" fn add(acc: i64, value: i64) -> i64 {
+            acc + value
+        }
+
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_STRING: i64 = 3;
+            const TAG_VECTOR: i64 = 4;
+            const RESULT_OWNED: i64 = 0x100;
+
+            let render_text = |ptr: *mut u8| {
+                let text = std::ffi::CStr::from_ptr(ptr as *const i8).to_str().unwrap().to_string();
+                _free(ptr);
+                text
+            };
+
+            let numbers = _seq_range(1, 6, 1);
+            assert_eq!(_vector_count(numbers), 5);
+
+            let rendered = _seq_map(render as *const () as i64, numbers, TAG_VECTOR, TAG_STRING | RESULT_OWNED);
+            assert_eq!(render_text(_list_to_string(rendered)), "(1 2 3 4 5)");
+            _vector_free(rendered);
+
+            let odds = _seq_filter(is_odd as *const () as i64, numbers, TAG_VECTOR, TAG_NUMBER, 1);
+            assert_eq!(render_text(_list_to_string(odds)), "(1 3 5)");
+            assert_eq!(_seq_reduce(add as *const () as i64, 10, TAG_NUMBER, odds, TAG_VECTOR, TAG_NUMBER), 19);
+            assert_eq!(_seq_reduce_first(add as *const () as i64, 0, numbers, TAG_VECTOR, TAG_NUMBER), 15);
+            _vector_free(odds);
+
+            let groups = _seq_group_by(is_odd as *const () as i64, numbers, TAG_VECTOR, TAG_NUMBER);
+            assert_eq!(render_text(_map_to_string(groups)), "{0 [2 4] 1 [1 3 5]}");
+            _map_free(groups);
+
+            let chars = _seq_items(c"abca".as_ptr().cast::<u8>(), TAG_STRING);
+            let counts = _seq_frequencies(chars, TAG_VECTOR);
+            assert_eq!(render_text(_map_to_string(counts)), "{\"a\" 2 \"b\" 1 \"c\" 1}");
+            _map_free(counts);
+            _vector_free(chars);
+
+            _vector_free(numbers);
+        }
+    }
+
+    #[test]
+    fn sorts_are_stable_and_allocate_one_list() {
+        // Items are key * 100 + position, so stability shows in the positions of equal keys
+        extern "C" fn by_key(left: i64, right: i64) -> i64 {
+            left / 100 - right / 100
+        }
+        extern "C" fn key(value: i64) -> i64 {
+            value / 100
+        }
+        extern "C" fn descending(left: i64, right: i64) -> i64 {
+            (left > right) as i64
+        }
+
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_BOOLEAN: i64 = 2;
+            const TAG_STRING: i64 = 3;
+            const TAG_VECTOR: i64 = 4;
+
+            let items: Vec<i64> = (0..45).map(|position| (position * 7 % 5) * 100 + position).collect();
+            let tags = vec![TAG_NUMBER; items.len()];
+            let source = _vector_create(items.as_ptr(), tags.as_ptr(), items.len() as u64);
+            let contents = |list: *mut u8| {
+                let values = (0.._vector_count(list)).map(|index| vector::vector_element(list, index as usize).0).collect::<Vec<_>>();
+                _vector_free(list);
+                values
+            };
+
+            let mut expected = items.clone();
+            expected.sort();
+            assert_eq!(contents(_seq_sort(0, source, TAG_VECTOR, 0)), expected);
+
+            expected.sort_by_key(|value| value / 100);
+            assert_eq!(contents(_seq_sort(by_key as *const () as i64, source, TAG_VECTOR, TAG_NUMBER)), expected);
+            assert_eq!(contents(_seq_sort_by(key as *const () as i64, 0, source, TAG_VECTOR, TAG_NUMBER, 0)), expected);
+
+            expected.sort_by(|left, right| right.cmp(left));
+            assert_eq!(contents(_seq_sort(descending as *const () as i64, source, TAG_VECTOR, TAG_BOOLEAN)), expected);
+            _vector_free(source);
+
+            // Mixed kinds order by kind first
+            let text = _string_from_number(1);
+            let mixed = [text as i64, 2, 1];
+            let mixed_tags = [TAG_STRING, TAG_NUMBER, TAG_BOOLEAN];
+            let source = _vector_create(mixed.as_ptr(), mixed_tags.as_ptr(), 3);
+            let sorted = _seq_sort(0, source, TAG_VECTOR, 0);
+            let order = (0..3).map(|index| vector::vector_element(sorted, index)).collect::<Vec<_>>();
+            assert_eq!(order, vec![(1, TAG_BOOLEAN as u8), (2, TAG_NUMBER as u8), (text as i64, TAG_STRING as u8)]);
+            _vector_free(sorted);
+            _vector_free(source);
+            _free(text);
+        }
+    }
+
+    #[test]
+    fn sorted_maps_stay_ordered_across_versions() {
+        extern "C" fn descending(left: i64, right: i64) -> i64 {
+            (left > right) as i64
+        }
+
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_BOOLEAN: i64 = 2;
+            const EXCLUSIVE: i64 = 1 << 8 | TAG_NUMBER;
+            const INCLUSIVE: i64 = 2 << 8 | TAG_NUMBER;
+
+            let keys: Vec<i64> = (0..200).map(|position| position * 37 % 200).collect();
+            let values: Vec<i64> = keys.iter().map(|key| key * 10).collect();
+            let tags = vec![TAG_NUMBER; keys.len()];
+            let hashed = _map_create(keys.as_ptr(), tags.as_ptr(), values.as_ptr(), tags.as_ptr(), keys.len() as u64);
+            let sorted = _sorted_map_from(0, hashed, 0);
+            let keys_of = |map: *const u8| (0.._map_count(map) as usize).map(|index| map::map_entry(map, index).0).collect::<Vec<_>>();
+            assert_eq!(keys_of(sorted), (0..200).collect::<Vec<_>>());
+
+            let (mut value, mut tag) = (0i64, 0u8);
+            assert_eq!(_map_get(sorted, 123, TAG_NUMBER, &mut value, &mut tag), 1);
+            assert_eq!((value, tag), (1230, TAG_NUMBER as u8));
+
+            // Updates leave the previous version intact
+            let grown = _map_assoc(sorted, 500, TAG_NUMBER, 1, TAG_NUMBER);
+            let shrunk = _map_dissoc(grown, 0, TAG_NUMBER);
+            let replaced = _map_assoc(shrunk, 7, TAG_NUMBER, 1, TAG_NUMBER);
+            assert_eq!((_map_count(sorted), _map_count(grown), _map_count(shrunk), _map_count(replaced)), (200, 201, 200, 200));
+            assert_eq!(keys_of(shrunk).first(), Some(&1));
+            assert_eq!(keys_of(shrunk).last(), Some(&500));
+            assert_eq!(map::map_entry(replaced, 6).2, 1);
+            assert_eq!(map::map_entry(shrunk, 6).2, 70);
+            for map in [grown, shrunk, replaced] {
+                _map_free(map);
+            }
+
+            let contents = |list: *mut u8| {
+                let entries = (0.._vector_count(list)).map(|index| vector::vector_element(list, index as usize).0).collect::<Vec<_>>();
+                let keys = entries.iter().map(|entry| vector::vector_element(*entry as *const u8, 0).0).collect::<Vec<_>>();
+                _vector_free(list);
+                keys
+            };
+            assert_eq!(contents(_sorted_subseq(sorted, 10, EXCLUSIVE, 14, INCLUSIVE, 0)), vec![11, 12, 13, 14]);
+            assert_eq!(contents(_sorted_subseq(sorted, 197, INCLUSIVE, 0, 0, 1)), vec![199, 198, 197]);
+            assert_eq!(contents(_sorted_subseq(sorted, 0, 0, 2, EXCLUSIVE, 1)), vec![1, 0]);
+            _map_free(sorted);
+
+            // A set under a boolean comparator, with its members listed directly
+            let members = [3i64, 1, 2, 3];
+            let set = _sorted_set_from(descending as *const () as i64, _set_create(members.as_ptr(), tags.as_ptr(), 4), TAG_BOOLEAN);
+            assert_eq!(keys_of(set), vec![3, 2, 1]);
+            assert_eq!(_set_contains(set, 2, TAG_NUMBER), 1);
+            let list = _sorted_subseq(set, 3, EXCLUSIVE, 0, 0, 0);
+            assert_eq!((0.._vector_count(list)).map(|index| vector::vector_element(list, index as usize).0).collect::<Vec<_>>(), vec![2, 1]);
+            _vector_free(list);
+            _set_free(set);
+        }
+    }
+
+    #[test]
+    fn hash_maps_keep_earlier_versions() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_ANY: i64 = 0xff;
+
+            let lookup = |map: *const u8, key: i64, tag: i64| {
+                let (mut value, mut value_tag) = (0i64, 0u8);
+                (_map_get(map, key, tag, &mut value, &mut value_tag) != 0).then_some(value)
+            };
+
+            let mut map = _map_clone(core::ptr::null());
+            let mut halfway = core::ptr::null_mut();
+            for key in 0..5000 {
+                let next = _map_assoc(map, key, TAG_NUMBER, key * 2, TAG_NUMBER);
+                if key == 2500 {
+                    halfway = map;
+                } else {
+                    _map_free(map);
+                }
+                map = next;
+            }
+            assert_eq!(_map_count(map), 5000);
+            assert!((0..5000).all(|key| lookup(map, key, TAG_NUMBER) == Some(key * 2)));
+            let keys = (0..5000).map(|index| map::map_entry(map, index).0).collect::<std::collections::BTreeSet<_>>();
+            assert_eq!(keys.len(), 5000);
+
+            let mut trimmed = _map_clone(map);
+            for key in (0..5000).step_by(2) {
+                let next = _map_dissoc(trimmed, key, TAG_NUMBER);
+                _map_free(trimmed);
+                trimmed = next;
+            }
+            assert_eq!(_map_count(trimmed), 2500);
+            assert_eq!(lookup(trimmed, 10, TAG_NUMBER), None);
+            assert_eq!(lookup(trimmed, 11, TAG_NUMBER), Some(22));
+            // The earlier versions are untouched
+            assert_eq!((_map_count(halfway), lookup(halfway, 10, TAG_NUMBER), lookup(halfway, 2500, TAG_NUMBER)), (2500, Some(20), None));
+            assert_eq!(lookup(map, 4998, TAG_NUMBER), Some(9996));
+
+            // An untagged word hashes like the number it holds without being equal to it
+            let shared = _map_assoc(core::ptr::null(), 7, TAG_NUMBER, 1, TAG_NUMBER);
+            let both = _map_assoc(shared, 7, TAG_ANY, 2, TAG_NUMBER);
+            assert_eq!(_map_count(both), 2);
+            assert_eq!((lookup(both, 7, TAG_NUMBER), lookup(both, 7, TAG_ANY)), (Some(1), Some(2)));
+            let untagged = _map_dissoc(both, 7, TAG_NUMBER);
+            assert_eq!((lookup(untagged, 7, TAG_NUMBER), lookup(untagged, 7, TAG_ANY)), (None, Some(2)));
+
+            for map in [untagged, both, shared, trimmed, map, halfway] {
+                _map_free(map);
+            }
+        }
+    }
+
+    #[test]
+    fn hash_tries_collide_collapse_and_copy_paths() {
+        unsafe {
+            const TAG_NUMBER: u8 = 1;
+            const TAG_VECTOR: u8 = 4;
+
+            let entry = |key: i64, key_tag: u8, value: i64| trie::Entry {
+                key,
+                value,
+                key_tag,
+                value_tag: TAG_NUMBER,
+            };
+            let put = |node: *mut trie::Node, entry: trie::Entry| {
+                let mut added = false;
+                let next = trie::insert(node, entry, &mut added);
+                trie::release(node);
+                next
+            };
+            let value_of = |node: *const trie::Node, key: i64, key_tag: u8| trie::find(node, key_tag, key).map(|stored| stored.value);
+
+            // [1 0] and [2 9628085639353618463] have the same full hash, so they share one bucket
+            let first = _vector_create([1i64, 0].as_ptr(), core::ptr::null(), 2) as i64;
+            let second = _vector_create([2i64, 9628085639353618463u64 as i64].as_ptr(), core::ptr::null(), 2) as i64;
+            assert_eq!(value::value_hash(first, TAG_VECTOR), value::value_hash(second, TAG_VECTOR));
+            let colliding = put(put(core::ptr::null_mut(), entry(first, TAG_VECTOR, 1)), entry(second, TAG_VECTOR, 2));
+            assert!(trie::is_bucket(colliding));
+            assert_eq!((value_of(colliding, first, TAG_VECTOR), value_of(colliding, second, TAG_VECTOR)), (Some(1), Some(2)));
+            let without_first = trie::remove(colliding, TAG_VECTOR, first).unwrap();
+            assert_eq!((value_of(without_first, first, TAG_VECTOR), value_of(without_first, second, TAG_VECTOR)), (None, Some(2)));
+            assert_eq!(trie::remove(without_first, TAG_VECTOR, second), Some(core::ptr::null_mut()));
+
+            // Forty keys fill more slots than one level holds
+            let mut wide = core::ptr::null_mut();
+            for key in 0..40 {
+                wide = put(wide, entry(key, TAG_NUMBER, key * key));
+            }
+            assert!(!trie::is_bucket(wide));
+            assert!((0..40).all(|key| value_of(wide, key, TAG_NUMBER) == Some(key * key)));
+
+            // An assoc copies the path to its bucket and shares the rest with the version it started from
+            let mut added = false;
+            let changed = trie::insert(wide, entry(5, TAG_NUMBER, 7), &mut added);
+            assert!(!added);
+            assert_eq!((value_of(wide, 5, TAG_NUMBER), value_of(changed, 5, TAG_NUMBER)), (Some(25), Some(7)));
+            let shared = |key: i64| core::ptr::eq(trie::find(wide, TAG_NUMBER, key).unwrap(), trie::find(changed, TAG_NUMBER, key).unwrap());
+            assert!(!shared(5));
+            assert!((0..40).filter(|key| *key != 5).all(shared));
+
+            // Removing all but one key pulls its bucket up to the root
+            let mut shrunk = trie::retain(changed);
+            for key in 0..39 {
+                let next = trie::remove(shrunk, TAG_NUMBER, key).unwrap();
+                trie::release(shrunk);
+                shrunk = next;
+            }
+            assert!(trie::is_bucket(shrunk));
+            assert_eq!(value_of(shrunk, 39, TAG_NUMBER), Some(1521));
+
+            // Dropping the original leaves the shared nodes to the updated version
+            trie::release(wide);
+            assert!((0..40).all(|key| value_of(changed, key, TAG_NUMBER) == Some(if key == 5 { 7 } else { key * key })));
+
+            for node in [shrunk, changed, without_first, colliding] {
+                trie::release(node);
+            }
+            _vector_free(first as *mut u8);
+            _vector_free(second as *mut u8);
+        }
+    }
+
+    #[test]
+    fn lazy_sequences_realize_on_demand() {
+        static CALLS: core::sync::atomic::AtomicI64 = core::sync::atomic::AtomicI64::new(0);
+        extern "C" fn square(value: i64) -> i64 {
+            CALLS.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+            value * value
+        }
+        extern "C" fn is_even(value: i64) -> i64 {
+            (value % 2 == 0) as i64
+        }
+        extern "C" fn double(value: i64) -> i64 {
+            value * 2
+        }
+        extern "C" fn add(acc: i64, value: i64) -> i64 {
+            acc + value
+        }
+
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_VECTOR: i64 = 4;
+            const TAG_LAZY_SEQ: i64 = 10;
+
+            let render_text = |seq: *mut u8| {
+                let list = _lazy_items(seq);
+                _lazy_free(seq);
+                let ptr = _list_to_string(list);
+                let text = std::ffi::CStr::from_ptr(ptr as *const i8).to_str().unwrap().to_string();
+                _free(ptr);
+                _vector_free(list);
+                text
+            };
+
+            let naturals = _lazy_range(0, 1);
+            let squares = _lazy_map(square as *const () as i64, naturals, TAG_NUMBER);
+            let even_squares = _lazy_filter(is_even as *const () as i64, squares, TAG_NUMBER, 1);
+            _lazy_free(naturals);
+            _lazy_free(squares);
+
+            assert_eq!(render_text(_lazy_take(3, even_squares)), "(0 4 16)");
+            assert_eq!(CALLS.load(core::sync::atomic::Ordering::Relaxed), 5);
+            // Realized items are memoized
+            assert_eq!(render_text(_lazy_take(2, even_squares)), "(0 4)");
+            assert_eq!(CALLS.load(core::sync::atomic::Ordering::Relaxed), 5);
+
+            let rest = _lazy_drop(2, even_squares);
+            assert_eq!(render_text(_lazy_take(2, rest)), "(16 36)");
+            _lazy_free(rest);
+            _lazy_free(even_squares);
+
+            let powers = _lazy_iterate(double as *const () as i64, 1, TAG_NUMBER, TAG_NUMBER);
+            assert_eq!(render_text(_lazy_take(5, powers)), "(1 2 4 8 16)");
+            _lazy_free(powers);
+
+            let threes = _lazy_repeat(2, 1, 3, TAG_NUMBER);
+            assert_eq!(_lazy_first(threes), 3);
+            assert_eq!(_lazy_count(threes), 2);
+            assert_eq!(render_text(_lazy_retain(threes)), "(3 3)");
+            _lazy_free(threes);
+
+            let pattern = _seq_range(1, 4, 1);
+            let cycled = _lazy_cycle(pattern, TAG_VECTOR);
+            _vector_free(pattern);
+            assert_eq!(render_text(_lazy_take(7, cycled)), "(1 2 3 1 2 3 1)");
+            _lazy_free(cycled);
+
+            // Read in order, a sequence keeps only its newest chunk: a hundred thousand items would
+            // not fit in the heap together
+            let far = _lazy_stream(_lazy_range(0, 1));
+            assert_eq!(lazy::lazy_item(far, 100_000), Some((100_000, TAG_NUMBER as u8)));
+            _lazy_free(far);
+            let source = _lazy_range(0, 1);
+            let doubled = _lazy_stream(_lazy_map(double as *const () as i64, source, TAG_NUMBER));
+            _lazy_free(source);
+            assert_eq!(lazy::lazy_item(doubled, 100_000), Some((200_000, TAG_NUMBER as u8)));
+            _lazy_free(doubled);
+            let source = _lazy_range(0, 1);
+            let prefix = _lazy_stream(_lazy_take(200_000, source));
+            _lazy_free(source);
+            assert_eq!(_seq_reduce(add as *const () as i64, 0, TAG_NUMBER, prefix, TAG_LAZY_SEQ, TAG_NUMBER), 19_999_900_000);
+            _lazy_free(prefix);
+            let held = _lazy_range(0, 1);
+            assert_eq!(_seq_nth_last_read(held, TAG_LAZY_SEQ, 100_000, 0, 0), 100_000);
+            _lazy_free(held);
+
+            // Any other reader may come back for earlier items
+            let held = _lazy_range(0, 1);
+            let shared = _lazy_stream(_lazy_retain(held));
+            assert_eq!(lazy::lazy_item(shared, 1_000), Some((1_000, TAG_NUMBER as u8)));
+            _lazy_free(shared);
+            assert_eq!(lazy::lazy_item(held, 3), Some((3, TAG_NUMBER as u8)));
+            _lazy_free(held);
+        }
+    }
+
+    #[test]
+    fn seq_protocol_walks_any_collection() {
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_STRING: i64 = 3;
+            const TAG_VECTOR: i64 = 4;
+            const TAG_MAP: i64 = 5;
+            const TAG_SET: i64 = 7;
+            const TAG_LAZY_SEQ: i64 = 10;
+
+            let render_text = |list: *mut u8| {
+                let ptr = _list_to_string(list);
+                let text = std::ffi::CStr::from_ptr(ptr as *const i8).to_str().unwrap().to_string();
+                _free(ptr);
+                _vector_free(list);
+                text
+            };
+
+            let numbers = _seq_range(1, 4, 1);
+            assert_eq!(_seq_first(numbers, TAG_VECTOR), 1);
+            assert_eq!(_seq_last(numbers, TAG_VECTOR), 3);
+            assert_eq!(_seq_nth(numbers, TAG_VECTOR, 1, 0, 0), 2);
+            assert_eq!(_seq_nth(numbers, TAG_VECTOR, 5, 42, 1), 42);
+            assert_eq!(render_text(_seq_rest(numbers, TAG_VECTOR)), "(2 3)");
+            assert_eq!(render_text(_seq_of(numbers, TAG_VECTOR)), "(1 2 3)");
+            assert_eq!(_seq_empty(numbers, TAG_VECTOR), 0);
+
+            // Walking with next ends in null
+            let mut remaining = _seq_of(numbers, TAG_VECTOR);
+            let mut total = 0;
+            while !remaining.is_null() {
+                total += _seq_first(remaining, TAG_VECTOR);
+                let next = _seq_next(remaining, TAG_VECTOR);
+                _vector_free(remaining);
+                remaining = next;
+            }
+            assert_eq!(total, 6);
+
+            let values = [3i64, 1, 2];
+            let tags = [TAG_NUMBER; 3];
+            let set = _set_create(values.as_ptr(), tags.as_ptr(), 3);
+            assert_eq!(_seq_last(set, TAG_SET) + _seq_first(set, TAG_SET) + _seq_nth(set, TAG_SET, 1, 0, 0), 6);
+            let set_rest = _seq_rest(set, TAG_SET);
+            assert_eq!(_vector_count(set_rest), 2);
+            _vector_free(set_rest);
+            _set_free(set);
+
+            let naturals = _lazy_range(0, 1);
+            assert_eq!(_seq_nth(naturals, TAG_LAZY_SEQ, 10, 0, 0), 10);
+            let tail = _seq_next(naturals, TAG_LAZY_SEQ);
+            assert_eq!(_seq_first(tail, TAG_LAZY_SEQ), 1);
+            _lazy_free(tail);
+            _lazy_free(naturals);
+
+            let single = _lazy_repeat(1, 1, 7, TAG_NUMBER);
+            assert!(_seq_next(single, TAG_LAZY_SEQ).is_null());
+            assert_eq!(_seq_last(single, TAG_LAZY_SEQ), 7);
+            _lazy_free(single);
+
+            assert!(_seq_of(core::ptr::null(), TAG_VECTOR).is_null());
+            assert_eq!(_seq_empty(core::ptr::null(), TAG_LAZY_SEQ), 1);
+            assert_eq!(_seq_empty(c"".as_ptr().cast::<u8>(), TAG_STRING), 1);
+            assert_eq!(_seq_empty(c"a".as_ptr().cast::<u8>(), TAG_STRING), 0);
+            let empty_map = _map_clone(core::ptr::null());
+            assert_eq!(_seq_empty(empty_map, TAG_MAP), 1);
+            _map_free(empty_map);
+            _vector_free(numbers);
+        }
+    }
+
+    #[test]
+    fn atoms_own_their_value_and_run_watches() {
+        use core::sync::atomic::{AtomicI64, Ordering};
+        static CHANGES: AtomicI64 = AtomicI64::new(0);
+
+        extern "C" fn watch(key: i64, _atom: i64, old: i64, new: i64) -> i64 {
+            CHANGES.fetch_add(key * 100 + old * 10 + new, Ordering::Relaxed);
+            0
+        }
+
+        unsafe {
+            const TAG_NUMBER: i64 = 1;
+            const TAG_STRING: i64 = 3;
+            const TAG_VECTOR: i64 = 4;
+
+            // The atom keeps its own copy of the strings a vector borrows
+            let text = _string_clone(c"ab".as_ptr().cast::<u8>());
+            let values = [text as i64];
+            let tags = [TAG_STRING];
+            let vector = _vector_create(values.as_ptr(), tags.as_ptr(), 1);
+            let names = _atom_create(vector as i64, TAG_VECTOR);
+            _vector_free(vector);
+            _free(text);
+
+            let current = _atom_deref(names) as *mut u8;
+            assert_eq!(_vector_count(current), 1);
+            let mut out_value = 0i64;
+            assert_eq!(_vector_get(current, 0, &mut out_value), 1);
+            assert_eq!(_string_count(out_value as *const u8), 2);
+            assert_eq!(_atom_compare_and_set(names, current as i64, TAG_VECTOR, 0, 0), 1);
+            assert_eq!(_atom_compare_and_set(names, current as i64, TAG_VECTOR, 0, 0), 0);
+            _vector_free(current);
+            _atom_free(names);
+
+            // Watches see the old and new value; adding one under the same key replaces it
+            let counter = _atom_create(1, TAG_NUMBER);
+            _atom_free(_atom_add_watch(counter, 9, TAG_NUMBER, watch as *const () as usize as i64, TAG_NUMBER));
+            _atom_free(_atom_add_watch(counter, 7, TAG_NUMBER, watch as *const () as usize as i64, TAG_NUMBER));
+            _atom_free(_atom_add_watch(counter, 7, TAG_NUMBER, watch as *const () as usize as i64, TAG_NUMBER));
+            assert_eq!(_atom_reset(counter, 2, TAG_NUMBER), 2);
+            assert_eq!(CHANGES.load(Ordering::Relaxed), 912 + 712);
+            assert_eq!(_atom_deref(counter), 2);
+
+            let alias = _atom_retain(counter);
+            _atom_free(counter);
+            assert_eq!(_atom_deref(alias), 2);
+            _atom_free(alias);
+        }
+    }
 }
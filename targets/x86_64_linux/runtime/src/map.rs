@@ -1,16 +1,28 @@
 use core::mem::size_of;
-use core::ptr::{copy_nonoverlapping, null_mut};
+use core::ptr::{copy_nonoverlapping, null, null_mut};
 
+use crate::exceptions::{mark_block, Part, BLOCK_MAP};
+use crate::record::{is_record, record_assoc, record_clone, record_deep_clone, record_deep_free, record_entry, record_free, record_lookup, record_name, record_put, record_visit_parts, record_widen};
+use crate::sequence::{invoke, mode_tag, Cursor, ItemBuffer};
+use crate::sorted::{
+    is_sorted, sorted_assoc, sorted_clone, sorted_deep_clone, sorted_deep_free, sorted_dissoc, sorted_entry, sorted_free, sorted_lookup, sorted_put, sorted_release_outer, sorted_visit_parts,
+};
+use crate::trie::{self, Entry, Node};
 use crate::{
-    _allocate, _free, _set_clone, _set_to_string, _string_clone, _string_count, _string_equals, _string_from_number, _vector_clone, _vector_to_string, FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL,
+    _allocate, _atom_retain, _free, _lazy_retain, _list_to_string, _set_clone, _set_to_string, _string_clone, _string_count, _string_from_number, _vector_clone, _vector_create, _vector_to_string,
+    FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL,
 };
 
 #[repr(C)]
 struct MapHeader {
     length: u64,
-    capacity: u64,
+    flags: u64,
+    root: *mut Node,
 }
 
+/// Header flag for maps that own their heap keys and values; `_map_free` releases them one level deep.
+const OWNS_ELEMENTS: u64 = 1;
+
 #[repr(C)]
 struct EntryRender {
     ptr: *mut u8,
@@ -24,6 +36,16 @@ struct MapRenderSlot {
     value: EntryRender,
 }
 
+impl EntryRender {
+    unsafe fn text(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            core::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
 const TAG_NIL: u8 = 0;
 const TAG_NUMBER: u8 = 1;
 const TAG_BOOLEAN: u8 = 2;
@@ -32,109 +54,21 @@ const TAG_VECTOR: u8 = 4;
 const TAG_MAP: u8 = 5;
 const TAG_KEYWORD: u8 = 6;
 const TAG_SET: u8 = 7;
-const TAG_ANY: u8 = 0xff;
-
-#[inline]
-fn padded_tag_bytes(len: usize) -> usize {
-    if len == 0 {
-        return 0;
-    }
-
-    let align = size_of::<i64>();
-    let remainder = len % align;
-    if remainder == 0 {
-        len
-    } else {
-        len + (align - remainder)
-    }
-}
-
-#[inline]
-fn map_allocation_size(len: usize) -> Option<usize> {
-    let header = size_of::<MapHeader>();
-    let padded = padded_tag_bytes(len);
-    let tags_total = padded.checked_mul(2)?;
-    let data_bytes = len.checked_mul(size_of::<i64>())?;
-    let values_total = data_bytes.checked_mul(2)?;
-    header.checked_add(tags_total)?.checked_add(values_total)
-}
-
-#[inline]
-unsafe fn map_key_tags_ptr(map: *const MapHeader) -> *const u8 {
-    (map as *const u8).add(size_of::<MapHeader>())
-}
-
-#[inline]
-unsafe fn map_key_tags_ptr_mut(map: *mut MapHeader) -> *mut u8 {
-    (map as *mut u8).add(size_of::<MapHeader>())
-}
-
-#[inline]
-unsafe fn map_value_tags_ptr(map: *const MapHeader) -> *const u8 {
-    let len = (*map).capacity as usize;
-    map_key_tags_ptr(map).add(padded_tag_bytes(len))
-}
-
-#[inline]
-unsafe fn map_value_tags_ptr_mut(map: *mut MapHeader) -> *mut u8 {
-    let len = (*map).capacity as usize;
-    map_key_tags_ptr_mut(map).add(padded_tag_bytes(len))
-}
-
-#[inline]
-unsafe fn map_key_data_ptr(map: *const MapHeader) -> *const i64 {
-    let len = (*map).capacity as usize;
-    let offset = size_of::<MapHeader>() + padded_tag_bytes(len) * 2;
-    (map as *const u8).add(offset) as *const i64
-}
-
-#[inline]
-unsafe fn map_key_data_ptr_mut(map: *mut MapHeader) -> *mut i64 {
-    let len = (*map).capacity as usize;
-    let offset = size_of::<MapHeader>() + padded_tag_bytes(len) * 2;
-    (map as *mut u8).add(offset) as *mut i64
-}
-
-#[inline]
-unsafe fn map_value_data_ptr(map: *const MapHeader) -> *const i64 {
-    let len = (*map).capacity as usize;
-    map_key_data_ptr(map).add(len)
-}
-
-#[inline]
-unsafe fn map_value_data_ptr_mut(map: *mut MapHeader) -> *mut i64 {
-    let len = (*map).capacity as usize;
-    map_key_data_ptr_mut(map).add(len)
-}
-
-unsafe fn map_allocate(len: usize) -> *mut MapHeader {
-    match map_allocation_size(len) {
-        Some(total) => {
-            let raw = _allocate(total as u64);
-            if raw.is_null() {
-                null_mut()
-            } else {
-                let header = raw as *mut MapHeader;
-                (*header).length = len as u64;
-                (*header).capacity = len as u64;
-
-                if len > 0 {
-                    let padded = padded_tag_bytes(len);
-                    let key_tags = map_key_tags_ptr_mut(header);
-                    let value_tags = map_value_tags_ptr_mut(header);
-                    let mut idx = 0usize;
-                    while idx < padded {
-                        *key_tags.add(idx) = TAG_ANY;
-                        *value_tags.add(idx) = TAG_ANY;
-                        idx += 1;
-                    }
-                }
-
-                header
-            }
-        }
-        None => null_mut(),
+const TAG_LIST: u8 = 8;
+const TAG_SYMBOL: u8 = 9;
+const TAG_LAZY_SEQ: u8 = 10;
+const TAG_ATOM: u8 = 11;
+
+/// A new map over `root`, taking over the reference to it
+unsafe fn map_allocate(root: *mut Node, length: u64) -> *mut MapHeader {
+    let map = _allocate(size_of::<MapHeader>() as u64) as *mut MapHeader;
+    if map.is_null() {
+        trie::release(root);
+        return null_mut();
     }
+    mark_block(map as *const u8, BLOCK_MAP);
+    map.write(MapHeader { length, flags: 0, root });
+    map
 }
 
 #[inline]
@@ -168,102 +102,18 @@ fn canonical_boolean(value: i64) -> i64 {
     }
 }
 
-unsafe fn map_keys_equal(stored_tag: u8, stored_value: i64, query_tag: u8, query_value: i64) -> bool {
-    if stored_tag != query_tag {
-        return false;
-    }
-
-    match stored_tag {
-        TAG_NIL | TAG_NUMBER => stored_value == query_value,
-        TAG_BOOLEAN => canonical_boolean(stored_value) == canonical_boolean(query_value),
-        TAG_STRING | TAG_KEYWORD => {
-            let left = stored_value as *const u8;
-            let right = query_value as *const u8;
-            _string_equals(left, right) != 0
-        }
-        TAG_VECTOR | TAG_MAP => stored_value == query_value,
-        _ => false,
-    }
-}
-
-unsafe fn map_find_index(map: *const MapHeader, key_tag: u8, key_value: i64) -> Option<usize> {
+/// The stored key and the value (with their tags) under `key`.
+unsafe fn map_lookup(map: *const MapHeader, key_tag: u8, key_value: i64) -> Option<(i64, u8, i64, u8)> {
     if map.is_null() {
         return None;
     }
-
-    let len = (*map).length as usize;
-    if len == 0 {
-        return None;
-    }
-
-    let key_tags = map_key_tags_ptr(map);
-    let key_data = map_key_data_ptr(map);
-
-    let mut idx = 0usize;
-    while idx < len {
-        let stored_tag = *key_tags.add(idx);
-        let stored_value = *key_data.add(idx);
-        if map_keys_equal(stored_tag, stored_value, key_tag, key_value) {
-            return Some(idx);
-        }
-        idx += 1;
-    }
-
-    None
-}
-
-unsafe fn map_copy_entries(dst: *mut MapHeader, src: *const MapHeader) {
-    if dst.is_null() || src.is_null() {
-        return;
+    if is_sorted(map as *const u8) {
+        return sorted_lookup(map as *const u8, key_tag, key_value);
     }
-
-    let len = (*src).length as usize;
-    if len == 0 {
-        return;
-    }
-
-    let src_key_tags = map_key_tags_ptr(src);
-    let src_value_tags = map_value_tags_ptr(src);
-    let src_key_data = map_key_data_ptr(src);
-    let src_value_data = map_value_data_ptr(src);
-
-    let dst_key_tags = map_key_tags_ptr_mut(dst);
-    let dst_value_tags = map_value_tags_ptr_mut(dst);
-    let dst_key_data = map_key_data_ptr_mut(dst);
-    let dst_value_data = map_value_data_ptr_mut(dst);
-
-    copy_nonoverlapping(src_key_tags, dst_key_tags, len);
-    copy_nonoverlapping(src_value_tags, dst_value_tags, len);
-    copy_nonoverlapping(src_key_data, dst_key_data, len);
-    copy_nonoverlapping(src_value_data, dst_value_data, len);
-
-    let padded = padded_tag_bytes((*dst).capacity as usize);
-    let mut idx = len;
-    while idx < padded {
-        *dst_key_tags.add(idx) = TAG_ANY;
-        *dst_value_tags.add(idx) = TAG_ANY;
-        idx += 1;
+    if is_record(map as *const u8) {
+        return record_lookup(map as *const u8, key_tag, key_value);
     }
-}
-
-#[inline]
-unsafe fn map_write_entry(map: *mut MapHeader, index: usize, key_tag: u8, key_value: i64, value_tag: u8, value_value: i64) {
-    let key_tags = map_key_tags_ptr_mut(map);
-    let value_tags = map_value_tags_ptr_mut(map);
-    let key_data = map_key_data_ptr_mut(map);
-    let value_data = map_value_data_ptr_mut(map);
-    *key_tags.add(index) = key_tag;
-    *key_data.add(index) = key_value;
-    *value_tags.add(index) = value_tag;
-    *value_data.add(index) = value_value;
-}
-
-#[inline]
-unsafe fn map_write_value(map: *mut MapHeader, index: usize, value_tag: u8, value_value: i64) {
-    let value_tags = map_value_tags_ptr_mut(map);
-    let value_data = map_value_data_ptr_mut(map);
-    *value_tags.add(index) = value_tag;
-    *value_data.add(index) = value_value;
+    trie::find((*map).root, key_tag, key_value).map(|entry| (entry.key, entry.key_tag, entry.value, entry.value_tag))
 }
 
 unsafe fn render_map_key(tag: u8, value: i64) -> EntryRender {
@@ -381,6 +231,7 @@ unsafe fn render_map_key(tag: u8, value: i64) -> EntryRender {
                 }
             }
         }
+        TAG_VECTOR | TAG_LIST | TAG_MAP | TAG_SET => render_map_value(tag, value),
         _ => EntryRender {
             ptr: NIL_LITERAL.as_ptr() as *mut u8,
             len: 3,
@@ -411,7 +262,7 @@ unsafe fn render_map_value(tag: u8, value: i64) -> EntryRender {
                 }
             }
         }
-        TAG_STRING => {
+        TAG_STRING | TAG_SYMBOL => {
             if value == 0 {
                 EntryRender {
                     ptr: NIL_LITERAL.as_ptr() as *mut u8,
@@ -483,6 +334,30 @@ unsafe fn render_map_value(tag: u8, value: i64) -> EntryRender {
                 }
             }
         }
+        TAG_LIST => {
+            if value == 0 {
+                EntryRender {
+                    ptr: NIL_LITERAL.as_ptr() as *mut u8,
+                    len: 3,
+                    owned: false,
+                }
+            } else {
+                let rendered = _list_to_string(value as *const u8);
+                if rendered.is_null() {
+                    EntryRender {
+                        ptr: NIL_LITERAL.as_ptr() as *mut u8,
+                        len: 3,
+                        owned: false,
+                    }
+                } else {
+                    EntryRender {
+                        ptr: rendered,
+                        len: _string_count(rendered) as usize,
+                        owned: true,
+                    }
+                }
+            }
+        }
         TAG_MAP => {
             if value == 0 {
                 EntryRender {
@@ -568,106 +443,58 @@ unsafe fn render_map_value(tag: u8, value: i64) -> EntryRender {
 
 unsafe fn map_clone_impl(map: *const MapHeader) -> *mut MapHeader {
     if map.is_null() {
-        return map_allocate(0);
+        return map_allocate(null_mut(), 0);
     }
-
-    let len = (*map).length as usize;
-    let cloned = map_allocate(len);
-    if cloned.is_null() {
-        return null_mut();
+    if is_sorted(map as *const u8) {
+        return sorted_clone(map as *const u8) as *mut MapHeader;
     }
-
-    map_copy_entries(cloned, map);
-    cloned
+    if is_record(map as *const u8) {
+        return record_clone(map as *const u8) as *mut MapHeader;
+    }
+    map_allocate(trie::retain((*map).root), (*map).length)
 }
 
 unsafe fn map_assoc_impl(map: *const MapHeader, key_tag: u8, key_value: i64, value_tag: u8, value_value: i64) -> *mut MapHeader {
-    if map.is_null() {
-        let new_map = map_allocate(1);
-        if new_map.is_null() {
-            return null_mut();
-        }
-        map_write_entry(new_map, 0, key_tag, key_value, value_tag, value_value);
-        return new_map;
+    if is_sorted(map as *const u8) {
+        return sorted_assoc(map as *const u8, key_value, key_tag, value_value, value_tag) as *mut MapHeader;
     }
-
-    let len = (*map).length as usize;
-    match map_find_index(map, key_tag, key_value) {
-        Some(index) => {
-            let new_map = map_allocate(len);
-            if new_map.is_null() {
-                return null_mut();
-            }
-            map_copy_entries(new_map, map);
-            map_write_value(new_map, index, value_tag, value_value);
-            new_map
-        }
-        None => {
-            let new_map = map_allocate(len + 1);
-            if new_map.is_null() {
-                return null_mut();
-            }
-            map_copy_entries(new_map, map);
-            map_write_entry(new_map, len, key_tag, key_value, value_tag, value_value);
-            (*new_map).length = (len + 1) as u64;
-            (*new_map).capacity = (len + 1) as u64;
-            new_map
+    if is_record(map as *const u8) {
+        if let Some(updated) = record_assoc(map as *const u8, key_tag, key_value, value_tag, value_value) {
+            return updated as *mut MapHeader;
         }
+        let widened = record_widen(map as *const u8) as *mut MapHeader;
+        map_put(widened, key_tag, key_value, value_tag, value_value);
+        return widened;
     }
+
+    let (root, length) = if map.is_null() { (null_mut(), 0) } else { ((*map).root, (*map).length) };
+    let entry = Entry {
+        key: key_value,
+        value: value_value,
+        key_tag,
+        value_tag,
+    };
+    let mut added = false;
+    let root = trie::insert(root, entry, &mut added);
+    map_allocate(root, length + added as u64)
 }
 
 unsafe fn map_dissoc_impl(map: *const MapHeader, key_tag: u8, key_value: i64) -> *mut MapHeader {
     if map.is_null() {
-        return map_allocate(0);
+        return map_allocate(null_mut(), 0);
     }
-
-    let len = (*map).length as usize;
-    if len == 0 {
-        return map_allocate(0);
+    if is_sorted(map as *const u8) {
+        return sorted_dissoc(map as *const u8, key_value, key_tag) as *mut MapHeader;
+    }
+    if is_record(map as *const u8) {
+        let widened = record_widen(map as *const u8);
+        let result = map_dissoc_impl(widened as *const MapHeader, key_tag, key_value);
+        _map_free(widened);
+        return result;
     }
 
-    match map_find_index(map, key_tag, key_value) {
-        Some(index) => {
-            if len == 1 {
-                return map_allocate(0);
-            }
-
-            let new_len = len - 1;
-            let new_map = map_allocate(new_len);
-            if new_map.is_null() {
-                return null_mut();
-            }
-
-            let src_key_tags = map_key_tags_ptr(map);
-            let src_value_tags = map_value_tags_ptr(map);
-            let src_key_data = map_key_data_ptr(map);
-            let src_value_data = map_value_data_ptr(map);
-
-            let dst_key_tags = map_key_tags_ptr_mut(new_map);
-            let dst_value_tags = map_value_tags_ptr_mut(new_map);
-            let dst_key_data = map_key_data_ptr_mut(new_map);
-            let dst_value_data = map_value_data_ptr_mut(new_map);
-
-            if index > 0 {
-                copy_nonoverlapping(src_key_tags, dst_key_tags, index);
-                copy_nonoverlapping(src_value_tags, dst_value_tags, index);
-                copy_nonoverlapping(src_key_data, dst_key_data, index);
-                copy_nonoverlapping(src_value_data, dst_value_data, index);
-            }
-
-            if index + 1 < len {
-                let tail_len = len - index - 1;
-                copy_nonoverlapping(src_key_tags.add(index + 1), dst_key_tags.add(index), tail_len);
-                copy_nonoverlapping(src_value_tags.add(index + 1), dst_value_tags.add(index), tail_len);
-                copy_nonoverlapping(src_key_data.add(index + 1), dst_key_data.add(index), tail_len);
-                copy_nonoverlapping(src_value_data.add(index + 1), dst_value_data.add(index), tail_len);
-            }
-
-            (*new_map).length = new_len as u64;
-            (*new_map).capacity = new_len as u64;
-
-            new_map
-        }
+    match trie::remove((*map).root, key_tag, key_value) {
+        Some(root) => map_allocate(root, (*map).length - 1),
         None => map_clone_impl(map),
     }
 }
@@ -679,7 +506,7 @@ pub unsafe extern "C" fn _map_create(keys: *const i64, key_tags: *const i64, val
     }
 
     let len = count as usize;
-    let map = map_allocate(len);
+    let map = map_allocate(null_mut(), 0);
     if map.is_null() {
         return null_mut();
     }
@@ -693,21 +520,11 @@ pub unsafe extern "C" fn _map_create(keys: *const i64, key_tags: *const i64, val
         return null_mut();
     }
 
-    let key_tags_src = key_tags;
-    let value_tags_src = value_tags;
-    let key_tags_dst = map_key_tags_ptr_mut(map);
-    let value_tags_dst = map_value_tags_ptr_mut(map);
-    let key_data_dst = map_key_data_ptr_mut(map);
-    let value_data_dst = map_value_data_ptr_mut(map);
-
     let mut idx = 0usize;
     while idx < len {
-        let key_tag = (*key_tags_src.add(idx) & 0xff) as u8;
-        let value_tag = (*value_tags_src.add(idx) & 0xff) as u8;
-        *key_tags_dst.add(idx) = key_tag;
-        *value_tags_dst.add(idx) = value_tag;
-        *key_data_dst.add(idx) = *keys.add(idx);
-        *value_data_dst.add(idx) = *values.add(idx);
+        let key_tag = (*key_tags.add(idx) & 0xff) as u8;
+        let value_tag = (*value_tags.add(idx) & 0xff) as u8;
+        map_put(map, key_tag, *keys.add(idx), value_tag, *values.add(idx));
         idx += 1;
     }
 
@@ -742,7 +559,7 @@ pub unsafe extern "C" fn _map_contains(map: *const u8, key: i64, key_tag: i64) -
     if header.is_null() {
         return 0;
     }
-    if map_find_index(header, key_tag_u8, key).is_some() {
+    if map_lookup(header, key_tag_u8, key).is_some() {
         1
     } else {
         0
@@ -760,12 +577,10 @@ pub unsafe extern "C" fn _map_get(map: *const u8, key: i64, key_tag: i64, out_va
         return 0;
     }
 
-    match map_find_index(header, key_tag_u8, key) {
-        Some(index) => {
-            let value_tags = map_value_tags_ptr(header);
-            let value_data = map_value_data_ptr(header);
-            *out_tag = *value_tags.add(index);
-            *out_value = *value_data.add(index);
+    match map_lookup(header, key_tag_u8, key) {
+        Some((_, _, value, value_tag)) => {
+            *out_tag = value_tag;
+            *out_value = value;
             1
         }
         None => 0,
@@ -782,18 +597,232 @@ pub unsafe extern "C" fn _map_count(map: *const u8) -> u64 {
     len
 }
 
+/// Set `key` to `value` in a map nothing else refers to yet and hand back the map to keep using:
+/// the same one, or a hash map of its entries when it is a record without that field.
+unsafe fn map_update(map: *mut MapHeader, key_tag: u8, key_value: i64, value_tag: u8, value_value: i64) -> *mut MapHeader {
+    if !is_record(map as *const u8) {
+        map_put(map, key_tag, key_value, value_tag, value_value);
+        return map;
+    }
+    if record_put(map as *mut u8, key_tag, key_value, value_tag, value_value) {
+        return map;
+    }
+    let widened = record_widen(map as *const u8) as *mut MapHeader;
+    _map_free(map as *mut u8);
+    map_put(widened, key_tag, key_value, value_tag, value_value);
+    widened
+}
+
+/// Set `key` to `value` in a hash or sorted map nothing else refers to yet, replacing the value of
+/// an existing key.
+unsafe fn map_put(map: *mut MapHeader, key_tag: u8, key_value: i64, value_tag: u8, value_value: i64) {
+    if is_sorted(map as *const u8) {
+        return sorted_put(map as *mut u8, key_value, key_tag, value_value, value_tag);
+    }
+    let entry = Entry {
+        key: key_value,
+        value: value_value,
+        key_tag,
+        value_tag,
+    };
+    let mut added = false;
+    let root = trie::insert((*map).root, entry, &mut added);
+    trie::release((*map).root);
+    (*map).root = root;
+    (*map).length += added as u64;
+}
+
+/// A copy of `map` (empty for null) for `map_insert` to fill, for the set helpers sharing this
+/// layout.
+pub(crate) unsafe fn map_copy(map: *const u8) -> *mut u8 {
+    map_clone_impl(map as *const MapHeader) as *mut u8
+}
+
+/// Add or replace an entry of a map built by `map_copy`.
+pub(crate) unsafe fn map_insert(map: *mut u8, key: i64, key_tag: u8, value: i64, value_tag: u8) {
+    map_put(map as *mut MapHeader, key_tag, key, value_tag, value);
+}
+
+/// Collect the keys (or values) of `map` into a list sharing them.
+unsafe fn map_column(map: *const u8, values: bool) -> *mut u8 {
+    let mut items = ItemBuffer::new();
+    let len = _map_count(map) as usize;
+    let mut idx = 0usize;
+    while idx < len {
+        let (key, key_tag, value, value_tag) = map_entry(map, idx);
+        if values {
+            items.push(value, value_tag);
+        } else {
+            items.push(key, key_tag);
+        }
+        idx += 1;
+    }
+    items.finish(false)
+}
+
+/// # Safety
+///
+/// `map` must be null or point to a managed map. The returned list shares the keys in entry order
+/// and is released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_keys(map: *const u8) -> *mut u8 {
+    map_column(map, false)
+}
+
+/// # Safety
+///
+/// `map` must be null or point to a managed map. The returned list shares the values in entry
+/// order and is released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_vals(map: *const u8) -> *mut u8 {
+    map_column(map, true)
+}
+
+/// Merge two maps; entries of `right` replace those of `left` with the same key.
+///
+/// # Safety
+///
+/// `left` and `right` must each be null or point to a managed map. The result shares their keys
+/// and values and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_merge(left: *const u8, right: *const u8) -> *mut u8 {
+    let extra = _map_count(right) as usize;
+    let mut merged = map_clone_impl(left as *const MapHeader);
+    if merged.is_null() {
+        return null_mut();
+    }
+    let mut idx = 0usize;
+    while idx < extra {
+        let (key, key_tag, value, value_tag) = map_entry(right, idx);
+        merged = map_update(merged, key_tag, key, value_tag, value);
+        idx += 1;
+    }
+    merged as *mut u8
+}
+
+/// Merge two maps, combining the values of keys present in both with `function`.
+///
+/// # Safety
+///
+/// `function` must be a compiled two-argument function whose results are described by
+/// `result_mode` and are not heap values; `left` and `right` must each be null or point to a
+/// managed map. The result shares their keys and values and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_merge_with(function: i64, left: *const u8, right: *const u8, result_mode: i64) -> *mut u8 {
+    let extra = _map_count(right) as usize;
+    let mut merged = map_clone_impl(left as *const MapHeader);
+    if merged.is_null() {
+        return null_mut();
+    }
+    let mut idx = 0usize;
+    while idx < extra {
+        let (key, key_tag, value, value_tag) = map_entry(right, idx);
+        merged = match map_lookup(merged, key_tag, key) {
+            Some((_, _, existing, _)) => {
+                let combined = invoke(function, existing, value);
+                map_update(merged, key_tag, key, mode_tag(result_mode), combined)
+            }
+            None => map_update(merged, key_tag, key, value_tag, value),
+        };
+        idx += 1;
+    }
+    merged as *mut u8
+}
+
+/// Keep only the entries of `map` whose keys appear in `keys`.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map and `keys` must be a vector, list, set or null
+/// described by `keys_tag`. The result shares the entries and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_select_keys(map: *const u8, keys: *const u8, keys_tag: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(keys, keys_tag);
+    let selected = map_clone_impl(null());
+    if selected.is_null() {
+        return null_mut();
+    }
+    let header = map as *const MapHeader;
+    while let Some((key, key_tag)) = cursor.next() {
+        if let Some((stored_key, stored_key_tag, value, value_tag)) = map_lookup(header, key_tag, key) {
+            map_put(selected, stored_key_tag, stored_key, value_tag, value);
+        }
+    }
+    selected as *mut u8
+}
+
+/// Pair up `keys` with `vals` into a map, stopping at the shorter of the two.
+///
+/// # Safety
+///
+/// `keys` and `vals` must be vectors, lists, sets or null described by their tags. The result
+/// shares the items and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_zipmap(keys: *const u8, keys_tag: i64, vals: *const u8, vals_tag: i64) -> *mut u8 {
+    let mut key_cursor = Cursor::new(keys, keys_tag);
+    let mut value_cursor = Cursor::new(vals, vals_tag);
+    let zipped = map_clone_impl(null());
+    if zipped.is_null() {
+        return null_mut();
+    }
+    while let (Some((key, key_tag)), Some((value, value_tag))) = (key_cursor.next(), value_cursor.next()) {
+        map_put(zipped, key_tag, key, value_tag, value);
+    }
+    zipped as *mut u8
+}
+
+/// The `[key value]` entry stored under `key`, or null when `map` has none.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map. The returned vector shares the stored key and
+/// value and is released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_find(map: *const u8, key: i64, key_tag: i64) -> *mut u8 {
+    match map_lookup(map as *const MapHeader, (key_tag & 0xff) as u8, key) {
+        Some((stored_key, stored_key_tag, value, value_tag)) => {
+            let values = [stored_key, value];
+            let tags = [stored_key_tag as i64, value_tag as i64];
+            _vector_create(values.as_ptr(), tags.as_ptr(), 2)
+        }
+        None => null_mut(),
+    }
+}
+
+/// Swap the keys and values of `map`; of values stored under several keys, the last entry wins.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map. The result shares its keys and values and is
+/// released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _map_invert(map: *const u8) -> *mut u8 {
+    let len = _map_count(map) as usize;
+    let inverted = map_clone_impl(null());
+    if inverted.is_null() {
+        return null_mut();
+    }
+    let mut idx = 0usize;
+    while idx < len {
+        let (key, key_tag, value, value_tag) = map_entry(map, idx);
+        map_put(inverted, value_tag, value, key_tag, key);
+        idx += 1;
+    }
+    inverted as *mut u8
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn _map_value_clone(value: i64, tag: i64) -> i64 {
     let tag_u8 = (tag & 0xff) as u8;
     match tag_u8 {
-        TAG_STRING | TAG_KEYWORD => {
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => {
             if value == 0 {
                 0
             } else {
                 _string_clone(value as *const u8) as i64
             }
         }
-        TAG_VECTOR => {
+        TAG_VECTOR | TAG_LIST => {
             if value == 0 {
                 0
             } else {
@@ -814,6 +843,8 @@ pub unsafe extern "C" fn _map_value_clone(value: i64, tag: i64) -> i64 {
                 _set_clone(value as *const u8) as i64
             }
         }
+        TAG_LAZY_SEQ => _lazy_retain(value as *mut u8) as i64,
+        TAG_ATOM => _atom_retain(value as *mut u8) as i64,
         _ => value,
     }
 }
@@ -833,8 +864,11 @@ pub unsafe extern "C" fn _map_to_string(map: *const u8) -> *mut u8 {
 
     let header = map as *const MapHeader;
     let len = (*header).length as usize;
+    // Records print as `#Name{...}`, fields in declaration order
+    let name: &[u8] = if is_record(map) { record_name(map) } else { &[] };
+    let prefix_len = if name.is_empty() { 0 } else { name.len() + 1 };
 
-    if len == 0 {
+    if len == 0 && prefix_len == 0 {
         let dst = _allocate(3);
         if dst.is_null() {
             return null_mut();
@@ -846,28 +880,20 @@ pub unsafe extern "C" fn _map_to_string(map: *const u8) -> *mut u8 {
     }
 
     let slots_size = len.checked_mul(size_of::<MapRenderSlot>()).unwrap_or(0);
-    if slots_size == 0 {
+    let slots_ptr = if slots_size == 0 { null_mut() } else { _allocate(slots_size as u64) as *mut MapRenderSlot };
+    if slots_ptr.is_null() && len > 0 {
         return null_mut();
     }
 
-    let slots_ptr = _allocate(slots_size as u64) as *mut MapRenderSlot;
-    if slots_ptr.is_null() {
-        return null_mut();
-    }
-
-    let key_tags = map_key_tags_ptr(header);
-    let value_tags = map_value_tags_ptr(header);
-    let key_data = map_key_data_ptr(header);
-    let value_data = map_value_data_ptr(header);
-
-    let mut total_len = 2usize; // '{' and '}'
+    let mut total_len = 2 + prefix_len; // '{' and '}', after any `#Name`
     let mut idx = 0usize;
     let mut overflow = false;
 
     while idx < len {
         let slot = slots_ptr.add(idx);
-        (*slot).key = render_map_key(*key_tags.add(idx), *key_data.add(idx));
-        (*slot).value = render_map_value(*value_tags.add(idx), *value_data.add(idx));
+        let (key, key_tag, value, value_tag) = map_entry(map, idx);
+        (*slot).key = render_map_key(key_tag, key);
+        (*slot).value = render_map_value(value_tag, value);
 
         if !overflow {
             total_len = match total_len.checked_add((*slot).key.len) {
@@ -913,6 +939,12 @@ pub unsafe extern "C" fn _map_to_string(map: *const u8) -> *mut u8 {
         return null_mut();
     }
 
+    // Hash order means nothing to a reader, so hash maps print by key like the interpreter's do
+    if !is_sorted(map) && !is_record(map) {
+        let slots = core::slice::from_raw_parts_mut(slots_ptr, len);
+        slots.sort_unstable_by(|left, right| left.key.text().cmp(right.key.text()));
+    }
+
     let total_with_null = match total_len.checked_add(1) {
         Some(val) => val,
         None => {
@@ -928,6 +960,11 @@ pub unsafe extern "C" fn _map_to_string(map: *const u8) -> *mut u8 {
     }
 
     let mut offset = 0usize;
+    if prefix_len > 0 {
+        *dst = b'#';
+        copy_nonoverlapping(name.as_ptr(), dst.add(1), name.len());
+        offset += prefix_len;
+    }
     *dst.add(offset) = b'{';
     offset += 1;
 
@@ -969,5 +1006,132 @@ pub unsafe extern "C" fn _map_free(map: *mut u8) {
     if map.is_null() {
         return;
     }
+
+    if is_sorted(map) {
+        return sorted_free(map);
+    }
+    if is_record(map) {
+        return record_free(map);
+    }
+
+    let header = map as *const MapHeader;
+    if (*header).flags & OWNS_ELEMENTS != 0 {
+        trie::for_each((*header).root, &mut |entry| {
+            crate::sequence::release_value(entry.key, entry.key_tag);
+            crate::sequence::release_value(entry.value, entry.value_tag);
+        });
+    }
+
+    trie::release((*header).root);
+    _free(map);
+}
+
+/// Mark `map` as owning its heap keys and values so `_map_free` releases them with it.
+///
+/// # Safety
+///
+/// The caller must ensure that `map` is either null or points to a managed map whose heap keys and
+/// values are referenced from nowhere else.
+pub(crate) unsafe fn map_mark_owning(map: *mut u8) {
+    if !map.is_null() {
+        (*(map as *mut MapHeader)).flags |= OWNS_ELEMENTS;
+    }
+}
+
+/// Read the key and value (with their tags) stored at `index`.
+///
+/// # Safety
+///
+/// The caller must ensure that `map` points to a managed map holding more than `index` entries.
+pub(crate) unsafe fn map_entry(map: *const u8, index: usize) -> (i64, u8, i64, u8) {
+    if is_sorted(map) {
+        return sorted_entry(map, index);
+    }
+    if is_record(map) {
+        return record_entry(map, index);
+    }
+    let entry = trie::entry_at((*(map as *const MapHeader)).root, index);
+    (entry.key, entry.key_tag, entry.value, entry.value_tag)
+}
+
+/// Clone a map (or set) together with every heap value reachable from it.
+///
+/// # Safety
+///
+/// The caller must ensure that `map` is either null or points to a managed map.
+pub(crate) unsafe fn map_deep_clone(map: *const u8) -> *mut u8 {
+    if map.is_null() {
+        return null_mut();
+    }
+    if is_sorted(map) {
+        return sorted_deep_clone(map);
+    }
+    if is_record(map) {
+        return record_deep_clone(map);
+    }
+
+    let header = map as *const MapHeader;
+    let root = trie::copy_with((*header).root, &mut |entry| Entry {
+        key: crate::exceptions::value_deep_clone(entry.key, entry.key_tag),
+        value: crate::exceptions::value_deep_clone(entry.value, entry.value_tag),
+        ..entry
+    });
+    map_allocate(root, (*header).length) as *mut u8
+}
+
+/// Visit the nodes of a map (or set) and the keys and values it holds.
+///
+/// # Safety
+///
+/// `map` must point to a managed map or set.
+pub(crate) unsafe fn map_visit_parts(map: *const u8, visit: &mut dyn FnMut(Part) -> bool) {
+    if is_sorted(map) {
+        sorted_visit_parts(map, visit);
+    } else if is_record(map) {
+        record_visit_parts(map, visit);
+    } else {
+        trie::visit_parts((*(map as *const MapHeader)).root, visit);
+    }
+}
+
+/// Drop the reference a map (or set) holds to its root when `stays` picks it, as unwinding an
+/// exception frees the map without releasing it.
+///
+/// # Safety
+///
+/// `map` must point to a managed map or set.
+pub(crate) unsafe fn map_release_outer(map: *const u8, stays: &dyn Fn(*const u8) -> bool) {
+    if is_sorted(map) {
+        sorted_release_outer(map, stays);
+    } else if !is_record(map) {
+        let root = (*(map as *const MapHeader)).root;
+        if stays(root as *const u8) {
+            trie::release(root);
+        }
+    }
+}
+
+/// Release a map (or set) and every heap value it owns.
+///
+/// # Safety
+///
+/// The caller must ensure that `map` is either null or a map produced by `map_deep_clone`.
+pub(crate) unsafe fn map_deep_free(map: *mut u8) {
+    if map.is_null() {
+        return;
+    }
+    if is_sorted(map) {
+        return sorted_deep_free(map);
+    }
+    if is_record(map) {
+        return record_deep_free(map);
+    }
+
+    let header = map as *const MapHeader;
+    trie::for_each((*header).root, &mut |entry| {
+        crate::exceptions::value_deep_free(entry.key, entry.key_tag);
+        crate::exceptions::value_deep_free(entry.value, entry.value_tag);
+    });
+    trie::release((*header).root);
     _free(map);
 }
@@ -0,0 +1,282 @@
+use core::mem::size_of;
+use core::ptr::{copy_nonoverlapping, null_mut};
+
+use crate::exceptions::{mark_block, Part, BLOCK_RECORD};
+use crate::map::{map_copy, map_insert};
+use crate::value::values_equal;
+use crate::{_allocate, _free, _string_count};
+
+// Records, built by the constructors `defrecord` declares, are maps with a fixed set of keyword
+// keys. Their header begins with the same length and flags as the hash maps of `map.rs`, flagged
+// `RECORD`, and the field values follow it at fixed offsets so compiled code reads a known field
+// with a single load. The header also carries the record's type id and a layout string naming the
+// type and its fields: `Point\0:x\0:y`, one NUL-terminated entry after another. Field keys point
+// into the layout, which the compiler emits as a literal, so they are never freed.
+//
+// Only `assoc` of a declared field keeps a record a record. Any other update (a new key, `dissoc`)
+// yields a hash map of the same entries, and a record equals only records of the same type.
+
+/// Header flag for records, alongside `map.rs`'s `OWNS_ELEMENTS` and `sorted.rs`'s flags
+pub(crate) const RECORD: u64 = 8;
+
+const OWNS_ELEMENTS: u64 = 1;
+const TAG_KEYWORD: u8 = 6;
+
+#[repr(C)]
+struct RecordHeader {
+    length: u64,
+    flags: u64,
+    type_id: u64,
+    layout: *const u8,
+}
+
+/// One field, at `size_of::<RecordHeader>() + index * size_of::<Field>()`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Field {
+    value: i64,
+    tag: u64,
+}
+
+unsafe fn header(record: *const u8) -> *mut RecordHeader {
+    record as *mut RecordHeader
+}
+
+unsafe fn fields<'a>(record: *const u8) -> &'a mut [Field] {
+    let base = record.add(size_of::<RecordHeader>()) as *mut Field;
+    core::slice::from_raw_parts_mut(base, (*header(record)).length as usize)
+}
+
+/// The NUL-terminated entry at `index` of a layout string: 0 names the type, 1 onwards the fields
+unsafe fn layout_entry(layout: *const u8, index: usize) -> *const u8 {
+    let mut entry = layout;
+    for _ in 0..index {
+        entry = entry.add(_string_count(entry) as usize + 1);
+    }
+    entry
+}
+
+/// A copy of `record` with its own field block; the field values are shared
+unsafe fn record_copy(record: *const u8) -> *mut u8 {
+    let length = (*header(record)).length as usize;
+    let size = size_of::<RecordHeader>() + length * size_of::<Field>();
+    let copy = _allocate(size as u64);
+    if copy.is_null() {
+        return null_mut();
+    }
+    mark_block(copy, BLOCK_RECORD);
+    copy_nonoverlapping(record, copy, size);
+    (*header(copy)).flags &= !OWNS_ELEMENTS;
+    copy
+}
+
+/// Position of the field stored under `key`
+unsafe fn field_index(record: *const u8, key_tag: u8, key: i64) -> Option<usize> {
+    let layout = (*header(record)).layout;
+    let mut entry = layout_entry(layout, 1);
+    for index in 0..(*header(record)).length as usize {
+        if values_equal(TAG_KEYWORD, entry as i64, key_tag, key) {
+            return Some(index);
+        }
+        entry = entry.add(_string_count(entry) as usize + 1);
+    }
+    None
+}
+
+/// Whether `map` is a record rather than a hash or sorted map.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map or set.
+#[inline]
+pub(crate) unsafe fn is_record(map: *const u8) -> bool {
+    !map.is_null() && (*header(map)).flags & RECORD != 0
+}
+
+/// The type id of a record, or None for any other map.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map or set.
+pub(crate) unsafe fn record_type(map: *const u8) -> Option<u64> {
+    is_record(map).then(|| (*header(map)).type_id)
+}
+
+/// The bytes of the record's type name.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_name<'a>(record: *const u8) -> &'a [u8] {
+    let layout = (*header(record)).layout;
+    core::slice::from_raw_parts(layout, _string_count(layout) as usize)
+}
+
+/// The stored key and the value (with their tags) under `key`.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_lookup(record: *const u8, key_tag: u8, key: i64) -> Option<(i64, u8, i64, u8)> {
+    field_index(record, key_tag, key).map(|index| record_entry(record, index))
+}
+
+/// The key and value (with their tags) of the field at `index`, in declaration order.
+///
+/// # Safety
+///
+/// `record` must point to a record with more than `index` fields.
+pub(crate) unsafe fn record_entry(record: *const u8, index: usize) -> (i64, u8, i64, u8) {
+    let key = layout_entry((*header(record)).layout, index + 1);
+    let field = fields(record)[index];
+    (key as i64, TAG_KEYWORD, field.value, field.tag as u8)
+}
+
+/// A new record sharing the field values of `record`.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_clone(record: *const u8) -> *mut u8 {
+    record_copy(record)
+}
+
+/// A new record with the field `key` set to `value`, or None when `key` is not one of its fields.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_assoc(record: *const u8, key_tag: u8, key: i64, value_tag: u8, value: i64) -> Option<*mut u8> {
+    let index = field_index(record, key_tag, key)?;
+    let updated = record_copy(record);
+    if !updated.is_null() {
+        fields(updated)[index] = Field { value, tag: value_tag as u64 };
+    }
+    Some(updated)
+}
+
+/// Set the field `key` to `value` in a record nothing else refers to yet, in place; false when
+/// `key` is not one of its fields.
+///
+/// # Safety
+///
+/// `record` must point to a record owned by the caller alone.
+pub(crate) unsafe fn record_put(record: *mut u8, key_tag: u8, key: i64, value_tag: u8, value: i64) -> bool {
+    match field_index(record, key_tag, key) {
+        Some(index) => {
+            fields(record)[index] = Field { value, tag: value_tag as u64 };
+            true
+        }
+        None => false,
+    }
+}
+
+/// A new hash map holding the entries of `record`, sharing its values.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_widen(record: *const u8) -> *mut u8 {
+    let map = map_copy(null_mut());
+    if map.is_null() {
+        return null_mut();
+    }
+    for index in 0..(*header(record)).length as usize {
+        let (key, key_tag, value, value_tag) = record_entry(record, index);
+        map_insert(map, key, key_tag, value, value_tag);
+    }
+    map
+}
+
+/// Visit the field values of a record.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_visit_parts(record: *const u8, visit: &mut dyn FnMut(Part) -> bool) {
+    for field in fields(record).iter() {
+        visit(Part::Value(field.value, field.tag as u8));
+    }
+}
+
+/// Release a record, and its field values when it owns them.
+///
+/// # Safety
+///
+/// `record` must point to a record that is not used afterwards.
+pub(crate) unsafe fn record_free(record: *mut u8) {
+    if (*header(record)).flags & OWNS_ELEMENTS != 0 {
+        for field in fields(record).iter() {
+            crate::sequence::release_value(field.value, field.tag as u8);
+        }
+    }
+    _free(record);
+}
+
+/// Clone a record together with every heap value reachable from it.
+///
+/// # Safety
+///
+/// `record` must point to a record.
+pub(crate) unsafe fn record_deep_clone(record: *const u8) -> *mut u8 {
+    let copy = record_copy(record);
+    if !copy.is_null() {
+        for field in fields(copy).iter_mut() {
+            field.value = crate::exceptions::value_deep_clone(field.value, field.tag as u8);
+        }
+    }
+    copy
+}
+
+/// Release a record produced by `record_deep_clone` and every heap value it owns.
+///
+/// # Safety
+///
+/// `record` must point to a record produced by `record_deep_clone`.
+pub(crate) unsafe fn record_deep_free(record: *mut u8) {
+    for field in fields(record).iter() {
+        crate::exceptions::value_deep_free(field.value, field.tag as u8);
+    }
+    _free(record);
+}
+
+/// The type id of a record, or 0 for a hash or sorted map, so compiled protocol dispatch can
+/// jump on it.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map or set.
+#[no_mangle]
+pub unsafe extern "C" fn _record_type_id(map: *const u8) -> i64 {
+    record_type(map).unwrap_or(0) as i64
+}
+
+/// Build a record of type `type_id` whose fields take `values` and `tags` in declaration order.
+///
+/// # Safety
+///
+/// `layout` must be a layout string naming `count` fields that outlives the record, and `values`
+/// and `tags` must hold `count` entries each. The record shares the values and is released with
+/// `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _record_create(type_id: i64, layout: *const u8, values: *const i64, tags: *const i64, count: u64) -> *mut u8 {
+    let length = count as usize;
+    let record = _allocate((size_of::<RecordHeader>() + length * size_of::<Field>()) as u64);
+    if record.is_null() {
+        return null_mut();
+    }
+    mark_block(record, BLOCK_RECORD);
+    (record as *mut RecordHeader).write(RecordHeader {
+        length: count,
+        flags: RECORD,
+        type_id: type_id as u64,
+        layout,
+    });
+    for (index, field) in fields(record).iter_mut().enumerate() {
+        *field = Field {
+            value: *values.add(index),
+            tag: (*tags.add(index) & 0xff) as u64,
+        };
+    }
+    record
+}
@@ -0,0 +1,921 @@
+use core::arch::asm;
+use core::mem::size_of;
+use core::ptr::{copy_nonoverlapping, null, null_mut};
+
+use crate::exceptions::throw_message;
+use crate::lazy::lazy_item;
+use crate::map::{map_entry, map_mark_owning};
+use crate::vector::{list_items_from, vector_element, vector_mark_owning, vector_set_element};
+use crate::{
+    _allocate, _atom_free, _free, _lazy_drop, _lazy_free, _lazy_retain, _lazy_stream, _map_assoc, _map_clone, _map_count, _map_free, _map_get, _map_value_clone, _set_free, _string_count, _vector_count,
+    _vector_create, _vector_free,
+};
+
+// Sequence helpers back the compiled higher-order library (`map`, `filter`, `reduce`, ...). They
+// walk vectors, lists and sets in place; strings and maps are first expanded with `_seq_items` into
+// a list that owns the characters or `[key value]` entries, so every item a helper sees is borrowed.
+//
+// Callbacks are compiled functions passed by address. Their results are described by a
+// `result_mode`: the low byte is the tag of the values the callback returns and `RESULT_OWNED`
+// marks results the caller owns. Owned results that end up in a returned collection make that
+// collection own its elements (see `OWNS_ELEMENTS` in vector.rs and map.rs); discarded ones are
+// released straight away.
+//
+// The seq protocol (`_seq_first`, `_seq_rest`, `_seq_next`, `_seq_of`, `_seq_nth`, `_seq_last`,
+// `_seq_empty`) walks a tagged vector, list, set, nil or lazy sequence without the caller knowing
+// which one it holds. Items come back borrowed from the collection, and the remaining items come
+// back as a list sharing them, or as another lazy sequence for a lazy one.
+
+const TAG_NIL: u8 = 0;
+const TAG_NUMBER: u8 = 1;
+const TAG_BOOLEAN: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_VECTOR: u8 = 4;
+const TAG_MAP: u8 = 5;
+const TAG_KEYWORD: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_LIST: u8 = 8;
+const TAG_SYMBOL: u8 = 9;
+const TAG_LAZY_SEQ: u8 = 10;
+const TAG_ATOM: u8 = 11;
+
+/// `result_mode` bit for callbacks whose results are owned by the caller.
+const RESULT_OWNED: i64 = 0x100;
+
+static NOT_SEQUENCE_MESSAGE: [u8; 24] = *b"Value is not a sequence\0";
+static RANGE_STEP_MESSAGE: [u8; 28] = *b"range step must not be zero\0";
+static EMPTY_REDUCE_MESSAGE: [u8; 53] = *b"reduce of an empty collection needs an initial value\0";
+static MAP_ENTRY_MESSAGE: [u8; 37] = *b"into a map needs [key value] entries\0";
+pub(crate) static INDEX_BOUNDS_MESSAGE: [u8; 20] = *b"Index out of bounds\0";
+pub(crate) static OUT_OF_MEMORY_MESSAGE: [u8; 14] = *b"Out of memory\0";
+
+/// Release a heap value owned by a container that owns its elements.
+///
+/// # Safety
+///
+/// Heap tags require `value` to be null or point to a managed value of that kind that is referenced
+/// from nowhere else.
+pub(crate) unsafe fn release_value(value: i64, tag: u8) {
+    if value == 0 {
+        return;
+    }
+
+    match tag {
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => _free(value as *mut u8),
+        TAG_VECTOR | TAG_LIST => _vector_free(value as *mut u8),
+        TAG_MAP => _map_free(value as *mut u8),
+        TAG_SET => _set_free(value as *mut u8),
+        TAG_LAZY_SEQ => _lazy_free(value as *mut u8),
+        TAG_ATOM => _atom_free(value as *mut u8),
+        _ => {}
+    }
+}
+
+#[inline]
+pub(crate) fn mode_tag(result_mode: i64) -> u8 {
+    (result_mode & 0xff) as u8
+}
+
+#[inline]
+pub(crate) fn mode_owned(result_mode: i64) -> bool {
+    result_mode & RESULT_OWNED != 0
+}
+
+/// Release a callback result that is not kept.
+#[inline]
+pub(crate) unsafe fn discard_result(value: i64, result_mode: i64) {
+    if mode_owned(result_mode) {
+        release_value(value, mode_tag(result_mode));
+    }
+}
+
+/// Call a compiled callback. Compiled code uses rbx as scratch, so it is saved around the call.
+#[inline(never)]
+pub(crate) unsafe fn invoke(function: i64, first: i64, second: i64) -> i64 {
+    let result: i64;
+    asm!(
+        "push rbx",
+        "sub rsp, 8",
+        "call {function}",
+        "add rsp, 8",
+        "pop rbx",
+        function = in(reg) function,
+        in("rdi") first,
+        in("rsi") second,
+        lateout("rax") result,
+        clobber_abi("C"),
+    );
+    result
+}
+
+/// Walks the items of a vector, list or set (nil is empty).
+pub(crate) struct Cursor {
+    coll: *const u8,
+    is_set: bool,
+    is_lazy: bool,
+    index: usize,
+    len: usize,
+}
+
+impl Cursor {
+    pub(crate) unsafe fn new(coll: *const u8, coll_tag: i64) -> Cursor {
+        let tag = (coll_tag & 0xff) as u8;
+        if coll.is_null() {
+            return Cursor {
+                coll,
+                is_set: false,
+                is_lazy: false,
+                index: 0,
+                len: 0,
+            };
+        }
+
+        match tag {
+            TAG_VECTOR | TAG_LIST => Cursor {
+                coll,
+                is_set: false,
+                is_lazy: false,
+                index: 0,
+                len: _vector_count(coll) as usize,
+            },
+            TAG_SET => Cursor {
+                coll,
+                is_set: true,
+                is_lazy: false,
+                index: 0,
+                len: _map_count(coll) as usize,
+            },
+            TAG_NIL => Cursor {
+                coll: null(),
+                is_set: false,
+                is_lazy: false,
+                index: 0,
+                len: 0,
+            },
+            _ => throw_message(&NOT_SEQUENCE_MESSAGE),
+        }
+    }
+
+    /// Like `new`, but a lazy sequence is walked too, realizing each item as it is reached. Its
+    /// items are borrowed only until the next one is read.
+    unsafe fn in_order(coll: *const u8, coll_tag: i64) -> Cursor {
+        if coll.is_null() || (coll_tag & 0xff) as u8 != TAG_LAZY_SEQ {
+            return Cursor::new(coll, coll_tag);
+        }
+        Cursor {
+            coll,
+            is_set: false,
+            is_lazy: true,
+            index: 0,
+            len: usize::MAX,
+        }
+    }
+
+    pub(crate) unsafe fn next(&mut self) -> Option<(i64, u8)> {
+        if self.index >= self.len {
+            return None;
+        }
+
+        let item = if self.is_lazy {
+            match lazy_item(self.coll as *mut u8, self.index) {
+                Some(item) => item,
+                None => {
+                    self.len = self.index;
+                    return None;
+                }
+            }
+        } else if self.is_set {
+            let (key, key_tag, _, _) = map_entry(self.coll, self.index);
+            (key, key_tag)
+        } else {
+            vector_element(self.coll, self.index)
+        };
+        self.index += 1;
+        Some(item)
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.len - self.index
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.index = self.index.saturating_add(count).min(self.len);
+    }
+}
+
+/// Growable value/tag storage used to assemble result lists.
+pub(crate) struct ItemBuffer {
+    values: *mut i64,
+    tags: *mut i64,
+    len: usize,
+    capacity: usize,
+}
+
+impl ItemBuffer {
+    pub(crate) fn new() -> ItemBuffer {
+        ItemBuffer {
+            values: null_mut(),
+            tags: null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    pub(crate) unsafe fn push(&mut self, value: i64, tag: u8) {
+        self.push_tagged(value, tag as i64);
+    }
+
+    /// Push an item whose stored tag carries extra bits above the value tag.
+    pub(crate) unsafe fn push_tagged(&mut self, value: i64, tag: i64) {
+        if self.len == self.capacity {
+            let capacity = if self.capacity == 0 { 8 } else { self.capacity * 2 };
+            let bytes = (capacity * size_of::<i64>()) as u64;
+            let values = _allocate(bytes) as *mut i64;
+            let tags = _allocate(bytes) as *mut i64;
+            if values.is_null() || tags.is_null() {
+                _free(values as *mut u8);
+                _free(tags as *mut u8);
+                throw_message(&OUT_OF_MEMORY_MESSAGE);
+            }
+            if self.len > 0 {
+                copy_nonoverlapping(self.values, values, self.len);
+                copy_nonoverlapping(self.tags, tags, self.len);
+            }
+            _free(self.values as *mut u8);
+            _free(self.tags as *mut u8);
+            self.values = values;
+            self.tags = tags;
+            self.capacity = capacity;
+        }
+
+        *self.values.add(self.len) = value;
+        *self.tags.add(self.len) = tag;
+        self.len += 1;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The heap blocks holding the values and the tags, null before the first push.
+    pub(crate) fn blocks(&self) -> [*const u8; 2] {
+        [self.values as *const u8, self.tags as *const u8]
+    }
+
+    /// Overwrite the item at `index`, which must be below `len`.
+    pub(crate) unsafe fn set(&mut self, index: usize, value: i64, tag: i64) {
+        *self.values.add(index) = value;
+        *self.tags.add(index) = tag;
+    }
+
+    /// The value and stored tag at `index`, which must be below `len`.
+    pub(crate) unsafe fn get(&self, index: usize) -> (i64, i64) {
+        (*self.values.add(index), *self.tags.add(index))
+    }
+
+    /// Release the buffer without building a list.
+    pub(crate) unsafe fn release(self) {
+        _free(self.values as *mut u8);
+        _free(self.tags as *mut u8);
+    }
+
+    /// Build a list from the buffered items, optionally owning them, and release the buffer.
+    pub(crate) unsafe fn finish(self, owning: bool) -> *mut u8 {
+        let list = _vector_create(self.values, self.tags, self.len as u64);
+        _free(self.values as *mut u8);
+        _free(self.tags as *mut u8);
+        if owning {
+            vector_mark_owning(list);
+        }
+        list
+    }
+}
+
+/// Copy a one-character string starting at `src`, returning it with the number of bytes consumed.
+unsafe fn copy_char(src: *const u8) -> (*mut u8, usize) {
+    let lead = *src;
+    let mut width = if lead < 0x80 {
+        1
+    } else if lead >> 5 == 0b110 {
+        2
+    } else if lead >> 4 == 0b1110 {
+        3
+    } else {
+        4
+    };
+
+    let mut idx = 1;
+    while idx < width {
+        if *src.add(idx) == 0 {
+            width = idx;
+            break;
+        }
+        idx += 1;
+    }
+
+    let dst = _allocate(width as u64 + 1);
+    copy_nonoverlapping(src, dst, width);
+    *dst.add(width) = 0;
+    (dst, width)
+}
+
+/// # Safety
+///
+/// `coll` must be null or point to a managed value described by `coll_tag`. Strings yield a list of
+/// one-character strings and maps a list of `[key value]` vectors; the list owns those items. Other
+/// collections yield a list sharing their items. Release the result with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_items(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let tag = (coll_tag & 0xff) as u8;
+    let mut items = ItemBuffer::new();
+
+    if coll.is_null() {
+        return items.finish(false);
+    }
+
+    match tag {
+        TAG_STRING => {
+            let len = _string_count(coll) as usize;
+            let mut offset = 0usize;
+            while offset < len {
+                let (ch, width) = copy_char(coll.add(offset));
+                items.push(ch as i64, TAG_STRING);
+                offset += width;
+            }
+            items.finish(true)
+        }
+        TAG_MAP => {
+            let len = _map_count(coll) as usize;
+            let mut idx = 0usize;
+            while idx < len {
+                let (key, key_tag, value, value_tag) = map_entry(coll, idx);
+                let values = [key, value];
+                let tags = [key_tag as i64, value_tag as i64];
+                let entry = _vector_create(values.as_ptr(), tags.as_ptr(), 2);
+                items.push(entry as i64, TAG_VECTOR);
+                idx += 1;
+            }
+            items.finish(true)
+        }
+        _ => {
+            let mut cursor = Cursor::new(coll, coll_tag);
+            while let Some((value, item_tag)) = cursor.next() {
+                items.push(value, item_tag);
+            }
+            items.finish(false)
+        }
+    }
+}
+
+/// # Safety
+///
+/// `function` must be a compiled one-argument function, `coll` a vector, list, set or null
+/// described by `coll_tag`, and `result_mode` must describe the callback results. Release the
+/// returned list with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_map(function: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let mut results = ItemBuffer::new();
+    while let Some((value, _)) = cursor.next() {
+        results.push(invoke(function, value, 0), mode_tag(result_mode));
+    }
+    results.finish(mode_owned(result_mode))
+}
+
+/// Keep the items whose predicate result is truthy (`keep` = 1) or falsey (`keep` = 0).
+///
+/// # Safety
+///
+/// Same requirements as `_seq_map`. The returned list shares the kept items.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_filter(function: i64, coll: *const u8, coll_tag: i64, result_mode: i64, keep: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let mut results = ItemBuffer::new();
+    while let Some((value, tag)) = cursor.next() {
+        let verdict = invoke(function, value, 0);
+        discard_result(verdict, result_mode);
+        if (verdict != 0) == (keep != 0) {
+            results.push(value, tag);
+        }
+    }
+    results.finish(false)
+}
+
+/// Fold `coll` into `init` with a two-argument `function`.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_map`; `init` must be described by `init_tag`. `coll` may also be a
+/// lazy sequence, walked in order. The result is always owned by the caller: an accumulator that
+/// never came from the callback is cloned.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_reduce(function: i64, init: i64, init_tag: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> i64 {
+    let cursor = Cursor::in_order(coll, coll_tag);
+    reduce_from(function, cursor, init, (init_tag & 0xff) as u8, false, result_mode)
+}
+
+/// `_seq_reduce` reading `coll` for the last time: a lazy sequence the caller holds the only
+/// reference to frees its items as the fold passes them.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_reduce`; the caller releases `coll` without reading it again.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_reduce_last_read(function: i64, init: i64, init_tag: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> i64 {
+    _seq_reduce(function, init, init_tag, last_read(coll, coll_tag), coll_tag, result_mode)
+}
+
+/// Fold `coll` starting from its first item. An empty collection calls the zero-argument
+/// `empty_function` instead (0 when the callback has no such arity).
+///
+/// # Safety
+///
+/// Same requirements as `_seq_reduce`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_reduce_first(function: i64, empty_function: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> i64 {
+    let mut cursor = Cursor::in_order(coll, coll_tag);
+    match cursor.next() {
+        Some((first, first_tag)) if cursor.is_lazy => reduce_from(function, cursor, _map_value_clone(first, first_tag as i64), first_tag, true, result_mode),
+        Some((first, first_tag)) => reduce_from(function, cursor, first, first_tag, false, result_mode),
+        None => {
+            if empty_function == 0 {
+                throw_message(&EMPTY_REDUCE_MESSAGE);
+            }
+            let result = invoke(empty_function, 0, 0);
+            if mode_owned(result_mode) {
+                result
+            } else {
+                _map_value_clone(result, mode_tag(result_mode) as i64)
+            }
+        }
+    }
+}
+
+/// `_seq_reduce_first` reading `coll` for the last time, like `_seq_reduce_last_read`.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_reduce_last_read`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_reduce_first_last_read(function: i64, empty_function: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> i64 {
+    _seq_reduce_first(function, empty_function, last_read(coll, coll_tag), coll_tag, result_mode)
+}
+
+/// A lazy sequence's items go once the cursor moves past them, so over one the accumulator is kept
+/// as a clone of whatever the callback returns.
+unsafe fn reduce_from(function: i64, mut cursor: Cursor, init: i64, init_tag: u8, init_owned: bool, result_mode: i64) -> i64 {
+    let mut acc = init;
+    let mut acc_tag = init_tag;
+    let mut acc_owned = init_owned;
+
+    while let Some((value, _)) = cursor.next() {
+        let mut next = invoke(function, acc, value);
+        let mut next_owned = mode_owned(result_mode);
+        if cursor.is_lazy && !next_owned {
+            next = _map_value_clone(next, mode_tag(result_mode) as i64);
+            next_owned = true;
+        }
+        if acc_owned {
+            release_value(acc, acc_tag);
+        }
+        acc = next;
+        acc_tag = mode_tag(result_mode);
+        acc_owned = next_owned;
+    }
+
+    if acc_owned {
+        acc
+    } else {
+        _map_value_clone(acc, acc_tag as i64)
+    }
+}
+
+/// # Safety
+///
+/// Same requirements as `_seq_map`. Returns 1 when the predicate holds for every item.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_every(function: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> i64 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    while let Some((value, _)) = cursor.next() {
+        let verdict = invoke(function, value, 0);
+        discard_result(verdict, result_mode);
+        if verdict == 0 {
+            return 0;
+        }
+    }
+    1
+}
+
+/// # Safety
+///
+/// Same requirements as `_seq_map`. Returns the first truthy callback result, or nil.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_some(function: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> i64 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    while let Some((value, _)) = cursor.next() {
+        let result = invoke(function, value, 0);
+        if result != 0 {
+            return result;
+        }
+        discard_result(result, result_mode);
+    }
+    0
+}
+
+/// # Safety
+///
+/// Always safe to call; throws when `step` is zero. Release the returned list with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_range(start: i64, end: i64, step: i64) -> *mut u8 {
+    if step == 0 {
+        throw_message(&RANGE_STEP_MESSAGE);
+    }
+
+    // The list is built at its final size, so a range takes one block rather than a growing buffer
+    let span = if step > 0 { end as i128 - start as i128 } else { start as i128 - end as i128 };
+    let stride = (step as i128).abs();
+    let len = if span > 0 { (span + stride - 1) / stride } else { 0 };
+    if len > u64::MAX as i128 {
+        throw_message(&OUT_OF_MEMORY_MESSAGE);
+    }
+
+    let list = _vector_create(null(), null(), len as u64);
+    if list.is_null() {
+        throw_message(&OUT_OF_MEMORY_MESSAGE);
+    }
+    let mut index = 0;
+    while (index as i128) < len {
+        let value = start as i128 + index as i128 * step as i128;
+        vector_set_element(list, index, value as i64, TAG_NUMBER);
+        index += 1;
+    }
+    list
+}
+
+/// Add the items of `coll` to `to`: vectors append, lists (and nil) prepend, sets and maps
+/// associate; a map target takes `[key value]` entries.
+///
+/// # Safety
+///
+/// `to` and `coll` must be null or point to managed values described by their tags; `coll` must be
+/// a vector, list or set. The result shares the items of both and is released with the free
+/// function for `to_tag`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_into(to: *const u8, to_tag: i64, coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let target_tag = (to_tag & 0xff) as u8;
+    let mut cursor = Cursor::new(coll, coll_tag);
+
+    match target_tag {
+        TAG_SET | TAG_MAP => {
+            let mut current = _map_clone(to);
+            while let Some((value, tag)) = cursor.next() {
+                let next = if target_tag == TAG_SET {
+                    _map_assoc(current, value, tag as i64, 1, TAG_BOOLEAN as i64)
+                } else {
+                    if (tag != TAG_VECTOR && tag != TAG_LIST) || _vector_count(value as *const u8) != 2 {
+                        _map_free(current);
+                        throw_message(&MAP_ENTRY_MESSAGE);
+                    }
+                    let (key, key_tag) = vector_element(value as *const u8, 0);
+                    let (entry_value, entry_tag) = vector_element(value as *const u8, 1);
+                    _map_assoc(current, key, key_tag as i64, entry_value, entry_tag as i64)
+                };
+                _map_free(current);
+                current = next;
+            }
+            current
+        }
+        TAG_VECTOR => {
+            let mut results = ItemBuffer::new();
+            let mut existing = Cursor::new(to, to_tag);
+            while let Some((value, tag)) = existing.next() {
+                results.push(value, tag);
+            }
+            while let Some((value, tag)) = cursor.next() {
+                results.push(value, tag);
+            }
+            results.finish(false)
+        }
+        TAG_LIST | TAG_NIL => {
+            let mut added = ItemBuffer::new();
+            while let Some((value, tag)) = cursor.next() {
+                added.push(value, tag);
+            }
+
+            let mut results = ItemBuffer::new();
+            let mut idx = added.len;
+            while idx > 0 {
+                idx -= 1;
+                results.push(*added.values.add(idx), *added.tags.add(idx) as u8);
+            }
+            let mut existing = Cursor::new(to, to_tag);
+            while let Some((value, tag)) = existing.next() {
+                results.push(value, tag);
+            }
+            added.release();
+            results.finish(false)
+        }
+        _ => throw_message(&NOT_SEQUENCE_MESSAGE),
+    }
+}
+
+/// # Safety
+///
+/// `coll` must be a vector, list, set or null described by `coll_tag`. The returned list shares the
+/// first `count` items.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_take(count: i64, coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let mut results = ItemBuffer::new();
+    let mut taken = 0i64;
+    while taken < count {
+        match cursor.next() {
+            Some((value, tag)) => results.push(value, tag),
+            None => break,
+        }
+        taken += 1;
+    }
+    results.finish(false)
+}
+
+/// # Safety
+///
+/// Same requirements as `_seq_take`. The returned list shares the items after the first `count`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_drop(count: i64, coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let mut results = ItemBuffer::new();
+    let mut skipped = 0i64;
+    while let Some((value, tag)) = cursor.next() {
+        if skipped < count {
+            skipped += 1;
+            continue;
+        }
+        results.push(value, tag);
+    }
+    results.finish(false)
+}
+
+/// # Safety
+///
+/// `left` and `right` must be vectors, lists, sets or null described by their tags. The returned
+/// list shares the items of both.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_concat(left: *const u8, left_tag: i64, right: *const u8, right_tag: i64) -> *mut u8 {
+    let mut results = ItemBuffer::new();
+    let mut cursor = Cursor::new(left, left_tag);
+    while let Some((value, tag)) = cursor.next() {
+        results.push(value, tag);
+    }
+    let mut cursor = Cursor::new(right, right_tag);
+    while let Some((value, tag)) = cursor.next() {
+        results.push(value, tag);
+    }
+    results.finish(false)
+}
+
+/// # Safety
+///
+/// `coll` must be a vector, list, set or null described by `coll_tag`. The returned list shares the
+/// items in reverse order.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_reverse(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let mut items = ItemBuffer::new();
+    while let Some((value, tag)) = cursor.next() {
+        items.push(value, tag);
+    }
+    let mut results = ItemBuffer::new();
+    let mut index = items.len();
+    while index > 0 {
+        index -= 1;
+        let (value, tag) = items.get(index);
+        results.push_tagged(value, tag);
+    }
+    items.release();
+    results.finish(false)
+}
+
+/// # Safety
+///
+/// `coll` must be a vector, list, set or null described by `coll_tag`. The returned map shares the
+/// items as keys and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_frequencies(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let mut counts = _map_clone(null());
+
+    while let Some((value, tag)) = cursor.next() {
+        let mut seen = 0i64;
+        let mut seen_tag = 0u8;
+        if _map_get(counts, value, tag as i64, &mut seen, &mut seen_tag) == 0 {
+            seen = 0;
+        }
+        counts = replace_map(counts, _map_assoc(counts, value, tag as i64, seen + 1, TAG_NUMBER as i64));
+    }
+    counts
+}
+
+/// Group the items of `coll` into vectors keyed by the callback result.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_map`. The returned map owns its keys and group vectors (which share
+/// the items) and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_group_by(function: i64, coll: *const u8, coll_tag: i64, result_mode: i64) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let key_tag = mode_tag(result_mode);
+    let mut groups = _map_clone(null());
+
+    while let Some((value, tag)) = cursor.next() {
+        let key = invoke(function, value, 0);
+        let mut group = 0i64;
+        let mut group_tag = 0u8;
+        let known = _map_get(groups, key, key_tag as i64, &mut group, &mut group_tag) != 0;
+
+        let mut extended = ItemBuffer::new();
+        let mut existing = Cursor::new(if known { group as *const u8 } else { null() }, TAG_VECTOR as i64);
+        while let Some((item, item_tag)) = existing.next() {
+            extended.push(item, item_tag);
+        }
+        extended.push(value, tag);
+        let extended = extended.finish(false);
+
+        if known {
+            // Reassociating keeps the stored key, so the repeated one is released afterwards.
+            _vector_free(group as *mut u8);
+            groups = replace_map(groups, _map_assoc(groups, key, key_tag as i64, extended as i64, TAG_VECTOR as i64));
+            discard_result(key, result_mode);
+        } else {
+            let stored_key = if mode_owned(result_mode) { key } else { _map_value_clone(key, key_tag as i64) };
+            groups = replace_map(groups, _map_assoc(groups, stored_key, key_tag as i64, extended as i64, TAG_VECTOR as i64));
+        }
+    }
+
+    map_mark_owning(groups);
+    groups
+}
+
+#[inline]
+unsafe fn replace_map(old: *mut u8, new: *mut u8) -> *mut u8 {
+    if old != new {
+        _map_free(old);
+    }
+    new
+}
+
+/// `coll` as a helper reading it for the last time sees it: a lazy sequence is marked to stream.
+unsafe fn last_read(coll: *const u8, coll_tag: i64) -> *const u8 {
+    if (coll_tag & 0xff) as u8 == TAG_LAZY_SEQ {
+        return _lazy_stream(coll as *mut u8);
+    }
+    coll
+}
+
+/// The item at `index` of a vector, list, set, nil or lazy sequence, borrowed from it.
+unsafe fn item_at(coll: *const u8, coll_tag: i64, index: usize) -> Option<(i64, u8)> {
+    if (coll_tag & 0xff) as u8 == TAG_LAZY_SEQ {
+        return lazy_item(coll as *mut u8, index);
+    }
+    let mut cursor = Cursor::new(coll, coll_tag);
+    cursor.skip(index);
+    cursor.next()
+}
+
+/// The items of a vector, list, set or nil from `start` on, as a list sharing them. A vector or
+/// list gives a view, so stepping through one with `rest` does not copy it again at every step.
+unsafe fn items_from(coll: *const u8, coll_tag: i64, start: usize) -> *mut u8 {
+    if !coll.is_null() && matches!((coll_tag & 0xff) as u8, TAG_VECTOR | TAG_LIST) {
+        return list_items_from(coll, start);
+    }
+    let mut cursor = Cursor::new(coll, coll_tag);
+    cursor.skip(start);
+    let mut items = ItemBuffer::new();
+    while let Some((value, tag)) = cursor.next() {
+        items.push(value, tag);
+    }
+    items.finish(false)
+}
+
+/// The first item (nil when there is none).
+///
+/// # Safety
+///
+/// `coll` must be null or a vector, list, set or lazy sequence described by `coll_tag`. The item is
+/// borrowed from `coll`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_first(coll: *const u8, coll_tag: i64) -> i64 {
+    item_at(coll, coll_tag, 0).map_or(0, |(value, _)| value)
+}
+
+/// Everything after the first item: a possibly empty list, or a lazy sequence for a lazy one.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_first`. A list result shares the items and is released with
+/// `_vector_free`; a lazy result retains `coll` and is released with `_lazy_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_rest(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    if (coll_tag & 0xff) as u8 == TAG_LAZY_SEQ {
+        return _lazy_drop(1, coll as *mut u8);
+    }
+    items_from(coll, coll_tag, 1)
+}
+
+/// Like `_seq_rest`, but null when no items remain so the result can be tested for truth.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_rest`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_next(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    if item_at(coll, coll_tag, 1).is_none() {
+        return null_mut();
+    }
+    _seq_rest(coll, coll_tag)
+}
+
+/// The items as a sequence: null when there are none, otherwise a list sharing them or the lazy
+/// sequence itself.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_rest`; a lazy result is another reference to `coll`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_of(coll: *const u8, coll_tag: i64) -> *mut u8 {
+    if item_at(coll, coll_tag, 0).is_none() {
+        return null_mut();
+    }
+    if (coll_tag & 0xff) as u8 == TAG_LAZY_SEQ {
+        return _lazy_retain(coll as *mut u8);
+    }
+    items_from(coll, coll_tag, 0)
+}
+
+/// The item at `index`. An index out of range gives `default` when `has_default` is 1 and throws
+/// otherwise; a lazy sequence is realized only up to `index`.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_first`. The item is borrowed from `coll` and `default` is returned
+/// as it is.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_nth(coll: *const u8, coll_tag: i64, index: i64, default: i64, has_default: i64) -> i64 {
+    let item = if index < 0 { None } else { item_at(coll, coll_tag, index as usize) };
+    match item {
+        Some((value, _)) => value,
+        None if has_default != 0 => default,
+        None => throw_message(&INDEX_BOUNDS_MESSAGE),
+    }
+}
+
+/// `_seq_nth` reading `coll` for the last time: a lazy sequence the caller holds the only
+/// reference to keeps just the chunk holding the item.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_nth`; the caller releases `coll` without reading it again.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_nth_last_read(coll: *const u8, coll_tag: i64, index: i64, default: i64, has_default: i64) -> i64 {
+    _seq_nth(last_read(coll, coll_tag), coll_tag, index, default, has_default)
+}
+
+/// The last item (nil when there is none); does not return for an unbounded lazy sequence.
+///
+/// # Safety
+///
+/// Same requirements as `_seq_first`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_last(coll: *const u8, coll_tag: i64) -> i64 {
+    if (coll_tag & 0xff) as u8 == TAG_LAZY_SEQ {
+        let mut last = 0;
+        let mut index = 0usize;
+        while let Some((value, _)) = item_at(coll, coll_tag, index) {
+            last = value;
+            index += 1;
+        }
+        return last;
+    }
+
+    let mut cursor = Cursor::new(coll, coll_tag);
+    cursor.skip(cursor.remaining().saturating_sub(1));
+    cursor.next().map_or(0, |(value, _)| value)
+}
+
+/// 1 when a collection, string or lazy sequence has no items, 0 otherwise.
+///
+/// # Safety
+///
+/// `coll` must be null or a managed value described by `coll_tag`. A lazy sequence realizes at
+/// most its first item.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_empty(coll: *const u8, coll_tag: i64) -> i64 {
+    let empty = match (coll_tag & 0xff) as u8 {
+        _ if coll.is_null() => true,
+        TAG_STRING => _string_count(coll) == 0,
+        TAG_MAP => _map_count(coll) == 0,
+        _ => item_at(coll, coll_tag, 0).is_none(),
+    };
+    empty as i64
+}
@@ -1,17 +1,14 @@
 use core::mem::size_of;
 use core::ptr::{copy_nonoverlapping, null_mut};
 
+use crate::map::{map_copy, map_entry, map_insert};
+use crate::sequence::{discard_result, invoke};
+use crate::sorted::is_sorted;
 use crate::{
-    _allocate, _free, _map_assoc, _map_clone, _map_contains, _map_count, _map_create, _map_dissoc, _map_free, _map_to_string, _string_clone, _string_count, _string_from_number, _vector_to_string,
-    FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL,
+    _allocate, _free, _list_to_string, _map_assoc, _map_clone, _map_contains, _map_count, _map_create, _map_dissoc, _map_free, _map_to_string, _string_clone, _string_count, _string_from_number,
+    _vector_to_string, FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL,
 };
 
-#[repr(C)]
-struct MapHeader {
-    length: u64,
-    capacity: u64,
-}
-
 #[repr(C)]
 struct EntryRender {
     ptr: *mut u8,
@@ -19,6 +16,16 @@ struct EntryRender {
     owned: bool,
 }
 
+impl EntryRender {
+    unsafe fn text(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            core::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
 const TAG_NIL: u8 = 0;
 const TAG_NUMBER: u8 = 1;
 const TAG_BOOLEAN: u8 = 2;
@@ -26,35 +33,10 @@ const TAG_STRING: u8 = 3;
 const TAG_VECTOR: u8 = 4;
 const TAG_MAP: u8 = 5;
 const TAG_KEYWORD: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_LIST: u8 = 8;
 const TAG_BOOLEAN_I64: i64 = TAG_BOOLEAN as i64;
 
-#[inline]
-fn padded_tag_bytes(len: usize) -> usize {
-    if len == 0 {
-        return 0;
-    }
-
-    let align = size_of::<i64>();
-    let remainder = len % align;
-    if remainder == 0 {
-        len
-    } else {
-        len + (align - remainder)
-    }
-}
-
-#[inline]
-unsafe fn map_key_tags_ptr(map: *const MapHeader) -> *const u8 {
-    (map as *const u8).add(size_of::<MapHeader>())
-}
-
-#[inline]
-unsafe fn map_key_data_ptr(map: *const MapHeader) -> *const i64 {
-    let len = (*map).capacity as usize;
-    let offset = size_of::<MapHeader>() + padded_tag_bytes(len) * 2;
-    (map as *const u8).add(offset) as *const i64
-}
-
 #[inline]
 unsafe fn release_entry(entry: &EntryRender) {
     if entry.owned && !entry.ptr.is_null() {
@@ -200,51 +182,25 @@ unsafe fn render_set_entry(tag: u8, value: i64) -> EntryRender {
                 }
             }
         }
-        TAG_VECTOR => {
-            if value == 0 {
+        TAG_VECTOR | TAG_LIST | TAG_MAP | TAG_SET => {
+            let rendered = match tag {
+                TAG_VECTOR if value != 0 => _vector_to_string(value as *const u8),
+                TAG_LIST => _list_to_string(value as *const u8),
+                TAG_MAP if value != 0 => _map_to_string(value as *const u8),
+                TAG_SET => _set_to_string(value as *const u8),
+                _ => null_mut(),
+            };
+            if rendered.is_null() {
                 EntryRender {
                     ptr: NIL_LITERAL.as_ptr() as *mut u8,
                     len: 3,
                     owned: false,
                 }
             } else {
-                let rendered = _vector_to_string(value as *const u8);
-                if rendered.is_null() {
-                    EntryRender {
-                        ptr: NIL_LITERAL.as_ptr() as *mut u8,
-                        len: 3,
-                        owned: false,
-                    }
-                } else {
-                    EntryRender {
-                        ptr: rendered,
-                        len: _string_count(rendered) as usize,
-                        owned: true,
-                    }
-                }
-            }
-        }
-        TAG_MAP => {
-            if value == 0 {
                 EntryRender {
-                    ptr: NIL_LITERAL.as_ptr() as *mut u8,
-                    len: 3,
-                    owned: false,
-                }
-            } else {
-                let rendered = _map_to_string(value as *const u8);
-                if rendered.is_null() {
-                    EntryRender {
-                        ptr: NIL_LITERAL.as_ptr() as *mut u8,
-                        len: 3,
-                        owned: false,
-                    }
-                } else {
-                    EntryRender {
-                        ptr: rendered,
-                        len: _string_count(rendered) as usize,
-                        owned: true,
-                    }
+                    ptr: rendered,
+                    len: _string_count(rendered) as usize,
+                    owned: true,
                 }
             }
         }
@@ -325,6 +281,115 @@ pub unsafe extern "C" fn _set_count(set: *const u8) -> u64 {
     len
 }
 
+/// Insert every member of `source` accepted by `keep` into `result`, a set built by `map_copy`.
+unsafe fn collect_members(result: *mut u8, source: *const u8, mut keep: impl FnMut(i64, u8) -> bool) -> *mut u8 {
+    if result.is_null() {
+        return null_mut();
+    }
+    let len = _set_count(source) as usize;
+    let mut idx = 0usize;
+    while idx < len {
+        let (value, tag, _, _) = map_entry(source, idx);
+        if keep(value, tag) {
+            map_insert(result, value, tag, 1, TAG_BOOLEAN);
+        }
+        idx += 1;
+    }
+    result
+}
+
+/// # Safety
+///
+/// `set` must be null or point to a managed set. The result shares its members and `value`, and is
+/// released with `_set_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _set_conj(set: *const u8, value: i64, value_tag: i64) -> *mut u8 {
+    _map_assoc(set, value, value_tag, 1, TAG_BOOLEAN_I64)
+}
+
+/// The members of `left` followed by those of `right` it lacks.
+///
+/// # Safety
+///
+/// `left` and `right` must each be null or point to a managed set. The result shares their members
+/// and is released with `_set_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _set_union(left: *const u8, right: *const u8) -> *mut u8 {
+    let extended = map_copy(left);
+    collect_members(extended, right, |_, _| true)
+}
+
+/// The members of `left` that are also in `right`.
+///
+/// # Safety
+///
+/// Same requirements as `_set_union`.
+#[no_mangle]
+pub unsafe extern "C" fn _set_intersection(left: *const u8, right: *const u8) -> *mut u8 {
+    let empty = map_copy(null_mut());
+    collect_members(empty, left, |value, tag| _map_contains(right, value, tag as i64) != 0)
+}
+
+/// The members of `left` that are not in `right`.
+///
+/// # Safety
+///
+/// Same requirements as `_set_union`.
+#[no_mangle]
+pub unsafe extern "C" fn _set_difference(left: *const u8, right: *const u8) -> *mut u8 {
+    let empty = map_copy(null_mut());
+    collect_members(empty, left, |value, tag| _map_contains(right, value, tag as i64) == 0)
+}
+
+/// 1 when every member of `left` is in `right`, else 0.
+///
+/// # Safety
+///
+/// `left` and `right` must each be null or point to a managed set.
+#[no_mangle]
+pub unsafe extern "C" fn _set_subset(left: *const u8, right: *const u8) -> i64 {
+    let len = _set_count(left) as usize;
+    if len > _set_count(right) as usize {
+        return 0;
+    }
+    let mut idx = 0usize;
+    while idx < len {
+        let (value, tag, _, _) = map_entry(left, idx);
+        if _map_contains(right, value, tag as i64) == 0 {
+            return 0;
+        }
+        idx += 1;
+    }
+    1
+}
+
+/// 1 when every member of `right` is in `left`, else 0.
+///
+/// # Safety
+///
+/// Same requirements as `_set_subset`.
+#[no_mangle]
+pub unsafe extern "C" fn _set_superset(left: *const u8, right: *const u8) -> i64 {
+    _set_subset(right, left)
+}
+
+/// The members of `set` whose predicate result is truthy.
+///
+/// # Safety
+///
+/// `function` must be a compiled one-argument function whose results are described by
+/// `result_mode`, and `set` must be null or point to a managed set. The result shares the kept
+/// members and is released with `_set_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _set_select(function: i64, set: *const u8, result_mode: i64) -> *mut u8 {
+    let empty = map_copy(null_mut());
+    collect_members(empty, set, |value, _| {
+        let verdict = invoke(function, value, 0);
+        discard_result(verdict, result_mode);
+        verdict != 0
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn _set_free(set: *mut u8) {
     _map_free(set);
@@ -344,12 +409,7 @@ pub unsafe extern "C" fn _set_to_string(set: *const u8) -> *mut u8 {
         return dst;
     }
 
-    let header = set as *const MapHeader;
-    if header.is_null() {
-        return null_mut();
-    }
-
-    let len = (*header).length as usize;
+    let len = _set_count(set) as usize;
     if len == 0 {
         let dst = _allocate(4);
         if dst.is_null() {
@@ -372,16 +432,14 @@ pub unsafe extern "C" fn _set_to_string(set: *const u8) -> *mut u8 {
         return null_mut();
     }
 
-    let key_tags = map_key_tags_ptr(header);
-    let key_data = map_key_data_ptr(header);
-
     let mut total_len = 3usize; // '#', '{', '}'
     let mut idx = 0usize;
     let mut overflow = false;
 
     while idx < len {
         let entry = entries.add(idx);
-        (*entry) = render_set_entry(*key_tags.add(idx), *key_data.add(idx));
+        let (value, tag, _, _) = map_entry(set, idx);
+        (*entry) = render_set_entry(tag, value);
 
         if !overflow {
             if idx > 0 {
@@ -411,6 +469,11 @@ pub unsafe extern "C" fn _set_to_string(set: *const u8) -> *mut u8 {
         return null_mut();
     }
 
+    // Like maps, hash sets print their members in order rather than by hash
+    if !is_sorted(set) {
+        core::slice::from_raw_parts_mut(entries, len).sort_unstable_by(|left, right| left.text().cmp(right.text()));
+    }
+
     let total_with_null = match total_len.checked_add(1) {
         Some(val) => val,
         None => {
@@ -0,0 +1,242 @@
+use core::cmp::Ordering;
+use core::ptr::null;
+
+use crate::_vector_create;
+use crate::sequence::{discard_result, invoke, mode_tag, Cursor};
+use crate::value::value_compare;
+use crate::vector::{vector_element, vector_set_element};
+
+// `sort` and `sort-by` copy the items into the result list and sort that list in place, so the
+// result is the only allocation. The sort is a stable merge sort whose merges rotate runs into
+// place (SymMerge, Kim & Kutzner) instead of copying them through a scratch buffer: O(n log n)
+// comparisons and O(n log² n) moves. Short runs are insertion sorted first.
+//
+// Items order by `_value_compare` unless a compiled comparator is given. A comparator returning a
+// boolean says whether its first argument goes first; any other result is read as a number, and a
+// negative one puts the first argument first. `sort-by` compares the results of a key function,
+// called again for every comparison rather than cached, so no key buffer is needed.
+
+const TAG_BOOLEAN: u8 = 2;
+
+/// Runs up to this length are insertion sorted before merging
+const INSERTION_RUN: usize = 20;
+
+/// How two items order: by a key function (0 for the item itself), then by a comparator (0 for
+/// `_value_compare`)
+struct Order {
+    key_function: i64,
+    key_mode: i64,
+    comparator: i64,
+    comparator_mode: i64,
+}
+
+impl Order {
+    unsafe fn key(&self, (value, tag): (i64, u8)) -> (i64, u8) {
+        if self.key_function == 0 {
+            (value, tag)
+        } else {
+            (invoke(self.key_function, value, 0), mode_tag(self.key_mode))
+        }
+    }
+
+    /// Whether `left` must go ahead of `right`
+    unsafe fn before(&self, left: (i64, u8), right: (i64, u8)) -> bool {
+        let (left_key, left_tag) = self.key(left);
+        let (right_key, right_tag) = self.key(right);
+
+        let verdict = if self.comparator == 0 {
+            value_compare(left_tag, left_key, right_tag, right_key) == Ordering::Less
+        } else {
+            let result = invoke(self.comparator, left_key, right_key);
+            discard_result(result, self.comparator_mode);
+            if mode_tag(self.comparator_mode) == TAG_BOOLEAN {
+                result != 0
+            } else {
+                result < 0
+            }
+        };
+
+        if self.key_function != 0 {
+            discard_result(left_key, self.key_mode);
+            discard_result(right_key, self.key_mode);
+        }
+        verdict
+    }
+}
+
+/// A list being sorted in place
+struct Run {
+    list: *mut u8,
+    order: Order,
+}
+
+impl Run {
+    unsafe fn less(&self, left: usize, right: usize) -> bool {
+        self.order.before(vector_element(self.list, left), vector_element(self.list, right))
+    }
+
+    unsafe fn swap(&self, left: usize, right: usize) {
+        let (left_value, left_tag) = vector_element(self.list, left);
+        let (right_value, right_tag) = vector_element(self.list, right);
+        vector_set_element(self.list, left, right_value, right_tag);
+        vector_set_element(self.list, right, left_value, left_tag);
+    }
+
+    unsafe fn reverse(&self, mut start: usize, mut end: usize) {
+        while start + 1 < end {
+            end -= 1;
+            self.swap(start, end);
+            start += 1;
+        }
+    }
+
+    /// Exchange the blocks `[start, mid)` and `[mid, end)`
+    unsafe fn rotate(&self, start: usize, mid: usize, end: usize) {
+        self.reverse(start, mid);
+        self.reverse(mid, end);
+        self.reverse(start, end);
+    }
+
+    unsafe fn insertion_sort(&self, start: usize, end: usize) {
+        for index in start + 1..end {
+            let mut current = index;
+            while current > start && self.less(current, current - 1) {
+                self.swap(current, current - 1);
+                current -= 1;
+            }
+        }
+    }
+
+    /// Merge the sorted blocks `[start, mid)` and `[mid, end)`, keeping equal items in order
+    unsafe fn merge(&self, start: usize, mid: usize, end: usize) {
+        if mid - start == 1 {
+            // Move the single left item after every right item that goes ahead of it
+            let (mut low, mut high) = (mid, end);
+            while low < high {
+                let probe = (low + high) / 2;
+                if self.less(probe, start) {
+                    low = probe + 1;
+                } else {
+                    high = probe;
+                }
+            }
+            for index in start..low - 1 {
+                self.swap(index, index + 1);
+            }
+            return;
+        }
+        if end - mid == 1 {
+            // Move the single right item ahead of every left item it goes ahead of
+            let (mut low, mut high) = (start, mid);
+            while low < high {
+                let probe = (low + high) / 2;
+                if self.less(mid, probe) {
+                    high = probe;
+                } else {
+                    low = probe + 1;
+                }
+            }
+            for index in (low + 1..=mid).rev() {
+                self.swap(index, index - 1);
+            }
+            return;
+        }
+
+        let half = (start + end) / 2;
+        let span = half + mid;
+        let (mut low, mut high) = if mid > half { (span - end, half) } else { (start, mid) };
+        let last = span - 1;
+        while low < high {
+            let probe = (low + high) / 2;
+            if self.less(last - probe, probe) {
+                high = probe;
+            } else {
+                low = probe + 1;
+            }
+        }
+
+        let cut = span - low;
+        if low < mid && mid < cut {
+            self.rotate(low, mid, cut);
+        }
+        if start < low && low < half {
+            self.merge(start, low, half);
+        }
+        if half < cut && cut < end {
+            self.merge(half, cut, end);
+        }
+    }
+
+    unsafe fn sort(&self, len: usize) {
+        let mut start = 0;
+        while start < len {
+            let end = (start + INSERTION_RUN).min(len);
+            self.insertion_sort(start, end);
+            start = end;
+        }
+
+        let mut width = INSERTION_RUN;
+        while width < len {
+            let mut start = 0;
+            while start + width < len {
+                let end = (start + 2 * width).min(len);
+                self.merge(start, start + width, end);
+                start = end;
+            }
+            width *= 2;
+        }
+    }
+}
+
+/// Copy the items of `coll` into a new list and sort it.
+unsafe fn sorted_list(coll: *const u8, coll_tag: i64, order: Order) -> *mut u8 {
+    let mut cursor = Cursor::new(coll, coll_tag);
+    let len = cursor.remaining();
+    let list = _vector_create(null(), null(), len as u64);
+
+    let mut index = 0;
+    while let Some((value, tag)) = cursor.next() {
+        vector_set_element(list, index, value, tag);
+        index += 1;
+    }
+
+    let run = Run { list, order };
+    run.sort(len);
+    run.list
+}
+
+/// Sort the items of `coll` by `_value_compare`, or by a two-argument `comparator` when it is
+/// not 0.
+///
+/// # Safety
+///
+/// `comparator` must be 0 or a compiled two-argument function whose results `comparator_mode`
+/// describes, and `coll` a vector, list, set or null described by `coll_tag`. The returned list
+/// shares the items and is released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_sort(comparator: i64, coll: *const u8, coll_tag: i64, comparator_mode: i64) -> *mut u8 {
+    let order = Order {
+        key_function: 0,
+        key_mode: 0,
+        comparator,
+        comparator_mode,
+    };
+    sorted_list(coll, coll_tag, order)
+}
+
+/// Sort the items of `coll` by the results of `key_function`, compared like `_seq_sort` does.
+///
+/// # Safety
+///
+/// `key_function` must be a compiled one-argument function whose results `key_mode` describes;
+/// otherwise the same requirements as `_seq_sort`.
+#[no_mangle]
+pub unsafe extern "C" fn _seq_sort_by(key_function: i64, comparator: i64, coll: *const u8, coll_tag: i64, key_mode: i64, comparator_mode: i64) -> *mut u8 {
+    let order = Order {
+        key_function,
+        key_mode,
+        comparator,
+        comparator_mode,
+    };
+    sorted_list(coll, coll_tag, order)
+}
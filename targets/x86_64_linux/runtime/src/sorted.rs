@@ -0,0 +1,637 @@
+use core::cmp::Ordering;
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+use crate::exceptions::{mark_block, throw_message, value_deep_clone, value_deep_free, Part, BLOCK_MAP, BLOCK_SORTED_NODE};
+use crate::map::map_entry;
+use crate::sequence::{discard_result, invoke, mode_tag, ItemBuffer};
+use crate::value::value_compare;
+use crate::{_allocate, _free, _map_count, _map_free, _vector_create};
+
+// Sorted maps and sets are persistent AVL trees. Their header begins with the same length and flags
+// as the hash maps of `map.rs`, flagged `SORTED`, so `_map_count` reads their size directly and the other `_map_*`/`_set_*` helpers hand them to the functions here. An update copies
+// only the path down to the changed node and shares the rest of the tree with the previous version
+// through per-node reference counts: cloning a sorted map is O(1), and freeing one releases only the
+// nodes no other version still uses. Nodes also count the entries below them, which gives the
+// positional access `map_entry` offers for walking a map by index, at O(log n) a step.
+//
+// Keys order by `_value_compare`, or by a compiled comparator following `sort`'s conventions: a
+// boolean result says whether the first key goes first, any other result orders by its sign.
+
+/// Header flag for sorted maps, alongside `map.rs`'s `OWNS_ELEMENTS`
+pub(crate) const SORTED: u64 = 2;
+/// Header flag for sorted sets, whose subsequences hold members rather than `[key value]` entries
+const SORTED_SET: u64 = 4;
+
+const TAG_BOOLEAN: u8 = 2;
+const TAG_VECTOR: u8 = 4;
+
+/// `_sorted_subseq` bound kinds, packed above the bound key's tag
+const BOUND_EXCLUSIVE: i64 = 1;
+const BOUND_INCLUSIVE: i64 = 2;
+
+static NOT_SORTED_MESSAGE: [u8; 36] = *b"subseq requires a sorted map or set\0";
+
+#[repr(C)]
+struct SortedHeader {
+    length: u64,
+    flags: u64,
+    root: *mut Node,
+    comparator: i64,
+    comparator_mode: i64,
+}
+
+#[repr(C)]
+struct Node {
+    refs: u64,
+    size: u64,
+    height: u64,
+    left: *mut Node,
+    right: *mut Node,
+    key: i64,
+    value: i64,
+    key_tag: u8,
+    value_tag: u8,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    key: i64,
+    key_tag: u8,
+    value: i64,
+    value_tag: u8,
+}
+
+impl Entry {
+    fn key(&self) -> (i64, u8) {
+        (self.key, self.key_tag)
+    }
+}
+
+#[inline]
+unsafe fn height(node: *const Node) -> u64 {
+    if node.is_null() {
+        0
+    } else {
+        (*node).height
+    }
+}
+
+#[inline]
+unsafe fn size(node: *const Node) -> u64 {
+    if node.is_null() {
+        0
+    } else {
+        (*node).size
+    }
+}
+
+#[inline]
+unsafe fn entry_of(node: *const Node) -> Entry {
+    Entry {
+        key: (*node).key,
+        key_tag: (*node).key_tag,
+        value: (*node).value,
+        value_tag: (*node).value_tag,
+    }
+}
+
+/// A new node holding `entry` above `left` and `right`, taking over their references
+unsafe fn create(entry: Entry, left: *mut Node, right: *mut Node) -> *mut Node {
+    let node = _allocate(size_of::<Node>() as u64) as *mut Node;
+    mark_block(node as *const u8, BLOCK_SORTED_NODE);
+    node.write(Node {
+        refs: 1,
+        size: size(left) + size(right) + 1,
+        height: height(left).max(height(right)) + 1,
+        left,
+        right,
+        key: entry.key,
+        value: entry.value,
+        key_tag: entry.key_tag,
+        value_tag: entry.value_tag,
+    });
+    node
+}
+
+#[inline]
+unsafe fn retain(node: *mut Node) -> *mut Node {
+    if !node.is_null() {
+        (*node).refs += 1;
+    }
+    node
+}
+
+unsafe fn release(node: *mut Node) {
+    if node.is_null() {
+        return;
+    }
+    (*node).refs -= 1;
+    if (*node).refs == 0 {
+        release((*node).left);
+        release((*node).right);
+        _free(node as *mut u8);
+    }
+}
+
+/// Split a node into new references to its children and its entry, dropping the reference to it
+unsafe fn take(node: *mut Node) -> (*mut Node, Entry, *mut Node) {
+    let parts = (retain((*node).left), entry_of(node), retain((*node).right));
+    release(node);
+    parts
+}
+
+/// `create`, rotating to restore the balance when `left` and `right` differ by two levels
+unsafe fn balance(entry: Entry, left: *mut Node, right: *mut Node) -> *mut Node {
+    let (left_height, right_height) = (height(left), height(right));
+    if left_height > right_height + 1 {
+        let (outer, left_entry, inner) = take(left);
+        if height(outer) >= height(inner) {
+            create(left_entry, outer, create(entry, inner, right))
+        } else {
+            let (inner_left, inner_entry, inner_right) = take(inner);
+            create(inner_entry, create(left_entry, outer, inner_left), create(entry, inner_right, right))
+        }
+    } else if right_height > left_height + 1 {
+        let (inner, right_entry, outer) = take(right);
+        if height(outer) >= height(inner) {
+            create(right_entry, create(entry, left, inner), outer)
+        } else {
+            let (inner_left, inner_entry, inner_right) = take(inner);
+            create(inner_entry, create(entry, left, inner_left), create(right_entry, inner_right, outer))
+        }
+    } else {
+        create(entry, left, right)
+    }
+}
+
+/// How the keys of one sorted map order
+#[derive(Clone, Copy)]
+struct Order {
+    comparator: i64,
+    comparator_mode: i64,
+}
+
+impl Order {
+    unsafe fn of(map: *const SortedHeader) -> Order {
+        Order {
+            comparator: (*map).comparator,
+            comparator_mode: (*map).comparator_mode,
+        }
+    }
+
+    unsafe fn call(&self, (left, _): (i64, u8), (right, _): (i64, u8)) -> i64 {
+        let result = invoke(self.comparator, left, right);
+        discard_result(result, self.comparator_mode);
+        result
+    }
+
+    unsafe fn compare(&self, left: (i64, u8), right: (i64, u8)) -> Ordering {
+        if self.comparator == 0 {
+            return value_compare(left.1, left.0, right.1, right.0);
+        }
+
+        let result = self.call(left, right);
+        if mode_tag(self.comparator_mode) != TAG_BOOLEAN {
+            result.cmp(&0)
+        } else if result != 0 {
+            Ordering::Less
+        } else if self.call(right, left) != 0 {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
+/// A new reference to `node` with `entry` added, or replacing the value stored under an equal key
+/// (which keeps its original key); `node` itself is unchanged
+unsafe fn insert(order: Order, node: *mut Node, entry: Entry, added: &mut bool) -> *mut Node {
+    if node.is_null() {
+        *added = true;
+        return create(entry, null_mut(), null_mut());
+    }
+
+    let current = entry_of(node);
+    match order.compare(entry.key(), current.key()) {
+        Ordering::Equal => {
+            let replaced = Entry {
+                value: entry.value,
+                value_tag: entry.value_tag,
+                ..current
+            };
+            create(replaced, retain((*node).left), retain((*node).right))
+        }
+        Ordering::Less => balance(current, insert(order, (*node).left, entry, added), retain((*node).right)),
+        Ordering::Greater => balance(current, retain((*node).left), insert(order, (*node).right, entry, added)),
+    }
+}
+
+/// A new reference to `node` without the entry stored under `key`, or None when there is none
+unsafe fn remove(order: Order, node: *mut Node, key: (i64, u8)) -> Option<*mut Node> {
+    if node.is_null() {
+        return None;
+    }
+
+    let current = entry_of(node);
+    match order.compare(key, current.key()) {
+        Ordering::Equal => Some(join(retain((*node).left), retain((*node).right))),
+        Ordering::Less => remove(order, (*node).left, key).map(|left| balance(current, left, retain((*node).right))),
+        Ordering::Greater => remove(order, (*node).right, key).map(|right| balance(current, retain((*node).left), right)),
+    }
+}
+
+/// Join two sibling trees, every key of `left` ordering before those of `right`
+unsafe fn join(left: *mut Node, right: *mut Node) -> *mut Node {
+    if left.is_null() {
+        return right;
+    }
+    if right.is_null() {
+        return left;
+    }
+    let (first, rest) = remove_first(right);
+    balance(first, left, rest)
+}
+
+/// The first entry of a tree and the rest of it, taking over the reference to `node`
+unsafe fn remove_first(node: *mut Node) -> (Entry, *mut Node) {
+    let (left, entry, right) = take(node);
+    if left.is_null() {
+        return (entry, right);
+    }
+    let (first, rest) = remove_first(left);
+    (first, balance(entry, rest, right))
+}
+
+/// The node stored under `key`
+unsafe fn find(order: Order, root: *const Node, key: (i64, u8)) -> Option<*const Node> {
+    let mut node = root;
+    while !node.is_null() {
+        match order.compare(key, ((*node).key, (*node).key_tag)) {
+            Ordering::Less => node = (*node).left,
+            Ordering::Greater => node = (*node).right,
+            Ordering::Equal => return Some(node),
+        }
+    }
+    None
+}
+
+/// A tree of the same shape holding deep clones of every key and value
+unsafe fn deep_copy(node: *const Node) -> *mut Node {
+    if node.is_null() {
+        return null_mut();
+    }
+    let mut entry = entry_of(node);
+    entry.key = value_deep_clone(entry.key, entry.key_tag);
+    entry.value = value_deep_clone(entry.value, entry.value_tag);
+    create(entry, deep_copy((*node).left), deep_copy((*node).right))
+}
+
+/// Visit the nodes below and including `node` and their keys and values, skipping the nodes below
+/// one the visitor turns down
+unsafe fn visit_nodes(node: *const Node, visit: &mut dyn FnMut(Part) -> bool) {
+    if node.is_null() || !visit(Part::Block(node as *const u8)) {
+        return;
+    }
+    visit(Part::Value((*node).key, (*node).key_tag));
+    visit(Part::Value((*node).value, (*node).value_tag));
+    visit_nodes((*node).left, visit);
+    visit_nodes((*node).right, visit);
+}
+
+/// Drop the references the node at `node` holds to the children `stays` picks, as unwinding an
+/// exception frees the node without releasing it
+pub(crate) unsafe fn release_outer(node: *const u8, stays: &dyn Fn(*const u8) -> bool) {
+    let node = node as *const Node;
+    for child in [(*node).left, (*node).right] {
+        if stays(child as *const u8) {
+            release(child);
+        }
+    }
+}
+
+unsafe fn deep_release(node: *mut Node) {
+    if node.is_null() {
+        return;
+    }
+    deep_release((*node).left);
+    deep_release((*node).right);
+    value_deep_free((*node).key, (*node).key_tag);
+    value_deep_free((*node).value, (*node).value_tag);
+}
+
+#[inline]
+unsafe fn header(map: *const u8) -> *mut SortedHeader {
+    map as *mut SortedHeader
+}
+
+unsafe fn sorted_allocate(template: *const SortedHeader, root: *mut Node, length: u64) -> *mut u8 {
+    let map = _allocate(size_of::<SortedHeader>() as u64) as *mut SortedHeader;
+    mark_block(map as *const u8, BLOCK_MAP);
+    map.write(SortedHeader {
+        length,
+        flags: (*template).flags,
+        root,
+        comparator: (*template).comparator,
+        comparator_mode: (*template).comparator_mode,
+    });
+    map as *mut u8
+}
+
+/// Whether `map` is a sorted map or set rather than an array map.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map or set.
+#[inline]
+pub(crate) unsafe fn is_sorted(map: *const u8) -> bool {
+    !map.is_null() && (*header(map)).flags & SORTED != 0
+}
+
+/// Whether `map` is a sorted set.
+///
+/// # Safety
+///
+/// `map` must be null or point to a managed map or set.
+pub(crate) unsafe fn is_sorted_set(map: *const u8) -> bool {
+    is_sorted(map) && (*header(map)).flags & SORTED_SET != 0
+}
+
+/// A new sorted map sharing the whole tree of `map`.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_clone(map: *const u8) -> *mut u8 {
+    let source = header(map);
+    sorted_allocate(source, retain((*source).root), (*source).length)
+}
+
+/// A new sorted map with `key` set to `value`.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_assoc(map: *const u8, key: i64, key_tag: u8, value: i64, value_tag: u8) -> *mut u8 {
+    let source = header(map);
+    let entry = Entry { key, key_tag, value, value_tag };
+    let mut added = false;
+    let root = insert(Order::of(source), (*source).root, entry, &mut added);
+    sorted_allocate(source, root, (*source).length + added as u64)
+}
+
+/// A new sorted map without the entry stored under `key`.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_dissoc(map: *const u8, key: i64, key_tag: u8) -> *mut u8 {
+    let source = header(map);
+    match remove(Order::of(source), (*source).root, (key, key_tag)) {
+        Some(root) => sorted_allocate(source, root, (*source).length - 1),
+        None => sorted_clone(map),
+    }
+}
+
+/// Set `key` to `value` in a sorted map nothing else refers to yet, in place.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set owned by the caller alone.
+pub(crate) unsafe fn sorted_put(map: *mut u8, key: i64, key_tag: u8, value: i64, value_tag: u8) {
+    let target = header(map);
+    let entry = Entry { key, key_tag, value, value_tag };
+    let mut added = false;
+    let root = insert(Order::of(target), (*target).root, entry, &mut added);
+    release((*target).root);
+    (*target).root = root;
+    (*target).length += added as u64;
+}
+
+/// The stored key and the value (with their tags) under `key`.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_lookup(map: *const u8, key_tag: u8, key: i64) -> Option<(i64, u8, i64, u8)> {
+    let source = header(map);
+    find(Order::of(source), (*source).root, (key, key_tag)).map(|node| {
+        let entry = entry_of(node);
+        (entry.key, entry.key_tag, entry.value, entry.value_tag)
+    })
+}
+
+/// The key and value (with their tags) at position `index` in key order.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set holding more than `index` entries.
+pub(crate) unsafe fn sorted_entry(map: *const u8, mut index: usize) -> (i64, u8, i64, u8) {
+    let mut node = (*header(map)).root as *const Node;
+    loop {
+        let before = size((*node).left) as usize;
+        if index < before {
+            node = (*node).left;
+        } else if index == before {
+            let entry = entry_of(node);
+            return (entry.key, entry.key_tag, entry.value, entry.value_tag);
+        } else {
+            index -= before + 1;
+            node = (*node).right;
+        }
+    }
+}
+
+/// Release a sorted map and the nodes no other map shares.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set that is not used afterwards.
+pub(crate) unsafe fn sorted_free(map: *mut u8) {
+    release((*header(map)).root);
+    _free(map);
+}
+
+/// Visit the tree nodes of a sorted map or set and the keys and values they hold.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_visit_parts(map: *const u8, visit: &mut dyn FnMut(Part) -> bool) {
+    visit_nodes((*header(map)).root, visit);
+}
+
+/// Drop the reference a sorted map holds to its root when `stays` picks it, as unwinding an
+/// exception frees the map without releasing it.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_release_outer(map: *const u8, stays: &dyn Fn(*const u8) -> bool) {
+    let root = (*header(map)).root;
+    if stays(root as *const u8) {
+        release(root);
+    }
+}
+
+/// Clone a sorted map together with every heap value reachable from it.
+///
+/// # Safety
+///
+/// `map` must point to a sorted map or set.
+pub(crate) unsafe fn sorted_deep_clone(map: *const u8) -> *mut u8 {
+    let source = header(map);
+    sorted_allocate(source, deep_copy((*source).root), (*source).length)
+}
+
+/// Release a sorted map and every heap value it owns.
+///
+/// # Safety
+///
+/// `map` must be a sorted map produced by `sorted_deep_clone`.
+pub(crate) unsafe fn sorted_deep_free(map: *mut u8) {
+    deep_release((*header(map)).root);
+    sorted_free(map);
+}
+
+/// Move the entries of `map` into a new sorted map or set and release `map`.
+unsafe fn sorted_from(comparator: i64, map: *mut u8, comparator_mode: i64, flags: u64) -> *mut u8 {
+    let template = SortedHeader {
+        length: 0,
+        flags,
+        root: null_mut(),
+        comparator,
+        comparator_mode,
+    };
+    let sorted = sorted_allocate(&template, null_mut(), 0);
+    let len = _map_count(map) as usize;
+    let mut idx = 0usize;
+    while idx < len {
+        let (key, key_tag, value, value_tag) = map_entry(map, idx);
+        sorted_put(sorted, key, key_tag, value, value_tag);
+        idx += 1;
+    }
+    _map_free(map);
+    sorted
+}
+
+/// Move the entries of `map` into a new map kept sorted by `comparator`, or by `_value_compare`
+/// when it is 0, and release `map`. Of entries with equal keys, the last value wins.
+///
+/// # Safety
+///
+/// `comparator` must be 0 or a compiled two-argument function whose results `comparator_mode`
+/// describes, and `map` null or a managed map used nowhere else. The result shares the keys and
+/// values and is released with `_map_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _sorted_map_from(comparator: i64, map: *mut u8, comparator_mode: i64) -> *mut u8 {
+    sorted_from(comparator, map, comparator_mode, SORTED)
+}
+
+/// Move the members of `set` into a new set kept sorted like `_sorted_map_from` does.
+///
+/// # Safety
+///
+/// Same requirements as `_sorted_map_from`, for a set released with `_set_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _sorted_set_from(comparator: i64, set: *mut u8, comparator_mode: i64) -> *mut u8 {
+    sorted_from(comparator, set, comparator_mode, SORTED | SORTED_SET)
+}
+
+/// One end of a `_sorted_subseq` range
+struct Bound {
+    key: (i64, u8),
+    inclusive: bool,
+}
+
+impl Bound {
+    fn unpack(key: i64, bound: i64) -> Option<Bound> {
+        match bound >> 8 {
+            BOUND_EXCLUSIVE | BOUND_INCLUSIVE => Some(Bound {
+                key: (key, (bound & 0xff) as u8),
+                inclusive: bound >> 8 == BOUND_INCLUSIVE,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The entries of a sorted map between two bounds, visited in or against key order
+struct Range {
+    order: Order,
+    start: Option<Bound>,
+    end: Option<Bound>,
+    descending: bool,
+}
+
+impl Range {
+    unsafe fn walk(&self, node: *const Node, visit: &mut impl FnMut(Entry)) {
+        if node.is_null() {
+            return;
+        }
+
+        let key = ((*node).key, (*node).key_tag);
+        let from_start = self.start.as_ref().map(|bound| (self.order.compare(key, bound.key), bound.inclusive));
+        let to_end = self.end.as_ref().map(|bound| (self.order.compare(key, bound.key), bound.inclusive));
+        // Keys before this one can only be in range when it lies past the start, and keys after it
+        // when it lies before the end
+        let earlier = from_start.is_none_or(|(ordering, _)| ordering.is_gt());
+        let later = to_end.is_none_or(|(ordering, _)| ordering.is_lt());
+        let inside = from_start.is_none_or(|(ordering, inclusive)| ordering.is_gt() || (inclusive && ordering.is_eq()))
+            && to_end.is_none_or(|(ordering, inclusive)| ordering.is_lt() || (inclusive && ordering.is_eq()));
+
+        let (first, first_open, second, second_open) = if self.descending {
+            ((*node).right, later, (*node).left, earlier)
+        } else {
+            ((*node).left, earlier, (*node).right, later)
+        };
+        if first_open {
+            self.walk(first, visit);
+        }
+        if inside {
+            visit(entry_of(node));
+        }
+        if second_open {
+            self.walk(second, visit);
+        }
+    }
+}
+
+/// The entries of a sorted map (as `[key value]` vectors) or members of a sorted set from `start`
+/// to `end`, ascending, or descending when `descending` is 1. Each bound packs the tag of its key
+/// with an exclusive (1) or inclusive (2) kind above it; a kind of 0 leaves that end open.
+///
+/// # Safety
+///
+/// `coll` must be null or point to a managed map or set, and the bound keys must be values their
+/// tags describe. Throws when `coll` is not sorted. The returned list shares the keys and values
+/// and is released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _sorted_subseq(coll: *const u8, start: i64, start_bound: i64, end: i64, end_bound: i64, descending: i64) -> *mut u8 {
+    let mut items = ItemBuffer::new();
+    if coll.is_null() {
+        return items.finish(false);
+    }
+    if !is_sorted(coll) {
+        throw_message(&NOT_SORTED_MESSAGE);
+    }
+
+    let source = header(coll);
+    let members = (*source).flags & SORTED_SET != 0;
+    let range = Range {
+        order: Order::of(source),
+        start: Bound::unpack(start, start_bound),
+        end: Bound::unpack(end, end_bound),
+        descending: descending != 0,
+    };
+    range.walk((*source).root, &mut |entry| {
+        if members {
+            items.push(entry.key, entry.key_tag);
+        } else {
+            let values = [entry.key, entry.value];
+            let tags = [entry.key_tag as i64, entry.value_tag as i64];
+            items.push(_vector_create(values.as_ptr(), tags.as_ptr(), 2) as i64, TAG_VECTOR);
+        }
+    });
+    items.finish(!members)
+}
@@ -4,6 +4,9 @@ pub static TRUE_LITERAL: [u8; 5] = *b"true\0";
 pub static FALSE_LITERAL: [u8; 6] = *b"false\0";
 pub static NIL_LITERAL: [u8; 4] = *b"nil\0";
 
+const SYS_MINCORE: isize = 27;
+const PAGE_SIZE: usize = 4096;
+
 fn count_decimal_digits(mut value: u64) -> usize {
     let mut digits = 1;
     while value >= 10 {
@@ -153,6 +156,50 @@ pub unsafe extern "C" fn _string_equals(left: *const u8, right: *const u8) -> i6
     1
 }
 
+/// FNV-1a hash of a byte string. The compiler uses the same function to lay out `case` tables.
+pub fn string_hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Bucket of a string in a table of `buckets` entries; null strings land in bucket 0.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn _string_hash(ptr: *const u8, buckets: i64) -> i64 {
+    if ptr.is_null() || buckets <= 0 {
+        return 0;
+    }
+
+    let len = _string_count(ptr) as usize;
+    (string_hash_bytes(core::slice::from_raw_parts(ptr, len)) % buckets as u64) as i64
+}
+
+/// 1 when an untyped word may be read as a string, that is when it points into mapped memory.
+/// Nil, booleans and the numbers a program works with do not, so `case` sends them to its default
+/// instead of hashing them.
+///
+/// # Safety
+///
+/// `ptr` may be any word; only its page is probed, without reading it.
+#[no_mangle]
+pub unsafe extern "C" fn _string_readable(ptr: *const u8) -> i64 {
+    if ptr.is_null() {
+        return 0;
+    }
+
+    // mincore fails with ENOMEM for an unmapped page and never touches the page itself
+    let page = ptr as usize & !(PAGE_SIZE - 1);
+    let mut residency = 0u8;
+    (crate::syscall6(SYS_MINCORE, page, 1, &mut residency as *mut u8 as usize, 0, 0, 0) == 0) as i64
+}
+
 #[no_mangle]
 pub extern "C" fn _string_from_boolean(value: i64) -> *mut u8 {
     if value == 0 {
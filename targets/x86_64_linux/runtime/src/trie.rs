@@ -0,0 +1,381 @@
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+use crate::exceptions::{mark_block, Part, BLOCK_TRIE_NODE};
+use crate::value::{value_hash, values_equal};
+use crate::{_allocate, _free};
+
+// Hash maps and sets are persistent hash array mapped tries. Each level of the trie consumes five
+// bits of a key's `value_hash`: a branch keeps a bitmap of its occupied slots and one child per set
+// bit, and a bucket holds the entries whose keys share one full hash (almost always a single
+// entry). Buckets sit as high in the trie as the hashes around them allow, so a lookup follows a
+// handful of branches and then compares keys with `values_equal` only within one bucket.
+//
+// Like the AVL trees of `sorted.rs`, nodes are reference counted and never change once built: an
+// update copies the branches on the path to the changed bucket and shares every other node with
+// the previous version. Nodes also count the entries below them, so entries can be visited by
+// position, in hash order, at O(log n) a step.
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+/// One key and its value, with their tags
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct Entry {
+    pub key: i64,
+    pub value: i64,
+    pub key_tag: u8,
+    pub value_tag: u8,
+}
+
+impl Entry {
+    unsafe fn has_key(&self, key_tag: u8, key: i64) -> bool {
+        values_equal(self.key_tag, self.key, key_tag, key)
+    }
+}
+
+/// A branch, followed by `width` child pointers, or a bucket (`bitmap` 0), followed by `width` entries
+#[repr(C)]
+pub(crate) struct Node {
+    refs: u32,
+    size: u32,
+    bitmap: u32,
+    width: u32,
+    hash: u64,
+}
+
+/// The trie slot `hash` falls in at the level consuming the bits from `shift` up
+#[inline]
+fn slot(hash: u64, shift: u32) -> u32 {
+    ((hash >> shift) & MASK) as u32
+}
+
+#[inline]
+pub(crate) unsafe fn is_bucket(node: *const Node) -> bool {
+    (*node).bitmap == 0
+}
+
+#[inline]
+unsafe fn children<'a>(node: *const Node) -> &'a [*mut Node] {
+    core::slice::from_raw_parts((node as *const u8).add(size_of::<Node>()) as *const *mut Node, (*node).width as usize)
+}
+
+#[inline]
+unsafe fn entries<'a>(node: *const Node) -> &'a [Entry] {
+    core::slice::from_raw_parts((node as *const u8).add(size_of::<Node>()) as *const Entry, (*node).width as usize)
+}
+
+/// Position among a branch's children of the child in `slot`
+#[inline]
+unsafe fn child_index(node: *const Node, slot: u32) -> usize {
+    ((*node).bitmap & ((1u32 << slot) - 1)).count_ones() as usize
+}
+
+unsafe fn allocate(hash: u64, bitmap: u32, width: usize, item_size: usize) -> *mut Node {
+    let node = _allocate((size_of::<Node>() + width * item_size) as u64) as *mut Node;
+    mark_block(node as *const u8, BLOCK_TRIE_NODE);
+    node.write(Node {
+        refs: 1,
+        size: 0,
+        hash,
+        bitmap,
+        width: width as u32,
+    });
+    node
+}
+
+/// A new bucket for `width` entries whose keys hash to `hash`, with the entries left to fill in
+unsafe fn bucket<'a>(hash: u64, width: usize) -> (*mut Node, &'a mut [Entry]) {
+    let node = allocate(hash, 0, width, size_of::<Entry>());
+    (*node).size = width as u32;
+    let items = core::slice::from_raw_parts_mut((node as *mut u8).add(size_of::<Node>()) as *mut Entry, width);
+    (node, items)
+}
+
+/// A new bucket holding just `entry`
+unsafe fn single(hash: u64, entry: Entry) -> *mut Node {
+    let (node, items) = bucket(hash, 1);
+    items[0] = entry;
+    node
+}
+
+/// A new branch over `nodes`, taking over their references, in the slots `bitmap` marks
+unsafe fn branch(bitmap: u32, nodes: &[*mut Node]) -> *mut Node {
+    let node = allocate(0, bitmap, nodes.len(), size_of::<*mut Node>());
+    let target = (node as *mut u8).add(size_of::<Node>()) as *mut *mut Node;
+    core::ptr::copy_nonoverlapping(nodes.as_ptr(), target, nodes.len());
+    (*node).size = nodes.iter().map(|child| (**child).size).sum();
+    node
+}
+
+/// Take another reference to `node`
+#[inline]
+pub(crate) unsafe fn retain(node: *mut Node) -> *mut Node {
+    if !node.is_null() {
+        (*node).refs += 1;
+    }
+    node
+}
+
+/// Drop a reference to `node`, freeing it and the nodes only it used once no map refers to it
+pub(crate) unsafe fn release(node: *mut Node) {
+    if node.is_null() {
+        return;
+    }
+    (*node).refs -= 1;
+    if (*node).refs == 0 {
+        if !is_bucket(node) {
+            for &child in children(node) {
+                release(child);
+            }
+        }
+        _free(node as *mut u8);
+    }
+}
+
+/// A new branch like `node` with the child at `index` swapped for `child`
+unsafe fn with_child(node: *const Node, index: usize, child: *mut Node) -> *mut Node {
+    let mut nodes = [null_mut(); 32];
+    let width = children(node).len();
+    for (position, &existing) in children(node).iter().enumerate() {
+        nodes[position] = if position == index { child } else { retain(existing) };
+    }
+    branch((*node).bitmap, &nodes[..width])
+}
+
+/// A new branch like `node` with `child` added in the empty `slot`
+unsafe fn with_new_child(node: *const Node, slot: u32, child: *mut Node) -> *mut Node {
+    let mut nodes = [null_mut(); 32];
+    let index = child_index(node, slot);
+    let existing = children(node);
+    for (position, &other) in existing.iter().enumerate() {
+        nodes[position + (position >= index) as usize] = retain(other);
+    }
+    nodes[index] = child;
+    branch((*node).bitmap | (1 << slot), &nodes[..existing.len() + 1])
+}
+
+/// A new branch like `node` without the child at `index`
+unsafe fn without_child(node: *const Node, slot: u32, index: usize) -> *mut Node {
+    let mut nodes = [null_mut(); 32];
+    let existing = children(node);
+    for (position, &other) in existing.iter().enumerate().filter(|(position, _)| *position != index) {
+        nodes[position - (position > index) as usize] = retain(other);
+    }
+    branch((*node).bitmap & !(1 << slot), &nodes[..existing.len() - 1])
+}
+
+/// The branches needed to hold two nodes with different hashes below the level at `shift`
+unsafe fn join(shift: u32, left: *mut Node, left_hash: u64, right: *mut Node, right_hash: u64) -> *mut Node {
+    let (left_slot, right_slot) = (slot(left_hash, shift), slot(right_hash, shift));
+    if left_slot == right_slot {
+        let below = join(shift + BITS, left, left_hash, right, right_hash);
+        return branch(1 << left_slot, &[below]);
+    }
+    let pair = if left_slot < right_slot { [left, right] } else { [right, left] };
+    branch((1 << left_slot) | (1 << right_slot), &pair)
+}
+
+/// A new reference to `node` with `entry` added, or replacing the value stored under an equal key
+/// (which keeps its original key); `node` itself is unchanged
+pub(crate) unsafe fn insert(node: *mut Node, entry: Entry, added: &mut bool) -> *mut Node {
+    insert_at(node, 0, value_hash(entry.key, entry.key_tag), entry, added)
+}
+
+unsafe fn insert_at(node: *mut Node, shift: u32, hash: u64, entry: Entry, added: &mut bool) -> *mut Node {
+    if node.is_null() {
+        *added = true;
+        return single(hash, entry);
+    }
+
+    if is_bucket(node) {
+        if (*node).hash != hash {
+            *added = true;
+            return join(shift, retain(node), (*node).hash, single(hash, entry), hash);
+        }
+        let existing = entries(node);
+        return match existing.iter().position(|stored| stored.has_key(entry.key_tag, entry.key)) {
+            Some(index) => {
+                let (replaced, items) = bucket(hash, existing.len());
+                items.copy_from_slice(existing);
+                items[index].value = entry.value;
+                items[index].value_tag = entry.value_tag;
+                replaced
+            }
+            None => {
+                *added = true;
+                let (extended, items) = bucket(hash, existing.len() + 1);
+                items[..existing.len()].copy_from_slice(existing);
+                items[existing.len()] = entry;
+                extended
+            }
+        };
+    }
+
+    let slot = slot(hash, shift);
+    let index = child_index(node, slot);
+    if (*node).bitmap & (1 << slot) == 0 {
+        *added = true;
+        return with_new_child(node, slot, single(hash, entry));
+    }
+    let child = insert_at(children(node)[index], shift + BITS, hash, entry, added);
+    with_child(node, index, child)
+}
+
+/// A new reference to `node` without the entry stored under `key` (null once nothing is left), or
+/// None when there is none
+pub(crate) unsafe fn remove(node: *mut Node, key_tag: u8, key: i64) -> Option<*mut Node> {
+    remove_at(node, 0, value_hash(key, key_tag), key_tag, key)
+}
+
+unsafe fn remove_at(node: *mut Node, shift: u32, hash: u64, key_tag: u8, key: i64) -> Option<*mut Node> {
+    if node.is_null() {
+        return None;
+    }
+
+    if is_bucket(node) {
+        if (*node).hash != hash {
+            return None;
+        }
+        let existing = entries(node);
+        let index = existing.iter().position(|stored| stored.has_key(key_tag, key))?;
+        if existing.len() == 1 {
+            return Some(null_mut());
+        }
+        let (shrunk, items) = bucket(hash, existing.len() - 1);
+        items[..index].copy_from_slice(&existing[..index]);
+        items[index..].copy_from_slice(&existing[index + 1..]);
+        return Some(shrunk);
+    }
+
+    let slot = slot(hash, shift);
+    if (*node).bitmap & (1 << slot) == 0 {
+        return None;
+    }
+    let index = child_index(node, slot);
+    let child = remove_at(children(node)[index], shift + BITS, hash, key_tag, key)?;
+    let siblings = children(node);
+    let remaining = if child.is_null() { siblings.len() - 1 } else { siblings.len() };
+
+    if remaining == 0 {
+        return Some(null_mut());
+    }
+    // A bucket left alone under a branch takes the branch's place
+    if remaining == 1 {
+        let only = if child.is_null() { siblings[1 - index] } else { child };
+        if is_bucket(only) {
+            return Some(if child.is_null() { retain(only) } else { child });
+        }
+    }
+    Some(if child.is_null() { without_child(node, slot, index) } else { with_child(node, index, child) })
+}
+
+/// The entry stored under `key`
+pub(crate) unsafe fn find<'a>(root: *const Node, key_tag: u8, key: i64) -> Option<&'a Entry> {
+    let hash = value_hash(key, key_tag);
+    let mut node = root;
+    let mut shift = 0;
+    while !node.is_null() {
+        if is_bucket(node) {
+            if (*node).hash != hash {
+                return None;
+            }
+            return entries(node).iter().find(|stored| stored.has_key(key_tag, key));
+        }
+        let slot = slot(hash, shift);
+        if (*node).bitmap & (1 << slot) == 0 {
+            return None;
+        }
+        node = children(node)[child_index(node, slot)];
+        shift += BITS;
+    }
+    None
+}
+
+/// The entry at position `index` in hash order
+///
+/// # Safety
+///
+/// `root` must hold more than `index` entries.
+pub(crate) unsafe fn entry_at<'a>(root: *const Node, mut index: usize) -> &'a Entry {
+    let mut node = root;
+    while !is_bucket(node) {
+        for &child in children(node) {
+            let size = (*child).size as usize;
+            if index < size {
+                node = child;
+                break;
+            }
+            index -= size;
+        }
+    }
+    &entries(node)[index]
+}
+
+/// Visit every entry below `node` in hash order
+pub(crate) unsafe fn for_each(node: *const Node, visit: &mut impl FnMut(&Entry)) {
+    if node.is_null() {
+        return;
+    }
+    if is_bucket(node) {
+        entries(node).iter().for_each(visit);
+    } else {
+        for &child in children(node) {
+            for_each(child, visit);
+        }
+    }
+}
+
+/// A trie of the same shape holding new nodes, with each entry passed through `copy`
+pub(crate) unsafe fn copy_with(node: *const Node, copy: &mut impl FnMut(Entry) -> Entry) -> *mut Node {
+    if node.is_null() {
+        return null_mut();
+    }
+    if is_bucket(node) {
+        let existing = entries(node);
+        let (copied, items) = bucket((*node).hash, existing.len());
+        for (item, stored) in items.iter_mut().zip(existing) {
+            *item = copy(*stored);
+        }
+        return copied;
+    }
+    let mut nodes = [null_mut(); 32];
+    let existing = children(node);
+    for (slot, &child) in nodes.iter_mut().zip(existing) {
+        *slot = copy_with(child, copy);
+    }
+    branch((*node).bitmap, &nodes[..existing.len()])
+}
+
+/// Visit the nodes below and including `node` and the keys and values of their entries, skipping
+/// the nodes below one the visitor turns down
+pub(crate) unsafe fn visit_parts(node: *const Node, visit: &mut dyn FnMut(Part) -> bool) {
+    if node.is_null() || !visit(Part::Block(node as *const u8)) {
+        return;
+    }
+    if is_bucket(node) {
+        for entry in entries(node) {
+            visit(Part::Value(entry.key, entry.key_tag));
+            visit(Part::Value(entry.value, entry.value_tag));
+        }
+    } else {
+        for &child in children(node) {
+            visit_parts(child, visit);
+        }
+    }
+}
+
+/// Drop the references the node at `node` holds to the children `stays` picks, as unwinding an
+/// exception frees the node without releasing it
+pub(crate) unsafe fn release_outer(node: *const u8, stays: &dyn Fn(*const u8) -> bool) {
+    let node = node as *const Node;
+    if is_bucket(node) {
+        return;
+    }
+    for &child in children(node) {
+        if stays(child as *const u8) {
+            release(child);
+        }
+    }
+}
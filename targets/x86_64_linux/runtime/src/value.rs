@@ -0,0 +1,339 @@
+use core::cmp::Ordering;
+
+use crate::allocator::is_allocated;
+use crate::exceptions::{block_kind, BLOCK_ATOM, BLOCK_LAZY_SEQ, BLOCK_MAP, BLOCK_RECORD, BLOCK_VECTOR, BLOCK_VECTOR_VIEW};
+use crate::map::map_entry;
+use crate::record::record_type;
+use crate::sorted::is_sorted_set;
+use crate::strings::_string_readable;
+use crate::vector::vector_element;
+use crate::{_lazy_count, _lazy_items, _map_count, _map_get, _string_count, _string_equals, _vector_count, _vector_free, string_hash_bytes};
+
+// Structural equality, hashing and ordering of tagged values, used by compiled `=`, `compare` and
+// `hash` and by map and set lookups. Vectors and lists compare element by element, maps and sets by
+// their entries in any order (a record only equals records of its own type), and a lazy sequence
+// is realized and treated as a list. Values whose tag is unknown (`TAG_ANY`) compare as machine
+// words and hash as numbers.
+//
+// The hash helpers are plain functions so the interpreter can produce the same hash for the same
+// value.
+
+const TAG_NIL: u8 = 0;
+const TAG_NUMBER: u8 = 1;
+const TAG_BOOLEAN: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_VECTOR: u8 = 4;
+const TAG_MAP: u8 = 5;
+const TAG_KEYWORD: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_LIST: u8 = 8;
+const TAG_SYMBOL: u8 = 9;
+const TAG_LAZY_SEQ: u8 = 10;
+const TAG_ATOM: u8 = 11;
+
+#[inline]
+fn canonical_boolean(value: i64) -> i64 {
+    if value == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+/// Hash of a scalar with the given tag, where `bits` is the number, boolean (0 or 1) or string hash
+pub fn hash_scalar(tag: u8, bits: u64) -> u64 {
+    // splitmix64 finalizer, seeded with the tag so equal bits under different tags differ
+    let mut z = bits.wrapping_add((tag as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Fold the hash of the next element of a vector or list into `hash`
+pub fn hash_ordered(hash: u64, item: u64) -> u64 {
+    hash.wrapping_mul(31).wrapping_add(item)
+}
+
+/// Hash of one map entry; entries (and set members) are summed so their order does not matter
+pub fn hash_entry(key: u64, value: u64) -> u64 {
+    hash_ordered(key, value)
+}
+
+/// Position of a tag in the order `compare` puts values of different kinds in
+pub fn tag_rank(tag: u8) -> u8 {
+    match tag {
+        TAG_NIL => 0,
+        TAG_BOOLEAN => 1,
+        TAG_NUMBER => 2,
+        TAG_STRING => 3,
+        TAG_KEYWORD => 4,
+        TAG_SYMBOL => 5,
+        TAG_LIST | TAG_LAZY_SEQ => 6,
+        TAG_VECTOR => 7,
+        TAG_MAP => 8,
+        TAG_SET => 9,
+        _ => 10,
+    }
+}
+
+unsafe fn string_bytes<'a>(value: i64) -> &'a [u8] {
+    let ptr = value as *const u8;
+    if ptr.is_null() {
+        return &[];
+    }
+    core::slice::from_raw_parts(ptr, _string_count(ptr) as usize)
+}
+
+unsafe fn sequence_len(seq: *const u8) -> usize {
+    // A null list is empty
+    if seq.is_null() {
+        0
+    } else {
+        _vector_count(seq) as usize
+    }
+}
+
+unsafe fn map_len(map: *const u8) -> usize {
+    if map.is_null() {
+        0
+    } else {
+        _map_count(map) as usize
+    }
+}
+
+/// Run `body` over `value` with a lazy sequence realized into a list (released afterwards)
+unsafe fn with_realized<T>(value: i64, tag: u8, body: impl FnOnce(i64, u8) -> T) -> T {
+    if tag != TAG_LAZY_SEQ {
+        return body(value, tag);
+    }
+    let items = _lazy_items(value as *mut u8);
+    let result = body(items as i64, TAG_LIST);
+    _vector_free(items);
+    result
+}
+
+/// Structural equality of two tagged values: scalars compare by value, strings and keywords by
+/// content, vectors and lists element by element, and maps and sets by their entries in any order.
+///
+/// # Safety
+///
+/// Heap tags require the matching value to be null or point to a managed value of that kind.
+pub(crate) unsafe fn values_equal(left_tag: u8, left: i64, right_tag: u8, right: i64) -> bool {
+    if left_tag == TAG_LAZY_SEQ || right_tag == TAG_LAZY_SEQ {
+        return with_realized(left, left_tag, |left, left_tag| {
+            with_realized(right, right_tag, |right, right_tag| values_equal(left_tag, left, right_tag, right))
+        });
+    }
+    if left_tag != right_tag {
+        return false;
+    }
+
+    match left_tag {
+        TAG_BOOLEAN => canonical_boolean(left) == canonical_boolean(right),
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => _string_equals(left as *const u8, right as *const u8) != 0,
+        TAG_LIST => left == right || sequences_equal(left as *const u8, right as *const u8),
+        // Only a list can be null and non-nil, so a null vector, map or set is nil
+        TAG_VECTOR => left == right || (left != 0 && right != 0 && sequences_equal(left as *const u8, right as *const u8)),
+        TAG_MAP | TAG_SET => left == right || (left != 0 && right != 0 && same_map_kind(left as *const u8, right as *const u8) && maps_equal(left as *const u8, right as *const u8)),
+        _ => left == right,
+    }
+}
+
+unsafe fn sequences_equal(left: *const u8, right: *const u8) -> bool {
+    let len = sequence_len(left);
+    if len != sequence_len(right) {
+        return false;
+    }
+
+    (0..len).all(|idx| {
+        let (left_value, left_tag) = vector_element(left, idx);
+        let (right_value, right_tag) = vector_element(right, idx);
+        values_equal(left_tag, left_value, right_tag, right_value)
+    })
+}
+
+/// Records equal only records of their own type, other maps only other maps
+unsafe fn same_map_kind(left: *const u8, right: *const u8) -> bool {
+    record_type(left) == record_type(right)
+}
+
+unsafe fn maps_equal(left: *const u8, right: *const u8) -> bool {
+    let len = map_len(left);
+    if len != map_len(right) {
+        return false;
+    }
+
+    (0..len).all(|idx| {
+        let (key, key_tag, value, value_tag) = map_entry(left, idx);
+        let mut other = 0i64;
+        let mut other_tag = 0u8;
+        _map_get(right, key, key_tag as i64, &mut other, &mut other_tag) != 0 && values_equal(value_tag, value, other_tag, other)
+    })
+}
+
+/// Structural hash of a tagged value; values that are `values_equal` hash the same.
+///
+/// # Safety
+///
+/// Same requirements as `values_equal`.
+pub(crate) unsafe fn value_hash(value: i64, tag: u8) -> u64 {
+    match tag {
+        TAG_NIL => hash_scalar(TAG_NIL, 0),
+        TAG_BOOLEAN => hash_scalar(TAG_BOOLEAN, canonical_boolean(value) as u64),
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => hash_scalar(tag, string_hash_bytes(string_bytes(value))),
+        TAG_VECTOR | TAG_LIST => {
+            let seq = value as *const u8;
+            let len = sequence_len(seq);
+            (0..len).fold(hash_scalar(tag, len as u64), |hash, idx| {
+                let (item, item_tag) = vector_element(seq, idx);
+                hash_ordered(hash, value_hash(item, item_tag))
+            })
+        }
+        TAG_MAP | TAG_SET => {
+            let map = value as *const u8;
+            let len = map_len(map);
+            (0..len).fold(hash_scalar(tag, len as u64), |hash, idx| {
+                let (key, key_tag, entry_value, value_tag) = map_entry(map, idx);
+                let entry = if tag == TAG_SET {
+                    value_hash(key, key_tag)
+                } else {
+                    hash_entry(value_hash(key, key_tag), value_hash(entry_value, value_tag))
+                };
+                hash.wrapping_add(entry)
+            })
+        }
+        TAG_LAZY_SEQ => with_realized(value, tag, |list, list_tag| value_hash(list, list_tag)),
+        _ => hash_scalar(TAG_NUMBER, value as u64),
+    }
+}
+
+/// Total order over tagged values: kinds order by `tag_rank`, numbers numerically, false before
+/// true, strings, keywords and symbols by their bytes, and vectors and lists element by element
+/// with a shorter prefix first. Maps and sets order by size, then by hash.
+///
+/// # Safety
+///
+/// Same requirements as `values_equal`.
+pub(crate) unsafe fn value_compare(left_tag: u8, left: i64, right_tag: u8, right: i64) -> Ordering {
+    if left_tag == TAG_LAZY_SEQ || right_tag == TAG_LAZY_SEQ {
+        return with_realized(left, left_tag, |left, left_tag| {
+            with_realized(right, right_tag, |right, right_tag| value_compare(left_tag, left, right_tag, right))
+        });
+    }
+    if left_tag != right_tag {
+        return tag_rank(left_tag).cmp(&tag_rank(right_tag));
+    }
+
+    match left_tag {
+        TAG_NIL => Ordering::Equal,
+        TAG_BOOLEAN => canonical_boolean(left).cmp(&canonical_boolean(right)),
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => string_bytes(left).cmp(string_bytes(right)),
+        TAG_VECTOR | TAG_LIST => {
+            let (left, right) = (left as *const u8, right as *const u8);
+            let (left_len, right_len) = (sequence_len(left), sequence_len(right));
+            (0..left_len.min(right_len))
+                .map(|idx| {
+                    let (left_value, left_tag) = vector_element(left, idx);
+                    let (right_value, right_tag) = vector_element(right, idx);
+                    value_compare(left_tag, left_value, right_tag, right_value)
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| left_len.cmp(&right_len))
+        }
+        TAG_MAP | TAG_SET => {
+            if values_equal(left_tag, left, right_tag, right) {
+                return Ordering::Equal;
+            }
+            map_len(left as *const u8)
+                .cmp(&map_len(right as *const u8))
+                .then_with(|| value_hash(left, left_tag).cmp(&value_hash(right, right_tag)))
+                .then(Ordering::Greater)
+        }
+        _ => left.cmp(&right),
+    }
+}
+
+/// Whether two tagged values are structurally equal (1) or not (0).
+///
+/// # Safety
+///
+/// Heap tags require the matching value to be null or point to a managed value of that kind.
+#[no_mangle]
+pub unsafe extern "C" fn _value_equals(left: i64, right: i64, left_tag: i64, right_tag: i64) -> i64 {
+    values_equal(left_tag as u8, left, right_tag as u8, right) as i64
+}
+
+/// Structural hash of a tagged value.
+///
+/// # Safety
+///
+/// A heap tag requires `value` to be null or point to a managed value of that kind.
+#[no_mangle]
+pub unsafe extern "C" fn _value_hash(value: i64, tag: i64) -> i64 {
+    value_hash(value, tag as u8) as i64
+}
+
+/// -1, 0 or 1 as `left` orders before, with or after `right`.
+///
+/// # Safety
+///
+/// Heap tags require the matching value to be null or point to a managed value of that kind.
+#[no_mangle]
+pub unsafe extern "C" fn _value_compare(left: i64, right: i64, left_tag: i64, right_tag: i64) -> i64 {
+    value_compare(left_tag as u8, left, right_tag as u8, right) as i64
+}
+
+/// The tag of an untyped word, for protocol calls whose first argument's kind is only known at run
+/// time. Heap collections carry their kind in their allocator label; any other word in mapped
+/// memory is a string, or a keyword when it starts with `:`, and the rest are numbers. Lists and
+/// vectors share a layout and read as vectors, hash sets read as maps, and `false` reads as nil.
+///
+/// # Safety
+///
+/// `value` may be any word; it is only read when it points into mapped memory.
+#[no_mangle]
+pub unsafe extern "C" fn _value_tag(value: i64) -> i64 {
+    let ptr = value as *const u8;
+    let tag = if value == 0 {
+        TAG_NIL
+    } else if is_allocated(ptr) {
+        match block_kind(ptr) {
+            BLOCK_MAP if is_sorted_set(ptr) => TAG_SET,
+            BLOCK_MAP | BLOCK_RECORD => TAG_MAP,
+            BLOCK_VECTOR | BLOCK_VECTOR_VIEW => TAG_VECTOR,
+            BLOCK_LAZY_SEQ => TAG_LAZY_SEQ,
+            BLOCK_ATOM => TAG_ATOM,
+            _ => text_tag(ptr),
+        }
+    } else if _string_readable(ptr) != 0 {
+        text_tag(ptr)
+    } else {
+        TAG_NUMBER
+    };
+    tag as i64
+}
+
+/// `count` of an untyped value, by the kind `_value_tag` reads off it; 0 for nil and numbers.
+///
+/// # Safety
+///
+/// Same requirements as `_value_tag`.
+#[no_mangle]
+pub unsafe extern "C" fn _value_count(value: i64) -> i64 {
+    let ptr = value as *const u8;
+    match _value_tag(value) as u8 {
+        TAG_VECTOR => _vector_count(ptr) as i64,
+        TAG_MAP | TAG_SET => _map_count(ptr) as i64,
+        TAG_LAZY_SEQ => _lazy_count(ptr as *mut u8),
+        TAG_STRING | TAG_KEYWORD => _string_count(ptr) as i64,
+        _ => 0,
+    }
+}
+
+unsafe fn text_tag(text: *const u8) -> u8 {
+    if *text == b':' {
+        TAG_KEYWORD
+    } else {
+        TAG_STRING
+    }
+}
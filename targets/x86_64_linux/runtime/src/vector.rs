@@ -1,12 +1,32 @@
 use core::mem::size_of;
 use core::ptr::{copy_nonoverlapping, null_mut};
 
-use crate::{_allocate, _free, _map_to_string, _string_clone, _string_count, _string_from_number, FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL};
+use crate::exceptions::{mark_block, throw_message, BLOCK_VECTOR, BLOCK_VECTOR_VIEW};
+use crate::sequence::{release_value, INDEX_BOUNDS_MESSAGE, OUT_OF_MEMORY_MESSAGE};
+use crate::{_allocate, _free, _map_to_string, _map_value_clone, _string_clone, _string_count, _string_from_number, FALSE_LITERAL, NIL_LITERAL, TRUE_LITERAL};
 
 #[repr(C)]
 struct VectorHeader {
     length: u64,
     capacity: u64,
+    flags: u64,
+}
+
+/// Header flag for vectors that own their heap elements; `_vector_free` releases them one level deep.
+const OWNS_ELEMENTS: u64 = 1;
+/// Header flag for a view, a list reading its items from another block instead of holding them.
+const VIEW: u64 = 2;
+/// One reference to a block that views read from, counted in the high half of its flags.
+const VIEW_REFERENCE: u64 = 1 << 32;
+
+/// The items of `source` from `offset` on, `length` of them. `rest` returns views, so walking a
+/// list with first/rest copies its items once rather than once per step. A view's source is never
+/// a view itself, and it stays allocated while views refer to it.
+#[repr(C)]
+struct VectorView {
+    header: VectorHeader,
+    source: *mut VectorHeader,
+    offset: u64,
 }
 
 const TAG_NIL: u8 = 0;
@@ -15,8 +35,17 @@ const TAG_BOOLEAN: u8 = 2;
 const TAG_STRING: u8 = 3;
 const TAG_VECTOR: u8 = 4;
 const TAG_MAP: u8 = 5;
+const TAG_KEYWORD: u8 = 6;
+const TAG_LIST: u8 = 8;
+const TAG_SYMBOL: u8 = 9;
 const TAG_ANY: u8 = 0xff;
 
+/// Capacity a full vector grows to, so a run of in-place appends copies its items O(log n) times
+const MIN_GROWN_CAPACITY: usize = 4;
+
+static EMPTY_VECTOR_POP_MESSAGE: [u8; 23] = *b"Can't pop empty vector\0";
+static EMPTY_LIST_POP_MESSAGE: [u8; 21] = *b"Can't pop empty list\0";
+
 #[repr(C)]
 struct ElementRender {
     ptr: *mut u8,
@@ -41,48 +70,65 @@ fn padded_tag_bytes(len: usize) -> usize {
 
 #[inline]
 unsafe fn vector_tags_ptr(vec: *const VectorHeader) -> *const u8 {
+    if (*vec).flags & VIEW != 0 {
+        let view = vec as *const VectorView;
+        return vector_tags_ptr((*view).source).add((*view).offset as usize);
+    }
     (vec as *const u8).add(size_of::<VectorHeader>())
 }
 
+// The mutable accessors are only used on vectors that hold their items, never on views.
 #[inline]
 unsafe fn vector_tags_ptr_mut(vec: *mut VectorHeader) -> *mut u8 {
     (vec as *mut u8).add(size_of::<VectorHeader>())
 }
 
+// The tag bytes and values are laid out for `capacity` elements, so a vector with spare capacity
+// can grow in place; only the first `length` of each are in use.
 #[inline]
-fn vector_allocation_size(len: usize) -> Option<usize> {
+fn vector_allocation_size(capacity: usize) -> Option<usize> {
     let header = size_of::<VectorHeader>();
-    let value_bytes = len.checked_mul(size_of::<i64>())?;
-    header.checked_add(padded_tag_bytes(len))?.checked_add(value_bytes)
+    let value_bytes = capacity.checked_mul(size_of::<i64>())?;
+    header.checked_add(padded_tag_bytes(capacity))?.checked_add(value_bytes)
 }
 
 #[inline]
 unsafe fn vector_data_ptr(vec: *const VectorHeader) -> *const i64 {
-    let len = (*vec).length as usize;
-    let offset = size_of::<VectorHeader>() + padded_tag_bytes(len);
+    if (*vec).flags & VIEW != 0 {
+        let view = vec as *const VectorView;
+        return vector_data_ptr((*view).source).add((*view).offset as usize);
+    }
+    let capacity = (*vec).capacity as usize;
+    let offset = size_of::<VectorHeader>() + padded_tag_bytes(capacity);
     (vec as *const u8).add(offset) as *const i64
 }
 
 #[inline]
 unsafe fn vector_data_ptr_mut(vec: *mut VectorHeader) -> *mut i64 {
-    let len = (*vec).length as usize;
-    let offset = size_of::<VectorHeader>() + padded_tag_bytes(len);
+    let capacity = (*vec).capacity as usize;
+    let offset = size_of::<VectorHeader>() + padded_tag_bytes(capacity);
     (vec as *mut u8).add(offset) as *mut i64
 }
 
 unsafe fn vector_allocate(len: usize) -> *mut VectorHeader {
-    match vector_allocation_size(len) {
+    vector_allocate_with_capacity(len, len)
+}
+
+unsafe fn vector_allocate_with_capacity(len: usize, capacity: usize) -> *mut VectorHeader {
+    match vector_allocation_size(capacity) {
         Some(total) => {
             let raw = _allocate(total as u64);
             if raw.is_null() {
                 null_mut()
             } else {
+                mark_block(raw, BLOCK_VECTOR);
                 let header = raw as *mut VectorHeader;
                 (*header).length = len as u64;
-                (*header).capacity = len as u64;
-                if len > 0 {
+                (*header).capacity = capacity as u64;
+                (*header).flags = 0;
+                if capacity > 0 {
                     let tags_ptr = vector_tags_ptr_mut(header);
-                    let tag_bytes = padded_tag_bytes(len);
+                    let tag_bytes = padded_tag_bytes(capacity);
                     let mut idx = 0usize;
                     while idx < tag_bytes {
                         *tags_ptr.add(idx) = TAG_ANY;
@@ -133,7 +179,7 @@ unsafe fn materialize_element_render(value: i64, tag: u8) -> ElementRender {
                 }
             }
         }
-        TAG_STRING => {
+        TAG_STRING | TAG_KEYWORD | TAG_SYMBOL => {
             if value == 0 {
                 ElementRender {
                     ptr: NIL_LITERAL.as_ptr() as *mut u8,
@@ -181,6 +227,30 @@ unsafe fn materialize_element_render(value: i64, tag: u8) -> ElementRender {
                 }
             }
         }
+        TAG_LIST => {
+            if value == 0 {
+                ElementRender {
+                    ptr: NIL_LITERAL.as_ptr() as *mut u8,
+                    len: 3,
+                    owned: false,
+                }
+            } else {
+                let nested = _list_to_string(value as *const u8);
+                if nested.is_null() {
+                    ElementRender {
+                        ptr: NIL_LITERAL.as_ptr() as *mut u8,
+                        len: 3,
+                        owned: false,
+                    }
+                } else {
+                    ElementRender {
+                        ptr: nested,
+                        len: _string_count(nested) as usize,
+                        owned: true,
+                    }
+                }
+            }
+        }
         TAG_MAP => {
             if value == 0 {
                 ElementRender {
@@ -444,37 +514,51 @@ pub unsafe extern "C" fn _vector_clone(vec: *const u8) -> *mut u8 {
     new_vec as *mut u8
 }
 
+/// `_vector_clone` of a vector nothing else refers to, which hands over the vector itself.
+///
+/// # Safety
+///
+/// Same requirements as `_vector_conj_owned`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_clone_owned(vec: *mut u8) -> *mut u8 {
+    vec
+}
+
 /// # Safety
 ///
 /// The caller must ensure `vec` is either null or points to a managed vector. The caller owns the
 /// returned string and must release it with `_free`.
 #[no_mangle]
 pub unsafe extern "C" fn _vector_to_string(vec: *const u8) -> *mut u8 {
-    if vec.is_null() {
-        let dst = _allocate(3);
-        if dst.is_null() {
-            return null_mut();
-        }
-        *dst = b'[';
-        *dst.add(1) = b']';
-        *dst.add(2) = 0;
-        return dst;
-    }
+    render_sequence(vec, b'[', b']')
+}
 
-    let header = vec as *const VectorHeader;
-    let len = (*header).length as usize;
+/// # Safety
+///
+/// The caller must ensure `list` is either null or points to a managed list. The caller owns the
+/// returned string and must release it with `_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _list_to_string(list: *const u8) -> *mut u8 {
+    render_sequence(list, b'(', b')')
+}
+
+/// Render the elements of a vector or list separated by spaces between `open` and `close`
+unsafe fn render_sequence(vec: *const u8, open: u8, close: u8) -> *mut u8 {
+    let len = if vec.is_null() { 0 } else { (*(vec as *const VectorHeader)).length as usize };
 
     if len == 0 {
         let dst = _allocate(3);
         if dst.is_null() {
             return null_mut();
         }
-        *dst = b'[';
-        *dst.add(1) = b']';
+        *dst = open;
+        *dst.add(1) = close;
         *dst.add(2) = 0;
         return dst;
     }
 
+    let header = vec as *const VectorHeader;
+
     let entries_size = len.checked_mul(size_of::<ElementRender>()).unwrap_or(0);
     if entries_size == 0 {
         return null_mut();
@@ -488,7 +572,7 @@ pub unsafe extern "C" fn _vector_to_string(vec: *const u8) -> *mut u8 {
     let values = vector_data_ptr(header);
     let tags = vector_tags_ptr(header);
 
-    let mut total_len = 2usize; // '[' and ']'
+    let mut total_len = 2usize; // opening and closing delimiters
     let mut idx = 0usize;
     let mut overflow = false;
 
@@ -542,7 +626,7 @@ pub unsafe extern "C" fn _vector_to_string(vec: *const u8) -> *mut u8 {
     }
 
     let mut offset = 0usize;
-    *dst.add(offset) = b'[';
+    *dst.add(offset) = open;
     offset += 1;
 
     idx = 0;
@@ -561,7 +645,7 @@ pub unsafe extern "C" fn _vector_to_string(vec: *const u8) -> *mut u8 {
         idx += 1;
     }
 
-    *dst.add(offset) = b']';
+    *dst.add(offset) = close;
     offset += 1;
     *dst.add(offset) = 0;
 
@@ -579,5 +663,458 @@ pub unsafe extern "C" fn _vector_free(vec: *mut u8) {
     if vec.is_null() {
         return;
     }
+
+    let header = vec as *mut VectorHeader;
+    if (*header).flags & VIEW != 0 {
+        release_view_source((*(header as *const VectorView)).source);
+        _free(vec);
+        return;
+    }
+    if (*header).flags >= VIEW_REFERENCE {
+        // Views still read the items; the last one to go frees the block.
+        (*header).flags -= VIEW_REFERENCE;
+        return;
+    }
+    if (*header).flags & OWNS_ELEMENTS != 0 {
+        let len = (*header).length as usize;
+        let tags = vector_tags_ptr(header);
+        let data = vector_data_ptr(header);
+        let mut idx = 0usize;
+        while idx < len {
+            release_value(*data.add(idx), *tags.add(idx));
+            idx += 1;
+        }
+    }
+
+    _free(vec);
+}
+
+/// Drop a view's reference to `source`, freeing it with the last one.
+unsafe fn release_view_source(source: *mut VectorHeader) {
+    (*source).flags -= VIEW_REFERENCE;
+    if (*source).flags < VIEW_REFERENCE {
+        _free(source as *mut u8);
+    }
+}
+
+/// The items of `seq` from `start` on as a view. A view of a view reads from the same source; any
+/// other list or vector first has those items copied into a new block for the views to share.
+/// Running out of memory throws.
+unsafe fn vector_view(seq: *const VectorHeader, start: usize) -> *mut u8 {
+    let len = (*seq).length as usize;
+    if start >= len {
+        return vector_allocate(0) as *mut u8;
+    }
+
+    let view = _allocate(size_of::<VectorView>() as u64) as *mut VectorView;
+    if view.is_null() {
+        throw_message(&OUT_OF_MEMORY_MESSAGE);
+    }
+    let (source, offset) = if (*seq).flags & VIEW != 0 {
+        let outer = seq as *const VectorView;
+        ((*outer).source, (*outer).offset as usize + start)
+    } else {
+        let copy = _vector_slice(seq as *const u8, start as i64, -1) as *mut VectorHeader;
+        if copy.is_null() {
+            _free(view as *mut u8);
+            throw_message(&OUT_OF_MEMORY_MESSAGE);
+        }
+        (copy, 0)
+    };
+    mark_block(view as *const u8, BLOCK_VECTOR_VIEW);
+    (*source).flags += VIEW_REFERENCE;
+    (*view).header = VectorHeader {
+        length: (len - start) as u64,
+        capacity: 0,
+        flags: VIEW,
+    };
+    (*view).source = source;
+    (*view).offset = offset as u64;
+    view as *mut u8
+}
+
+/// The block a view reads its items from, or `None` for a vector holding its own.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` points to a managed vector.
+pub(crate) unsafe fn vector_view_source(vec: *const u8) -> Option<*const u8> {
+    let header = vec as *const VectorHeader;
+    ((*header).flags & VIEW != 0).then(|| (*(vec as *const VectorView)).source as *const u8)
+}
+
+/// Drop the reference the view `view`, about to be freed by unwinding, holds to its source when
+/// the source stays.
+///
+/// # Safety
+///
+/// The caller must ensure that `view` points to a view.
+pub(crate) unsafe fn vector_view_release_outer(view: *const u8, stays: &dyn Fn(*const u8) -> bool) {
+    let source = (*(view as *const VectorView)).source;
+    if stays(source as *const u8) {
+        release_view_source(source);
+    }
+}
+
+/// Mark `vec` as owning its heap elements so `_vector_free` releases them with it.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` is either null or points to a managed vector whose heap
+/// elements are referenced from nowhere else.
+pub(crate) unsafe fn vector_mark_owning(vec: *mut u8) {
+    if !vec.is_null() {
+        (*(vec as *mut VectorHeader)).flags |= OWNS_ELEMENTS;
+    }
+}
+
+/// Read the value and tag stored at `index`.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` points to a managed vector holding more than `index` elements.
+pub(crate) unsafe fn vector_element(vec: *const u8, index: usize) -> (i64, u8) {
+    let header = vec as *const VectorHeader;
+    (*vector_data_ptr(header).add(index), *vector_tags_ptr(header).add(index))
+}
+
+/// Store `value` with `tag` at `index`.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` points to a managed vector holding more than `index` elements.
+pub(crate) unsafe fn vector_set_element(vec: *mut u8, index: usize, value: i64, tag: u8) {
+    let header = vec as *mut VectorHeader;
+    *vector_data_ptr_mut(header).add(index) = value;
+    *vector_tags_ptr_mut(header).add(index) = tag;
+}
+
+/// # Safety
+///
+/// The caller must ensure that `seq` is either null (the empty list) or points to a managed list
+/// or vector. The returned list shares `value` and the elements of `seq` without cloning them and
+/// must be released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _list_cons(value: i64, tag: i64, seq: *const u8) -> *mut u8 {
+    let tail_len = if seq.is_null() { 0 } else { (*(seq as *const VectorHeader)).length as usize };
+    let len = match tail_len.checked_add(1) {
+        Some(len) => len,
+        None => return null_mut(),
+    };
+
+    let list = vector_allocate(len);
+    if list.is_null() {
+        return null_mut();
+    }
+
+    let dst = vector_data_ptr_mut(list);
+    let dst_tags = vector_tags_ptr_mut(list);
+    *dst = value;
+    *dst_tags = (tag & 0xff) as u8;
+
+    if tail_len > 0 {
+        let header = seq as *const VectorHeader;
+        copy_nonoverlapping(vector_data_ptr(header), dst.add(1), tail_len);
+        copy_nonoverlapping(vector_tags_ptr(header), dst_tags.add(1), tail_len);
+    }
+
+    list as *mut u8
+}
+
+/// # Safety
+///
+/// The caller must ensure that `seq` is either null or points to a managed list or vector. The
+/// result is always a list (empty for an empty or null `seq`), a view sharing the remaining
+/// elements, and must be released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _list_rest(seq: *const u8) -> *mut u8 {
+    if seq.is_null() {
+        return vector_allocate(0) as *mut u8;
+    }
+    vector_view(seq as *const VectorHeader, 1)
+}
+
+/// The items of a list or vector from `start` on, as a list sharing them (see `_list_rest`).
+///
+/// # Safety
+///
+/// The caller must ensure that `seq` points to a managed list or vector.
+pub(crate) unsafe fn list_items_from(seq: *const u8, start: usize) -> *mut u8 {
+    vector_view(seq as *const VectorHeader, start)
+}
+
+/// `_list_cons` with the list first, the argument order of `_vector_conj`.
+///
+/// # Safety
+///
+/// Same requirements as `_list_cons`.
+#[no_mangle]
+pub unsafe extern "C" fn _list_conj(seq: *const u8, value: i64, tag: i64) -> *mut u8 {
+    _list_cons(value, tag, seq)
+}
+
+/// # Safety
+///
+/// The caller must ensure that `list` is either null or points to a managed list. The result shares
+/// the remaining elements and must be released with `_vector_free`; an empty list throws.
+#[no_mangle]
+pub unsafe extern "C" fn _list_pop(list: *const u8) -> *mut u8 {
+    if list.is_null() || (*(list as *const VectorHeader)).length == 0 {
+        throw_message(&EMPTY_LIST_POP_MESSAGE);
+    }
+    _list_rest(list)
+}
+
+fn grown_capacity(len: usize) -> usize {
+    len.saturating_mul(2).max(MIN_GROWN_CAPACITY)
+}
+
+/// Copy the first `len` elements of `src` into a new vector with room for `capacity`. The copy
+/// shares the elements and does not own them.
+unsafe fn vector_copy(src: *const VectorHeader, len: usize, capacity: usize) -> *mut VectorHeader {
+    let copy = vector_allocate_with_capacity(len, capacity);
+    if copy.is_null() || len == 0 {
+        return copy;
+    }
+    copy_nonoverlapping(vector_data_ptr(src), vector_data_ptr_mut(copy), len);
+    copy_nonoverlapping(vector_tags_ptr(src), vector_tags_ptr_mut(copy), len);
+    copy
+}
+
+/// Make room for one more element in a vector the caller owns, moving it to a larger block when
+/// it is full. Returns the vector to write into.
+unsafe fn vector_reserve_one(vec: *mut VectorHeader) -> *mut VectorHeader {
+    let len = (*vec).length as usize;
+    if len < (*vec).capacity as usize {
+        return vec;
+    }
+    let grown = vector_copy(vec, len, grown_capacity(len));
+    if grown.is_null() {
+        return null_mut();
+    }
+    if (*vec).flags & VIEW != 0 {
+        _vector_free(vec as *mut u8);
+    } else {
+        (*grown).flags = (*vec).flags;
+        _free(vec as *mut u8);
+    }
+    grown
+}
+
+/// Store `value` at `index` of a vector the caller owns. A vector that owns its elements takes a
+/// copy of a heap value and releases the element it replaces.
+unsafe fn vector_store(vec: *mut VectorHeader, index: usize, value: i64, tag: u8, replacing: bool) {
+    let owning = (*vec).flags & OWNS_ELEMENTS != 0;
+    let data = vector_data_ptr_mut(vec);
+    let tags = vector_tags_ptr_mut(vec);
+    if owning && replacing {
+        release_value(*data.add(index), *tags.add(index));
+    }
+    *data.add(index) = if owning { _map_value_clone(value, tag as i64) } else { value };
+    *tags.add(index) = tag;
+}
+
+unsafe fn vector_conj_into(vec: *mut VectorHeader, value: i64, tag: i64) -> *mut u8 {
+    if vec.is_null() {
+        return null_mut();
+    }
+    let len = (*vec).length as usize;
+    vector_store(vec, len, value, (tag & 0xff) as u8, false);
+    (*vec).length = (len + 1) as u64;
+    vec as *mut u8
+}
+
+/// Append a value. The result is a new vector sharing the elements of `vec`, with spare capacity
+/// so appending to it in place (`_vector_conj_owned`) does not copy again.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` is either null (the empty vector) or points to a managed
+/// vector. The result must be released with `_vector_free`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_conj(vec: *const u8, value: i64, tag: i64) -> *mut u8 {
+    let len = if vec.is_null() { 0 } else { (*(vec as *const VectorHeader)).length as usize };
+    let copy = if vec.is_null() {
+        vector_allocate_with_capacity(0, MIN_GROWN_CAPACITY)
+    } else {
+        vector_copy(vec as *const VectorHeader, len, grown_capacity(len + 1))
+    };
+    vector_conj_into(copy, value, tag)
+}
+
+/// Append a value to a vector nothing else refers to, reusing its block while it has room.
+///
+/// # Safety
+///
+/// The caller must own `vec` (or pass null) and must not use it afterwards; the returned vector
+/// replaces it.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_conj_owned(vec: *mut u8, value: i64, tag: i64) -> *mut u8 {
+    if vec.is_null() {
+        return _vector_conj(vec, value, tag);
+    }
+    vector_conj_into(vector_reserve_one(vec as *mut VectorHeader), value, tag)
+}
+
+unsafe fn checked_assoc_index(vec: *const u8, index: i64) -> usize {
+    let len = if vec.is_null() { 0 } else { (*(vec as *const VectorHeader)).length };
+    if index < 0 || index as u64 > len {
+        throw_message(&INDEX_BOUNDS_MESSAGE);
+    }
+    index as usize
+}
+
+/// Replace the element at `index`; an index equal to the length appends. The result is a new
+/// vector sharing the other elements of `vec`, and an index past the end throws.
+///
+/// # Safety
+///
+/// Same requirements as `_vector_conj`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_assoc(vec: *const u8, index: i64, value: i64, tag: i64) -> *mut u8 {
+    let index = checked_assoc_index(vec, index);
+    let len = if vec.is_null() { 0 } else { (*(vec as *const VectorHeader)).length as usize };
+    if index == len {
+        return _vector_conj(vec, value, tag);
+    }
+    let copy = vector_copy(vec as *const VectorHeader, len, len);
+    if !copy.is_null() {
+        vector_store(copy, index, value, (tag & 0xff) as u8, false);
+    }
+    copy as *mut u8
+}
+
+/// `_vector_assoc` on a vector nothing else refers to, updating it in place.
+///
+/// # Safety
+///
+/// Same requirements as `_vector_conj_owned`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_assoc_owned(vec: *mut u8, index: i64, value: i64, tag: i64) -> *mut u8 {
+    let index = checked_assoc_index(vec, index);
+    if vec.is_null() || index == (*(vec as *const VectorHeader)).length as usize {
+        return _vector_conj_owned(vec, value, tag);
+    }
+    if (*(vec as *const VectorHeader)).flags & VIEW != 0 {
+        let copy = _vector_assoc(vec, index as i64, value, tag);
+        _vector_free(vec);
+        return copy;
+    }
+    vector_store(vec as *mut VectorHeader, index, value, (tag & 0xff) as u8, true);
+    vec
+}
+
+unsafe fn checked_pop_length(vec: *const u8) -> usize {
+    if vec.is_null() || (*(vec as *const VectorHeader)).length == 0 {
+        throw_message(&EMPTY_VECTOR_POP_MESSAGE);
+    }
+    (*(vec as *const VectorHeader)).length as usize
+}
+
+/// Everything but the last element, as a new vector sharing them; an empty vector throws.
+///
+/// # Safety
+///
+/// Same requirements as `_vector_conj`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_pop(vec: *const u8) -> *mut u8 {
+    let len = checked_pop_length(vec);
+    vector_copy(vec as *const VectorHeader, len - 1, len - 1) as *mut u8
+}
+
+/// `_vector_pop` on a vector nothing else refers to, shrinking it in place.
+///
+/// # Safety
+///
+/// Same requirements as `_vector_conj_owned`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_pop_owned(vec: *mut u8) -> *mut u8 {
+    let len = checked_pop_length(vec);
+    let header = vec as *mut VectorHeader;
+    if (*header).flags & OWNS_ELEMENTS != 0 {
+        release_value(*vector_data_ptr(header).add(len - 1), *vector_tags_ptr(header).add(len - 1));
+    }
+    (*header).length = (len - 1) as u64;
+    vec
+}
+
+/// The last element, borrowed from `vec`; 0 (nil) for an empty vector.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` is either null or points to a managed vector.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_peek(vec: *const u8) -> i64 {
+    let len = _vector_count(vec) as usize;
+    if len == 0 {
+        return 0;
+    }
+    vector_element(vec, len - 1).0
+}
+
+/// Elements `start` up to `end` (the end of the vector when `end` is negative) as a new vector
+/// sharing them; bounds outside the vector throw.
+///
+/// # Safety
+///
+/// Same requirements as `_vector_conj`.
+#[no_mangle]
+pub unsafe extern "C" fn _vector_subvec(vec: *const u8, start: i64, end: i64) -> *mut u8 {
+    let len = _vector_count(vec) as i64;
+    let end = if end < 0 { len } else { end };
+    if start < 0 || start > end || end > len {
+        throw_message(&INDEX_BOUNDS_MESSAGE);
+    }
+    if vec.is_null() {
+        return vector_allocate(0) as *mut u8;
+    }
+    _vector_slice(vec, start, end)
+}
+
+/// Clone a vector together with every heap value reachable from it.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` is either null or points to a managed vector.
+pub(crate) unsafe fn vector_deep_clone(vec: *const u8) -> *mut u8 {
+    let cloned = _vector_clone(vec) as *mut VectorHeader;
+    if cloned.is_null() {
+        return null_mut();
+    }
+
+    let len = (*cloned).length as usize;
+    let tags = vector_tags_ptr(cloned);
+    let data = vector_data_ptr_mut(cloned);
+
+    let mut idx = 0usize;
+    while idx < len {
+        *data.add(idx) = crate::exceptions::value_deep_clone(*data.add(idx), *tags.add(idx));
+        idx += 1;
+    }
+
+    cloned as *mut u8
+}
+
+/// Release a vector and every heap value it owns.
+///
+/// # Safety
+///
+/// The caller must ensure that `vec` is either null or a vector produced by `vector_deep_clone`.
+pub(crate) unsafe fn vector_deep_free(vec: *mut u8) {
+    if vec.is_null() {
+        return;
+    }
+
+    let header = vec as *const VectorHeader;
+    let len = (*header).length as usize;
+    let tags = vector_tags_ptr(header);
+    let data = vector_data_ptr(header);
+
+    let mut idx = 0usize;
+    while idx < len {
+        crate::exceptions::value_deep_free(*data.add(idx), *tags.add(idx));
+        idx += 1;
+    }
+
     _free(vec);
 }